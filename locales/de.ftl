@@ -0,0 +1,399 @@
+# Deutsche Übersetzung der Meldungen von librarian. Siehe src/i18n.rs.
+
+## Rückfragen
+
+confirm-question = { $question } (y/n):
+confirm-invalid = Bitte mit y (ja) oder n (nein) antworten.
+
+## librarian check
+
+check-mismatch = { $key } ({ $title }) ist als { $expected } erfasst, sieht aber wie { $detected } aus.
+check-untyped = { $key } ({ $title }) hat keinen Dokumenttyp, sieht aber wie { $detected } aus.
+check-change-type = Dokumenttyp auf { $type } ändern?
+check-attachment-missing = Bei { $key } ({ $title }) fehlt der Anhang { $name }.
+check-attachment-modified = Der Anhang { $name } von { $key } ({ $title }) wurde verändert.
+check-attachment-update = Neue Prüfsumme übernehmen?
+check-style = { $key }: { $field } „{ $old }“ sollte „{ $new }“ lauten.
+check-style-apply = Stilregeln anwenden?
+check-summary = { $problems ->
+        [one] 1 Problem
+       *[other] { $problems } Probleme
+    } gefunden und { $fixed } behoben.
+
+## librarian bulk-edit
+
+edit-no-matches = Keine Ressourcen passen zu { $query }.
+edit-select = Welche Ressourcen bearbeiten? (z. B. 1,3-5, all oder none):
+edit-invalid-selection = Ungültige Auswahl.
+edit-done = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } bearbeitet.
+
+## librarian linkcheck
+
+linkcheck-dead = tot: { $key } ({ $title }): { $url } [{ $reason }]
+linkcheck-archived-at = archiviert unter { $archive }
+linkcheck-moved = verschoben: { $key } ({ $title }): { $url } -> { $redirect }
+linkcheck-archived = archiviert: { $url } unter { $archive }
+linkcheck-archive-failed = Archivierung von { $url } fehlgeschlagen: { $error }
+linkcheck-summary = { $count } Links geprüft, { $dead } tot.
+
+## Zusätzliche Speicher
+
+ancillary-safe-mode = Abgesicherter Modus: { $store } wird übersprungen.
+ancillary-queued = { $store } ist zur Neuerstellung vorgemerkt und wird ignoriert.
+ancillary-corrupted = Warnung: { $store } konnte nicht gelesen werden: { $error }. Wird ignoriert und zur Neuerstellung vorgemerkt.
+
+## librarian tag
+
+tag-created = { $count ->
+        [one] 1 Tag
+       *[other] { $count } Tags
+    } zum Tag-Baum hinzugefügt.
+tag-unknown = { $key } hat das unbekannte Tag { $tag }
+tag-removed = Tag { $tag } entfernt, { $count } Ressourcen aktualisiert.
+tag-renamed = Tag { $old } in { $new } umbenannt, { $count } Ressourcen aktualisiert.
+tag-merged = Tag { $from } mit { $into } zusammengeführt, { $count } Ressourcen aktualisiert.
+tag-added = Tag { $tag } hinzugefügt, { $count } Ressourcen aktualisiert.
+tag-apply-nothing = Keine zu { $query } passende Ressource muss geändert werden.
+tag-apply-add = Tag { $tag } zu den { $count } obigen Ressourcen hinzufügen?
+tag-apply-remove = Tag { $tag } von den { $count } obigen Ressourcen entfernen?
+
+## librarian collection
+
+collection-created = Sammlung { $name } mit { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } erstellt.
+collection-added = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } zur Sammlung { $name } hinzugefügt.
+collection-removed = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } aus der Sammlung { $name } entfernt.
+collection-deleted = Sammlung { $name } entfernt.
+
+## librarian fmt
+
+fmt-done = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } formatiert.
+
+## librarian catalog
+
+init-library = Bibliothek { $id }
+catalog-same-identifier = { $title } hat dieselbe DOI oder ISBN wie { $key } ({ $other }).
+catalog-attach-file = Sie zu den Anhängen von { $key } hinzufügen?
+metadata-fetch-failed = Metadaten für { $identifier } konnten nicht abgerufen werden: { $error }
+relation-unknown = { $key }: { $relation } verweist auf { $target }, das nicht im Katalog ist.
+catalog-duplicate = { $path } hat denselben Inhalt wie { $original }.
+catalog-delete-duplicate = { $path } löschen?
+catalog-duplicates-kept = { $count ->
+        [one] 1 doppelte Datei wurde
+       *[other] { $count } doppelte Dateien wurden
+    } behalten und nicht katalogisiert. Entferne sie oder führe catalog mit --delete-duplicates aus.
+catalog-orphans = { $count ->
+        [one] 1 katalogisierte Ressource ist
+       *[other] { $count } katalogisierte Ressourcen sind
+    } nicht mehr im Ressourcenverzeichnis:
+catalog-remove-orphan = { $title } ({ $key }) stilllegen? Die Metadaten bleiben erhalten.
+catalog-plan-add = { $title } ({ $key }) würde hinzugefügt.
+catalog-plan-checksum = Die Prüfsumme von { $title } ({ $key }) würde auf { $checksum } aktualisiert.
+catalog-plan-remove = { $title } ({ $key }) würde entfernt.
+catalog-plan-retire = { $title } ({ $key }) würde stillgelegt.
+catalog-plan-restore = { $title } ({ $key }) würde wiederhergestellt, da die Datei zurück ist.
+catalog-plan-delete = { $path } würde gelöscht.
+catalog-plan-modify = { $fields } von { $title } ({ $key }) würde geändert.
+catalog-plan-settings = { $fields } des Katalogs würde geändert.
+catalog-plan-none = Nichts würde geändert.
+catalog-new-document-type = Dokumenttyp { $type } hinzugefügt, erkannt am Inhalt einer neuen Datei.
+
+## Reading workflow
+
+reading-changed = { $field } von { $title } auf { $value } gesetzt.
+
+## Batch operations
+
+batch-resuming = Fortsetzung, { $done ->
+        [one] 1 Ressource wurde
+       *[other] { $done } Ressourcen wurden
+    } bereits verarbeitet.
+batch-interrupted = Nach { $done } von { $total } Ressourcen unterbrochen. Führe { $operation } erneut mit --resume aus, um fortzufahren.
+enrich-summary = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } angereichert, { $failed } fehlgeschlagen.
+
+enrich-same-identifier = Warnung: { $key } hat dieselbe DOI oder ISBN wie { $other } ({ $title }).
+## librarian authors
+
+authors-merged = { $from } in { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } durch { $to } ersetzt.
+
+## librarian watch
+
+watch-watching = Überwache { $path } auf neue Ressourcen.
+watch-cataloged = { $title } ({ $key }) katalogisiert.
+watch-updated = Prüfsumme von { $title } ({ $key }) aktualisiert.
+watch-duplicate = { $path } ist bereits eine Ressource ({ $key }). Duplikat wird nicht katalogisiert.
+watch-duplicate-deleted = { $path } ist bereits eine Ressource ({ $key }). Duplikat wird entfernt.
+watch-inbox-conflict = { $name } ist bereits im Ressourcenverzeichnis und wurde im Eingang belassen.
+storage-symlink-skipped = Symbolischer Link { $path } übersprungen. Mit --follow-symlinks wird sein Ziel katalogisiert.
+watch-move-failed = { $path } konnte nicht in die Bibliothek verschoben werden.
+watch-failed = Änderungen können nicht beobachtet werden: { $error }
+
+## librarian review
+
+inbox-staged = { $count ->
+        [one] 1 neue Datei
+       *[other] { $count } neue Dateien
+    } aus dem Eingang zur Prüfung bereitgestellt.
+inbox-duplicate = { $name } im Eingang ist bereits eine Ressource ({ $key }). Duplikat wird entfernt.
+review-empty = Keine Ressourcen warten auf Prüfung.
+review-header = Ausstehende Ressource { $index } von { $count }:
+review-prompt = Annehmen [a], Metadaten abrufen [f], Feld bearbeiten [e], Tags vergeben [t], überspringen [s], löschen [d] oder beenden [q]?
+review-edit = Feld und Wert (feld=wert):
+review-tags = Tags, durch Leerzeichen getrennt:
+review-accepted = { $title } zur Bibliothek hinzugefügt.
+review-deleted = { $title } gelöscht.
+review-no-identifier = Keine DOI oder arXiv-Kennung gefunden. Setze eine mit [e], z. B. doi=10.1000/xyz.
+review-invalid = Ungültige Antwort, bitte a, f, e, t, s, d oder q eingeben.
+
+## librarian catalog --interactive
+
+describe-header = Neue Ressource { $title } ({ $key }):
+describe-title = Titel:
+describe-authors = Autoren (durch ";" getrennt):
+describe-date = Datum (z. B. 2019-05):
+describe-tags = Tags (durch Leerzeichen getrennt):
+describe-content = Inhaltstyp:
+describe-document = Dokumenttyp:
+describe-unknown-content = { $value } ist kein Inhaltstyp des Katalogs.
+describe-unknown-document = { $value } ist kein Dokumenttyp des Katalogs.
+
+## librarian cache
+
+cache-future-modified = { $path } wurde in der Zukunft geändert, die Uhr geht möglicherweise falsch. Die Prüfsumme wird neu berechnet.
+cache-entries = { $count ->
+        [one] 1 Eintrag
+       *[other] { $count } Einträge
+    }, davon { $new } neuer Dateien.
+cache-last-run = Letzte Katalogisierung ({ $time }): { $hits ->
+        [one] 1 Treffer
+       *[other] { $hits } Treffer
+    }, { $misses ->
+        [one] 1 Fehlschlag
+       *[other] { $misses } Fehlschläge
+    }.
+cache-no-run = Noch keine Katalogisierung hat den Cache verwendet.
+cache-ages = Geprüft innerhalb eines Tages: { $day }, einer Woche: { $week }, 30 Tagen: { $month }, früher: { $older }.
+cache-cleared = { $count ->
+        [one] 1 Eintrag
+       *[other] { $count } Einträge
+    } aus dem Cache entfernt.
+cache-pruned = { $count ->
+        [one] 1 Eintrag
+       *[other] { $count } Einträge
+    } ohne Ressource aus dem Cache entfernt.
+
+## librarian resolve
+
+resolve-current = Prüfsumme { $revision } von { $count }, die aktuelle.
+resolve-historical = Prüfsumme { $revision } von { $count }, die aktuelle ist { $checksum }.
+
+## librarian versions and checkout
+
+versions-current = (aktuell)
+versions-kept = (aufbewahrt)
+versions-lost = (keine Kopie aufbewahrt)
+versions-unchanged = Diese Revision ist bereits die aktuelle.
+versions-restored = „{ $title }“ auf { $checksum } zurückgesetzt.
+
+## librarian todo
+
+todo-missing = Bei { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } fehlt { $field }:
+todo-none = Keiner Ressource fehlt eines der Felder.
+
+## librarian lint
+
+lint-missing-field = { $field } fehlt
+lint-unknown-tag = Schlagwort { $tag } ist nicht im Schlagwortbaum
+lint-future-date = Datum { $date } liegt in der Zukunft
+lint-early-date = Datum { $date } liegt vor { $year }
+lint-duplicate-title = gleicher Titel und gleiche Autoren wie { $other }
+lint-dead-url = URL { $url } ist nicht erreichbar ({ $status })
+lint-violation = { $key } ({ $title }): { $problem }
+lint-summary = { $count ->
+        [0] Keine Regelverstöße.
+        [one] 1 Regelverstoß.
+       *[other] { $count } Regelverstöße.
+    }
+
+## librarian bibtex --check
+
+bibtex-incomplete = { $key } ({ $title }): { $fields } fehlt
+bibtex-check-summary = { $count } von { $total } Ressourcen haben unvollständige Metadaten.
+
+## librarian dedupe
+
+dedupe-none = Keine Ressourcen scheinen dasselbe Werk zu sein.
+dedupe-header = Mögliche Duplikate { $index } von { $count } ({ $score } % ähnlich):
+dedupe-prompt = In [1] oder [2] zusammenführen, beide als Versionen behalten [v], kein Duplikat [n], überspringen [s] oder beenden [q]?
+dedupe-merged = { $other } wurde in { $keep } zusammengeführt.
+dedupe-superseded = { $newer } ersetzt jetzt { $older }.
+dedupe-invalid = Ungültige Antwort, bitte 1, 2, v, n, s oder q eingeben.
+
+## librarian merge-resources
+
+merge-choose = { $field }: [1] { $kept } oder [2] { $merged } behalten?
+
+## librarian undo
+
+journal-failed = Journal konnte nicht geschrieben werden: { $error }
+undo-empty = Es gibt nichts rückgängig zu machen.
+undo-done = { $operation } vom { $time } wurde rückgängig gemacht, was { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } betraf.
+undo-files = { $count ->
+        [one] 1 Datei, die der Vorgang verschoben oder gelöscht hatte, wurde
+       *[other] { $count } Dateien, die der Vorgang verschoben oder gelöscht hatte, wurden
+    } wiederhergestellt.
+undo-file-skipped = { $path } konnte nicht wiederhergestellt werden, weil die gesicherte Kopie fehlt oder eine andere Datei an ihrer Stelle ist.
+
+## librarian log and diff
+
+history-commit-failed = Der Katalog konnte nicht in Git eingecheckt werden: { $error }
+history-empty = Der Katalog hat keine Commits.
+history-missing = { $revision } ist weder ein Katalog noch eine Git-Revision, die den Katalog der Bibliothek enthält.
+history-no-changes = Keine Ressourcen wurden geändert.
+
+## librarian sync
+
+sync-choose = { $key } { $field }: [1] lokal { $local } oder [2] entfernt { $remote } behalten?
+sync-missing = Der entfernten Bibliothek fehlt { $file }.
+sync-pull-failed = Der Katalog von { $remote } konnte nicht gelesen werden, nichts wurde synchronisiert: { $error }
+sync-other-library = { $remote } ist Bibliothek { $library }, dies ist aber Bibliothek { $local }. Nur Kopien derselben Bibliothek können synchronisiert werden.
+sync-push-failed = Der Katalog konnte nicht nach { $remote } geschrieben werden.
+sync-done = Mit { $remote } synchronisiert: { $changed ->
+        [one] 1 Ressource
+       *[other] { $changed } Ressourcen
+    } geändert, { $fetched } Dateien geholt, { $sent } Dateien gesendet, { $conflicts ->
+        [one] 1 Konflikt
+       *[other] { $conflicts } Konflikte
+    }.
+
+## librarian export-bundle and import-bundle
+
+bundle-resource-missing = Ressource { $key } fehlt im Paket und wird übersprungen.
+bundle-nothing = Keine Ressourcen passen zu „{ $query }“.
+bundle-exported = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } nach { $file } geschrieben.
+bundle-duplicate = { $key } übersprungen, da die Bibliothek sie bereits als „{ $title }“ enthält.
+bundle-imported = { $count ->
+        [one] 1 Ressource
+       *[other] { $count } Ressourcen
+    } importiert, { $duplicates } bereits vorhandene übersprungen.
+
+## remote storage
+
+remote-invalid = { $location } ist weder ein S3-Bucket („s3://bucket/prefix“) noch eine WebDAV-URL.
+remote-upload-failed = Hochladen von { $key } fehlgeschlagen: { $error }
+remote-download-failed = Herunterladen von { $key } fehlgeschlagen: { $error }
+remote-verify-failed = Prüfen von { $key } fehlgeschlagen: { $error }
+remote-missing = Die entfernte Kopie von { $key } fehlt.
+remote-changed = Die entfernte Kopie von { $key } hat sich seit dem Hochladen geändert.
+remote-done = { $uploaded ->
+        [one] 1 Datei
+       *[other] { $uploaded } Dateien
+    } hochgeladen, { $verified } geprüft, { $problems ->
+        [one] 1 Problem
+       *[other] { $problems } Probleme
+    }.
+
+## librarian serve
+
+serve-listening = Die Bibliothek wird schreibgeschützt unter http://{ $address } bereitgestellt, Strg-C beendet.
+serve-listening-write = Die Bibliothek wird unter http://{ $address } bereitgestellt und nimmt Änderungen an, Strg-C beendet.
+serve-respond-failed = Antwort fehlgeschlagen: { $error }
+index-progress = { $title } indiziert ({ $done } von { $total }).
+index-thumbnail-failed = Vorschaubild von { $title } konnte nicht erstellt werden.
+index-no-pdftoppm = pdftoppm wurde nicht gefunden, daher werden keine Vorschaubilder erstellt: { $error }
+index-pdftoppm-failed = pdftoppm konnte nicht ausgeführt werden, daher werden keine Vorschaubilder erstellt: { $error }
+
+## librarian capture
+
+capture-existing = Die Bibliothek enthält diese Datei bereits als „{ $title }“.
+capture-done = „{ $title }“ in den Eingang übernommen.
+
+## hooks
+
+hook-failed = Der Hook { $event } ist fehlgeschlagen: { $error }
+
+## Resource selection
+
+pick-prompt = { $purpose } (1-{ $count }), leer lassen zum Abbrechen:
+pick-change = Zu ändernde Ressource
+pick-open = Zu öffnende Ressource
+pick-archive = Zu archivierende Ressource
+pick-note = Ressource für Notizen
+pick-versions = Ressource, deren Versionen aufgelistet werden
+pick-checkout = Wiederherzustellende Ressource
+
+## Missing files
+
+resource-missing = Ressource { $key } fehlt und wird übersprungen.
+attachment-missing = Anhang { $name } von { $key } fehlt und wird übersprungen.
+
+## librarian import and import-csv
+
+import-latex-name = Name mit LaTeX-Befehlen wird ignoriert: { $name }
+import-invalid-identifier = Ungültiger Bezeichner wird ignoriert: { $error }
+import-file-prompt = Datei für { $entry }, leer lassen zum Überspringen:
+import-not-file = { $path } ist keine Datei.
+import-no-file = Keine Datei für { $entry }, wird übersprungen.
+import-summary = { $created ->
+        [one] 1 Ressource
+       *[other] { $created } Ressourcen
+    } erstellt, { $updated } aktualisiert und { $skipped } übersprungen.
+import-csv-missing-column = Die CSV-Datei hat keine Spalte { $column }.
+
+## librarian cite and unused
+
+cite-skipped = { $path } wird übersprungen: { $error }
+cite-summary = { $cited ->
+        [one] 1 zitierter Schlüssel
+       *[other] { $cited } zitierte Schlüssel
+    }, { $cataloged } davon im Katalog.
+cite-missing = Zitierte Schlüssel, die nicht im Katalog sind:
+unused-summary = { $count } von { $total } Katalogeinträgen werden nie zitiert.
+
+## librarian fetch, fetch-file and add-url
+
+fetch-existing = { $source } ist bereits eine Ressource ({ $key }).
+download-resuming = Download von { $url } wurde unterbrochen ({ $error }) und wird fortgesetzt.
+download-interrupted = Download von { $url } wurde unterbrochen ({ $error }). Führen Sie den Befehl erneut aus, um ihn fortzusetzen.
+download-checksum-mismatch = { $url } hat die Prüfsumme { $checksum } statt { $expected }
+add-url-asset-failed = { $url } konnte nicht heruntergeladen werden: { $error }
+
+## librarian styles
+
+styles-built-in = { $id }: { $title } (eingebaut)
+styles-locales = Sprachdateien: { $locales }
+styles-added-style = Stil { $id } hinzugefügt.
+styles-added-locale = Sprachdatei { $id } hinzugefügt.
+
+## --timings
+
+timings-header = Zeiten:
@@ -0,0 +1,399 @@
+# User-facing messages of librarian. See src/i18n.rs.
+
+## Prompts
+
+confirm-question = { $question } (y/n):
+confirm-invalid = Please respond with y or n.
+
+## librarian check
+
+check-mismatch = { $key } ({ $title }) is recorded as { $expected } but looks like { $detected }.
+check-untyped = { $key } ({ $title }) has no document type but looks like { $detected }.
+check-change-type = Change its document type to { $type }?
+check-attachment-missing = { $key } ({ $title }) is missing its attachment { $name }.
+check-attachment-modified = The attachment { $name } of { $key } ({ $title }) was modified.
+check-attachment-update = Record its new checksum?
+check-style = { $key }: { $field } "{ $old }" should be "{ $new }".
+check-style-apply = Apply the style rules?
+check-summary = Found { $problems ->
+        [one] 1 problem
+       *[other] { $problems } problems
+    } and fixed { $fixed }.
+
+## librarian bulk-edit
+
+edit-no-matches = No resources match { $query }.
+edit-select = Edit which resources? (e.g., 1,3-5, all or none):
+edit-invalid-selection = Invalid selection.
+edit-done = Edited { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }.
+
+## librarian linkcheck
+
+linkcheck-dead = dead: { $key } ({ $title }): { $url } [{ $reason }]
+linkcheck-archived-at = archived at { $archive }
+linkcheck-moved = moved: { $key } ({ $title }): { $url } -> { $redirect }
+linkcheck-archived = archived: { $url } at { $archive }
+linkcheck-archive-failed = Failed to archive { $url }: { $error }
+linkcheck-summary = Checked { $count } links, { $dead } dead.
+
+## Ancillary stores
+
+ancillary-safe-mode = Safe mode: skipping the { $store }.
+ancillary-queued = The { $store } is queued for rebuild, ignoring it.
+ancillary-corrupted = Warning: failed to read the { $store }: { $error }. Ignoring it and queuing it for rebuild.
+
+## librarian tag
+
+tag-created = Added { $count ->
+        [one] 1 tag
+       *[other] { $count } tags
+    } to the tag tree.
+tag-unknown = { $key } has unknown tag { $tag }
+tag-removed = Removed tag { $tag }, updating { $count } resources.
+tag-renamed = Renamed tag { $old } to { $new }, updating { $count } resources.
+tag-merged = Merged tag { $from } into { $into }, updating { $count } resources.
+tag-added = Added tag { $tag }, updating { $count } resources.
+tag-apply-nothing = No resources matching { $query } need to change.
+tag-apply-add = Add tag { $tag } to the { $count } resources above?
+tag-apply-remove = Remove tag { $tag } from the { $count } resources above?
+
+## librarian collection
+
+collection-created = Created collection { $name } with { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }.
+collection-added = Added { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    } to collection { $name }.
+collection-removed = Removed { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    } from collection { $name }.
+collection-deleted = Removed collection { $name }.
+
+## librarian fmt
+
+fmt-done = Restyled { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }.
+
+## librarian catalog
+
+init-library = Library { $id }
+catalog-same-identifier = { $title } has the same DOI or ISBN as { $key } ({ $other }).
+catalog-attach-file = Add it to the attachments of { $key }?
+metadata-fetch-failed = Failed to fetch metadata for { $identifier }: { $error }
+relation-unknown = { $key }: { $relation } refers to { $target }, which isn't in the catalog.
+catalog-duplicate = { $path } has the same content as { $original }.
+catalog-delete-duplicate = Delete { $path }?
+catalog-duplicates-kept = { $count ->
+        [one] 1 duplicate file was kept and not cataloged. Remove it
+       *[other] { $count } duplicate files were kept and not cataloged. Remove them
+    } or run catalog with --delete-duplicates.
+catalog-orphans = { $count ->
+        [one] 1 cataloged resource is
+       *[other] { $count } cataloged resources are
+    } no longer in the resources directory:
+catalog-remove-orphan = Retire { $title } ({ $key })? Its metadata is kept.
+catalog-plan-add = Would add { $title } ({ $key }).
+catalog-plan-checksum = Would update the checksum of { $title } ({ $key }) to { $checksum }.
+catalog-plan-remove = Would remove { $title } ({ $key }).
+catalog-plan-retire = Would retire { $title } ({ $key }).
+catalog-plan-restore = Would restore { $title } ({ $key }), whose file is back.
+catalog-plan-delete = Would delete { $path }.
+catalog-plan-modify = Would change { $fields } of { $title } ({ $key }).
+catalog-plan-settings = Would change { $fields } of the catalog.
+catalog-plan-none = Nothing would change.
+catalog-new-document-type = Added the document type { $type }, detected from the content of a new file.
+
+## Reading workflow
+
+reading-changed = Set { $field } of { $title } to { $value }.
+
+## Batch operations
+
+batch-resuming = Resuming, { $done ->
+        [one] 1 resource was
+       *[other] { $done } resources were
+    } already processed.
+batch-interrupted = Interrupted after { $done } of { $total } resources. Run { $operation } again with --resume to continue.
+enrich-summary = Enriched { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }, { $failed } failed.
+enrich-same-identifier = Warning: { $key } has the same DOI or ISBN as { $other } ({ $title }).
+
+## librarian authors
+
+authors-merged = Replaced { $from } with { $to } in { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }.
+
+## librarian watch
+
+watch-watching = Watching { $path } for new resources.
+watch-cataloged = Cataloged { $title } ({ $key }).
+watch-updated = Updated the checksum of { $title } ({ $key }).
+watch-duplicate = { $path } is already a resource ({ $key }). Leaving the duplicate uncataloged.
+watch-duplicate-deleted = { $path } is already a resource ({ $key }). Removing duplicate.
+watch-inbox-conflict = { $name } is already in the resources directory, so it was left in the inbox.
+storage-symlink-skipped = Skipped the symbolic link { $path }. Pass --follow-symlinks to catalog its target.
+watch-move-failed = Failed to move { $path } into the library.
+watch-failed = Failed to watch for changes: { $error }
+
+## librarian review
+
+inbox-staged = Staged { $count ->
+        [one] 1 new file
+       *[other] { $count } new files
+    } from the inbox for review.
+inbox-duplicate = { $name } in the inbox is already a resource ({ $key }). Removing duplicate.
+review-empty = No resources are pending review.
+review-header = Pending resource { $index } of { $count }:
+review-prompt = [a]ccept, [f]etch metadata, [e]dit a field, [t]ag, [s]kip, [d]elete or [q]uit?
+review-edit = Field and value (field=value):
+review-tags = Tags, separated by spaces:
+review-accepted = Added { $title } to the library.
+review-deleted = Deleted { $title }.
+review-no-identifier = No DOI or arXiv identifier was found. Set one with [e]dit, e.g., doi=10.1000/xyz.
+review-invalid = Invalid response, please enter a, f, e, t, s, d or q.
+
+## librarian catalog --interactive
+
+describe-header = New resource { $title } ({ $key }):
+describe-title = Title:
+describe-authors = Authors (separated by ";"):
+describe-date = Date (e.g., 2019-05):
+describe-tags = Tags (separated by spaces):
+describe-content = Content type:
+describe-document = Document type:
+describe-unknown-content = { $value } isn't a content type of the catalog.
+describe-unknown-document = { $value } isn't a document type of the catalog.
+
+## librarian cache
+
+cache-future-modified = { $path } was modified in the future, its clock may be skewed. Recomputing its checksum.
+cache-entries = { $count ->
+        [one] 1 entry
+       *[other] { $count } entries
+    }, { $new } of them for new files.
+cache-last-run = Last catalog ({ $time }): { $hits ->
+        [one] 1 hit
+       *[other] { $hits } hits
+    }, { $misses ->
+        [one] 1 miss
+       *[other] { $misses } misses
+    }.
+cache-no-run = No catalog has used the cache yet.
+cache-ages = Verified within a day: { $day }, a week: { $week }, 30 days: { $month }, earlier: { $older }.
+cache-cleared = Removed { $count ->
+        [one] 1 entry
+       *[other] { $count } entries
+    } from the cache.
+cache-pruned = Pruned { $count ->
+        [one] 1 entry
+       *[other] { $count } entries
+    } without a resource from the cache.
+
+## librarian resolve
+
+resolve-current = Checksum { $revision } of { $count }, the current one.
+resolve-historical = Checksum { $revision } of { $count }, the current one is { $checksum }.
+
+## librarian versions and checkout
+
+versions-current = (current)
+versions-kept = (kept)
+versions-lost = (no copy kept)
+versions-unchanged = This revision is already the current one.
+versions-restored = Restored "{ $title }" to { $checksum }.
+
+## librarian todo
+
+todo-missing = { $count ->
+        [one] 1 resource lacks
+       *[other] { $count } resources lack
+    } { $field }:
+todo-none = No resource lacks any of the fields.
+
+## librarian lint
+
+lint-missing-field = missing { $field }
+lint-unknown-tag = tag { $tag } isn't in the tag tree
+lint-future-date = date { $date } is in the future
+lint-early-date = date { $date } is before { $year }
+lint-duplicate-title = same title and authors as { $other }
+lint-dead-url = URL { $url } can't be reached ({ $status })
+lint-violation = { $key } ({ $title }): { $problem }
+lint-summary = { $count ->
+        [0] No lint violations.
+        [one] 1 lint violation.
+       *[other] { $count } lint violations.
+    }
+
+## librarian bibtex --check
+
+bibtex-incomplete = { $key } ({ $title }): missing { $fields }
+bibtex-check-summary = { $count } of { $total } resources have incomplete metadata.
+
+## librarian dedupe
+
+dedupe-none = No resources look like the same work.
+dedupe-header = Possible duplicates { $index } of { $count } ({ $score }% similar):
+dedupe-prompt = Merge into [1] or [2], keep both as [v]ersions, [n]ot duplicates, [s]kip or [q]uit?
+dedupe-merged = Merged { $other } into { $keep }.
+dedupe-superseded = { $newer } now supersedes { $older }.
+dedupe-invalid = Invalid response, please enter 1, 2, v, n, s or q.
+
+## librarian merge-resources
+
+merge-choose = { $field }: keep [1] { $kept } or [2] { $merged }?
+
+## librarian undo
+
+journal-failed = Failed to write journal: { $error }
+undo-empty = There is nothing to undo.
+undo-done = Undid { $operation } from { $time }, which changed { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }.
+undo-files = Restored { $count ->
+        [one] 1 file
+       *[other] { $count } files
+    } the operation moved or deleted.
+undo-file-skipped = Couldn't restore { $path }, because its saved copy is missing or another file is in its place.
+
+## librarian log and diff
+
+history-commit-failed = Failed to commit the catalog to git: { $error }
+history-empty = The catalog has no commits.
+history-missing = { $revision } is neither a catalog nor a git revision containing the library's catalog.
+history-no-changes = No resources changed.
+
+## librarian sync
+
+sync-choose = { $key } { $field }: keep [1] local { $local } or [2] remote { $remote }?
+sync-missing = The remote library lacks { $file }.
+sync-pull-failed = Failed to read the catalog of { $remote }, nothing was synced: { $error }
+sync-other-library = { $remote } is library { $library }, but this is library { $local }. Only copies of the same library can be synced.
+sync-push-failed = Failed to write the catalog to { $remote }.
+sync-done = Synced with { $remote }: { $changed ->
+        [one] 1 resource
+       *[other] { $changed } resources
+    } changed, { $fetched } files fetched, { $sent } files sent, { $conflicts ->
+        [one] 1 conflict
+       *[other] { $conflicts } conflicts
+    }.
+
+## librarian export-bundle and import-bundle
+
+bundle-resource-missing = Resource { $key } is missing from the bundle, skipping.
+bundle-nothing = No resources match "{ $query }".
+bundle-exported = Wrote { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    } to { $file }.
+bundle-duplicate = Skipped { $key }, which the library already has as "{ $title }".
+bundle-imported = Imported { $count ->
+        [one] 1 resource
+       *[other] { $count } resources
+    }, skipping { $duplicates } already in the library.
+
+## remote storage
+
+remote-invalid = { $location } is not an S3 bucket ("s3://bucket/prefix") or a WebDAV URL.
+remote-upload-failed = Failed to upload { $key }: { $error }
+remote-download-failed = Failed to download { $key }: { $error }
+remote-verify-failed = Failed to verify { $key }: { $error }
+remote-missing = The remote copy of { $key } is missing.
+remote-changed = The remote copy of { $key } changed since it was uploaded.
+remote-done = Uploaded { $uploaded ->
+        [one] 1 file
+       *[other] { $uploaded } files
+    }, verified { $verified }, { $problems ->
+        [one] 1 problem
+       *[other] { $problems } problems
+    }.
+
+## librarian serve
+
+serve-listening = Serving the library read-only on http://{ $address }, press Ctrl-C to stop.
+serve-listening-write = Serving the library on http://{ $address }, accepting changes, press Ctrl-C to stop.
+serve-respond-failed = Failed to respond: { $error }
+index-progress = Indexed { $title } ({ $done } of { $total }).
+index-thumbnail-failed = Failed to render a thumbnail of { $title }.
+index-no-pdftoppm = pdftoppm wasn't found, so no thumbnails are rendered: { $error }
+index-pdftoppm-failed = Failed to run pdftoppm, so no thumbnails are rendered: { $error }
+
+## librarian capture
+
+capture-existing = The library already has this file as "{ $title }".
+capture-done = Captured "{ $title }" to the inbox.
+
+## hooks
+
+hook-failed = The { $event } hook failed: { $error }
+
+## Resource selection
+
+pick-prompt = { $purpose } (1-{ $count }), leave empty to cancel:
+pick-change = Resource to change
+pick-open = Resource to open
+pick-archive = Resource to archive
+pick-note = Resource to write notes for
+pick-versions = Resource to list
+pick-checkout = Resource to restore
+
+## Missing files
+
+resource-missing = Resource { $key } is missing, skipping.
+attachment-missing = Attachment { $name } of { $key } is missing, skipping.
+
+## librarian import and import-csv
+
+import-latex-name = Ignoring name with LaTeX commands: { $name }
+import-invalid-identifier = Ignoring invalid identifier: { $error }
+import-file-prompt = File for { $entry }, leave empty to skip:
+import-not-file = { $path } is not a file.
+import-no-file = No file for { $entry }, skipping.
+import-summary = Created { $created ->
+        [one] 1 resource
+       *[other] { $created } resources
+    }, updated { $updated } and skipped { $skipped }.
+import-csv-missing-column = The CSV file has no column { $column }.
+
+## librarian cite and unused
+
+cite-skipped = Skipping { $path }: { $error }
+cite-summary = { $cited ->
+        [one] 1 cited key
+       *[other] { $cited } cited keys
+    }, { $cataloged } in the catalog.
+cite-missing = Cited keys not in the catalog:
+unused-summary = { $count } of { $total } catalog entries are never cited.
+
+## librarian fetch, fetch-file and add-url
+
+fetch-existing = { $source } is already a resource ({ $key }).
+download-resuming = Download of { $url } was interrupted ({ $error }), resuming.
+download-interrupted = Download of { $url } was interrupted ({ $error }). Run the command again to resume it.
+download-checksum-mismatch = { $url } has the checksum { $checksum } instead of { $expected }
+add-url-asset-failed = Failed to download { $url }: { $error }
+
+## librarian styles
+
+styles-built-in = { $id }: { $title } (built in)
+styles-locales = Locales: { $locales }
+styles-added-style = Added style { $id }.
+styles-added-locale = Added locale { $id }.
+
+## --timings
+
+timings-header = Timings:
@@ -0,0 +1,104 @@
+use crate::mirror::ancillary_files;
+use crate::storage::Storage;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Ancillary directories whose files are written while working with a
+/// resource. Thumbnails are generated, so they don't count as activity.
+const ACTIVITY_DIRS: [&str; 3] = ["notes", "quotes", "annotations"];
+
+/// Search ranking boost of resources that were recently worked with.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct RecentActivity {
+    /// Boost of a resource worked with just now, as a fraction of its
+    /// search score (e.g., 1.0 doubles the score).
+    #[serde(default = "RecentActivity::default_weight")]
+    pub weight: f64,
+    /// Number of days after which the boost has decayed to half.
+    #[serde(default = "RecentActivity::default_half_life")]
+    pub half_life_days: f64,
+}
+
+impl RecentActivity {
+    fn default_weight() -> f64 {
+        1.0
+    }
+
+    fn default_half_life() -> f64 {
+        14.0
+    }
+
+    /// Factor a search score is multiplied by for a resource last worked
+    /// with at `last`.
+    ///
+    /// # Arguments
+    ///
+    /// * `last` - Time of the most recent activity, if any.
+    /// * `now` - Current time.
+    pub fn factor(&self, last: Option<SystemTime>, now: SystemTime) -> f64 {
+        let last = match last {
+            Some(l) => l,
+            None => return 1.0,
+        };
+        let days = now
+            .duration_since(last)
+            .map(|d| d.as_secs_f64() / 86400.0)
+            .unwrap_or(0.0);
+        1.0 + self.weight * 0.5_f64.powf(days / self.half_life_days)
+    }
+}
+
+/// Time a resource was last opened or annotated.
+///
+/// Opening is detected from the access time of the resource file, and
+/// annotating from the modification time of its notes, quotes and
+/// annotations. Filesystems mounted with `noatime` never update access
+/// times, in which case only annotations count.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `storage` - Resources directory of the library.
+/// * `key` - Initial checksum of the resource.
+pub fn last_activity(
+    library: &Path,
+    storage: &Storage,
+    key: &str,
+) -> Option<SystemTime> {
+    let opened = std::fs::metadata(storage.resource_path(key))
+        .and_then(|m| m.accessed())
+        .ok();
+    let annotated = ancillary_files(library, key)
+        .into_iter()
+        .filter(|f| ACTIVITY_DIRS.iter().any(|d| f.starts_with(d)))
+        .filter_map(|f| {
+            std::fs::metadata(library.join(f))
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .max();
+    opened.max(annotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_activity_factor() {
+        let boost = RecentActivity {
+            weight: 1.0,
+            half_life_days: 7.0,
+        };
+        let now = SystemTime::now();
+        let days = |d: u64| Some(now - Duration::from_secs(d * 86400));
+        assert!(boost.factor(None, now) == 1.0);
+        assert!(boost.factor(days(0), now) == 2.0);
+        assert!(boost.factor(days(7), now) == 1.5);
+        assert!(boost.factor(days(14), now) == 1.25);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::i18n::message;
+
+use std::path::Path;
+
+/// Name of the file in the library directory listing the ancillary
+/// stores that were skipped and must be rebuilt, one per line.
+const REBUILD_QUEUE: &str = ".rebuild";
+
+/// Ancillary stores queued for rebuild.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+pub fn queued_rebuilds(library: &Path) -> Vec<String> {
+    std::fs::read_to_string(library.join(REBUILD_QUEUE))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn write_queue(library: &Path, queue: &[String]) {
+    let path = library.join(REBUILD_QUEUE);
+    if queue.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path).expect("Failed to remove rebuild queue");
+        }
+    } else {
+        std::fs::write(path, queue.join("\n") + "\n")
+            .expect("Failed to write rebuild queue");
+    }
+}
+
+/// Queue an ancillary store for rebuild.
+pub fn queue_rebuild(library: &Path, store: &str) {
+    let mut queue = queued_rebuilds(library);
+    if !queue.iter().any(|s| s == store) {
+        queue.push(store.to_string());
+        write_queue(library, &queue);
+    }
+}
+
+/// Remove an ancillary store from the rebuild queue after it has been
+/// rebuilt.
+pub fn mark_rebuilt(library: &Path, store: &str) {
+    let mut queue = queued_rebuilds(library);
+    let len = queue.len();
+    queue.retain(|s| s != store);
+    if queue.len() != len {
+        write_queue(library, &queue);
+    }
+}
+
+/// Read an ancillary store, such as the cache, without letting a
+/// corrupted store stop the command.
+///
+/// Ancillary stores only hold data that can be regenerated from the
+/// catalog and the resources, so the command can always run without
+/// them.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `store` - Name of the store, e.g., "cache".
+/// * `safe_mode` - Skip the store without reading it.
+/// * `read` - Function reading the store.
+///
+/// # Returns
+///
+/// `None` if the store was skipped, which happens in safe mode, when it
+/// fails to parse and when it's already queued for rebuild. The store is
+/// then queued for rebuild and the caller should proceed as if it were
+/// empty.
+pub fn read_ancillary<T>(
+    library: &Path,
+    store: &str,
+    safe_mode: bool,
+    read: impl FnOnce() -> Result<T, String>,
+) -> Option<T> {
+    if safe_mode {
+        eprintln!(
+            "{}",
+            message("ancillary-safe-mode", &[("store", store.into())])
+        );
+        queue_rebuild(library, store);
+        return None;
+    }
+    if queued_rebuilds(library).iter().any(|s| s == store) {
+        eprintln!(
+            "{}",
+            message("ancillary-queued", &[("store", store.into())])
+        );
+        return None;
+    }
+    match read() {
+        Ok(t) => Some(t),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                message(
+                    "ancillary-corrupted",
+                    &[("store", store.into()), ("error", e.into())]
+                )
+            );
+            queue_rebuild(library, store);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_ancillary() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-ancillary-{}", std::process::id()));
+        std::fs::create_dir_all(&library).unwrap();
+
+        assert!(read_ancillary(&library, "cache", false, || Ok(1)) == Some(1));
+        let corrupted: Result<i32, String> = Err(String::from("EOF"));
+        assert!(
+            read_ancillary(&library, "cache", false, || corrupted).is_none()
+        );
+        assert!(queued_rebuilds(&library) == vec!["cache"]);
+        // Queued stores are skipped until they have been rebuilt.
+        assert!(read_ancillary(&library, "cache", false, || Ok(1)).is_none());
+        assert!(read_ancillary(&library, "links", true, || Ok(1)).is_none());
+        assert!(queued_rebuilds(&library) == vec!["cache", "links"]);
+        mark_rebuilt(&library, "cache");
+        mark_rebuilt(&library, "links");
+        assert!(!library.join(REBUILD_QUEUE).exists());
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::resource::{Attachment, Resource};
+use crate::storage::Storage;
+
+use std::path::Path;
+
+/// Problem with an attachment found by `attachment_problems`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachmentProblem {
+    /// The file of the attachment doesn't exist.
+    Missing,
+    /// The file's checksum differs from the recorded one, which is
+    /// given.
+    Modified(String),
+}
+
+/// Attachments of a resource whose files are missing or were modified
+/// since they were attached.
+///
+/// # Arguments
+///
+/// * `storage` - Resources directory of the library.
+/// * `resource` - Resource whose attachments are checked.
+pub fn attachment_problems<'a>(
+    storage: &Storage,
+    resource: &'a Resource,
+) -> Vec<(&'a Attachment, AttachmentProblem)> {
+    let dir = storage.attachments_path(&resource.historical_checksums[0]);
+    resource
+        .attachments
+        .iter()
+        .flatten()
+        .filter_map(|a| {
+            let path = dir.join(&a.name);
+            if !path.is_file() {
+                return Some((a, AttachmentProblem::Missing));
+            }
+            let checksum = file_checksum(&path);
+            match checksum == a.checksum {
+                true => None,
+                false => Some((a, AttachmentProblem::Modified(checksum))),
+            }
+        })
+        .collect()
+}
+
+/// Resource with a current or historical checksum.
+fn find_resource<'a>(
+    catalog: &'a mut Catalog,
+    checksum: &str,
+) -> &'a mut Resource {
+    catalog
+        .resource_mut(checksum)
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum))
+}
+
+/// Attach files to a resource. The files are copied into the resources
+/// directory.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum of the resource.
+/// * `files` - Files to attach.
+/// * `description` - What the files hold (e.g., "slides").
+pub fn librarian_attachment_add(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    checksum: &str,
+    files: &[&str],
+    description: Option<&str>,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let resource = find_resource(catalog, checksum);
+    let dir = storage.attachments_path(&resource.historical_checksums[0]);
+    for file in files {
+        let file = Path::new(file);
+        if !file.is_file() {
+            panic!("{} is not a file", file.display());
+        }
+        let name = file
+            .file_name()
+            .expect("attachment has no file name")
+            .to_string_lossy()
+            .to_string();
+        let attachments = resource.attachments.get_or_insert_with(Vec::new);
+        if attachments.iter().any(|a| a.name == name) {
+            panic!("{} already has an attachment named {}", checksum, name);
+        }
+        if !storage.is_dry_run() {
+            std::fs::create_dir_all(&dir)
+                .expect("Failed to create attachments directory");
+            std::fs::copy(file, dir.join(&name)).expect(
+                "Failed to copy attachment into the resources directory",
+            );
+        }
+        attachments.push(Attachment {
+            name,
+            checksum: file_checksum(file),
+            description: description.map(String::from),
+        });
+    }
+    store.write(catalog);
+}
+
+/// Remove an attachment from a resource, deleting its file.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum of the resource.
+/// * `name` - File name of the attachment.
+pub fn librarian_attachment_rm(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    checksum: &str,
+    name: &str,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let resource = find_resource(catalog, checksum);
+    let attachments = resource.attachments.get_or_insert_with(Vec::new);
+    let len = attachments.len();
+    attachments.retain(|a| a.name != name);
+    if attachments.len() == len {
+        panic!("{} has no attachment named {}", checksum, name);
+    }
+    if attachments.is_empty() {
+        resource.attachments = None;
+    }
+    let dir = storage.attachments_path(&resource.historical_checksums[0]);
+    if !storage.is_dry_run() {
+        if dir.join(name).exists() {
+            std::fs::remove_file(dir.join(name))
+                .expect("Failed to remove attachment");
+        }
+        // This fails if other attachments remain, which is fine.
+        std::fs::remove_dir(&dir).ok();
+    }
+    store.write(catalog);
+}
+
+/// Print the path of each attachment of a resource, along with its
+/// description.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum of the resource.
+pub fn librarian_attachment_list(
+    catalog: &Catalog,
+    resources_path: &Path,
+    checksum: &str,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let resource = catalog
+        .resources
+        .iter()
+        .find(|r| {
+            r.checksum == checksum
+                || r.historical_checksums.iter().any(|c| c == checksum)
+        })
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum));
+    let dir = storage.attachments_path(&resource.historical_checksums[0]);
+    for attachment in resource.attachments.iter().flatten() {
+        match &attachment.description {
+            Some(d) => {
+                println!("{} ({})", dir.join(&attachment.name).display(), d)
+            }
+            None => println!("{}", dir.join(&attachment.name).display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageLayout;
+
+    #[test]
+    fn test_attachment_problems() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-attachments-{}", std::process::id()));
+        let storage = Storage::new(&dir, StorageLayout::Flat);
+        std::fs::create_dir_all(storage.attachments_path("aa")).unwrap();
+        let slides = storage.attachments_path("aa").join("slides.pdf");
+        std::fs::write(&slides, "slides").unwrap();
+        let mut resource: Resource = serde_json::from_str(
+            "{\"title\": \"A\", \"checksum\": \"ab\", \"historical_checksums\": [\"aa\", \"ab\"]}",
+        )
+        .unwrap();
+        resource.attachments = Some(vec![
+            Attachment {
+                name: String::from("slides.pdf"),
+                checksum: file_checksum(&slides),
+                description: None,
+            },
+            Attachment {
+                name: String::from("errata.txt"),
+                checksum: String::from("00"),
+                description: None,
+            },
+        ]);
+        let problems = attachment_problems(&storage, &resource);
+        assert!(problems.len() == 1);
+        assert!(problems[0].0.name == "errata.txt");
+        assert!(problems[0].1 == AttachmentProblem::Missing);
+
+        std::fs::write(&slides, "revised slides").unwrap();
+        let problems = attachment_problems(&storage, &resource);
+        assert!(
+            problems[0].1
+                == AttachmentProblem::Modified(file_checksum(&slides))
+        );
+        // Attachments aren't resources.
+        assert!(storage.entries().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Name of the audit log in the library directory.
+const AUDIT_LOG: &str = ".audit";
+
+/// Record of one operation that modified the catalog.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Number of seconds since "the epoch".
+    pub time: u64,
+    /// Subcommand that performed the operation.
+    pub operation: String,
+    /// Initial checksums of the affected resources.
+    pub resources: Vec<String>,
+    /// Description of each change applied to the resources.
+    pub changes: Vec<String>,
+}
+
+impl AuditEntry {
+    pub fn new(
+        operation: &str,
+        resources: Vec<String>,
+        changes: Vec<String>,
+    ) -> AuditEntry {
+        AuditEntry {
+            time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            operation: operation.to_string(),
+            resources,
+            changes,
+        }
+    }
+}
+
+/// Append an entry to the library's audit log.
+///
+/// The log is kept in the library directory, alongside the cache, with
+/// one JSON object per line so that appending never rewrites earlier
+/// entries.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `entry` - Entry to append.
+pub fn record(library: &Path, entry: &AuditEntry) {
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(library.join(AUDIT_LOG))
+        .expect("Failed to open audit log");
+    writeln!(log, "{}", serde_json::to_string(entry).unwrap())
+        .expect("Failed to write audit log");
+}
@@ -0,0 +1,312 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::{Name, NameFormat, Resource};
+
+use indexmap::IndexMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+impl Catalog {
+    /// Canonical spelling of an author's name, according to the
+    /// catalog's author aliases. Names without an alias are their own
+    /// canonical spelling.
+    pub fn canonical_author(&self, name: &Name) -> String {
+        let name = name.format(NameFormat::FirstLast);
+        self.author_aliases
+            .iter()
+            .find(|(_, variants)| variants.contains(&name))
+            .map(|(canonical, _)| canonical.clone())
+            .unwrap_or(name)
+    }
+
+    /// Replace one spelling of a name with another in the authors and
+    /// editors of every resource. An alias entry of the replaced
+    /// spelling is merged into the entry of the new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Spelling that is replaced.
+    /// * `to` - Spelling that replaces it.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the resources that changed.
+    pub fn merge_author(&mut self, from: &Name, to: &Name) -> Vec<String> {
+        let spelling = |n: &Name| n.format(NameFormat::FirstLast);
+        let (from_spelling, to_spelling) = (spelling(from), spelling(to));
+        let mut changed = vec![];
+        for resource in self.resources.iter_mut() {
+            let mut modified = false;
+            for names in
+                resource.author.iter_mut().chain(resource.editor.iter_mut())
+            {
+                if !names.iter().any(|n| spelling(n) == from_spelling) {
+                    continue;
+                }
+                let mut merged: Vec<Name> = vec![];
+                for name in names.drain(..) {
+                    let name = match spelling(&name) == from_spelling {
+                        true => to.clone(),
+                        false => name,
+                    };
+                    if !merged.contains(&name) {
+                        merged.push(name);
+                    }
+                }
+                *names = merged;
+                modified = true;
+            }
+            if modified {
+                changed.push(resource.historical_checksums[0].clone());
+            }
+        }
+        if let Some(variants) = self.author_aliases.shift_remove(&from_spelling)
+        {
+            let entry =
+                self.author_aliases.entry(to_spelling.clone()).or_default();
+            for variant in variants {
+                if variant != to_spelling && !entry.contains(&variant) {
+                    entry.push(variant);
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Whether one part of a given name could be the same as another, either
+/// because they're equal or because one is the initial of the other.
+fn compatible_part(a: &str, b: &str) -> bool {
+    let initial = |p: &str| {
+        let p = p.trim_end_matches('.');
+        match p.chars().count() == 1 {
+            true => p.chars().next(),
+            false => None,
+        }
+    };
+    match (initial(a), initial(b)) {
+        (Some(x), _) => b.starts_with(x),
+        (_, Some(y)) => a.starts_with(y),
+        (None, None) => a.eq_ignore_ascii_case(b),
+    }
+}
+
+/// Whether two differently written names likely belong to the same
+/// person (e.g., "R. P. Feynman" and "Richard Phillips Feynman").
+fn likely_same_person(a: &Name, b: &Name) -> bool {
+    let last = |n: &Name| n.last.as_ref().map(|l| l.to_lowercase());
+    if last(a).is_none() || last(a) != last(b) {
+        return false;
+    }
+    match (&a.first, &b.first) {
+        (Some(x), Some(y)) => {
+            compatible_part(x, y)
+                && match (&a.middle, &b.middle) {
+                    (Some(x), Some(y)) => compatible_part(x, y),
+                    _ => true,
+                }
+        }
+        _ => false,
+    }
+}
+
+/// Group resources by author, merging the spelling variants listed in
+/// the catalog's author aliases.
+///
+/// # Returns
+///
+/// The resources of each author, keyed by the canonical name and sorted
+/// by last name.
+pub fn resources_by_author(
+    catalog: &Catalog,
+) -> IndexMap<String, (Name, Vec<&Resource>)> {
+    let mut authors = IndexMap::<String, (Name, Vec<&Resource>)>::new();
+    for resource in &catalog.resources {
+        for name in resource.author.iter().flatten() {
+            let canonical = catalog.canonical_author(name);
+            let entry = authors.entry(canonical.clone()).or_insert_with(|| {
+                let name = Name::try_from(canonical.as_str())
+                    .unwrap_or_else(|_| name.clone());
+                (name, vec![])
+            });
+            if !entry.1.iter().any(|r| std::ptr::eq(*r, resource)) {
+                entry.1.push(resource);
+            }
+        }
+    }
+    authors.sort_by(|_, (a, _), _, (b, _)| {
+        a.format(NameFormat::LastFirst)
+            .to_lowercase()
+            .cmp(&b.format(NameFormat::LastFirst).to_lowercase())
+    });
+    authors
+}
+
+/// List the authors in the library along with their resources.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `author` - Only list the resources of this author, who can be
+///   given by any of the spellings in the author aliases.
+/// * `suggest` - Instead of listing authors, print pairs of names that
+///   likely belong to the same person but aren't aliased yet.
+pub fn librarian_authors(
+    catalog: &Catalog,
+    author: Option<&str>,
+    suggest: bool,
+) {
+    let authors = resources_by_author(catalog);
+
+    if suggest {
+        let names: Vec<(&String, &Name)> =
+            authors.iter().map(|(k, (n, _))| (k, n)).collect();
+        for (i, (a, a_name)) in names.iter().enumerate() {
+            for (b, b_name) in &names[i + 1..] {
+                if likely_same_person(a_name, b_name) {
+                    println!("{} / {}", a, b);
+                }
+            }
+        }
+        return;
+    }
+
+    let wanted = author.map(|a| match Name::try_from(a) {
+        Ok(n) => catalog.canonical_author(&n),
+        Err(_) => a.to_string(),
+    });
+    for (canonical, (name, resources)) in &authors {
+        if wanted.as_ref().is_some_and(|w| w != canonical) {
+            continue;
+        }
+        println!("{} ({})", name.format(catalog.name_format), resources.len());
+        for resource in resources {
+            println!("    {} ({})", resource.title, resource.bibtex_key());
+        }
+    }
+}
+
+/// Rewrite an author's name in every resource that has it, e.g., after
+/// `librarian authors --suggest` found two spellings of the same person.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `from` - Spelling of the name that is replaced.
+/// * `to` - Spelling of the name that replaces it.
+pub fn librarian_merge_authors(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    from: &str,
+    to: &str,
+) {
+    let parse = |n: &str| {
+        Name::try_from(n)
+            .unwrap_or_else(|e| panic!("Invalid name {}: {}", n, e))
+    };
+    let (from, to) = (parse(from), parse(to));
+    let keys = catalog.merge_author(&from, &to);
+    let (from, to) = (
+        from.format(NameFormat::FirstLast),
+        to.format(NameFormat::FirstLast),
+    );
+    if !keys.is_empty() {
+        catalog.assign_citekeys();
+        store.write(catalog);
+        if !catalog.dry_run {
+            record(
+                resources_path
+                    .parent()
+                    .expect("resources path does not have a parent"),
+                &AuditEntry::new(
+                    "authors merge",
+                    keys.clone(),
+                    keys.iter()
+                        .map(|k| format!("{} author: {} -> {}", k, from, to))
+                        .collect(),
+                ),
+            );
+        }
+    }
+    println!(
+        "{}",
+        message(
+            "authors-merged",
+            &[
+                ("from", from.into()),
+                ("to", to.into()),
+                ("count", keys.len().into())
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resources_by_author() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"author_aliases\": {\"Richard Phillips Feynman\": [\"R. P. Feynman\", \"Richard Feynman\"]},
+              \"resources\": [
+              {\"title\": \"QED\", \"author\": [\"Richard Phillips Feynman\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Lectures\", \"author\": [\"R. P. Feynman\", \"Robert Leighton\"], \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Path Integrals\", \"author\": [\"R. Feynman\"], \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let authors = resources_by_author(&catalog);
+        let names: Vec<&String> = authors.keys().collect();
+        assert!(
+            names
+                == vec![
+                    "R. Feynman",
+                    "Richard Phillips Feynman",
+                    "Robert Leighton"
+                ]
+        );
+        assert!(authors["Richard Phillips Feynman"].1.len() == 2);
+        assert!(likely_same_person(
+            &authors["R. Feynman"].0,
+            &authors["Richard Phillips Feynman"].0
+        ));
+        assert!(!likely_same_person(
+            &authors["Robert Leighton"].0,
+            &authors["Richard Phillips Feynman"].0
+        ));
+    }
+
+    #[test]
+    fn test_merge_author() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"author_aliases\": {\"R. Feynman\": [\"Dick Feynman\"]},
+              \"resources\": [
+              {\"title\": \"QED\", \"author\": [\"R. Feynman\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Lectures\", \"author\": [\"R. Feynman\", \"Richard P. Feynman\"], \"editor\": [\"R. Feynman\"],
+               \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Surely\", \"author\": [\"Ralph Leighton\"], \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let to = Name::try_from("Richard P. Feynman").unwrap();
+        let changed =
+            catalog.merge_author(&Name::try_from("R. Feynman").unwrap(), &to);
+        assert!(changed == vec!["aa", "bb"]);
+        assert!(catalog.resources[0].author == Some(vec![to.clone()]));
+        // Both spellings in one list collapse into one author.
+        assert!(catalog.resources[1].author == Some(vec![to.clone()]));
+        assert!(catalog.resources[1].editor == Some(vec![to]));
+        assert!(
+            catalog.author_aliases["Richard P. Feynman"]
+                == vec!["Dick Feynman"]
+        );
+        assert!(!catalog.author_aliases.contains_key("R. Feynman"));
+    }
+}
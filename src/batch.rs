@@ -0,0 +1,219 @@
+use crate::i18n::message;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Directory of the library holding the checkpoints of interrupted
+/// batch operations.
+const CHECKPOINTS_DIR: &str = ".checkpoints";
+
+/// Set once the user interrupts a batch operation.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // A second Ctrl-C terminates immediately.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Whether the user interrupted the batch operation.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Handle Ctrl-C by letting the running batch operation finish the
+/// items in flight and write its results, instead of terminating.
+pub fn catch_interrupt() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// Process items on up to `jobs` threads at a time.
+///
+/// Results are handed to `done` on the calling thread as they become
+/// available, so it can update shared state such as the catalog. No new
+/// items are started once the user interrupts the operation (see
+/// `catch_interrupt`), but items in flight are finished.
+///
+/// # Arguments
+///
+/// * `items` - Items to process.
+/// * `jobs` - Maximum number of items processed at the same time.
+/// * `work` - Processing of an item.
+/// * `done` - Handling of the result of an item.
+pub fn run<T, R>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut done: impl FnMut(&T, R),
+) where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    let (next, work) = (&next, &work);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(items.len()) {
+            let tx = tx.clone();
+            scope.spawn(move || loop {
+                if interrupted() {
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= items.len() {
+                    break;
+                }
+                tx.send((i, work(&items[i]))).unwrap();
+            });
+        }
+        drop(tx);
+        for (i, result) in rx {
+            done(&items[i], result);
+        }
+    });
+}
+
+/// Write a file by renaming a complete temporary file over it, so that
+/// an interruption never leaves a partially written file behind.
+pub fn write_atomically(path: &Path, contents: &str) {
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, contents).unwrap_or_else(|e| {
+        panic!("Failed to write {}: {}", path.display(), e)
+    });
+    std::fs::rename(&temporary, path).unwrap_or_else(|e| {
+        panic!("Failed to write {}: {}", path.display(), e)
+    });
+}
+
+/// Progress of a batch operation, which an interrupted operation leaves
+/// in the library so that it can be resumed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Checkpoint {
+    /// Initial checksums of the resources already processed.
+    pub done: HashSet<String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Checkpoint of an operation. The checkpoint left by an earlier
+    /// run is continued when `resume` is set, and discarded otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `library` - Library directory.
+    /// * `operation` - Subcommand of the operation.
+    /// * `resume` - Continue the earlier run.
+    pub fn open(library: &Path, operation: &str, resume: bool) -> Checkpoint {
+        let path = library
+            .join(CHECKPOINTS_DIR)
+            .join(format!("{}.json", operation));
+        let mut checkpoint: Checkpoint = match resume {
+            true => std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|c| serde_json::from_str(&c).ok())
+                .unwrap_or_default(),
+            false => Checkpoint::default(),
+        };
+        checkpoint.path = path;
+        if !checkpoint.done.is_empty() {
+            println!(
+                "{}",
+                message(
+                    "batch-resuming",
+                    &[("done", checkpoint.done.len().into())]
+                )
+            );
+        }
+        checkpoint
+    }
+
+    /// Record the end of a run. An interrupted run saves the checkpoint
+    /// and reports how to resume it, while a complete run removes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Subcommand of the operation.
+    /// * `total` - Number of items of the operation, including those
+    ///   processed by earlier runs.
+    pub fn finish(&self, operation: &str, total: usize) {
+        if self.done.len() < total {
+            std::fs::create_dir_all(self.path.parent().unwrap())
+                .expect("Failed to create checkpoints directory");
+            write_atomically(&self.path, &serde_json::to_string(self).unwrap());
+            println!(
+                "{}",
+                message(
+                    "batch-interrupted",
+                    &[
+                        ("done", self.done.len().into()),
+                        ("total", total.into()),
+                        ("operation", operation.into()),
+                    ]
+                )
+            );
+        } else if self.path.exists() {
+            std::fs::remove_file(&self.path)
+                .expect("Failed to remove checkpoint");
+            std::fs::remove_dir(self.path.parent().unwrap()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run() {
+        let items: Vec<u64> = (0..20).collect();
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let mut results = vec![];
+        run(
+            &items,
+            3,
+            |i| {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(n, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i * 2
+            },
+            |i, r| results.push((*i, r)),
+        );
+        results.sort();
+        assert!(
+            results == items.iter().map(|i| (*i, i * 2)).collect::<Vec<_>>()
+        );
+        assert!(most.load(Ordering::SeqCst) <= 3);
+
+        let library = std::env::temp_dir()
+            .join(format!("librarian-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&library).unwrap();
+        let mut checkpoint = Checkpoint::open(&library, "enrich", true);
+        checkpoint.done.insert(String::from("aa"));
+        checkpoint.finish("enrich", 2);
+        let mut resumed = Checkpoint::open(&library, "enrich", true);
+        assert!(resumed.done.contains("aa"));
+        assert!(Checkpoint::open(&library, "enrich", false).done.is_empty());
+        resumed.done.insert(String::from("bb"));
+        resumed.finish("enrich", 2);
+        assert!(!library.join(CHECKPOINTS_DIR).exists());
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
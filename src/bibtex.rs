@@ -1,15 +1,25 @@
 use crate::catalog::Catalog;
-use crate::resource::{Name, Resource};
+use crate::hooks::run_hook;
+use crate::i18n::message;
+use crate::instance::attachments_dir;
+use crate::resource::{DateTime, Name, Resource};
+use crate::search::ResourceFilter;
+use crate::storage::Storage;
 
 use indexmap::IndexMap;
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
 
 /// BibTeX entry types.
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, Eq, PartialEq,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum BibtexType {
     Article,
@@ -26,48 +36,788 @@ pub enum BibtexType {
     Video,
 }
 
+/// Content type as defined in the catalog.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ContentType {
+    /// BibTeX type of the content type's resources, which are serialized
+    /// with the default field mapping.
+    Bibtex(BibtexType),
+    /// BibTeX type of the content type's resources along with changes
+    /// to the default field mapping.
+    Mapped {
+        bibtex: BibtexType,
+        /// BibTeX fields mapped to the resource field supplying their
+        /// value (e.g., "institution": "organization"), or to null to
+        /// omit the BibTeX field.
+        #[serde(default)]
+        fields: IndexMap<String, Option<String>>,
+    },
+}
+
+impl ContentType {
+    /// BibTeX type of the content type's resources.
+    pub fn bibtex_type(&self) -> &BibtexType {
+        match self {
+            ContentType::Bibtex(bt) => bt,
+            ContentType::Mapped { bibtex, .. } => bibtex,
+        }
+    }
+}
+
+/// Fields whose values are used verbatim by BibLaTeX and must not be
+/// escaped.
+const VERBATIM_FIELDS: [&str; 4] = ["url", "doi", "file", "eprint"];
+
+/// Escape the characters of a field value that are special to LaTeX
+/// (e.g., "&" -> "\&") and convert non-ASCII characters to LaTeX
+/// escapes where possible.
+///
+/// Existing escapes and LaTeX commands (anything following a
+/// backslash) and inline math (between a pair of dollar signs) are left
+/// alone, so values that already contain LaTeX aren't escaped twice. A
+/// dollar sign without a closing one is escaped. Matched braces are
+/// also left alone since they're commonly used to protect
+/// capitalization, while unmatched braces, which would end the field
+/// early, are written as commands. BibTeX counts braces even when
+/// they're escaped, so "\{" isn't enough.
+fn bibtex_escape(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let mut unmatched = HashSet::new();
+    let mut open = vec![];
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            '{' => open.push(i),
+            '}' if open.pop().is_none() => {
+                unmatched.insert(i);
+            }
+            _ => (),
+        }
+    }
+    unmatched.extend(open);
+    let brace = |c: char| match c {
+        '{' => "\\textbraceleft{}",
+        _ => "\\textbraceright{}",
+    };
+
+    let mut escaped = String::new();
+    let mut math = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => match chars.get(i + 1) {
+                Some(&n) if unmatched.contains(&(i + 1)) => {
+                    escaped.push_str(brace(n));
+                    i += 1;
+                }
+                Some(&n) => {
+                    escaped.push(c);
+                    escaped.push(n);
+                    i += 1;
+                }
+                None => escaped.push_str("\\textbackslash{}"),
+            },
+            '{' | '}' if unmatched.contains(&i) => escaped.push_str(brace(c)),
+            '$' if math => {
+                math = false;
+                escaped.push(c);
+            }
+            '$' => {
+                let closed = (i + 1..chars.len())
+                    .any(|j| chars[j] == '$' && chars[j - 1] != '\\');
+                match closed {
+                    true => {
+                        math = true;
+                        escaped.push(c);
+                    }
+                    false => escaped.push_str("\\$"),
+                }
+            }
+            _ if math => escaped.push(c),
+            '&' | '%' | '_' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            _ if c.is_ascii() => escaped.push(c),
+            // Characters without a LaTeX equivalent are kept, since
+            // they still work with BibLaTeX and biber.
+            _ => match latex_escape(&c.to_string()) {
+                Ok(e) => escaped.push_str(&e),
+                Err(_) => escaped.push(c),
+            },
+        }
+        i += 1;
+    }
+    escaped
+}
+
 fn bibtex_serialize_field(field: &str, value: Option<String>) -> String {
     match value {
         Some(v) => {
             let indent = "    ";
-            // Escape any unescaped ampersands (i.e., '&' -> '\&', but
-            // '\&' -> '\&').
-            let re = Regex::new(r"(?P<c>[^\\])&").unwrap();
-            let v = re.replace_all(&v, r"$c\&");
+            let v = match VERBATIM_FIELDS.contains(&field) {
+                true => v,
+                false => bibtex_escape(&v),
+            };
             format!("{}{}={{{}}},\n", indent, field, v)
         }
         None => String::new(),
     }
 }
 
-/// Serialize a list of names into a BibTeX format.
+/// Wrap the value of title fields in an extra pair of braces, which
+/// keeps BibTeX styles from changing their capitalization.
+fn protect_title(field: &str, value: String) -> String {
+    match field {
+        "title" | "subtitle" => format!("{{{}}}", value),
+        _ => value,
+    }
+}
+
+/// Join a list of names as expected by BibTeX name fields.
+fn bibtex_names(names: Option<Vec<Name>>) -> Option<String> {
+    match names {
+        Some(x) if !x.is_empty() => Some(
+            x.iter()
+                .map(|n| n.bibtex())
+                .collect::<Vec<String>>()
+                .join(" and "),
+        ),
+        _ => None,
+    }
+}
+
+/// Fields that only make sense on the local machine or to the owner of
+/// the library, which are removed from sanitized entries.
+const LOCAL_FIELDS: [&str; 4] = ["file", "keywords", "annotation", "note"];
+
+/// Fields that journals commonly require for each entry type. Fields
+/// separated by a slash are alternatives, only one of which is
+/// required.
+pub fn required_fields(bibtex_type: &BibtexType) -> &'static [&'static str] {
+    match bibtex_type {
+        BibtexType::Article => &["author", "title", "journal", "year"],
+        BibtexType::Book => &["author/editor", "title", "publisher", "year"],
+        BibtexType::Collection => &["editor", "title", "publisher", "year"],
+        BibtexType::Online => &["title", "url"],
+        BibtexType::Patent => &["author", "title", "number", "year"],
+        BibtexType::Report | BibtexType::TechReport => {
+            &["author", "title", "institution", "year"]
+        }
+        BibtexType::Software => &["author", "title", "year"],
+        BibtexType::Image
+        | BibtexType::Manual
+        | BibtexType::Miscellaneous
+        | BibtexType::Video => &["title"],
+    }
+}
+
+/// Fields BibLaTeX requires for each entry type, according to its
+/// manual. Fields separated by a slash are alternatives, only one of
+/// which is required, and include the legacy BibTeX names BibLaTeX
+/// accepts in their place (e.g., "journal" for "journaltitle").
+pub fn biblatex_required_fields(
+    bibtex_type: &BibtexType,
+) -> &'static [&'static str] {
+    match bibtex_type {
+        BibtexType::Article => {
+            &["author", "title", "journaltitle/journal", "date/year"]
+        }
+        BibtexType::Book => &["author", "title", "date/year"],
+        BibtexType::Collection => &["editor", "title", "date/year"],
+        BibtexType::Online => {
+            &["author/editor", "title", "date/year/urldate", "url"]
+        }
+        BibtexType::Patent => &["author", "title", "number", "date/year"],
+        BibtexType::Report => {
+            &["author", "title", "type", "institution", "date/year"]
+        }
+        // techreport is an alias of report with its type already set.
+        BibtexType::TechReport => {
+            &["author", "title", "institution", "date/year"]
+        }
+        BibtexType::Manual
+        | BibtexType::Miscellaneous
+        | BibtexType::Software
+        | BibtexType::Image
+        | BibtexType::Video => &["author/editor", "title", "date/year"],
+    }
+}
+
+/// Required fields missing from an entry.
 ///
 /// # Arguments
 ///
-/// * `field` - Field identifier (e.g., "author").
-/// * `names` - Collection of names.
-fn bibtex_serialize_names(field: &str, names: Option<Vec<Name>>) -> String {
-    match names {
-        Some(x) => {
-            if x.len() > 0 {
-                bibtex_serialize_field(
-                    field,
-                    Some(
-                        x.iter()
-                            .map(|n| String::from(n.clone()))
-                            .collect::<Vec<String>>()
-                            .join(" and "),
-                    ),
-                )
-            } else {
-                String::new()
+/// * `required` - Required fields, as returned by `required_fields`.
+/// * `fields` - Fields of the entry.
+fn missing_fields<'a>(
+    required: &[&'a str],
+    fields: &[(&str, String)],
+) -> Vec<&'a str> {
+    required
+        .iter()
+        .filter(|r| !r.split('/').any(|r| fields.iter().any(|(f, _)| *f == r)))
+        .copied()
+        .collect()
+}
+
+/// Convert the non-ASCII characters of a field value to LaTeX escapes
+/// (e.g., "é" -> "{\'e}").
+///
+/// # Return
+///
+/// Returns the converted value, or the first character without a known
+/// LaTeX equivalent.
+pub(crate) fn latex_escape(value: &str) -> Result<String, char> {
+    let mut escaped = String::new();
+    let mut chars = value.nfd().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii() {
+            // Decomposed accented letters are followed by their
+            // combining accent.
+            let accent = match chars.peek() {
+                Some('\u{300}') => Some("`"),
+                Some('\u{301}') => Some("'"),
+                Some('\u{302}') => Some("^"),
+                Some('\u{303}') => Some("~"),
+                Some('\u{304}') => Some("="),
+                Some('\u{306}') => Some("u"),
+                Some('\u{307}') => Some("."),
+                Some('\u{308}') => Some("\""),
+                Some('\u{30a}') => Some("r"),
+                Some('\u{30b}') => Some("H"),
+                Some('\u{30c}') => Some("v"),
+                Some('\u{327}') => Some("c"),
+                Some('\u{328}') => Some("k"),
+                _ => None,
+            };
+            match accent {
+                Some(a) => {
+                    chars.next();
+                    // Letter accents need a space to separate them from
+                    // the letter, and dotless i and j are used below
+                    // accents.
+                    let letter = match c {
+                        'i' => String::from("\\i"),
+                        'j' => String::from("\\j"),
+                        _ => c.to_string(),
+                    };
+                    match a.chars().all(char::is_alphabetic) {
+                        true => {
+                            escaped.push_str(&format!("{{\\{} {}}}", a, letter))
+                        }
+                        false => {
+                            escaped.push_str(&format!("{{\\{}{}}}", a, letter))
+                        }
+                    }
+                }
+                None => escaped.push(c),
             }
+            continue;
         }
-        None => String::new(),
+        let replacement = match c {
+            'ß' => "{\\ss}",
+            'æ' => "{\\ae}",
+            'Æ' => "{\\AE}",
+            'œ' => "{\\oe}",
+            'Œ' => "{\\OE}",
+            'ø' => "{\\o}",
+            'Ø' => "{\\O}",
+            'ł' => "{\\l}",
+            'Ł' => "{\\L}",
+            'ı' => "{\\i}",
+            '–' => "--",
+            '—' => "---",
+            '‘' => "`",
+            '’' => "'",
+            '“' => "``",
+            '”' => "''",
+            '…' => "\\ldots{}",
+            '\u{a0}' => "~",
+            _ => return Err(c),
+        };
+        escaped.push_str(replacement);
+    }
+    Ok(escaped)
+}
+
+/// Convert the LaTeX escapes of a BibTeX field value to Unicode (e.g.,
+/// "Schr{\"o}dinger" -> "Schrödinger") and remove protective braces.
+pub fn latex_unescape(value: &str) -> String {
+    let accent = Regex::new(
+        r#"\\(?:([`'^"~=.])\s*(?:\{\s*(\\?[A-Za-z])\s*\}|(\\?[A-Za-z]))|([uvHckr])(?:\{\s*(\\?[A-Za-z])\s*\}|\s+(\\?[A-Za-z])))"#,
+    )
+    .unwrap();
+    let value = accent.replace_all(value, |c: &regex::Captures| {
+        let combining = match c.get(1).or_else(|| c.get(4)).unwrap().as_str() {
+            "`" => '\u{300}',
+            "'" => '\u{301}',
+            "^" => '\u{302}',
+            "~" => '\u{303}',
+            "=" => '\u{304}',
+            "u" => '\u{306}',
+            "." => '\u{307}',
+            "\"" => '\u{308}',
+            "r" => '\u{30a}',
+            "H" => '\u{30b}',
+            "v" => '\u{30c}',
+            "c" => '\u{327}',
+            _ => '\u{328}',
+        };
+        let letter = [2, 3, 5, 6]
+            .iter()
+            .find_map(|&i| c.get(i))
+            .unwrap()
+            .as_str();
+        format!("{}{}", letter.trim_start_matches('\\'), combining)
+    });
+    let symbol =
+        Regex::new(r"\\(ss|ae|AE|oe|OE|o|O|l|L|i|j)\b(?:\{\})?\s?").unwrap();
+    let value = symbol.replace_all(&value, |c: &regex::Captures| {
+        match &c[1] {
+            "ss" => "ß",
+            "ae" => "æ",
+            "AE" => "Æ",
+            "oe" => "œ",
+            "OE" => "Œ",
+            "o" => "ø",
+            "O" => "Ø",
+            "l" => "ł",
+            "L" => "Ł",
+            "i" => "ı",
+            _ => "ȷ",
+        }
+        .to_string()
+    });
+    let value = value.replace("---", "—").replace("--", "–");
+    // Escaped characters and text symbols are converted in the same
+    // pass as braces are removed, so that escaped braces are kept.
+    let special = Regex::new(
+        r"\\([&%_$#{}])|\\(ldots|textbackslash|textasciitilde|textasciicircum|textbraceleft|textbraceright)(?:\{\}|\s|\b)|~|[{}]",
+    )
+    .unwrap();
+    special
+        .replace_all(&value, |c: &regex::Captures| {
+            if let Some(escaped) = c.get(1) {
+                return escaped.as_str().to_string();
+            }
+            match c.get(2).map_or(&c[0], |s| s.as_str()) {
+                "ldots" => "…",
+                "textbackslash" => "\\",
+                "textasciitilde" => "~",
+                "textasciicircum" => "^",
+                "textbraceleft" => "{",
+                "textbraceright" => "}",
+                "~" => "\u{a0}",
+                _ => "",
+            }
+            .to_string()
+        })
+        .nfc()
+        .collect()
+}
+
+/// Entry of a BibTeX or BibLaTeX file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BibtexEntry {
+    /// Entry type in lowercase (e.g., "article").
+    pub entry_type: String,
+    pub key: String,
+    /// Field values with lowercase field names, in the order they
+    /// appear in the file. Values are stored as written, except that
+    /// `@string` abbreviations and concatenations are expanded and
+    /// whitespace is collapsed.
+    pub fields: IndexMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct BibtexParseError {
+    details: String,
+}
+
+impl BibtexParseError {
+    fn new(msg: &str) -> BibtexParseError {
+        BibtexParseError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for BibtexParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for BibtexParseError {
+    fn description(&self) -> &str {
+        &self.details
     }
 }
 
+/// Recursive descent parser over the contents of a BibTeX file.
+struct BibtexParser<'a> {
+    contents: &'a str,
+    pos: usize,
+    /// Abbreviations defined by `@string` entries.
+    strings: HashMap<String, String>,
+}
+
+impl<'a> BibtexParser<'a> {
+    fn new(contents: &'a str) -> BibtexParser<'a> {
+        let mut strings = HashMap::<String, String>::new();
+        // The standard styles predefine the month abbreviations.
+        for (i, m) in [
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep",
+            "oct", "nov", "dec",
+        ]
+        .iter()
+        .enumerate()
+        {
+            strings.insert(m.to_string(), (i + 1).to_string());
+        }
+        BibtexParser {
+            contents,
+            pos: 0,
+            strings,
+        }
+    }
+
+    fn error(&self, msg: &str) -> BibtexParseError {
+        let line = self.contents[..self.pos].matches('\n').count() + 1;
+        BibtexParseError::new(&format!("line {}: {}", line, msg))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.contents[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c.is_whitespace() {
+                true => self.pos += c.len_utf8(),
+                false => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), BibtexParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(x) if x == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{}'", c))),
+        }
+    }
+
+    /// Read an identifier, such as an entry type, field name or
+    /// abbreviation.
+    fn identifier(&mut self) -> Result<&'a str, BibtexParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            match c.is_whitespace() || "{}()=,#\"".contains(c) {
+                true => break,
+                false => self.pos += c.len_utf8(),
+            }
+        }
+        match self.pos > start {
+            true => Ok(&self.contents[start..self.pos]),
+            false => Err(self.error("expected an identifier")),
+        }
+    }
+
+    /// Read the contents of a group delimited by `open` and `close`,
+    /// starting just after `open`. Braces within the group must be
+    /// balanced.
+    fn group(&mut self, close: char) -> Result<&'a str, BibtexParseError> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            if depth == 0 && c == close {
+                let group = &self.contents[start..self.pos];
+                self.pos += 1;
+                return Ok(group);
+            }
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => (),
+            }
+            if depth < 0 {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        Err(self.error(&format!("unterminated group, expected '{}'", close)))
+    }
+
+    /// Read a field value, which is a concatenation (with `#`) of
+    /// braced strings, quoted strings, numbers and abbreviations.
+    fn value(&mut self) -> Result<String, BibtexParseError> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    value.push_str(self.group('}')?);
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    value.push_str(self.group('"')?);
+                }
+                Some(_) => {
+                    let id = self.identifier()?;
+                    match id.chars().all(|c| c.is_ascii_digit()) {
+                        true => value.push_str(id),
+                        false => match self.strings.get(&id.to_lowercase()) {
+                            Some(s) => value.push_str(s),
+                            None => {
+                                return Err(self.error(&format!(
+                                    "undefined abbreviation {}",
+                                    id
+                                )))
+                            }
+                        },
+                    }
+                }
+                None => return Err(self.error("expected a value")),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some('#') => self.pos += 1,
+                _ => break,
+            }
+        }
+        Ok(value.split_whitespace().collect::<Vec<&str>>().join(" "))
+    }
+
+    /// Parse every entry, ignoring any text between entries.
+    fn entries(&mut self) -> Result<Vec<BibtexEntry>, BibtexParseError> {
+        let mut entries = Vec::<BibtexEntry>::new();
+        while let Some(at) = self.contents[self.pos..].find('@') {
+            self.pos += at + 1;
+            let entry_type = self.identifier()?.to_lowercase();
+            self.skip_whitespace();
+            let close = match self.peek() {
+                Some('{') => '}',
+                Some('(') => ')',
+                // An '@' in the text between entries (e.g., in an email
+                // address) doesn't start an entry.
+                _ => continue,
+            };
+            self.pos += 1;
+
+            match entry_type.as_str() {
+                "comment" | "preamble" => {
+                    self.group(close)?;
+                }
+                "string" => {
+                    let name = self.identifier()?.to_lowercase();
+                    self.expect('=')?;
+                    let value = self.value()?;
+                    self.strings.insert(name, value);
+                    self.expect(close)?;
+                }
+                _ => {
+                    let key = self.identifier()?.to_string();
+                    let mut fields = IndexMap::<String, String>::new();
+                    loop {
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(',') => self.pos += 1,
+                            Some(c) if c == close => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => {
+                                return Err(self.error(&format!(
+                                    "expected ',' or '{}'",
+                                    close
+                                )))
+                            }
+                        }
+                        self.skip_whitespace();
+                        // Allow a trailing comma.
+                        if self.peek() == Some(close) {
+                            continue;
+                        }
+                        let name = self.identifier()?.to_lowercase();
+                        self.expect('=')?;
+                        let value = self.value()?;
+                        fields.insert(name, value);
+                    }
+                    entries.push(BibtexEntry {
+                        entry_type,
+                        key,
+                        fields,
+                    });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Parse the entries of a BibTeX or BibLaTeX file.
+pub fn parse_bibtex(
+    contents: &str,
+) -> Result<Vec<BibtexEntry>, BibtexParseError> {
+    BibtexParser::new(contents).entries()
+}
+
 impl Resource {
+    /// Key identifying the resource's BibTeX entry.
+    pub fn bibtex_key(&self) -> String {
+        match &self.citekey {
+            Some(k) => k.clone(),
+            None => self.historical_checksums[0].clone(),
+        }
+    }
+
+    /// Value of a resource field as written in BibTeX entries.
+    fn bibtex_value(&self, field: &str) -> Option<String> {
+        match field {
+            "author" => bibtex_names(self.author.clone()),
+            "editor" => bibtex_names(self.editor.clone()),
+            // urldate is a date, so any time of day is dropped.
+            "archived" => self.archived.clone().map(|d| {
+                String::from(DateTime {
+                    hour: None,
+                    minute: None,
+                    second: None,
+                    ..d
+                })
+            }),
+            // Page ranges are separated by an en dash.
+            "pages" => self
+                .pages
+                .as_ref()
+                .map(|p| p.replace("--", "-").replace(['-', '–'], "--")),
+            "tags" => self
+                .tags
+                .as_ref()
+                .filter(|t| !t.is_empty())
+                .map(|t| t.join(", ")),
+            // BibLaTeX has a single keywords field, which holds the
+            // resource's keywords followed by its tags.
+            "keywords" => {
+                let mut keywords: Vec<&String> =
+                    self.keywords.iter().flatten().collect();
+                for tag in self.tags.iter().flatten() {
+                    if !keywords.contains(&tag) {
+                        keywords.push(tag);
+                    }
+                }
+                match keywords.is_empty() {
+                    true => None,
+                    false => Some(
+                        keywords
+                            .iter()
+                            .map(|k| k.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(", "),
+                    ),
+                }
+            }
+            _ => self.field_string(field),
+        }
+    }
+
+    /// BibTeX fields of the current resource, except for the file, in
+    /// the order they are serialized. Fields without a value are
+    /// omitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - Content type of the resource, whose field
+    ///   mapping overrides the default one.
+    fn bibtex_fields<'a>(
+        &self,
+        content_type: &'a ContentType,
+    ) -> Vec<(&'a str, String)> {
+        let mut fields = vec![
+            ("title", Some(self.title.clone())),
+            ("subtitle", self.subtitle.clone()),
+            ("author", self.bibtex_value("author")),
+            ("editor", self.bibtex_value("editor")),
+            ("date", self.date.clone().map(String::from)),
+            ("edition", self.edition.clone()),
+            ("version", self.version.clone()),
+            ("publisher", self.publisher.clone()),
+            // Organization is used to populate BibLaTeX's
+            // organization and institution fields. The reason is
+            // that I don't understand why these are both
+            // needed. See the note in the readme.
+            ("organization", self.organization.clone()),
+            ("institution", self.organization.clone()),
+            ("journal", self.journal.clone()),
+            ("volume", self.volume.clone()),
+            ("series", self.series.clone()),
+            // Manufacturer part numbers identify datasheets and manuals
+            // the way report numbers identify reports.
+            ("number", self.number.clone().or(self.part_number.clone())),
+            ("chapter", self.chapter.clone()),
+            ("pages", self.bibtex_value("pages")),
+            ("doi", self.doi.clone().map(String::from)),
+            ("isbn", self.isbn.clone().map(String::from)),
+            ("issn", self.issn.clone().map(String::from)),
+            ("url", self.url.as_ref().map(|u| u.to_string())),
+            ("urldate", self.bibtex_value("archived")),
+            // BibLaTeX has no field for archived copies, so styles must
+            // be told to print this one.
+            (
+                "archiveurl",
+                self.archive_url.as_ref().map(|u| u.to_string()),
+            ),
+            ("eprint", self.eprint.clone()),
+            ("eprinttype", self.eprinttype.clone()),
+            ("eprintclass", self.eprintclass.clone()),
+            ("language", self.language.clone()),
+        ];
+        // BibTeX styles don't understand BibLaTeX's eprint fields, but
+        // many recognize arXiv identifiers through archivePrefix and
+        // primaryClass.
+        if self.eprinttype.as_deref() == Some("arxiv") {
+            fields.push(("archivePrefix", Some(String::from("arXiv"))));
+            fields.push(("primaryClass", self.eprintclass.clone()));
+        }
+        fields.push(("abstract", self.r#abstract.clone()));
+        fields.push(("keywords", self.bibtex_value("keywords")));
+        if let ContentType::Mapped {
+            fields: mapping, ..
+        } = content_type
+        {
+            for (field, source) in mapping {
+                let value = source.as_ref().and_then(|s| self.bibtex_value(s));
+                match fields.iter().position(|(f, _)| f == field) {
+                    Some(i) => fields[i].1 = value,
+                    None => fields.push((field.as_str(), value)),
+                }
+            }
+        }
+        fields
+            .into_iter()
+            .filter_map(|(f, v)| v.map(|v| (f, v)))
+            .collect()
+    }
+
+    /// First line of the resource's BibTeX entry.
+    fn bibtex_header(&self, bibtex_type: &BibtexType) -> String {
+        let bibtex_type_string = serde_json::to_string(bibtex_type).unwrap();
+        format!(
+            "@{}{{{},\n",
+            &bibtex_type_string[1..bibtex_type_string.len() - 1],
+            self.bibtex_key()
+        )
+    }
+
     /// Serialized BibTeX entry of the current resource.
     ///
     /// # Arguments
@@ -75,103 +825,136 @@ impl Resource {
     /// * `content_types` - A collection of content types as defined
     /// in the catalog. The map key is a string identifying the
     /// content type and the map value is the associated BibTeX type.
-    /// * `resources_path` - Path to resources directory. This is used
-    /// to provide the absolute path to the resource.
+    /// * `files` - Absolute paths of the resource's file, which are
+    ///   separated by semicolons in the file field.
+    /// * `protect_titles` - Wrap titles in an extra pair of braces to
+    ///   preserve their capitalization.
     pub fn serialize_bibtex(
         &self,
-        content_types: &IndexMap<String, BibtexType>,
-        resources_path: &PathBuf,
+        content_types: &IndexMap<String, ContentType>,
+        files: &[PathBuf],
+        protect_titles: bool,
     ) -> String {
-        let mut bibtex_entry = String::new();
-
-        match self.bibtex_type(content_types) {
-            Some(bt) => {
-                let mut bibtex_type_string =
-                    serde_json::to_string(&bt).unwrap();
-                bibtex_type_string = bibtex_type_string
-                    [1..bibtex_type_string.len() - 1]
-                    .to_string();
-                bibtex_entry.push_str(
-                    format!(
-                        "{}{}{{{},\n",
-                        "@",
-                        bibtex_type_string.as_str(),
-                        self.historical_checksums[0]
-                    )
-                    .as_str(),
-                );
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "title",
-                    Some(self.title.clone()),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "subtitle",
-                    self.subtitle.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_names(
-                    "author",
-                    self.author.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_names(
-                    "editor",
-                    self.editor.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "date",
-                    match &self.date {
-                        Some(d) => {
-                            let mut date = serde_json::to_string(&d).unwrap();
-                            date = date[1..date.len() - 1].to_string();
-                            Some(date)
-                        }
-                        None => None,
-                    },
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "edition",
-                    self.edition.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "version",
-                    self.version.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "publisher",
-                    self.publisher.clone(),
-                ));
-                // Organization is used to populate BibLaTeX's
-                // organization and institution fields. The reason is
-                // that I don't understand why these are both
-                // needed. See the note in the readme.
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "organization",
-                    self.organization.clone(),
-                ));
-                bibtex_entry.push_str(&bibtex_serialize_field(
-                    "institution",
-                    self.organization.clone(),
-                ));
-                // TODO remaining fields
+        match self.content_type(content_types) {
+            Some(ct) => {
+                let mut bibtex_entry = self.bibtex_header(ct.bibtex_type());
+                for (field, value) in self.bibtex_fields(ct) {
+                    let value = match protect_titles {
+                        true => protect_title(field, value),
+                        false => value,
+                    };
+                    bibtex_entry
+                        .push_str(&bibtex_serialize_field(field, Some(value)));
+                }
                 bibtex_entry.push_str(&bibtex_serialize_field(
                     "file",
-                    Some(format!(
-                        "{}/{}",
-                        resources_path
-                            .clone()
-                            .into_os_string()
-                            .into_string()
-                            .unwrap(),
-                        self.historical_checksums[0],
-                    )),
+                    Some(
+                        files
+                            .iter()
+                            .map(|f| f.display().to_string())
+                            .collect::<Vec<String>>()
+                            .join(";"),
+                    ),
                 ));
                 bibtex_entry.push_str("}\n");
                 bibtex_entry
             }
-            None => bibtex_entry,
+            None => String::new(),
+        }
+    }
+
+    /// Serialized BibTeX entry of the current resource, prepared for
+    /// submission with a manuscript.
+    ///
+    /// Local-only fields are removed, non-ASCII characters are
+    /// converted to LaTeX escapes and a year is added for styles that
+    /// don't understand the date field.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_types` - See `serialize_bibtex`.
+    /// * `protect_titles` - See `serialize_bibtex`.
+    ///
+    /// # Return
+    ///
+    /// The entry and a description of every problem that would likely
+    /// fail a journal's checks (e.g., missing required fields).
+    pub fn serialize_bibtex_sanitized(
+        &self,
+        content_types: &IndexMap<String, ContentType>,
+        protect_titles: bool,
+    ) -> (String, Vec<String>) {
+        let ct = match self.content_type(content_types) {
+            Some(ct) => ct,
+            None => return (String::new(), vec![]),
+        };
+        let bt = ct.bibtex_type();
+        let mut problems = Vec::<String>::new();
+        let mut fields: Vec<(&str, String)> = self
+            .bibtex_fields(ct)
+            .into_iter()
+            .filter(|(f, _)| !LOCAL_FIELDS.contains(f))
+            .collect();
+        if let Some(y) = self.date.as_ref().and_then(|d| d.year) {
+            fields.push(("year", format!("{:04}", y)));
+        }
+
+        for missing in missing_fields(required_fields(bt), &fields) {
+            problems.push(format!("missing {}", missing));
+        }
+
+        let mut bibtex_entry = self.bibtex_header(bt);
+        for (field, value) in fields {
+            if let Err(c) = latex_escape(&value) {
+                problems.push(format!(
+                    "{} contains the unsupported character '{}'",
+                    field, c
+                ));
+            }
+            let value = match protect_titles {
+                true => protect_title(field, value),
+                false => value,
+            };
+            bibtex_entry.push_str(&bibtex_serialize_field(field, Some(value)));
+        }
+        bibtex_entry.push_str("}\n");
+        (bibtex_entry, problems)
+    }
+
+    /// BibLaTeX required fields missing from the resource's BibTeX
+    /// entry. See `biblatex_required_fields`.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_types` - See `serialize_bibtex`.
+    pub fn missing_biblatex_fields(
+        &self,
+        content_types: &IndexMap<String, ContentType>,
+    ) -> Vec<&'static str> {
+        match self.content_type(content_types) {
+            Some(ct) => missing_fields(
+                biblatex_required_fields(ct.bibtex_type()),
+                &self.bibtex_fields(ct),
+            ),
+            None => vec![],
         }
     }
 }
 
+/// Files the file field of generated BibTeX entries points to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileField {
+    /// The resource in the resources directory.
+    Storage,
+    /// The resource's first location in the named instance, which has a
+    /// human-readable name. Resources that aren't in the instance point
+    /// to the resources directory.
+    Instance(String),
+    /// The resource's first location in the named instance followed by
+    /// the resource in the resources directory.
+    Both(String),
+}
+
 /// Generate BibTeX entries for cataloged resources.
 ///
 /// # Arguments
@@ -181,76 +964,274 @@ impl Resource {
 /// local filesystem.
 /// * `bibtex_file_path` - File where BibTeX data should be written. If no
 /// file is given, data will be written to stdout.
+/// * `sanitize` - Prepare entries for submission with a manuscript
+///   and report problems to stderr. See
+///   `Resource::serialize_bibtex_sanitized`.
+/// * `include_private` - Include private resources.
+/// * `filter` - Only generate entries for the resources selected by
+///   this filter, e.g., to create a bibliography for a single paper.
+/// * `protect_titles` - Wrap titles in an extra pair of braces to
+///   preserve their capitalization.
+/// * `file_field` - Files the file field points to.
+/// * `merge` - Merge the entries into an existing BibTeX file instead of
+///   overwriting it. See `merge_bibtex`.
+/// * `check` - Instead of generating entries, report the resources
+///   missing fields BibLaTeX requires to stderr.
+///
+/// # Returns
+///
+/// The number of resources missing required fields with `check`, and
+/// zero otherwise.
+#[allow(clippy::too_many_arguments)]
 pub fn librarian_bibtex(
     catalog: &Catalog,
     resources_path: &PathBuf,
     bibtex_file_path: Option<&str>,
-) {
-    let bibtex_entries: String = catalog
-        .resources
+    sanitize: bool,
+    include_private: bool,
+    filter: &ResourceFilter,
+    protect_titles: bool,
+    file_field: &FileField,
+    merge: bool,
+    check: bool,
+) -> usize {
+    let storage = Storage::of(resources_path, catalog);
+    let instance = match file_field {
+        FileField::Storage => None,
+        FileField::Instance(name) | FileField::Both(name) => {
+            let instance = catalog
+                .instances
+                .get(name)
+                .unwrap_or_else(|| panic!("Unknown instance {}", name));
+            let root = resources_path
+                .parent()
+                .expect("resources path does not have a parent")
+                .join(&instance.path);
+            Some((root, instance.layout(catalog)))
+        }
+    };
+    // Attachments follow the resource, in the same locations.
+    let files = |resource: &Resource| {
+        let key = &resource.historical_checksums[0];
+        let attachments = || resource.attachments.iter().flatten();
+        let mut files: Vec<PathBuf> = match &instance {
+            Some((root, layout)) => layout
+                .get(key)
+                .and_then(|p| p.first())
+                .map(|p| root.join(p)),
+            None => None,
+        }
+        .into_iter()
+        .collect();
+        if let Some(path) = files.first().cloned() {
+            files.extend(
+                attachments().map(|a| attachments_dir(&path).join(&a.name)),
+            );
+        }
+        if files.is_empty() || matches!(file_field, FileField::Both(_)) {
+            files.push(storage.resource_path(key));
+            files.extend(
+                attachments()
+                    .map(|a| storage.attachments_path(key).join(&a.name)),
+            );
+        }
+        files
+    };
+    let resources = filter.apply(catalog, include_private);
+    if check {
+        let mut incomplete = 0;
+        for resource in &resources {
+            let missing =
+                resource.missing_biblatex_fields(&catalog.content_types);
+            if !missing.is_empty() {
+                incomplete += 1;
+                eprintln!(
+                    "{}",
+                    message(
+                        "bibtex-incomplete",
+                        &[
+                            ("key", resource.bibtex_key().into()),
+                            ("title", resource.title.as_str().into()),
+                            ("fields", missing.join(", ").into())
+                        ]
+                    )
+                );
+            }
+        }
+        eprintln!(
+            "{}",
+            message(
+                "bibtex-check-summary",
+                &[
+                    ("count", incomplete.into()),
+                    ("total", resources.len().into())
+                ]
+            )
+        );
+        return incomplete;
+    }
+    let bibtex_entries: Vec<(String, String)> = resources
         .iter()
-        .map(|r| r.serialize_bibtex(&catalog.content_types, resources_path))
+        .map(|r| {
+            let entry = match sanitize {
+                true => {
+                    let (entry, problems) = r.serialize_bibtex_sanitized(
+                        &catalog.content_types,
+                        protect_titles,
+                    );
+                    for p in problems {
+                        eprintln!("{}: {}", r.bibtex_key(), p);
+                    }
+                    entry
+                }
+                false => r.serialize_bibtex(
+                    &catalog.content_types,
+                    &files(r),
+                    protect_titles,
+                ),
+            };
+            (r.bibtex_key(), entry)
+        })
+        .filter(|(_, e)| !e.is_empty())
         .collect();
 
     match bibtex_file_path {
         Some(f) => {
-            let mut bibtex_file = OpenOptions::new()
-                .read(false)
-                .write(true)
-                .create(true)
-                .open(&f)
-                .expect("Failed to open or create catalog");
-            bibtex_file.write(bibtex_entries.as_bytes()).ok();
+            let contents = match merge && Path::new(f).exists() {
+                true => {
+                    let owned: HashSet<String> = catalog
+                        .resources
+                        .iter()
+                        .map(|r| r.bibtex_key())
+                        .collect();
+                    merge_bibtex(
+                        &std::fs::read_to_string(f)
+                            .expect("Failed to read BibTeX file"),
+                        &bibtex_entries,
+                        &owned,
+                    )
+                }
+                false => bibtex_entries.into_iter().map(|(_, e)| e).collect(),
+            };
+            std::fs::write(f, contents).expect("Failed to write BibTeX file");
         }
         None => {
-            println!("{}", bibtex_entries);
+            let contents: String =
+                bibtex_entries.into_iter().map(|(_, e)| e).collect();
+            println!("{}", contents);
         }
     }
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let env: Vec<(&str, &str)> = bibtex_file_path
+        .map(|f| ("LIBRARIAN_BIBTEX", f))
+        .into_iter()
+        .collect();
+    run_hook(library, "post-bibtex", &env, None);
+    0
+}
+
+/// Merge generated entries into the contents of an existing BibTeX
+/// file.
+///
+/// Entries of the file whose keys belong to the catalog are replaced by
+/// the generated entry with the same key, in place. Everything else in
+/// the file, such as hand-written entries, `@string` definitions and
+/// comments, is kept verbatim. Generated entries that aren't in the file
+/// yet are appended.
+///
+/// # Arguments
+///
+/// * `existing` - Contents of the existing file.
+/// * `entries` - Key and serialized entry of each generated entry.
+/// * `owned` - Keys of every cataloged resource.
+fn merge_bibtex(
+    existing: &str,
+    entries: &[(String, String)],
+    owned: &HashSet<String>,
+) -> String {
+    let start = Regex::new(r"(?m)^[ \t]*@").unwrap();
+    let header =
+        Regex::new(r"^\s*@\s*[a-zA-Z]+\s*[{(]\s*([^,\s]+)\s*,").unwrap();
+    let generated: HashMap<&str, &str> = entries
+        .iter()
+        .map(|(k, e)| (k.as_str(), e.as_str()))
+        .collect();
+    let mut merged_keys = HashSet::<&str>::new();
+
+    let mut starts: Vec<usize> =
+        start.find_iter(existing).map(|m| m.start()).collect();
+    starts.push(existing.len());
+    let mut merged = existing[..starts[0]].to_string();
+    for bounds in starts.windows(2) {
+        let chunk = &existing[bounds[0]..bounds[1]];
+        let key = header.captures(chunk).map(|c| c.get(1).unwrap().as_str());
+        match key
+            .filter(|k| owned.contains(*k))
+            .and_then(|k| generated.get_key_value(k))
+        {
+            Some((k, entry)) => {
+                // Keep the blank lines separating the entry from the
+                // next one.
+                let trailing = &chunk[chunk.trim_end().len()..];
+                merged.push_str(entry.trim_end());
+                merged.push_str(trailing);
+                merged_keys.insert(k);
+            }
+            None => merged.push_str(chunk),
+        }
+    }
+
+    for (key, entry) in entries {
+        if !merged_keys.contains(key.as_str()) {
+            if !merged.is_empty() && !merged.ends_with('\n') {
+                merged.push('\n');
+            }
+            merged.push_str(entry);
+        }
+    }
+    merged
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryFrom;
 
     #[test]
-    fn test_bibtex_serialize_names() {
+    fn test_bibtex_names() {
         let mut names: Vec<Name> = vec![
-            Name {
-                first: Some(String::from("Richard")),
-                middle: Some(String::from("Phillips")),
-                last: Some(String::from("Feynman")),
-            },
-            Name {
-                first: Some(String::from("Albert")),
-                middle: None,
-                last: Some(String::from("Einstein")),
-            },
-            Name {
-                first: None,
-                middle: None,
-                last: Some(String::from("Dirac")),
-            },
+            Name::try_from("Richard Phillips Feynman").unwrap(),
+            Name::try_from("Ludwig van Beethoven").unwrap(),
+            Name::try_from("Martin Luther King Jr.").unwrap(),
+            Name::try_from("{Jet Propulsion Laboratory}").unwrap(),
+            Name::try_from("Dirac").unwrap(),
         ];
 
         assert!(
-            bibtex_serialize_names("author", Some(names.clone()))
-                == "    author={Richard Phillips Feynman and Albert Einstein and Dirac},\n"
+            bibtex_serialize_field("author", bibtex_names(Some(names.clone())))
+                == "    author={Feynman, Richard Phillips and van Beethoven, Ludwig and King, Jr., Martin Luther and {Jet Propulsion Laboratory} and Dirac},\n"
         );
 
-        names.pop();
+        names.truncate(2);
         assert!(
-            bibtex_serialize_names("editor", Some(names.clone()))
-                == "    editor={Richard Phillips Feynman and Albert Einstein},\n"
+            bibtex_serialize_field("editor", bibtex_names(Some(names.clone())))
+                == "    editor={Feynman, Richard Phillips and van Beethoven, Ludwig},\n"
         );
 
         names.pop();
         assert!(
-            bibtex_serialize_names("annotator", Some(names.clone()))
-                == "    annotator={Richard Phillips Feynman},\n"
+            bibtex_serialize_field(
+                "annotator",
+                bibtex_names(Some(names.clone()))
+            ) == "    annotator={Feynman, Richard Phillips},\n"
         );
 
         names.pop();
-        assert!(bibtex_serialize_names("forward", Some(names)) == "");
+        assert!(
+            bibtex_serialize_field("forward", bibtex_names(Some(names))) == ""
+        );
     }
 
     #[test]
@@ -268,4 +1249,260 @@ mod tests {
             ) == String::from("    publisher={John Wiley \\& Sons},\n")
         );
     }
+
+    #[test]
+    fn test_bibtex_escape() {
+        let cases = [
+            ("AT&T Bell Labs", r"AT\&T Bell Labs"),
+            ("100% Efficient", r"100\% Efficient"),
+            (
+                "The C# Programming Language",
+                r"The C\# Programming Language",
+            ),
+            ("snake_case Identifiers", r"snake\_case Identifiers"),
+            ("Home~Page", r"Home\textasciitilde{}Page"),
+            ("Schrödinger’s Cat", r#"Schr{\"o}dinger's Cat"#),
+            ("Gödel, Escher, Bach", r#"G{\"o}del, Escher, Bach"#),
+            (r"Already \& escaped", r"Already \& escaped"),
+            (r"The $H_\infty$ Norm", r"The $H_\infty$ Norm"),
+            ("{CMOS} Circuits", r"{CMOS} Circuits"),
+            ("Ψ Functions", "Ψ Functions"),
+            ("Costs in $", r"Costs in \$"),
+            ("f(x) = {a", r"f(x) = \textbraceleft{}a"),
+            ("b} and {c}", r"b\textbraceright{} and {c}"),
+            (r"Open \{ Brace", r"Open \textbraceleft{} Brace"),
+            (r"C:\", r"C:\textbackslash{}"),
+        ];
+        for (value, escaped) in cases.iter() {
+            assert!(bibtex_escape(value) == *escaped);
+        }
+        assert!(
+            bibtex_serialize_field(
+                "url",
+                Some(String::from("https://example.com/a_b%20c"))
+            ) == "    url={https://example.com/a_b%20c},\n"
+        );
+        assert!(
+            bibtex_serialize_field(
+                "title",
+                Some(protect_title("title", String::from("RF & CMOS")))
+            ) == "    title={{RF \\& CMOS}},\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_biblatex_fields() {
+        let content_types: IndexMap<String, ContentType> =
+            serde_json::from_str("{\"paper\": \"article\"}").unwrap();
+        let resource =
+            |json: &str| -> Resource { serde_json::from_str(json).unwrap() };
+        assert!(
+            resource(
+                "{\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"content\": \"paper\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            )
+            .missing_biblatex_fields(&content_types)
+                == vec!["journaltitle/journal", "date/year"]
+        );
+        assert!(resource(
+            "{\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"journal\": \"JSSC\", \"date\": \"1998\", \"content\": \"paper\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+        )
+        .missing_biblatex_fields(&content_types)
+        .is_empty());
+    }
+
+    #[test]
+    fn test_merge_bibtex() {
+        let existing = concat!(
+            "% Bibliography of the thesis.\n",
+            "@string{jssc = \"IEEE J. Solid-State Circuits\"}\n\n",
+            "@article{hajimiri1998,\n    title={Old Title},\n}\n\n",
+            "@misc{handwritten,\n  note = {keep me},\n}\n",
+        );
+        let entries = vec![
+            (
+                String::from("hajimiri1998"),
+                String::from(
+                    "@article{hajimiri1998,\n    title={Phase Noise},\n}\n",
+                ),
+            ),
+            (
+                String::from("lee2000"),
+                String::from("@book{lee2000,\n    title={CMOS RF},\n}\n"),
+            ),
+        ];
+        let owned: HashSet<String> =
+            vec![String::from("hajimiri1998"), String::from("lee2000")]
+                .into_iter()
+                .collect();
+        assert!(
+            merge_bibtex(existing, &entries, &owned)
+                == concat!(
+                    "% Bibliography of the thesis.\n",
+                    "@string{jssc = \"IEEE J. Solid-State Circuits\"}\n\n",
+                    "@article{hajimiri1998,\n    title={Phase Noise},\n}\n\n",
+                    "@misc{handwritten,\n  note = {keep me},\n}\n",
+                    "@book{lee2000,\n    title={CMOS RF},\n}\n",
+                )
+        );
+    }
+
+    #[test]
+    fn test_serialize_bibtex() {
+        let content_types: IndexMap<String, ContentType> = serde_json::from_str(
+            "{\"paper\": \"article\", \"book\": \"book\", \"webpage\": \"online\", \"datasheet\": \"manual\",
+              \"thesis\": {\"bibtex\": \"report\", \"fields\": {\"organization\": null, \"note\": \"version\", \"location\": \"extra.shelf\"}}}",
+        )
+        .unwrap();
+        let files = [std::path::Path::new("/lib").join("aa")];
+        let file = format!(
+            "    file={{{}}},\n",
+            std::path::Path::new("/lib").join("aa").display()
+        );
+        let entry = |json: &str| {
+            let resource: Resource = serde_json::from_str(json).unwrap();
+            resource.serialize_bibtex(&content_types, &files, false)
+        };
+
+        assert!(
+            entry(
+                "{\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"date\": \"1998-02\", \"journal\": \"IEEE Journal of Solid-State Circuits\", \"volume\": \"33\", \"number\": \"2\", \"pages\": \"179-194\", \"doi\": \"10.1109/4.658619\", \"abstract\": \"Phase noise & jitter.\", \"keywords\": [\"phase noise\", \"rf\"], \"tags\": [\"rf\", \"oscillators\"], \"content\": \"paper\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            ) == String::from("@article{aa,\n")
+                + "    title={Phase Noise},\n"
+                + "    author={Hajimiri, Ali},\n"
+                + "    date={1998-02},\n"
+                + "    journal={IEEE Journal of Solid-State Circuits},\n"
+                + "    volume={33},\n"
+                + "    number={2},\n"
+                + "    pages={179--194},\n"
+                + "    doi={10.1109/4.658619},\n"
+                + "    abstract={Phase noise \\& jitter.},\n"
+                + "    keywords={phase noise, rf, oscillators},\n"
+                + &file
+                + "}\n"
+        );
+        assert!(
+            entry(
+                "{\"title\": \"Classical Electrodynamics\", \"author\": [\"John David Jackson\"], \"date\": \"1999\", \"edition\": \"3\", \"series\": \"Physics\", \"chapter\": \"7\", \"language\": \"english\", \"publisher\": \"John Wiley & Sons\", \"isbn\": \"978-0-471-30932-1\", \"content\": \"book\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            ) == String::from("@book{aa,\n")
+                + "    title={Classical Electrodynamics},\n"
+                + "    author={Jackson, John David},\n"
+                + "    date={1999},\n"
+                + "    edition={3},\n"
+                + "    publisher={John Wiley \\& Sons},\n"
+                + "    series={Physics},\n"
+                + "    chapter={7},\n"
+                + "    isbn={978-0-471-30932-1},\n"
+                + "    language={english},\n"
+                + &file
+                + "}\n"
+        );
+        assert!(
+            entry(
+                "{\"title\": \"Rust\", \"url\": \"https://www.rust-lang.org/\", \"archived\": \"2021-03-04T10:20\", \"archive_url\": \"https://web.archive.org/web/20210304102000/https://www.rust-lang.org/\", \"content\": \"webpage\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            ) == String::from("@online{aa,\n")
+                + "    title={Rust},\n"
+                + "    url={https://www.rust-lang.org/},\n"
+                + "    urldate={2021-03-04},\n"
+                + "    archiveurl={https://web.archive.org/web/20210304102000/https://www.rust-lang.org/},\n"
+                + &file
+                + "}\n"
+        );
+        assert!(
+            entry(
+                "{\"title\": \"LM358\", \"organization\": \"Texas Instruments\", \"part_number\": \"LM358\", \"content\": \"datasheet\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            ) == String::from("@manual{aa,\n")
+                + "    title={LM358},\n"
+                + "    organization={Texas Instruments},\n"
+                + "    institution={Texas Instruments},\n"
+                + "    number={LM358},\n"
+                + &file
+                + "}\n"
+        );
+
+        // Content types can override the default field mapping.
+        assert!(
+            entry(
+                "{\"title\": \"Low Noise Amplifiers\", \"author\": [\"Jane Doe\"], \"version\": \"Draft\", \"organization\": \"MIT\", \"extra\": {\"shelf\": \"B3\"}, \"content\": \"thesis\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}"
+            ) == String::from("@report{aa,\n")
+                + "    title={Low Noise Amplifiers},\n"
+                + "    author={Doe, Jane},\n"
+                + "    version={Draft},\n"
+                + "    institution={MIT},\n"
+                + "    note={Draft},\n"
+                + "    location={B3},\n"
+                + &file
+                + "}\n"
+        );
+    }
+
+    #[test]
+    fn test_latex_escape() {
+        assert!(
+            latex_escape("Schrödinger – Poincaré, Łojasiewicz, naïve")
+                == Ok(String::from(
+                    "Schr{\\\"o}dinger -- Poincar{\\'e}, {\\L}ojasiewicz, na{\\\"\\i}ve"
+                ))
+        );
+        assert!(latex_escape("Ağa") == Ok(String::from("A{\\u g}a")));
+        assert!(latex_escape("Ψ") == Err('Ψ'));
+    }
+
+    #[test]
+    fn test_parse_bibtex() {
+        let contents = r#"
+            Text between entries, such as someone@example.com, is ignored.
+            @String{prl = "Phys. Rev. Lett."}
+            @comment{@article{ignored, title={Ignored}}}
+            @ARTICLE{jackson1999,
+              Title = {Classical {Electrodynamics}},
+              journal = prl # { (Erratum)},
+              year = 1999,
+              month = jan,
+            }
+            @book(feynman1964, title="The {"}Feynman{"} Lectures")
+        "#;
+        let entries = parse_bibtex(contents).unwrap();
+        assert!(entries.len() == 2);
+        assert!(entries[0].entry_type == "article");
+        assert!(entries[0].key == "jackson1999");
+        assert!(entries[0].fields["title"] == "Classical {Electrodynamics}");
+        assert!(entries[0].fields["journal"] == "Phys. Rev. Lett. (Erratum)");
+        assert!(entries[0].fields["month"] == "1");
+        assert!(entries[1].fields["title"] == "The {\"}Feynman{\"} Lectures");
+
+        assert!(parse_bibtex("@article{key, title={Unbalanced}").is_err());
+        assert!(parse_bibtex("@article{key, journal=undefined}").is_err());
+    }
+
+    #[test]
+    fn test_latex_unescape() {
+        assert!(
+            latex_unescape(
+                "Schr{\\\"o}dinger -- Poincar\\'e, {\\L}ojasiewicz, na\\\"{\\i}ve \\& {NASA}"
+            ) == "Schrödinger – Poincaré, Łojasiewicz, naïve & NASA"
+        );
+        assert!(latex_unescape("A{\\u g}a \\c{c}") == "Ağa ç");
+        assert!(
+            latex_unescape(r"\{x\} \textasciicircum{}2 \ldots\ end")
+                == "{x} ^2 …\\ end"
+        );
+    }
+
+    #[test]
+    fn test_escape_round_trip() {
+        let values = [
+            "AT&T Bell Labs",
+            "100% of the #1 snake_case Tool",
+            "Home~Page",
+            "x^2 + y^2",
+            "Costs in $",
+            "f(x) = {a",
+            "b} and c",
+            "Gödel, Escher, Bach",
+            r"C:\",
+        ];
+        for value in values.iter() {
+            assert!(latex_unescape(&bibtex_escape(value)) == *value);
+        }
+    }
 }
@@ -0,0 +1,395 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::Resource;
+use crate::search::search;
+use crate::storage::{Storage, StorageLayout, ATTACHMENTS_DIR};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Catalog fragment at the top of a bundle.
+const BUNDLE_CATALOG: &str = "catalog.json";
+
+/// Directory of a bundle holding the resource files, in the flat layout,
+/// and their attachments.
+const BUNDLE_RESOURCES: &str = "resources";
+
+/// Catalog fragment describing the resources of a bundle. Fields that
+/// only make sense in the sender's library, such as reading status and
+/// rating, are cleared, relations to resources outside the bundle are
+/// dropped, and the document and content types of the resources are
+/// included.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources` - Resources of the bundle.
+fn bundle_catalog(catalog: &Catalog, resources: &[&Resource]) -> Catalog {
+    let mut fragment: Catalog = serde_json::from_str(
+        "{\"document_types\": {}, \"content_types\": {}, \"resources\": []}",
+    )
+    .unwrap();
+    let keys: Vec<&String> = resources
+        .iter()
+        .map(|r| &r.historical_checksums[0])
+        .collect();
+    for resource in resources {
+        let mut resource = (*resource).clone();
+        resource.read_status = None;
+        resource.rating = None;
+        resource.priority = None;
+        resource.state = None;
+        resource.private = false;
+        resource.remote = None;
+        // Bundled files are stored under their checksum when imported.
+        resource.path = None;
+        if let Some(relations) = resource.relations.as_mut() {
+            for targets in relations.values_mut() {
+                targets.retain(|t| keys.contains(&t));
+            }
+            relations.retain(|_, targets| !targets.is_empty());
+        }
+        if resource.relations.as_ref().is_some_and(|r| r.is_empty()) {
+            resource.relations = None;
+        }
+        if let Some((name, t)) = resource
+            .document
+            .as_ref()
+            .and_then(|d| catalog.document_types.get_key_value(d))
+        {
+            fragment.document_types.insert(name.clone(), t.clone());
+        }
+        if let Some((name, t)) = resource
+            .content
+            .as_ref()
+            .and_then(|c| catalog.content_types.get_key_value(c))
+        {
+            fragment.content_types.insert(name.clone(), t.clone());
+        }
+        fragment.resources.push(resource);
+    }
+    fragment
+}
+
+/// Write the resources matching a query, their files and their
+/// attachments to a zstd-compressed tar archive, which another library
+/// can import with `librarian_import_bundle`, e.g., to share a curated
+/// set of papers with a collaborator.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Search query selecting the resources.
+/// * `output` - Archive to create, conventionally ending in `.tar.zst`.
+/// * `include_private` - Include private resources.
+pub fn librarian_export_bundle(
+    catalog: &Catalog,
+    resources_path: &Path,
+    query: &str,
+    output: &Path,
+    include_private: bool,
+) {
+    let resources: Vec<&Resource> = search(catalog, query)
+        .into_iter()
+        .filter(|r| include_private || !r.private)
+        .collect();
+    if resources.is_empty() {
+        println!("{}", message("bundle-nothing", &[("query", query.into())]));
+        return;
+    }
+    let storage = Storage::of(resources_path, catalog)
+        .remote(catalog.remote_storage.as_deref());
+    let bundled =
+        Storage::new(Path::new(BUNDLE_RESOURCES), StorageLayout::Flat);
+    let file = File::create(output).expect("Failed to create bundle");
+    let encoder = zstd::Encoder::new(file, 0)
+        .expect("Failed to compress bundle")
+        .auto_finish();
+    let mut archive = tar::Builder::new(encoder);
+
+    let fragment = bundle_catalog(catalog, &resources);
+    let contents = serde_json::to_vec_pretty(&fragment).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    );
+    header.set_cksum();
+    archive
+        .append_data(&mut header, BUNDLE_CATALOG, contents.as_slice())
+        .expect("Failed to write bundle");
+    for resource in &resources {
+        let key = &resource.historical_checksums[0];
+        let (path, attachments) =
+            (storage.fetch(key), storage.attachments_path(key));
+        match (path.is_dir(), path.exists()) {
+            (true, _) => {
+                archive.append_dir_all(bundled.resource_path(key), &path)
+            }
+            (false, true) => {
+                archive.append_path_with_name(&path, bundled.resource_path(key))
+            }
+            (false, false) => {
+                eprintln!(
+                    "{}",
+                    message(
+                        "resource-missing",
+                        &[("key", key.as_str().into())]
+                    )
+                );
+                Ok(())
+            }
+        }
+        .expect("Failed to write bundle");
+        if attachments.is_dir() {
+            archive
+                .append_dir_all(bundled.attachments_path(key), &attachments)
+                .expect("Failed to write bundle");
+        }
+    }
+    archive.into_inner().expect("Failed to write bundle");
+    println!(
+        "{}",
+        message(
+            "bundle-exported",
+            &[
+                ("count", resources.len().into()),
+                ("file", output.display().to_string().into())
+            ]
+        )
+    );
+}
+
+/// Add the resources of a bundle created by `librarian_export_bundle` to
+/// the library.
+///
+/// Resources the library already has, because it has their file or
+/// another resource with the same DOI or ISBN, are skipped, and the
+/// relations of the new resources to them refer to the library's
+/// resources instead. Tags missing from the library's tag tree are
+/// added to it, and citation keys are generated again.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `bundle` - Bundle to import.
+pub fn librarian_import_bundle(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    bundle: &Path,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let unpacked = std::env::temp_dir()
+        .join(format!("librarian-bundle-{}", std::process::id()));
+    let file = File::open(bundle).expect("Failed to open bundle");
+    tar::Archive::new(
+        zstd::Decoder::new(file).expect("Failed to decompress bundle"),
+    )
+    .unpack(&unpacked)
+    .expect("Failed to unpack bundle");
+    let contents = std::fs::read_to_string(unpacked.join(BUNDLE_CATALOG))
+        .expect("Bundle lacks a catalog");
+    let fragment: Catalog =
+        serde_json::from_str(&contents).expect("Bundle catalog is invalid");
+
+    // Initial checksum of each bundled resource in the library.
+    let mut keys = HashMap::<String, String>::new();
+    let mut duplicates = vec![];
+    for resource in &fragment.resources {
+        let key = resource.historical_checksums[0].clone();
+        let existing = catalog.resources.iter().find(|r| {
+            r.is_duplicate_of(resource)
+                || r.historical_checksums
+                    .iter()
+                    .any(|c| resource.historical_checksums.contains(c))
+        });
+        match existing {
+            Some(r) => {
+                duplicates.push((key.clone(), r.title.clone()));
+                keys.insert(key, r.historical_checksums[0].clone());
+            }
+            None => {
+                keys.insert(key.clone(), key);
+            }
+        }
+    }
+
+    let bundled =
+        Storage::new(&unpacked.join(BUNDLE_RESOURCES), StorageLayout::Flat);
+    let storage = Storage::of(resources_path, catalog);
+    let mut added = vec![];
+    for (name, t) in &fragment.document_types {
+        if !catalog.document_types.contains_key(name) {
+            catalog.document_types.insert(name.clone(), t.clone());
+        }
+    }
+    for (name, t) in &fragment.content_types {
+        if !catalog.content_types.contains_key(name) {
+            catalog.content_types.insert(name.clone(), t.clone());
+        }
+    }
+    for resource in &fragment.resources {
+        let key = &resource.historical_checksums[0];
+        if duplicates.iter().any(|(d, _)| d == key) {
+            continue;
+        }
+        let from = bundled.resource_path(key);
+        if !from.exists() {
+            eprintln!(
+                "{}",
+                message(
+                    "bundle-resource-missing",
+                    &[("key", key.as_str().into())]
+                )
+            );
+            continue;
+        }
+        storage.store(&from, key);
+        let attachments = bundled.attachments_path(key);
+        if attachments.is_dir() && !catalog.dry_run {
+            let to = storage.attachments_path(key);
+            std::fs::create_dir_all(resources_path.join(ATTACHMENTS_DIR))
+                .expect("Failed to create attachments directory");
+            std::fs::rename(&attachments, &to)
+                .expect("Failed to move attachments");
+        }
+
+        let mut resource = resource.clone();
+        resource.citekey = None;
+        resource.date_added = None;
+        resource.date_modified = None;
+        if let Some(relations) = resource.relations.as_mut() {
+            for targets in relations.values_mut() {
+                for target in targets.iter_mut() {
+                    if let Some(k) = keys.get(target) {
+                        *target = k.clone();
+                    }
+                }
+            }
+        }
+        if !catalog.tags.is_empty() {
+            catalog
+                .validate_tags(&resource.tags.clone().unwrap_or_default(), true)
+                .unwrap();
+        }
+        catalog.resources.push(resource);
+        added.push(key.clone());
+    }
+    std::fs::remove_dir_all(&unpacked).ok();
+
+    catalog.assign_citekeys();
+    catalog.sort();
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "import-bundle",
+                added.clone(),
+                vec![format!("Imported from {}", bundle.display())],
+            ),
+        );
+    }
+    for (key, title) in &duplicates {
+        println!(
+            "{}",
+            message(
+                "bundle-duplicate",
+                &[
+                    ("key", key.as_str().into()),
+                    ("title", title.as_str().into())
+                ]
+            )
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "bundle-imported",
+            &[
+                ("count", added.len().into()),
+                ("duplicates", duplicates.len().into())
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-bundle-test-{}", std::process::id()));
+        let library = |name: &str, resources: &str| {
+            let root = dir.join(name);
+            std::fs::create_dir_all(root.join("resources")).unwrap();
+            std::fs::write(
+                root.join("catalog.json"),
+                format!(
+                    "{{\"document_types\": {{}}, \"content_types\": {{}}, \"resources\": [{}]}}",
+                    resources
+                ),
+            )
+            .unwrap();
+            root
+        };
+        let sender = library(
+            "sender",
+            "{\"title\": \"Phase Noise\", \"doi\": \"10.1109/4.658619\", \"rating\": 5,
+              \"relations\": {\"cites\": [\"bb\", \"cc\"]},
+              \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+             {\"title\": \"Oscillators\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}",
+        );
+        std::fs::write(sender.join("resources/aa"), "a").unwrap();
+        std::fs::write(sender.join("resources/bb"), "b").unwrap();
+        // The recipient already has "bb" under another name.
+        let recipient = library(
+            "recipient",
+            "{\"title\": \"Oscillators\", \"checksum\": \"bb\", \"historical_checksums\": [\"dd\", \"bb\"]}",
+        );
+
+        let catalog = CatalogStore::open(&sender.join("catalog.json")).read();
+        let output = dir.join("bundle.tar.zst");
+        librarian_export_bundle(
+            &catalog,
+            &sender.join("resources"),
+            "",
+            &output,
+            false,
+        );
+        let mut store = CatalogStore::open(&recipient.join("catalog.json"));
+        let mut catalog = store.read();
+        librarian_import_bundle(
+            &mut store,
+            &mut catalog,
+            &recipient.join("resources"),
+            &output,
+        );
+
+        let catalog = store.read();
+        assert!(catalog.resources.len() == 2);
+        let imported = &catalog.resources[1];
+        assert!(imported.title == "Phase Noise" && imported.rating.is_none());
+        // The relation to "bb" refers to the recipient's resource, and
+        // the one to "cc", which isn't in the bundle, is dropped.
+        let relations = serde_json::to_value(&imported.relations).unwrap();
+        assert!(relations == serde_json::json!({"cites": ["dd"]}));
+        assert!(recipient.join("resources/aa").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
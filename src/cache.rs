@@ -1,7 +1,33 @@
+use crate::batch::write_atomically;
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::resource::DateTime;
+use crate::storage::Storage;
+use crate::timings::{time, Phase};
+
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Number of seconds by which a modification time may disagree with
+/// the cache before it is considered to be from a skewed clock.
+const CLOCK_SKEW_TOLERANCE: u64 = 120;
+
+/// Number of bytes read from each end of a file for its sample
+/// checksum.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// Version of the cache format. Caches of another version are
+/// discarded, and rebuilt by the next `librarian catalog`.
+pub const CACHE_VERSION: u32 = 3;
+
+/// Prefix of the keys of new files in the cache (see `cache_key`).
+const IDENTITY_PREFIX: &str = "@";
 
 /// Data stored in the cache for each resource.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -9,12 +35,291 @@ pub struct CacheFields {
     /// Number of seconds since "the epoch".
     pub last_verified: u64,
     pub checksum: String,
+    /// Size of the file in bytes when it was verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Modification time of the file, in seconds since "the epoch",
+    /// when it was verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Device of the file or directory when it was verified, on systems
+    /// that have inodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<u64>,
+    /// Inode of the file or directory when it was verified, on systems
+    /// that have them. Copies that preserve the modification time, such
+    /// as `cp -p`, get a new one, while renaming a file keeps it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    /// SHA-1 checksum of the beginning and end of the file, which is
+    /// much cheaper to compute than the full checksum.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<String>,
+}
+
+/// Modification time of a file in seconds since "the epoch".
+fn modified_secs(metadata: &std::fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Device and inode of a file or directory, which identify it as long
+/// as it exists, whatever its name.
+#[cfg(unix)]
+fn identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Identity of the file or directory at a path.
+fn path_identity(path: &Path) -> Option<(u64, u64)> {
+    identity(&std::fs::metadata(path).ok()?)
+}
+
+/// SHA-1 checksum of the first and last `SAMPLE_SIZE` bytes of a file.
+fn sample_checksum(path: &Path) -> Option<String> {
+    time(Phase::Hash, || sample_file_checksum(path))
+}
+
+fn sample_file_checksum(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut hasher = Sha1::new();
+    let mut buffer = Vec::new();
+    (&mut file)
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut buffer)
+        .ok()?;
+    if size > SAMPLE_SIZE {
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(SAMPLE_SIZE).max(SAMPLE_SIZE),
+        ))
+        .ok()?;
+        file.read_to_end(&mut buffer).ok()?;
+    }
+    hasher.update(&buffer);
+    Some(hex::encode(hasher.finalize()))
+}
+
+impl CacheFields {
+    /// Cache data for a resource whose checksum was just computed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File or directory of the resource.
+    /// * `checksum` - Checksum of the resource.
+    /// * `now` - Current time in seconds since "the epoch".
+    pub fn new(path: &Path, checksum: String, now: u64) -> CacheFields {
+        let metadata = std::fs::metadata(path).ok();
+        let is_file = metadata.as_ref().is_some_and(|m| m.is_file());
+        CacheFields {
+            last_verified: now,
+            checksum,
+            size: metadata.as_ref().filter(|_| is_file).map(|m| m.len()),
+            modified: metadata.as_ref().and_then(modified_secs),
+            device: metadata.as_ref().and_then(identity).map(|i| i.0),
+            inode: metadata.as_ref().and_then(identity).map(|i| i.1),
+            sample: match is_file {
+                true => sample_checksum(path),
+                false => None,
+            },
+        }
+    }
+
+    /// Device and inode of the file or directory when it was verified.
+    fn identity(&self) -> Option<(u64, u64)> {
+        self.device.zip(self.inode)
+    }
+
+    /// Whether the cached checksum can be trusted for a resource
+    /// without recomputing it.
+    ///
+    /// Modification times are unreliable when files come from machines
+    /// with skewed clocks, are copied with `cp -p` or are touched by sync
+    /// tools, so the cache is only trusted when the size, modification
+    /// time, inode and sample checksum recorded at verification all
+    /// still match.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File or directory of the resource.
+    /// * `now` - Current time in seconds since "the epoch".
+    pub fn is_valid(&self, path: &Path, now: u64) -> bool {
+        let metadata = match std::fs::metadata(path) {
+            Ok(m) => m,
+            Err(_) => return false,
+        };
+        let modified = match modified_secs(&metadata) {
+            Some(m) => m,
+            None => return false,
+        };
+        if modified > now + CLOCK_SKEW_TOLERANCE {
+            eprintln!(
+                "{}",
+                message(
+                    "cache-future-modified",
+                    &[("path", path.display().to_string().into())]
+                )
+            );
+            return false;
+        }
+        self.modified == Some(modified)
+            && self.identity() == identity(&metadata)
+            && (!metadata.is_file()
+                || (self.size == Some(metadata.len())
+                    && self.sample.is_some()
+                    && self.sample == sample_checksum(path)))
+    }
+}
+
+/// Contents of a library's cache file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Cache {
+    /// Format of the cache (see `CACHE_VERSION`).
+    pub version: u32,
+    /// Identifier of the library the cache belongs to. See
+    /// `Catalog::library_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_id: Option<String>,
+    /// Cached data of each resource, where the key is a string of the
+    /// file name and the value is the `CacheFields` corresponding to
+    /// that resource.
+    pub resources: IndexMap<String, CacheFields>,
+    /// How well the cache served the last `librarian catalog` that used
+    /// it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<CacheRun>,
+}
+
+/// Cache hits and misses of a run of `librarian catalog`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CacheRun {
+    /// Number of seconds since "the epoch".
+    pub time: u64,
+    /// Files whose cached checksum was used.
+    pub hits: u64,
+    /// Files whose checksum was computed.
+    pub misses: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Cache {
+        Cache {
+            version: CACHE_VERSION,
+            library_id: None,
+            resources: IndexMap::new(),
+            last_run: None,
+        }
+    }
+}
+
+/// Key of the cache entry of a file in the resources directory.
+///
+/// The files of cataloged resources are cached under the initial
+/// checksum of their resource. New files aren't named after theirs until
+/// they're cataloged, so caching them under their name or checksum
+/// would collide with another entry when they're renamed. Instead, they
+/// are cached under their identity, their device and inode, which
+/// survives the rename. A new file with the identity and contents of
+/// an entry, such as a resource's file renamed by hand, takes the key
+/// of that entry. On systems without inodes, new files are cached under
+/// their name.
+///
+/// # Arguments
+///
+/// * `resources` - Cached data of each resource.
+/// * `path` - File or directory.
+/// * `name` - Initial checksum of the resource the file belongs to, or
+///   the name of a new file (see `Storage::key`).
+/// * `cataloged` - Whether the file belongs to a cataloged resource.
+/// * `now` - Current time in seconds since "the epoch".
+pub fn cache_key(
+    resources: &IndexMap<String, CacheFields>,
+    path: &Path,
+    name: &str,
+    cataloged: bool,
+    now: u64,
+) -> String {
+    if cataloged {
+        return name.to_string();
+    }
+    let identity = match path_identity(path) {
+        Some(i) => i,
+        None => return name.to_string(),
+    };
+    resources
+        .iter()
+        .find(|(_, f)| f.identity() == Some(identity) && f.is_valid(path, now))
+        .map(|(k, _)| k.clone())
+        .unwrap_or_else(|| {
+            format!("{}{}:{}", IDENTITY_PREFIX, identity.0, identity.1)
+        })
+}
+
+/// Whether a cache key is that of a new file (see `cache_key`).
+pub fn is_identity_key(key: &str) -> bool {
+    key.starts_with(IDENTITY_PREFIX)
+}
+
+/// Move the entries of new files that were cataloged from their
+/// identity to the initial checksum of their resource (see
+/// `cache_key`). New files that weren't cataloged, such as kept
+/// duplicates, keep their entry.
+///
+/// # Arguments
+///
+/// * `resources` - Cached data of each resource.
+/// * `catalog` - Library catalog, once it was updated.
+/// * `storage` - Resources directory, once the new files were stored.
+pub fn rekey(
+    resources: &mut IndexMap<String, CacheFields>,
+    catalog: &Catalog,
+    storage: &Storage,
+) {
+    let new: Vec<String> = resources
+        .keys()
+        .filter(|k| is_identity_key(k))
+        .cloned()
+        .collect();
+    for key in new {
+        let fields = &resources[&key];
+        let resource = catalog.resources.iter().find(|r| {
+            r.checksum == fields.checksum
+                && path_identity(
+                    &storage.resource_path(&r.historical_checksums[0]),
+                ) == fields.identity()
+        });
+        if let Some(r) = resource {
+            let fields = resources.shift_remove(&key).unwrap();
+            resources.insert(r.historical_checksums[0].clone(), fields);
+        }
+    }
+}
+
+/// Version of a cache file, which is read before the rest of the file.
+/// Caches written before versions were recorded have none.
+#[derive(Deserialize)]
+struct CacheVersion {
+    #[serde(default)]
+    version: Option<u32>,
 }
 
 /// Reads a cache from a file into a `Cache` instance.
 ///
 /// If the catalog doesn't exist, this function will initialize it to
-/// an empty cache with the correct structure.
+/// an empty cache with the correct structure. A cache of another
+/// version (see `CACHE_VERSION`) is discarded, so it's read as an empty
+/// cache.
 ///
 /// # Arguments
 ///
@@ -22,12 +327,8 @@ pub struct CacheFields {
 ///
 /// # Returns
 ///
-/// The cache as an `IndexMap` where the key is a string of the file
-/// name and the value is the `CacheFields` corresponding to that
-/// resource.
-pub fn read_cache_from_file(
-    cache_file: &mut File,
-) -> IndexMap<String, CacheFields> {
+/// An error if the cache can't be parsed.
+pub fn read_cache_from_file(cache_file: &mut File) -> Result<Cache, String> {
     let mut cache_contents = String::new();
     cache_file
         .read_to_string(&mut cache_contents)
@@ -40,8 +341,307 @@ pub fn read_cache_from_file(
         // cache_contents needs the current valid file contents to parse json
         cache_contents = new_cache_contents.to_string();
     }
+    parse_cache(&cache_contents)
+}
+
+/// Parse the contents of a cache file. Empty contents, and a cache of
+/// another version, are read as an empty cache.
+///
+/// # Returns
+///
+/// An error if the cache can't be parsed.
+pub fn parse_cache(contents: &str) -> Result<Cache, String> {
+    if contents.is_empty() {
+        return Ok(Cache::default());
+    }
+    let version: CacheVersion =
+        serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    match version.version {
+        Some(CACHE_VERSION) => {
+            serde_json::from_str(contents).map_err(|e| e.to_string())
+        }
+        _ => Ok(Cache::default()),
+    }
+}
 
-    let cache: IndexMap<String, CacheFields> =
-        serde_json::from_str(&cache_contents).unwrap();
+/// Read the cache of a library, which is empty if there is none.
+fn read_cache(library: &Path, catalog: &Catalog) -> Cache {
+    let path = library.join(".cache");
+    if !path.exists() {
+        return Cache::default();
+    }
+    let mut file = File::open(&path).expect("Failed to open cache");
+    let cache = read_cache_from_file(&mut file)
+        .unwrap_or_else(|e| panic!("Failed to parse cache: {}", e));
+    catalog.check_library_id(cache.library_id.as_deref(), "cache");
     cache
 }
+
+/// Write the cache of a library.
+fn write_cache(library: &Path, catalog: &Catalog, cache: Cache) {
+    write_atomically(
+        &library.join(".cache"),
+        &serde_json::to_string_pretty(&Cache {
+            version: CACHE_VERSION,
+            library_id: catalog.library_id.clone(),
+            ..cache
+        })
+        .unwrap(),
+    );
+}
+
+/// Print the number of entries in the cache, the hits and misses of the
+/// last `librarian catalog` and how long ago the entries were verified.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog` - Library catalog.
+pub fn librarian_cache_stats(library: &Path, catalog: &Catalog) {
+    let cache = read_cache(library, catalog);
+    let new = cache
+        .resources
+        .keys()
+        .filter(|k| is_identity_key(k))
+        .count();
+    println!(
+        "{}",
+        message(
+            "cache-entries",
+            &[("count", cache.resources.len().into()), ("new", new.into())]
+        )
+    );
+    match &cache.last_run {
+        Some(run) => println!(
+            "{}",
+            message(
+                "cache-last-run",
+                &[
+                    (
+                        "time",
+                        String::from(DateTime::from(
+                            SystemTime::UNIX_EPOCH
+                                + Duration::from_secs(run.time)
+                        ))
+                        .into()
+                    ),
+                    ("hits", run.hits.into()),
+                    ("misses", run.misses.into())
+                ]
+            )
+        ),
+        None => println!("{}", message("cache-no-run", &[])),
+    }
+    if cache.resources.is_empty() {
+        return;
+    }
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // Verified within a day, a week, 30 days, and earlier.
+    let mut ages = [0; 4];
+    for fields in cache.resources.values() {
+        let days = now.saturating_sub(fields.last_verified) / (24 * 60 * 60);
+        ages[match days {
+            0 => 0,
+            1..=6 => 1,
+            7..=29 => 2,
+            _ => 3,
+        }] += 1;
+    }
+    println!(
+        "{}",
+        message(
+            "cache-ages",
+            &[
+                ("day", ages[0].into()),
+                ("week", ages[1].into()),
+                ("month", ages[2].into()),
+                ("older", ages[3].into())
+            ]
+        )
+    );
+}
+
+/// Remove every entry from the cache, so that the next `librarian
+/// catalog` computes every checksum.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog` - Library catalog.
+pub fn librarian_cache_clear(library: &Path, catalog: &Catalog) {
+    let cache = read_cache(library, catalog);
+    let count = cache.resources.len();
+    write_cache(library, catalog, Cache::default());
+    println!("{}", message("cache-cleared", &[("count", count.into())]));
+}
+
+/// Remove the entries of the cache that don't belong to a cataloged
+/// resource, such as those of deleted resources and of new files that
+/// weren't cataloged.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog` - Library catalog.
+pub fn librarian_cache_prune(library: &Path, catalog: &Catalog) {
+    let mut cache = read_cache(library, catalog);
+    let keys: std::collections::HashSet<&String> = catalog
+        .resources
+        .iter()
+        .map(|r| &r.historical_checksums[0])
+        .collect();
+    let count = cache.resources.len();
+    cache.resources.retain(|k, _| keys.contains(k));
+    let pruned = count - cache.resources.len();
+    write_cache(library, catalog, cache);
+    println!("{}", message("cache-pruned", &[("count", pruned.into())]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_read_old_cache() {
+        let path = std::env::temp_dir()
+            .join(format!("librarian-old-cache-{}", std::process::id()));
+        let read = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            read_cache_from_file(&mut File::open(&path).unwrap()).unwrap()
+        };
+        // Caches of older formats are discarded.
+        let cache =
+            read("{\"aa\": {\"last_verified\": 1, \"checksum\": \"aa\"}}");
+        assert!(cache.resources.is_empty());
+        let cache = read(
+            "{\"library_id\": \"x\", \"resources\": {\"aa\": {\"last_verified\": 1, \"checksum\": \"aa\"}}}",
+        );
+        assert!(cache.library_id.is_none() && cache.resources.is_empty());
+        let cache = read(&format!(
+            "{{\"version\": {}, \"resources\": {{\"aa\": {{\"last_verified\": 1, \"checksum\": \"aa\"}}}}}}",
+            CACHE_VERSION
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(cache.resources["aa"].checksum == "aa");
+    }
+
+    #[test]
+    fn test_cache_fields_is_valid() {
+        let path = std::env::temp_dir()
+            .join(format!("librarian-cache-{}", std::process::id()));
+        std::fs::write(&path, "original").unwrap();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let fields = CacheFields::new(&path, String::from("aa"), now);
+        assert!(fields.is_valid(&path, now));
+
+        // Content changed by a tool that preserves the modification
+        // time.
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "replaced").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(!fields.is_valid(&path, now));
+
+        // Copied over with the same size and modification time.
+        let fields = CacheFields::new(&path, String::from("bb"), now);
+        let copy = path.with_extension("copy");
+        std::fs::copy(&path, &copy).unwrap();
+        File::options()
+            .write(true)
+            .open(&copy)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        std::fs::rename(&copy, &path).unwrap();
+        #[cfg(unix)]
+        assert!(!fields.is_valid(&path, now));
+
+        // Modified in the future by a machine with a skewed clock.
+        let fields = CacheFields::new(&path, String::from("bb"), now);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(3600))
+            .unwrap();
+        assert!(!fields.is_valid(&path, now));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_key() {
+        use crate::storage::StorageLayout;
+
+        let root = std::env::temp_dir()
+            .join(format!("librarian-cache-key-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut resources = IndexMap::new();
+        // A new file is cached under its identity, which it keeps when
+        // it's renamed to its checksum, so its entry doesn't collide
+        // with that of the resource whose initial checksum it has.
+        std::fs::write(root.join("paper.pdf"), "a").unwrap();
+        let key = cache_key(
+            &resources,
+            &root.join("paper.pdf"),
+            "paper.pdf",
+            false,
+            now,
+        );
+        assert!(is_identity_key(&key));
+        resources.insert(
+            key.clone(),
+            CacheFields::new(&root.join("paper.pdf"), "aa".to_string(), now),
+        );
+        std::fs::write(root.join("aa"), "b").unwrap();
+        resources.insert(
+            "aa".to_string(),
+            CacheFields::new(&root.join("aa"), "bb".to_string(), now),
+        );
+        std::fs::rename(root.join("paper.pdf"), root.join("cc")).unwrap();
+        assert!(
+            cache_key(&resources, &root.join("cc"), "cc", true, now) == "cc"
+        );
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        rekey(
+            &mut resources,
+            &catalog,
+            &Storage::new(&root, StorageLayout::Flat),
+        );
+        assert!(resources["cc"].checksum == "aa");
+        assert!(resources["aa"].checksum == "bb");
+
+        // A resource's file renamed by hand keeps its entry.
+        std::fs::rename(root.join("cc"), root.join("renamed.pdf")).unwrap();
+        assert!(
+            cache_key(
+                &resources,
+                &root.join("renamed.pdf"),
+                "renamed.pdf",
+                false,
+                now
+            ) == "cc"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::download::download;
+use crate::i18n::message;
+use crate::inbox::INBOX_DIR;
+use crate::resource::{Name, Resource};
+use crate::webpage::html_metadata;
+
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+/// Metadata given with a capture, which overrides what is found in the
+/// captured file.
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// What a capture refers to.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Url(Url),
+    File(PathBuf),
+}
+
+/// Source of a capture and the title given with it. Besides a URL or a
+/// file, the source can be an org-protocol URL, as sent by a browser
+/// bookmarklet (e.g.,
+/// "org-protocol://librarian?url=https%3A%2F%2Fexample.com&title=Example"),
+/// whose `url` and `title` parameters are used.
+fn parse_source(source: &str) -> (Source, Option<String>) {
+    if source.starts_with("org-protocol:") {
+        let url = Url::parse(source)
+            .unwrap_or_else(|e| panic!("Invalid URL {}: {}", source, e));
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        let target = param("url").unwrap_or_else(|| {
+            panic!("{} lacks a url parameter", source);
+        });
+        return (parse_source(&target).0, param("title"));
+    }
+    match Url::parse(source) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => {
+            (Source::Url(url), None)
+        }
+        _ => (Source::File(PathBuf::from(source)), None),
+    }
+}
+
+/// File name a downloaded URL is stored under, which gives the
+/// resource's document type. Pages whose URL has no extension are taken
+/// to be HTML.
+fn download_name(url: &Url) -> String {
+    let segment = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .filter(|s| !s.is_empty())
+        .or_else(|| url.host_str())
+        .unwrap_or("page")
+        .to_string();
+    match Path::new(&segment).extension() {
+        Some(_) => segment,
+        None => format!("{}.html", segment),
+    }
+}
+
+/// Add a URL or a file to the library's inbox as a pending resource, in
+/// one step, and print its citation key, so that an editor or browser
+/// keybinding can capture a paper and cite it right away. A file is
+/// copied, so that it stays where it is. The resource is reviewed later
+/// with `librarian review`, like the other files of the inbox.
+///
+/// Capturing a file the library already has prints the key of the
+/// existing resource.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `source` - URL, org-protocol URL or path of the file to capture.
+/// * `capture` - Metadata given with the capture.
+pub fn librarian_capture(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    source: &str,
+    capture: &Capture,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    if !catalog.tags.is_empty() {
+        if let Err(e) = catalog.check_tags(&capture.tags) {
+            panic!("{}", e);
+        }
+    }
+    let authors: Vec<Name> = capture
+        .authors
+        .iter()
+        .map(|a| {
+            Name::try_from(a.as_str())
+                .unwrap_or_else(|e| panic!("Invalid author {}: {}", a, e))
+        })
+        .collect();
+    let (source, title) = parse_source(source);
+
+    // The file is prepared in a hidden directory of the inbox, which
+    // isn't staged.
+    let inbox = library.join(INBOX_DIR);
+    let staging = inbox.join(format!(".capture-{}", std::process::id()));
+    std::fs::create_dir_all(&staging)
+        .expect("Failed to create inbox directory");
+    let path = match &source {
+        Source::Url(url) => {
+            let agent = ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(30))
+                .timeout_read(Duration::from_secs(60))
+                .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+                .build();
+            let path = staging.join(download_name(url));
+            if let Err(e) = download(&agent, url.as_str(), &path, None) {
+                std::fs::remove_dir_all(&staging).ok();
+                panic!("{}", e);
+            }
+            path
+        }
+        Source::File(file) => {
+            let name = file
+                .file_name()
+                .unwrap_or_else(|| panic!("{} is not a file", file.display()));
+            let path = staging.join(name);
+            std::fs::copy(file, &path).unwrap_or_else(|e| {
+                panic!("Failed to copy {}: {}", file.display(), e)
+            });
+            path
+        }
+    };
+
+    let checksum = file_checksum(&path);
+    let existing = catalog
+        .resources
+        .iter()
+        .chain(&catalog.pending)
+        .find(|r| r.historical_checksums.contains(&checksum));
+    if let Some(resource) = existing {
+        std::fs::remove_dir_all(&staging).ok();
+        eprintln!(
+            "{}",
+            message(
+                "capture-existing",
+                &[("title", resource.title.as_str().into())]
+            )
+        );
+        println!("{}", resource.bibtex_key());
+        return;
+    }
+
+    let mut resource: Resource = catalog.new_resource(
+        &path,
+        &path,
+        checksum.clone(),
+        &catalog.filename_regexes(),
+        true,
+        false,
+    );
+    if path.extension().is_some_and(|e| e == "html") {
+        let html = std::fs::read(&path).expect("Failed to read page");
+        html_metadata(&String::from_utf8_lossy(&html)).apply(&mut resource);
+    }
+    if let Source::Url(url) = source {
+        resource.url = Some(url);
+    }
+    if let Some(title) = capture.title.clone().or(title) {
+        resource.title = title;
+    }
+    if !authors.is_empty() {
+        resource.author = Some(authors);
+    }
+    if !capture.tags.is_empty() {
+        resource.tags = Some(capture.tags.clone());
+    }
+    resource.citekey = catalog.new_citekey(&resource);
+    if !catalog.dry_run {
+        std::fs::rename(&path, inbox.join(&checksum))
+            .expect("Failed to move file to inbox");
+    }
+    std::fs::remove_dir_all(&staging).ok();
+
+    let key = resource.bibtex_key();
+    eprintln!(
+        "{}",
+        message("capture-done", &[("title", resource.title.as_str().into())])
+    );
+    catalog.pending.push(resource);
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "capture",
+                vec![checksum],
+                vec![format!("Captured {} to the inbox", key)],
+            ),
+        );
+    }
+    println!("{}", key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_source() {
+        let (source, title) = parse_source(
+            "org-protocol://librarian?url=https%3A%2F%2Farxiv.org%2Fpdf%2F1706.03762&title=Attention",
+        );
+        let url = Url::parse("https://arxiv.org/pdf/1706.03762").unwrap();
+        assert!(source == Source::Url(url.clone()));
+        assert!(title.as_deref() == Some("Attention"));
+        // Pages without an extension are stored as HTML.
+        assert!(
+            download_name(
+                &Url::parse("https://example.com/blog/post").unwrap()
+            ) == "post.html"
+        );
+        assert!(
+            parse_source("papers/oscillators.pdf").0
+                == Source::File(PathBuf::from("papers/oscillators.pdf"))
+        );
+    }
+}
@@ -1,49 +1,370 @@
-use crate::bibtex::BibtexType;
-use crate::cache::{read_cache_from_file, CacheFields};
-use crate::resource::{DocumentType, Resource};
+use crate::activity::RecentActivity;
+use crate::ancillary::{mark_rebuilt, read_ancillary};
+use crate::batch::write_atomically;
+use crate::bibtex::ContentType;
+use crate::cache::{
+    cache_key, is_identity_key, parse_cache, read_cache_from_file, rekey,
+    Cache, CacheFields, CacheRun, CACHE_VERSION,
+};
+use crate::check::{detect_extension, extension_media_type};
+use crate::describe::describe_resources;
+use crate::hooks::run_hook;
+use crate::i18n::message;
+use crate::inbox::{ask, stage_inbox, INBOX_DIR};
+use crate::instance::Instance;
+use crate::lint::LintRules;
+use crate::metadata::{fetch_crossref, filename_metadata};
+use crate::pdf::{pdf_doi, pdf_metadata};
+use crate::provider::MetadataProvider;
+use crate::remote::synchronize;
+use crate::resource::{
+    Attachment, DateTime, DocumentType, Doi, MediaType, NameFormat, Resource,
+    Tag,
+};
+use crate::spreadsheet::ImportProfile;
+use crate::storage::{Storage, StorageLayout};
+use crate::style::StyleRules;
+use crate::timings::{time, Phase};
+use crate::versions::save_revisions;
 
 use hex;
 use indexmap::IndexMap;
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::{File, OpenOptions};
-use std::io::{prelude::*, stdin, stdout, Read, SeekFrom, Write};
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use walkdir::WalkDir;
 
 /// Library catalog contained within the catalog.json file.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Catalog {
-    // pub tags: Vec<Tag>,
+    /// Identifier generated when the library is created, which is
+    /// recorded in the library's other files so that files belonging to
+    /// another library are detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_id: Option<String>,
+    /// Tree of the tags resources can have, keyed by tag name. Resource
+    /// tags aren't validated when this is empty.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tags: IndexMap<String, Tag>,
     pub document_types: IndexMap<String, DocumentType>,
-    pub content_types: IndexMap<String, BibtexType>,
-    // pub instances: Vec<Instance>,
+    pub content_types: IndexMap<String, ContentType>,
+    /// Regular expressions matched against the file name of each new
+    /// resource. The named captures `title`, `subtitle`, `author` and
+    /// `year` of the first matching pattern initialize the
+    /// corresponding fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filename_patterns: Vec<String>,
+    /// Naming scheme of the files in the resources directory.
+    #[serde(default, skip_serializing_if = "StorageLayout::is_flat")]
+    pub storage: StorageLayout,
+    /// Name resource files after their checksum followed by the
+    /// extension of their document type (e.g., "<checksum>.pdf"), so
+    /// that viewers and file managers recognize their type.
+    /// `librarian catalog` renames the files when this changes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub file_extensions: bool,
+    /// Keep a copy of every revision of the files of resources in the
+    /// `.versions` directory of the resources directory, so that the
+    /// contents a resource had at one of its historical checksums can
+    /// be restored (see `librarian_checkout`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_revisions: bool,
+    /// Pattern of the citation keys given to new resources, such as
+    /// "{lastname}{year}{shorttitle}". See `generate_citekey`. Resources
+    /// use their initial checksum as their key when this is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citekey_scheme: Option<String>,
+    /// Style names are displayed in, e.g., in generated indexes.
+    #[serde(default, skip_serializing_if = "NameFormat::is_first_last")]
+    pub name_format: NameFormat,
+    /// Spelling variants of author names, keyed by the canonical
+    /// spelling, so that the resources of an author are grouped
+    /// together however their names were written.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub author_aliases: IndexMap<String, Vec<String>>,
+    /// Rules making titles and journal names consistent, applied by
+    /// `librarian fmt` and `librarian check`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<StyleRules>,
+    /// Rules checked by `librarian lint`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lint: Option<LintRules>,
+    /// Pairs of resources `librarian dedupe` was told aren't the same
+    /// work, by initial checksum.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distinct: Vec<[String; 2]>,
+    /// Lifecycle states of resources in the library's curation
+    /// workflow, each mapped to the states a resource can move to from
+    /// it. Resources without a state are in the first state.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub states: IndexMap<String, Vec<String>>,
+    /// Boost the search ranking of resources that were recently opened
+    /// or annotated. Search results are ranked by relevance alone when
+    /// this is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_activity: Option<RecentActivity>,
+    /// Mappings of the columns of CSV files to resource fields, keyed by
+    /// the name passed to `import-csv --profile`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub import_profiles: IndexMap<String, ImportProfile>,
+    /// External programs returning the metadata of resources, keyed by
+    /// name and tried in order by `librarian fetch-metadata` and
+    /// `librarian enrich`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub metadata_providers: IndexMap<String, MetadataProvider>,
+    /// Extension of new reading notes, which determines their markup
+    /// (e.g., "org"). Notes are written in Markdown when this is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_extension: Option<String>,
+    /// Reading lists of resources, keyed by name. Each lists the initial
+    /// checksums of its resources in the order they should be read.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub collections: IndexMap<String, Vec<String>>,
+    /// Directories of human-readable links to the resources, keyed by
+    /// the name of the instance.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub instances: IndexMap<String, Instance>,
+    /// Commit the catalog to the git repository of the library after
+    /// each command that changes it, creating the repository if needed
+    /// (see `librarian log`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git: bool,
+    /// Location of the storage holding the resource files, an S3 bucket
+    /// ("s3://bucket/prefix") or a WebDAV collection (its HTTP or HTTPS
+    /// URL). `librarian catalog` uploads new files there and removes
+    /// their local copy, and commands needing a file download it again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_storage: Option<String>,
+    /// Resources staged from the library's inbox that haven't been
+    /// reviewed yet (see `librarian review`). Their files are kept in
+    /// the inbox under their checksum until they're accepted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<Resource>,
+    /// Resources are omitted from the top-level catalog file of a split
+    /// catalog, so this may be missing.
+    #[serde(default)]
     pub resources: Vec<Resource>,
+    /// Hash of each resource as it was read, keyed by initial checksum,
+    /// which tells the resources that changed when the catalog is
+    /// written.
+    #[serde(skip)]
+    loaded: HashMap<String, u64>,
+    /// Whether the catalog was read for a dry run (see
+    /// `CatalogStore::dry_run`), which files of the library are left
+    /// alone in.
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+/// Location where a catalog is stored on disk.
+///
+/// A catalog can either be stored in a single JSON file or split across
+/// a directory. The split layout stores everything except the resources
+/// in `catalog.json` at the top of the directory and each resource in
+/// its own file, `resources/<initial checksum>.json`. This keeps
+/// unrelated metadata edits in separate files, which avoids most merge
+/// conflicts when the catalog is version-controlled.
+///
+/// Either way, files are replaced by writing a temporary file and
+/// renaming it, so an interrupted write never truncates the catalog.
+pub struct CatalogStore {
+    layout: CatalogLayout,
+    /// Print the changes to the catalog instead of writing them.
+    dry_run: bool,
+}
+
+/// Whether a catalog is stored in a single file or split across a
+/// directory, and where.
+enum CatalogLayout {
+    File(PathBuf),
+    Split(PathBuf),
+}
+
+impl CatalogStore {
+    /// Open the catalog at `catalog_path`.
+    ///
+    /// A directory is treated as a split catalog. Anything else is
+    /// treated as a single-file catalog, which is created when it's
+    /// first read if it doesn't exist.
+    pub fn open(catalog_path: &Path) -> CatalogStore {
+        let layout = match catalog_path.is_dir() {
+            true => CatalogLayout::Split(catalog_path.to_path_buf()),
+            false => CatalogLayout::File(catalog_path.to_path_buf()),
+        };
+        CatalogStore {
+            layout,
+            dry_run: false,
+        }
+    }
+
+    /// Store that prints the changes to the catalog instead of writing
+    /// them (see `print_plan`). Catalogs it reads are marked as read
+    /// for a dry run, so that their storage leaves files where they are
+    /// (see `Storage::of`) and commands skip their other changes.
+    pub fn dry_run(mut self, dry_run: bool) -> CatalogStore {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Read the catalog, initializing it if it doesn't exist yet. The
+    /// catalog can be read again to pick up changes made by others.
+    pub fn read(&mut self) -> Catalog {
+        let dry_run = self.dry_run;
+        let mut catalog = time(Phase::Serialization, || match &self.layout {
+            // A dry run doesn't initialize the catalog.
+            CatalogLayout::File(p) if dry_run => {
+                let contents = std::fs::read_to_string(p).unwrap_or_default();
+                serde_json::from_str(match contents.as_str() {
+                    "" => EMPTY_CATALOG,
+                    c => c,
+                })
+                .unwrap()
+            }
+            CatalogLayout::File(p) => Catalog::read_from_file(
+                &mut OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(p)
+                    .expect("Failed to open or create catalog"),
+            ),
+            CatalogLayout::Split(d) => Catalog::read_from_directory(d),
+        });
+        catalog.dry_run = dry_run;
+        catalog.snapshot();
+        catalog
+    }
+
+    /// Replace the stored catalog with `catalog`, after recording when
+    /// its resources were added or modified (see `Catalog::stamp`). A
+    /// dry run prints how the stored catalog would change instead.
+    pub fn write(&mut self, catalog: &mut Catalog) {
+        if self.dry_run {
+            let stored = self.read();
+            print_plan(&stored, catalog);
+            return;
+        }
+        catalog.stamp(DateTime::from(SystemTime::now()));
+        let catalog = &*catalog;
+        time(Phase::Serialization, || match &self.layout {
+            CatalogLayout::File(p) => write_atomically(
+                p,
+                &serde_json::to_string_pretty(catalog).unwrap(),
+            ),
+            CatalogLayout::Split(d) => catalog.write_to_directory(d),
+        })
+    }
+}
+
+/// Contents of a new catalog.
+const EMPTY_CATALOG: &str = concat!(
+    "{\n",
+    // "  \"tags\": [],\n",
+    "  \"document_types\": {},\n",
+    "  \"content_types\": {},\n",
+    // "  \"instances\": [],\n",
+    "  \"resources\": []\n",
+    "}",
+);
+
+/// Hash of a resource, which changes with any of its fields.
+fn resource_hash(resource: &Resource) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // Custom fields are a map, which can't be hashed, so the serialized
+    // resource is hashed instead.
+    serde_json::to_string(resource).unwrap().hash(&mut hasher);
+    hasher.finish()
 }
 
 impl Catalog {
+    /// Remember the current state of every resource, which `stamp`
+    /// compares resources against.
+    fn snapshot(&mut self) {
+        self.loaded = self
+            .resources
+            .iter()
+            .map(|r| (r.historical_checksums[0].clone(), resource_hash(r)))
+            .collect();
+    }
+
+    /// Set the date new resources were added and the date changed
+    /// resources were modified, compared to the last snapshot. The
+    /// catalog is then snapshot again.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Date and time of the changes.
+    pub fn stamp(&mut self, now: DateTime) {
+        for resource in self.resources.iter_mut() {
+            match self.loaded.get(&resource.historical_checksums[0]) {
+                Some(h) if *h == resource_hash(resource) => (),
+                Some(_) => resource.date_modified = Some(now.clone()),
+                None => {
+                    if resource.date_added.is_none() {
+                        resource.date_added = Some(now.clone());
+                    }
+                }
+            }
+        }
+        self.snapshot();
+    }
+
     /// Update the catalog to reflect the current resources.
     ///
     /// This function performs several tasks. It:
     /// 1. Adds new resources to the catalog.
     /// 2. Updates the checksums of files that have been modified.
-    /// 3. Deletes catalog entries no longer backed by a resource (orphans).
+    /// 3. Retires catalog entries no longer backed by a resource
+    ///    (orphans), keeping their metadata (see `Resource::retired`).
     ///
     /// # Arguments
     ///
+    /// * `storage` - Resources directory, which new resources are
+    ///   moved to their location in.
     /// * `resources` - Checksum and file path for every resource.
-    /// * `remove_orphans` - Whether to remove orphans when
+    /// * `incremental` - `resources` only holds the files that changed,
+    ///   as found by `librarian watch`, rather than every resource. The
+    ///   other cataloged resources aren't orphans then, and are left to
+    ///   a full update. A resource only counts as moved when its file is
+    ///   gone.
+    /// * `remove_orphans` - Whether to retire orphans when
     /// cataloging. If set to "ask", prompt for each orphan to be
-    /// removed. When set to "true", automatically remove all orphans
+    /// retired. When set to "true", automatically retire all orphans
     /// without prompting. When set to "false", automatically keep all
     /// orphans without prompting.
+    /// * `purge_retired` - Remove retired resources from the catalog,
+    ///   including the orphans retired by this update.
+    /// * `detect_doi` - Whether to search new PDF resources for their
+    ///   DOI.
+    /// * `fetch_metadata` - Whether to fetch metadata from CrossRef for
+    ///   new resources whose DOI was detected.
+    /// * `attach_duplicates` - Whether to add the file of a new
+    ///   resource to the attachments of an existing resource with the
+    ///   same DOI or ISBN, instead of cataloging it separately. The
+    ///   existing resource keeps its file. Takes the same values as
+    ///   `remove_orphans`.
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
+        storage: &Storage,
         resources: &IndexMap<String, PathBuf>,
+        incremental: bool,
         remove_orphans: &str,
+        purge_retired: bool,
+        detect_doi: bool,
+        fetch_metadata: bool,
+        attach_duplicates: &str,
     ) {
         // Create a hashmap of all cataloged resources for fast
         // lookup. The first entry of the hashmap is the initial checksum
@@ -66,29 +387,21 @@ impl Catalog {
                 .insert(resource.historical_checksums[0].clone());
         }
 
-        // Hashmap of document types, where the key is the extension
-        // and the value is the document type name. This is used for
-        // fast lookup of an associated document type for a given
-        // extension, which is used when initializing a new resource
-        // in the catalog.
-        let mut doc_types = IndexMap::<String, String>::new();
-        for (key, value) in &self.document_types {
-            // Lower-case the document type extension and the actual
-            // file extension (later) to ensure comparisons don't fail
-            // as a result of case.
-            doc_types
-                .insert(value.extension.to_lowercase().clone(), key.clone());
-        }
+        let filename_patterns = self.filename_regexes();
+
+        // Initial checksums of the cataloged resources whose file is
+        // still where it was.
+        let present: HashSet<String> =
+            resources.values().map(|p| storage.key(p)).collect();
+        let gone = |key: &String| match incremental {
+            true => !storage.resource_path(key).exists(),
+            false => !present.contains(key),
+        };
 
         // Catalog each new resource or update the checksum if the
         // resource's contents have changed.
         for (checksum, resource_path) in resources {
-            let mut file_name = resource_path
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string();
+            let file_name = storage.key(resource_path);
             match catalog_resources.get_mut(&file_name) {
                 // update the checksum if it's changed
                 Some(r) => {
@@ -97,119 +410,343 @@ impl Catalog {
                         r.historical_checksums.push(new_checksum.clone());
                         r.checksum = new_checksum;
                     }
+                    r.retired = false;
                     orphaned_catalog_resources.remove(&file_name);
                 }
                 None => {
-                    // rename the file to the current SHA-1 contents
                     let checksum = checksum.to_string();
-                    let new_file_path =
-                        resource_path.parent().unwrap().join(checksum.clone());
-
-                    // If the file extension matches a document type
-                    // extension, initialize the document type to
-                    // that. Also, remove the extension from the
-                    // title.
-                    let doc_type: Option<String>;
-                    match resource_path.extension() {
-                        // ignore extension case
-                        Some(e) => match e.to_ascii_lowercase().to_str() {
-                            Some(e) => {
-                                match doc_types.get(e) {
-                                    Some(d) => {
-                                        doc_type = Some(d.clone());
-                                        // This shouldn't fail if getting the extension
-                                        // succeeds.
-                                        file_name = resource_path
-                                            .file_stem()
-                                            .unwrap()
-                                            .to_str()
-                                            .unwrap()
-                                            .to_string();
-                                    }
-                                    None => {
-                                        doc_type = None;
-                                    }
-                                }
-                            }
-                            None => {
-                                doc_type = None;
-                            }
-                        },
-                        None => {
-                            doc_type = None;
+                    let folder = storage.folder_path(resource_path);
+                    // A resource moved to another folder has the same
+                    // contents as a resource whose file is gone.
+                    let moved = catalog_resources
+                        .values_mut()
+                        .filter(|r| {
+                            let key = &r.historical_checksums[0];
+                            orphaned_catalog_resources.contains(key)
+                                && gone(key)
+                        })
+                        .find(|r| r.checksum == checksum);
+                    if let Some(r) = moved {
+                        let key = r.historical_checksums[0].clone();
+                        if folder.is_none() {
+                            storage.store(resource_path, &key);
                         }
+                        r.path = folder;
+                        r.retired = false;
+                        orphaned_catalog_resources.remove(&key);
+                        continue;
+                    }
+                    // Files in a subdirectory stay where they are. The
+                    // others are renamed to their SHA-1 contents.
+                    let new_file_path = match &folder {
+                        Some(_) => resource_path.clone(),
+                        None => storage.store(resource_path, &checksum),
                     };
-                    std::fs::rename(resource_path, new_file_path.clone())
-                        .unwrap();
-
-                    catalog_resources.insert(
+                    let mut resource = self.new_resource(
+                        resource_path,
+                        &new_file_path,
                         checksum.clone(),
-                        Resource {
-                            title: file_name,
-                            subtitle: None,
-                            author: None,
-                            editor: None,
-                            date: None,
-                            edition: None,
-                            version: None,
-                            publisher: None,
-                            organization: None,
-                            journal: None,
-                            volume: None,
-                            number: None,
-                            part_number: None,
-                            doi: None,
-                            tags: None,
-                            document: doc_type,
-                            content: None,
-                            url: None,
-                            checksum: checksum.clone(),
-                            historical_checksums: std::vec!(checksum),
-                        },
+                        &filename_patterns,
+                        detect_doi,
+                        fetch_metadata,
                     );
-                }
-            }
-        }
+                    // Files cataloged in place aren't moved over another
+                    // resource's file.
+                    let in_place = folder.is_some();
+                    resource.path = folder;
 
-        // remove cataloged resources that are no longer in the resources
-        // directory
-        for resource in orphaned_catalog_resources.iter() {
-            let delete = match remove_orphans {
-                "true" => true,
-                "false" => false,
-                "ask" => {
-                    let mut response = String::new();
-                    loop {
-                        print!("Remove orphan {}? (y/n): ", resource);
-                        stdout().flush().expect("Failed to flush output stream.");
-                        match stdin().read_line(&mut response) {
-                            Ok(_) => {
-                                if response == "y\n" {
-                                    break true;
-                                } else if response == "n\n" {
-                                    break false;
-                                } else {
-                                    println!("Invalid response, please enter 'y' or 'n'.");
-                                    response.clear();
-                                }
-                            }
-                            Err(_) => {
-                                println!("Invalid string, please enter 'y' or 'n'.");
-                                response.clear();
+                    let duplicate = catalog_resources
+                        .values_mut()
+                        .filter(|_| !in_place)
+                        .find(|r| resource.is_duplicate_of(r));
+                    if let Some(d) = duplicate {
+                        let key = d.historical_checksums[0].clone();
+                        println!(
+                            "{}",
+                            message(
+                                "catalog-same-identifier",
+                                &[
+                                    ("title", resource.title.as_str().into()),
+                                    ("key", key.as_str().into()),
+                                    ("other", d.title.as_str().into())
+                                ]
+                            )
+                        );
+                        if confirm(
+                            attach_duplicates,
+                            &message(
+                                "catalog-attach-file",
+                                &[("key", key.as_str().into())],
+                            ),
+                        ) {
+                            let attachments =
+                                d.attachments.get_or_insert_with(Vec::new);
+                            let mut name = resource_path
+                                .file_name()
+                                .unwrap()
+                                .to_string_lossy()
+                                .to_string();
+                            if attachments.iter().any(|a| a.name == name) {
+                                name = format!("{}-{}", checksum, name);
                             }
+                            storage.attach(&checksum, &key, &name);
+                            attachments.push(Attachment {
+                                name,
+                                checksum,
+                                description: None,
+                            });
+                            continue;
                         }
                     }
+                    catalog_resources.insert(checksum, resource);
                 }
-                &_ => panic!("Possible argument values should prevent this condition from being reached. Check clap setup.")
-            };
+            }
+        }
 
-            if delete {
-                catalog_resources.remove(resource);
+        // Retire cataloged resources that are no longer in the resources
+        // directory, after listing them in catalog order.
+        let orphans: Vec<Resource> = catalog_resources
+            .values()
+            .filter(|r| {
+                !incremental
+                    && orphaned_catalog_resources
+                        .contains(&r.historical_checksums[0])
+                    && r.remote.is_none()
+                    && !r.retired
+            })
+            .cloned()
+            .collect();
+        if !orphans.is_empty() {
+            println!(
+                "{}",
+                message("catalog-orphans", &[("count", orphans.len().into())])
+            );
+            for orphan in &orphans {
+                println!(
+                    "  {} {}",
+                    orphan.historical_checksums[0], orphan.title
+                );
             }
         }
+        for orphan in orphans {
+            let key = &orphan.historical_checksums[0];
+            let question = message(
+                "catalog-remove-orphan",
+                &[
+                    ("key", key.as_str().into()),
+                    ("title", orphan.title.as_str().into()),
+                ],
+            );
+            if confirm(remove_orphans, &question) {
+                catalog_resources.get_mut(key).unwrap().retired = true;
+            }
+        }
+        if purge_retired {
+            catalog_resources.retain(|_, r| !r.retired);
+        }
 
         self.resources = catalog_resources.values().cloned().collect();
 
+        self.sort();
+        self.assign_citekeys();
+    }
+
+    /// Compiled regular expressions of `filename_patterns`.
+    pub(crate) fn filename_regexes(&self) -> Vec<Regex> {
+        self.filename_patterns
+            .iter()
+            .map(|p| {
+                Regex::new(p).unwrap_or_else(|e| {
+                    panic!("Invalid filename pattern {}: {}", p, e)
+                })
+            })
+            .collect()
+    }
+
+    /// Resource for a new file. The document type is initialized from
+    /// the file's extension, and the title from its name without that
+    /// extension, unless metadata found in the file name (see
+    /// `filename_patterns`) or embedded in the document says otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Path the file was found at, whose name is used.
+    /// * `path` - Current location of the file.
+    /// * `checksum` - Checksum of the file.
+    /// * `filename_patterns` - See `filename_regexes`.
+    /// * `detect_doi` - See description for `Catalog.update`.
+    /// * `fetch_metadata` - See description for `Catalog.update`.
+    pub(crate) fn new_resource(
+        &mut self,
+        name: &Path,
+        path: &Path,
+        checksum: String,
+        filename_patterns: &[Regex],
+        detect_doi: bool,
+        fetch_metadata: bool,
+    ) -> Resource {
+        let doc_type = self.document_type(name);
+        let file_name = match doc_type {
+            Some(_) => name.file_stem(),
+            None => name.file_name(),
+        }
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+        let filename_metadata =
+            filename_metadata(filename_patterns, &file_name);
+        // Files whose extension isn't that of a document type get the
+        // type of their content.
+        let doc_type = doc_type.or_else(|| self.detect_document_type(path));
+        let mut resource = Resource::new(file_name, doc_type, checksum);
+        // Prefer metadata embedded in the document over the file name.
+        if let Some(m) = filename_metadata {
+            m.apply(&mut resource);
+        }
+        if let Some(m) = pdf_metadata(path) {
+            m.apply(&mut resource);
+        }
+        if detect_doi && resource.doi.is_none() {
+            resource.doi =
+                pdf_doi(path).and_then(|d| Doi::try_from(d.as_str()).ok());
+        }
+        if let (true, Some(doi)) =
+            (detect_doi && fetch_metadata, resource.doi.clone())
+        {
+            // A failed fetch shouldn't abort cataloging the remaining
+            // resources.
+            match fetch_crossref(&doi) {
+                Ok(m) => m.apply(&mut resource),
+                Err(e) => eprintln!(
+                    "{}",
+                    message(
+                        "metadata-fetch-failed",
+                        &[
+                            ("identifier", doi.as_str().into()),
+                            ("error", e.to_string().into())
+                        ]
+                    )
+                ),
+            }
+        }
+        resource
+    }
+
+    /// Find a resource by its current checksum or any of its historical
+    /// checksums.
+    pub fn resource_mut(&mut self, checksum: &str) -> Option<&mut Resource> {
+        self.resources.iter_mut().find(|r| {
+            r.checksum == checksum
+                || r.historical_checksums.iter().any(|c| c == checksum)
+        })
+    }
+
+    /// Give the library an identifier if it doesn't have one yet.
+    pub fn ensure_library_id(&mut self) {
+        if self.library_id.is_none() {
+            self.library_id = Some(new_library_id());
+        }
+    }
+
+    /// Panic if a file records a different library identifier than the
+    /// catalog, which means it was copied from another library or the
+    /// library path points at the wrong directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `found` - Library identifier recorded in the file. Files
+    ///   written before identifiers were introduced don't have one and
+    ///   are accepted.
+    /// * `file` - Description of the file for the error message.
+    pub fn check_library_id(&self, found: Option<&str>, file: &str) {
+        if let (Some(f), Some(id)) = (found, &self.library_id) {
+            if f != id {
+                panic!(
+                    "The {} belongs to library {}, but the catalog is library {}. Check the library path, or delete the {} if it was copied from another library.",
+                    file, f, id, file
+                );
+            }
+        }
+    }
+
+    /// Resources to include in an export.
+    ///
+    /// # Arguments
+    ///
+    /// * `include_private` - Include private resources.
+    pub fn exported_resources(
+        &self,
+        include_private: bool,
+    ) -> impl Iterator<Item = &Resource> {
+        self.resources
+            .iter()
+            .filter(move |r| include_private || !r.private)
+    }
+
+    /// Document type whose extension matches the extension of a file,
+    /// ignoring case.
+    pub fn document_type(&self, path: &Path) -> Option<String> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.document_types
+            .iter()
+            .find(|(_, d)| d.extension.to_lowercase() == extension)
+            .map(|(k, _)| k.clone())
+    }
+
+    /// Document type of files with an extension, ignoring case. A
+    /// document type is added for the extension if there is none, with
+    /// the media type of the extension if it's a common one.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - Lowercase extension.
+    pub fn document_type_for(&mut self, extension: &str) -> String {
+        if let Some(d) =
+            self.document_type(Path::new(&format!("file.{}", extension)))
+        {
+            return d;
+        }
+        let mut name = extension.to_string();
+        while self.document_types.contains_key(&name) {
+            name.push('_');
+        }
+        let mime = extension_media_type(extension)
+            .map(|m| MediaType::try_from(m).unwrap());
+        println!(
+            "{}",
+            message(
+                "catalog-new-document-type",
+                &[("type", name.as_str().into())]
+            )
+        );
+        self.document_types.insert(
+            name.clone(),
+            DocumentType {
+                extension: extension.to_string(),
+                mime,
+            },
+        );
+        self.document_types.sort_keys();
+        name
+    }
+
+    /// Document type of a file according to its content, which is added
+    /// to the catalog if needed (see `document_type_for`).
+    ///
+    /// # Returns
+    ///
+    /// `None` for file types that aren't recognized.
+    pub fn detect_document_type(&mut self, path: &Path) -> Option<String> {
+        let extension = match path.is_file() {
+            true => detect_extension(path)?,
+            false => return None,
+        };
+        Some(self.document_type_for(extension))
+    }
+
+    /// Sort the resources, content types and document types.
+    pub fn sort(&mut self) {
         // Sort resources according to several fields, in sequence. A
         // tie in one field will then sort by the next field in the
         // sequence. The order of fields is:
@@ -265,29 +802,109 @@ impl Catalog {
 
         // initialize the catalog file if it's empty
         if catalog_contents == "" {
-            let new_catalog_contents = concat!(
-                "{\n",
-                // "  \"tags\": [],\n",
-                "  \"document_types\": {},\n",
-                "  \"content_types\": {},\n",
-                // "  \"instances\": [],\n",
-                "  \"resources\": []\n",
-                "}",
-            );
-            catalog_file.write(new_catalog_contents.as_bytes()).unwrap();
+            catalog_file.write(EMPTY_CATALOG.as_bytes()).unwrap();
             // catalog_contents needs the current valid file contents to parse json
-            catalog_contents = new_catalog_contents.to_string();
+            catalog_contents = EMPTY_CATALOG.to_string();
         }
 
         let catalog: Catalog = serde_json::from_str(&catalog_contents).unwrap();
         catalog
     }
+
+    /// Reads a split catalog from a directory into a `Catalog` instance.
+    ///
+    /// See `CatalogStore` for a description of the directory layout. An
+    /// empty directory is initialized to an empty catalog.
+    pub fn read_from_directory(catalog_dir: &Path) -> Catalog {
+        let mut settings_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(catalog_dir.join("catalog.json"))
+            .expect("Failed to open or create split catalog settings");
+        let mut catalog = Catalog::read_from_file(&mut settings_file);
+
+        let resources_dir = catalog_dir.join("resources");
+        std::fs::create_dir_all(&resources_dir)
+            .expect("Failed to create split catalog resources directory");
+        for entry in std::fs::read_dir(&resources_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = std::fs::read_to_string(&path)
+                .expect("failed to read catalog resource file");
+            let resource: Resource = serde_json::from_str(&contents)
+                .unwrap_or_else(|e| {
+                    panic!("Failed to parse {}: {}", path.display(), e)
+                });
+            catalog.resources.push(resource);
+        }
+
+        catalog.sort();
+        catalog
+    }
+
+    /// Write the catalog to a directory using the split layout.
+    ///
+    /// Resource files are only rewritten when their contents change and
+    /// files of resources no longer in the catalog are removed.
+    pub fn write_to_directory(&self, catalog_dir: &Path) {
+        let mut settings = serde_json::to_value(self).unwrap();
+        settings.as_object_mut().unwrap().remove("resources");
+        write_atomically(
+            &catalog_dir.join("catalog.json"),
+            &serde_json::to_string_pretty(&settings).unwrap(),
+        );
+
+        let resources_dir = catalog_dir.join("resources");
+        std::fs::create_dir_all(&resources_dir)
+            .expect("Failed to create split catalog resources directory");
+        let mut stale: HashSet<PathBuf> = std::fs::read_dir(&resources_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+            .collect();
+        for resource in &self.resources {
+            let path = resources_dir
+                .join(format!("{}.json", resource.historical_checksums[0]));
+            let contents = serde_json::to_string_pretty(resource).unwrap();
+            stale.remove(&path);
+            if std::fs::read_to_string(&path).ok().as_ref() != Some(&contents) {
+                write_atomically(&path, &contents);
+            }
+        }
+        for path in stale {
+            std::fs::remove_file(path)
+                .expect("Failed to remove stale catalog resource file");
+        }
+    }
 }
 
-/// Clear the contents of a file.
-fn clear_file(file: &mut std::fs::File) {
-    file.set_len(0).unwrap();
-    file.seek(SeekFrom::Start(0)).unwrap();
+/// Decide whether to apply a fix, according to a `fix` argument of
+/// "ask", "true" or "false". If set to "ask", prompt with `question`.
+/// When the input ends (e.g., when it isn't a terminal), the answer is
+/// no.
+pub(crate) fn confirm(fix: &str, question: &str) -> bool {
+    match fix {
+        "true" => true,
+        "false" => false,
+        "ask" => loop {
+            let prompt =
+                message("confirm-question", &[("question", question.into())]);
+            match ask(&prompt).as_deref() {
+                Some("y") => break true,
+                Some("n") => break false,
+                Some(_) => println!("{}", message("confirm-invalid", &[])),
+                None => {
+                    println!();
+                    break false;
+                }
+            }
+        },
+        &_ => panic!("Possible argument values should prevent this condition from being reached. Check clap setup."),
+    }
 }
 
 // Compute the SHA1 checksum for the contents of a file.
@@ -296,7 +913,7 @@ fn file_sha1(filepath: &PathBuf, hasher: &mut Sha1) {
     // allocation at any given time.
     let chunk_size = 0x4000;
     let mut f = File::open(filepath).expect("failed to open file");
-    loop {
+    time(Phase::Hash, || loop {
         let mut chunk = Vec::<u8>::with_capacity(chunk_size);
         let bytes_read = std::io::Read::by_ref(&mut f)
             .take(chunk_size as u64)
@@ -306,7 +923,36 @@ fn file_sha1(filepath: &PathBuf, hasher: &mut Sha1) {
         if bytes_read < chunk_size {
             break;
         }
+    })
+}
+
+/// Random (version 4) UUID identifying a library.
+fn new_library_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .expect("Failed to generate a library identifier");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Compute the SHA1 checksum of a file or directory, as used to
+/// identify a resource.
+pub fn file_checksum(path: &Path) -> String {
+    let mut hasher = Sha1::new();
+    match path.is_dir() {
+        true => directory_recursive_sha1(&path.to_path_buf(), &mut hasher),
+        false => file_sha1(&path.to_path_buf(), &mut hasher),
     }
+    hex::encode(hasher.finalize())
 }
 
 /// Compute a SHA1 checksum for the contents of a directory.
@@ -351,7 +997,10 @@ fn directory_recursive_sha1(directory_path: &PathBuf, hasher: &mut Sha1) {
 fn sha1(file_or_dir: &walkdir::DirEntry) -> String {
     let content_sha: String;
     let mut hasher = Sha1::new();
-    if file_or_dir.file_type().is_dir() {
+    // Followed symbolic links are hashed as their target.
+    if file_or_dir.file_type().is_dir()
+        || (file_or_dir.path_is_symlink() && file_or_dir.path().is_dir())
+    {
         directory_recursive_sha1(&file_or_dir.clone().into_path(), &mut hasher);
         content_sha = hex::encode(hasher.finalize());
     } else {
@@ -361,8 +1010,145 @@ fn sha1(file_or_dir: &walkdir::DirEntry) -> String {
     content_sha
 }
 
+/// Initialize a library: create its resources and inbox directories and
+/// give it an identifier.
+///
+/// This is safe to run on an existing library, which only receives an
+/// identifier if it doesn't have one yet.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+pub fn librarian_init(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+) {
+    std::fs::create_dir_all(resources_path)
+        .expect("Failed to create resources directory");
+    std::fs::create_dir_all(
+        resources_path
+            .parent()
+            .expect("resources path does not have a parent")
+            .join(INBOX_DIR),
+    )
+    .expect("Failed to create inbox directory");
+    catalog.ensure_library_id();
+    catalog_store.write(catalog);
+    println!(
+        "{}",
+        message(
+            "init-library",
+            &[("id", catalog.library_id.clone().unwrap().into())]
+        )
+    );
+}
+
+/// Names of the fields that differ between two values serialized as
+/// JSON objects, such as two versions of a resource.
+fn changed_fields<T: Serialize>(before: &T, after: &T) -> Vec<String> {
+    let object = |v: &T| match serde_json::to_value(v).unwrap() {
+        serde_json::Value::Object(o) => o,
+        _ => serde_json::Map::new(),
+    };
+    let (before, after) = (object(before), object(after));
+    let mut fields: Vec<String> = before
+        .keys()
+        .chain(after.keys().filter(|k| !before.contains_key(*k)))
+        .filter(|k| before.get(*k) != after.get(*k))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields
+}
+
+/// Print the resources added to and removed from the catalog, the
+/// resources whose checksum or fields changed and the settings of the
+/// catalog that changed, e.g., for a dry run.
+///
+/// # Arguments
+///
+/// * `before` - Catalog before the changes.
+/// * `after` - Catalog after the changes.
+fn print_plan(before: &Catalog, after: &Catalog) {
+    let settings: Vec<String> = changed_fields(before, after)
+        .into_iter()
+        .filter(|f| f != "resources")
+        .collect();
+    let keys = |c: &Catalog| -> IndexMap<String, Resource> {
+        c.resources
+            .iter()
+            .map(|r| (r.historical_checksums[0].clone(), r.clone()))
+            .collect()
+    };
+    let (before, after) = (keys(before), keys(after));
+    let mut changed = false;
+    for (key, resource) in &after {
+        let mut fields = vec![];
+        let id = match before.get(key) {
+            None => "catalog-plan-add",
+            Some(r) if r.checksum != resource.checksum => {
+                "catalog-plan-checksum"
+            }
+            Some(r) if !r.retired && resource.retired => "catalog-plan-retire",
+            Some(r) if r.retired && !resource.retired => "catalog-plan-restore",
+            Some(r) => {
+                fields = changed_fields(r, resource);
+                match fields.is_empty() {
+                    true => continue,
+                    false => "catalog-plan-modify",
+                }
+            }
+        };
+        changed = true;
+        println!(
+            "{}",
+            message(
+                id,
+                &[
+                    ("key", key.as_str().into()),
+                    ("title", resource.title.as_str().into()),
+                    ("checksum", resource.checksum.as_str().into()),
+                    ("fields", fields.join(", ").into()),
+                ]
+            )
+        );
+    }
+    for (key, resource) in
+        before.iter().filter(|(k, _)| !after.contains_key(*k))
+    {
+        changed = true;
+        println!(
+            "{}",
+            message(
+                "catalog-plan-remove",
+                &[
+                    ("key", key.as_str().into()),
+                    ("title", resource.title.as_str().into()),
+                ]
+            )
+        );
+    }
+    if !settings.is_empty() {
+        changed = true;
+        println!(
+            "{}",
+            message(
+                "catalog-plan-settings",
+                &[("fields", settings.join(", ").into())]
+            )
+        );
+    }
+    if !changed {
+        println!("{}", message("catalog-plan-none", &[]));
+    }
+}
+
 /// Register new resources and update the checksum of existing
-/// resources.
+/// resources. New files in the library's inbox are staged for review
+/// (see `stage_inbox`).
 ///
 /// # Arguments
 ///
@@ -372,30 +1158,89 @@ fn sha1(file_or_dir: &walkdir::DirEntry) -> String {
 /// checksum of all resources will be computed, but the cache file
 /// will still be updated.
 /// * `remove_orphans` - See description for `Catalog.update`.
+/// * `purge_retired` - See description for `Catalog.update`.
+/// * `detect_doi` - See description for `Catalog.update`.
+/// * `fetch_metadata` - See description for `Catalog.update`.
+/// * `attach_duplicates` - See description for `Catalog.update`.
+/// * `delete_duplicates` - Whether to delete a new file with the same
+///   content as another file of the resources directory: "ask" prompts
+///   for each, and "true" and "false" delete or keep every duplicate
+///   without prompting. Kept duplicates aren't cataloged.
+/// * `interactive` - Prompt for the metadata of each new resource (see
+///   `describe_resources`).
+/// * `dry_run` - Print the changes to the catalog instead of making
+///   them. Nothing is moved, deleted or written, and questions are
+///   answered "no" without being asked.
+/// * `safe_mode` - Neither read nor write the cache, and queue it for
+///   rebuild. A cache that fails to parse is handled the same way, with
+///   a warning. The next run outside of safe mode recomputes every
+///   checksum and rebuilds the cache.
+/// * `follow_symlinks` - Catalog the targets of symbolic links in the
+///   resources directory, in place, instead of skipping them with a
+///   warning.
+#[allow(clippy::too_many_arguments)]
 pub fn librarian_catalog(
-    catalog_file: &mut std::fs::File,
+    catalog_store: &mut CatalogStore,
     catalog: &mut Catalog,
-    resources_path: &PathBuf,
+    resources_path: &Path,
     disable_cache: bool,
     remove_orphans: &str,
+    purge_retired: bool,
+    detect_doi: bool,
+    fetch_metadata: bool,
+    attach_duplicates: &str,
+    delete_duplicates: &str,
+    interactive: bool,
+    dry_run: bool,
+    safe_mode: bool,
+    follow_symlinks: bool,
 ) {
+    // A dry run doesn't ask.
+    fn unasked(policy: &str, dry_run: bool) -> &str {
+        match (dry_run, policy) {
+            (true, "ask") => "false",
+            _ => policy,
+        }
+    }
+    let remove_orphans = unasked(remove_orphans, dry_run);
+    let attach_duplicates = unasked(attach_duplicates, dry_run);
+    let delete_duplicates = unasked(delete_duplicates, dry_run);
     // Construct the cache object from the cache file. This is
     // necessary regardless of whether we use this file to avoid
     // computing checksums because we will still need to update the
     // cache with the last time the checksum of each resource was
     // verified.
-    let mut cache_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(
-            resources_path
-                .parent()
-                .expect("resources path does not have a parent")
-                .join(".cache"),
-        )
-        .expect("Failed to open or create catalog");
-    let mut cache = read_cache_from_file(&mut cache_file);
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    if !dry_run && !run_hook(library, "pre-catalog", &[], None) {
+        return;
+    }
+    let cache_path = library.join(".cache");
+    let cache_data = time(Phase::CacheIo, || match dry_run {
+        // A dry run neither creates the cache nor queues it for
+        // rebuild.
+        true => std::fs::read_to_string(&cache_path)
+            .ok()
+            .filter(|_| !safe_mode)
+            .and_then(|c| parse_cache(&c).ok()),
+        false => read_ancillary(library, "cache", safe_mode, || {
+            read_cache_from_file(
+                &mut OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&cache_path)
+                    .expect("Failed to open or create cache"),
+            )
+        }),
+    })
+    .unwrap_or_default();
+    catalog.ensure_library_id();
+    catalog.check_library_id(cache_data.library_id.as_deref(), "cache");
+    let mut cache = cache_data.resources;
+    let previous_run = cache_data.last_run;
 
     // `SystemTime` is used to calculate the number of seconds since
     // "the epoch". This will work regardless of your local timezone.
@@ -403,6 +1248,12 @@ pub fn librarian_catalog(
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
+    // Without the cache, the hits and misses of the last run that used
+    // it are kept.
+    let mut run = CacheRun {
+        time: now,
+        ..CacheRun::default()
+    };
 
     // When we iterate through all resources we remove each resource
     // from `cache_orphans`. The entries that remain after iterating
@@ -422,122 +1273,403 @@ pub fn librarian_catalog(
 
     // Construct a hashmap of the SHA-1 checksum and path of each
     // resource. This also updates the cache (if
-    // ``disable_cache==false``) and deletes new resources for which
+    // ``disable_cache==false``) and reports new resources for which
     // there is an existing resource with identical content.
+    let storage = Storage::of(resources_path, catalog)
+        .dry_run(dry_run)
+        .follow_symlinks(follow_symlinks);
     let mut resources = IndexMap::<String, PathBuf>::new();
-    WalkDir::new(resources_path)
-        .min_depth(1)
-        .max_depth(1)
-        .into_iter()
-        .for_each(|f| {
-            let file = f.unwrap();
-            let file_name: String =
-                file.file_name().to_str().unwrap().to_string();
-
-            cache_orphans.remove(&file_name);
-
-            let mut cache_invalid = false;
-            let mut cache_checksum = String::new();
-
-            // If the resource's checksum was verified more recently
-            // than the resource was modified, use that catalog
-            // checksum. Otherwise, recompute the checksum and update
-            // the cache verification time.
-            match disable_cache {
-                true => {
+    let mut kept_duplicates = 0;
+    let entries = time(Phase::Walk, || storage.entries());
+    entries.into_iter().for_each(|file| {
+        let file_name = storage.key(file.path());
+        let entry_key = cache_key(
+            &cache,
+            file.path(),
+            &file_name,
+            catalog_resources.contains(&file_name),
+            now,
+        );
+
+        cache_orphans.remove(&entry_key);
+
+        let mut cache_invalid = false;
+        let mut cache_checksum = String::new();
+
+        // If the resource's checksum was verified more recently
+        // than the resource was modified, use that catalog
+        // checksum. Otherwise, recompute the checksum and update
+        // the cache verification time.
+        match disable_cache {
+            true => {
+                cache_invalid = true;
+            }
+            false => match cache.get(&entry_key) {
+                Some(cache_data) if cache_data.is_valid(file.path(), now) => {
+                    cache_checksum = cache_data.checksum.clone();
+                }
+                _ => {
                     cache_invalid = true;
                 }
-                false => match cache.get(&file_name) {
-                    Some(cache_data) => match file.metadata() {
-                        Ok(m) => match m.modified() {
-                            Ok(modified) => {
-                                if modified
-                                    .duration_since(SystemTime::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs()
-                                    > cache_data.last_verified
-                                {
-                                    cache_invalid = true;
-                                } else {
-                                    cache_checksum =
-                                        cache_data.checksum.clone();
-                                }
-                            }
-                            Err(_) => {
-                                cache_invalid = true;
-                            }
-                        },
-                        Err(_) => {
-                            cache_invalid = true;
-                        }
-                    },
-                    None => {
-                        cache_invalid = true;
-                    }
-                },
-            }
+            },
+        }
 
-            let content_sha: String = match cache_invalid {
-                true => {
-                    let checksum = sha1(&file);
-                    let mut cache_key = file_name.clone();
-                    // If the resource is new (i.e., not previously
-                    // cataloged), then the index should be set to the
-                    // checksum, not the old file name. This is
-                    // necessary because the file is not renamed to
-                    // the initial checksum until we call
-                    // `catalog.update`. We cannot simply search the
-                    // cache for this value because the cache entry
-                    // could have been deleted.
-                    if !catalog_resources.contains(&file_name) {
-                        cache_key = checksum.clone();
-                    }
-                    // insert updates an existing key if it already exists
-                    cache.insert(
-                        cache_key,
-                        CacheFields {
-                            last_verified: now,
-                            checksum: checksum.clone(),
-                        },
-                    );
-                    checksum
-                }
-                false => cache_checksum,
-            };
+        match cache_invalid {
+            true => run.misses += 1,
+            false => run.hits += 1,
+        }
+        let content_sha: String = match cache_invalid {
+            true => {
+                let checksum = sha1(&file);
+                // insert updates an existing key if it already exists
+                cache.insert(
+                    entry_key,
+                    CacheFields::new(file.path(), checksum.clone(), now),
+                );
+                checksum
+            }
+            false => cache_checksum,
+        };
 
-            // If a resource exists with identical content to the
-            // current resource, delete the current resource.
-            if resources.contains_key(&content_sha) {
-                let metadata = std::fs::metadata(file.path()).unwrap();
+        // A file with the same content as another file is a duplicate,
+        // which is reported and deleted or kept out of the catalog. Of a
+        // cataloged file and a new file, the new file is the duplicate.
+        let is_cataloged =
+            |path: &Path| catalog_resources.contains(&storage.key(path));
+        let duplicate = match resources.get(&content_sha) {
+            None => None,
+            Some(original)
+                if is_cataloged(file.path()) && !is_cataloged(original) =>
+            {
+                let path = resources
+                    .insert(content_sha.clone(), file.path().to_path_buf())
+                    .unwrap();
+                Some((path, file.path().to_path_buf()))
+            }
+            Some(original) => {
+                Some((file.path().to_path_buf(), original.clone()))
+            }
+        };
+        match duplicate {
+            Some((path, original)) => {
                 println!(
-                    "{:?} is already a resource ({:?}). Removing duplicate.",
-                    file.path(),
-                    content_sha
+                    "{}",
+                    message(
+                        "catalog-duplicate",
+                        &[
+                            ("path", path.display().to_string().into()),
+                            ("original", original.display().to_string().into())
+                        ]
+                    )
                 );
-                if metadata.is_dir() {
-                    std::fs::remove_dir_all(file.path()).unwrap();
-                } else {
-                    std::fs::remove_file(file.path()).unwrap();
+                let question = message(
+                    "catalog-delete-duplicate",
+                    &[("path", path.display().to_string().into())],
+                );
+                match confirm(delete_duplicates, &question) {
+                    true if dry_run => println!(
+                        "{}",
+                        message(
+                            "catalog-plan-delete",
+                            &[("path", path.display().to_string().into())]
+                        )
+                    ),
+                    true => Storage::remove_entry(&path)
+                        .expect("Failed to remove duplicate"),
+                    false => kept_duplicates += 1,
                 }
-            } else {
-                resources
-                    .insert(content_sha, file.clone().path().to_path_buf());
             }
-        });
+            None => {
+                resources.insert(content_sha, file.path().to_path_buf());
+            }
+        }
+    });
+    if kept_duplicates > 0 && !dry_run {
+        println!(
+            "{}",
+            message(
+                "catalog-duplicates-kept",
+                &[("count", kept_duplicates.into())]
+            )
+        );
+    }
 
     // remove all orphans from the cache
     cache_orphans.iter().for_each(|o| {
         cache.remove(o.0);
     });
 
+    // update catalog and write it to disk
+    let before = catalog.clone();
+    let cataloged: HashSet<String> = catalog
+        .resources
+        .iter()
+        .map(|r| r.historical_checksums[0].clone())
+        .collect();
+    catalog.update(
+        &storage,
+        &resources,
+        false,
+        remove_orphans,
+        purge_retired,
+        detect_doi,
+        fetch_metadata,
+        attach_duplicates,
+    );
+    if dry_run {
+        print_plan(&before, catalog);
+        return;
+    }
+    if interactive {
+        let new: Vec<String> = catalog
+            .resources
+            .iter()
+            .map(|r| r.historical_checksums[0].clone())
+            .filter(|k| !cataloged.contains(k))
+            .collect();
+        describe_resources(catalog, &new);
+    }
+    for unknown in catalog.resolve_relations() {
+        eprintln!(
+            "{}",
+            message(
+                "relation-unknown",
+                &[
+                    ("key", unknown.key.into()),
+                    ("relation", unknown.relation.name().into()),
+                    ("target", unknown.target.into()),
+                ]
+            )
+        );
+    }
+    let staged = stage_inbox(catalog, &library.join(INBOX_DIR));
+    if staged > 0 {
+        println!("{}", message("inbox-staged", &[("count", staged.into())]));
+    }
+    // New resources and those whose document type changed are named
+    // after their current document type.
+    let storage = Storage::of(resources_path, catalog);
+    storage.rename_files(
+        catalog.resources.iter().map(|r| &r.historical_checksums[0]),
+    );
+    save_revisions(catalog, &storage);
+    synchronize(catalog, &storage);
+    catalog_store.write(catalog);
+
+    // New files that were cataloged are cached under their resource
+    // from now on, and resources whose file changed are cached again.
+    rekey(&mut cache, catalog, &storage);
+    let checksums: IndexMap<&String, &String> = catalog
+        .resources
+        .iter()
+        .map(|r| (&r.historical_checksums[0], &r.checksum))
+        .collect();
+    cache.retain(|key, _| checksums.contains_key(key) || is_identity_key(key));
+    for (key, fields) in cache.iter_mut() {
+        if is_identity_key(key) {
+            continue;
+        }
+        if &fields.checksum != checksums[key] {
+            *fields = CacheFields::new(
+                &storage.resource_path(key),
+                checksums[key].clone(),
+                now,
+            );
+        }
+    }
+
     cache.sort_by(|a_key, _, b_key, _| a_key.partial_cmp(&b_key).unwrap());
 
     // write new cache contents to file
-    clear_file(&mut cache_file);
-    serde_json::to_writer_pretty(&mut cache_file, &cache).unwrap();
+    if !safe_mode {
+        time(Phase::CacheIo, || {
+            write_atomically(
+                &cache_path,
+                &serde_json::to_string_pretty(&Cache {
+                    version: CACHE_VERSION,
+                    library_id: catalog.library_id.clone(),
+                    resources: cache,
+                    last_run: match disable_cache {
+                        true => previous_run,
+                        false => Some(run),
+                    },
+                })
+                .unwrap(),
+            )
+        });
+        mark_rebuilt(library, "cache");
+    }
+    run_hook(library, "post-catalog", &[], None);
+}
 
-    // update catalog and write it to disk
-    catalog.update(&resources, remove_orphans);
-    clear_file(catalog_file);
-    serde_json::to_writer_pretty(catalog_file, &catalog).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::search;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_stamp() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        catalog.snapshot();
+        let now = DateTime::try_from("2024-03-01T10:00:00").unwrap();
+        catalog.resources[1].title = String::from("B, revised");
+        catalog.resources.push(Resource::new(
+            String::from("C"),
+            None,
+            String::from("cc"),
+        ));
+        catalog.stamp(now.clone());
+        assert!(catalog.resources[0].date_modified.is_none());
+        assert!(catalog.resources[1].date_modified == Some(now.clone()));
+        assert!(catalog.resources[2].date_added == Some(now.clone()));
+        assert!(catalog.resources[2].date_modified.is_none());
+        // Stamping again doesn't count the stamps as modifications.
+        let later = DateTime::try_from("2024-03-02").unwrap();
+        catalog.stamp(later);
+        assert!(catalog.resources[1].date_modified == Some(now));
+    }
+
+    #[test]
+    fn test_detect_document_type() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"article\": {\"extension\": \"PDF\", \"mime\": null}},
+              \"content_types\": {}}",
+        )
+        .unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-detect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let detect = |catalog: &mut Catalog, contents: &[u8]| {
+            let path = dir.join("download");
+            std::fs::write(&path, contents).unwrap();
+            catalog.detect_document_type(&path)
+        };
+        assert!(
+            detect(&mut catalog, b"%PDF-1.7\n").as_deref() == Some("article")
+        );
+        // Types the catalog lacks are added with their media type.
+        assert!(
+            detect(&mut catalog, b"\x89PNG\r\n\x1a\n").as_deref()
+                == Some("png")
+        );
+        assert!(
+            catalog.document_types["png"].mime
+                == Some(MediaType::try_from("image/png").unwrap())
+        );
+        assert!(detect(&mut catalog, b"plain text").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retire_orphans() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"],
+               \"tags\": [\"rf\"]}
+            ]}",
+        )
+        .unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-retire-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = Storage::new(&dir, StorageLayout::Flat);
+        let mut update = |catalog: &mut Catalog, keys: &[&str], purge| {
+            let resources: IndexMap<String, PathBuf> =
+                keys.iter().map(|k| (k.to_string(), dir.join(k))).collect();
+            catalog.update(
+                &storage, &resources, false, "true", purge, false, false,
+                "false",
+            );
+        };
+        let b = |catalog: &Catalog| {
+            catalog
+                .resources
+                .iter()
+                .find(|r| r.checksum == "bb")
+                .cloned()
+        };
+        update(&mut catalog, &["aa"], false);
+        assert!(catalog.resources.len() == 2);
+        assert!(b(&catalog).unwrap().retired);
+        assert!(b(&catalog).unwrap().tags.is_some());
+        assert!(search(&catalog, "B").is_empty());
+        // A retired resource comes back with its file.
+        update(&mut catalog, &["aa", "bb"], false);
+        assert!(!b(&catalog).unwrap().retired);
+        update(&mut catalog, &["aa"], true);
+        assert!(catalog.resources.len() == 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_attach_duplicates() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"],
+               \"doi\": \"10.1000/xyz\"}
+            ]}",
+        )
+        .unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-attach-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("aa"), "original").unwrap();
+        let scan = "%PDF-1.4\nstream\nBT (doi:10.1000/xyz) Tj ET\nendstream\n";
+        std::fs::write(dir.join("scan.pdf"), scan).unwrap();
+        let storage = Storage::new(&dir, StorageLayout::Flat);
+        let resources: IndexMap<String, PathBuf> = vec![
+            ("aa".to_string(), dir.join("aa")),
+            ("bb".to_string(), dir.join("scan.pdf")),
+        ]
+        .into_iter()
+        .collect();
+        catalog.update(
+            &storage, &resources, false, "false", false, true, false, "true",
+        );
+
+        // The existing resource keeps its file and gains the new one.
+        assert!(catalog.resources.len() == 1);
+        let resource = &catalog.resources[0];
+        assert!(resource.checksum == "aa");
+        assert!(std::fs::read_to_string(dir.join("aa")).unwrap() == "original");
+        let attachments = resource.attachments.as_ref().unwrap();
+        assert!(attachments[0].name == "scan.pdf");
+        assert!(attachments[0].checksum == "bb");
+        assert!(
+            std::fs::read_to_string(
+                storage.attachments_path("aa").join("scan.pdf")
+            )
+            .unwrap()
+                == scan
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-dry-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("catalog.json");
+        let mut store = CatalogStore::open(&path).dry_run(true);
+        // The catalog isn't created...
+        let mut catalog = store.read();
+        assert!(catalog.dry_run);
+        assert!(!path.exists());
+        // ...or changed.
+        std::fs::write(&path, EMPTY_CATALOG).unwrap();
+        catalog.file_extensions = true;
+        store.write(&mut catalog);
+        assert!(std::fs::read_to_string(&path).unwrap() == EMPTY_CATALOG);
+        assert!(Storage::of(&dir, &catalog).is_dry_run());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,282 @@
+use crate::attachments::{attachment_problems, AttachmentProblem};
+use crate::catalog::{confirm, Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::storage::Storage;
+use crate::style::apply_change;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Media types of common document extensions, lowercase. These are
+/// given to document types that don't have one.
+pub const MEDIA_TYPES: [(&str, &str); 17] = [
+    ("pdf", "application/pdf"),
+    ("epub", "application/epub+zip"),
+    ("djvu", "image/vnd.djvu"),
+    ("mobi", "application/x-mobipocket-ebook"),
+    ("cbz", "application/vnd.comicbook+zip"),
+    ("ps", "application/postscript"),
+    ("html", "text/html"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("zip", "application/zip"),
+    ("mp4", "video/mp4"),
+    ("mp3", "audio/mpeg"),
+];
+
+/// Media type of a file extension, ignoring case, if it's a common one.
+pub fn extension_media_type(extension: &str) -> Option<&'static str> {
+    MEDIA_TYPES
+        .iter()
+        .find(|(e, _)| extension.eq_ignore_ascii_case(e))
+        .map(|(_, t)| *t)
+}
+
+/// Guess the file extension of a file from its leading bytes.
+///
+/// # Return
+///
+/// Returns `None` for file types that aren't recognized, which includes
+/// most plain text files.
+pub fn detect_extension(path: &Path) -> Option<&'static str> {
+    let mut head = Vec::<u8>::new();
+    File::open(path)
+        .ok()?
+        .take(512)
+        .read_to_end(&mut head)
+        .ok()?;
+    let starts = |magic: &[u8]| head.starts_with(magic);
+
+    if starts(b"%PDF-") {
+        Some("pdf")
+    } else if starts(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if starts(b"\xff\xd8\xff") {
+        Some("jpg")
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Some("gif")
+    } else if starts(b"AT&TFORM") {
+        Some("djvu")
+    } else if starts(b"%!PS") {
+        Some("ps")
+    } else if starts(b"\x1f\x8b") {
+        Some("gz")
+    } else if starts(b"PK\x03\x04") {
+        // EPUB files are ZIP archives whose first entry is an
+        // uncompressed "mimetype" file.
+        match head.get(30..58) == Some(b"mimetypeapplication/epub+zip") {
+            true => Some("epub"),
+            false => Some("zip"),
+        }
+    } else if head.get(4..8) == Some(b"ftyp") {
+        Some("mp4")
+    } else {
+        let text = String::from_utf8_lossy(&head).trim_start().to_lowercase();
+        if text.starts_with("<!doctype html") || text.starts_with("<html") {
+            Some("html")
+        } else if text.starts_with("<?xml") {
+            Some("xml")
+        } else {
+            None
+        }
+    }
+}
+
+/// Check cataloged resources for problems, and offer to fix them.
+///
+/// The attachments of every resource are always checked against their
+/// recorded checksums. A modified attachment can be fixed by recording
+/// its new checksum. Resources are also checked against the catalog's
+/// style rules, if any.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `detect_type` - Compare the type of each file, as determined from
+///   its content, with its document type. This catches broken
+///   downloads, such as an HTML error page saved in place of a PDF.
+///   Resources without a document type are given the detected one.
+/// * `fix` - Whether to fix problems. If set to "ask", prompt for each
+///   problem. When set to "true" or "false", fix all or none of the
+///   problems without prompting.
+pub fn librarian_check(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    detect_type: bool,
+    fix: &str,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let mut problems = 0;
+    let mut fixed = 0;
+
+    if detect_type {
+        for i in 0..catalog.resources.len() {
+            let resource = &catalog.resources[i];
+            // Resources cataloged before their type was detected may
+            // have no document type.
+            let expected = resource
+                .document
+                .as_ref()
+                .and_then(|d| catalog.document_types.get(d))
+                .map(|d| d.extension.to_lowercase());
+            let path = storage.resource_path(&resource.historical_checksums[0]);
+            let detected = match path.is_file() {
+                true => detect_extension(&path),
+                false => None,
+            };
+            let detected = match detected {
+                Some(d) if Some(d) != expected.as_deref() => d,
+                _ => continue,
+            };
+
+            problems += 1;
+            let key = resource.historical_checksums[0].as_str();
+            let title = resource.title.as_str();
+            println!(
+                "{}",
+                match &expected {
+                    Some(e) => message(
+                        "check-mismatch",
+                        &[
+                            ("key", key.into()),
+                            ("title", title.into()),
+                            ("expected", e.as_str().into()),
+                            ("detected", detected.into()),
+                        ]
+                    ),
+                    None => message(
+                        "check-untyped",
+                        &[
+                            ("key", key.into()),
+                            ("title", title.into()),
+                            ("detected", detected.into()),
+                        ]
+                    ),
+                }
+            );
+            if confirm(
+                fix,
+                &message("check-change-type", &[("type", detected.into())]),
+            ) {
+                let document_type = catalog.document_type_for(detected);
+                catalog.resources[i].document = Some(document_type);
+                fixed += 1;
+            }
+        }
+    }
+
+    for i in 0..catalog.resources.len() {
+        let resource = &catalog.resources[i];
+        let key = resource.historical_checksums[0].clone();
+        let problems_found: Vec<(String, AttachmentProblem)> =
+            attachment_problems(&storage, resource)
+                .into_iter()
+                .map(|(a, p)| (a.name.clone(), p))
+                .collect();
+        for (name, problem) in problems_found {
+            problems += 1;
+            let args = [
+                ("key", key.as_str().into()),
+                ("title", catalog.resources[i].title.as_str().into()),
+                ("name", name.as_str().into()),
+            ];
+            let checksum = match problem {
+                AttachmentProblem::Missing => {
+                    println!("{}", message("check-attachment-missing", &args));
+                    continue;
+                }
+                AttachmentProblem::Modified(c) => c,
+            };
+            println!("{}", message("check-attachment-modified", &args));
+            if confirm(fix, &message("check-attachment-update", &[])) {
+                catalog.resources[i]
+                    .attachments
+                    .iter_mut()
+                    .flatten()
+                    .find(|a| a.name == name)
+                    .unwrap()
+                    .checksum = checksum;
+                fixed += 1;
+            }
+        }
+    }
+
+    if let Some(rules) = catalog.style.clone() {
+        for i in 0..catalog.resources.len() {
+            for change in rules.changes(&catalog.resources[i]) {
+                problems += 1;
+                let resource = &catalog.resources[i];
+                println!(
+                    "{}",
+                    message(
+                        "check-style",
+                        &[
+                            (
+                                "key",
+                                resource.historical_checksums[0]
+                                    .as_str()
+                                    .into()
+                            ),
+                            ("field", change.field.into()),
+                            ("old", change.old.as_str().into()),
+                            ("new", change.new.as_str().into()),
+                        ]
+                    )
+                );
+                if confirm(fix, &message("check-style-apply", &[])) {
+                    apply_change(&mut catalog.resources[i], &change);
+                    fixed += 1;
+                }
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        message(
+            "check-summary",
+            &[("problems", problems.into()), ("fixed", fixed.into())]
+        )
+    );
+    if fixed > 0 {
+        catalog_store.write(catalog);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_extension() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let detect = |contents: &[u8]| {
+            let path = dir.join("file");
+            std::fs::write(&path, contents).unwrap();
+            detect_extension(&path)
+        };
+
+        assert!(detect(b"%PDF-1.7\n") == Some("pdf"));
+        assert!(
+            detect(b"\n  <!DOCTYPE html><html><title>404</title>")
+                == Some("html")
+        );
+        let mut epub = b"PK\x03\x04".to_vec();
+        epub.extend([0u8; 26].iter());
+        epub.extend(b"mimetypeapplication/epub+zip");
+        assert!(detect(&epub) == Some("epub"));
+        assert!(detect(b"plain text") == None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::storage::Storage;
+
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Extract the citation keys used in a LaTeX document.
+///
+/// This recognizes every command whose name contains "cite" (e.g.,
+/// `\cite`, `\textcite`, `\parencite*`), including optional pre- and
+/// postnote arguments.
+fn latex_citations(contents: &str) -> Vec<String> {
+    let re = Regex::new(
+        r"\\[a-zA-Z]*cite[a-zA-Z]*\*?\s*(?:\[[^\]]*\]\s*){0,2}\{([^}]*)\}",
+    )
+    .unwrap();
+    re.captures_iter(contents)
+        .flat_map(|c| {
+            c[1].split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+/// Extract the citation keys used in a Pandoc Markdown document, which
+/// are written as `[@key]` or `[see @key, p. 3; @other]`.
+fn markdown_citations(contents: &str) -> Vec<String> {
+    let brackets = Regex::new(r"\[([^\[\]]*@[^\[\]]*)\]").unwrap();
+    let key = Regex::new(r"(?:^|[\s;\[])-?@([\w][\w:.#$%&+?<>~/-]*)").unwrap();
+    brackets
+        .captures_iter(contents)
+        .flat_map(|c| {
+            key.captures_iter(&c[1])
+                .map(|k| {
+                    // Trailing punctuation belongs to the surrounding
+                    // text, not the key.
+                    k[1].trim_end_matches(|c: char| ".:".contains(c))
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+/// Collect the citation keys used by all LaTeX and Markdown files in a
+/// directory, searched recursively.
+pub fn cited_keys(directory: &Path) -> BTreeSet<String> {
+    let mut keys = BTreeSet::<String>::new();
+    for f in WalkDir::new(directory).into_iter() {
+        let path: PathBuf = f.expect("failed to read directory").into_path();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let citations = match extension.as_deref() {
+            Some("tex") => latex_citations,
+            Some("md") => markdown_citations,
+            _ => continue,
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => keys.extend(citations(&contents)),
+            Err(e) => eprintln!(
+                "{}",
+                message(
+                    "cite-skipped",
+                    &[
+                        ("path", path.display().to_string().into()),
+                        ("error", e.to_string().into())
+                    ]
+                )
+            ),
+        }
+    }
+    keys
+}
+
+/// Report the citations of a LaTeX or Markdown project that are not in
+/// the catalog and optionally write a bibliography of the cited
+/// resources.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `tex_dir` - Directory containing the project's documents.
+/// * `bibtex_file_path` - File to write the BibTeX entries of all cited
+///   resources to.
+pub fn librarian_used(
+    catalog: &Catalog,
+    resources_path: &Path,
+    tex_dir: &Path,
+    bibtex_file_path: Option<&str>,
+) {
+    let cited = cited_keys(tex_dir);
+    let cited_resources: Vec<_> = catalog
+        .resources
+        .iter()
+        .filter(|r| cited.contains(&r.bibtex_key()))
+        .collect();
+    let cataloged: BTreeSet<String> =
+        cited_resources.iter().map(|r| r.bibtex_key()).collect();
+
+    println!(
+        "{}",
+        message(
+            "cite-summary",
+            &[
+                ("cited", cited.len().into()),
+                ("cataloged", cataloged.len().into())
+            ]
+        )
+    );
+    let missing: Vec<&String> = cited.difference(&cataloged).collect();
+    if !missing.is_empty() {
+        println!("{}", message("cite-missing", &[]));
+        for key in missing {
+            println!("  {}", key);
+        }
+    }
+
+    if let Some(f) = bibtex_file_path {
+        let bibtex_entries: String = cited_resources
+            .iter()
+            .map(|r| {
+                r.serialize_bibtex(
+                    &catalog.content_types,
+                    &[Storage::of(resources_path, catalog)
+                        .resource_path(&r.historical_checksums[0])],
+                    false,
+                )
+            })
+            .collect();
+        std::fs::write(f, bibtex_entries).expect("Failed to write BibTeX file");
+    }
+}
+
+/// Extract the keys of all entries in a BibTeX file.
+fn bibtex_keys(contents: &str) -> Vec<String> {
+    let re =
+        Regex::new(r"(?m)^\s*@\s*([a-zA-Z]+)\s*[{(]\s*([^,\s]+)\s*,").unwrap();
+    re.captures_iter(contents)
+        // These entry types don't have keys.
+        .filter(|c| {
+            !["comment", "preamble", "string"]
+                .contains(&c[1].to_lowercase().as_str())
+        })
+        .map(|c| c[2].to_string())
+        .collect()
+}
+
+/// Report the entries of a BibTeX file generated from the catalog that
+/// are never cited by a LaTeX or Markdown project.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `bibtex_file_path` - BibTeX file used by the project.
+/// * `tex_dir` - Directory containing the project's documents.
+pub fn librarian_unused(
+    catalog: &Catalog,
+    bibtex_file_path: &Path,
+    tex_dir: &Path,
+) {
+    let contents = std::fs::read_to_string(bibtex_file_path)
+        .expect("Failed to read BibTeX file");
+    let cited = cited_keys(tex_dir);
+    let catalog_keys: BTreeSet<String> =
+        catalog.resources.iter().map(|r| r.bibtex_key()).collect();
+    // Entries that weren't generated from the catalog were presumably
+    // added by hand and are left to the user.
+    let entries: Vec<String> = bibtex_keys(&contents)
+        .into_iter()
+        .filter(|k| catalog_keys.contains(k))
+        .collect();
+    let unused: Vec<&String> =
+        entries.iter().filter(|k| !cited.contains(*k)).collect();
+
+    println!(
+        "{}",
+        message(
+            "unused-summary",
+            &[
+                ("count", unused.len().into()),
+                ("total", entries.len().into())
+            ]
+        )
+    );
+    for key in unused {
+        println!("  {}", key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latex_citations() {
+        let contents = concat!(
+            "As shown in \\cite{jackson1999, feynman1964}, and by ",
+            "\\textcite[p.~3]{einstein1905}, or \\parencite*[see][12]{dirac}.",
+            " \\nocite{} does nothing."
+        );
+        assert!(
+            latex_citations(contents)
+                == vec!["jackson1999", "feynman1964", "einstein1905", "dirac"]
+        );
+    }
+
+    #[test]
+    fn test_markdown_citations() {
+        let contents = concat!(
+            "Blah blah [see @doe99, pp. 33-35; also @smith04, chap. 1].\n",
+            "Blah [@88259e88e7677e5ae8a31e33f177a2198cabe95c].\n",
+            "Email me at someone@example.com [-@doe99].",
+        );
+        assert!(
+            markdown_citations(contents)
+                == vec![
+                    "doe99",
+                    "smith04",
+                    "88259e88e7677e5ae8a31e33f177a2198cabe95c",
+                    "doe99"
+                ]
+        );
+    }
+
+    #[test]
+    fn test_bibtex_keys() {
+        let contents = concat!(
+            "@string{prl = {Physical Review Letters}}\n",
+            "@article{ jackson1999,\n  title={Electrodynamics},\n}\n",
+            "@Book(feynman1964, title={Lectures})\n",
+            "@comment{not an entry, really}\n",
+        );
+        assert!(bibtex_keys(contents) == vec!["jackson1999", "feynman1964"]);
+    }
+}
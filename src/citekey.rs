@@ -0,0 +1,180 @@
+use crate::catalog::Catalog;
+use crate::resource::Resource;
+
+use regex::Regex;
+use std::collections::HashSet;
+use unicode_normalization::UnicodeNormalization;
+
+/// Title words skipped when choosing the short title of a key.
+const STOP_WORDS: [&str; 18] = [
+    "a", "an", "and", "are", "as", "at", "by", "can", "for", "from", "in",
+    "is", "of", "on", "or", "the", "to", "with",
+];
+
+/// Lowercase ASCII letters and digits of a string. Accented letters
+/// are replaced by their base letter and everything else is dropped.
+pub(crate) fn key_part(text: &str) -> String {
+    text.nfd()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Value of a citation key scheme placeholder for a resource, or `None`
+/// if the resource lacks the field.
+fn placeholder(resource: &Resource, name: &str) -> Option<String> {
+    let value = match name {
+        "lastname" => resource
+            .author
+            .as_ref()
+            .and_then(|a| a.first())
+            .and_then(|n| n.last.clone().or_else(|| n.literal.clone()))
+            .map(|l| key_part(&l)),
+        "year" => resource
+            .date
+            .as_ref()
+            .and_then(|d| d.year)
+            .map(|y| y.to_string()),
+        "shorttitle" => resource
+            .title
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .map(key_part)
+            .find(|w| !w.is_empty() && !STOP_WORDS.contains(&w.as_str())),
+        "title" => Some(key_part(&resource.title)),
+        _ => panic!("Unknown citation key placeholder {{{}}}", name),
+    };
+    value.filter(|v| !v.is_empty())
+}
+
+/// Citation key of a resource according to a scheme, without collision
+/// handling.
+///
+/// # Arguments
+///
+/// * `scheme` - Key pattern, where `{lastname}` is replaced by the last
+///   name of the first author, `{year}` by the year, `{shorttitle}` by
+///   the first significant word of the title and `{title}` by the whole
+///   title.
+/// * `resource` - Resource to generate a key for.
+///
+/// # Returns
+///
+/// `None` if the resource lacks a field used by the scheme, so that the
+/// key can be generated once its metadata is complete instead of being
+/// fixed to an incomplete key.
+pub fn generate_citekey(scheme: &str, resource: &Resource) -> Option<String> {
+    let re = Regex::new(r"\{(\w+)\}").unwrap();
+    let mut key = String::new();
+    let mut last = 0;
+    for capture in re.captures_iter(scheme) {
+        let whole = capture.get(0).unwrap();
+        key.push_str(&scheme[last..whole.start()]);
+        key.push_str(&placeholder(resource, &capture[1])?);
+        last = whole.end();
+    }
+    key.push_str(&scheme[last..]);
+    Some(key)
+}
+
+/// Suffix distinguishing the `n`th resource with the same generated key:
+/// nothing for the first, then "a" through "z", "aa", "ab" and so on.
+fn suffix(n: usize) -> String {
+    let mut suffix = String::new();
+    let mut n = n;
+    while n > 0 {
+        n -= 1;
+        suffix.insert(0, (b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    suffix
+}
+
+impl Catalog {
+    /// Give a citation key to every resource without one, according to
+    /// the catalog's citation key scheme.
+    ///
+    /// Keys are stored in the catalog and never regenerated, so they
+    /// remain stable when metadata is edited later. A key that collides
+    /// with the BibTeX key of another resource is suffixed with "a",
+    /// "b", "c", etc.
+    pub fn assign_citekeys(&mut self) {
+        let scheme = match &self.citekey_scheme {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let mut used: HashSet<String> =
+            self.resources.iter().map(|r| r.bibtex_key()).collect();
+        for resource in self.resources.iter_mut() {
+            if resource.citekey.is_some() {
+                continue;
+            }
+            if let Some(base) = generate_citekey(&scheme, resource) {
+                let key = (0..)
+                    .map(|n| format!("{}{}", base, suffix(n)))
+                    .find(|k| !used.contains(k))
+                    .unwrap();
+                used.insert(key.clone());
+                resource.citekey = Some(key);
+            }
+        }
+    }
+
+    /// Citation key a resource outside the catalog would get, such as a
+    /// pending resource of the inbox, distinct from the keys of the
+    /// catalog's resources and pending resources. `None` if the catalog
+    /// has no scheme or the resource lacks a field the scheme uses.
+    pub fn new_citekey(&self, resource: &Resource) -> Option<String> {
+        let base = generate_citekey(self.citekey_scheme.as_ref()?, resource)?;
+        let used: HashSet<String> = self
+            .resources
+            .iter()
+            .chain(&self.pending)
+            .map(|r| r.bibtex_key())
+            .collect();
+        (0..)
+            .map(|n| format!("{}{}", base, suffix(n)))
+            .find(|k| !used.contains(k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_citekeys() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"citekey_scheme\": \"{lastname}{year}{shorttitle}\", \"resources\": [
+              {\"title\": \"Can Quantum-Mechanical Description of Physical Reality Be Considered Complete?\", \"author\": [\"Albert Einstein\"], \"date\": \"1935\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Quantum Theory\", \"author\": [\"Albert Einstein\"], \"date\": \"1935\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Über die Quantenmechanik\", \"author\": [\"Erwin Schrödinger\"], \"date\": \"1926\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+              {\"title\": \"Notes\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]}
+            ]}",
+        )
+        .unwrap();
+        catalog.assign_citekeys();
+        let keys: Vec<String> =
+            catalog.resources.iter().map(|r| r.bibtex_key()).collect();
+        assert!(
+            keys == vec![
+                "einstein1935quantum",
+                "einstein1935quantuma",
+                "schrodinger1926uber",
+                "dd"
+            ]
+        );
+
+        // Existing keys are kept when metadata changes.
+        catalog.resources[0].date = None;
+        catalog.assign_citekeys();
+        assert!(catalog.resources[0].bibtex_key() == "einstein1935quantum");
+    }
+
+    #[test]
+    fn test_suffix() {
+        assert!(suffix(0) == "");
+        assert!(suffix(1) == "a");
+        assert!(suffix(26) == "z");
+        assert!(suffix(27) == "aa");
+    }
+}
@@ -0,0 +1,252 @@
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::Resource;
+
+impl Catalog {
+    /// Resources of a collection, in collection order. Resources that
+    /// are no longer in the catalog are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the collection.
+    pub fn collection(&self, name: &str) -> Vec<&Resource> {
+        self.collections
+            .get(name)
+            .unwrap_or_else(|| panic!("No collection {}", name))
+            .iter()
+            .filter_map(|k| {
+                self.resources
+                    .iter()
+                    .find(|r| r.historical_checksums[0] == *k)
+            })
+            .collect()
+    }
+
+    /// Initial checksum of the resource with a current or historical
+    /// checksum, which is how collections refer to resources.
+    fn initial_checksum(&self, checksum: &str) -> String {
+        self.resources
+            .iter()
+            .find(|r| {
+                r.checksum == checksum
+                    || r.historical_checksums.iter().any(|c| c == checksum)
+            })
+            .map(|r| r.historical_checksums[0].clone())
+            .unwrap_or_else(|| panic!("No resource with checksum {}", checksum))
+    }
+
+    /// Add resources to a collection, which is created if it doesn't
+    /// exist. Resources already in the collection keep their position.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the collection.
+    /// * `checksums` - Current or historical checksums of the resources.
+    /// * `position` - Position (starting at 1) the resources are
+    ///   inserted at. They are appended if this is omitted.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the added resources.
+    pub fn add_to_collection(
+        &mut self,
+        name: &str,
+        checksums: &[&str],
+        position: Option<usize>,
+    ) -> Vec<String> {
+        let keys: Vec<String> =
+            checksums.iter().map(|c| self.initial_checksum(c)).collect();
+        let collection = self.collections.entry(name.to_string()).or_default();
+        let mut index = position
+            .map(|p| p.saturating_sub(1).min(collection.len()))
+            .unwrap_or(collection.len());
+        let mut added = vec![];
+        for key in keys {
+            if collection.contains(&key) || added.contains(&key) {
+                continue;
+            }
+            collection.insert(index, key.clone());
+            index += 1;
+            added.push(key);
+        }
+        added
+    }
+
+    /// Remove resources from a collection.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the removed resources.
+    pub fn remove_from_collection(
+        &mut self,
+        name: &str,
+        checksums: &[&str],
+    ) -> Vec<String> {
+        let keys: Vec<String> =
+            checksums.iter().map(|c| self.initial_checksum(c)).collect();
+        let collection = self
+            .collections
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("No collection {}", name));
+        let removed = keys
+            .into_iter()
+            .filter(|k| collection.contains(k))
+            .collect::<Vec<String>>();
+        collection.retain(|k| !removed.contains(k));
+        removed
+    }
+}
+
+/// Create a collection.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `name` - Name of the collection.
+/// * `checksums` - Checksums of the initial resources of the
+///   collection, in order.
+pub fn librarian_collection_create(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    name: &str,
+    checksums: &[&str],
+) {
+    if catalog.collections.contains_key(name) {
+        panic!("Collection {} already exists", name);
+    }
+    let added = catalog.add_to_collection(name, checksums, None);
+    store.write(catalog);
+    println!(
+        "{}",
+        message(
+            "collection-created",
+            &[("name", name.into()), ("count", added.len().into())]
+        )
+    );
+}
+
+/// Add resources to an existing collection.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `name` - Name of the collection.
+/// * `checksums` - Checksums of the resources.
+/// * `position` - Position (starting at 1) the resources are inserted
+///   at. They are appended if this is omitted.
+pub fn librarian_collection_add(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    name: &str,
+    checksums: &[&str],
+    position: Option<usize>,
+) {
+    if !catalog.collections.contains_key(name) {
+        panic!("No collection {}", name);
+    }
+    let added = catalog.add_to_collection(name, checksums, position);
+    store.write(catalog);
+    println!(
+        "{}",
+        message(
+            "collection-added",
+            &[("name", name.into()), ("count", added.len().into())]
+        )
+    );
+}
+
+/// Remove resources from a collection, or the collection itself when
+/// no resources are given. The resources stay in the catalog.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `name` - Name of the collection.
+/// * `checksums` - Checksums of the resources.
+pub fn librarian_collection_rm(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    name: &str,
+    checksums: &[&str],
+) {
+    let summary = match checksums.is_empty() {
+        true => {
+            catalog
+                .collections
+                .shift_remove(name)
+                .unwrap_or_else(|| panic!("No collection {}", name));
+            message("collection-deleted", &[("name", name.into())])
+        }
+        false => {
+            let removed = catalog.remove_from_collection(name, checksums);
+            message(
+                "collection-removed",
+                &[("name", name.into()), ("count", removed.len().into())],
+            )
+        }
+    };
+    store.write(catalog);
+    println!("{}", summary);
+}
+
+/// Print the name and size of every collection.
+pub fn librarian_collection_list(catalog: &Catalog) {
+    for (name, keys) in &catalog.collections {
+        println!("{} ({})", name, keys.len());
+    }
+}
+
+/// Print the resources of a collection in order, along with their
+/// initial checksums.
+pub fn librarian_collection_show(catalog: &Catalog, name: &str) {
+    for (i, resource) in catalog.collection(name).iter().enumerate() {
+        println!(
+            "{:>3}. {} {}",
+            i + 1,
+            resource.historical_checksums[0],
+            resource.title
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"a2\", \"historical_checksums\": [\"a1\", \"a2\"]},
+              {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"C\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let titles = |catalog: &Catalog| {
+            catalog
+                .collection("reading")
+                .iter()
+                .map(|r| r.title.clone())
+                .collect::<Vec<String>>()
+        };
+        // Resources are stored under their initial checksum.
+        assert!(
+            catalog.add_to_collection("reading", &["cc", "a2"], None)
+                == vec!["cc", "a1"]
+        );
+        assert!(
+            catalog.add_to_collection("reading", &["bb", "cc"], Some(1))
+                == vec!["bb"]
+        );
+        assert!(titles(&catalog) == vec!["B", "C", "A"]);
+        assert!(
+            catalog.remove_from_collection("reading", &["a2", "bb"])
+                == vec!["a1", "bb"]
+        );
+        assert!(titles(&catalog) == vec!["C"]);
+    }
+}
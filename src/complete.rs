@@ -0,0 +1,108 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+/// Fields of a resource that completion candidates come from. The other
+/// fields are skipped rather than parsed, which is most of the time it
+/// takes to read a large catalog.
+#[derive(Deserialize)]
+struct CompletionResource {
+    title: String,
+    tags: Option<Vec<String>>,
+    /// Authors as written in the catalog, which is how a `Name` is
+    /// serialized.
+    author: Option<Vec<String>>,
+    citekey: Option<String>,
+    historical_checksums: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionCatalog {
+    #[serde(default)]
+    resources: Vec<CompletionResource>,
+}
+
+/// Parse a stored catalog file, or resource file of a split catalog.
+fn parse<T: DeserializeOwned>(path: &Path) -> T {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Resources of a stored catalog, in a single file or split across a
+/// directory (see `CatalogStore`). A catalog that hasn't been
+/// initialized yet has none.
+fn read_resources(catalog_path: &Path) -> Vec<CompletionResource> {
+    if catalog_path.is_dir() {
+        return match std::fs::read_dir(catalog_path.join("resources")) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.extension().and_then(|e| e.to_str()) == Some("json")
+                })
+                .map(|p| parse(&p))
+                .collect(),
+            Err(_) => vec![],
+        };
+    }
+    match std::fs::metadata(catalog_path) {
+        Ok(m) if m.len() > 0 => {
+            parse::<CompletionCatalog>(catalog_path).resources
+        }
+        _ => vec![],
+    }
+}
+
+/// Print completion candidates, one per line.
+///
+/// This is intended to back citation and tag completion in editors,
+/// so it prints nothing but the sorted, de-duplicated candidates. It
+/// reads the stored catalog itself, only parsing the fields candidates
+/// come from, so that it starts quickly even for large libraries.
+///
+/// # Arguments
+///
+/// * `catalog_path` - Location of the stored catalog.
+/// * `what` - Kind of candidate: "keys", "titles", "tags" or
+///   "authors". Keys are the BibTeX keys of the resources.
+/// * `prefix` - Only print candidates starting with this prefix,
+///   ignoring case.
+pub fn librarian_complete(
+    catalog_path: &Path,
+    what: &str,
+    prefix: Option<&str>,
+) {
+    let mut candidates = BTreeSet::<String>::new();
+    for resource in read_resources(catalog_path) {
+        match what {
+            "keys" => {
+                // See `Resource::bibtex_key`.
+                let checksums = resource.historical_checksums;
+                candidates.insert(
+                    resource.citekey.unwrap_or_else(|| checksums[0].clone()),
+                );
+            }
+            "titles" => {
+                candidates.insert(resource.title);
+            }
+            "tags" => candidates.extend(resource.tags.into_iter().flatten()),
+            "authors" => {
+                candidates.extend(resource.author.into_iter().flatten())
+            }
+            &_ => panic!("Possible argument values should prevent this condition from being reached. Check clap setup."),
+        }
+    }
+
+    let prefix = prefix.unwrap_or("").to_lowercase();
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for candidate in candidates
+        .iter()
+        .filter(|c| c.to_lowercase().starts_with(&prefix))
+    {
+        writeln!(out, "{}", candidate).unwrap();
+    }
+}
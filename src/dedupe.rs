@@ -0,0 +1,323 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::citekey::key_part;
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::merge::{librarian_merge_resources, MergePreference};
+use crate::relations::Relation;
+use crate::resource::Resource;
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Similarity above which `librarian dedupe` flags two resources,
+/// unless another is given.
+pub const DEDUPE_THRESHOLD: f64 = 0.8;
+
+/// Fields shown side by side for each pair of resources.
+const COMPARED_FIELDS: [&str; 7] = [
+    "title", "author", "date", "journal", "doi", "eprint", "document",
+];
+
+/// Width of the column of the first resource.
+const COLUMN_WIDTH: usize = 40;
+
+/// Normalized words of a text, ignoring case, accents and punctuation.
+fn words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| c.is_whitespace() || c == '-')
+        .map(key_part)
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Normalized last names of the authors of a resource.
+fn last_names(resource: &Resource) -> BTreeSet<String> {
+    resource
+        .author
+        .iter()
+        .flatten()
+        .filter_map(|n| n.last.as_ref().or(n.literal.as_ref()))
+        .map(|l| key_part(l))
+        .collect()
+}
+
+/// Jaccard index of two sets, the size of their intersection over the
+/// size of their union.
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    match a.union(b).count() {
+        0 => 0.0,
+        union => a.intersection(b).count() as f64 / union as f64,
+    }
+}
+
+/// Similarity of the metadata of two resources, from 0 to 1.
+///
+/// Resources with the same DOI, ISBN or arXiv identifier are identical.
+/// Otherwise, the words of their titles are compared, and so are the
+/// last names of their authors when both have authors, with the title
+/// weighing twice as much. This matches, e.g., the preprint and the
+/// published version of a paper, whose titles often differ by a few
+/// words.
+pub fn similarity(a: &Resource, b: &Resource) -> f64 {
+    let same_eprint = matches!(
+        (&a.eprint, &b.eprint),
+        (Some(x), Some(y)) if x.eq_ignore_ascii_case(y)
+    );
+    if a.is_duplicate_of(b) || same_eprint {
+        return 1.0;
+    }
+    let title = jaccard(&words(&a.title), &words(&b.title));
+    let (a_names, b_names) = (last_names(a), last_names(b));
+    match a_names.is_empty() || b_names.is_empty() {
+        true => title,
+        false => (2.0 * title + jaccard(&a_names, &b_names)) / 3.0,
+    }
+}
+
+/// Pairs of resources that are likely the same work, by initial
+/// checksum, most similar first. Resources related to each other and
+/// pairs marked as distinct aren't flagged.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `threshold` - Similarity (see `similarity`) from which resources
+///   are flagged.
+pub fn near_duplicates(
+    catalog: &Catalog,
+    threshold: f64,
+) -> Vec<(String, String, f64)> {
+    let related = |a: &Resource, b: &Resource| {
+        a.relations
+            .iter()
+            .flatten()
+            .any(|(_, t)| t.contains(&b.historical_checksums[0]))
+    };
+    let mut pairs = vec![];
+    for (i, a) in catalog.resources.iter().enumerate() {
+        for b in &catalog.resources[i + 1..] {
+            let (a_key, b_key) =
+                (&a.historical_checksums[0], &b.historical_checksums[0]);
+            if related(a, b)
+                || related(b, a)
+                || catalog.distinct.iter().any(|[x, y]| {
+                    (x == a_key && y == b_key) || (x == b_key && y == a_key)
+                })
+            {
+                continue;
+            }
+            let score = similarity(a, b);
+            if score >= threshold {
+                pairs.push((a_key.clone(), b_key.clone(), score));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    pairs
+}
+
+/// Shorten a value to fit a column.
+fn fit(value: &str, width: usize) -> String {
+    match value.chars().count() > width {
+        true => value.chars().take(width - 1).chain(Some('…')).collect(),
+        false => value.to_string(),
+    }
+}
+
+/// Print the fields of two resources side by side.
+fn print_pair(a: &Resource, b: &Resource) {
+    let row = |label: &str, x: &str, y: &str| {
+        println!(
+            "  {:<9} {:<width$} | {}",
+            label,
+            fit(x, COLUMN_WIDTH),
+            y,
+            width = COLUMN_WIDTH
+        )
+    };
+    row(
+        "",
+        &a.historical_checksums[0][..8],
+        &b.historical_checksums[0][..8],
+    );
+    for field in COMPARED_FIELDS {
+        let (x, y) = (a.field_string(field), b.field_string(field));
+        if x.is_some() || y.is_some() {
+            row(
+                field,
+                x.as_deref().unwrap_or("-"),
+                y.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+}
+
+/// Walk through the pairs of resources that are likely the same work,
+/// e.g., the preprint and the published version of a paper. Each pair
+/// is shown side by side, and the user can merge one resource into the
+/// other, which keeps the merged file as an attachment, keep both as
+/// versions with the later one superseding the earlier one, or mark
+/// them as distinct so that they aren't flagged again.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `threshold` - Similarity from which resources are flagged (see
+///   `similarity`).
+pub fn librarian_dedupe(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    threshold: f64,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let pairs = near_duplicates(catalog, threshold);
+    if pairs.is_empty() {
+        println!("{}", message("dedupe-none", &[]));
+        return;
+    }
+    for (index, (a_key, b_key, score)) in pairs.iter().enumerate() {
+        let find = |key: &str| {
+            catalog
+                .resources
+                .iter()
+                .find(|r| r.historical_checksums[0] == key)
+                .cloned()
+        };
+        // Either resource may have been merged into another already.
+        let (a, b) = match (find(a_key), find(b_key)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+        println!(
+            "\n{}",
+            message(
+                "dedupe-header",
+                &[
+                    ("index", (index + 1).into()),
+                    ("count", pairs.len().into()),
+                    ("score", format!("{:.0}", score * 100.0).into())
+                ]
+            )
+        );
+        print_pair(&a, &b);
+        loop {
+            let choice = match ask(&message("dedupe-prompt", &[])) {
+                Some(c) => c,
+                None => return,
+            };
+            match choice.as_str() {
+                "1" | "2" => {
+                    let (keep, other) = match choice.as_str() {
+                        "1" => (a_key, b_key),
+                        _ => (b_key, a_key),
+                    };
+                    librarian_merge_resources(
+                        store,
+                        catalog,
+                        resources_path,
+                        keep,
+                        other,
+                        MergePreference::Kept,
+                        true,
+                    );
+                    println!(
+                        "{}",
+                        message(
+                            "dedupe-merged",
+                            &[
+                                ("other", other.as_str().into()),
+                                ("keep", keep.as_str().into())
+                            ]
+                        )
+                    );
+                }
+                "v" => {
+                    // The later resource supersedes the earlier one. An
+                    // undated resource counts as the earliest, and the
+                    // first resource supersedes the second when their
+                    // dates are the same.
+                    let (newer, older) = match b.date > a.date {
+                        true => (b_key, a_key),
+                        false => (a_key, b_key),
+                    };
+                    catalog
+                        .resource_mut(newer)
+                        .unwrap()
+                        .relations
+                        .get_or_insert_with(Default::default)
+                        .entry(Relation::Supersedes)
+                        .or_default()
+                        .push(older.clone());
+                    store.write(catalog);
+                    if !catalog.dry_run {
+                        record(
+                            library,
+                            &AuditEntry::new(
+                                "dedupe",
+                                vec![newer.clone(), older.clone()],
+                                vec![format!("{} supersedes {}", newer, older)],
+                            ),
+                        );
+                    }
+                    println!(
+                        "{}",
+                        message(
+                            "dedupe-superseded",
+                            &[
+                                ("newer", newer.as_str().into()),
+                                ("older", older.as_str().into())
+                            ]
+                        )
+                    );
+                }
+                "n" => {
+                    catalog.distinct.push([a_key.clone(), b_key.clone()]);
+                    store.write(catalog);
+                }
+                "s" => (),
+                "q" => return,
+                _ => {
+                    println!("{}", message("dedupe-invalid", &[]));
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_duplicates() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"distinct\": [[\"dd\", \"cc\"]],
+              \"resources\": [
+              {\"title\": \"A General Theory of Phase Noise in Electrical Oscillators\", \"author\": [\"Ali Hajimiri\", \"Thomas H. Lee\"],
+               \"eprint\": \"9801001\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"A general theory of phase noise in electrical oscillators\", \"author\": [\"A. Hajimiri\", \"T. Lee\"],
+               \"doi\": \"10.1109/4.658619\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Introduction\", \"author\": [\"Jane Doe\"], \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+              {\"title\": \"Introduction\", \"author\": [\"John Roe\"], \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]},
+              {\"title\": \"Notes\", \"eprint\": \"9801001\", \"checksum\": \"ee\", \"historical_checksums\": [\"ee\"]}
+            ]}",
+        )
+        .unwrap();
+        let pairs = near_duplicates(&catalog, 0.6);
+        let pairs: Vec<(&str, &str)> = pairs
+            .iter()
+            .map(|(a, b, _)| (a.as_str(), b.as_str()))
+            .collect();
+        // "aa" and "ee" share an arXiv identifier, "aa" and "bb" differ
+        // only in case and initials, and "cc" and "dd" are distinct.
+        assert!(pairs == vec![("aa", "bb"), ("aa", "ee")]);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::pdf::split_authors;
+use crate::resource::{DateTime, Name};
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+/// Completion of the last item of a list against existing values.
+struct Values {
+    values: Vec<String>,
+    /// Character separating the items of the list.
+    separator: char,
+}
+
+/// Values completing the item before the cursor, ignoring case.
+///
+/// # Arguments
+///
+/// * `values` - Existing values.
+/// * `separator` - Character separating the items of the line.
+/// * `line` - Line being edited.
+/// * `pos` - Position of the cursor.
+///
+/// # Returns
+///
+/// The position where the item starts, along with the values it may
+/// be completed to.
+fn complete_item(
+    values: &[String],
+    separator: char,
+    line: &str,
+    pos: usize,
+) -> (usize, Vec<String>) {
+    let start = line[..pos].rfind(separator).map_or(0, |i| i + 1);
+    let item = &line[start..pos];
+    let start = start + item.len() - item.trim_start().len();
+    let prefix = item.trim_start().to_lowercase();
+    (
+        start,
+        values
+            .iter()
+            .filter(|v| v.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect(),
+    )
+}
+
+impl Completer for Values {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_item(&self.values, self.separator, line, pos))
+    }
+}
+
+impl Hinter for Values {
+    type Hint = String;
+}
+
+impl Highlighter for Values {}
+
+impl Validator for Values {}
+
+impl Helper for Values {}
+
+/// Prompt for the value of a field, starting from its current value.
+///
+/// # Arguments
+///
+/// * `editor` - Line editor.
+/// * `label` - Message identifier of the prompt.
+/// * `initial` - Current value.
+/// * `values` - Existing values the items of the field are completed to.
+/// * `separator` - Character separating the items of the field.
+///
+/// # Returns
+///
+/// The trimmed value, or `None` if the user ended the input.
+fn prompt(
+    editor: &mut Editor<Values, DefaultHistory>,
+    label: &str,
+    initial: &str,
+    values: Vec<String>,
+    separator: char,
+) -> Option<String> {
+    editor.set_helper(Some(Values { values, separator }));
+    match editor.readline_with_initial(
+        &format!("{} ", message(label, &[])),
+        (initial, ""),
+    ) {
+        Ok(line) => Some(line.trim().to_string()),
+        Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => None,
+        Err(e) => panic!("Failed to read response: {}", e),
+    }
+}
+
+/// Prompt until a value is accepted by `parse`, which returns an error
+/// to print for values it rejects.
+fn prompt_valid<T>(
+    editor: &mut Editor<Values, DefaultHistory>,
+    label: &str,
+    initial: &str,
+    values: &[String],
+    separator: char,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Option<T> {
+    let mut initial = initial.to_string();
+    loop {
+        let value =
+            prompt(editor, label, &initial, values.to_vec(), separator)?;
+        match parse(&value) {
+            Ok(v) => return Some(v),
+            Err(e) => {
+                eprintln!("{}", e);
+                initial = value;
+            }
+        }
+    }
+}
+
+/// Prompt for the title, authors, date, tags, content type and
+/// document type of new resources. Each prompt starts from the
+/// resource's current value, which is kept by pressing enter, and Tab
+/// completes values already used in the library. Ending the input
+/// (Ctrl-D) or interrupting it (Ctrl-C) keeps the remaining resources as
+/// they are. Citation keys are generated again from the new metadata.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `keys` - Initial checksums of the resources to describe.
+pub fn describe_resources(catalog: &mut Catalog, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+    let mut editor = Editor::<Values, DefaultHistory>::new()
+        .expect("Failed to read from the terminal");
+    let mut authors = BTreeSet::new();
+    let mut tags: BTreeSet<String> = catalog.tags.keys().cloned().collect();
+    for resource in &catalog.resources {
+        authors.extend(
+            resource.author.iter().flatten().cloned().map(String::from),
+        );
+        tags.extend(resource.tags.iter().flatten().cloned());
+    }
+    let authors: Vec<String> = authors.into_iter().collect();
+    let tags: Vec<String> = tags.into_iter().collect();
+    let content_types: Vec<String> =
+        catalog.content_types.keys().cloned().collect();
+    let document_types: Vec<String> =
+        catalog.document_types.keys().cloned().collect();
+
+    for key in keys {
+        let mut resource = catalog
+            .resources
+            .iter()
+            .find(|r| &r.historical_checksums[0] == key)
+            .unwrap()
+            .clone();
+        println!(
+            "\n{}",
+            message(
+                "describe-header",
+                &[
+                    ("title", resource.title.as_str().into()),
+                    ("key", key.as_str().into())
+                ]
+            )
+        );
+
+        let described = (|| {
+            let title = prompt(
+                &mut editor,
+                "describe-title",
+                &resource.title,
+                vec![],
+                '\n',
+            )?;
+            if !title.is_empty() {
+                resource.title = title;
+            }
+            let initial = resource
+                .author
+                .iter()
+                .flatten()
+                .cloned()
+                .map(String::from)
+                .collect::<Vec<_>>()
+                .join("; ");
+            resource.author = prompt_valid(
+                &mut editor,
+                "describe-authors",
+                &initial,
+                &authors,
+                ';',
+                |a| {
+                    let names = split_authors(a)
+                        .iter()
+                        .map(|n| Name::try_from(n.as_str()))
+                        .collect::<Result<Vec<Name>, _>>()
+                        .map_err(|e| e.to_string())?;
+                    Ok(Some(names).filter(|n| !n.is_empty()))
+                },
+            )?;
+            let initial =
+                resource.date.clone().map(String::from).unwrap_or_default();
+            resource.date = prompt_valid(
+                &mut editor,
+                "describe-date",
+                &initial,
+                &[],
+                '\n',
+                |d| match d.is_empty() {
+                    true => Ok(None),
+                    false => DateTime::try_from(d)
+                        .map(Some)
+                        .map_err(|e| e.to_string()),
+                },
+            )?;
+            let initial = resource.tags.clone().unwrap_or_default().join(" ");
+            resource.tags = prompt_valid(
+                &mut editor,
+                "describe-tags",
+                &initial,
+                &tags,
+                ' ',
+                |t| {
+                    let t: Vec<String> =
+                        t.split_whitespace().map(String::from).collect();
+                    catalog.check_tags(&t)?;
+                    Ok(Some(t).filter(|t| !t.is_empty()))
+                },
+            )?;
+            let known = |names: &[String], id: &str, value: &str| match value
+                .is_empty()
+                || names.iter().any(|n| n == value)
+            {
+                true => Ok(Some(value.to_string()).filter(|v| !v.is_empty())),
+                false => Err(message(id, &[("value", value.into())])),
+            };
+            resource.content = prompt_valid(
+                &mut editor,
+                "describe-content",
+                resource.content.as_deref().unwrap_or(""),
+                &content_types,
+                '\n',
+                |c| known(&content_types, "describe-unknown-content", c),
+            )?;
+            resource.document = prompt_valid(
+                &mut editor,
+                "describe-document",
+                resource.document.as_deref().unwrap_or(""),
+                &document_types,
+                '\n',
+                |d| known(&document_types, "describe-unknown-document", d),
+            )?;
+            Some(())
+        })();
+        match described {
+            // The citation key was generated from the metadata the
+            // resource was cataloged with.
+            Some(()) => {
+                resource.citekey = None;
+                *catalog.resource_mut(key).unwrap() = resource;
+            }
+            None => break,
+        }
+    }
+    catalog.assign_citekeys();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_item() {
+        let tags = vec![
+            String::from("optics"),
+            String::from("oscillators"),
+            String::from("rf"),
+        ];
+        let (start, candidates) = complete_item(&tags, ' ', "rf Os", 5);
+        assert!(start == 3);
+        assert!(candidates == vec!["oscillators"]);
+        let (start, candidates) = complete_item(&tags, ' ', "", 0);
+        assert!(start == 0 && candidates.len() == 3);
+
+        let authors = vec![String::from("Ali Hajimiri")];
+        let (start, candidates) =
+            complete_item(&authors, ';', "Thomas Lee; ali", 15);
+        assert!(start == 12);
+        assert!(candidates == vec!["Ali Hajimiri"]);
+    }
+}
@@ -0,0 +1,582 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::check::detect_extension;
+use crate::i18n::message;
+use crate::metadata::{fetch_arxiv, fetch_crossref, normalize_arxiv_id};
+use crate::resource::{Doi, Resource};
+use crate::storage::Storage;
+
+use sha1::{Digest, Sha1};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+/// Directory of the library holding interrupted downloads until they
+/// are resumed.
+pub const DOWNLOADS_DIR: &str = ".downloads";
+
+/// Number of times a download is resumed after its connection breaks
+/// before giving up.
+const ATTEMPTS: u64 = 5;
+
+#[derive(Debug)]
+pub struct DownloadError {
+    details: String,
+}
+
+impl DownloadError {
+    fn new(msg: &str) -> DownloadError {
+        DownloadError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for DownloadError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Way a request for the rest of a download failed.
+enum Failure {
+    /// The connection broke, and the download can be resumed.
+    Interrupted(String),
+    /// The server refused the download, which can't be resumed.
+    Refused(String),
+}
+
+/// Request the part of a resource that isn't in the partial file yet
+/// and append it to the file.
+///
+/// The server's ETag is kept next to the partial file, so that a
+/// resumed request only continues the same version of the resource.
+/// A server that ignores the range, or whose resource changed, sends
+/// the whole resource, which replaces the partial file.
+fn fetch_remaining(
+    agent: &ureq::Agent,
+    url: &str,
+    partial: &Path,
+) -> Result<(), Failure> {
+    let etag_path = partial.with_extension("etag");
+    let offset = std::fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+    let mut request = agent.get(url);
+    if offset > 0 {
+        request = request.set("Range", &format!("bytes={}-", offset));
+        if let Ok(etag) = std::fs::read_to_string(&etag_path) {
+            request = request.set("If-Range", etag.trim());
+        }
+    }
+    let response = match request.call() {
+        Ok(r) => r,
+        // The partial file already holds the whole resource.
+        Err(ureq::Error::Status(416, _)) if offset > 0 => return Ok(()),
+        Err(ureq::Error::Status(code, r)) => {
+            return Err(Failure::Refused(format!(
+                "{} returned {} {}",
+                url,
+                code,
+                r.status_text()
+            )))
+        }
+        Err(e) => return Err(Failure::Interrupted(e.to_string())),
+    };
+    match response.header("ETag") {
+        Some(etag) => std::fs::write(&etag_path, etag)
+            .map_err(|e| Failure::Refused(e.to_string()))?,
+        None => std::fs::remove_file(&etag_path).unwrap_or(()),
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(response.status() == 206)
+        .truncate(response.status() != 206)
+        .open(partial)
+        .map_err(|e| Failure::Refused(e.to_string()))?;
+    std::io::copy(&mut response.into_reader(), &mut file)
+        .map_err(|e| Failure::Interrupted(e.to_string()))?;
+    Ok(())
+}
+
+/// Download a resource into a partial file, resuming the download
+/// whenever its connection breaks. A partial file left by an earlier
+/// run is resumed as well.
+///
+/// The partial file is removed when the download fails for good or its
+/// content doesn't match the expected checksum. It is kept after
+/// repeated interruptions, so that a later run can resume it.
+///
+/// # Arguments
+///
+/// * `agent` - HTTP agent.
+/// * `url` - URL of the resource.
+/// * `partial` - Partial file the resource is downloaded to.
+/// * `expected` - Expected SHA-1 checksum of the resource.
+///
+/// # Returns
+///
+/// The SHA-1 checksum of the downloaded file.
+pub fn download(
+    agent: &ureq::Agent,
+    url: &str,
+    partial: &Path,
+    expected: Option<&str>,
+) -> Result<String, DownloadError> {
+    let discard = || {
+        std::fs::remove_file(partial).unwrap_or(());
+        std::fs::remove_file(partial.with_extension("etag")).unwrap_or(());
+    };
+    for attempt in 1..=ATTEMPTS {
+        match fetch_remaining(agent, url, partial) {
+            Ok(()) => break,
+            Err(Failure::Refused(e)) => {
+                discard();
+                return Err(DownloadError::new(&e));
+            }
+            Err(Failure::Interrupted(e)) if attempt == ATTEMPTS => {
+                return Err(DownloadError::new(&message(
+                    "download-interrupted",
+                    &[("url", url.into()), ("error", e.into())],
+                )))
+            }
+            Err(Failure::Interrupted(e)) => {
+                eprintln!(
+                    "{}",
+                    message(
+                        "download-resuming",
+                        &[("url", url.into()), ("error", e.into())]
+                    )
+                );
+                std::thread::sleep(Duration::from_secs(attempt));
+            }
+        }
+    }
+
+    let checksum = file_checksum(partial);
+    if let Some(e) = expected {
+        if !e.eq_ignore_ascii_case(&checksum) {
+            discard();
+            return Err(DownloadError::new(&message(
+                "download-checksum-mismatch",
+                &[
+                    ("url", url.into()),
+                    ("checksum", checksum.into()),
+                    ("expected", e.into()),
+                ],
+            )));
+        }
+    }
+    std::fs::remove_file(partial.with_extension("etag")).unwrap_or(());
+    Ok(checksum)
+}
+
+/// Download a file and catalog it, recording the URL it was downloaded
+/// from. Interrupted downloads are resumed, and the checksum of the
+/// file is verified before it's cataloged.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `url` - URL of the file.
+/// * `sha1` - Expected SHA-1 checksum of the file.
+/// * `name` - File name of the download, which gives the resource's
+///   initial title and document type. This defaults to the last
+///   segment of the URL.
+pub fn librarian_fetch(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    url: &str,
+    sha1: Option<&str>,
+    name: Option<&str>,
+) {
+    let parsed = Url::parse(url)
+        .unwrap_or_else(|e| panic!("Invalid URL {}: {}", url, e));
+    let name = name
+        .map(String::from)
+        .or_else(|| {
+            parsed
+                .path_segments()
+                .and_then(|mut s| s.next_back())
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+        })
+        .unwrap_or_else(|| String::from("download"));
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let downloads = library.join(DOWNLOADS_DIR);
+    std::fs::create_dir_all(&downloads)
+        .expect("Failed to create downloads directory");
+    // Partial files are named after the URL so that the next run for
+    // the same URL finds them.
+    let partial = downloads.join(format!(
+        "{}.part",
+        hex::encode(Sha1::digest(url.as_bytes()))
+    ));
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let checksum = download(&agent, url, &partial, sha1)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    if catalog.resource_mut(&checksum).is_some() {
+        std::fs::remove_file(&partial).unwrap_or(());
+        println!(
+            "{}",
+            message(
+                "fetch-existing",
+                &[("source", url.into()), ("key", checksum.into())]
+            )
+        );
+    } else {
+        Storage::of(resources_path, catalog).store(&partial, &checksum);
+        let file_name = Path::new(&name);
+        let mut resource = Resource::new(
+            file_name
+                .file_stem()
+                .unwrap_or(file_name.as_os_str())
+                .to_string_lossy()
+                .to_string(),
+            catalog.document_type(file_name),
+            checksum.clone(),
+        );
+        resource.url = Some(parsed);
+        catalog.resources.push(resource);
+        catalog.sort();
+        catalog.assign_citekeys();
+        store.write(catalog);
+        println!("{}", checksum);
+    }
+    // This fails if other downloads are still partial, which is fine.
+    std::fs::remove_dir(&downloads).ok();
+}
+
+/// Location of the best open-access PDF of a work, given its Unpaywall
+/// record.
+fn unpaywall_pdf(work: &serde_json::Value) -> Option<String> {
+    std::iter::once(&work["best_oa_location"])
+        .chain(work["oa_locations"].as_array().into_iter().flatten())
+        .find_map(|l| l["url_for_pdf"].as_str())
+        .map(String::from)
+}
+
+/// Ask Unpaywall for the location of an open-access PDF of a DOI.
+fn unpaywall(
+    agent: &ureq::Agent,
+    doi: &Doi,
+    email: &str,
+) -> Result<String, DownloadError> {
+    let failed = |e: &dyn fmt::Display| {
+        DownloadError::new(&format!(
+            "Unpaywall request for {} failed: {}",
+            doi, e
+        ))
+    };
+    let work: serde_json::Value = agent
+        .get(&format!("https://api.unpaywall.org/v2/{}", doi))
+        .query("email", email)
+        .call()
+        .map_err(|e| failed(&e))?
+        .into_json()
+        .map_err(|e| failed(&e))?;
+    unpaywall_pdf(&work).ok_or_else(|| {
+        DownloadError::new(&format!(
+            "Unpaywall knows no open-access PDF of {}",
+            doi
+        ))
+    })
+}
+
+/// Download the open-access PDF of a work into a partial file of the
+/// library's downloads directory, trying arXiv before Unpaywall.
+///
+/// # Returns
+///
+/// The partial file and its checksum.
+fn download_pdf(
+    downloads: &Path,
+    doi: Option<&Doi>,
+    arxiv: Option<&str>,
+    email: Option<&str>,
+) -> Result<(PathBuf, String), DownloadError> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let mut urls: Vec<Result<String, DownloadError>> = vec![];
+    if let Some(a) = arxiv {
+        urls.push(Ok(format!("https://arxiv.org/pdf/{}", a)));
+    }
+    if let Some(d) = doi {
+        urls.push(match email {
+            Some(e) => unpaywall(&agent, d, e),
+            None => Err(DownloadError::new(
+                "Unpaywall needs an email address, given by --email or UNPAYWALL_EMAIL",
+            )),
+        });
+    }
+    let mut errors = vec![];
+    for url in urls {
+        let result = url.and_then(|url| {
+            let partial = downloads.join(format!(
+                "{}.part",
+                hex::encode(Sha1::digest(url.as_bytes()))
+            ));
+            let checksum = download(&agent, &url, &partial, None)?;
+            // Publishers often answer with a landing or login page
+            // instead of the PDF.
+            match detect_extension(&partial) {
+                Some("pdf") => Ok((partial, checksum)),
+                other => {
+                    std::fs::remove_file(&partial).unwrap_or(());
+                    Err(DownloadError::new(&format!(
+                        "{} isn't a PDF ({})",
+                        url,
+                        other.unwrap_or("unknown type")
+                    )))
+                }
+            }
+        });
+        match result {
+            Ok(r) => return Ok(r),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    Err(DownloadError::new(&errors.join("; ")))
+}
+
+/// Download the open-access PDF of a work by its DOI or arXiv
+/// identifier and catalog it. A resource with the identifier whose file
+/// is missing, such as one whose file was never downloaded, receives
+/// the PDF as its file. Otherwise, a new resource is created with
+/// metadata fetched from CrossRef or arXiv.
+///
+/// PDFs come from arXiv for arXiv identifiers and from the locations
+/// known to Unpaywall for DOIs.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `identifier` - DOI or arXiv identifier of the work.
+/// * `email` - Email address sent with Unpaywall requests, which
+///   Unpaywall requires.
+pub fn librarian_fetch_file(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    identifier: &str,
+    email: Option<&str>,
+) {
+    let given_doi = Doi::try_from(identifier).ok();
+    let given_arxiv = match given_doi {
+        Some(_) => None,
+        None => Some(normalize_arxiv_id(identifier)),
+    };
+    let arxiv_of = |r: &Resource| match r.eprinttype.as_deref() {
+        Some("arxiv") => r.eprint.as_deref().map(normalize_arxiv_id),
+        _ => None,
+    };
+    let existing = catalog.resources.iter().find(|r| {
+        (given_doi.is_some() && r.doi == given_doi)
+            || (given_arxiv.is_some() && arxiv_of(r) == given_arxiv)
+    });
+    // A cataloged resource may know the work's other identifier.
+    let doi = given_doi.or_else(|| existing.and_then(|r| r.doi.clone()));
+    let arxiv = given_arxiv.or_else(|| existing.and_then(arxiv_of));
+    let key = existing.map(|r| r.historical_checksums[0].clone());
+    let storage = Storage::of(resources_path, catalog);
+    if let Some(k) = &key {
+        if storage.resource_path(k).exists() {
+            panic!("{} already has a file", k);
+        }
+    }
+
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let downloads = library.join(DOWNLOADS_DIR);
+    std::fs::create_dir_all(&downloads)
+        .expect("Failed to create downloads directory");
+    let email = email
+        .map(String::from)
+        .or_else(|| std::env::var("UNPAYWALL_EMAIL").ok());
+    let (partial, checksum) = download_pdf(
+        &downloads,
+        doi.as_ref(),
+        arxiv.as_deref(),
+        email.as_deref(),
+    )
+    .unwrap_or_else(|e| panic!("No PDF of {} found: {}", identifier, e));
+
+    if let Some(other) = catalog.resource_mut(&checksum) {
+        std::fs::remove_file(&partial).unwrap_or(());
+        std::fs::remove_dir(&downloads).ok();
+        println!(
+            "{}",
+            message(
+                "fetch-existing",
+                &[
+                    ("source", identifier.into()),
+                    ("key", other.historical_checksums[0].as_str().into())
+                ]
+            )
+        );
+        return;
+    }
+    let pdf = catalog.document_type(Path::new("file.pdf"));
+    let key = match key {
+        Some(k) => {
+            storage.store(&partial, &k);
+            let resource = catalog.resource_mut(&k).unwrap();
+            resource.historical_checksums.push(checksum.clone());
+            resource.checksum = checksum.clone();
+            resource.document = pdf;
+            k
+        }
+        None => {
+            storage.store(&partial, &checksum);
+            let mut resource =
+                Resource::new(identifier.to_string(), pdf, checksum.clone());
+            let metadata = match (&doi, &arxiv) {
+                (Some(d), _) => fetch_crossref(d),
+                (None, Some(a)) => fetch_arxiv(a),
+                (None, None) => unreachable!(),
+            };
+            match metadata {
+                Ok(m) => m.apply(&mut resource),
+                Err(e) => eprintln!(
+                    "{}",
+                    message(
+                        "metadata-fetch-failed",
+                        &[
+                            ("identifier", identifier.into()),
+                            ("error", e.to_string().into())
+                        ]
+                    )
+                ),
+            }
+            resource.doi = doi.clone();
+            if let Some(a) = &arxiv {
+                resource.eprint = Some(a.clone());
+                resource.eprinttype = Some(String::from("arxiv"));
+            }
+            catalog.resources.push(resource);
+            catalog.sort();
+            checksum.clone()
+        }
+    };
+    std::fs::remove_dir(&downloads).ok();
+    catalog.assign_citekeys();
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "fetch-file",
+                vec![key.clone()],
+                vec![format!("{} file: {} ({})", key, checksum, identifier)],
+            ),
+        );
+    }
+    println!("{}", checksum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_resumed_download() {
+        let body: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url =
+            format!("http://{}/video.mp4", listener.local_addr().unwrap());
+        let served = body.clone();
+        // The first connection breaks halfway through, and the others
+        // serve the requested range.
+        let server = std::thread::spawn(move || {
+            for (i, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                let mut offset = 0;
+                for line in BufReader::new(stream.try_clone().unwrap()).lines()
+                {
+                    let line = line.unwrap();
+                    if let Some(r) = line.strip_prefix("Range: bytes=") {
+                        offset = r.trim_end_matches('-').parse().unwrap();
+                    }
+                    if line.is_empty() {
+                        break;
+                    }
+                }
+                let status = match offset {
+                    0 => "200 OK",
+                    _ => "206 Partial Content",
+                };
+                let rest = &served[offset..];
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nETag: \"v1\"\r\n\r\n",
+                    status,
+                    rest.len()
+                )
+                .unwrap();
+                let sent = match i {
+                    0 => rest.len() / 2,
+                    _ => rest.len(),
+                };
+                stream.write_all(&rest[..sent]).unwrap();
+            }
+        });
+
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let partial = dir.join("video.part");
+        let agent = ureq::AgentBuilder::new().build();
+        let expected = hex::encode(Sha1::digest(&body));
+        let checksum =
+            download(&agent, &url, &partial, Some(&expected)).unwrap();
+        server.join().unwrap();
+        assert!(checksum == expected);
+        assert!(std::fs::read(&partial).unwrap() == body);
+        assert!(!partial.with_extension("etag").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unpaywall_pdf() {
+        let work = serde_json::json!({
+            "best_oa_location": {"url_for_pdf": null, "url": "https://example.org/landing"},
+            "oa_locations": [
+                {"url_for_pdf": null},
+                {"url_for_pdf": "https://example.org/paper.pdf"}
+            ]
+        });
+        assert!(
+            unpaywall_pdf(&work).as_deref()
+                == Some("https://example.org/paper.pdf")
+        );
+        assert!(unpaywall_pdf(&serde_json::json!({"is_oa": false})).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::resource::Resource;
+use crate::search::search;
+
+use std::path::Path;
+
+/// Changes applied to every selected resource by a bulk edit.
+#[derive(Debug, Clone, Default)]
+pub struct BulkEdit {
+    /// Tags to add.
+    pub add_tags: Vec<String>,
+    /// Tags to remove.
+    pub remove_tags: Vec<String>,
+    /// Fields to set, as field name and value. Values starting with "["
+    /// and "null" are parsed as JSON (e.g., a list of authors, or
+    /// removing the field). Everything else is a string.
+    pub set: Vec<(String, String)>,
+    /// Add tags missing from the catalog's tag tree to it, rather than
+    /// rejecting them.
+    pub create_tags: bool,
+}
+
+impl BulkEdit {
+    /// Description of each change, as recorded in the audit log.
+    fn changes(&self) -> Vec<String> {
+        self.add_tags
+            .iter()
+            .map(|t| format!("added tag {}", t))
+            .chain(
+                self.remove_tags
+                    .iter()
+                    .map(|t| format!("removed tag {}", t)),
+            )
+            .chain(self.set.iter().map(|(f, v)| format!("set {} to {}", f, v)))
+            .collect()
+    }
+
+    /// A copy of a resource with the changes applied.
+    ///
+    /// Fields are set through the resource's serialized form, so values
+    /// are validated the same way as when the catalog is read.
+    pub(crate) fn apply(
+        &self,
+        resource: &Resource,
+    ) -> Result<Resource, String> {
+        let mut tags: Vec<String> = resource.tags.clone().unwrap_or_default();
+        for tag in &self.add_tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags.retain(|t| !self.remove_tags.contains(t));
+
+        let mut value = serde_json::to_value(resource).unwrap();
+        value["tags"] = match tags.is_empty() {
+            true => serde_json::Value::Null,
+            false => serde_json::to_value(tags).unwrap(),
+        };
+        for (field, v) in &self.set {
+            value[field.as_str()] = match v.starts_with('[') || v == "null" {
+                true => serde_json::from_str(v)
+                    .map_err(|e| format!("{}: {}", field, e))?,
+                false => serde_json::Value::String(v.clone()),
+            };
+        }
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+}
+
+/// Parse a selection of numbered results, such as "1,3-5" or "all".
+///
+/// # Arguments
+///
+/// * `input` - Selection entered by the user. Numbers start at 1.
+/// * `count` - Number of results.
+///
+/// # Returns
+///
+/// The zero-based indices of the selected results, or `None` if the
+/// selection is invalid.
+fn parse_selection(input: &str, count: usize) -> Option<Vec<usize>> {
+    match input.trim() {
+        "all" => return Some((0..count).collect()),
+        "" | "none" => return Some(vec![]),
+        _ => (),
+    }
+    let mut selection = Vec::new();
+    for part in input.split(',').map(str::trim) {
+        let (first, last) = match part.split_once('-') {
+            Some((f, l)) => (f.trim().parse().ok()?, l.trim().parse().ok()?),
+            None => {
+                let n: usize = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if first == 0 || last < first || last > count {
+            return None;
+        }
+        for i in first - 1..last {
+            if !selection.contains(&i) {
+                selection.push(i);
+            }
+        }
+    }
+    Some(selection)
+}
+
+/// Apply tag additions, tag removals and field edits to several
+/// resources at once.
+///
+/// The resources matching a search query are listed with numbers and
+/// the user selects which of them to edit. The edit is recorded as a
+/// single audit log entry.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Search query whose results can be selected.
+/// * `edit` - Changes to apply.
+/// * `all` - Edit every result without asking.
+pub fn librarian_bulk_edit(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    query: &str,
+    edit: &BulkEdit,
+    all: bool,
+) {
+    let results: Vec<(String, String)> = search(catalog, query)
+        .iter()
+        .map(|r| (r.historical_checksums[0].clone(), r.title.clone()))
+        .collect();
+    if results.is_empty() {
+        println!("{}", message("edit-no-matches", &[("query", query.into())]));
+        return;
+    }
+
+    let selection = match all {
+        true => (0..results.len()).collect(),
+        false => {
+            for (i, (key, title)) in results.iter().enumerate() {
+                println!("{:>3}. {} ({})", i + 1, title, key);
+            }
+            loop {
+                // Nothing is selected when the input ends.
+                let response =
+                    ask(&message("edit-select", &[])).unwrap_or_default();
+                match parse_selection(&response, results.len()) {
+                    Some(s) => break s,
+                    None => {
+                        println!("{}", message("edit-invalid-selection", &[]))
+                    }
+                }
+            }
+        }
+    };
+    if selection.is_empty() {
+        return;
+    }
+
+    // Validate every edit before changing anything, so that the edit is
+    // applied to all selected resources or none.
+    catalog
+        .validate_tags(&edit.add_tags, edit.create_tags)
+        .unwrap_or_else(|e| {
+            panic!("{}. Pass --create-tags to add them to the tag tree.", e)
+        });
+    let keys: Vec<String> =
+        selection.iter().map(|i| results[*i].0.clone()).collect();
+    let mut edited = Vec::new();
+    for key in &keys {
+        let resource = catalog.resource_mut(key).unwrap();
+        match edit.apply(resource) {
+            Ok(r) => edited.push(r),
+            Err(e) => panic!("Failed to edit resource {}: {}", key, e),
+        }
+    }
+    for (key, resource) in keys.iter().zip(edited) {
+        *catalog.resource_mut(key).unwrap() = resource;
+    }
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new("bulk-edit", keys.clone(), edit.changes()),
+        );
+    }
+    println!("{}", message("edit-done", &[("count", keys.len().into())]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selection() {
+        assert!(parse_selection("1,3-5", 6) == Some(vec![0, 2, 3, 4]));
+        assert!(parse_selection(" all\n", 3) == Some(vec![0, 1, 2]));
+        assert!(parse_selection("none", 3) == Some(vec![]));
+        assert!(parse_selection("0", 3).is_none());
+        assert!(parse_selection("2-7", 3).is_none());
+        assert!(parse_selection("a", 3).is_none());
+    }
+
+    #[test]
+    fn test_bulk_edit_apply() {
+        let resource: Resource = serde_json::from_str(
+            "{\"title\": \"Phase Noise\", \"tags\": [\"rf\", \"draft\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}",
+        )
+        .unwrap();
+        let edit = BulkEdit {
+            add_tags: vec![String::from("oscillators")],
+            remove_tags: vec![String::from("draft")],
+            set: vec![
+                (String::from("journal"), String::from("JSSC")),
+                (String::from("date"), String::from("1998-02")),
+            ],
+            ..Default::default()
+        };
+        let edited = edit.apply(&resource).unwrap();
+        assert!(
+            edited.tags
+                == Some(vec![String::from("rf"), String::from("oscillators")])
+        );
+        assert!(edited.journal == Some(String::from("JSSC")));
+        assert!(edited.date.unwrap().month == Some(2));
+
+        let invalid = BulkEdit {
+            set: vec![(String::from("date"), String::from("February"))],
+            ..Default::default()
+        };
+        assert!(invalid.apply(&resource).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::catalog::Catalog;
+
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+/// Serialize a JSON value with object keys sorted and without
+/// whitespace, so that equal values always serialize identically.
+fn canonical_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.to_string()).to_string());
+                out.push(':');
+                canonical_json(&map[key.as_str()], out);
+            }
+            out.push('}');
+        }
+        Value::Array(array) => {
+            out.push('[');
+            for (i, v) in array.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonical_json(v, out);
+            }
+            out.push(']');
+        }
+        v => out.push_str(&v.to_string()),
+    }
+}
+
+/// Digest of every cataloged resource, including its checksum and
+/// metadata.
+///
+/// The digest doesn't depend on the order of resources in the catalog
+/// or on how the catalog is stored.
+pub fn fingerprint(catalog: &Catalog) -> String {
+    let mut resources: Vec<_> = catalog.resources.iter().collect();
+    resources.sort_by(|a, b| {
+        a.historical_checksums[0].cmp(&b.historical_checksums[0])
+    });
+    let mut hasher = Sha1::new();
+    for resource in resources {
+        let mut canonical = String::new();
+        canonical_json(
+            &serde_json::to_value(resource).unwrap(),
+            &mut canonical,
+        );
+        hasher.update(canonical.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Print the fingerprint of a library, which can be compared between
+/// machines to confirm that their libraries are identical after a
+/// sync.
+///
+/// The fingerprint is computed from the catalog alone, so the catalog
+/// should be up to date (see `librarian_catalog`) for it to reflect the
+/// contents of the resources.
+pub fn librarian_fingerprint(catalog: &Catalog) {
+    println!("{}", fingerprint(catalog));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let catalog = |resources: &str| -> Catalog {
+            serde_json::from_str(&format!(
+                "{{\"document_types\": {{}}, \"content_types\": {{}}, \"resources\": [{}]}}",
+                resources
+            ))
+            .unwrap()
+        };
+        let a = "{\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}";
+        let b = "{\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}";
+        let b_retitled = "{\"title\": \"C\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}";
+
+        let ab = fingerprint(&catalog(&format!("{}, {}", a, b)));
+        assert!(ab == fingerprint(&catalog(&format!("{}, {}", b, a))));
+        assert!(ab != fingerprint(&catalog(&format!("{}, {}", a, b_retitled))));
+        assert!(ab != fingerprint(&catalog(a)));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::batch::write_atomically;
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::pdf::{is_pdf, pdf_text};
+use crate::storage::Storage;
+
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Directory of the library holding the text of each resource.
+pub const TEXT_DIR: &str = ".text";
+
+/// Directory of the library holding a thumbnail of the first page of
+/// each PDF resource.
+pub const THUMBNAIL_DIR: &str = ".thumbnails";
+
+/// Height of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Extensions of plain text files, whose text is their content.
+const TEXT_EXTENSIONS: [&str; 6] = ["txt", "md", "org", "tex", "rst", "bib"];
+
+/// Location of the text of a resource.
+///
+/// # Arguments
+///
+/// * `library` - Location of the library.
+/// * `key` - Initial checksum of the resource.
+pub fn text_path(library: &Path, key: &str) -> PathBuf {
+    library.join(TEXT_DIR).join(format!("{}.txt", key))
+}
+
+/// Location of the thumbnail of a resource. See `text_path`.
+pub fn thumbnail_path(library: &Path, key: &str) -> PathBuf {
+    library.join(THUMBNAIL_DIR).join(format!("{}.png", key))
+}
+
+/// Whether a file derived from a resource's file is missing or older
+/// than the resource's file.
+fn is_stale(derived: &Path, file: &Path) -> bool {
+    let modified =
+        |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(derived), modified(file)) {
+        (Some(d), Some(f)) => d < f,
+        _ => true,
+    }
+}
+
+/// Text of a resource's file.
+///
+/// # Arguments
+///
+/// * `file` - Resource's file.
+/// * `extension` - Extension of the resource's document type, since
+///   the file is usually named by its checksum. Files of resources
+///   without a document type are read as plain text if they look like
+///   it (see `is_plain_text`).
+///
+/// # Returns
+///
+/// The text of a PDF or plain text file, which is empty when none
+/// could be extracted, or `None` for other files.
+fn extract_text(file: &Path, extension: Option<&str>) -> Option<String> {
+    if is_pdf(file) {
+        return pdf_text(file);
+    }
+    let text = match extension {
+        Some(e) => TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()),
+        None => is_plain_text(file),
+    };
+    match text {
+        true => {
+            let contents = std::fs::read(file).ok()?;
+            Some(String::from_utf8_lossy(&contents).into_owned())
+        }
+        false => None,
+    }
+}
+
+/// Whether the start of a file is UTF-8 text without NUL bytes.
+fn is_plain_text(file: &Path) -> bool {
+    let mut start = Vec::new();
+    let read =
+        File::open(file).and_then(|f| f.take(4096).read_to_end(&mut start));
+    match (read, std::str::from_utf8(&start)) {
+        (Ok(_), Ok(s)) => !s.contains('\0'),
+        // The last character may be cut off.
+        (Ok(_), Err(e)) => e.error_len().is_none() && !start.contains(&0),
+        _ => false,
+    }
+}
+
+/// Render the first page of a PDF as a PNG with `pdftoppm`.
+///
+/// # Returns
+///
+/// Whether the thumbnail was rendered, or an error if `pdftoppm`
+/// couldn't be run.
+fn render_thumbnail(file: &Path, thumbnail: &Path) -> std::io::Result<bool> {
+    // pdftoppm adds the extension itself.
+    let status = Command::new("pdftoppm")
+        .args(["-png", "-singlefile", "-f", "1", "-l", "1", "-scale-to"])
+        .arg(THUMBNAIL_SIZE.to_string())
+        .arg(file)
+        .arg(thumbnail.with_extension(""))
+        .status()?;
+    Ok(status.success())
+}
+
+/// Lower the scheduling priority of the calling thread, so that
+/// indexing only takes the processor when nothing else needs it. On
+/// Linux, the priority belongs to the thread rather than the process,
+/// and programs it runs inherit it.
+fn lower_priority() {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+/// Resource for the worker to index.
+struct Job {
+    key: String,
+    title: String,
+    file: PathBuf,
+    /// Extension of the resource's document type.
+    extension: Option<String>,
+}
+
+/// Number of resources queued for indexing and of those indexed,
+/// shared with the worker.
+#[derive(Default)]
+struct Progress {
+    queued: AtomicUsize,
+    done: AtomicUsize,
+}
+
+/// Background worker that extracts the text of resources and renders
+/// thumbnails of their first pages, one resource at a time at a low
+/// priority, so that a long-running command stays responsive while a
+/// large batch of resources is indexed.
+///
+/// Text is kept in `TEXT_DIR` and thumbnails in `THUMBNAIL_DIR` of the
+/// library, named by the initial checksum of their resource. A
+/// resource is only indexed again when its file is newer than its text
+/// or thumbnail. Thumbnails need `pdftoppm`, from Poppler, and are
+/// skipped without it.
+pub struct Indexer {
+    jobs: Sender<Job>,
+    progress: Arc<Progress>,
+}
+
+impl Indexer {
+    /// Start the worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `library` - Location of the library.
+    /// * `delay` - Pause after indexing each resource, which throttles
+    ///   the worker.
+    pub fn spawn(library: &Path, delay: Duration) -> Indexer {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let progress = Arc::new(Progress::default());
+        let library = library.to_path_buf();
+        let shared = Arc::clone(&progress);
+        std::thread::spawn(move || {
+            lower_priority();
+            let mut thumbnails = true;
+            for job in rx {
+                let mut indexed = false;
+                let text = text_path(&library, &job.key);
+                if is_stale(&text, &job.file) {
+                    if let Some(t) =
+                        extract_text(&job.file, job.extension.as_deref())
+                    {
+                        std::fs::create_dir_all(library.join(TEXT_DIR))
+                            .expect("Failed to create text directory");
+                        write_atomically(&text, &t);
+                        indexed = true;
+                    }
+                }
+                let thumbnail = thumbnail_path(&library, &job.key);
+                if thumbnails
+                    && is_pdf(&job.file)
+                    && is_stale(&thumbnail, &job.file)
+                {
+                    std::fs::create_dir_all(library.join(THUMBNAIL_DIR))
+                        .expect("Failed to create thumbnail directory");
+                    match render_thumbnail(&job.file, &thumbnail) {
+                        Ok(true) => indexed = true,
+                        Ok(false) => eprintln!(
+                            "{}",
+                            message(
+                                "index-thumbnail-failed",
+                                &[("title", job.title.as_str().into())]
+                            )
+                        ),
+                        Err(e) => {
+                            let id = match e.kind() {
+                                ErrorKind::NotFound => "index-no-pdftoppm",
+                                _ => "index-pdftoppm-failed",
+                            };
+                            eprintln!(
+                                "{}",
+                                message(id, &[("error", e.to_string().into())])
+                            );
+                            thumbnails = false;
+                        }
+                    }
+                }
+                let done = shared.done.fetch_add(1, Ordering::SeqCst) + 1;
+                if indexed {
+                    println!(
+                        "{}",
+                        message(
+                            "index-progress",
+                            &[
+                                ("title", job.title.as_str().into()),
+                                ("done", done.into()),
+                                (
+                                    "total",
+                                    shared.queued.load(Ordering::SeqCst).into()
+                                )
+                            ]
+                        )
+                    );
+                    std::thread::sleep(delay);
+                }
+            }
+        });
+        Indexer { jobs, progress }
+    }
+
+    /// Queue resources for indexing. Resources without a file in the
+    /// resources directory, such as those in remote storage, are
+    /// skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - Library catalog.
+    /// * `storage` - Resources directory.
+    /// * `keys` - Initial checksums of the resources.
+    pub fn queue<'a>(
+        &self,
+        catalog: &Catalog,
+        storage: &Storage,
+        keys: impl IntoIterator<Item = &'a String>,
+    ) {
+        for key in keys {
+            let file = storage.resource_path(key);
+            let resource = catalog
+                .resources
+                .iter()
+                .find(|r| &r.historical_checksums[0] == key);
+            let resource = match resource {
+                Some(r) if file.is_file() => r,
+                _ => continue,
+            };
+            self.progress.queued.fetch_add(1, Ordering::SeqCst);
+            self.jobs
+                .send(Job {
+                    key: key.clone(),
+                    title: resource.title.clone(),
+                    file,
+                    extension: resource
+                        .document
+                        .as_ref()
+                        .and_then(|d| catalog.document_types.get(d))
+                        .map(|d| d.extension.clone()),
+                })
+                .expect("Indexing worker stopped");
+        }
+    }
+
+    /// Number of resources indexed and queued so far.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.progress.done.load(Ordering::SeqCst),
+            self.progress.queued.load(Ordering::SeqCst),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_text() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-fulltext-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let notes = dir.join("notes");
+        std::fs::write(&notes, "Phase noise of oscillators").unwrap();
+        let pdf = dir.join("paper.pdf");
+        std::fs::write(
+            &pdf,
+            "%PDF-1.4\nstream\nBT (Leeson's model) Tj ET\nendstream\n",
+        )
+        .unwrap();
+        let video = dir.join("lecture");
+        std::fs::write(&video, [0u8, 1, 2]).unwrap();
+        let untyped = dir.join("untyped");
+        std::fs::write(&untyped, "Flicker noise").unwrap();
+
+        assert!(
+            extract_text(&notes, Some("MD")).as_deref()
+                == Some("Phase noise of oscillators")
+        );
+        assert!(extract_text(&pdf, None).as_deref() == Some("Leeson's model"));
+        assert!(extract_text(&video, Some("mp4")).is_none());
+        assert!(extract_text(&video, None).is_none());
+        assert!(
+            extract_text(&untyped, None).as_deref() == Some("Flicker noise")
+        );
+
+        let text = text_path(&dir, "aa");
+        assert!(is_stale(&text, &notes));
+        std::fs::create_dir_all(dir.join(TEXT_DIR)).unwrap();
+        std::fs::write(&text, "").unwrap();
+        assert!(!is_stale(&text, &notes));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
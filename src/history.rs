@@ -0,0 +1,506 @@
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::merge::display_value;
+use crate::resource::Resource;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Fields left out of the changes of a resource, which change along
+/// with every other field.
+const STAMP_FIELDS: [&str; 1] = ["date_modified"];
+
+/// Catalog compared against in revisions that don't have one yet.
+const EMPTY_CATALOG: &str = "{\"document_types\": {}, \"content_types\": {}}";
+
+/// How a resource changed between two versions of a catalog.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Change of a resource between two versions of a catalog.
+#[derive(Debug, Clone)]
+pub struct ResourceChange {
+    pub kind: ChangeKind,
+    /// Initial checksum of the resource.
+    pub key: String,
+    pub title: String,
+    /// Fields of a modified resource, with their value before and after
+    /// the change. A missing field is null.
+    pub fields: Vec<(String, Value, Value)>,
+}
+
+/// Changes of the resources between two versions of a catalog, with the
+/// removed resources in the order of the old catalog and the others in
+/// the order of the new one.
+///
+/// # Arguments
+///
+/// * `before` - Older catalog.
+/// * `after` - Newer catalog.
+pub fn resource_changes(
+    before: &Catalog,
+    after: &Catalog,
+) -> Vec<ResourceChange> {
+    let find = |catalog: &'_ Catalog, key: &str| -> Option<Resource> {
+        catalog
+            .resources
+            .iter()
+            .find(|r| r.historical_checksums[0] == key)
+            .cloned()
+    };
+    let change = |kind, resource: &Resource, fields| ResourceChange {
+        kind,
+        key: resource.historical_checksums[0].clone(),
+        title: resource.title.clone(),
+        fields,
+    };
+
+    let mut changes = vec![];
+    for resource in &before.resources {
+        if find(after, &resource.historical_checksums[0]).is_none() {
+            changes.push(change(ChangeKind::Removed, resource, vec![]));
+        }
+    }
+    for resource in &after.resources {
+        let old = match find(before, &resource.historical_checksums[0]) {
+            Some(r) => r,
+            None => {
+                changes.push(change(ChangeKind::Added, resource, vec![]));
+                continue;
+            }
+        };
+        let (old, new) = (
+            serde_json::to_value(&old).unwrap(),
+            serde_json::to_value(resource).unwrap(),
+        );
+        let (old, new) = (old.as_object().unwrap(), new.as_object().unwrap());
+        let mut names: Vec<&String> = old.keys().collect();
+        names.extend(new.keys().filter(|k| !old.contains_key(*k)));
+        let fields: Vec<(String, Value, Value)> = names
+            .into_iter()
+            .filter(|n| !STAMP_FIELDS.contains(&n.as_str()))
+            .map(|n| {
+                let value =
+                    |o: &serde_json::Map<String, Value>| o.get(n).cloned();
+                (n.clone(), value(old), value(new))
+            })
+            .filter(|(_, a, b)| a != b)
+            .map(|(n, a, b)| {
+                (n, a.unwrap_or(Value::Null), b.unwrap_or(Value::Null))
+            })
+            .collect();
+        if !fields.is_empty() {
+            changes.push(change(ChangeKind::Modified, resource, fields));
+        }
+    }
+    changes
+}
+
+/// One-line summary of the changes of an operation, such as "catalog:
+/// added 3 resources".
+///
+/// # Arguments
+///
+/// * `operation` - Subcommand of the operation.
+/// * `changes` - Changes of the resources.
+pub fn summary(operation: &str, changes: &[ResourceChange]) -> String {
+    let count = |kind| changes.iter().filter(|c| c.kind == kind).count();
+    let parts: Vec<String> = [
+        ("added", count(ChangeKind::Added)),
+        ("removed", count(ChangeKind::Removed)),
+        ("modified", count(ChangeKind::Modified)),
+    ]
+    .iter()
+    .filter(|(_, n)| *n > 0)
+    .map(|(verb, n)| match n {
+        1 => format!("{} 1 resource", verb),
+        n => format!("{} {} resources", verb, n),
+    })
+    .collect();
+    match parts.is_empty() {
+        true => format!("{}: updated catalog settings", operation),
+        false => format!("{}: {}", operation, parts.join(", ")),
+    }
+}
+
+/// Line describing the change of a resource, e.g., "+ <key> <title>".
+fn change_line(change: &ResourceChange) -> String {
+    let sign = match change.kind {
+        ChangeKind::Added => '+',
+        ChangeKind::Removed => '-',
+        ChangeKind::Modified => '~',
+    };
+    format!("{} {} {}", sign, change.key, change.title)
+}
+
+/// Run git in the library directory.
+///
+/// # Returns
+///
+/// The standard output of git, or its standard error if it fails.
+fn git(library: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(library)
+        .args(args)
+        .output()
+        .map_err(|e| e.to_string())?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+/// Location of the stored catalog relative to the library directory,
+/// which git commands run in.
+fn relative_path(library: &Path, catalog_path: &Path) -> String {
+    catalog_path
+        .strip_prefix(library)
+        .expect("catalog is outside the library directory")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Contents of git blobs, read by a single `git cat-file` so that large
+/// split catalogs are read quickly.
+fn read_blobs(library: &Path, ids: &[&str]) -> Vec<String> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(library)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run git");
+    {
+        let mut stdin = child.stdin.take().unwrap();
+        for id in ids {
+            writeln!(stdin, "{}", id).expect("Failed to write to git");
+        }
+    }
+    let output = child.wait_with_output().expect("Failed to run git");
+    let mut blobs = vec![];
+    let mut rest = &output.stdout[..];
+    while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+        let header = String::from_utf8_lossy(&rest[..end]).into_owned();
+        let size: usize = header
+            .rsplit(' ')
+            .next()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| panic!("Unexpected git output: {}", header));
+        let contents = &rest[end + 1..end + 1 + size];
+        blobs.push(String::from_utf8_lossy(contents).into_owned());
+        rest = &rest[(end + 2 + size).min(rest.len())..];
+    }
+    blobs
+}
+
+/// Catalog as it was committed in a revision, or `None` if the revision
+/// doesn't have it or doesn't exist.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `path` - Location of the stored catalog relative to the library
+///   directory (see `relative_path`).
+/// * `revision` - Git revision, such as "HEAD~2".
+fn catalog_at(library: &Path, path: &str, revision: &str) -> Option<Catalog> {
+    let tree = git(library, &["ls-tree", "-r", revision, "--", path]).ok()?;
+    // Each line is "<mode> blob <id>\t<path>".
+    let entries: Vec<(&str, &str)> = tree
+        .lines()
+        .filter_map(|l| {
+            let (info, file) = l.split_once('\t')?;
+            Some((info.rsplit(' ').next()?, file))
+        })
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    let ids: Vec<&str> = entries.iter().map(|(id, _)| *id).collect();
+    let blobs = read_blobs(library, &ids);
+    fn parse<'a, T: Deserialize<'a>>(file: &str, contents: &'a str) -> T {
+        serde_json::from_str(contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", file, e))
+    }
+
+    if let [(_, file)] = entries[..] {
+        if file == path {
+            return Some(parse(file, &blobs[0]));
+        }
+    }
+    let settings = format!("{}/catalog.json", path);
+    let resources = format!("{}/resources/", path);
+    let mut catalog: Catalog = entries
+        .iter()
+        .zip(&blobs)
+        .find(|((_, file), _)| *file == settings)
+        .map(|((_, file), contents)| parse(file, contents))
+        .expect("Split catalog lacks its settings");
+    for ((_, file), contents) in entries.iter().zip(&blobs) {
+        if file.starts_with(&resources) && file.ends_with(".json") {
+            let resource: Resource = parse(file, contents);
+            catalog.resources.push(resource);
+        }
+    }
+    catalog.sort();
+    Some(catalog)
+}
+
+/// Commit the stored catalog to the library's git repository, which is
+/// created if needed, with a message describing how its resources
+/// changed since the last commit. Failures are only reported, since the
+/// operation itself succeeded.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog_path` - Location of the stored catalog.
+/// * `operation` - Subcommand of the operation.
+/// * `catalog` - Stored catalog.
+pub fn commit_catalog(
+    library: &Path,
+    catalog_path: &Path,
+    operation: &str,
+    catalog: &Catalog,
+) {
+    let path = relative_path(library, catalog_path);
+    let committed = catalog_at(library, &path, "HEAD")
+        .unwrap_or_else(|| serde_json::from_str(EMPTY_CATALOG).unwrap());
+    let changes = resource_changes(&committed, catalog);
+    let body: Vec<String> = changes.iter().map(change_line).collect();
+    let committed = git(library, &["rev-parse", "--is-inside-work-tree"])
+        .or_else(|_| git(library, &["init", "-q"]))
+        .and_then(|_| git(library, &["add", "-A", "--", &path]))
+        .and_then(|_| git(library, &["status", "--porcelain", "--", &path]))
+        .and_then(|status| match status.is_empty() {
+            true => Ok(String::new()),
+            false => git(
+                library,
+                &[
+                    "commit",
+                    "-q",
+                    "-m",
+                    &summary(operation, &changes),
+                    "-m",
+                    &body.join("\n"),
+                    "--",
+                    &path,
+                ],
+            ),
+        });
+    if let Err(e) = committed {
+        eprintln!(
+            "{}",
+            message("history-commit-failed", &[("error", e.into())])
+        );
+    }
+}
+
+/// Print the commits of the catalog, most recent first, each with the
+/// resources it added, removed or modified.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog_path` - Location of the stored catalog.
+/// * `count` - Number of commits shown.
+pub fn librarian_log(library: &Path, catalog_path: &Path, count: usize) {
+    let path = relative_path(library, catalog_path);
+    let log = git(
+        library,
+        &[
+            "log",
+            &format!("--max-count={}", count),
+            "--date=short",
+            "--format=%H%x09%ad%x09%s",
+            "--",
+            &path,
+        ],
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+    if log.is_empty() {
+        println!("{}", message("history-empty", &[]));
+        return;
+    }
+    for line in log.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (hash, date, subject) = (
+            fields.next().unwrap(),
+            fields.next().unwrap_or(""),
+            fields.next().unwrap_or(""),
+        );
+        println!("{} {} {}", &hash[..hash.len().min(8)], date, subject);
+        let at = |revision: &str| {
+            catalog_at(library, &path, revision)
+                .unwrap_or_else(|| serde_json::from_str(EMPTY_CATALOG).unwrap())
+        };
+        let (before, after) = (at(&format!("{}^", hash)), at(hash));
+        for change in resource_changes(&before, &after) {
+            println!("    {}", change_line(&change));
+        }
+    }
+}
+
+/// Catalog to compare, given either as a catalog file or split catalog
+/// directory, such as a backup, or as a git revision of the library's
+/// catalog.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `path` - Location of the stored catalog relative to the library
+///   directory.
+/// * `version` - Catalog location or git revision.
+fn load_version(library: &Path, path: &str, version: &str) -> Catalog {
+    let location = Path::new(version);
+    if location.exists() {
+        return CatalogStore::open(location).read();
+    }
+    catalog_at(library, path, version).unwrap_or_else(|| {
+        panic!(
+            "{}",
+            message("history-missing", &[("revision", version.into())])
+        )
+    })
+}
+
+/// Changes of the resources as a JSON array, with an object for each
+/// resource giving its kind of change ("added", "removed" or
+/// "modified"), checksum, title and, when it was modified, the old and
+/// new value of each changed field.
+pub fn changes_json(changes: &[ResourceChange]) -> Value {
+    changes
+        .iter()
+        .map(|c| {
+            let fields: serde_json::Map<String, Value> = c
+                .fields
+                .iter()
+                .map(|(f, old, new)| {
+                    (f.clone(), json!({"old": old, "new": new}))
+                })
+                .collect();
+            let kind = match c.kind {
+                ChangeKind::Added => "added",
+                ChangeKind::Removed => "removed",
+                ChangeKind::Modified => "modified",
+            };
+            json!({
+                "change": kind,
+                "checksum": c.key,
+                "title": c.title,
+                "fields": fields,
+            })
+        })
+        .collect()
+}
+
+/// Print how the resources of two versions of the catalog differ,
+/// field by field.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `catalog_path` - Location of the stored catalog.
+/// * `catalog` - Library catalog, which is the newer version when `new`
+///   is omitted.
+/// * `old` - Older version, as a catalog location or git revision (see
+///   `load_version`).
+/// * `new` - Newer version, if any.
+/// * `json` - Print the changes as JSON (see `changes_json`).
+pub fn librarian_diff(
+    library: &Path,
+    catalog_path: &Path,
+    catalog: &Catalog,
+    old: &str,
+    new: Option<&str>,
+    json: bool,
+) {
+    let path = relative_path(library, catalog_path);
+    let before = load_version(library, &path, old);
+    let changes = match new {
+        Some(new) => {
+            resource_changes(&before, &load_version(library, &path, new))
+        }
+        None => resource_changes(&before, catalog),
+    };
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&changes_json(&changes)).unwrap()
+        );
+        return;
+    }
+    if changes.is_empty() {
+        println!("{}", message("history-no-changes", &[]));
+    }
+    for change in &changes {
+        println!("{}", change_line(change));
+        for (field, old, new) in &change.fields {
+            let shown = |v: &Value| match v {
+                Value::Null => String::from("-"),
+                v => display_value(v),
+            };
+            println!("    {}: {} -> {}", field, shown(old), shown(new));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_changes() {
+        let catalog = |resources: &str| -> Catalog {
+            serde_json::from_str(&format!(
+                "{{\"document_types\": {{}}, \"content_types\": {{}}, \"resources\": [{}]}}",
+                resources
+            ))
+            .unwrap()
+        };
+        let before = catalog(
+            "{\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+             {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}",
+        );
+        let after = catalog(
+            "{\"title\": \"B\", \"date\": \"1998\", \"date_modified\": \"2024-03-01\",
+              \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+             {\"title\": \"C\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+             {\"title\": \"D\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]}",
+        );
+        let changes = resource_changes(&before, &after);
+        let kinds: Vec<(ChangeKind, &str)> =
+            changes.iter().map(|c| (c.kind, c.key.as_str())).collect();
+        assert!(
+            kinds
+                == vec![
+                    (ChangeKind::Removed, "aa"),
+                    (ChangeKind::Modified, "bb"),
+                    (ChangeKind::Added, "cc"),
+                    (ChangeKind::Added, "dd"),
+                ]
+        );
+        // The modification stamp isn't a change of its own.
+        assert!(
+            changes[1].fields.len() == 1 && changes[1].fields[0].0 == "date"
+        );
+        assert!(
+            summary("catalog", &changes)
+                == "catalog: added 2 resources, removed 1 resource, modified 1 resource"
+        );
+        assert!(
+            summary("tags apply", &[])
+                == "tags apply: updated catalog settings"
+        );
+    }
+}
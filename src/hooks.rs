@@ -0,0 +1,128 @@
+use crate::i18n::message;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Directory of the library holding the hooks, executable files named
+/// after the event they run on (e.g., `post-add-resource`).
+pub const HOOKS_DIR: &str = ".librarian/hooks";
+
+/// Events hooks can run on.
+///
+/// * `pre-catalog` - Before `librarian catalog` looks at the resources
+///   directory. A failing hook stops the catalog from being updated.
+/// * `post-catalog` - After `librarian catalog` updated the catalog.
+/// * `post-add-resource` - For every resource a command added to the
+///   catalog, once the command is done, with the resource as JSON on
+///   stdin.
+/// * `post-bibtex` - After `librarian bibtex` wrote its entries, with
+///   the BibTeX file in `LIBRARIAN_BIBTEX` if one was written.
+pub const HOOK_EVENTS: [&str; 4] = [
+    "pre-catalog",
+    "post-catalog",
+    "post-add-resource",
+    "post-bibtex",
+];
+
+/// Run the hook of an event, if the library has one.
+///
+/// Hooks run in the library directory, with the library directory in
+/// `LIBRARIAN_LIBRARY` and the event in `LIBRARIAN_EVENT`. Their output
+/// goes to the terminal.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `event` - One of `HOOK_EVENTS`.
+/// * `env` - Additional environment variables.
+/// * `input` - What is written to the hook's stdin.
+///
+/// # Returns
+///
+/// Whether the hook succeeded, which it does when there is none.
+/// Failures are reported.
+pub fn run_hook(
+    library: &Path,
+    event: &str,
+    env: &[(&str, &str)],
+    input: Option<&str>,
+) -> bool {
+    debug_assert!(HOOK_EVENTS.contains(&event));
+    let path = library.join(HOOKS_DIR).join(event);
+    if !path.is_file() {
+        return true;
+    }
+    let report = |error: String| {
+        eprintln!(
+            "{}",
+            message(
+                "hook-failed",
+                &[("event", event.into()), ("error", error.into())]
+            )
+        );
+        false
+    };
+    let child = Command::new(&path)
+        .current_dir(library)
+        .env("LIBRARIAN_LIBRARY", library)
+        .env("LIBRARIAN_EVENT", event)
+        .envs(env.iter().copied())
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => return report(e.to_string()),
+    };
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // A hook that doesn't read its input closes the pipe early,
+        // which isn't a failure.
+        stdin.write_all(input.as_bytes()).ok();
+    }
+    match child.wait() {
+        Ok(status) if status.success() => true,
+        Ok(status) => report(status.to_string()),
+        Err(e) => report(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let library = std::env::temp_dir()
+            .join(format!("librarian-hooks-{}", std::process::id()));
+        let hooks = library.join(HOOKS_DIR);
+        std::fs::create_dir_all(&hooks).unwrap();
+        let hook = |event: &str, script: &str| {
+            let path = hooks.join(event);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            std::fs::set_permissions(
+                &path,
+                std::fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+        };
+        hook("post-add-resource", "cat > \"$LIBRARIAN_EVENT.out\"");
+        hook("pre-catalog", "exit 1");
+
+        // Events without a hook succeed.
+        assert!(run_hook(&library, "post-catalog", &[], None));
+        assert!(!run_hook(&library, "pre-catalog", &[], None));
+        assert!(run_hook(&library, "post-add-resource", &[], Some("{}")));
+        assert!(
+            std::fs::read_to_string(library.join("post-add-resource.out"))
+                .unwrap()
+                == "{}"
+        );
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
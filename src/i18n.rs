@@ -0,0 +1,106 @@
+use fluent::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+/// Translations of the user-facing messages, as Fluent resources keyed
+/// by language. English is the fallback for languages and messages
+/// without a translation.
+const LOCALES: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// Language of the user's locale, from the usual environment variables
+/// (e.g., "de" for "de_DE.UTF-8").
+fn user_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|v| !v.is_empty())
+        .and_then(|v| {
+            let tag = v.split('.').next().unwrap().replace('_', "-");
+            tag.parse::<LanguageIdentifier>().ok()
+        })
+        .map(|l| l.language.as_str().to_string())
+}
+
+fn bundle(language: &str) -> Option<FluentBundle<FluentResource>> {
+    let (_, source) = LOCALES.iter().find(|(l, _)| *l == language)?;
+    let resource = FluentResource::try_new(source.to_string())
+        .unwrap_or_else(|_| panic!("Invalid translations for {}", language));
+    let mut bundle =
+        FluentBundle::new(vec![language.parse().expect("Invalid language")]);
+    // Isolation marks around arguments are meant for bidirectional text
+    // and show up as garbage in many terminals.
+    bundle.set_use_isolating(false);
+    bundle.add_resource(resource).unwrap();
+    Some(bundle)
+}
+
+thread_local! {
+    /// Bundle of the user's language, followed by the English fallback.
+    static BUNDLES: Vec<FluentBundle<FluentResource>> = user_language()
+        .filter(|l| l != "en")
+        .and_then(|l| bundle(&l))
+        .into_iter()
+        .chain(bundle("en"))
+        .collect();
+}
+
+fn format(
+    bundles: &[FluentBundle<FluentResource>],
+    id: &str,
+    args: &[(&str, FluentValue)],
+) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    for bundle in bundles {
+        if let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) {
+            let mut errors = vec![];
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .to_string();
+        }
+    }
+    panic!("No message {}", id);
+}
+
+/// User-facing message in the user's language.
+///
+/// # Arguments
+///
+/// * `id` - Identifier of the message in `locales/*.ftl`.
+/// * `args` - Values of the message's variables, e.g., `("count",
+///   3.into())`. Numbers should be passed as numbers so that plurals
+///   are selected correctly.
+pub fn message(id: &str, args: &[(&str, FluentValue)]) -> String {
+    BUNDLES.with(|b| format(b, id, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let en = vec![bundle("en").unwrap()];
+        let de = vec![bundle("de").unwrap(), bundle("en").unwrap()];
+        let args = [("count", 1.into()), ("query", "noise".into())];
+        assert!(format(&en, "edit-done", &args) == "Edited 1 resource.");
+        assert!(format(&de, "edit-done", &args) == "1 Ressource bearbeitet.");
+        // Every English message must have a translation in every language.
+        let ids: Vec<String> = LOCALES[0]
+            .1
+            .lines()
+            .filter_map(|l| l.split_once(" =").map(|(id, _)| id.to_string()))
+            .filter(|id| !id.starts_with(' ') && !id.starts_with('#'))
+            .collect();
+        for (language, _) in LOCALES.iter() {
+            let bundle = bundle(language).unwrap();
+            for id in &ids {
+                assert!(bundle.has_message(id), "{} lacks {}", language, id);
+            }
+        }
+    }
+}
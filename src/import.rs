@@ -0,0 +1,528 @@
+use crate::bibtex::{latex_unescape, parse_bibtex, BibtexEntry, BibtexType};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::metadata::Metadata;
+use crate::resource::{DateTime, Name, Resource};
+use crate::storage::Storage;
+
+use regex::Regex;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Split a BibTeX name list on "and", except within braces.
+fn split_names(names: &str) -> Vec<String> {
+    let mut split = Vec::<String>::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for word in names.split_whitespace() {
+        if depth == 0 && word.eq_ignore_ascii_case("and") {
+            split.push(current.trim().to_string());
+            current.clear();
+            continue;
+        }
+        depth += word.matches('{').count() as i32;
+        depth -= word.matches('}').count() as i32;
+        current.push_str(word);
+        current.push(' ');
+    }
+    split.push(current.trim().to_string());
+    split.into_iter().filter(|n| !n.is_empty()).collect()
+}
+
+/// Convert a BibTeX name, written either as "First von Last" or as
+/// "von Last, First" (or "von Last, Jr, First"), to a `Name`. A name
+/// entirely in braces (e.g., "{Jet Propulsion Laboratory}") is kept
+/// verbatim. Names with LaTeX commands that can't be converted to text
+/// are skipped with a warning.
+fn bibtex_name(name: &str) -> Option<Name> {
+    let name = name.trim();
+    let mut depth = 0;
+    let braced = name.starts_with('{')
+        && name.char_indices().all(|(i, c)| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => (),
+            }
+            depth > 0 || i == name.len() - 1
+        });
+    let unescaped = latex_unescape(name);
+    let command = Regex::new(r"\\[A-Za-z]").unwrap();
+    if command.is_match(&unescaped) {
+        eprintln!("{}", message("import-latex-name", &[("name", name.into())]));
+        return None;
+    }
+    match braced {
+        true => Name::try_from(format!("{{{}}}", unescaped.trim()).as_str()),
+        false => Name::try_from(unescaped.as_str()),
+    }
+    .ok()
+}
+
+/// Month number of a BibTeX month field, which is either a number or
+/// an (abbreviated) English month name.
+pub(crate) fn bibtex_month(month: &str) -> Option<u32> {
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct",
+        "nov", "dec",
+    ];
+    match month.trim().parse::<u32>() {
+        Ok(m) => Some(m),
+        Err(_) => months
+            .iter()
+            .position(|m| month.trim().to_lowercase().starts_with(m))
+            .map(|m| m as u32 + 1),
+    }
+}
+
+/// Identifier such as an ISBN, or `None` with a warning when it isn't
+/// valid.
+fn identifier<'a, T>(value: &'a str) -> Option<T>
+where
+    T: TryFrom<&'a str>,
+    T::Error: std::fmt::Display,
+{
+    T::try_from(value)
+        .map_err(|e| {
+            eprintln!(
+                "{}",
+                message(
+                    "import-invalid-identifier",
+                    &[("error", e.to_string().into())]
+                )
+            )
+        })
+        .ok()
+}
+
+/// Metadata described by the fields of a BibTeX entry.
+fn entry_metadata(entry: &BibtexEntry) -> Metadata {
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| entry.fields.get(*n))
+            .map(|v| latex_unescape(v))
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut metadata = Metadata::new();
+    metadata.title = field(&["title"]);
+    metadata.subtitle = field(&["subtitle"]);
+    metadata.author = entry.fields.get("author").map(|a| {
+        split_names(a)
+            .iter()
+            .filter_map(|n| bibtex_name(n))
+            .collect()
+    });
+    let date = match (field(&["date"]), field(&["year"])) {
+        (Some(d), _) => Some(d),
+        (None, Some(y)) => {
+            match field(&["month"]).and_then(|m| bibtex_month(&m)) {
+                Some(m) => Some(format!("{}-{:02}", y, m)),
+                None => Some(y),
+            }
+        }
+        (None, None) => None,
+    };
+    metadata.date = date.and_then(|d| DateTime::try_from(d.as_str()).ok());
+    metadata.publisher = field(&["publisher"]);
+    metadata.journal = field(&["journaltitle", "journal"]);
+    metadata.volume = field(&["volume"]);
+    metadata.number = field(&["number"]);
+    metadata.doi = field(&["doi"]).and_then(|d| identifier(&d));
+    metadata.isbn = field(&["isbn"]).and_then(|i| identifier(&i));
+    metadata.issn = field(&["issn"]).and_then(|i| identifier(&i));
+    metadata.eprint = field(&["eprint"]);
+    metadata.eprinttype =
+        field(&["eprinttype", "archiveprefix"]).map(|t| t.to_lowercase());
+    metadata.eprintclass = field(&["eprintclass", "primaryclass"]);
+    metadata.r#abstract = field(&["abstract"]);
+    metadata
+}
+
+/// Overwrite the fields of `resource` with the fields of a BibTeX
+/// entry.
+///
+/// # Arguments
+///
+/// * `entry` - BibTeX entry.
+/// * `resource` - Resource to update.
+/// * `catalog` - Catalog, whose content types are used to find the
+///   resource's content type from the entry type.
+fn apply_entry(
+    entry: &BibtexEntry,
+    resource: &mut Resource,
+    catalog: &Catalog,
+) {
+    entry_metadata(entry).apply(resource);
+    let field = |name: &str| {
+        entry
+            .fields
+            .get(name)
+            .map(|v| latex_unescape(v))
+            .filter(|v| !v.is_empty())
+    };
+    if let Some(e) = entry.fields.get("editor") {
+        resource.editor = Some(
+            split_names(e)
+                .iter()
+                .filter_map(|n| bibtex_name(n))
+                .collect(),
+        );
+    }
+    if let Some(x) = field("edition") {
+        resource.edition = Some(x);
+    }
+    if let Some(x) = field("version") {
+        resource.version = Some(x);
+    }
+    if let Some(x) = field("series") {
+        resource.series = Some(x);
+    }
+    // Page ranges are written with a hyphen in the catalog.
+    if let Some(x) = field("pages") {
+        resource.pages = Some(x.replace("--", "-").replace('–', "-"));
+    }
+    if let Some(x) = field("chapter") {
+        resource.chapter = Some(x);
+    }
+    if let Some(x) = field("language") {
+        resource.language = Some(x);
+    }
+    if let Some(x) = field("organization")
+        .or_else(|| field("institution"))
+        .or_else(|| field("school"))
+    {
+        resource.organization = Some(x);
+    }
+    if let Some(x) = field("url").and_then(|u| Url::parse(&u).ok()) {
+        resource.url = Some(x);
+    }
+    if let Some(k) = field("keywords") {
+        resource.tags = Some(
+            k.split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        );
+    }
+
+    // BibTeX's "misc" is spelled out in `BibtexType`.
+    let entry_type = match entry.entry_type.as_str() {
+        "misc" => "miscellaneous",
+        t => t,
+    };
+    if let Ok(bt) = serde_json::from_value::<BibtexType>(
+        serde_json::Value::String(entry_type.to_string()),
+    ) {
+        if let Some((content, _)) = catalog
+            .content_types
+            .iter()
+            .find(|(_, t)| *t.bibtex_type() == bt)
+        {
+            resource.content = Some(content.clone());
+        }
+    }
+}
+
+/// Path of the first file listed in the entry's `file` field.
+///
+/// Both plain paths and JabRef's "description:path:type" format are
+/// understood. Relative paths are relative to the BibTeX file.
+fn entry_file(entry: &BibtexEntry, bibtex_dir: &Path) -> Option<PathBuf> {
+    let file = entry.fields.get("file")?.split(';').next()?.trim();
+    // JabRef escapes the colons of paths (e.g., "C\\:/papers/x.pdf").
+    let mut parts = vec![String::new()];
+    let mut chars = file.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&':') => {
+                parts.last_mut().unwrap().push(chars.next().unwrap())
+            }
+            ':' => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+    let file = match parts.len() {
+        3 => parts.swap_remove(1),
+        _ => file.replace("\\:", ":"),
+    };
+    let path = bibtex_dir.join(file);
+    match path.is_file() {
+        true => Some(path),
+        false => None,
+    }
+}
+
+/// Key of an entry along with its title, or only the title of entries
+/// without a key, such as rows of a CSV file.
+fn entry_label(entry: &BibtexEntry) -> String {
+    let title = entry.fields.get("title").map(|t| t.as_str()).unwrap_or("");
+    match entry.key.is_empty() {
+        true => title.to_string(),
+        false => format!("{} ({})", entry.key, title),
+    }
+}
+
+/// Prompt for the file of a BibTeX entry that couldn't be linked
+/// automatically.
+fn ask_entry_file(entry: &BibtexEntry) -> Option<PathBuf> {
+    loop {
+        let response = ask(&message(
+            "import-file-prompt",
+            &[("entry", entry_label(entry).into())],
+        ))
+        .unwrap_or_default();
+        if response.is_empty() {
+            return None;
+        }
+        let path = PathBuf::from(&response);
+        match path.is_file() {
+            true => return Some(path),
+            false => println!(
+                "{}",
+                message("import-not-file", &[("path", response.into())])
+            ),
+        }
+    }
+}
+
+/// Create or update catalog resources from BibTeX entries.
+///
+/// An entry updates an existing resource when its key is the resource's
+/// BibTeX key or when its file is already cataloged. Otherwise, its
+/// file is copied into the resources directory as a new resource.
+/// Entries without a file are skipped, since every resource must be
+/// backed by a file.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `entries` - Entries to import.
+/// * `base_dir` - Directory that relative file paths of the entries
+///   are relative to.
+/// * `interactive` - Prompt for the file of entries whose `file` field
+///   is missing or doesn't point to a file.
+pub(crate) fn import_entries(
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    entries: Vec<BibtexEntry>,
+    base_dir: &Path,
+    interactive: bool,
+) {
+    let (mut created, mut updated, mut skipped) = (0, 0, 0);
+    for entry in entries {
+        let existing = catalog
+            .resources
+            .iter()
+            .position(|r| r.bibtex_key() == entry.key);
+        if let Some(i) = existing {
+            let mut resource = catalog.resources[i].clone();
+            apply_entry(&entry, &mut resource, catalog);
+            catalog.resources[i] = resource;
+            updated += 1;
+            continue;
+        }
+
+        let file = match (entry_file(&entry, base_dir), interactive) {
+            (Some(f), _) => Some(f),
+            (None, true) => ask_entry_file(&entry),
+            (None, false) => None,
+        };
+        let file = match file {
+            Some(f) => f,
+            None => {
+                eprintln!(
+                    "{}",
+                    message(
+                        "import-no-file",
+                        &[("entry", entry_label(&entry).into())]
+                    )
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let checksum = file_checksum(&file);
+        let mut resource = match catalog.resource_mut(&checksum) {
+            Some(r) => {
+                updated += 1;
+                r.clone()
+            }
+            None => {
+                let path = Storage::of(resources_path, catalog)
+                    .resource_path(&checksum);
+                if !catalog.dry_run {
+                    std::fs::create_dir_all(path.parent().unwrap())
+                        .expect("Failed to create resources directory");
+                    std::fs::copy(&file, path).expect(
+                        "Failed to copy file into the resources directory",
+                    );
+                }
+                created += 1;
+                let resource = Resource::new(
+                    file.file_stem().unwrap().to_string_lossy().to_string(),
+                    catalog.document_type(&file),
+                    checksum.clone(),
+                );
+                catalog.resources.push(resource.clone());
+                resource
+            }
+        };
+        apply_entry(&entry, &mut resource, catalog);
+        // Keep the entry's key so that documents citing it don't need
+        // to change.
+        if resource.citekey.is_none()
+            && !entry.key.is_empty()
+            && entry.key != checksum
+        {
+            resource.citekey = Some(entry.key.clone());
+        }
+        *catalog.resource_mut(&checksum).unwrap() = resource;
+    }
+    catalog.assign_citekeys();
+
+    println!(
+        "{}",
+        message(
+            "import-summary",
+            &[
+                ("created", created.into()),
+                ("updated", updated.into()),
+                ("skipped", skipped.into())
+            ]
+        )
+    );
+}
+
+/// Create or update catalog resources from the entries of a BibTeX
+/// file. See `import_entries`.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `bibtex_file_path` - BibTeX file to import.
+/// * `interactive` - Prompt for the file of entries whose `file` field
+///   is missing or doesn't point to a file.
+pub fn librarian_import_bibtex(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    bibtex_file_path: &Path,
+    interactive: bool,
+) {
+    let contents = std::fs::read_to_string(bibtex_file_path)
+        .expect("Failed to read BibTeX file");
+    let entries = parse_bibtex(&contents).unwrap_or_else(|e| {
+        panic!("Failed to parse {}: {}", bibtex_file_path.display(), e)
+    });
+    import_entries(
+        catalog,
+        resources_path,
+        entries,
+        bibtex_file_path.parent().unwrap_or(Path::new(".")),
+        interactive,
+    );
+    catalog.sort();
+    catalog_store.write(catalog);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_metadata() {
+        let entries = parse_bibtex(
+            r#"@article{epr,
+              author = {Einstein, A. and Podolsky, B. and Nathan {Rosen}},
+              title = "Can Quantum-Mechanical Description of Physical Reality Be Considered Complete?",
+              journal = {Phys. Rev.},
+              year = 1935, month = may,
+              volume = {47},
+              doi = {10.1103/PhysRev.47.777},
+            }"#,
+        )
+        .unwrap();
+        let metadata = entry_metadata(&entries[0]);
+        assert!(
+            metadata.author
+                == Some(vec![
+                    Name::try_from("A. Einstein").unwrap(),
+                    Name::try_from("B. Podolsky").unwrap(),
+                    Name::try_from("Nathan Rosen").unwrap(),
+                ])
+        );
+        assert!(metadata.journal.as_deref() == Some("Phys. Rev."));
+        assert!(metadata.date.map(String::from).as_deref() == Some("1935-05"));
+        assert!(metadata.doi.unwrap().as_str() == "10.1103/PhysRev.47.777");
+    }
+
+    #[test]
+    fn test_entry_file() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-entry-file-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("C:/papers")).unwrap();
+        std::fs::write(dir.join("C:/papers/x.pdf"), "x").unwrap();
+        let entries = parse_bibtex(
+            r#"@article{a, file = {:C\:/papers/x.pdf:PDF}}
+               @article{b, file = {C\:/papers/x.pdf}}"#,
+        )
+        .unwrap();
+        for entry in &entries {
+            assert!(
+                entry_file(entry, &dir) == Some(dir.join("C:/papers/x.pdf"))
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_reread() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("paper.pdf"), "paper").unwrap();
+        let entries = parse_bibtex(
+            r#"@article{paper,
+              author = {Foo \emph{Bar} and Paul Erd{\H{o}}s and \foo{Pat} Smith and Pat O"Brien},
+              title = {A "quoted" \textbackslash{} title},
+              file = {paper.pdf},
+            }"#,
+        )
+        .unwrap();
+        let mut store = CatalogStore::open(&dir.join("catalog.json"));
+        let mut catalog = store.read();
+        import_entries(
+            &mut catalog,
+            &dir.join("resources"),
+            entries,
+            &dir,
+            false,
+        );
+        store.write(&mut catalog);
+        // The catalog can still be read.
+        let catalog = CatalogStore::open(&dir.join("catalog.json")).read();
+        let resource = &catalog.resources[0];
+        let authors = resource.author.as_ref().unwrap();
+        assert!(authors.len() == 2);
+        assert!(authors[0].last.as_deref() == Some("Erdős"));
+        assert!(authors[1].last.as_deref() == Some("O\"Brien"));
+        assert!(resource.title.contains('\\'));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_names() {
+        assert!(
+            split_names("{Barnes and Noble} and John Doe")
+                == vec!["{Barnes and Noble}", "John Doe"]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::edit::BulkEdit;
+use crate::i18n::message;
+use crate::metadata::{fetch_arxiv, fetch_crossref};
+use crate::pdf::pdf_doi;
+use crate::resource::{Doi, Resource};
+use crate::storage::Storage;
+
+use std::convert::TryFrom;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+
+/// Directory of the library where new files are dropped to be reviewed
+/// before they're added to the resources directory.
+pub const INBOX_DIR: &str = "inbox";
+
+/// Catalog the new files of an inbox as pending resources. Each file is
+/// renamed to its checksum and stays in the inbox until its resource is
+/// reviewed. Files with the content of a cataloged or pending resource
+/// are removed.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `inbox` - Inbox directory, which may not exist.
+///
+/// # Returns
+///
+/// The number of staged files.
+pub fn stage_inbox(catalog: &mut Catalog, inbox: &Path) -> usize {
+    let mut paths = match std::fs::read_dir(inbox) {
+        Ok(entries) => entries
+            .map(|e| e.expect("Failed to read inbox").path())
+            .collect::<Vec<_>>(),
+        Err(_) => return 0,
+    };
+    paths.sort();
+    let patterns = catalog.filename_regexes();
+    let mut staged = 0;
+    for path in paths {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        if name.starts_with('.')
+            || catalog.pending.iter().any(|r| r.checksum == name)
+        {
+            continue;
+        }
+        let checksum = file_checksum(&path);
+        let existing = catalog
+            .resource_mut(&checksum)
+            .map(|r| r.historical_checksums[0].clone())
+            .or_else(|| {
+                catalog
+                    .pending
+                    .iter()
+                    .find(|r| r.checksum == checksum)
+                    .map(|r| r.checksum.clone())
+            });
+        if let Some(key) = existing {
+            println!(
+                "{}",
+                message(
+                    "inbox-duplicate",
+                    &[("name", name.into()), ("key", key.into())]
+                )
+            );
+            if !catalog.dry_run {
+                match path.is_dir() {
+                    true => std::fs::remove_dir_all(&path),
+                    false => std::fs::remove_file(&path),
+                }
+                .expect("Failed to remove duplicate from inbox");
+            }
+            continue;
+        }
+        let resource = catalog.new_resource(
+            &path,
+            &path,
+            checksum.clone(),
+            &patterns,
+            false,
+            false,
+        );
+        if !catalog.dry_run {
+            std::fs::rename(&path, inbox.join(&checksum))
+                .expect("Failed to rename file in inbox");
+        }
+        catalog.pending.push(resource);
+        staged += 1;
+    }
+    staged
+}
+
+/// Print a prompt and read the response, unless the input ended or
+/// can't be read.
+pub(crate) fn ask(prompt: &str) -> Option<String> {
+    let mut response = String::new();
+    print!("{} ", prompt);
+    stdout().flush().expect("Failed to flush output stream.");
+    match stdin().read_line(&mut response) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(response.trim().to_string()),
+    }
+}
+
+/// Fill a pending resource with the metadata of its DOI or arXiv
+/// identifier. A PDF without either is searched for a DOI first.
+///
+/// # Arguments
+///
+/// * `resource` - Pending resource.
+/// * `path` - Location of its file in the inbox.
+fn fetch_pending(resource: &mut Resource, path: &Path) {
+    if resource.doi.is_none() {
+        resource.doi =
+            pdf_doi(path).and_then(|d| Doi::try_from(d.as_str()).ok());
+    }
+    let metadata = match (&resource.doi, resource.eprinttype.as_deref()) {
+        (Some(d), _) => fetch_crossref(d),
+        (None, Some("arxiv")) => fetch_arxiv(resource.eprint.as_ref().unwrap()),
+        _ => {
+            println!("{}", message("review-no-identifier", &[]));
+            return;
+        }
+    };
+    match metadata {
+        Ok(m) => m.apply(resource),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Walk through the pending resources of the inbox one at a time. The
+/// user can fetch the metadata of each, edit its fields and tags, and
+/// then accept it, which moves its file into the resources directory
+/// and adds it to the catalog, or delete it. New files in the inbox are
+/// staged first.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+pub fn librarian_review(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let inbox = library.join(INBOX_DIR);
+    if stage_inbox(catalog, &inbox) > 0 {
+        store.write(catalog);
+    }
+    if catalog.pending.is_empty() {
+        println!("{}", message("review-empty", &[]));
+        return;
+    }
+
+    let storage = Storage::of(resources_path, catalog);
+    let mut i = 0;
+    while i < catalog.pending.len() {
+        let checksum = catalog.pending[i].checksum.clone();
+        let path = inbox.join(&checksum);
+        println!(
+            "\n{}\n{}",
+            message(
+                "review-header",
+                &[
+                    ("index", (i + 1).into()),
+                    ("count", catalog.pending.len().into())
+                ]
+            ),
+            serde_json::to_string_pretty(&catalog.pending[i]).unwrap()
+        );
+        let choice = match ask(&message("review-prompt", &[])) {
+            Some(c) => c,
+            None => break,
+        };
+        match choice.as_str() {
+            "a" => {
+                let resource = catalog.pending.remove(i);
+                storage.store(&path, &checksum);
+                let title = resource.title.clone();
+                catalog.resources.push(resource);
+                catalog.sort();
+                catalog.assign_citekeys();
+                store.write(catalog);
+                if !catalog.dry_run {
+                    record(
+                        library,
+                        &AuditEntry::new(
+                            "review",
+                            vec![checksum.clone()],
+                            vec![format!(
+                                "{} accepted from the inbox",
+                                checksum
+                            )],
+                        ),
+                    );
+                }
+                println!(
+                    "{}",
+                    message("review-accepted", &[("title", title.into())])
+                );
+            }
+            "f" => {
+                fetch_pending(&mut catalog.pending[i], &path);
+                store.write(catalog);
+            }
+            "e" => {
+                let response =
+                    ask(&message("review-edit", &[])).unwrap_or_default();
+                let edit = match response.split_once('=') {
+                    Some((field, value)) => BulkEdit {
+                        set: vec![(
+                            field.trim().to_string(),
+                            value.trim().to_string(),
+                        )],
+                        ..Default::default()
+                    },
+                    None => continue,
+                };
+                match edit.apply(&catalog.pending[i]) {
+                    Ok(r) => {
+                        catalog.pending[i] = r;
+                        store.write(catalog);
+                    }
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            "t" => {
+                let tags: Vec<String> = ask(&message("review-tags", &[]))
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect();
+                if let Err(e) = catalog.check_tags(&tags) {
+                    eprintln!("{}", e);
+                    continue;
+                }
+                let edit = BulkEdit {
+                    add_tags: tags,
+                    ..Default::default()
+                };
+                catalog.pending[i] = edit.apply(&catalog.pending[i]).unwrap();
+                store.write(catalog);
+            }
+            "d" => {
+                let resource = catalog.pending.remove(i);
+                if !catalog.dry_run {
+                    match path.is_dir() {
+                        true => std::fs::remove_dir_all(&path),
+                        false => std::fs::remove_file(&path),
+                    }
+                    .expect("Failed to remove file from inbox");
+                }
+                store.write(catalog);
+                println!(
+                    "{}",
+                    message(
+                        "review-deleted",
+                        &[("title", resource.title.into())]
+                    )
+                );
+            }
+            "s" => i += 1,
+            "q" => break,
+            _ => println!("{}", message("review-invalid", &[])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_inbox() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-inbox-{}", std::process::id()));
+        let inbox = library.join(INBOX_DIR);
+        std::fs::create_dir_all(&inbox).unwrap();
+        std::fs::write(inbox.join("Phase Noise.pdf"), "phase noise").unwrap();
+        std::fs::write(inbox.join("copy.pdf"), "cataloged").unwrap();
+        let cataloged = file_checksum(&inbox.join("copy.pdf"));
+        let mut catalog: Catalog = serde_json::from_str(&format!(
+            "{{\"document_types\": {{\"pdf\": {{\"extension\": \"pdf\"}}}}, \"content_types\": {{}}, \"resources\": [
+              {{\"title\": \"A\", \"checksum\": \"{0}\", \"historical_checksums\": [\"{0}\"]}}
+            ]}}",
+            cataloged
+        ))
+        .unwrap();
+
+        assert!(stage_inbox(&mut catalog, &inbox) == 1);
+        assert!(catalog.resources.len() == 1);
+        let pending = &catalog.pending[0];
+        assert!(pending.title == "Phase Noise");
+        assert!(pending.document.as_deref() == Some("pdf"));
+        // The duplicate was removed and the staged file renamed.
+        let names: Vec<_> = std::fs::read_dir(&inbox)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert!(names == vec![pending.checksum.as_str()]);
+        // Staged files aren't staged again.
+        assert!(stage_inbox(&mut catalog, &inbox) == 0);
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
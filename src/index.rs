@@ -0,0 +1,220 @@
+use crate::catalog::Catalog;
+use crate::resource::{NameFormat, Resource};
+use crate::search::{search, ResourceFilter};
+use crate::storage::Storage;
+
+use std::path::Path;
+
+/// Format of a generated index of the library.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexFormat {
+    /// An org-mode table.
+    Org,
+    /// A Markdown list.
+    Markdown,
+}
+
+/// Authors of a resource, separated by semicolons since names can
+/// contain commas.
+fn authors(resource: &Resource, name_format: NameFormat) -> String {
+    resource
+        .author
+        .iter()
+        .flatten()
+        .map(|n| n.format(name_format))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Tags of a resource, separated by commas.
+fn tags(resource: &Resource) -> String {
+    resource
+        .tags
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Escape text for use in an org-mode table cell or link description.
+fn org_escape(text: &str) -> String {
+    text.replace('|', "\\vert{}")
+        .replace('[', "{")
+        .replace(']', "}")
+}
+
+/// Escape text for use in Markdown.
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\`*_[]<>#|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Row of an org-mode table describing a resource.
+fn org_row(
+    resource: &Resource,
+    path: &Path,
+    name_format: NameFormat,
+) -> String {
+    let mut row = format!(
+        "| [[file:{}][{}]] | {} | {} |",
+        path.display(),
+        org_escape(&resource.title),
+        org_escape(&authors(resource, name_format)),
+        org_escape(&tags(resource)),
+    );
+    if let Some(u) = &resource.url {
+        row += &format!(" [[{}][link]] |", u);
+    } else {
+        row += " |";
+    }
+    row + "\n"
+}
+
+/// Item of a Markdown list describing a resource.
+fn markdown_item(
+    resource: &Resource,
+    path: &Path,
+    name_format: NameFormat,
+) -> String {
+    let mut item = format!(
+        "- [{}](<{}>)",
+        markdown_escape(&resource.title),
+        path.display()
+    );
+    let authors = authors(resource, name_format);
+    if !authors.is_empty() {
+        item += &format!(", {}", markdown_escape(&authors));
+    }
+    let tags = tags(resource);
+    if !tags.is_empty() {
+        item += &format!(" (tags: {})", markdown_escape(&tags));
+    }
+    if let Some(u) = &resource.url {
+        item += &format!(" [link](<{}>)", u);
+    }
+    item + "\n"
+}
+
+/// Render resources as an index in the given format.
+fn render(
+    resources: &[&Resource],
+    storage: &Storage,
+    format: IndexFormat,
+    name_format: NameFormat,
+) -> String {
+    let mut index = String::new();
+    if format == IndexFormat::Org {
+        index += "| Title | Authors | Tags | URL |\n|-\n";
+    }
+    for resource in resources {
+        let path = storage.resource_path(&resource.historical_checksums[0]);
+        index += &match format {
+            IndexFormat::Org => org_row(resource, &path, name_format),
+            IndexFormat::Markdown => {
+                markdown_item(resource, &path, name_format)
+            }
+        };
+    }
+    index
+}
+
+/// Generate an index of the library, listing the title, authors and
+/// tags of each resource along with a link to its file.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `file` - File where the index should be written. If no file is
+///   given, the index will be written to stdout.
+/// * `format` - Format of the index.
+/// * `query` - Only include resources matching this search query. If
+///   omitted, every resource is included in catalog order.
+/// * `collection` - Only include the resources of this collection, in
+///   collection order.
+/// * `include_private` - Include private resources.
+/// * `name_format` - Style of author names. The catalog's name format is
+///   used if this is omitted.
+#[allow(clippy::too_many_arguments)]
+pub fn librarian_export_index(
+    catalog: &Catalog,
+    resources_path: &Path,
+    file: Option<&str>,
+    format: IndexFormat,
+    query: Option<&str>,
+    collection: Option<&str>,
+    include_private: bool,
+    name_format: Option<NameFormat>,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let resources: Vec<&Resource> = match (query, collection) {
+        (Some(q), None) => search(catalog, q)
+            .into_iter()
+            .filter(|r| include_private || !r.private)
+            .collect(),
+        _ => ResourceFilter {
+            query: query.map(String::from),
+            collection: collection.map(String::from),
+            ..Default::default()
+        }
+        .apply(catalog, include_private),
+    };
+    let index = render(
+        &resources,
+        &storage,
+        format,
+        name_format.unwrap_or(catalog.name_format),
+    );
+
+    match file {
+        Some(f) => std::fs::write(f, index).expect("Failed to write index"),
+        None => print!("{}", index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageLayout;
+
+    #[test]
+    fn test_render() {
+        let resource: Resource = serde_json::from_str(
+            "{\"title\": \"Signals [and] Systems\", \"author\": [\"Alan V. Oppenheim\"], \"tags\": [\"dsp\"], \"checksum\": \"ab\", \"historical_checksums\": [\"ab\"]}",
+        )
+        .unwrap();
+        let storage = Storage::new(Path::new("/lib"), StorageLayout::Flat);
+        let path = Path::new("/lib").join("ab");
+
+        assert!(
+            render(
+                &[&resource],
+                &storage,
+                IndexFormat::Org,
+                NameFormat::FirstLast
+            )
+                == format!(
+                    "| Title | Authors | Tags | URL |\n|-\n| [[file:{}][Signals {{and}} Systems]] | Alan V. Oppenheim | dsp | |\n",
+                    path.display()
+                )
+        );
+        assert!(
+            render(
+                &[&resource],
+                &storage,
+                IndexFormat::Markdown,
+                NameFormat::LastInitials
+            ) == format!(
+                    "- [Signals \\[and\\] Systems](<{}>), Oppenheim, A. V. (tags: dsp)\n",
+                    path.display()
+                )
+        );
+    }
+}
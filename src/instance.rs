@@ -1,21 +1,481 @@
 use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::resource::Resource;
+use crate::search::{search, ResourceFilter};
+use crate::storage::Storage;
 
+use indexmap::IndexMap;
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-enum InstantiateTagsSpecifier {
+/// File marking a directory as an instance created by librarian, which
+/// may therefore be replaced when it is instantiated again.
+const INSTANCE_MARKER: &str = ".librarian-instance";
+
+/// Tags whose directories a resource is placed in.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum InstantiateTagsSpecifier {
+    /// Only the directory of the resource's first tag.
+    #[default]
     Primary,
+    /// The directory of every tag of the resource.
     All,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Instance {
-    instantiate_tags: InstantiateTagsSpecifier,
-    directory_name_space_delimeter: char,
-    file_name_pattern: String,
+fn default_space_delimeter() -> char {
+    ' '
+}
+
+/// Placement of the resources of one content type in an instance,
+/// overriding the instance's own.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Layout {
+    /// Pattern of the directory resources are placed in, instead of the
+    /// directories of their tags. It accepts the same placeholders as
+    /// file name patterns and may contain slashes to nest directories,
+    /// e.g., "@journal@/@year@". Directories of missing values are
+    /// skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory_pattern: Option<String>,
+    /// Pattern of the file names of resources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name_pattern: Option<String>,
+}
+
+/// Directory of human-readable links to the library's resources.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Instance {
+    /// Directory of the instance, relative to the library directory.
+    pub path: PathBuf,
+    /// Only place resources matching this search query in the
+    /// instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Only place the resources of this collection in the instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// Only place resources of these content types in the instance.
+    /// Every content type is placed if this is empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_types: Vec<String>,
+    /// Pattern of the file names of resources. `@field@` is replaced by
+    /// the value of a resource field, `@year@` by the year,
+    /// `@extension@` by the extension of the document type,
+    /// `@author[N]:last@` by the first, middle, particle, last name or
+    /// suffix of the Nth author and `@position@` by the position of the resource in the
+    /// instance's collection, padded with zeros so that file names sort
+    /// in reading order.
+    pub file_name_pattern: String,
+    /// Pattern of the directory resources are placed in, instead of the
+    /// directories of their tags (see `Layout::directory_pattern`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory_pattern: Option<String>,
+    /// Layouts of content types that are placed differently from the
+    /// rest of the instance, keyed by content type (e.g., videos by year
+    /// and papers by journal).
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub layouts: IndexMap<String, Layout>,
+    /// Character replacing spaces in the names of directories.
+    #[serde(default = "default_space_delimeter")]
+    pub directory_name_space_delimeter: char,
+    #[serde(default)]
+    pub instantiate_tags: InstantiateTagsSpecifier,
+}
+
+impl Instance {
+    /// Value of a file name pattern placeholder for a resource. Missing
+    /// values are empty.
+    fn placeholder(
+        &self,
+        catalog: &Catalog,
+        resource: &Resource,
+        position: &str,
+        name: &str,
+    ) -> String {
+        let author = Regex::new(
+            r"^author\[(\d+)\]:(first|middle|particle|last|suffix)$",
+        )
+        .unwrap();
+        let value = match name {
+            "year" => resource
+                .date
+                .as_ref()
+                .and_then(|d| d.year)
+                .map(|y| y.to_string()),
+            "position" => Some(position.to_string()),
+            "extension" => resource
+                .document
+                .as_ref()
+                .and_then(|d| catalog.document_types.get(d))
+                .map(|d| d.extension.clone()),
+            _ => match author.captures(name) {
+                Some(c) => resource
+                    .author
+                    .as_ref()
+                    .and_then(|a| a.get(c[1].parse::<usize>().unwrap()))
+                    .and_then(|n| match &c[2] {
+                        "first" => n.first.clone(),
+                        "middle" => n.middle.clone(),
+                        "particle" => n.particle.clone(),
+                        "suffix" => n.suffix.clone(),
+                        _ => n.last.clone().or_else(|| n.literal.clone()),
+                    }),
+                None => resource.field_string(name),
+            },
+        };
+        // Slashes would create directories.
+        value.unwrap_or_default().replace('/', "-")
+    }
+
+    /// Pattern with its placeholders replaced by the values of a
+    /// resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Value of the `@position@` placeholder.
+    fn fill(
+        &self,
+        pattern: &str,
+        catalog: &Catalog,
+        resource: &Resource,
+        position: &str,
+    ) -> String {
+        let re = Regex::new(r"@([^@]+)@").unwrap();
+        re.replace_all(pattern, |c: &regex::Captures| {
+            self.placeholder(catalog, resource, position, &c[1])
+        })
+        .to_string()
+    }
+
+    /// Layout of a resource's content type, if the instance has one.
+    fn content_layout(&self, resource: &Resource) -> Option<&Layout> {
+        resource.content.as_ref().and_then(|c| self.layouts.get(c))
+    }
+
+    /// File name of a resource in the instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Value of the `@position@` placeholder.
+    fn file_name(
+        &self,
+        catalog: &Catalog,
+        resource: &Resource,
+        position: &str,
+    ) -> String {
+        let pattern = self
+            .content_layout(resource)
+            .and_then(|l| l.file_name_pattern.as_ref())
+            .unwrap_or(&self.file_name_pattern);
+        let name = self.fill(pattern, catalog, resource, position);
+        // Document types without an extension (e.g., web pages) leave a
+        // trailing period.
+        name.trim_end_matches('.').to_string()
+    }
+
+    /// Directories of the instance a resource is placed in, relative to
+    /// the instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Value of the `@position@` placeholder.
+    fn directories(
+        &self,
+        catalog: &Catalog,
+        resource: &Resource,
+        position: &str,
+    ) -> Vec<PathBuf> {
+        let delimeter = self.directory_name_space_delimeter.to_string();
+        let pattern = self
+            .content_layout(resource)
+            .and_then(|l| l.directory_pattern.as_ref())
+            .or(self.directory_pattern.as_ref());
+        if let Some(pattern) = pattern {
+            return vec![pattern
+                .split('/')
+                .map(|p| self.fill(p, catalog, resource, position))
+                .filter(|d| !d.is_empty())
+                .map(|d| d.replace(' ', &delimeter))
+                .collect()];
+        }
+        let tags: Vec<&String> = match self.instantiate_tags {
+            InstantiateTagsSpecifier::Primary => {
+                resource.tags.iter().flatten().take(1).collect()
+            }
+            InstantiateTagsSpecifier::All => {
+                resource.tags.iter().flatten().collect()
+            }
+        };
+        match tags.is_empty() {
+            true => vec![PathBuf::new()],
+            false => tags
+                .iter()
+                .map(|t| PathBuf::from(t.replace(' ', &delimeter)))
+                .collect(),
+        }
+    }
+
+    /// Location of every resource in the instance, relative to the
+    /// instance. Resources that would have the same path are numbered in
+    /// catalog order, or in collection order for an instance of a
+    /// collection.
+    ///
+    /// # Returns
+    ///
+    /// The paths of each resource placed in the instance, keyed by the
+    /// resource's initial checksum.
+    pub fn layout(&self, catalog: &Catalog) -> IndexMap<String, Vec<PathBuf>> {
+        let resources: Vec<&Resource> = match (&self.collection, &self.filter) {
+            (Some(c), _) => ResourceFilter {
+                query: self.filter.clone(),
+                collection: Some(c.clone()),
+                ..Default::default()
+            }
+            .apply(catalog, true),
+            (None, Some(q)) => search(catalog, q),
+            (None, None) => catalog.resources.iter().collect(),
+        };
+        let resources = resources.into_iter().filter(|r| {
+            self.content_types.is_empty()
+                || r.content
+                    .as_ref()
+                    .is_some_and(|c| self.content_types.contains(c))
+        });
+        // Positions refer to the whole collection, so that filtering an
+        // instance doesn't renumber its files.
+        let order: Vec<&String> = self
+            .collection
+            .iter()
+            .flat_map(|c| &catalog.collections[c])
+            .collect();
+        let width = order.len().to_string().len();
+        let mut used = HashSet::<PathBuf>::new();
+        let mut layout = IndexMap::<String, Vec<PathBuf>>::new();
+        for resource in resources {
+            let position = order
+                .iter()
+                .position(|k| **k == resource.historical_checksums[0])
+                .map(|p| format!("{:0width$}", p + 1, width = width))
+                .unwrap_or_default();
+            let name = self.file_name(catalog, resource, &position);
+            let (stem, extension) = match name.rfind('.') {
+                Some(i) if i > 0 => (&name[..i], &name[i..]),
+                _ => (name.as_str(), ""),
+            };
+            for directory in self.directories(catalog, resource, &position) {
+                let path = (1..)
+                    .map(|n| match n {
+                        1 => directory.join(&name),
+                        _ => directory
+                            .join(format!("{} ({}){}", stem, n, extension)),
+                    })
+                    .find(|p| !used.contains(p))
+                    .unwrap();
+                used.insert(path.clone());
+                layout
+                    .entry(resource.historical_checksums[0].clone())
+                    .or_default()
+                    .push(path);
+            }
+        }
+        layout
+    }
+}
+
+/// Directory of an instance holding the attachments of the resource at
+/// `path`, e.g., "rf/Phase Noise attachments" for "rf/Phase Noise.pdf".
+pub fn attachments_dir(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{} attachments", stem))
 }
 
-pub fn librarian_instantiate(_catalog: &Catalog) {
-    // TODO not yet implemented
-    // assert!(false);
+/// Hard link a file, or every file of a directory, to another location.
+fn hard_link(from: &Path, to: &Path) {
+    for entry in WalkDir::new(from) {
+        let entry = entry.unwrap();
+        let relative = entry.path().strip_prefix(from).unwrap();
+        let dest = match relative.as_os_str().is_empty() {
+            true => to.to_path_buf(),
+            false => to.join(relative),
+        };
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&dest)
+                .expect("Failed to create instance directory");
+        } else {
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)
+                    .expect("Failed to create instance directory");
+            }
+            std::fs::hard_link(entry.path(), &dest)
+                .expect("Failed to link resource into instance");
+        }
+    }
+}
+
+/// Create one or more instances of the library.
+///
+/// An existing instance directory is replaced. Directories that weren't
+/// created by librarian are never removed, so instantiating into a
+/// non-empty directory that isn't an instance fails.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `names` - Instances to create. Every instance is created if this
+///   is empty.
+pub fn librarian_instantiate(
+    catalog: &Catalog,
+    resources_path: &Path,
+    names: &[&str],
+) {
+    for name in names {
+        if !catalog.instances.contains_key(*name) {
+            panic!("Unknown instance {}", name);
+        }
+    }
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let storage = Storage::of(resources_path, catalog)
+        .remote(catalog.remote_storage.as_deref());
+
+    for (name, instance) in &catalog.instances {
+        if !names.is_empty() && !names.contains(&name.as_str()) {
+            continue;
+        }
+        let root = library.join(&instance.path);
+        if root.join(INSTANCE_MARKER).exists() {
+            std::fs::remove_dir_all(&root)
+                .expect("Failed to remove previous instance");
+        } else if root.exists()
+            && std::fs::read_dir(&root)
+                .expect("Failed to read instance directory")
+                .next()
+                .is_some()
+        {
+            panic!(
+                "{} is not empty and is not an instance, refusing to replace it",
+                root.display()
+            );
+        }
+        std::fs::create_dir_all(&root)
+            .expect("Failed to create instance directory");
+        std::fs::write(root.join(INSTANCE_MARKER), "")
+            .expect("Failed to mark instance directory");
+
+        for (key, paths) in instance.layout(catalog) {
+            let resource = storage.fetch(&key);
+            if !resource.exists() {
+                eprintln!(
+                    "{}",
+                    message(
+                        "resource-missing",
+                        &[("key", key.as_str().into())]
+                    )
+                );
+                continue;
+            }
+            let attachments: Vec<&String> = catalog
+                .resources
+                .iter()
+                .find(|r| r.historical_checksums[0] == key)
+                .and_then(|r| r.attachments.as_ref())
+                .into_iter()
+                .flatten()
+                .map(|a| &a.name)
+                .collect();
+            for path in paths {
+                hard_link(&resource, &root.join(&path));
+                for name in &attachments {
+                    let attachment = storage.attachments_path(&key).join(name);
+                    match attachment.exists() {
+                        true => hard_link(
+                            &attachment,
+                            &root.join(attachments_dir(&path)).join(name),
+                        ),
+                        false => eprintln!(
+                            "{}",
+                            message(
+                                "attachment-missing",
+                                &[
+                                    ("name", name.as_str().into()),
+                                    ("key", key.as_str().into())
+                                ]
+                            )
+                        ),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"pdf\": {\"extension\": \"pdf\"}, \"website\": {\"extension\": \"\"}}, \"content_types\": {},
+              \"instances\": {\"primary\": {\"path\": \"instance\", \"file_name_pattern\": \"@title@ (@author[0]:last@, @year@).@extension@\", \"directory_name_space_delimeter\": \"_\", \"instantiate_tags\": \"all\"}},
+              \"resources\": [
+              {\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"date\": \"1998\", \"document\": \"pdf\", \"tags\": [\"phase noise\", \"rf\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"date\": \"1998\", \"document\": \"pdf\", \"tags\": [\"rf\"], \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"I/O\", \"document\": \"website\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let layout = catalog.instances["primary"].layout(&catalog);
+        assert!(
+            layout["aa"]
+                == vec![
+                    Path::new("phase_noise")
+                        .join("Phase Noise (Hajimiri, 1998).pdf"),
+                    Path::new("rf").join("Phase Noise (Hajimiri, 1998).pdf"),
+                ]
+        );
+        assert!(
+            layout["bb"]
+                == vec![Path::new("rf")
+                    .join("Phase Noise (Hajimiri, 1998) (2).pdf")]
+        );
+        assert!(layout["cc"] == vec![PathBuf::from("I-O (, )")]);
+    }
+
+    #[test]
+    fn test_content_layouts() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"pdf\": {\"extension\": \"pdf\"}, \"mp4\": {\"extension\": \"mp4\"}}, \"content_types\": {},
+              \"instances\": {\"media\": {\"path\": \"media\", \"content_types\": [\"paper\", \"video\"], \"file_name_pattern\": \"@title@.@extension@\",
+                \"layouts\": {\"paper\": {\"directory_pattern\": \"papers/@journal@\"}, \"video\": {\"directory_pattern\": \"videos/@year@\", \"file_name_pattern\": \"@year@ @title@.@extension@\"}}}},
+              \"resources\": [
+              {\"title\": \"Phase Noise\", \"journal\": \"IEEE JSSC\", \"document\": \"pdf\", \"content\": \"paper\", \"tags\": [\"rf\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Preprint\", \"document\": \"pdf\", \"content\": \"paper\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Lecture\", \"date\": \"2019\", \"document\": \"mp4\", \"content\": \"video\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+              {\"title\": \"Datasheet\", \"document\": \"pdf\", \"content\": \"datasheet\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]}
+            ]}",
+        )
+        .unwrap();
+        let layout = catalog.instances["media"].layout(&catalog);
+        assert!(
+            layout["aa"]
+                == vec![Path::new("papers/IEEE JSSC").join("Phase Noise.pdf")]
+        );
+        // Directories of missing values are skipped.
+        assert!(layout["bb"] == vec![Path::new("papers").join("Preprint.pdf")]);
+        assert!(
+            layout["cc"]
+                == vec![Path::new("videos/2019").join("2019 Lecture.mp4")]
+        );
+        assert!(!layout.contains_key("dd"));
+    }
 }
@@ -0,0 +1,444 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::{DateTime, Resource};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Directory of the library holding the journal, with one file per
+/// operation.
+const JOURNAL_DIR: &str = ".journal";
+
+/// Number of operations kept in the journal.
+const JOURNAL_LENGTH: usize = 20;
+
+/// Change an operation made to a file or directory of the library.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+enum FileChange {
+    Moved {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Deleted file or directory, which was moved to the operation's
+    /// trash directory under `name` instead.
+    Deleted {
+        path: PathBuf,
+        name: String,
+    },
+}
+
+/// Catalog as it was before an operation changed it, along with the
+/// changes the operation made to files.
+#[derive(Serialize, Deserialize, Debug)]
+struct JournalEntry {
+    /// Number of seconds since "the epoch" when the operation started.
+    time: u64,
+    /// Subcommand that performed the operation.
+    operation: String,
+    catalog: Value,
+    /// Changes to files, in the order they were made.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    files: Vec<FileChange>,
+}
+
+/// Changes to files made by the operation in progress, and the directory
+/// its deleted files are moved to.
+struct FileLog {
+    trash: PathBuf,
+    changes: Vec<FileChange>,
+}
+
+thread_local! {
+    /// Files are journaled process-wide, like timings, so that storage
+    /// doesn't have to be handed the journal.
+    static FILES: RefCell<Option<FileLog>> = const { RefCell::new(None) };
+}
+
+/// Record that a file or directory was moved, if an operation is being
+/// journaled.
+pub fn moved(from: &Path, to: &Path) {
+    FILES.with(|f| {
+        if let Some(log) = f.borrow_mut().as_mut() {
+            log.changes.push(FileChange::Moved {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            });
+        }
+    });
+}
+
+/// Move a file or directory that is being deleted to the trash of the
+/// operation in progress, so that undoing the operation restores it.
+///
+/// # Returns
+///
+/// Whether it was moved. It isn't when no operation is being journaled
+/// or it can't be moved, e.g., to another filesystem, in which case it
+/// has to be deleted.
+pub fn trash(path: &Path) -> bool {
+    FILES.with(|f| {
+        let mut log = f.borrow_mut();
+        let log = match log.as_mut() {
+            Some(l) => l,
+            None => return false,
+        };
+        let name = log.changes.len().to_string();
+        let moved = std::fs::create_dir_all(&log.trash)
+            .and_then(|_| std::fs::rename(path, log.trash.join(&name)));
+        if moved.is_ok() {
+            log.changes.push(FileChange::Deleted {
+                path: path.to_path_buf(),
+                name,
+            });
+        }
+        moved.is_ok()
+    })
+}
+
+/// Operation in progress, which is recorded in the library's journal if
+/// it changes the stored catalog or the files of the library (see
+/// `moved` and `trash`), so that `librarian undo` can restore them as
+/// they were before. Undo itself isn't recorded, so that undoing again
+/// goes further back.
+///
+/// The operation is recorded when the journal is dropped, so that an
+/// operation that panics after writing the catalog can be undone as
+/// well.
+pub struct Journal {
+    library: PathBuf,
+    catalog_path: PathBuf,
+    entry: JournalEntry,
+    /// Time the stored catalog was last modified when the operation
+    /// started.
+    modified: Option<SystemTime>,
+}
+
+/// Time a stored catalog was last modified. A split catalog rewrites
+/// the `catalog.json` at its top on every write.
+fn modified(catalog_path: &Path) -> Option<SystemTime> {
+    let path = match catalog_path.is_dir() {
+        true => catalog_path.join("catalog.json"),
+        false => catalog_path.to_path_buf(),
+    };
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Files of the journal, oldest first.
+fn journal_files(library: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> =
+        match std::fs::read_dir(library.join(JOURNAL_DIR)) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.extension().and_then(|e| e.to_str()) == Some("json")
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+    files.sort();
+    files
+}
+
+impl Journal {
+    /// Start journaling an operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `library` - Library directory.
+    /// * `catalog_path` - Location of the stored catalog.
+    /// * `operation` - Subcommand of the operation.
+    /// * `catalog` - Catalog as read before the operation.
+    pub fn begin(
+        library: &Path,
+        catalog_path: &Path,
+        operation: &str,
+        catalog: &Catalog,
+    ) -> Journal {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        FILES.with(|f| {
+            *f.borrow_mut() = Some(FileLog {
+                trash: library
+                    .join(JOURNAL_DIR)
+                    .join(format!("{:020}.pending", now.as_nanos())),
+                changes: vec![],
+            })
+        });
+        Journal {
+            library: library.to_path_buf(),
+            catalog_path: catalog_path.to_path_buf(),
+            entry: JournalEntry {
+                time: now.as_secs(),
+                operation: operation.to_string(),
+                catalog: serde_json::to_value(catalog).unwrap(),
+                files: vec![],
+            },
+            modified: modified(catalog_path),
+        }
+    }
+
+    /// Whether the operation wrote the stored catalog.
+    pub fn changed(&self) -> bool {
+        modified(&self.catalog_path) != self.modified
+    }
+
+    /// Resources of a catalog that weren't in the catalog before the
+    /// operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - Catalog after the operation.
+    pub fn added<'a>(&self, catalog: &'a Catalog) -> Vec<&'a Resource> {
+        let before: HashSet<&str> = self.entry.catalog["resources"]
+            .as_array()
+            .iter()
+            .flat_map(|r| r.iter())
+            .filter_map(|r| r["historical_checksums"][0].as_str())
+            .collect();
+        catalog
+            .resources
+            .iter()
+            .filter(|r| !before.contains(r.historical_checksums[0].as_str()))
+            .collect()
+    }
+}
+
+impl Drop for Journal {
+    /// Record the operation if it wrote the catalog, and forget the
+    /// oldest operations. This may run while panicking, so failures
+    /// are only reported.
+    fn drop(&mut self) {
+        let log = match FILES.with(|f| f.borrow_mut().take()) {
+            Some(l) => l,
+            None => return,
+        };
+        if (!self.changed() && log.changes.is_empty())
+            || self.entry.operation == "undo"
+        {
+            std::fs::remove_dir_all(&log.trash).ok();
+            return;
+        }
+        let trash = log.trash;
+        self.entry.files = log.changes;
+        let dir = self.library.join(JOURNAL_DIR);
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos();
+        let path = dir.join(format!("{:020}.json", nanos));
+        let written = std::fs::create_dir_all(&dir)
+            .and_then(|_| match trash.exists() {
+                true => std::fs::rename(&trash, path.with_extension("trash")),
+                false => Ok(()),
+            })
+            .and_then(|_| {
+                std::fs::write(
+                    &path,
+                    serde_json::to_string(&self.entry).unwrap(),
+                )
+            });
+        if let Err(e) = written {
+            eprintln!(
+                "{}",
+                message("journal-failed", &[("error", e.to_string().into())])
+            );
+            return;
+        }
+        let files = journal_files(&self.library);
+        for old in &files[..files.len().saturating_sub(JOURNAL_LENGTH)] {
+            std::fs::remove_file(old).ok();
+            std::fs::remove_dir_all(old.with_extension("trash")).ok();
+        }
+    }
+}
+
+/// Restore the catalog and the files of the library as they were before
+/// the most recent operation that changed them, and remove that
+/// operation from the journal, so that undoing again goes further back.
+/// Files the operation moved are moved back and files it deleted are
+/// restored, unless something else is in their place by now.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+pub fn librarian_undo(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let path = match journal_files(library).pop() {
+        Some(p) => p,
+        None => {
+            println!("{}", message("undo-empty", &[]));
+            return;
+        }
+    };
+    let contents =
+        std::fs::read_to_string(&path).expect("Failed to read journal");
+    let entry: JournalEntry =
+        serde_json::from_str(&contents).expect("Failed to parse journal");
+    let mut restored: Catalog = serde_json::from_value(entry.catalog)
+        .expect("Journaled catalog is invalid");
+    restored.dry_run = catalog.dry_run;
+
+    let keys = |c: &Catalog| -> HashSet<String> {
+        c.resources
+            .iter()
+            .map(|r| r.historical_checksums[0].clone())
+            .collect()
+    };
+    let (current, previous) = (keys(catalog), keys(&restored));
+    let mut affected: Vec<String> =
+        current.symmetric_difference(&previous).cloned().collect();
+    for resource in &restored.resources {
+        let key = &resource.historical_checksums[0];
+        let changed = catalog
+            .resources
+            .iter()
+            .find(|r| &r.historical_checksums[0] == key)
+            .is_some_and(|r| {
+                serde_json::to_value(r).unwrap()
+                    != serde_json::to_value(resource).unwrap()
+            });
+        if changed {
+            affected.push(key.clone());
+        }
+    }
+    affected.sort();
+
+    *catalog = restored;
+    store.write(catalog);
+
+    // A dry run leaves the files and the journal alone.
+    let mut restored_files = 0;
+    if !catalog.dry_run {
+        // Later changes may depend on earlier ones, so they're reversed
+        // first.
+        let trash = path.with_extension("trash");
+        let exists = |p: &Path| p.symlink_metadata().is_ok();
+        for change in entry.files.iter().rev() {
+            let (from, to) = match change {
+                FileChange::Moved { from, to } => (to.clone(), from.clone()),
+                FileChange::Deleted { path, name } => {
+                    (trash.join(name), path.clone())
+                }
+            };
+            let moved = match !exists(&from) || exists(&to) {
+                true => false,
+                false => std::fs::create_dir_all(to.parent().unwrap())
+                    .and_then(|_| std::fs::rename(&from, &to))
+                    .is_ok(),
+            };
+            match moved {
+                true => restored_files += 1,
+                false => eprintln!(
+                    "{}",
+                    message(
+                        "undo-file-skipped",
+                        &[("path", to.to_string_lossy().into_owned().into())]
+                    )
+                ),
+            }
+        }
+        std::fs::remove_dir_all(&trash).ok();
+        std::fs::remove_file(&path).expect("Failed to remove journal entry");
+    }
+    let when = String::from(DateTime::from(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(entry.time),
+    ));
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "undo",
+                affected.clone(),
+                vec![format!("Undid {} from {}", entry.operation, when)],
+            ),
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "undo-done",
+            &[
+                ("operation", entry.operation.into()),
+                ("time", when.into()),
+                ("count", affected.len().into())
+            ]
+        )
+    );
+    if restored_files > 0 {
+        println!(
+            "{}",
+            message("undo-files", &[("count", restored_files.into())])
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-journal-{}", std::process::id()));
+        let resources = library.join("resources");
+        std::fs::create_dir_all(&resources).unwrap();
+        let catalog_path = library.join("catalog.json");
+        std::fs::write(
+            &catalog_path,
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}
+            ]}",
+        )
+        .unwrap();
+        let mut store = CatalogStore::open(&catalog_path);
+        let mut catalog = store.read();
+
+        // An operation that doesn't write the catalog isn't journaled.
+        drop(Journal::begin(&library, &catalog_path, "search", &catalog));
+        assert!(journal_files(&library).is_empty());
+
+        // Deleted and moved files are restored along with the catalog.
+        std::fs::write(resources.join("aa"), "a").unwrap();
+        std::fs::write(library.join("new.pdf"), "b").unwrap();
+        let journal = Journal::begin(&library, &catalog_path, "rm", &catalog);
+        catalog.resources.clear();
+        std::thread::sleep(Duration::from_millis(10));
+        store.write(&mut catalog);
+        assert!(trash(&resources.join("aa")));
+        std::fs::rename(library.join("new.pdf"), resources.join("bb")).unwrap();
+        moved(&library.join("new.pdf"), &resources.join("bb"));
+        drop(journal);
+        assert!(journal_files(&library).len() == 1);
+        assert!(!trash(&resources.join("bb")));
+
+        librarian_undo(&mut store, &mut catalog, &resources);
+        assert!(store.read().resources[0].title == "A");
+        assert!(std::fs::read_to_string(resources.join("aa")).unwrap() == "a");
+        assert!(
+            library.join("new.pdf").exists() && !resources.join("bb").exists()
+        );
+        assert!(journal_files(&library).is_empty());
+        assert!(
+            std::fs::read_dir(library.join(JOURNAL_DIR))
+                .unwrap()
+                .count()
+                == 0
+        );
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+pub mod activity;
+pub mod ancillary;
+pub mod attachments;
+pub mod audit;
+pub mod authors;
+pub mod batch;
+pub mod bibtex;
+pub mod bundle;
+pub mod cache;
+pub mod capture;
+pub mod catalog;
+pub mod check;
+pub mod citations;
+pub mod citekey;
+pub mod collections;
+pub mod complete;
+pub mod dedupe;
+pub mod describe;
+pub mod download;
+pub mod edit;
+pub mod fingerprint;
+pub mod fulltext;
+pub mod history;
+pub mod hooks;
+pub mod i18n;
+pub mod import;
+pub mod inbox;
+pub mod index;
+pub mod instance;
+pub mod journal;
+pub mod linkcheck;
+pub mod lint;
+pub mod merge;
+pub mod metadata;
+pub mod mirror;
+pub mod notes;
+pub mod opds;
+pub mod pdf;
+pub mod provider;
+pub mod reading;
+pub mod relations;
+pub mod remote;
+pub mod resolve;
+pub mod resource;
+pub mod ris;
+pub mod schema;
+pub mod search;
+pub mod serve;
+pub mod spreadsheet;
+pub mod state;
+pub mod storage;
+pub mod style;
+pub mod styles;
+pub mod sync;
+pub mod tags;
+pub mod timings;
+pub mod todo;
+pub mod versions;
+pub mod watch;
+pub mod webpage;
@@ -0,0 +1,406 @@
+use crate::ancillary::{mark_rebuilt, read_ancillary};
+use crate::audit::{record, AuditEntry};
+use crate::batch::{
+    catch_interrupt, interrupted, run, write_atomically, Checkpoint,
+};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::{DateTime, Resource};
+use crate::search::pick_resource;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Maximum number of redirects followed before a link is considered
+/// dead.
+const MAX_REDIRECTS: usize = 10;
+
+/// Result of the last check of a resource's URL.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LinkStatus {
+    /// URL that was checked.
+    pub url: String,
+    /// Number of seconds since "the epoch" when the link was checked.
+    pub last_checked: u64,
+    /// HTTP status of the final response, if the server responded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Location the URL ultimately redirects to, if it redirects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+    /// Connection error, if the server couldn't be reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Wayback Machine snapshot of the URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<String>,
+}
+
+impl LinkStatus {
+    /// A link is dead if the server can't be reached or responds with
+    /// an error.
+    pub fn is_dead(&self) -> bool {
+        !matches!(self.status, Some(s) if s < 400)
+    }
+}
+
+/// HTTP agent for `check_link`, which follows redirects itself.
+pub(crate) fn link_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .redirects(0)
+        .timeout(Duration::from_secs(30))
+        .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Request a URL, following redirects manually so that the final
+/// location can be recorded.
+///
+/// Servers that don't support HEAD requests are retried with GET.
+pub(crate) fn check_link(agent: &ureq::Agent, url: &str) -> LinkStatus {
+    let mut status = LinkStatus {
+        url: url.to_string(),
+        ..Default::default()
+    };
+    let mut location = url.to_string();
+    for _ in 0..MAX_REDIRECTS {
+        let mut result = agent.head(&location).call();
+        if let Err(ureq::Error::Status(405, _))
+        | Err(ureq::Error::Status(501, _)) = result
+        {
+            result = agent.get(&location).call();
+        }
+        let response = match result {
+            Ok(r) => r,
+            Err(ureq::Error::Status(_, r)) => r,
+            Err(e) => {
+                status.error = Some(e.to_string());
+                return status;
+            }
+        };
+        status.status = Some(response.status());
+        let next = match response.status() {
+            300..=399 => response.header("Location").map(|l| {
+                url::Url::parse(&location)
+                    .and_then(|base| base.join(l))
+                    .map(|u| u.to_string())
+                    .unwrap_or_else(|_| l.to_string())
+            }),
+            _ => None,
+        };
+        match next {
+            Some(n) => location = n,
+            None => {
+                if location != url {
+                    status.redirect = Some(location);
+                }
+                return status;
+            }
+        }
+    }
+    status.error = Some(String::from("too many redirects"));
+    status
+}
+
+/// Ask the Wayback Machine to archive a URL.
+///
+/// # Returns
+///
+/// The location of the snapshot.
+fn archive_link(agent: &ureq::Agent, url: &str) -> Result<String, String> {
+    let response = agent
+        .get(&format!("https://web.archive.org/save/{}", url))
+        .call()
+        .map_err(|e| e.to_string())?;
+    Ok(response
+        .header("Content-Location")
+        .map(|l| format!("https://web.archive.org{}", l))
+        .unwrap_or_else(|| response.get_url().to_string()))
+}
+
+/// Check the URL of every cataloged resource and record the results in
+/// the library's `.links` file.
+///
+/// The results are kept out of the catalog for the same reason as the
+/// cache: they change frequently and aren't of interest to the user
+/// except as a report.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `delay` - Number of seconds each job waits between requests.
+/// * `archive` - Ask the Wayback Machine to archive every link that is
+///   still alive and hasn't been archived yet, so that a copy survives
+///   when it disappears.
+/// * `jobs` - Number of links checked at the same time.
+/// * `resume` - Skip the links checked by an interrupted earlier run.
+/// * `safe_mode` - Neither read nor write the `.links` file, and queue
+///   it for rebuild. A file that fails to parse is handled the same
+///   way, with a warning.
+pub fn librarian_linkcheck(
+    catalog: &Catalog,
+    resources_path: &Path,
+    delay: f64,
+    archive: bool,
+    jobs: usize,
+    resume: bool,
+    safe_mode: bool,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let links_path = library.join(".links");
+    let previous: IndexMap<String, LinkStatus> = match links_path.exists() {
+        true => read_ancillary(library, "links", safe_mode, || {
+            let contents = std::fs::read_to_string(&links_path)
+                .map_err(|e| e.to_string())?;
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        })
+        .unwrap_or_default(),
+        false => IndexMap::new(),
+    };
+
+    let agent = link_agent();
+    let delay = Duration::from_secs_f64(delay);
+    let mut checkpoint = Checkpoint::open(library, "linkcheck", resume);
+    let resources: Vec<(&Resource, &str)> = catalog
+        .resources
+        .iter()
+        .filter_map(|r| Some((r, r.url.as_ref()?.as_str())))
+        .collect();
+    let total = resources.len();
+    let pending: Vec<(&Resource, &str)> = resources
+        .into_iter()
+        .filter(|(r, _)| !checkpoint.done.contains(&r.historical_checksums[0]))
+        .collect();
+    // Links checked by an earlier run keep their status until they are
+    // checked again.
+    let mut links: IndexMap<String, LinkStatus> = match resume {
+        true => previous.clone(),
+        false => IndexMap::new(),
+    };
+    let mut checked = 0;
+    let mut dead = 0;
+
+    catch_interrupt();
+    run(
+        &pending,
+        jobs,
+        |(resource, url)| {
+            let key = &resource.historical_checksums[0];
+            let mut status = check_link(&agent, url);
+            status.last_checked = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("SystemTime before UNIX EPOCH!")
+                .as_secs();
+            // keep an earlier snapshot of the same URL
+            status.archived = previous
+                .get(key)
+                .filter(|p| p.url == *url)
+                .and_then(|p| p.archived.clone());
+            sleep(delay);
+            let archived =
+                match archive && !status.is_dead() && status.archived.is_none()
+                {
+                    true => {
+                        let archived = archive_link(&agent, url);
+                        sleep(delay);
+                        Some(archived)
+                    }
+                    false => None,
+                };
+            (status, archived)
+        },
+        |(resource, url), (mut status, archived)| {
+            let key = &resource.historical_checksums[0];
+            if status.is_dead() {
+                dead += 1;
+                let reason = match (&status.status, &status.error) {
+                    (Some(s), _) => s.to_string(),
+                    (None, Some(e)) => e.clone(),
+                    (None, None) => String::new(),
+                };
+                println!(
+                    "{}",
+                    message(
+                        "linkcheck-dead",
+                        &[
+                            ("key", key.into()),
+                            ("title", resource.title.as_str().into()),
+                            ("url", (*url).into()),
+                            ("reason", reason.into()),
+                        ]
+                    )
+                );
+                if let Some(a) = &status.archived {
+                    println!(
+                        "      {}",
+                        message(
+                            "linkcheck-archived-at",
+                            &[("archive", a.into())]
+                        )
+                    );
+                }
+            } else if let Some(r) = &status.redirect {
+                println!(
+                    "{}",
+                    message(
+                        "linkcheck-moved",
+                        &[
+                            ("key", key.into()),
+                            ("title", resource.title.as_str().into()),
+                            ("url", (*url).into()),
+                            ("redirect", r.into()),
+                        ]
+                    )
+                );
+            }
+            match archived {
+                Some(Ok(a)) => {
+                    println!(
+                        "{}",
+                        message(
+                            "linkcheck-archived",
+                            &[("url", (*url).into()), ("archive", (&a).into())]
+                        )
+                    );
+                    status.archived = Some(a);
+                }
+                Some(Err(e)) => eprintln!(
+                    "{}",
+                    message(
+                        "linkcheck-archive-failed",
+                        &[("url", (*url).into()), ("error", e.into())]
+                    )
+                ),
+                None => (),
+            }
+            links.insert(key.clone(), status);
+            checkpoint.done.insert(key.clone());
+            checked += 1;
+        },
+    );
+
+    println!(
+        "{}",
+        message(
+            "linkcheck-summary",
+            &[("count", checked.into()), ("dead", dead.into())]
+        )
+    );
+    if safe_mode {
+        return;
+    }
+    // An interrupted run keeps the earlier status of the links it
+    // didn't get to.
+    if interrupted() {
+        for (key, status) in previous {
+            links.entry(key).or_insert(status);
+        }
+    }
+    write_atomically(
+        &links_path,
+        &serde_json::to_string_pretty(&links).unwrap(),
+    );
+    checkpoint.finish("linkcheck", total);
+    mark_rebuilt(library, "links");
+}
+
+/// Archive the URL of a resource with the Wayback Machine and record
+/// the snapshot and the date of the snapshot (BibLaTeX's urldate) on
+/// the resource, so that citations point to a copy that survives link
+/// rot.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+pub fn librarian_archive(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    query: &str,
+) {
+    let (key, url) =
+        match pick_resource(catalog, query, &message("pick-archive", &[])) {
+            Some(r) => (
+                r.historical_checksums[0].clone(),
+                r.url
+                    .as_ref()
+                    .unwrap_or_else(|| panic!("{} has no URL", r.title))
+                    .to_string(),
+            ),
+            None => return,
+        };
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(120))
+        .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let snapshot = archive_link(&agent, &url)
+        .and_then(|s| Url::parse(&s).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            panic!(
+                "{}",
+                message(
+                    "linkcheck-archive-failed",
+                    &[("url", url.as_str().into()), ("error", e.into())]
+                )
+            )
+        });
+
+    let resource = catalog.resource_mut(&key).unwrap();
+    resource.archive_url = Some(snapshot.clone());
+    resource.archived = Some(DateTime::from(SystemTime::now()));
+    let archived = String::from(resource.archived.clone().unwrap());
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new(
+                "archive",
+                vec![key.clone()],
+                vec![
+                    format!("{} archive_url: {}", key, snapshot),
+                    format!("{} archived: {}", key, archived),
+                ],
+            ),
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "linkcheck-archived",
+            &[
+                ("url", url.as_str().into()),
+                ("archive", snapshot.as_str().into())
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_dead() {
+        let status = |status, error: Option<&str>| LinkStatus {
+            status,
+            error: error.map(String::from),
+            ..Default::default()
+        };
+        assert!(!status(Some(200), None).is_dead());
+        assert!(!status(Some(301), None).is_dead());
+        assert!(status(Some(404), None).is_dead());
+        assert!(status(None, Some("connection refused")).is_dead());
+    }
+}
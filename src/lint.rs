@@ -0,0 +1,264 @@
+use crate::batch::run;
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::linkcheck::{check_link, link_agent};
+use crate::resource::{DateTime, Resource};
+
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Year before which dates are flagged, unless the rules give another.
+const EARLIEST_YEAR: i32 = 1450;
+
+/// Rules checked by `librarian lint`, which fails when a resource
+/// breaks any of them.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct LintRules {
+    /// Fields every resource of a content type must have, keyed by
+    /// content type. Fields listed under "*" are required of every
+    /// resource.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub required_fields: IndexMap<String, Vec<String>>,
+    /// Require every tag to be in the catalog's tag tree.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub known_tags: bool,
+    /// Flag dates in the future and dates before `earliest_year`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dates: bool,
+    /// Earliest plausible year of a date. Defaults to 1450.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest_year: Option<i32>,
+    /// Flag resources with the same title and authors as another
+    /// resource, ignoring case.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate_titles: bool,
+    /// Request the URL of every resource and flag those that can't be
+    /// reached. This is slow, so it's off by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub check_urls: bool,
+}
+
+/// Rule broken by a resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Initial checksum of the resource.
+    pub key: String,
+    /// Description of the violation.
+    pub problem: String,
+}
+
+impl LintRules {
+    /// Violations of every rule except `check_urls`, which needs the
+    /// network.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - Library catalog.
+    /// * `now` - Current date, after which dates are in the future.
+    pub fn violations(
+        &self,
+        catalog: &Catalog,
+        now: &DateTime,
+    ) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut titles = HashMap::<(String, Vec<String>), &str>::new();
+        for resource in &catalog.resources {
+            let key = &resource.historical_checksums[0];
+            let mut violation = |id: &str, args: &[(&str, String)]| {
+                violations.push(Violation {
+                    key: key.clone(),
+                    problem: message(
+                        id,
+                        &args
+                            .iter()
+                            .map(|(n, v)| (*n, v.as_str().into()))
+                            .collect::<Vec<_>>(),
+                    ),
+                })
+            };
+
+            let required = self.required_fields.get("*").into_iter().chain(
+                resource
+                    .content
+                    .as_ref()
+                    .and_then(|c| self.required_fields.get(c)),
+            );
+            for field in required.flatten() {
+                if resource.field_string(field).is_none_or(|v| v.is_empty()) {
+                    violation(
+                        "lint-missing-field",
+                        &[("field", field.clone())],
+                    );
+                }
+            }
+
+            if self.known_tags {
+                for tag in resource.tags.iter().flatten() {
+                    if !catalog.tag_known(tag) {
+                        violation("lint-unknown-tag", &[("tag", tag.clone())]);
+                    }
+                }
+            }
+
+            if let (true, Some(date)) = (self.dates, &resource.date) {
+                let earliest = self.earliest_year.unwrap_or(EARLIEST_YEAR);
+                if date > now {
+                    violation(
+                        "lint-future-date",
+                        &[("date", String::from(date.clone()))],
+                    );
+                } else if date.year.is_some_and(|y| y < earliest) {
+                    violation(
+                        "lint-early-date",
+                        &[
+                            ("date", String::from(date.clone())),
+                            ("year", earliest.to_string()),
+                        ],
+                    );
+                }
+            }
+
+            if let (true, Some(authors)) =
+                (self.duplicate_titles, &resource.author)
+            {
+                let mut names: Vec<String> = authors
+                    .iter()
+                    .map(|a| String::from(a.clone()).to_lowercase())
+                    .collect();
+                names.sort();
+                let title = resource.title.trim().to_lowercase();
+                match titles.get(&(title.clone(), names.clone())) {
+                    Some(other) => violation(
+                        "lint-duplicate-title",
+                        &[("other", other.to_string())],
+                    ),
+                    None => {
+                        titles.insert((title, names), key);
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Check the resources against the catalog's lint rules and print the
+/// violations, e.g., from a pre-commit hook.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `check_urls` - Check URLs even if the rules don't.
+/// * `jobs` - Number of URLs requested at the same time.
+///
+/// # Returns
+///
+/// The number of violations.
+pub fn librarian_lint(
+    catalog: &Catalog,
+    check_urls: bool,
+    jobs: usize,
+) -> usize {
+    let rules = catalog.lint.clone().unwrap_or_default();
+    let mut violations = rules
+        .violations(catalog, &DateTime::from(std::time::SystemTime::now()));
+    if rules.check_urls || check_urls {
+        let agent = link_agent();
+        let resources: Vec<(&Resource, &str)> = catalog
+            .resources
+            .iter()
+            .filter_map(|r| Some((r, r.url.as_ref()?.as_str())))
+            .collect();
+        run(
+            &resources,
+            jobs,
+            |(_, url)| check_link(&agent, url),
+            |(resource, url), status| {
+                if status.is_dead() {
+                    violations.push(Violation {
+                        key: resource.historical_checksums[0].clone(),
+                        problem: message(
+                            "lint-dead-url",
+                            &[
+                                ("url", (*url).into()),
+                                (
+                                    "status",
+                                    status
+                                        .status
+                                        .map(|s| s.to_string())
+                                        .or(status.error)
+                                        .unwrap_or_default()
+                                        .into(),
+                                ),
+                            ],
+                        ),
+                    });
+                }
+            },
+        );
+    }
+
+    // Report the violations of each resource together, in catalog
+    // order.
+    let order: HashMap<&str, usize> = catalog
+        .resources
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (r.historical_checksums[0].as_str(), i))
+        .collect();
+    violations.sort_by_key(|v| order[v.key.as_str()]);
+    for violation in &violations {
+        let resource = &catalog.resources[order[violation.key.as_str()]];
+        println!(
+            "{}",
+            message(
+                "lint-violation",
+                &[
+                    ("key", violation.key.as_str().into()),
+                    ("title", resource.title.as_str().into()),
+                    ("problem", violation.problem.as_str().into()),
+                ]
+            )
+        );
+    }
+    println!(
+        "{}",
+        message("lint-summary", &[("count", violations.len().into())])
+    );
+    violations.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_lint_violations() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"tags\": {\"rf\": {}},
+              \"lint\": {\"required_fields\": {\"*\": [\"author\"], \"article\": [\"journal\"]},
+                         \"known_tags\": true, \"dates\": true, \"duplicate_titles\": true},
+              \"resources\": [
+              {\"title\": \"Phase Noise\", \"author\": [\"Ali Hajimiri\"], \"content\": \"article\", \"tags\": [\"rf\", \"draft\"],
+               \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"phase noise\", \"author\": [\"Ali Hajimiri\"], \"date\": \"2031\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Incunabulum\", \"date\": \"1200\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let now = DateTime::try_from("2024-03-01").unwrap();
+        let violations =
+            catalog.lint.as_ref().unwrap().violations(&catalog, &now);
+        let keys: Vec<&str> =
+            violations.iter().map(|v| v.key.as_str()).collect();
+        // "aa" lacks a journal and has an unknown tag, "bb" is dated in
+        // the future and duplicates "aa", and "cc" lacks an author and
+        // is dated too early.
+        assert!(keys == vec!["aa", "aa", "bb", "bb", "cc", "cc"]);
+        assert!(violations[3].problem.contains("aa"));
+    }
+}
@@ -1,64 +1,1014 @@
-mod bibtex;
-mod cache;
-mod catalog;
-mod instance;
-mod resource;
-mod search;
-
-use crate::bibtex::librarian_bibtex;
-use crate::catalog::{librarian_catalog, Catalog};
-use crate::instance::librarian_instantiate;
-use crate::search::librarian_search;
+use librarian::attachments::{
+    librarian_attachment_add, librarian_attachment_list,
+    librarian_attachment_rm,
+};
+use librarian::authors::{librarian_authors, librarian_merge_authors};
+use librarian::bibtex::{librarian_bibtex, FileField};
+use librarian::bundle::{librarian_export_bundle, librarian_import_bundle};
+use librarian::cache::{
+    librarian_cache_clear, librarian_cache_prune, librarian_cache_stats,
+};
+use librarian::capture::{librarian_capture, Capture};
+use librarian::catalog::{librarian_catalog, librarian_init, CatalogStore};
+use librarian::check::librarian_check;
+use librarian::citations::{librarian_unused, librarian_used};
+use librarian::collections::{
+    librarian_collection_add, librarian_collection_create,
+    librarian_collection_list, librarian_collection_rm,
+    librarian_collection_show,
+};
+use librarian::complete::librarian_complete;
+use librarian::dedupe::{librarian_dedupe, DEDUPE_THRESHOLD};
+use librarian::download::{librarian_fetch, librarian_fetch_file};
+use librarian::edit::{librarian_bulk_edit, BulkEdit};
+use librarian::fingerprint::librarian_fingerprint;
+use librarian::history::{commit_catalog, librarian_diff, librarian_log};
+use librarian::hooks::run_hook;
+use librarian::import::librarian_import_bibtex;
+use librarian::inbox::librarian_review;
+use librarian::index::{librarian_export_index, IndexFormat};
+use librarian::instance::librarian_instantiate;
+use librarian::journal::{librarian_undo, Journal};
+use librarian::linkcheck::{librarian_archive, librarian_linkcheck};
+use librarian::lint::librarian_lint;
+use librarian::merge::{
+    librarian_merge_resources, librarian_split, MergePreference,
+};
+use librarian::metadata::librarian_enrich;
+use librarian::metadata::librarian_fetch_metadata;
+use librarian::mirror::{librarian_mirror, librarian_verify_mirror};
+use librarian::notes::librarian_note;
+use librarian::reading::{
+    librarian_queue, librarian_set_reading, Priority, ReadStatus, ReadingChange,
+};
+use librarian::relations::{librarian_graph, librarian_show, GraphFormat};
+use librarian::resolve::librarian_resolve;
+use librarian::resource::{DateTime, NameFormat};
+use librarian::ris::{librarian_export_ris, librarian_import_ris};
+use librarian::schema::librarian_schema;
+use librarian::search::{librarian_search, ResourceFilter, SearchOrder};
+use librarian::serve::{librarian_serve, SERVE_ADDRESS};
+use librarian::spreadsheet::librarian_import_csv;
+use librarian::state::librarian_state_set;
+use librarian::storage::{
+    librarian_migrate_storage, librarian_open, StorageLayout,
+};
+use librarian::style::librarian_fmt;
+use librarian::styles::{librarian_styles_add, librarian_styles_list};
+use librarian::sync::{librarian_sync, SyncPreference};
+use librarian::tags::{
+    librarian_tag_add, librarian_tag_apply, librarian_tag_list,
+    librarian_tag_merge, librarian_tag_rename, librarian_tag_rm,
+};
+use librarian::timings;
+use librarian::todo::{librarian_todo, TODO_FIELDS};
+use librarian::versions::{librarian_checkout, librarian_versions};
+use librarian::watch::librarian_watch;
+use librarian::webpage::librarian_add_url;
 
 use clap::{app_from_crate, App, Arg};
+use std::convert::TryFrom;
 use std::env;
-use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Operations that never write the catalog or move or delete files of
+/// the library, which aren't journaled. An operation is a subcommand,
+/// followed by its own subcommand if it has one.
+const READ_ONLY_OPERATIONS: [&str; 33] = [
+    "todo",
+    "lint",
+    "linkcheck",
+    "instantiate",
+    "search",
+    "queue",
+    "note",
+    "open",
+    "show",
+    "graph",
+    "bibtex",
+    "export-ris",
+    "export-org",
+    "export-markdown",
+    "mirror",
+    "verify-mirror",
+    "export-bundle",
+    "used",
+    "unused",
+    "log",
+    "diff",
+    "resolve",
+    "versions",
+    "fingerprint",
+    "schema",
+    "cache",
+    "cache stats",
+    "styles",
+    "styles list",
+    "authors",
+    "collection list",
+    "collection show",
+    "attachment list",
+];
 
 fn main() {
+    let start = Instant::now();
     let args = parse_app_args();
+    if args.is_present("timings") {
+        timings::enable();
+    }
     let (resources_path, catalog_path) = library_paths(&args);
-    let mut catalog_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&catalog_path)
-        .expect("Failed to open or create catalog");
-    let mut catalog = Catalog::read_from_file(&mut catalog_file);
+    // Completion runs on every keystroke of an editor, so it reads the
+    // few fields it needs without loading the catalog.
+    if let Some(sub_args) = args.subcommand_matches("complete") {
+        librarian_complete(
+            &catalog_path,
+            sub_args
+                .value_of("what")
+                .expect("must specify what to complete"),
+            sub_args.value_of("prefix"),
+        );
+        return;
+    }
+    let dry_run = args.is_present("dry run");
+    let mut catalog_store = CatalogStore::open(&catalog_path).dry_run(dry_run);
+    let mut catalog = catalog_store.read();
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent")
+        .to_path_buf();
+
+    // Record operations that change the catalog so that they can be
+    // undone and committed to the library's git repository. Commands
+    // that only read it skip the snapshot this takes.
+    let operation = match args.subcommand() {
+        Some((name, sub_args)) => match sub_args.subcommand_name() {
+            Some(sub_name) => format!("{} {}", name, sub_name),
+            None => name.to_string(),
+        },
+        None => String::new(),
+    };
+    let journal = match args.subcommand() {
+        _ if READ_ONLY_OPERATIONS.contains(&operation.as_str()) => None,
+        // Without write access, the server only reads the catalog.
+        Some(("serve", sub_args)) if !sub_args.is_present("write") => None,
+        _ if dry_run => None,
+        _ => Some(Journal::begin(
+            &library,
+            &catalog_path,
+            &operation,
+            &catalog,
+        )),
+    };
 
     // Invoke the function for the given subcommand.
-    if args.is_present("catalog") {
+    if args.is_present("init") {
+        librarian_init(&mut catalog_store, &mut catalog, &resources_path);
+    } else if args.is_present("catalog") {
+        let sub_args = args.subcommand_matches("catalog").unwrap();
+        // Without queries, questions that no flag answers are answered
+        // "no".
+        let query = |policy| match policy {
+            "ask" if sub_args.is_present("no query") => "false",
+            _ => policy,
+        };
         librarian_catalog(
-            &mut catalog_file,
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.is_present("cache"),
+            query(
+                sub_args
+                    .value_of("remove orphans")
+                    .expect("remove-orphans requires a value"),
+            ),
+            sub_args.is_present("purge retired"),
+            sub_args.is_present("detect doi"),
+            sub_args.is_present("fetch metadata"),
+            query(
+                sub_args
+                    .value_of("attach duplicates")
+                    .expect("attach-duplicates requires a value"),
+            ),
+            query(match sub_args {
+                a if a.is_present("delete duplicates") => "true",
+                a if a.is_present("keep duplicates") => "false",
+                _ => "ask",
+            }),
+            sub_args.is_present("interactive"),
+            dry_run,
+            args.is_present("safe mode"),
+            sub_args.is_present("follow symlinks"),
+        );
+    } else if args.is_present("cache") {
+        let library = resources_path
+            .parent()
+            .expect("resources path does not have a parent");
+        match args.subcommand_matches("cache").unwrap().subcommand_name() {
+            Some("clear") => librarian_cache_clear(library, &catalog),
+            Some("prune") => librarian_cache_prune(library, &catalog),
+            _ => librarian_cache_stats(library, &catalog),
+        }
+    } else if args.is_present("todo") {
+        let sub_args = args.subcommand_matches("todo").unwrap();
+        librarian_todo(
+            &catalog,
+            &sub_args
+                .values_of("field")
+                .map(|f| f.collect::<Vec<_>>())
+                .unwrap_or_else(|| TODO_FIELDS.to_vec()),
+            sub_args.is_present("json"),
+        );
+    } else if args.is_present("review") {
+        librarian_review(&mut catalog_store, &mut catalog, &resources_path);
+    } else if args.is_present("watch") {
+        let sub_args = args.subcommand_matches("watch").unwrap();
+        librarian_watch(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            &sub_args
+                .values_of("inbox")
+                .map(|i| i.map(PathBuf::from).collect::<Vec<_>>())
+                .unwrap_or_default(),
+            sub_args
+                .value_of("settle")
+                .unwrap()
+                .parse()
+                .expect("settle must be a number of seconds"),
+            sub_args.is_present("detect doi"),
+            sub_args.is_present("fetch metadata"),
+            sub_args
+                .value_of("attach duplicates")
+                .expect("attach-duplicates requires a value"),
+            sub_args.is_present("delete duplicates"),
+            sub_args.is_present("follow symlinks"),
+            index(sub_args),
+        );
+    } else if args.is_present("check") {
+        let sub_args = args.subcommand_matches("check").unwrap();
+        librarian_check(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.is_present("detect type"),
+            sub_args.value_of("fix").expect("fix requires a value"),
+        );
+    } else if args.is_present("fmt") {
+        librarian_fmt(
+            &mut catalog_store,
             &mut catalog,
             &resources_path,
-            args.subcommand_matches("catalog")
+            dry_run,
+        );
+    } else if args.is_present("lint") {
+        let sub_args = args.subcommand_matches("lint").unwrap();
+        // Violations fail the command, e.g., to stop a commit from a
+        // pre-commit hook.
+        if librarian_lint(
+            &catalog,
+            sub_args.is_present("check urls"),
+            jobs(sub_args),
+        ) > 0
+        {
+            std::process::exit(1);
+        }
+    } else if args.is_present("linkcheck") {
+        let sub_args = args.subcommand_matches("linkcheck").unwrap();
+        librarian_linkcheck(
+            &catalog,
+            &resources_path,
+            sub_args
+                .value_of("delay")
                 .unwrap()
-                .is_present("cache"),
-            args.subcommand_matches("catalog")
+                .parse()
+                .expect("delay must be a number of seconds"),
+            sub_args.is_present("archive"),
+            jobs(sub_args),
+            sub_args.is_present("resume"),
+            args.is_present("safe mode"),
+        );
+    } else if args.is_present("archive") {
+        librarian_archive(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            args.subcommand_matches("archive")
                 .unwrap()
-                .value_of("remove orphans")
-                .expect("remove-orphans requires a value"),
+                .value_of("query")
+                .expect("must provide a resource"),
+        );
+    } else if args.is_present("enrich") {
+        let sub_args = args.subcommand_matches("enrich").unwrap();
+        librarian_enrich(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            jobs(sub_args),
+            sub_args.is_present("resume"),
+            sub_args.is_present("overwrite"),
         );
     } else if args.is_present("instantiate") {
-        librarian_instantiate(&catalog);
+        librarian_instantiate(
+            &catalog,
+            &resources_path,
+            &args
+                .subcommand_matches("instantiate")
+                .unwrap()
+                .values_of("instances")
+                .map(|i| i.collect::<Vec<&str>>())
+                .unwrap_or_default(),
+        );
     } else if args.is_present("search") {
+        let sub_args = args.subcommand_matches("search").unwrap();
         librarian_search(
             &catalog,
-            args.subcommand_matches("search")
+            &resources_path,
+            sub_args.value_of("query").unwrap_or(""),
+            sub_args.value_of("state"),
+            sub_args.is_present("notes"),
+            date_arg(sub_args, "added since").as_ref(),
+            date_arg(sub_args, "modified since").as_ref(),
+            match sub_args.value_of("sort") {
+                Some("added") => SearchOrder::Added,
+                Some("modified") => SearchOrder::Modified,
+                _ => SearchOrder::Relevance,
+            },
+            sub_args.is_present("retired"),
+        );
+    } else if args.is_present("queue") {
+        librarian_queue(&catalog);
+    } else if let Some((
+        operation @ ("mark-read" | "mark-reading" | "mark-unread" | "rate"
+        | "prioritize"),
+        sub_args,
+    )) = args.subcommand()
+    {
+        let change = match operation {
+            "mark-read" => ReadingChange::Status(ReadStatus::Read),
+            "mark-reading" => ReadingChange::Status(ReadStatus::Reading),
+            "mark-unread" => ReadingChange::Status(ReadStatus::Unread),
+            "rate" => ReadingChange::Rating(
+                sub_args.value_of("rating").unwrap().parse().unwrap(),
+            ),
+            _ => ReadingChange::Priority(
+                Priority::parse(sub_args.value_of("priority").unwrap())
+                    .unwrap(),
+            ),
+        };
+        librarian_set_reading(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args
+                .value_of("resource")
+                .expect("must provide a resource"),
+            operation,
+            change,
+        );
+    } else if args.is_present("note") {
+        librarian_note(
+            &catalog,
+            &resources_path,
+            args.subcommand_matches("note")
+                .unwrap()
+                .value_of("resource")
+                .expect("must provide a resource"),
+        );
+    } else if args.is_present("open") {
+        librarian_open(
+            &catalog,
+            &resources_path,
+            args.subcommand_matches("open")
+                .unwrap()
+                .value_of("resource")
+                .expect("must provide a resource"),
+        );
+    } else if args.is_present("show") {
+        let sub_args = args.subcommand_matches("show").unwrap();
+        librarian_show(
+            &catalog,
+            sub_args
+                .value_of("checksum")
+                .expect("must provide a checksum"),
+            sub_args.is_present("related"),
+        );
+    } else if args.is_present("fetch") {
+        let sub_args = args.subcommand_matches("fetch").unwrap();
+        librarian_fetch(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.value_of("url").expect("must provide a URL"),
+            sub_args.value_of("sha1"),
+            sub_args.value_of("name"),
+        );
+    } else if args.is_present("fetch-file") {
+        let sub_args = args.subcommand_matches("fetch-file").unwrap();
+        librarian_fetch_file(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args
+                .value_of("identifier")
+                .expect("must provide a DOI or arXiv identifier"),
+            sub_args.value_of("email"),
+        );
+    } else if args.is_present("capture") {
+        let sub_args = args.subcommand_matches("capture").unwrap();
+        let values = |name| {
+            sub_args
+                .values_of(name)
+                .map(|v| v.map(String::from).collect())
+                .unwrap_or_default()
+        };
+        librarian_capture(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.value_of("source").expect("must provide a source"),
+            &Capture {
+                title: sub_args.value_of("title").map(String::from),
+                authors: values("author"),
+                tags: values("tag"),
+            },
+        );
+    } else if args.is_present("add-url") {
+        let sub_args = args.subcommand_matches("add-url").unwrap();
+        librarian_add_url(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.value_of("url").expect("must provide a URL"),
+            sub_args.is_present("single file"),
+        );
+    } else if args.is_present("split") {
+        let sub_args = args.subcommand_matches("split").unwrap();
+        librarian_split(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args
+                .value_of("checksum")
+                .expect("must provide a checksum"),
+            Path::new(sub_args.value_of("file").expect("must provide a file")),
+            sub_args.value_of("title"),
+        );
+    } else if args.is_present("merge-resources") {
+        let sub_args = args.subcommand_matches("merge-resources").unwrap();
+        librarian_merge_resources(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.value_of("keep").expect("must provide a checksum"),
+            sub_args.value_of("other").expect("must provide a checksum"),
+            match sub_args.value_of("prefer") {
+                Some("newer") => MergePreference::Newer,
+                Some("ask") => MergePreference::Ask,
+                _ => MergePreference::Kept,
+            },
+            !sub_args.is_present("remove file"),
+        );
+    } else if args.is_present("sync") {
+        let sub_args = args.subcommand_matches("sync").unwrap();
+        librarian_sync(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            &catalog_path,
+            sub_args.value_of("remote").expect("must provide a remote"),
+            match sub_args.value_of("prefer") {
+                Some("local") => SyncPreference::Local,
+                Some("remote") => SyncPreference::Remote,
+                _ => SyncPreference::Ask,
+            },
+        );
+    } else if args.is_present("dedupe") {
+        librarian_dedupe(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            args.subcommand_matches("dedupe")
                 .unwrap()
+                .value_of("threshold")
+                .map_or(DEDUPE_THRESHOLD, |t| {
+                    t.parse().expect("threshold must be a number")
+                }),
+        );
+    } else if args.is_present("graph") {
+        let sub_args = args.subcommand_matches("graph").unwrap();
+        librarian_graph(
+            &catalog,
+            sub_args.value_of("file"),
+            match sub_args.value_of("format") {
+                Some("json") => GraphFormat::Json,
+                _ => GraphFormat::Dot,
+            },
+            sub_args.is_present("include private"),
+        );
+    } else if args.is_present("bibtex") {
+        let sub_args = args.subcommand_matches("bibtex").unwrap();
+        // Incomplete entries fail the check, like lint violations.
+        if librarian_bibtex(
+            &catalog,
+            &resources_path,
+            sub_args.value_of("file"),
+            sub_args.is_present("sanitize"),
+            sub_args.is_present("include private"),
+            &ResourceFilter {
+                query: sub_args.value_of("query").map(String::from),
+                tags: sub_args
+                    .values_of("tag")
+                    .map(|t| t.map(String::from).collect())
+                    .unwrap_or_default(),
+                content: sub_args.value_of("content").map(String::from),
+                state: sub_args.value_of("state").map(String::from),
+                collection: sub_args.value_of("collection").map(String::from),
+                retired: sub_args.is_present("retired"),
+            },
+            sub_args.is_present("protect titles"),
+            &match (
+                sub_args.value_of("file instance"),
+                sub_args.is_present("file both"),
+            ) {
+                (Some(i), false) => FileField::Instance(i.to_string()),
+                (Some(i), true) => FileField::Both(i.to_string()),
+                (None, _) => FileField::Storage,
+            },
+            sub_args.is_present("merge"),
+            sub_args.is_present("check"),
+        ) > 0
+        {
+            std::process::exit(1);
+        }
+    } else if args.is_present("fetch-metadata") {
+        let sub_args = args.subcommand_matches("fetch-metadata").unwrap();
+        librarian_fetch_metadata(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args
+                .value_of("checksum")
+                .expect("must provide a resource checksum"),
+            sub_args.value_of("doi"),
+            sub_args.value_of("arxiv"),
+            sub_args.value_of("provider"),
+        );
+    } else if args.is_present("import-bibtex") {
+        let sub_args = args.subcommand_matches("import-bibtex").unwrap();
+        librarian_import_bibtex(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            &PathBuf::from(
+                sub_args
+                    .value_of("file")
+                    .expect("must provide a BibTeX file"),
+            ),
+            sub_args.is_present("interactive"),
+        );
+    } else if args.is_present("import-ris") {
+        let sub_args = args.subcommand_matches("import-ris").unwrap();
+        librarian_import_ris(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            &PathBuf::from(
+                sub_args.value_of("file").expect("must provide an RIS file"),
+            ),
+            sub_args.is_present("interactive"),
+        );
+    } else if args.is_present("import-csv") {
+        let sub_args = args.subcommand_matches("import-csv").unwrap();
+        librarian_import_csv(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            &PathBuf::from(
+                sub_args.value_of("file").expect("must provide a CSV file"),
+            ),
+            sub_args
+                .value_of("profile")
+                .expect("must provide an import profile"),
+            sub_args.is_present("interactive"),
+        );
+    } else if args.is_present("export-ris") {
+        let sub_args = args.subcommand_matches("export-ris").unwrap();
+        librarian_export_ris(
+            &catalog,
+            &resources_path,
+            sub_args.value_of("file"),
+            sub_args.value_of("collection"),
+            sub_args.is_present("include private"),
+        );
+    } else if args.is_present("export-org")
+        || args.is_present("export-markdown")
+    {
+        let (sub_args, format) = match args.subcommand() {
+            Some(("export-org", a)) => (a, IndexFormat::Org),
+            Some((_, a)) => (a, IndexFormat::Markdown),
+            None => unreachable!(),
+        };
+        librarian_export_index(
+            &catalog,
+            &resources_path,
+            sub_args.value_of("file"),
+            format,
+            sub_args.value_of("query"),
+            sub_args.value_of("collection"),
+            sub_args.is_present("include private"),
+            sub_args.value_of("name format").map(|f| match f {
+                "last-first" => NameFormat::LastFirst,
+                "initials-last" => NameFormat::InitialsLast,
+                "last-initials" => NameFormat::LastInitials,
+                _ => NameFormat::FirstLast,
+            }),
+        );
+    } else if args.is_present("authors") {
+        let sub_args = args.subcommand_matches("authors").unwrap();
+        match sub_args.subcommand() {
+            Some(("merge", merge_args)) => librarian_merge_authors(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                merge_args.value_of("from").expect("must provide a name"),
+                merge_args.value_of("to").expect("must provide a name"),
+            ),
+            _ => librarian_authors(
+                &catalog,
+                sub_args.value_of("author"),
+                sub_args.is_present("suggest"),
+            ),
+        }
+    } else if args.is_present("bulk-edit") {
+        let sub_args = args.subcommand_matches("bulk-edit").unwrap();
+        let values = |name| {
+            sub_args
+                .values_of(name)
+                .map(|v| v.map(String::from).collect())
+                .unwrap_or_default()
+        };
+        let set: Vec<String> = values("set");
+        librarian_bulk_edit(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args
                 .value_of("query")
                 .expect("must provide a search query"),
+            &BulkEdit {
+                add_tags: values("add tag"),
+                remove_tags: values("remove tag"),
+                create_tags: sub_args.is_present("create tags"),
+                set: set
+                    .iter()
+                    .map(|s| match s.split_once('=') {
+                        Some((f, v)) => (f.to_string(), v.to_string()),
+                        None => panic!("Expected FIELD=VALUE, found {}", s),
+                    })
+                    .collect(),
+            },
+            sub_args.is_present("all"),
         );
-    } else if args.is_present("bibtex") {
-        librarian_bibtex(
+    } else if args.is_present("tag") {
+        let value = |sub_args: &clap::ArgMatches, name| {
+            sub_args
+                .value_of(name)
+                .unwrap_or_else(|| panic!("must provide {}", name))
+                .to_string()
+        };
+        match args.subcommand_matches("tag").unwrap().subcommand() {
+            Some(("add", sub_args)) => librarian_tag_add(
+                &mut catalog_store,
+                &mut catalog,
+                &value(sub_args, "tag"),
+            ),
+            Some(("rm", sub_args)) => librarian_tag_rm(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "tag"),
+            ),
+            Some(("rename", sub_args)) => librarian_tag_rename(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "old"),
+                &value(sub_args, "new"),
+            ),
+            Some(("merge", sub_args)) => librarian_tag_merge(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "from"),
+                &value(sub_args, "into"),
+            ),
+            Some(("apply", sub_args)) => librarian_tag_apply(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "tag"),
+                &value(sub_args, "query"),
+                sub_args.is_present("remove"),
+                sub_args.is_present("yes"),
+            ),
+            Some(("list", sub_args)) => librarian_tag_list(
+                &mut catalog_store,
+                &mut catalog,
+                sub_args.is_present("create"),
+            ),
+            _ => librarian_tag_list(&mut catalog_store, &mut catalog, false),
+        }
+    } else if args.is_present("attachment") {
+        let value = |sub_args: &clap::ArgMatches, name| {
+            sub_args
+                .value_of(name)
+                .unwrap_or_else(|| panic!("must provide {}", name))
+                .to_string()
+        };
+        match args.subcommand_matches("attachment").unwrap().subcommand() {
+            Some(("add", sub_args)) => librarian_attachment_add(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "checksum"),
+                &sub_args
+                    .values_of("files")
+                    .expect("must provide files to attach")
+                    .collect::<Vec<&str>>(),
+                sub_args.value_of("description"),
+            ),
+            Some(("rm", sub_args)) => librarian_attachment_rm(
+                &mut catalog_store,
+                &mut catalog,
+                &resources_path,
+                &value(sub_args, "checksum"),
+                &value(sub_args, "name"),
+            ),
+            Some(("list", sub_args)) => librarian_attachment_list(
+                &catalog,
+                &resources_path,
+                &value(sub_args, "checksum"),
+            ),
+            _ => panic!("must provide an attachment subcommand"),
+        }
+    } else if args.is_present("collection") {
+        let value = |sub_args: &clap::ArgMatches, name| {
+            sub_args
+                .value_of(name)
+                .unwrap_or_else(|| panic!("must provide {}", name))
+                .to_string()
+        };
+        fn checksums(sub_args: &clap::ArgMatches) -> Vec<&str> {
+            sub_args
+                .values_of("checksums")
+                .map(|c| c.collect())
+                .unwrap_or_default()
+        }
+        match args.subcommand_matches("collection").unwrap().subcommand() {
+            Some(("create", sub_args)) => librarian_collection_create(
+                &mut catalog_store,
+                &mut catalog,
+                &value(sub_args, "name"),
+                &checksums(sub_args),
+            ),
+            Some(("add", sub_args)) => librarian_collection_add(
+                &mut catalog_store,
+                &mut catalog,
+                &value(sub_args, "name"),
+                &checksums(sub_args),
+                sub_args.value_of("position").map(|p| {
+                    p.parse().expect("position must be a positive number")
+                }),
+            ),
+            Some(("rm", sub_args)) => librarian_collection_rm(
+                &mut catalog_store,
+                &mut catalog,
+                &value(sub_args, "name"),
+                &checksums(sub_args),
+            ),
+            Some(("show", sub_args)) => {
+                librarian_collection_show(&catalog, &value(sub_args, "name"))
+            }
+            _ => librarian_collection_list(&catalog),
+        }
+    } else if args.is_present("styles") {
+        let library = resources_path
+            .parent()
+            .expect("resources path does not have a parent");
+        match args.subcommand_matches("styles").unwrap().subcommand() {
+            Some(("add", sub_args)) => librarian_styles_add(
+                library,
+                sub_args
+                    .value_of("source")
+                    .expect("must provide a style file or URL"),
+                dry_run,
+            ),
+            _ => librarian_styles_list(library),
+        }
+    } else if args.is_present("state") {
+        let sub_args = args
+            .subcommand_matches("state")
+            .unwrap()
+            .subcommand_matches("set")
+            .expect("must provide a state subcommand");
+        librarian_state_set(
+            &mut catalog_store,
+            &mut catalog,
+            sub_args
+                .value_of("checksum")
+                .expect("must provide a resource checksum"),
+            sub_args.value_of("state").expect("must provide a state"),
+            sub_args.is_present("force"),
+        );
+    } else if args.is_present("mirror") {
+        let sub_args = args.subcommand_matches("mirror").unwrap();
+        librarian_mirror(
+            &catalog,
+            &resources_path,
+            &PathBuf::from(
+                sub_args
+                    .value_of("dest")
+                    .expect("must provide a mirror destination"),
+            ),
+            sub_args.value_of("signer").expect("must provide a signer"),
+            sub_args.value_of("key"),
+            sub_args.is_present("include private"),
+        );
+    } else if args.is_present("verify-mirror") {
+        librarian_verify_mirror(&PathBuf::from(
+            args.subcommand_matches("verify-mirror")
+                .unwrap()
+                .value_of("mirror")
+                .expect("must provide a mirror directory"),
+        ));
+    } else if args.is_present("export-bundle") {
+        let sub_args = args.subcommand_matches("export-bundle").unwrap();
+        librarian_export_bundle(
+            &catalog,
+            &resources_path,
+            sub_args.value_of("query").expect("must provide a query"),
+            Path::new(
+                sub_args.value_of("output").expect("must provide an output"),
+            ),
+            sub_args.is_present("include private"),
+        );
+    } else if args.is_present("import-bundle") {
+        librarian_import_bundle(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            Path::new(
+                args.subcommand_matches("import-bundle")
+                    .unwrap()
+                    .value_of("bundle")
+                    .expect("must provide a bundle"),
+            ),
+        );
+    } else if args.is_present("serve") {
+        let sub_args = args.subcommand_matches("serve").unwrap();
+        librarian_serve(
+            &mut catalog_store,
+            &catalog_path,
+            &resources_path,
+            sub_args.value_of("address").unwrap_or(SERVE_ADDRESS),
+            sub_args.is_present("write"),
+            sub_args.is_present("include private"),
+            index(sub_args),
+        );
+    } else if args.is_present("migrate-storage") {
+        librarian_migrate_storage(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            match args
+                .subcommand_matches("migrate-storage")
+                .unwrap()
+                .value_of("layout")
+                .expect("must provide a storage layout")
+            {
+                "sharded" => StorageLayout::Sharded,
+                _ => StorageLayout::Flat,
+            },
+        );
+    } else if args.is_present("used") {
+        let sub_args = args.subcommand_matches("used").unwrap();
+        librarian_used(
+            &catalog,
+            &resources_path,
+            &PathBuf::from(
+                sub_args
+                    .value_of("tex-dir")
+                    .expect("must provide a project directory"),
+            ),
+            sub_args.value_of("bib"),
+        );
+    } else if args.is_present("unused") {
+        let sub_args = args.subcommand_matches("unused").unwrap();
+        librarian_unused(
+            &catalog,
+            &PathBuf::from(
+                sub_args
+                    .value_of("bib")
+                    .expect("must provide a BibTeX file"),
+            ),
+            &PathBuf::from(
+                sub_args
+                    .value_of("tex-dir")
+                    .expect("must provide a project directory"),
+            ),
+        );
+    } else if args.is_present("undo") {
+        librarian_undo(&mut catalog_store, &mut catalog, &resources_path);
+    } else if args.is_present("log") {
+        librarian_log(
+            &library,
+            &catalog_path,
+            args.subcommand_matches("log")
+                .unwrap()
+                .value_of("count")
+                .unwrap()
+                .parse()
+                .expect("count must be a positive number"),
+        );
+    } else if args.is_present("diff") {
+        let sub_args = args.subcommand_matches("diff").unwrap();
+        librarian_diff(
+            &library,
+            &catalog_path,
+            &catalog,
+            sub_args.value_of("old").unwrap(),
+            sub_args.value_of("new"),
+            sub_args.is_present("json"),
+        );
+    } else if args.is_present("resolve") {
+        let sub_args = args.subcommand_matches("resolve").unwrap();
+        librarian_resolve(
+            &catalog,
+            &resources_path,
+            sub_args.value_of("checksum").unwrap(),
+            sub_args.is_present("path"),
+        );
+    } else if args.is_present("versions") {
+        librarian_versions(
             &catalog,
             &resources_path,
-            args.subcommand_matches("bibtex").unwrap().value_of("file"),
+            args.subcommand_matches("versions")
+                .unwrap()
+                .value_of("query")
+                .unwrap(),
+        );
+    } else if args.is_present("checkout") {
+        let sub_args = args.subcommand_matches("checkout").unwrap();
+        librarian_checkout(
+            &mut catalog_store,
+            &mut catalog,
+            &resources_path,
+            sub_args.value_of("query").unwrap(),
+            sub_args.value_of("revision").unwrap(),
+        );
+    } else if args.is_present("fingerprint") {
+        librarian_fingerprint(&catalog);
+    } else if args.is_present("schema") {
+        librarian_schema(
+            args.subcommand_matches("schema")
+                .unwrap()
+                .is_present("resource"),
         );
+    } else if args.is_present("convert-catalog") {
+        let sub_args = args.subcommand_matches("convert-catalog").unwrap();
+        let destination = PathBuf::from(
+            sub_args
+                .value_of("destination")
+                .expect("must provide a destination"),
+        );
+        if sub_args.is_present("split") {
+            std::fs::create_dir_all(&destination)
+                .expect("failed to create split catalog directory");
+        }
+        CatalogStore::open(&destination).write(&mut catalog);
     } else {
         panic!("Subcommand required.");
     }
+
+    if let Some(journal) = journal.as_ref().filter(|j| j.changed()) {
+        let stored = catalog_store.read();
+        for resource in journal.added(&stored) {
+            run_hook(
+                &library,
+                "post-add-resource",
+                &[("LIBRARIAN_CHECKSUM", &resource.historical_checksums[0])],
+                Some(&serde_json::to_string_pretty(resource).unwrap()),
+            );
+        }
+        if stored.git {
+            commit_catalog(&library, &catalog_path, &operation, &stored);
+        }
+    }
+
+    if args.is_present("timings") {
+        timings::report(start);
+    }
 }
 
 /// Parse and return command line arguments.
@@ -95,6 +1045,28 @@ fn parse_app_args() -> clap::ArgMatches {
                 .long("resources")
                 .default_value("resources"),
         )
+        .arg(
+            Arg::new("safe mode")
+                .about("skip ancillary stores such as the cache and queue them for rebuild")
+                .long_about("Use this when a corrupted cache or links file keeps a command from running. Stores that fail to parse are skipped automatically, with a warning.")
+                .long("safe-mode"),
+        )
+        .arg(
+            Arg::new("dry run")
+                .about("print the changes to the catalog without making them")
+                .long_about("Nothing is moved, deleted or written, the operation isn't journaled and hooks aren't run. catalog answers its questions no without asking them, so pass --remove-orphans true or --delete-duplicates to see the changes they would make. Files that a command downloads, or writes outside of the library, are still written.")
+                .global(true)
+                .long("dry-run"),
+        )
+        .arg(
+            Arg::new("timings")
+                .about("report the time spent walking, hashing, in cache I/O and in catalog serialization")
+                .long("timings"),
+        )
+        .subcommand(
+            App::new("init")
+                .about("create a library, or give an existing library an identifier"),
+        )
         .subcommand(
             App::new("catalog")
                 .about("catalogs all new original resources")
@@ -107,33 +1079,1400 @@ fn parse_app_args() -> clap::ArgMatches {
                 )
                 .arg(
                     Arg::new("remove orphans")
-                        .about("prompt to remove orphans, or don't ask and don't remove, or don't ask and do remove")
+                        .about("prompt to retire orphans, or don't ask and don't retire, or don't ask and do retire")
+                        .long_about("Orphans are cataloged resources whose file is no longer in the resources directory. Retired resources keep their metadata, but are left out of searches and exports, and come back when their file does.")
                         .takes_value(true)
                         .default_value("ask")
                         .possible_values(&["ask", "true", "false"])
                         .long("remove-orphans"),
                 )
+                .arg(
+                    Arg::new("purge retired")
+                        .about("remove retired resources from the catalog, including orphans retired now")
+                        .long("purge-retired"),
+                )
+                .arg(
+                    Arg::new("detect doi")
+                        .about("search the first pages of new PDF resources for a DOI")
+                        .long("detect-doi"),
+                )
+                .arg(
+                    Arg::new("fetch metadata")
+                        .about("fetch metadata from CrossRef for new resources with a detected DOI")
+                        .requires("detect doi")
+                        .long("fetch-metadata"),
+                )
+                .arg(
+                    Arg::new("attach duplicates")
+                        .about("prompt to add a new file having the same DOI or ISBN as a resource to the resource's attachments, or don't ask and catalog it separately, or don't ask and attach it")
+                        .takes_value(true)
+                        .default_value("ask")
+                        .possible_values(&["ask", "true", "false"])
+                        .long("attach-duplicates"),
+                )
+                .arg(
+                    Arg::new("no query")
+                        .about("don't ask any questions, and answer no to those no other flag answers")
+                        .long_about("Orphans are kept, and duplicates are neither attached nor deleted, unless --remove-orphans, --attach-duplicates or --delete-duplicates say otherwise. Useful when cataloging from a script.")
+                        .long("no-query"),
+                )
+                .arg(
+                    Arg::new("delete duplicates")
+                        .about("delete new files with the same content as another file without prompting")
+                        .long("delete-duplicates"),
+                )
+                .arg(
+                    Arg::new("keep duplicates")
+                        .about("keep new files with the same content as another file without prompting, and don't catalog them")
+                        .conflicts_with("delete duplicates")
+                        .long("keep-duplicates"),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .about("prompt for the title, authors, date, tags, content type and document type of each new resource")
+                        .long_about("Each prompt starts from the value found in the file or its name, which is kept by pressing enter. Tab completes authors, tags and types already used in the library.")
+                        .short('i')
+                        .long("interactive"),
+                )
+                .arg(
+                    Arg::new("follow symlinks")
+                        .about("catalog the targets of symbolic links in the resources directory instead of skipping them")
+                        .long_about("Links are cataloged where they are, as their target, and are never renamed. Removing a duplicate link removes the link, not its target.")
+                        .long("follow-symlinks"),
+                )
         )
         .subcommand(
-            App::new("instantiate").about("instantiates one or more instances from the catalog"),
+            App::new("cache")
+                .about("inspect and maintain the cache of checksums")
+                .long_about("Without a subcommand, stats are printed. The cache lets catalog skip computing the checksum of files that haven't changed. It only holds data that can be regenerated, so clearing it is always safe.")
+                .subcommand(
+                    App::new("stats")
+                        .about("print the number of entries, the hits and misses of the last catalog and how long ago entries were verified"),
+                )
+                .subcommand(
+                    App::new("clear")
+                        .about("remove every entry, so that the next catalog computes every checksum"),
+                )
+                .subcommand(
+                    App::new("prune")
+                        .about("remove the entries that don't belong to a cataloged resource"),
+                ),
         )
         .subcommand(
-            App::new("search")
-                .about("retrieve a resource based on its metainformation")
-                .arg(Arg::new("query").about("resource query").takes_value(true)),
+            App::new("todo")
+                .about("list the resources lacking key fields, grouped by field")
+                .long_about("By default, resources without authors, a date, a content type or tags are listed.")
+                .arg(
+                    Arg::new("field")
+                        .about("field to check, instead of the default fields")
+                        .long_about("May be given several times. Custom fields are written as extra.NAME.")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("field"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .about("print the number of resources lacking each field and the resources themselves as JSON")
+                        .long("json"),
+                ),
         )
         .subcommand(
-            App::new("bibtex")
-                .about("generate a BibTeX bibliography")
+            App::new("review")
+                .about("review the resources pending in the inbox and add them to the library")
+                .long_about("Files dropped into the inbox directory of the library are staged as pending resources, by this command or by catalog. Each pending resource is shown in turn, and its metadata can be fetched, its fields edited and its tags assigned before it's accepted into the resources directory."),
+        )
+        .subcommand(
+            App::new("watch")
+                .about("catalog new resources as they appear, until interrupted")
+                .long_about("Only the files that change are hashed, and the cache is kept up to date, so there's no need to run catalog afterwards. Files with the content of an existing resource are reported and left uncataloged, unless --delete-duplicates is given.")
                 .arg(
-                    Arg::new("file")
-                        .about("file to write BibTeX data to")
-                        .long_about(
-                            "If this argument is omitted, BibTeX data will be written to stdout.",
-                        ),
+                    Arg::new("inbox")
+                        .about("also take new files from a directory, such as ~/Downloads")
+                        .long_about("May be given several times. Files are moved from the inbox into the resources directory before they're cataloged, including those already there.")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("inbox"),
+                )
+                .arg(
+                    Arg::new("settle")
+                        .about("seconds without changes before new files are cataloged")
+                        .takes_value(true)
+                        .default_value("2")
+                        .long("settle"),
+                )
+                .arg(
+                    Arg::new("detect doi")
+                        .about("search the first pages of new PDF resources for a DOI")
+                        .long("detect-doi"),
+                )
+                .arg(
+                    Arg::new("fetch metadata")
+                        .about("fetch metadata from CrossRef for new resources with a detected DOI")
+                        .requires("detect doi")
+                        .long("fetch-metadata"),
+                )
+                .arg(
+                    Arg::new("attach duplicates")
+                        .about("prompt to add a new file having the same DOI or ISBN as a resource to the resource's attachments, or don't ask and catalog it separately, or don't ask and attach it")
+                        .takes_value(true)
+                        .default_value("ask")
+                        .possible_values(&["ask", "true", "false"])
+                        .long("attach-duplicates"),
+                )
+                .arg(
+                    Arg::new("delete duplicates")
+                        .about("delete new files with the same content as an existing resource")
+                        .long("delete-duplicates"),
+                )
+                .arg(
+                    Arg::new("follow symlinks")
+                        .about("catalog the targets of symbolic links in the resources directory instead of skipping them")
+                        .long_about("Links are cataloged where they are, as their target, and are never renamed. Removing a duplicate link removes the link, not its target.")
+                        .long("follow-symlinks"),
+                )
+                .arg(index_arg())
+                .arg(index_delay_arg())
+        )
+        .subcommand(
+            App::new("check")
+                .about("check cataloged resources for problems")
+                .arg(
+                    Arg::new("detect type")
+                        .about("compare the content type of each file with its document type")
+                        .long_about("Flags files whose content doesn't match the extension of their document type, such as an HTML error page saved as a PDF, and resources without a document type whose content is recognized. Fixing them sets the detected type, adding a document type to the catalog if needed.")
+                        .long("detect-type"),
+                )
+                .arg(
+                    Arg::new("fix")
+                        .about("prompt to fix problems, or don't ask and don't fix, or don't ask and do fix")
+                        .takes_value(true)
+                        .default_value("ask")
+                        .possible_values(&["ask", "true", "false"])
+                        .long("fix"),
                 ),
         )
-        .get_matches()
+        .subcommand(
+            App::new("fmt")
+                .about("apply the catalog's style rules to titles and journal names"),
+        )
+        .subcommand(
+            App::new("lint")
+                .about("check the resources against the lint rules of the catalog")
+                .long_about("Exits with a non-zero status if any resource breaks a rule, so that it can be run from a git pre-commit hook.")
+                .arg(
+                    Arg::new("check urls")
+                        .about("flag resources whose URL can't be reached, even if the rules don't")
+                        .long("check-urls"),
+                )
+                .arg(jobs_arg()),
+        )
+        .subcommand(
+            App::new("linkcheck")
+                .about("check the URL of each resource for dead links")
+                .arg(
+                    Arg::new("delay")
+                        .about("seconds to wait between requests")
+                        .takes_value(true)
+                        .default_value("1")
+                        .long("delay"),
+                )
+                .arg(
+                    Arg::new("archive")
+                        .about("archive live links with the Wayback Machine")
+                        .long("archive"),
+                )
+                .arg(jobs_arg())
+                .arg(resume_arg()),
+        )
+        .subcommand(
+            App::new("archive")
+                .about("archive the URL of a resource with the Wayback Machine")
+                .long_about("The snapshot URL and its date are recorded on the resource and exported as BibLaTeX's archiveurl and urldate fields.")
+                .arg(
+                    Arg::new("query")
+                        .about("checksum of the resource or search query matching it")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("enrich")
+                .about("fetch the metadata of every resource with a DOI or arXiv identifier, or one a metadata provider accepts")
+                .long_about("Fetched metadata only fills missing fields unless --overwrite is given. Ctrl-C stops after the requests in flight and writes the metadata fetched so far.")
+                .arg(
+                    Arg::new("overwrite")
+                        .about("replace existing fields with the fetched metadata")
+                        .long("overwrite"),
+                )
+                .arg(jobs_arg())
+                .arg(resume_arg()),
+        )
+        .subcommand(
+            App::new("instantiate")
+                .about("instantiates one or more instances from the catalog")
+                .arg(
+                    Arg::new("instances")
+                        .about("instances to instantiate")
+                        .long_about("If no instances are given, every instance is instantiated.")
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            App::new("search")
+                .about("retrieve a resource based on its metainformation")
+                .arg(Arg::new("query").about("resource query").takes_value(true))
+                .arg(
+                    Arg::new("state")
+                        .about("only print resources in a lifecycle state")
+                        .takes_value(true)
+                        .long("state"),
+                )
+                .arg(
+                    Arg::new("notes")
+                        .about("also search the reading notes of resources")
+                        .long("notes"),
+                )
+                .arg(
+                    Arg::new("added since")
+                        .about("only print resources added on or after a date (e.g., 2024-01-01)")
+                        .takes_value(true)
+                        .long("added-since"),
+                )
+                .arg(
+                    Arg::new("modified since")
+                        .about("only print resources modified on or after a date (e.g., 2024-01-01)")
+                        .takes_value(true)
+                        .long("modified-since"),
+                )
+                .arg(
+                    Arg::new("sort")
+                        .about("order of the printed resources")
+                        .long_about("Resources are ordered by relevance, or newest first by the date they were added or modified.")
+                        .takes_value(true)
+                        .long("sort")
+                        .possible_values(&["relevance", "added", "modified"])
+                        .default_value("relevance"),
+                )
+                .arg(
+                    Arg::new("retired")
+                        .about("also print retired resources, whose file is gone")
+                        .long("retired"),
+                ),
+        )
+        .subcommand(
+            App::new("queue")
+                .about("list the resources left to read, in reading order")
+                .long_about("Resources being read come first, followed by unread resources of high, normal and low priority."),
+        )
+        .subcommand(mark_subcommand("mark-read", "mark a resource as read"))
+        .subcommand(mark_subcommand(
+            "mark-reading",
+            "mark a resource as being read",
+        ))
+        .subcommand(mark_subcommand("mark-unread", "mark a resource as unread"))
+        .subcommand(
+            App::new("rate")
+                .about("rate a resource from 1 (poor) to 5 (excellent)")
+                .arg(
+                    Arg::new("resource")
+                        .about("checksum of the resource or search query matching it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("rating")
+                        .about("rating of the resource")
+                        .possible_values(&["1", "2", "3", "4", "5"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("prioritize")
+                .about("set how soon a resource should be read")
+                .arg(
+                    Arg::new("resource")
+                        .about("checksum of the resource or search query matching it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("priority")
+                        .about("priority of the resource")
+                        .possible_values(&["low", "normal", "high"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("note")
+                .about("open the reading notes of a resource in $EDITOR")
+                .arg(
+                    Arg::new("resource")
+                        .about("checksum of the resource or search query matching it")
+                        .long_about("When a query matches several resources, the resource is picked from a list of the best matches.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("open")
+                .about("open the file of a resource")
+                .long_about("The file is downloaded from the library's remote storage first if it isn't in the resources directory.")
+                .arg(
+                    Arg::new("resource")
+                        .about("checksum of the resource or search query matching it")
+                        .long_about("When a query matches several resources, the resource is picked from a list of the best matches.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("show")
+                .about("print a resource as JSON")
+                .arg(
+                    Arg::new("checksum")
+                        .about("current or historical checksum of the resource")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("related")
+                        .about("also print related resources, keyed by relation")
+                        .long("related"),
+                ),
+        )
+        .subcommand(
+            App::new("fetch")
+                .about("download a file and catalog it")
+                .long_about("Interrupted downloads are resumed, both while downloading and when the command is run again for the same URL. The file is only cataloged once it is complete and, when --sha1 is given, matches the expected checksum.")
+                .arg(
+                    Arg::new("url")
+                        .about("URL of the file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("sha1")
+                        .about("expected SHA-1 checksum of the file")
+                        .takes_value(true)
+                        .long("sha1"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .about("file name giving the initial title and document type, instead of the end of the URL")
+                        .takes_value(true)
+                        .long("name"),
+                ),
+        )
+        .subcommand(
+            App::new("fetch-file")
+                .about("download the open-access PDF of a DOI or arXiv identifier")
+                .long_about("PDFs come from arXiv and, for DOIs, from the open-access locations known to Unpaywall. A resource with the identifier whose file is missing receives the PDF as its file. Otherwise, a new resource is created with metadata from CrossRef or arXiv.")
+                .arg(
+                    Arg::new("identifier")
+                        .about("DOI or arXiv identifier of the work")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("email")
+                        .about("email address sent with Unpaywall requests, instead of UNPAYWALL_EMAIL")
+                        .takes_value(true)
+                        .long("email"),
+                ),
+        )
+        .subcommand(
+            App::new("capture")
+                .about("add a URL or file to the inbox in one step and print its citation key")
+                .long_about("Meant for editor and browser keybindings. Files are copied, and URLs downloaded, to the inbox as pending resources, which are reviewed later with `librarian review`. Only the citation key is printed to stdout, or the resource's checksum when the catalog has no citation key scheme. A file the library already has prints the key of the existing resource.")
+                .arg(
+                    Arg::new("source")
+                        .about("URL, file, or org-protocol URL with url and title parameters (e.g., org-protocol://librarian?url=...&title=...)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("title")
+                        .about("title of the resource")
+                        .takes_value(true)
+                        .long("title"),
+                )
+                .arg(
+                    Arg::new("author")
+                        .about("author of the resource, e.g., \"Ali Hajimiri\"")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("author"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .about("tag of the resource")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("tag"),
+                ),
+        )
+        .subcommand(
+            App::new("add-url")
+                .about("download a web page and catalog it")
+                .long_about("The page is stored as a directory holding index.html and the images, scripts and stylesheets it uses. Its title, authors and date are taken from the page.")
+                .arg(
+                    Arg::new("url")
+                        .about("URL of the page")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("single file")
+                        .about("store the page as one HTML file with everything it uses embedded")
+                        .long("single-file"),
+                ),
+        )
+        .subcommand(
+            App::new("split")
+                .about("catalog a second file with the metadata of a resource")
+                .long_about("This is meant for a file holding two works, or a work cataloged as two files. The new resource's metadata can then be edited independently.")
+                .arg(
+                    Arg::new("checksum")
+                        .about("current or historical checksum of the resource whose metadata is copied")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("file")
+                        .about("file of the new resource")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("title")
+                        .about("title of the new resource")
+                        .takes_value(true)
+                        .long("title"),
+                ),
+        )
+        .subcommand(
+            App::new("merge-resources")
+                .about("merge two resources describing the same work")
+                .long_about("The first resource is kept and takes over the metadata it lacks, the checksums, the relations and the collections of the second resource. The file of the second resource becomes an attachment of the first, unless it's removed.")
+                .visible_alias("merge")
+                .arg(
+                    Arg::new("keep")
+                        .about("checksum of the resource that is kept")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("other")
+                        .about("checksum of the resource that is merged into it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("prefer")
+                        .about("which value to keep when both resources set a field")
+                        .long_about("kept keeps the value of the first resource, newer the value of the resource modified most recently, and ask prompts for each field.")
+                        .takes_value(true)
+                        .possible_values(&["kept", "newer", "ask"])
+                        .default_value("kept")
+                        .long("prefer"),
+                )
+                .arg(
+                    Arg::new("remove file")
+                        .about("remove the file of the second resource instead of attaching it to the first")
+                        .long("remove-file"),
+                ),
+        )
+        .subcommand(
+            App::new("sync")
+                .about("synchronize the catalog and resources with a remote copy of the library")
+                .long_about("The remote is another library directory, an SSH location written host:path, which is reached with rsync, or an S3 bucket written s3://bucket/prefix, which is reached with the AWS command line interface. The catalogs are merged against the catalog as it was after the last sync with the same remote: changes made on one side are applied to the other, and fields changed on both sides are conflicts. Only the files of new or changed resources are copied, and both copies must use the same storage layout.")
+                .arg(
+                    Arg::new("remote")
+                        .about("remote library")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("prefer")
+                        .about("which value to keep when both copies changed a field")
+                        .long_about("local keeps the value of this copy, remote the value of the remote copy, and ask prompts for each field.")
+                        .takes_value(true)
+                        .possible_values(&["local", "remote", "ask"])
+                        .default_value("ask")
+                        .long("prefer"),
+                ),
+        )
+        .subcommand(
+            App::new("dedupe")
+                .about("find resources that are likely the same work and merge them")
+                .long_about("Resources with the same DOI, ISBN or arXiv identifier, or with similar titles and authors, such as the preprint and the published version of a paper, are shown side by side. Each pair can be merged, keeping the file of the merged resource as an attachment, kept as versions with the later one superseding the earlier one, or marked as distinct.")
+                .arg(
+                    Arg::new("threshold")
+                        .about("similarity from 0 to 1 from which resources are flagged (0.8 by default)")
+                        .takes_value(true)
+                        .long("threshold"),
+                ),
+        )
+        .subcommand(
+            App::new("graph")
+                .about("export the network of relations between resources")
+                .arg(
+                    Arg::new("file")
+                        .about("file to write the graph to")
+                        .long_about(
+                            "If this argument is omitted, the graph will be written to stdout.",
+                        ),
+                )
+                .arg(
+                    Arg::new("format")
+                        .about("format of the graph")
+                        .takes_value(true)
+                        .long("format")
+                        .possible_values(&["dot", "json"])
+                        .default_value("dot"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                ),
+        )
+        .subcommand(
+            App::new("bibtex")
+                .about("generate a BibTeX bibliography")
+                .arg(
+                    Arg::new("file")
+                        .about("file to write BibTeX data to")
+                        .long_about(
+                            "If this argument is omitted, BibTeX data will be written to stdout.",
+                        ),
+                )
+                .arg(
+                    Arg::new("sanitize")
+                        .about("prepare entries for submission with a manuscript")
+                        .long_about("Removes local-only fields, converts Unicode characters to LaTeX escapes and reports entries missing fields commonly required by journals.")
+                        .long("sanitize"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                )
+                .arg(
+                    Arg::new("retired")
+                        .about("include retired resources, whose file is gone")
+                        .long("retired"),
+                )
+                .arg(
+                    Arg::new("query")
+                        .about("only include resources matching a search query")
+                        .takes_value(true)
+                        .long("query"),
+                )
+                .arg(
+                    Arg::new("collection")
+                        .about("only include the resources of a collection, in collection order")
+                        .takes_value(true)
+                        .long("collection"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .about("only include resources with a tag")
+                        .long_about("May be given several times, in which case resources must have every tag.")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("tag"),
+                )
+                .arg(
+                    Arg::new("content")
+                        .about("only include resources with a content type")
+                        .takes_value(true)
+                        .long("content"),
+                )
+                .arg(
+                    Arg::new("state")
+                        .about("only include resources in a lifecycle state")
+                        .takes_value(true)
+                        .long("state"),
+                )
+                .arg(
+                    Arg::new("protect titles")
+                        .about("wrap titles in braces to preserve their capitalization")
+                        .long("protect-titles"),
+                )
+                .arg(
+                    Arg::new("check")
+                        .about("report resources missing fields BibLaTeX requires instead of generating entries")
+                        .long_about("Exits with status 1 when any resource is incomplete, e.g., to stop a build from a Makefile.")
+                        .long("check"),
+                )
+                .arg(
+                    Arg::new("merge")
+                        .about("merge the entries into an existing file")
+                        .long_about("Entries whose keys belong to cataloged resources are updated in place and every other entry, definition and comment of the file is kept. New entries are appended.")
+                        .conflicts_with("overwrite")
+                        .long("merge"),
+                )
+                .arg(
+                    Arg::new("overwrite")
+                        .about("replace an existing file with the generated entries (the default)")
+                        .long("overwrite"),
+                )
+                .arg(
+                    Arg::new("file instance")
+                        .about("point the file field to the resource in an instance")
+                        .long_about("Resources that aren't in the instance point to the resources directory.")
+                        .takes_value(true)
+                        .long("file-instance"),
+                )
+                .arg(
+                    Arg::new("file both")
+                        .about("list both the instance and the resources directory paths in the file field")
+                        .requires("file instance")
+                        .long("file-both"),
+                ),
+        )
+        .subcommand(
+            App::new("fetch-metadata")
+                .about("fill a resource's metadata from CrossRef, arXiv or a metadata provider")
+                .arg(
+                    Arg::new("checksum")
+                        .about("current or historical checksum of the resource")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("doi")
+                        .about("DOI to fetch metadata for from CrossRef")
+                        .long_about("If neither --doi nor --arxiv is given, the resource's existing DOI or arXiv eprint is used.")
+                        .takes_value(true)
+                        .long("doi"),
+                )
+                .arg(
+                    Arg::new("arxiv")
+                        .about("arXiv identifier to fetch metadata for from arXiv")
+                        .takes_value(true)
+                        .long("arxiv")
+                        .conflicts_with("doi"),
+                )
+                .arg(
+                    Arg::new("provider")
+                        .about("name of the metadata provider to fetch metadata from")
+                        .long_about("Resources without a DOI or arXiv identifier get their metadata from the first metadata provider of the catalog that has some.")
+                        .takes_value(true)
+                        .long("provider")
+                        .conflicts_with_all(&["doi", "arxiv"]),
+                ),
+        )
+        .subcommand(
+            App::new("import-bibtex")
+                .about("create or update resources from a BibTeX file")
+                .arg(
+                    Arg::new("file")
+                        .about("BibTeX or BibLaTeX file to import")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .about("prompt for the file of entries without a valid file field")
+                        .short('i')
+                        .long("interactive"),
+                ),
+        )
+        .subcommand(
+            App::new("import-ris")
+                .about("create or update resources from an RIS file")
+                .arg(
+                    Arg::new("file")
+                        .about("RIS file to import")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .about("prompt for the file of records without a valid L1 tag")
+                        .short('i')
+                        .long("interactive"),
+                ),
+        )
+        .subcommand(
+            App::new("import-csv")
+                .about("create or update resources from a CSV file")
+                .arg(
+                    Arg::new("file")
+                        .about("CSV file to import")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .about("import profile mapping the columns to fields")
+                        .long("profile")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .about("prompt for the file of rows without a valid file column")
+                        .short('i')
+                        .long("interactive"),
+                ),
+        )
+        .subcommand(
+            App::new("export-ris")
+                .about("generate an RIS file for reference managers")
+                .arg(
+                    Arg::new("file")
+                        .about("file to write RIS data to")
+                        .long_about(
+                            "If this argument is omitted, RIS data will be written to stdout.",
+                        ),
+                )
+                .arg(
+                    Arg::new("collection")
+                        .about("only export the resources of a collection, in collection order")
+                        .takes_value(true)
+                        .long("collection"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                ),
+        )
+        .subcommand(
+            App::new("export-org")
+                .about("generate an org-mode table indexing the library")
+                .arg(
+                    Arg::new("file")
+                        .about("file to write the table to")
+                        .long_about(
+                            "If this argument is omitted, the table will be written to stdout.",
+                        ),
+                )
+                .arg(
+                    Arg::new("query")
+                        .about("only include resources matching a search query")
+                        .takes_value(true)
+                        .long("query"),
+                )
+                .arg(
+                    Arg::new("collection")
+                        .about("only include the resources of a collection, in collection order")
+                        .takes_value(true)
+                        .long("collection"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                )
+                .arg(
+                    Arg::new("name format")
+                        .about("style of author names, instead of the catalog's name format")
+                        .takes_value(true)
+                        .possible_values(&["first-last", "last-first", "initials-last", "last-initials"])
+                        .long("name-format"),
+                ),
+        )
+        .subcommand(
+            App::new("export-markdown")
+                .about("generate a Markdown list indexing the library")
+                .arg(
+                    Arg::new("file")
+                        .about("file to write the list to")
+                        .long_about(
+                            "If this argument is omitted, the list will be written to stdout.",
+                        ),
+                )
+                .arg(
+                    Arg::new("query")
+                        .about("only include resources matching a search query")
+                        .takes_value(true)
+                        .long("query"),
+                )
+                .arg(
+                    Arg::new("collection")
+                        .about("only include the resources of a collection, in collection order")
+                        .takes_value(true)
+                        .long("collection"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                )
+                .arg(
+                    Arg::new("name format")
+                        .about("style of author names, instead of the catalog's name format")
+                        .takes_value(true)
+                        .possible_values(&["first-last", "last-first", "initials-last", "last-initials"])
+                        .long("name-format"),
+                ),
+        )
+        .subcommand(
+            App::new("authors")
+                .about("list authors along with their resources")
+                .long_about(
+                    "Spelling variants of a name listed in the catalog's author aliases are grouped under the canonical spelling.",
+                )
+                .arg(
+                    Arg::new("author")
+                        .about("only list the resources of this author"),
+                )
+                .arg(
+                    Arg::new("suggest")
+                        .about("print names that likely belong to the same author but aren't aliased")
+                        .long("suggest"),
+                )
+                .subcommand(
+                    App::new("merge")
+                        .about("rewrite an author's name in every resource that has it")
+                        .arg(Arg::new("from").about("spelling of the name that is replaced").required(true))
+                        .arg(Arg::new("to").about("spelling of the name that replaces it").required(true)),
+                ),
+        )
+        .subcommand(
+            App::new("bulk-edit")
+                .about("add or remove tags and edit fields of several resources at once")
+                .long_about("The resources matching the query are listed and the ones to edit are selected interactively.")
+                .arg(
+                    Arg::new("query")
+                        .about("search query whose results can be edited")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("add tag")
+                        .about("tag to add")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("add-tag"),
+                )
+                .arg(
+                    Arg::new("remove tag")
+                        .about("tag to remove")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("remove-tag"),
+                )
+                .arg(
+                    Arg::new("create tags")
+                        .about("add new tags to the catalog's tag tree")
+                        .long("create-tags"),
+                )
+                .arg(
+                    Arg::new("set")
+                        .about("set a field, given as FIELD=VALUE")
+                        .long_about("Values starting with [ and null are parsed as JSON, e.g., to set a list of authors or remove a field.")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("set"),
+                )
+                .arg(
+                    Arg::new("all")
+                        .about("edit every matching resource without asking")
+                        .long("all"),
+                ),
+        )
+        .subcommand(
+            App::new("tag")
+                .about("manage the tag tree and the tags of resources")
+                .long_about("Renaming, merging and removing a tag rewrites the tags of every affected resource in a single catalog update, which is recorded in the audit log.")
+                .subcommand(
+                    App::new("add")
+                        .about("add a tag to the tag tree")
+                        .arg(
+                            Arg::new("tag")
+                                .about("tag name, or full path such as physics/optics")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    App::new("rm")
+                        .about("remove a tag from the tag tree and from every resource")
+                        .arg(Arg::new("tag").about("tag name").required(true)),
+                )
+                .subcommand(
+                    App::new("rename")
+                        .about("rename a tag in the tag tree and on every resource")
+                        .arg(Arg::new("old").about("current name").required(true))
+                        .arg(Arg::new("new").about("new name").required(true)),
+                )
+                .subcommand(
+                    App::new("merge")
+                        .about("merge a tag into another")
+                        .arg(Arg::new("from").about("tag to merge, which is removed").required(true))
+                        .arg(Arg::new("into").about("tag to merge it into").required(true)),
+                )
+                .subcommand(
+                    App::new("apply")
+                        .about("add a tag to every resource matching a search query")
+                        .arg(Arg::new("tag").about("tag to add or remove").required(true))
+                        .arg(
+                            Arg::new("query")
+                                .about("search query selecting the resources")
+                                .takes_value(true)
+                                .required(true)
+                                .long("query"),
+                        )
+                        .arg(
+                            Arg::new("remove")
+                                .about("remove the tag instead of adding it")
+                                .long("remove"),
+                        )
+                        .arg(
+                            Arg::new("yes")
+                                .about("apply the change without asking for confirmation")
+                                .short('y')
+                                .long("yes"),
+                        ),
+                )
+                .subcommand(
+                    App::new("list")
+                        .about("print the tag tree and report unknown resource tags")
+                        .arg(
+                            Arg::new("create")
+                                .about("add unknown resource tags to the tag tree")
+                                .long("create"),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("attachment")
+                .about("manage secondary files of resources, such as slides and datasets")
+                .subcommand(
+                    App::new("add")
+                        .about("attach files to a resource")
+                        .arg(Arg::new("checksum").about("checksum of the resource").required(true))
+                        .arg(
+                            Arg::new("files")
+                                .about("files to attach, which are copied into the resources directory")
+                                .multiple(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("description")
+                                .about("what the files hold, such as slides or errata")
+                                .takes_value(true)
+                                .long("description"),
+                        ),
+                )
+                .subcommand(
+                    App::new("rm")
+                        .about("remove an attachment from a resource and delete its file")
+                        .arg(Arg::new("checksum").about("checksum of the resource").required(true))
+                        .arg(Arg::new("name").about("file name of the attachment").required(true)),
+                )
+                .subcommand(
+                    App::new("list")
+                        .about("print the attachments of a resource")
+                        .arg(Arg::new("checksum").about("checksum of the resource").required(true)),
+                ),
+        )
+        .subcommand(
+            App::new("collection")
+                .about("manage collections, ordered reading lists of resources")
+                .subcommand(
+                    App::new("create")
+                        .about("create a collection")
+                        .arg(Arg::new("name").about("name of the collection").required(true))
+                        .arg(
+                            Arg::new("checksums")
+                                .about("checksums of the initial resources, in order")
+                                .multiple(true),
+                        ),
+                )
+                .subcommand(
+                    App::new("add")
+                        .about("add resources to a collection")
+                        .arg(Arg::new("name").about("name of the collection").required(true))
+                        .arg(
+                            Arg::new("checksums")
+                                .about("checksums of the resources, in order")
+                                .multiple(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("position")
+                                .about("position to insert the resources at, starting at 1")
+                                .long_about("The resources are appended if this is omitted.")
+                                .takes_value(true)
+                                .long("position"),
+                        ),
+                )
+                .subcommand(
+                    App::new("rm")
+                        .about("remove resources from a collection, or the collection itself")
+                        .arg(Arg::new("name").about("name of the collection").required(true))
+                        .arg(
+                            Arg::new("checksums")
+                                .about("checksums of the resources")
+                                .long_about("The collection itself is removed if no resources are given. Resources always stay in the catalog.")
+                                .multiple(true),
+                        ),
+                )
+                .subcommand(App::new("list").about("list the collections"))
+                .subcommand(
+                    App::new("show")
+                        .about("list the resources of a collection in order")
+                        .arg(Arg::new("name").about("name of the collection").required(true)),
+                ),
+        )
+        .subcommand(
+            App::new("styles")
+                .about("manage CSL citation styles and locales")
+                .subcommand(App::new("list").about("list the available styles and locales"))
+                .subcommand(
+                    App::new("add")
+                        .about("add a style or locale to the library")
+                        .arg(
+                            Arg::new("source")
+                                .about("file or URL of the style or locale")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("state")
+                .about("manage the lifecycle states of resources")
+                .subcommand(
+                    App::new("set")
+                        .about("move a resource to another state")
+                        .arg(
+                            Arg::new("checksum")
+                                .about("checksum of the resource")
+                                .required(true),
+                        )
+                        .arg(Arg::new("state").about("new state").required(true))
+                        .arg(
+                            Arg::new("force")
+                                .about("allow transitions the catalog's workflow doesn't permit")
+                                .long("force"),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("mirror")
+                .about("create a read-only copy of the library with a checksum manifest")
+                .arg(
+                    Arg::new("dest")
+                        .about("directory to create the mirror in")
+                        .takes_value(true)
+                        .required(true)
+                        .long("dest"),
+                )
+                .arg(
+                    Arg::new("key")
+                        .about("secret key to sign the manifest with")
+                        .long_about("If this argument is omitted, the manifest is not signed.")
+                        .takes_value(true)
+                        .long("key"),
+                )
+                .arg(
+                    Arg::new("signer")
+                        .about("tool used to sign the manifest")
+                        .takes_value(true)
+                        .default_value("minisign")
+                        .possible_values(&["minisign", "ssh"])
+                        .long("signer"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                ),
+        )
+        .subcommand(
+            App::new("verify-mirror")
+                .about("check a mirror's files against its manifest")
+                .arg(
+                    Arg::new("mirror")
+                        .about("mirror directory")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("export-bundle")
+                .about("write the resources matching a query and their files to an archive another library can import")
+                .long_about("The archive is a zstd-compressed tar file holding the resource files, their attachments and a catalog fragment describing them. Reading status, rating, priority and state are left out, and so are relations to resources outside the bundle.")
+                .arg(
+                    Arg::new("query")
+                        .about("search query selecting the resources, which selects every resource when empty")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .about("archive to create, e.g., papers.tar.zst")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                ),
+        )
+        .subcommand(
+            App::new("import-bundle")
+                .about("add the resources of a bundle created by export-bundle to the library")
+                .long_about("Resources the library already has, by file or by DOI or ISBN, are skipped, and relations to them refer to the library's resources instead.")
+                .arg(
+                    Arg::new("bundle")
+                        .about("bundle to import")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("serve the library over HTTP for other programs, such as browser extensions and editors")
+                .long_about("GET /resources?q=<query> lists the resources matching a search query as JSON, GET /resources/<checksum> returns a resource, GET /resources/<checksum>/file its file and GET /bibtex?q=<query> the BibTeX entries of the matching resources. With --write, PATCH /resources/<checksum> sets the fields given as a JSON object. GET /opds is an OPDS catalog for e-reader apps, with feeds of every resource, of each tag and of each collection. GET /resources/<checksum>/text and GET /resources/<checksum>/thumbnail return the text and thumbnail of a resource once indexed, and with --index, GET /index the progress of indexing.")
+                .arg(
+                    Arg::new("address")
+                        .about("address and port to listen on (default 127.0.0.1:8080)")
+                        .takes_value(true)
+                        .long("address"),
+                )
+                .arg(
+                    Arg::new("write")
+                        .about("accept requests that change the catalog")
+                        .long("write"),
+                )
+                .arg(
+                    Arg::new("include private")
+                        .about("include private resources")
+                        .long("include-private"),
+                )
+                .arg(index_arg())
+                .arg(index_delay_arg()),
+        )
+        .subcommand(
+            App::new("migrate-storage")
+                .about("move resources to a different storage layout")
+                .arg(
+                    Arg::new("layout")
+                        .about("new storage layout")
+                        .long_about("The flat layout stores every resource directly in the resources directory. The sharded layout stores resources in subdirectories named by the first two characters of their initial checksum, which is faster for large libraries on some filesystems.")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["flat", "sharded"]),
+                ),
+        )
+        .subcommand(
+            App::new("used")
+                .about("report the citations of a LaTeX or Markdown project")
+                .arg(
+                    Arg::new("tex-dir")
+                        .about("directory containing the project's .tex and .md files")
+                        .takes_value(true)
+                        .default_value(".")
+                        .long("tex-dir"),
+                )
+                .arg(
+                    Arg::new("bib")
+                        .about("write a BibTeX file containing only the cited resources")
+                        .takes_value(true)
+                        .long("bib"),
+                ),
+        )
+        .subcommand(
+            App::new("unused")
+                .about("report catalog entries of a BibTeX file that a project never cites")
+                .arg(
+                    Arg::new("bib")
+                        .about("BibTeX file used by the project")
+                        .takes_value(true)
+                        .required(true)
+                        .long("bib"),
+                )
+                .arg(
+                    Arg::new("tex-dir")
+                        .about("directory containing the project's .tex and .md files")
+                        .takes_value(true)
+                        .default_value(".")
+                        .long("tex-dir"),
+                ),
+        )
+        .subcommand(
+            App::new("complete")
+                .about("print completion candidates for editor integration")
+                .arg(
+                    Arg::new("what")
+                        .about("kind of candidates to print")
+                        .takes_value(true)
+                        .default_value("keys")
+                        .possible_values(&["keys", "titles", "tags", "authors"])
+                        .long("what"),
+                )
+                .arg(
+                    Arg::new("prefix")
+                        .about("only print candidates starting with this prefix, ignoring case")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("undo")
+                .about("restore the catalog and files as they were before the last operation that changed them")
+                .long_about("The catalog is recorded before each operation that changes it, along with the files the operation moves or deletes, and the last 20 operations can be undone, most recent first. A file isn't restored when another file has taken its place since."),
+        )
+        .subcommand(
+            App::new("log")
+                .about("show the commits of the catalog with the resources each added, removed or modified")
+                .long_about("Requires the catalog to be kept in git, which the catalog's \"git\" setting does after each command that changes it.")
+                .arg(
+                    Arg::new("count")
+                        .about("number of commits shown")
+                        .takes_value(true)
+                        .default_value("20")
+                        .short('n')
+                        .long("count"),
+                ),
+        )
+        .subcommand(
+            App::new("diff")
+                .about("show the resources added, removed and modified between two versions of the catalog, field by field")
+                .long_about("Each version is either a catalog file or split catalog directory, such as a backup, or a git revision of the library's catalog.")
+                .arg(
+                    Arg::new("old")
+                        .about("older catalog or git revision")
+                        .default_value("HEAD"),
+                )
+                .arg(
+                    Arg::new("new")
+                        .about("newer catalog or git revision, instead of the current catalog"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .about("print the changes as JSON")
+                        .long("json"),
+                ),
+        )
+        .subcommand(
+            App::new("resolve")
+                .about("print the resource a current or historical checksum belongs to and the path of its file")
+                .long_about("Old BibTeX keys and notes may refer to a resource by a checksum its file no longer has. A unique prefix of the checksum is enough.")
+                .arg(
+                    Arg::new("checksum")
+                        .about("current or historical checksum, or its beginning")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("path")
+                        .about("only print the path of the resource's file")
+                        .long("path"),
+                ),
+        )
+        .subcommand(
+            App::new("versions")
+                .about("list the revisions of a resource's file and whether a copy of each was kept")
+                .long_about("Copies are kept when \"keep_revisions\" is true in the catalog, from the first catalog after it was set.")
+                .arg(
+                    Arg::new("query")
+                        .about("checksum of the resource or search query matching it")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("checkout")
+                .about("restore a resource's file to a kept revision")
+                .long_about("The current file is kept as a revision first, and the restored revision becomes the resource's current checksum.")
+                .arg(
+                    Arg::new("query")
+                        .about("checksum of the resource or search query matching it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("revision")
+                        .about("number of the revision, as listed by versions, or the beginning of its checksum")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("fingerprint")
+                .about("print a digest of the catalog's resources for comparing libraries after a sync"),
+        )
+        .subcommand(
+            App::new("schema")
+                .about("print the JSON schema of the catalog")
+                .arg(
+                    Arg::new("resource")
+                        .about("print the schema of a single resource file of a split catalog instead")
+                        .long("resource"),
+                ),
+        )
+        .subcommand(
+            App::new("convert-catalog")
+                .about("write the catalog to a new location, optionally using the split layout")
+                .arg(
+                    Arg::new("destination")
+                        .about("new catalog file, or directory when --split is given")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("split")
+                        .about("store each resource in its own file under the destination directory")
+                        .long("split"),
+                ),
+        )
+        .get_matches()
+}
+
+/// Argument limiting the concurrency of a batch operation.
+fn jobs_arg<'a>() -> Arg<'a> {
+    Arg::new("jobs")
+        .about("number of resources processed at the same time")
+        .takes_value(true)
+        .default_value("4")
+        .long("jobs")
+        .short('j')
+}
+
+/// Argument to index resources in the background of a long-running
+/// command.
+fn index_arg<'a>() -> Arg<'a> {
+    Arg::new("index")
+        .about("extract the text of resources and render thumbnails in the background")
+        .long_about("Indexing runs at a low priority, one resource at a time, and reports its progress. Text is kept in .text/ and thumbnails in .thumbnails/ of the library, and only files that changed since are indexed again. Thumbnails of PDFs need pdftoppm, from Poppler.")
+        .long("index")
+}
+
+/// Argument throttling background indexing.
+fn index_delay_arg<'a>() -> Arg<'a> {
+    Arg::new("index delay")
+        .about("seconds to pause after indexing each resource")
+        .takes_value(true)
+        .default_value("0.5")
+        .long("index-delay")
+}
+
+/// Pause after indexing each resource, if indexing.
+fn index(args: &clap::ArgMatches) -> Option<Duration> {
+    args.is_present("index").then(|| {
+        Duration::from_secs_f64(
+            args.value_of("index delay")
+                .unwrap()
+                .parse()
+                .expect("index-delay must be a number of seconds"),
+        )
+    })
+}
+
+/// Number of jobs given as an argument.
+fn jobs(args: &clap::ArgMatches) -> usize {
+    args.value_of("jobs")
+        .unwrap()
+        .parse()
+        .expect("jobs must be a positive number")
+}
+
+/// Argument resuming an interrupted batch operation.
+fn resume_arg<'a>() -> Arg<'a> {
+    Arg::new("resume")
+        .about("continue an interrupted run where it stopped")
+        .long("resume")
+}
+
+/// Date given as an argument.
+fn date_arg(args: &clap::ArgMatches, name: &str) -> Option<DateTime> {
+    args.value_of(name).map(|d| {
+        DateTime::try_from(d)
+            .unwrap_or_else(|e| panic!("Invalid date {}: {}", d, e))
+    })
+}
+
+/// Subcommand setting the reading status of a resource.
+fn mark_subcommand<'a>(name: &'a str, about: &'a str) -> App<'a> {
+    App::new(name).about(about).arg(
+        Arg::new("resource")
+            .about("checksum of the resource or search query matching it")
+            .long_about("When a query matches several resources, the resource is picked from a list of the best matches.")
+            .required(true),
+    )
 }
 
 /// Get the resources directory path and catalog file path according to
@@ -0,0 +1,547 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::notes::merge_notes;
+use crate::resource::{Attachment, Resource};
+use crate::storage::Storage;
+
+use serde_json::Value;
+use std::path::Path;
+
+/// Fields that describe the file of a resource or identify it, which
+/// merging never copies from the other resource.
+const IDENTITY_FIELDS: [&str; 7] = [
+    "document",
+    "date_added",
+    "date_modified",
+    "checksum",
+    "historical_checksums",
+    "attachments",
+    "relations",
+];
+
+impl Resource {
+    /// Fill the fields of this resource that aren't set with those of
+    /// another resource. Tags and custom fields are combined.
+    ///
+    /// # Returns
+    ///
+    /// A description of each field that changed.
+    pub fn absorb(&mut self, other: &Resource) -> Vec<String> {
+        self.absorb_with(other, |_, _, _| false)
+    }
+
+    /// Like `absorb`, but fields both resources set to different values
+    /// take the other resource's value when `take` says so.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Resource whose fields are merged into this one.
+    /// * `take` - Called with the name of the field, this resource's
+    ///   value and the other resource's value.
+    ///
+    /// # Returns
+    ///
+    /// A description of each field that changed.
+    pub fn absorb_with(
+        &mut self,
+        other: &Resource,
+        mut take: impl FnMut(&str, &Value, &Value) -> bool,
+    ) -> Vec<String> {
+        let mut fields = match serde_json::to_value(&*self).unwrap() {
+            Value::Object(o) => o,
+            _ => unreachable!(),
+        };
+        let mut changes = vec![];
+        if let Value::Object(others) = serde_json::to_value(other).unwrap() {
+            for (field, value) in others {
+                if IDENTITY_FIELDS.contains(&field.as_str()) {
+                    continue;
+                }
+                match (field.as_str(), fields.get_mut(&field)) {
+                    ("tags", Some(Value::Array(tags))) => {
+                        for tag in value.as_array().unwrap() {
+                            if !tags.contains(tag) {
+                                changes.push(format!("tags: +{}", tag));
+                                tags.push(tag.clone());
+                            }
+                        }
+                    }
+                    ("extra", Some(Value::Object(extra))) => {
+                        for (name, v) in value.as_object().unwrap() {
+                            if !extra.contains_key(name) {
+                                changes.push(format!("extra.{}: {}", name, v));
+                                extra.insert(name.clone(), v.clone());
+                            }
+                        }
+                    }
+                    (_, Some(current)) => {
+                        if *current != value && take(&field, current, &value) {
+                            changes.push(format!(
+                                "{}: {} -> {}",
+                                field, current, value
+                            ));
+                            *current = value;
+                        }
+                    }
+                    (_, None) => {
+                        changes.push(format!("{}: {}", field, value));
+                        fields.insert(field, value);
+                    }
+                }
+            }
+        }
+        *self = serde_json::from_value(Value::Object(fields))
+            .expect("Merged resource is invalid");
+        changes
+    }
+}
+
+/// Which value is kept when both merged resources set a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergePreference {
+    /// The value of the kept resource.
+    Kept,
+    /// The value of the resource modified most recently.
+    Newer,
+    /// The value the user chooses, field by field.
+    Ask,
+}
+
+impl Catalog {
+    /// Merge a resource into another resource, which takes over its
+    /// metadata (see `Resource::absorb_with`), checksums, relations and
+    /// place in collections. The attachments of the merged resource
+    /// become attachments of the kept resource, and so does its file
+    /// unless it's removed.
+    ///
+    /// This only changes the catalog. The caller moves the files.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - Initial checksum of the resource that is kept.
+    /// * `other` - Initial checksum of the resource that is merged and
+    ///   removed from the catalog.
+    /// * `take` - Whether a field both resources set takes the merged
+    ///   resource's value (see `Resource::absorb_with`).
+    /// * `attach` - Keep the file of the merged resource as an
+    ///   attachment.
+    ///
+    /// # Returns
+    ///
+    /// The merged resource, along with a description of each change.
+    pub fn merge_resources(
+        &mut self,
+        keep: &str,
+        other: &str,
+        take: impl FnMut(&str, &Value, &Value) -> bool,
+        attach: bool,
+    ) -> (Resource, Vec<String>) {
+        if keep == other {
+            panic!("Can't merge {} into itself", keep);
+        }
+        let index = self
+            .resources
+            .iter()
+            .position(|r| r.historical_checksums[0] == other)
+            .unwrap_or_else(|| panic!("No resource with checksum {}", other));
+        let merged = self.resources.remove(index);
+        let extension = merged
+            .document
+            .as_ref()
+            .and_then(|d| self.document_types.get(d))
+            .map(|d| d.extension.clone())
+            .unwrap_or_default();
+        let file_name = match extension.is_empty() {
+            true => other.to_string(),
+            false => format!("{}.{}", other, extension),
+        };
+
+        let resource = self
+            .resources
+            .iter_mut()
+            .find(|r| r.historical_checksums[0] == keep)
+            .unwrap_or_else(|| panic!("No resource with checksum {}", keep));
+        let mut changes = resource.absorb_with(&merged, take);
+        // The merged resource is still found by its checksums.
+        for checksum in &merged.historical_checksums {
+            if !resource.historical_checksums.contains(checksum) {
+                changes.push(format!("historical_checksums: +{}", checksum));
+                resource.historical_checksums.push(checksum.clone());
+            }
+        }
+        let attachments = resource.attachments.get_or_insert_with(Vec::new);
+        let file = Attachment {
+            name: file_name,
+            checksum: merged.checksum.clone(),
+            description: Some(format!("merged from \"{}\"", merged.title)),
+        };
+        let new_attachments = Some(file)
+            .filter(|_| attach)
+            .into_iter()
+            .chain(merged.attachments.iter().flatten().cloned());
+        for attachment in new_attachments {
+            if attachments.iter().any(|a| a.name == attachment.name) {
+                panic!(
+                    "{} already has an attachment named {}",
+                    keep, attachment.name
+                );
+            }
+            changes.push(format!("attachments: +{}", attachment.name));
+            attachments.push(attachment);
+        }
+        if attachments.is_empty() {
+            resource.attachments = None;
+        }
+        for (relation, targets) in merged.relations.iter().flatten() {
+            let existing = resource
+                .relations
+                .get_or_insert_with(Default::default)
+                .entry(*relation)
+                .or_default();
+            for target in targets {
+                if target != keep && !existing.contains(target) {
+                    existing.push(target.clone());
+                }
+            }
+        }
+
+        // Whatever referred to the merged resource now refers to the
+        // kept one.
+        for targets in self
+            .resources
+            .iter_mut()
+            .flat_map(|r| r.relations.iter_mut().flatten())
+            .map(|(_, targets)| targets)
+        {
+            if targets.iter().any(|t| t == other) {
+                targets.retain(|t| t != other);
+                if !targets.iter().any(|t| t == keep) {
+                    targets.push(keep.to_string());
+                }
+            }
+        }
+        for keys in self.collections.values_mut() {
+            if let Some(i) = keys.iter().position(|k| k == other) {
+                if keys.iter().any(|k| k == keep) {
+                    keys.remove(i);
+                } else {
+                    keys[i] = keep.to_string();
+                }
+            }
+        }
+        (merged, changes)
+    }
+}
+
+/// Initial checksum of the resource with a current or historical
+/// checksum.
+fn initial_checksum(catalog: &Catalog, checksum: &str) -> String {
+    catalog
+        .resources
+        .iter()
+        .find(|r| {
+            r.checksum == checksum
+                || r.historical_checksums.iter().any(|c| c == checksum)
+        })
+        .map(|r| r.historical_checksums[0].clone())
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum))
+}
+
+/// Catalog a second file with the metadata of an existing resource,
+/// e.g., when one PDF holds two papers or a work exists in two files.
+/// The file is copied into the resources directory and the metadata of
+/// the new resource can then be edited independently.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum of the resource whose
+///   metadata is copied.
+/// * `file` - File of the new resource.
+/// * `title` - Title of the new resource, instead of the copied one.
+pub fn librarian_split(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    checksum: &str,
+    file: &Path,
+    title: Option<&str>,
+) {
+    let key = initial_checksum(catalog, checksum);
+    if !file.is_file() {
+        panic!("{} is not a file", file.display());
+    }
+    let new_checksum = file_checksum(file);
+    if catalog.resource_mut(&new_checksum).is_some() {
+        panic!(
+            "{} is already a resource ({})",
+            file.display(),
+            new_checksum
+        );
+    }
+    let mut resource = catalog
+        .resources
+        .iter()
+        .find(|r| r.historical_checksums[0] == key)
+        .unwrap()
+        .clone();
+    resource.checksum = new_checksum.clone();
+    resource.historical_checksums = vec![new_checksum.clone()];
+    resource.citekey = None;
+    resource.date_added = None;
+    resource.date_modified = None;
+    resource.attachments = None;
+    resource.relations = None;
+    if let Some(t) = title {
+        resource.title = t.to_string();
+    }
+    if let Some(d) = catalog.document_type(file) {
+        resource.document = Some(d);
+    }
+
+    let path =
+        Storage::of(resources_path, catalog).resource_path(&new_checksum);
+    if !catalog.dry_run {
+        std::fs::create_dir_all(path.parent().unwrap())
+            .expect("Failed to create resources directory");
+        std::fs::copy(file, path)
+            .expect("Failed to copy file into the resources directory");
+    }
+    catalog.resources.push(resource);
+    catalog.sort();
+    catalog.assign_citekeys();
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new(
+                "split",
+                vec![key.clone(), new_checksum.clone()],
+                vec![format!("{} split from {}", new_checksum, key)],
+            ),
+        );
+    }
+    println!("{}", new_checksum);
+}
+
+/// Value of a field as the user would write it, without the quotes of
+/// JSON strings and with list items separated by semicolons.
+pub(crate) fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(display_value)
+            .collect::<Vec<_>>()
+            .join("; "),
+        v => v.to_string(),
+    }
+}
+
+/// Merge two resources describing the same work into one, e.g., after
+/// importing the same work from several sources. The first resource is
+/// kept, and the second is removed from the catalog after its metadata
+/// filled the fields the first lacks. Its checksums are added to the
+/// historical checksums of the first resource, and relations and
+/// collections referring to it refer to the first resource instead. Its
+/// file is kept as an attachment of the first resource or removed, and
+/// its reading notes become notes of the first resource (see
+/// `merge_notes`).
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `keep` - Current or historical checksum of the resource that is
+///   kept.
+/// * `other` - Current or historical checksum of the resource that is
+///   merged into it.
+/// * `prefer` - Which value is kept when both resources set a field.
+/// * `attach` - Keep the file of the merged resource as an attachment
+///   instead of removing it.
+pub fn librarian_merge_resources(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    keep: &str,
+    other: &str,
+    prefer: MergePreference,
+    attach: bool,
+) {
+    let keep = initial_checksum(catalog, keep);
+    let other = initial_checksum(catalog, other);
+    let modified = |key: &str| {
+        let r = catalog
+            .resources
+            .iter()
+            .find(|r| r.historical_checksums[0] == key)
+            .unwrap();
+        r.date_modified.clone().or_else(|| r.date_added.clone())
+    };
+    let newer = modified(&other) > modified(&keep);
+    let take = |field: &str, kept: &Value, merged: &Value| match prefer {
+        MergePreference::Kept => false,
+        MergePreference::Newer => newer,
+        MergePreference::Ask => loop {
+            let prompt = message(
+                "merge-choose",
+                &[
+                    ("field", field.into()),
+                    ("kept", display_value(kept).into()),
+                    ("merged", display_value(merged).into()),
+                ],
+            );
+            match ask(&prompt).as_deref() {
+                Some("1") | None => break false,
+                Some("2") => break true,
+                _ => (),
+            }
+        },
+    };
+    let (merged, changes) =
+        catalog.merge_resources(&keep, &other, take, attach);
+
+    let storage = Storage::of(resources_path, catalog);
+    let dir = storage.attachments_path(&keep);
+    let file_name = catalog
+        .resources
+        .iter()
+        .find(|r| r.historical_checksums[0] == keep)
+        .unwrap()
+        .attachments
+        .iter()
+        .flatten()
+        .find(|a| a.checksum == merged.checksum && attach)
+        .map(|a| a.name.clone());
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    // A dry run leaves the files of both resources where they are.
+    if !storage.is_dry_run() {
+        if file_name.is_some() || merged.attachments.is_some() {
+            std::fs::create_dir_all(&dir)
+                .expect("Failed to create attachments directory");
+        }
+        match file_name {
+            Some(name) => {
+                std::fs::rename(storage.resource_path(&other), dir.join(name))
+                    .expect("Failed to move the merged resource")
+            }
+            None => {
+                let path = storage.resource_path(&other);
+                match path.is_dir() {
+                    true => std::fs::remove_dir_all(&path),
+                    false => std::fs::remove_file(&path),
+                }
+                .expect("Failed to remove the merged resource")
+            }
+        }
+        let other_dir = storage.attachments_path(&other);
+        for attachment in merged.attachments.iter().flatten() {
+            std::fs::rename(
+                other_dir.join(&attachment.name),
+                dir.join(&attachment.name),
+            )
+            .expect("Failed to move attachment");
+        }
+        std::fs::remove_dir(&other_dir).ok();
+        merge_notes(library, &keep, &other);
+    }
+
+    store.write(catalog);
+    let mut descriptions = vec![format!("{} merged into {}", other, keep)];
+    descriptions.extend(changes.iter().map(|c| format!("{} {}", keep, c)));
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "merge-resources",
+                vec![keep, other],
+                descriptions,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_resources() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"pdf\": {\"extension\": \"pdf\"}}, \"content_types\": {},
+              \"collections\": {\"reading\": [\"bb\", \"a1\"]},
+              \"resources\": [
+              {\"title\": \"A\", \"tags\": [\"physics\"], \"checksum\": \"a2\", \"historical_checksums\": [\"a1\", \"a2\"]},
+              {\"title\": \"B\", \"journal\": \"Phys. Rev.\", \"tags\": [\"physics\", \"quantum\"], \"document\": \"pdf\",
+               \"relations\": {\"cites\": [\"a1\", \"cc\"]}, \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"C\", \"relations\": {\"cites\": [\"bb\"]}, \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let (merged, changes) =
+            catalog.merge_resources("a1", "bb", |_, _, _| false, true);
+        assert!(merged.title == "B");
+        assert!(catalog.resources.len() == 2);
+        let kept = &catalog.resources[0];
+        // The kept resource's fields win.
+        assert!(kept.title == "A");
+        assert!(kept.journal.as_deref() == Some("Phys. Rev."));
+        assert!(
+            kept.tags
+                == Some(vec![String::from("physics"), String::from("quantum")])
+        );
+        assert!(kept.attachments.as_ref().unwrap()[0].name == "bb.pdf");
+        assert!(changes.contains(&String::from("attachments: +bb.pdf")));
+        // References to the merged resource now point to the kept one.
+        assert!(
+            kept.relations.as_ref().unwrap().values().next().unwrap()
+                == &vec!["cc"]
+        );
+        assert!(
+            catalog.resources[1]
+                .relations
+                .as_ref()
+                .unwrap()
+                .values()
+                .next()
+                .unwrap()
+                == &vec!["a1"]
+        );
+        assert!(catalog.collections["reading"] == vec!["a1"]);
+        assert!(kept.historical_checksums == vec!["a1", "a2", "bb"]);
+    }
+
+    #[test]
+    fn test_merge_resources_preference() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"journal\": \"PRL\", \"attachments\": [{\"name\": \"notes.txt\", \"checksum\": \"nn\"}],
+               \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"journal\": \"Physical Review Letters\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        // Only the journal is taken from the merged resource, and its
+        // file is dropped.
+        let (_, changes) = catalog.merge_resources(
+            "aa",
+            "bb",
+            |field, _, _| field == "journal",
+            false,
+        );
+        let kept = &catalog.resources[0];
+        assert!(kept.title == "A");
+        assert!(kept.journal.as_deref() == Some("Physical Review Letters"));
+        assert!(kept.attachments.as_ref().unwrap().len() == 1);
+        assert!(changes.iter().any(|c| c.starts_with("journal: ")));
+    }
+}
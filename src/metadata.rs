@@ -0,0 +1,795 @@
+use crate::batch::{catch_interrupt, run, Checkpoint};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::pdf::split_authors;
+use crate::provider::{provide, provided, MetadataProvider};
+use crate::resource::{DateTime, Doi, Isbn, Issn, Name, Resource};
+use crate::storage::Storage;
+
+use indexmap::IndexMap;
+use regex::Regex;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Resource metadata retrieved from an external source.
+///
+/// Every field is optional since sources rarely provide complete
+/// information.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub author: Option<Vec<Name>>,
+    pub date: Option<DateTime>,
+    pub publisher: Option<String>,
+    pub journal: Option<String>,
+    pub volume: Option<String>,
+    pub number: Option<String>,
+    pub pages: Option<String>,
+    pub language: Option<String>,
+    pub doi: Option<Doi>,
+    pub isbn: Option<Isbn>,
+    pub issn: Option<Issn>,
+    pub eprint: Option<String>,
+    pub eprinttype: Option<String>,
+    pub eprintclass: Option<String>,
+    pub r#abstract: Option<String>,
+    pub keywords: Option<Vec<String>>,
+}
+
+impl Metadata {
+    /// Metadata without any field.
+    pub fn new() -> Metadata {
+        Metadata::default()
+    }
+
+    /// Overwrite the fields of `resource` with every field present in
+    /// the metadata.
+    pub fn apply(&self, resource: &mut Resource) {
+        if let Some(x) = &self.title {
+            resource.title = x.clone();
+        }
+        if self.subtitle.is_some() {
+            resource.subtitle = self.subtitle.clone();
+        }
+        if self.author.is_some() {
+            resource.author = self.author.clone();
+        }
+        if self.date.is_some() {
+            resource.date = self.date.clone();
+        }
+        if self.publisher.is_some() {
+            resource.publisher = self.publisher.clone();
+        }
+        if self.journal.is_some() {
+            resource.journal = self.journal.clone();
+        }
+        if self.volume.is_some() {
+            resource.volume = self.volume.clone();
+        }
+        if self.number.is_some() {
+            resource.number = self.number.clone();
+        }
+        if self.pages.is_some() {
+            resource.pages = self.pages.clone();
+        }
+        if self.language.is_some() {
+            resource.language = self.language.clone();
+        }
+        if self.doi.is_some() {
+            resource.doi = self.doi.clone();
+        }
+        if self.isbn.is_some() {
+            resource.isbn = self.isbn.clone();
+        }
+        if self.issn.is_some() {
+            resource.issn = self.issn.clone();
+        }
+        if self.eprint.is_some() {
+            resource.eprint = self.eprint.clone();
+        }
+        if self.eprinttype.is_some() {
+            resource.eprinttype = self.eprinttype.clone();
+        }
+        if self.eprintclass.is_some() {
+            resource.eprintclass = self.eprintclass.clone();
+        }
+        if self.r#abstract.is_some() {
+            resource.r#abstract = self.r#abstract.clone();
+        }
+        if self.keywords.is_some() {
+            resource.keywords = self.keywords.clone();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MetadataFetchError {
+    details: String,
+}
+
+impl MetadataFetchError {
+    fn new(msg: &str) -> MetadataFetchError {
+        MetadataFetchError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for MetadataFetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for MetadataFetchError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Retrieve the metadata associated with a DOI from the CrossRef API.
+pub fn fetch_crossref(doi: &Doi) -> Result<Metadata, MetadataFetchError> {
+    let response =
+        ureq::get(&format!("https://api.crossref.org/works/{}", doi))
+            .set(
+                "User-Agent",
+                concat!("librarian/", env!("CARGO_PKG_VERSION")),
+            )
+            .call()
+            .map_err(|e| {
+                MetadataFetchError::new(&format!(
+                    "CrossRef request for {} failed: {}",
+                    doi, e
+                ))
+            })?;
+    let work: serde_json::Value = response.into_json().map_err(|e| {
+        MetadataFetchError::new(&format!(
+            "CrossRef returned an invalid response for {}: {}",
+            doi, e
+        ))
+    })?;
+    Ok(crossref_metadata(&work["message"]))
+}
+
+/// Convert a CrossRef work (the "message" field of a CrossRef API
+/// response) into `Metadata`.
+fn crossref_metadata(work: &serde_json::Value) -> Metadata {
+    // CrossRef stores titles as arrays, of which we only use the first.
+    let first_string = |key: &str| match work[key].as_array() {
+        Some(a) => a.first().and_then(|x| x.as_str()).map(|x| x.to_string()),
+        None => None,
+    };
+    let string = |key: &str| work[key].as_str().map(|x| x.to_string());
+
+    let mut metadata = Metadata::new();
+    metadata.title = first_string("title");
+    metadata.subtitle = first_string("subtitle");
+    metadata.journal = first_string("container-title");
+    metadata.publisher = string("publisher");
+    metadata.volume = string("volume");
+    metadata.number = string("issue");
+    metadata.pages = string("page");
+    metadata.language = string("language");
+    metadata.doi = string("DOI").and_then(|d| Doi::try_from(d.as_str()).ok());
+    // The first valid identifier, since CrossRef lists the ISBNs and
+    // ISSNs of every edition and format.
+    let identifier = |key: &str| {
+        work[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+    metadata.isbn = identifier("ISBN")
+        .iter()
+        .find_map(|i| Isbn::try_from(i.as_str()).ok());
+    metadata.issn = identifier("ISSN")
+        .iter()
+        .find_map(|i| Issn::try_from(i.as_str()).ok());
+    // Abstracts are JATS XML (e.g., "<jats:p>...</jats:p>").
+    metadata.r#abstract = string("abstract")
+        .map(|a| strip_markup(&a))
+        .filter(|a| !a.is_empty());
+    metadata.keywords = work["subject"]
+        .as_array()
+        .map(|s| {
+            s.iter()
+                .filter_map(|x| x.as_str())
+                .map(String::from)
+                .collect::<Vec<String>>()
+        })
+        .filter(|s| !s.is_empty());
+    metadata.author = work["author"]
+        .as_array()
+        .map(|authors| authors.iter().filter_map(crossref_name).collect());
+
+    // Prefer the print publication date and fall back to the earliest
+    // date CrossRef knows about.
+    for key in &["published-print", "published", "issued", "created"] {
+        if let Some(parts) = work[key]["date-parts"][0].as_array() {
+            let mut date = DateTime::new();
+            date.year =
+                parts.first().and_then(|x| x.as_i64()).map(|x| x as i32);
+            date.month =
+                parts.get(1).and_then(|x| x.as_i64()).map(|x| x as i32);
+            date.day = parts.get(2).and_then(|x| x.as_i64()).map(|x| x as i32);
+            if date.year.is_some() && date.validate().is_ok() {
+                metadata.date = Some(date);
+                break;
+            }
+        }
+    }
+
+    metadata
+}
+
+/// Construct a `Name` from a CrossRef author, whose given names, family
+/// name and suffix are separate fields. Leading lowercase words of the
+/// family name are its particle (e.g., "van" in "van Beethoven"), and
+/// the other words are all kept as the last name (e.g., "García
+/// Márquez"). Organizations only have a name, which is kept as is.
+pub(crate) fn crossref_name(author: &serde_json::Value) -> Option<Name> {
+    let field = |f: &str| author[f].as_str().filter(|s| !s.trim().is_empty());
+    match (field("family"), field("name")) {
+        (Some(family), _) => {
+            Some(Name::from_parts(field("given"), family, field("suffix")))
+        }
+        (None, Some(name)) => {
+            let mut literal = Name::new();
+            literal.literal = Some(name.trim().to_string());
+            Some(literal)
+        }
+        (None, None) => None,
+    }
+}
+
+/// Strip URL and "arXiv:" prefixes from an arXiv identifier.
+pub fn normalize_arxiv_id(id: &str) -> String {
+    let re =
+        Regex::new(r"^(?i:https?://arxiv\.org/(abs|pdf)/|arxiv:)").unwrap();
+    re.replace(id.trim(), "")
+        .trim_end_matches(".pdf")
+        .to_string()
+}
+
+/// Retrieve the metadata associated with an arXiv identifier from the
+/// arXiv API.
+pub fn fetch_arxiv(id: &str) -> Result<Metadata, MetadataFetchError> {
+    let id = normalize_arxiv_id(id);
+    let feed = ureq::get("https://export.arxiv.org/api/query")
+        .query("id_list", &id)
+        .call()
+        .map_err(|e| {
+            MetadataFetchError::new(&format!(
+                "arXiv request for {} failed: {}",
+                id, e
+            ))
+        })?
+        .into_string()
+        .map_err(|e| {
+            MetadataFetchError::new(&format!(
+                "arXiv returned an invalid response for {}: {}",
+                id, e
+            ))
+        })?;
+    arxiv_metadata(&feed, &id)
+}
+
+/// Replace the predefined XML entities with the characters they
+/// represent.
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Text of an XML fragment, with its elements removed and whitespace
+/// collapsed.
+fn strip_markup(s: &str) -> String {
+    xml_unescape(
+        &Regex::new(r"<[^>]*>")
+            .unwrap()
+            .replace_all(s, " ")
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" "),
+    )
+}
+
+/// Convert the Atom feed returned by the arXiv API for a single
+/// identifier into `Metadata`.
+fn arxiv_metadata(
+    feed: &str,
+    id: &str,
+) -> Result<Metadata, MetadataFetchError> {
+    let entry = Regex::new(r"(?s)<entry>(.*?)</entry>")
+        .unwrap()
+        .captures(feed)
+        .map(|c| c[1].to_string())
+        .ok_or_else(|| {
+            MetadataFetchError::new(&format!("arXiv has no entry for {}", id))
+        })?;
+    // The text of the first element with the given tag, with
+    // whitespace collapsed since arXiv wraps long titles.
+    let element = |tag: &str| {
+        Regex::new(&format!(r"(?s)<{}[^>]*>(.*?)</{}>", tag, tag))
+            .unwrap()
+            .captures(&entry)
+            .map(|c| {
+                xml_unescape(
+                    &c[1].split_whitespace().collect::<Vec<&str>>().join(" "),
+                )
+            })
+    };
+    // arXiv reports unknown identifiers as an entry titled "Error".
+    if element("title").as_deref() == Some("Error") {
+        return Err(MetadataFetchError::new(&format!(
+            "arXiv has no entry for {}",
+            id
+        )));
+    }
+
+    let mut metadata = Metadata::new();
+    metadata.title = element("title");
+    metadata.doi =
+        element("arxiv:doi").and_then(|d| Doi::try_from(d.as_str()).ok());
+    metadata.journal = element("arxiv:journal_ref");
+    metadata.r#abstract = element("summary");
+    metadata.eprint = Some(id.to_string());
+    metadata.eprinttype = Some("arxiv".to_string());
+    metadata.eprintclass =
+        Regex::new(r#"<arxiv:primary_category[^>]*term="([^"]+)""#)
+            .unwrap()
+            .captures(&entry)
+            .map(|c| c[1].to_string());
+    metadata.date = element("published")
+        .and_then(|p| DateTime::try_from(&p[..p.len().min(10)]).ok());
+    let authors: Vec<Name> = Regex::new(r"(?s)<author>\s*<name>(.*?)</name>")
+        .unwrap()
+        .captures_iter(&entry)
+        .filter_map(|c| Name::try_from(xml_unescape(&c[1]).as_str()).ok())
+        .collect();
+    if !authors.is_empty() {
+        metadata.author = Some(authors);
+    }
+    Ok(metadata)
+}
+
+/// Metadata from the named captures of the first pattern matching a
+/// file name (e.g., `(?P<author>.+) - (?P<title>.+) \((?P<year>\d{4})\)`
+/// for "Author - Title (2019)").
+///
+/// # Arguments
+///
+/// * `patterns` - Patterns with any of the named captures `title`,
+///   `subtitle`, `author` and `year`.
+/// * `file_name` - File name without its extension.
+pub fn filename_metadata(
+    patterns: &[Regex],
+    file_name: &str,
+) -> Option<Metadata> {
+    let captures = patterns.iter().find_map(|p| p.captures(file_name))?;
+    let capture = |name: &str| {
+        captures
+            .name(name)
+            .map(|c| c.as_str().trim().to_string())
+            .filter(|c| !c.is_empty())
+    };
+
+    let mut metadata = Metadata::new();
+    metadata.title = capture("title");
+    metadata.subtitle = capture("subtitle");
+    metadata.author = capture("author")
+        .map(|a| {
+            split_authors(&a)
+                .iter()
+                .filter_map(|a| Name::try_from(a.as_str()).ok())
+                .collect::<Vec<Name>>()
+        })
+        .filter(|a| !a.is_empty());
+    metadata.date =
+        capture("year").and_then(|y| DateTime::try_from(y.as_str()).ok());
+    Some(metadata)
+}
+
+/// Fill the metadata of a cataloged resource from its DOI or arXiv
+/// identifier, or from the library's metadata providers.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum identifying the
+///   resource.
+/// * `doi` - DOI to fetch metadata for.
+/// * `arxiv` - arXiv identifier to fetch metadata for.
+/// * `provider` - Name of the metadata provider to fetch metadata from.
+///
+/// When neither `doi`, `arxiv` nor `provider` is given, the resource's
+/// existing DOI is used, or its arXiv `eprint` if it has no DOI. A
+/// resource with neither gets its metadata from the first metadata
+/// provider that has some.
+pub fn librarian_fetch_metadata(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    checksum: &str,
+    doi: Option<&str>,
+    arxiv: Option<&str>,
+    provider: Option<&str>,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let providers = match provider {
+        Some(name) => {
+            let provider =
+                catalog.metadata_providers.get(name).unwrap_or_else(|| {
+                    panic!("No metadata provider named {}", name)
+                });
+            let mut providers = IndexMap::new();
+            providers.insert(name.to_string(), provider.clone());
+            providers
+        }
+        None => catalog.metadata_providers.clone(),
+    };
+    let resource = catalog
+        .resource_mut(checksum)
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum));
+    let arxiv_eprint = match resource.eprinttype.as_deref() {
+        Some("arxiv") => resource.eprint.clone(),
+        _ => None,
+    };
+    let unidentified = doi.is_none()
+        && arxiv.is_none()
+        && resource.doi.is_none()
+        && arxiv_eprint.is_none();
+    if provider.is_some() || (unidentified && !providers.is_empty()) {
+        match provide(&providers, resource, &storage) {
+            Ok(Some(provided)) => *resource = provided,
+            Ok(None) => {
+                panic!("No metadata provider has metadata for the resource")
+            }
+            Err(e) => panic!("{}", e),
+        }
+    } else {
+        let metadata = match (doi, arxiv) {
+            (Some(d), _) => fetch_crossref(
+                &Doi::try_from(d).unwrap_or_else(|e| panic!("{}", e)),
+            ),
+            (None, Some(a)) => fetch_arxiv(a),
+            (None, None) => match (&resource.doi, arxiv_eprint) {
+                (Some(d), _) => fetch_crossref(d),
+                (None, Some(a)) => fetch_arxiv(&a),
+                (None, None) => panic!(
+                    "Resource has no DOI or arXiv identifier, provide one with --doi or --arxiv"
+                ),
+            },
+        };
+        match metadata {
+            Ok(metadata) => metadata.apply(resource),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    let resource = resource.clone();
+    for other in &catalog.resources {
+        if other.historical_checksums[0] != resource.historical_checksums[0]
+            && resource.is_duplicate_of(other)
+        {
+            eprintln!(
+                "{}",
+                message(
+                    "enrich-same-identifier",
+                    &[
+                        (
+                            "key",
+                            resource.historical_checksums[0].as_str().into()
+                        ),
+                        (
+                            "other",
+                            other.historical_checksums[0].as_str().into()
+                        ),
+                        ("title", other.title.as_str().into())
+                    ]
+                )
+            );
+        }
+    }
+    catalog.assign_citekeys();
+    catalog_store.write(catalog);
+}
+
+/// Resource with the metadata fetched from its DOI or arXiv identifier,
+/// then from the library's metadata providers.
+fn enrich_resource(
+    resource: &Resource,
+    providers: &IndexMap<String, MetadataProvider>,
+    storage: &Storage,
+) -> Result<Resource, String> {
+    let mut fetched = resource.clone();
+    let arxiv = match resource.eprinttype.as_deref() {
+        Some("arxiv") => resource.eprint.as_ref(),
+        _ => None,
+    };
+    let metadata = match (&resource.doi, arxiv) {
+        (Some(d), _) => Some(fetch_crossref(d)),
+        (None, Some(a)) => Some(fetch_arxiv(a)),
+        (None, None) => None,
+    };
+    if let Some(metadata) = metadata {
+        metadata.map_err(|e| e.to_string())?.apply(&mut fetched);
+    }
+    match provide(providers, &fetched, storage)? {
+        Some(provided) => Ok(provided),
+        None => Ok(fetched),
+    }
+}
+
+/// Fetch the metadata of every resource with a DOI or arXiv identifier,
+/// or an identifier one of the library's metadata providers accepts, a
+/// few resources at a time. Metadata providers run after CrossRef and
+/// arXiv, so their fields take precedence.
+///
+/// Fetched metadata only fills the fields a resource lacks, unless
+/// `overwrite` is set. Ctrl-C stops the enrichment after the requests
+/// in flight, and the metadata fetched so far is written to the catalog
+/// along with a checkpoint to resume from.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `jobs` - Number of requests made at the same time.
+/// * `resume` - Skip the resources enriched by an interrupted earlier
+///   run.
+/// * `overwrite` - Replace existing fields with the fetched metadata.
+pub fn librarian_enrich(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    jobs: usize,
+    resume: bool,
+    overwrite: bool,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let mut checkpoint = Checkpoint::open(library, "enrich", resume);
+    let storage = Storage::of(resources_path, catalog);
+    let providers = catalog.metadata_providers.clone();
+    let identified: Vec<Resource> = catalog
+        .resources
+        .iter()
+        .filter(|r| {
+            r.doi.is_some()
+                || r.eprinttype.as_deref() == Some("arxiv")
+                || provided(&providers, r, &storage)
+        })
+        .cloned()
+        .collect();
+    let total = identified.len();
+    let pending: Vec<Resource> = identified
+        .into_iter()
+        .filter(|r| !checkpoint.done.contains(&r.historical_checksums[0]))
+        .collect();
+    let (mut enriched, mut failed) = (0, 0);
+
+    catch_interrupt();
+    run(
+        &pending,
+        jobs,
+        |resource| enrich_resource(resource, &providers, &storage),
+        |original, fetched| {
+            let key = &original.historical_checksums[0];
+            checkpoint.done.insert(key.clone());
+            let resource = catalog.resource_mut(key).unwrap();
+            match fetched {
+                Ok(fetched) => {
+                    match overwrite {
+                        true => *resource = fetched,
+                        false => {
+                            resource.absorb(&fetched);
+                        }
+                    }
+                    enriched += 1;
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", key, e);
+                    failed += 1;
+                }
+            }
+        },
+    );
+
+    catalog.assign_citekeys();
+    catalog_store.write(catalog);
+    println!(
+        "{}",
+        message(
+            "enrich-summary",
+            &[("count", enriched.into()), ("failed", failed.into())]
+        )
+    );
+    checkpoint.finish("enrich", total);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossref_metadata() {
+        let work: serde_json::Value = serde_json::from_str(
+            "{
+              \"DOI\": \"10.1103/PhysRev.47.777\",
+              \"title\": [
+                \"Can Quantum-Mechanical Description of Physical Reality Be Considered Complete?\"
+              ],
+              \"container-title\": [\"Physical Review\"],
+              \"publisher\": \"American Physical Society (APS)\",
+              \"volume\": \"47\",
+              \"issue\": \"10\",
+              \"page\": \"777-780\",
+              \"author\": [
+                {\"given\": \"A.\", \"family\": \"Einstein\"},
+                {\"given\": \"B.\", \"family\": \"Podolsky\"},
+                {\"given\": \"N.\", \"family\": \"Rosen\"}
+              ],
+              \"published-print\": {\"date-parts\": [[1935, 5, 15]]},
+              \"abstract\": \"<jats:p>In a complete theory there is an element\\ncorresponding to each element of reality.</jats:p>\",
+              \"subject\": [\"General Physics and Astronomy\"]
+            }",
+        )
+        .unwrap();
+
+        let metadata = crossref_metadata(&work);
+        assert!(
+            metadata.title.as_deref()
+                == Some("Can Quantum-Mechanical Description of Physical Reality Be Considered Complete?")
+        );
+        assert!(metadata.journal.as_deref() == Some("Physical Review"));
+        assert!(metadata.volume.as_deref() == Some("47"));
+        assert!(metadata.number.as_deref() == Some("10"));
+        assert!(metadata.pages.as_deref() == Some("777-780"));
+        assert!(metadata.doi.unwrap().as_str() == "10.1103/PhysRev.47.777");
+        assert!(String::from(metadata.date.unwrap()) == "1935-05-15");
+        assert!(
+            metadata.r#abstract.as_deref()
+                == Some("In a complete theory there is an element corresponding to each element of reality.")
+        );
+        assert!(
+            metadata.keywords
+                == Some(vec![String::from("General Physics and Astronomy")])
+        );
+        let authors: Vec<String> = metadata
+            .author
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert!(authors == vec!["A. Einstein", "B. Podolsky", "N. Rosen"]);
+    }
+
+    #[test]
+    fn test_arxiv_metadata() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title type="html">ArXiv Query: search_query=&amp;id_list=hep-th/9711200</title>
+  <entry>
+    <id>http://arxiv.org/abs/hep-th/9711200v3</id>
+    <published>1997-11-27T19:41:53Z</published>
+    <title>The Large N Limit of Superconformal Field Theories and
+  Supergravity</title>
+    <summary>  We show that the large N limit of certain conformal field theories
+  includes a limit of supergravity.
+</summary>
+    <author>
+      <name>Juan M. Maldacena</name>
+    </author>
+    <arxiv:doi xmlns:arxiv="http://arxiv.org/schemas/atom">10.1023/A:1026654312961</arxiv:doi>
+    <arxiv:journal_ref xmlns:arxiv="http://arxiv.org/schemas/atom">Adv.Theor.Math.Phys.2:231-252,1998</arxiv:journal_ref>
+    <arxiv:primary_category xmlns:arxiv="http://arxiv.org/schemas/atom" term="hep-th" scheme="http://arxiv.org/schemas/atom"/>
+  </entry>
+</feed>"#;
+
+        let metadata = arxiv_metadata(feed, "hep-th/9711200").unwrap();
+        assert!(
+            metadata.title.as_deref()
+                == Some("The Large N Limit of Superconformal Field Theories and Supergravity")
+        );
+        assert!(metadata.doi.unwrap().as_str() == "10.1023/A:1026654312961");
+        assert!(metadata.eprint.as_deref() == Some("hep-th/9711200"));
+        assert!(metadata.eprinttype.as_deref() == Some("arxiv"));
+        assert!(metadata.eprintclass.as_deref() == Some("hep-th"));
+        assert!(
+            metadata.r#abstract.as_deref()
+                == Some("We show that the large N limit of certain conformal field theories includes a limit of supergravity.")
+        );
+        assert!(String::from(metadata.date.unwrap()) == "1997-11-27");
+        assert!(
+            String::from(metadata.author.unwrap()[0].clone())
+                == "Juan M. Maldacena"
+        );
+    }
+
+    #[test]
+    fn test_normalize_arxiv_id() {
+        assert!(normalize_arxiv_id("arXiv:2101.00001v2") == "2101.00001v2");
+        assert!(
+            normalize_arxiv_id("https://arxiv.org/abs/2101.00001")
+                == "2101.00001"
+        );
+        assert!(
+            normalize_arxiv_id("https://arxiv.org/pdf/2101.00001.pdf")
+                == "2101.00001"
+        );
+        assert!(normalize_arxiv_id("hep-th/9711200") == "hep-th/9711200");
+    }
+
+    #[test]
+    fn test_crossref_name() {
+        let name = crossref_name(
+            &serde_json::json!({"given": "Johann Sebastian", "family": "van Beethoven"}),
+        )
+        .unwrap();
+        assert!(name.particle.as_deref() == Some("van"));
+        assert!(String::from(name) == "Johann Sebastian van Beethoven");
+        let name = crossref_name(&serde_json::json!({
+            "given": "Gabriel José",
+            "family": "García Márquez",
+            "suffix": "Jr."
+        }))
+        .unwrap();
+        assert!(name.first.as_deref() == Some("Gabriel"));
+        assert!(name.middle.as_deref() == Some("José"));
+        assert!(name.last.as_deref() == Some("García Márquez"));
+        assert!(name.suffix.as_deref() == Some("Jr."));
+        let name = crossref_name(
+            &serde_json::json!({"name": "LIGO Scientific Collaboration"}),
+        )
+        .unwrap();
+        assert!(String::from(name) == "{LIGO Scientific Collaboration}");
+        assert!(crossref_name(
+            &serde_json::json!({"given": "Ludwig", "family": " "})
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_filename_metadata() {
+        let patterns = vec![
+            Regex::new(
+                r"^(?P<author>.+?) - (?P<title>.+) \((?P<year>\d{4})\)$",
+            )
+            .unwrap(),
+            Regex::new(r"^(?P<title>[^:]+): (?P<subtitle>.+)$").unwrap(),
+        ];
+        let metadata = filename_metadata(
+            &patterns,
+            "Hajimiri and Lee - The Design of Low Noise Oscillators (1999)",
+        )
+        .unwrap();
+        assert!(
+            metadata.title.as_deref()
+                == Some("The Design of Low Noise Oscillators")
+        );
+        assert!(metadata.author.map(|a| a.len()) == Some(2));
+        assert!(metadata.date.map(String::from).as_deref() == Some("1999"));
+
+        let metadata =
+            filename_metadata(&patterns, "Electrodynamics: A Primer").unwrap();
+        assert!(metadata.title.as_deref() == Some("Electrodynamics"));
+        assert!(metadata.subtitle.as_deref() == Some("A Primer"));
+        assert!(metadata.author.is_none());
+
+        assert!(filename_metadata(&patterns, "notes").is_none());
+    }
+}
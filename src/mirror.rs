@@ -0,0 +1,290 @@
+use crate::catalog::{file_checksum, Catalog};
+use crate::i18n::message;
+use crate::storage::Storage;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Name of the manifest file at the top of a mirror.
+const MANIFEST: &str = "MANIFEST";
+
+/// Directories of a library holding files that accompany resources,
+/// such as reading notes and page thumbnails. Each file is named after
+/// the initial checksum of its resource, with any extension.
+pub const ANCILLARY_DIRS: [&str; 4] =
+    ["notes", "quotes", "annotations", "thumbnails"];
+
+/// Ancillary files of a resource, relative to the library directory.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `key` - Initial checksum of the resource.
+pub fn ancillary_files(library: &Path, key: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in ANCILLARY_DIRS.iter() {
+        let entries = match std::fs::read_dir(library.join(dir)) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        let mut names: Vec<String> = entries
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|n| n == key || n.starts_with(&format!("{}.", key)))
+            .collect();
+        names.sort();
+        files.extend(names.into_iter().map(|n| Path::new(dir).join(n)));
+    }
+    files
+}
+
+/// Copy a file or directory, making every copied file read-only.
+fn copy_read_only(from: &Path, to: &Path) {
+    for entry in WalkDir::new(from) {
+        let entry = entry.unwrap();
+        let relative = entry.path().strip_prefix(from).unwrap();
+        // Joining an empty path would add a trailing separator.
+        let dest = match relative.as_os_str().is_empty() {
+            true => to.to_path_buf(),
+            false => to.join(relative),
+        };
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&dest)
+                .expect("Failed to create mirror directory");
+        } else {
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)
+                    .expect("Failed to create mirror directory");
+            }
+            std::fs::copy(entry.path(), &dest)
+                .expect("Failed to copy resource to mirror");
+            let mut permissions =
+                std::fs::metadata(&dest).unwrap().permissions();
+            permissions.set_readonly(true);
+            std::fs::set_permissions(&dest, permissions).unwrap();
+        }
+    }
+}
+
+/// Contents of a manifest listing the SHA-1 checksum of every file in a
+/// directory, in the format of `sha1sum`, so that it can be verified
+/// with `sha1sum -c MANIFEST`.
+fn manifest(directory: &Path) -> String {
+    let lines: Vec<String> = WalkDir::new(directory)
+        .sort_by_file_name()
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with(MANIFEST))
+        .map(|e| {
+            format!(
+                "{}  {}",
+                file_checksum(e.path()),
+                e.path().strip_prefix(directory).unwrap().display()
+            )
+        })
+        .collect();
+    lines.join("\n") + "\n"
+}
+
+/// Files of a directory that are missing or whose checksum differs
+/// from its manifest, as happens after a partial transfer.
+fn verify_manifest(directory: &Path) -> Vec<String> {
+    let manifest = std::fs::read_to_string(directory.join(MANIFEST))
+        .expect("Failed to read manifest");
+    let mut problems = Vec::new();
+    for line in manifest.lines().filter(|l| !l.is_empty()) {
+        let (checksum, file) = match line.split_once("  ") {
+            Some(x) => x,
+            None => panic!("Invalid manifest line: {}", line),
+        };
+        let path = directory.join(file);
+        if !path.is_file() {
+            problems.push(format!("{} is missing", file));
+        } else if file_checksum(&path) != checksum {
+            problems.push(format!("{} does not match its checksum", file));
+        }
+    }
+    problems
+}
+
+/// Sign a file with an external signing tool.
+///
+/// # Arguments
+///
+/// * `signer` - Either "minisign", which writes `<file>.minisig`, or
+///   "ssh", which uses `ssh-keygen` and writes `<file>.sig`.
+/// * `key` - Secret key file.
+/// * `file` - File to sign.
+fn sign(signer: &str, key: &str, file: &Path) {
+    let mut command = match signer {
+        "minisign" => {
+            let mut c = Command::new("minisign");
+            c.arg("-S").arg("-s").arg(key).arg("-m").arg(file);
+            c
+        }
+        "ssh" => {
+            let mut c = Command::new("ssh-keygen");
+            c.args(["-Y", "sign", "-n", "librarian", "-f"])
+                .arg(key)
+                .arg(file);
+            c
+        }
+        _ => panic!("Unknown signer {}", signer),
+    };
+    let status = command
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {}: {}", signer, e));
+    if !status.success() {
+        panic!("Failed to sign the manifest with {}", signer);
+    }
+}
+
+/// Create a read-only copy of the library, with a manifest of the
+/// checksums of all its files, optionally signed.
+///
+/// The mirror contains the catalog as a single file, the resources
+/// directory, using the library's storage layout and including the
+/// attachments of resources, and the ancillary files of the mirrored
+/// resources (see `ANCILLARY_DIRS`). Every file is
+/// listed in the manifest, so a partially transferred mirror can be
+/// detected with `librarian_verify_mirror`.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `dest` - Directory to create the mirror in. It must not exist or
+///   be empty.
+/// * `signer` - Tool to sign the manifest with. See `sign`.
+/// * `key` - Secret key to sign the manifest with. The manifest isn't
+///   signed if this is omitted.
+/// * `include_private` - Include private resources.
+pub fn librarian_mirror(
+    catalog: &Catalog,
+    resources_path: &Path,
+    dest: &Path,
+    signer: &str,
+    key: Option<&str>,
+    include_private: bool,
+) {
+    if dest.exists()
+        && std::fs::read_dir(dest)
+            .expect("Failed to read mirror destination")
+            .next()
+            .is_some()
+    {
+        panic!("Mirror destination {} is not empty", dest.display());
+    }
+    std::fs::create_dir_all(dest).expect("Failed to create mirror destination");
+
+    let mut catalog = catalog.clone();
+    catalog.resources.retain(|r| include_private || !r.private);
+
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let from = Storage::of(resources_path, &catalog);
+    let to = Storage::of(&dest.join("resources"), &catalog);
+    for resource in &catalog.resources {
+        let key = &resource.historical_checksums[0];
+        let path = from.resource_path(key);
+        match path.exists() {
+            true => copy_read_only(&path, &to.resource_path(key)),
+            false => eprintln!(
+                "{}",
+                message("resource-missing", &[("key", key.as_str().into())])
+            ),
+        }
+        if from.attachments_path(key).exists() {
+            copy_read_only(
+                &from.attachments_path(key),
+                &to.attachments_path(key),
+            );
+        }
+        for file in ancillary_files(library, key) {
+            copy_read_only(&library.join(&file), &dest.join(&file));
+        }
+    }
+    std::fs::write(
+        dest.join("catalog.json"),
+        serde_json::to_string_pretty(&catalog).unwrap(),
+    )
+    .expect("Failed to write mirror catalog");
+
+    let manifest_path = dest.join(MANIFEST);
+    std::fs::write(&manifest_path, manifest(dest))
+        .expect("Failed to write mirror manifest");
+    if let Some(k) = key {
+        sign(signer, k, &manifest_path);
+    }
+}
+
+/// Check that every file listed in a mirror's manifest is present and
+/// unchanged, reporting each problem to stderr.
+///
+/// # Arguments
+///
+/// * `mirror` - Mirror directory.
+pub fn librarian_verify_mirror(mirror: &Path) {
+    let problems = verify_manifest(mirror);
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    if !problems.is_empty() {
+        panic!("{} files of the mirror failed verification", problems.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("resources")).unwrap();
+        std::fs::write(dir.join("catalog.json"), "{}").unwrap();
+        std::fs::write(dir.join("resources").join("a"), "hello").unwrap();
+        std::fs::write(dir.join(MANIFEST), "ignored").unwrap();
+
+        let manifest = manifest(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            manifest
+                == format!(
+                    "{}  catalog.json\n{}  {}\n",
+                    "bf21a9e8fbc5a3846fb05b4fa0859e0917b2202f",
+                    "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+                    Path::new("resources").join("a").display()
+                )
+        );
+    }
+
+    #[test]
+    fn test_ancillary_files_and_verify_manifest() {
+        let dir = std::env::temp_dir()
+            .join(format!("librarian-ancillary-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("notes")).unwrap();
+        std::fs::create_dir_all(dir.join("thumbnails")).unwrap();
+        std::fs::write(dir.join("notes").join("aa.org"), "notes").unwrap();
+        std::fs::write(dir.join("notes").join("aab.org"), "other").unwrap();
+        std::fs::write(dir.join("thumbnails").join("aa.png"), "png").unwrap();
+        assert!(
+            ancillary_files(&dir, "aa")
+                == vec![
+                    Path::new("notes").join("aa.org"),
+                    Path::new("thumbnails").join("aa.png")
+                ]
+        );
+
+        std::fs::write(dir.join(MANIFEST), manifest(&dir)).unwrap();
+        assert!(verify_manifest(&dir).is_empty());
+        std::fs::write(dir.join("notes").join("aa.org"), "truncated").unwrap();
+        std::fs::remove_file(dir.join("thumbnails").join("aa.png")).unwrap();
+        let problems = verify_manifest(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(problems.len() == 2);
+    }
+}
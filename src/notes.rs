@@ -0,0 +1,211 @@
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::journal;
+use crate::mirror::ancillary_files;
+use crate::resource::Resource;
+use crate::search::pick_resource;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory of the library holding reading notes.
+const NOTES_DIR: &str = "notes";
+
+/// Notes file of a resource, relative to the library directory. An
+/// existing notes file is used whatever its extension, so notes written
+/// in another format are kept.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `key` - Initial checksum of the resource.
+/// * `extension` - Extension of a new notes file.
+pub fn notes_file(library: &Path, key: &str, extension: &str) -> PathBuf {
+    ancillary_files(library, key)
+        .into_iter()
+        .find(|f| f.starts_with(NOTES_DIR))
+        .unwrap_or_else(|| {
+            Path::new(NOTES_DIR).join(format!("{}.{}", key, extension))
+        })
+}
+
+/// Whether the notes of a resource contain every word of a query,
+/// ignoring case. Resources without notes never match.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `key` - Initial checksum of the resource.
+/// * `query` - Search query.
+pub fn notes_match(library: &Path, key: &str, query: &str) -> bool {
+    let notes: String = ancillary_files(library, key)
+        .into_iter()
+        .filter(|f| f.starts_with(NOTES_DIR))
+        .filter_map(|f| std::fs::read_to_string(library.join(f)).ok())
+        .collect::<Vec<String>>()
+        .join("\n")
+        .to_lowercase();
+    !notes.is_empty()
+        && query
+            .split_whitespace()
+            .all(|w| notes.contains(&w.to_lowercase()))
+}
+
+/// Give the notes of a resource merged into another resource to the
+/// kept resource. They're moved when the kept resource has no notes,
+/// and appended to its notes otherwise.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `keep` - Initial checksum of the kept resource.
+/// * `other` - Initial checksum of the merged resource.
+pub fn merge_notes(library: &Path, keep: &str, other: &str) {
+    let notes = |key: &str| {
+        ancillary_files(library, key)
+            .into_iter()
+            .filter(|f| f.starts_with(NOTES_DIR))
+            .map(|f| library.join(f))
+            .collect::<Vec<PathBuf>>()
+    };
+    for from in notes(other) {
+        match notes(keep).first() {
+            Some(to) => {
+                let mut kept = std::fs::read_to_string(to)
+                    .expect("Failed to read notes file");
+                let merged = std::fs::read_to_string(&from)
+                    .expect("Failed to read notes file");
+                if !kept.is_empty() && !kept.ends_with('\n') {
+                    kept.push('\n');
+                }
+                kept.push('\n');
+                kept.push_str(&merged);
+                std::fs::write(to, kept).expect("Failed to write notes file");
+                if !journal::trash(&from) {
+                    std::fs::remove_file(&from)
+                        .expect("Failed to remove notes file");
+                }
+            }
+            None => {
+                let name = from.file_name().unwrap().to_string_lossy();
+                let to = from.with_file_name(format!(
+                    "{}{}",
+                    keep,
+                    name.strip_prefix(other).unwrap()
+                ));
+                std::fs::rename(&from, &to).expect("Failed to move notes file");
+                journal::moved(&from, &to);
+            }
+        }
+    }
+}
+
+/// Heading of a new notes file, in the markup of its extension.
+fn notes_heading(resource: &Resource, extension: &str) -> String {
+    match extension {
+        "md" => format!("# {}\n\n", resource.title),
+        "org" => format!("#+title: {}\n\n", resource.title),
+        _ => String::new(),
+    }
+}
+
+/// Open the reading notes of a resource in the user's editor, creating
+/// them if they don't exist yet.
+///
+/// The editor is taken from `$VISUAL` or `$EDITOR`, falling back to
+/// `vi`.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+pub fn librarian_note(catalog: &Catalog, resources_path: &Path, query: &str) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let resource =
+        match pick_resource(catalog, query, &message("pick-note", &[])) {
+            Some(r) => r,
+            None => return,
+        };
+    let extension = catalog.notes_extension.as_deref().unwrap_or("md");
+    let path = library.join(notes_file(
+        library,
+        &resource.historical_checksums[0],
+        extension,
+    ));
+    if !path.exists() {
+        std::fs::create_dir_all(library.join(NOTES_DIR))
+            .expect("Failed to create notes directory");
+        std::fs::write(&path, notes_heading(resource, extension))
+            .expect("Failed to create notes file");
+    }
+
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+    // The editor may be given with arguments, e.g., "emacsclient -t".
+    let mut words = editor.split_whitespace();
+    let status = Command::new(words.next().unwrap())
+        .args(words)
+        .arg(&path)
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {}: {}", editor, e));
+    if !status.success() {
+        panic!("{} exited with {}", editor, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes_file() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-notes-{}", std::process::id()));
+        std::fs::create_dir_all(library.join(NOTES_DIR)).unwrap();
+
+        assert!(notes_file(&library, "aa", "md") == Path::new("notes/aa.md"));
+        assert!(!notes_match(&library, "aa", "noise"));
+        std::fs::write(
+            library.join("notes/aa.org"),
+            "Phase noise of ring oscillators",
+        )
+        .unwrap();
+        // Existing notes keep their extension.
+        assert!(notes_file(&library, "aa", "md") == Path::new("notes/aa.org"));
+        assert!(notes_match(&library, "aa", "Noise ring"));
+        assert!(!notes_match(&library, "aa", "noise LC"));
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn test_merge_notes() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-merge-notes-{}", std::process::id()));
+        let notes = library.join(NOTES_DIR);
+        std::fs::create_dir_all(&notes).unwrap();
+
+        std::fs::write(notes.join("bb.md"), "# B\n").unwrap();
+        merge_notes(&library, "aa", "bb");
+        assert!(!notes.join("bb.md").exists());
+        assert!(
+            std::fs::read_to_string(notes.join("aa.md")).unwrap() == "# B\n"
+        );
+        // Notes of both resources are kept.
+        std::fs::write(notes.join("cc.org"), "* C\n").unwrap();
+        std::fs::write(notes.join("cc.txt"), "C").unwrap();
+        merge_notes(&library, "aa", "cc");
+        assert!(!notes.join("cc.org").exists());
+        assert!(
+            std::fs::read_to_string(notes.join("aa.md")).unwrap()
+                == "# B\n\n* C\n\nC"
+        );
+        merge_notes(&library, "aa", "dd");
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
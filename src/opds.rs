@@ -0,0 +1,245 @@
+use crate::catalog::Catalog;
+use crate::check::extension_media_type;
+use crate::resource::{DateTime, NameFormat, Resource};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+/// Media type of an OPDS navigation feed, whose entries lead to other
+/// feeds.
+pub const NAVIGATION_TYPE: &str =
+    "application/atom+xml;profile=opds-catalog;kind=navigation";
+
+/// Media type of an OPDS acquisition feed, whose entries are resources
+/// that can be downloaded.
+pub const ACQUISITION_TYPE: &str =
+    "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// Escape the characters of a text that are special in XML.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Time in the format Atom requires, with the parts a date lacks set to
+/// their first value.
+fn atom_time(date: &DateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        date.year.unwrap_or(1970),
+        date.month.unwrap_or(1),
+        date.day.unwrap_or(1),
+        date.hour.unwrap_or(0),
+        date.minute.unwrap_or(0),
+        date.second.unwrap_or(0)
+    )
+}
+
+/// Media type of the file of a resource, `application/octet-stream` if
+/// its document type doesn't tell.
+pub fn media_type(catalog: &Catalog, resource: &Resource) -> String {
+    let document = match resource
+        .document
+        .as_ref()
+        .and_then(|d| catalog.document_types.get(d))
+    {
+        Some(d) => d,
+        None => return "application/octet-stream".to_string(),
+    };
+    match &document.mime {
+        Some(mime) => String::from(mime.clone()),
+        None => extension_media_type(&document.extension)
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+    }
+}
+
+/// Path segment of a tag or collection in a feed URL.
+fn segment(name: &str) -> String {
+    utf8_percent_encode(name, NON_ALPHANUMERIC).to_string()
+}
+
+/// Atom feed with a header and entries.
+fn feed(
+    id: &str,
+    title: &str,
+    kind: &str,
+    updated: &str,
+    entries: &str,
+) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">
+  <id>{}</id>
+  <title>{}</title>
+  <updated>{}</updated>
+  <link rel=\"start\" href=\"/opds\" type=\"{}\"/>
+  <link rel=\"self\" href=\"{}\" type=\"{}\"/>
+{}</feed>
+",
+        xml_escape(id),
+        xml_escape(title),
+        updated,
+        NAVIGATION_TYPE,
+        xml_escape(id),
+        kind,
+        entries
+    )
+}
+
+/// Feed leading to the resources of the library: all of them, those with
+/// each tag and those of each collection.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+pub fn navigation_feed(catalog: &Catalog) -> String {
+    let updated = atom_time(&DateTime::from(std::time::SystemTime::now()));
+    let entry = |href: String, title: &str| {
+        format!(
+            "  <entry>
+    <id>{}</id>
+    <title>{}</title>
+    <updated>{}</updated>
+    <link rel=\"subsection\" href=\"{}\" type=\"{}\"/>
+  </entry>
+",
+            xml_escape(&href),
+            xml_escape(title),
+            updated,
+            xml_escape(&href),
+            ACQUISITION_TYPE
+        )
+    };
+    let mut entries = entry("/opds/all".to_string(), "All resources");
+    for tag in catalog.tags.keys() {
+        entries.push_str(&entry(
+            format!("/opds/tags/{}", segment(tag)),
+            &catalog.tag_path(tag),
+        ));
+    }
+    for name in catalog.collections.keys() {
+        entries.push_str(&entry(
+            format!("/opds/collections/{}", segment(name)),
+            name,
+        ));
+    }
+    feed("/opds", "Library", NAVIGATION_TYPE, &updated, &entries)
+}
+
+/// Feed of resources, each with a link to download its file.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `id` - Path of the feed, which is its identifier.
+/// * `title` - Title of the feed.
+/// * `resources` - Resources of the feed.
+pub fn acquisition_feed(
+    catalog: &Catalog,
+    id: &str,
+    title: &str,
+    resources: &[&Resource],
+) -> String {
+    let time = |r: &Resource| {
+        r.date_modified
+            .as_ref()
+            .or(r.date_added.as_ref())
+            .map(atom_time)
+    };
+    let mut entries = String::new();
+    for resource in resources {
+        let key = &resource.historical_checksums[0];
+        let mut entry = format!(
+            "  <entry>
+    <id>urn:librarian:{}</id>
+    <title>{}</title>
+    <updated>{}</updated>
+",
+            key,
+            xml_escape(&resource.title),
+            time(resource).unwrap_or_else(|| atom_time(&DateTime::new()))
+        );
+        for author in resource.author.iter().flatten() {
+            entry.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                xml_escape(&author.format(NameFormat::FirstLast))
+            ));
+        }
+        if let Some(date) = &resource.date {
+            entry.push_str(&format!(
+                "    <dc:issued>{}</dc:issued>\n",
+                String::from(date.clone())
+            ));
+        }
+        if let Some(language) = &resource.language {
+            entry.push_str(&format!(
+                "    <dc:language>{}</dc:language>\n",
+                xml_escape(language)
+            ));
+        }
+        if let Some(publisher) = &resource.publisher {
+            entry.push_str(&format!(
+                "    <dc:publisher>{}</dc:publisher>\n",
+                xml_escape(publisher)
+            ));
+        }
+        for tag in resource.tags.iter().flatten() {
+            entry.push_str(&format!(
+                "    <category term=\"{}\" label=\"{}\"/>\n",
+                xml_escape(tag),
+                xml_escape(&catalog.tag_path(tag))
+            ));
+        }
+        if let Some(summary) = &resource.r#abstract {
+            entry.push_str(&format!(
+                "    <summary>{}</summary>\n",
+                xml_escape(summary)
+            ));
+        }
+        entry.push_str(&format!(
+            "    <link rel=\"http://opds-spec.org/acquisition\" href=\"/resources/{}/file\" type=\"{}\"/>
+  </entry>
+",
+            key,
+            media_type(catalog, resource)
+        ));
+        entries.push_str(&entry);
+    }
+    let updated = resources
+        .iter()
+        .filter_map(|r| time(r))
+        .max()
+        .unwrap_or_else(|| atom_time(&DateTime::new()));
+    feed(id, title, ACQUISITION_TYPE, &updated, &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquisition_feed() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"pdf\": {\"extension\": \"pdf\", \"mime\": null},
+                                  \"book\": {\"extension\": \"epub\", \"mime\": \"application/epub+zip\"}},
+              \"content_types\": {}, \"resources\": [
+              {\"title\": \"Noise & Oscillators\", \"author\": [\"Ali Hajimiri\"], \"document\": \"pdf\",
+               \"date_modified\": \"2024-03-01T10:00:00\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Circuits\", \"document\": \"book\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let resources: Vec<&Resource> = catalog.resources.iter().collect();
+        let feed = acquisition_feed(&catalog, "/opds/all", "All", &resources);
+        assert!(feed.contains("<title>Noise &amp; Oscillators</title>"));
+        assert!(feed.contains("<author><name>Ali Hajimiri</name></author>"));
+        assert!(feed
+            .contains("href=\"/resources/aa/file\" type=\"application/pdf\""));
+        assert!(feed.contains(
+            "href=\"/resources/bb/file\" type=\"application/epub+zip\""
+        ));
+        assert!(feed.contains("<updated>2024-03-01T10:00:00Z</updated>"));
+    }
+}
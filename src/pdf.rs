@@ -0,0 +1,526 @@
+use crate::metadata::Metadata;
+use crate::resource::{DateTime, Name};
+
+use flate2::read::ZlibDecoder;
+use regex::bytes::Regex;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Whether a file is a PDF, judged by its leading magic bytes.
+pub fn is_pdf(path: &Path) -> bool {
+    let mut magic = [0u8; 5];
+    match File::open(path) {
+        Ok(mut f) => f.read_exact(&mut magic).is_ok() && &magic == b"%PDF-",
+        Err(_) => false,
+    }
+}
+
+/// Read the title, authors and creation date embedded in a PDF.
+///
+/// The XMP metadata packet is preferred over the document information
+/// dictionary, since it is the newer of the two and usually the one
+/// kept up to date by authoring tools. Neither is found when it is
+/// stored in a compressed object stream.
+///
+/// # Return
+///
+/// Returns `None` if the file is not a PDF or carries no usable
+/// metadata.
+pub fn pdf_metadata(path: &Path) -> Option<Metadata> {
+    if !is_pdf(path) {
+        return None;
+    }
+    let contents = std::fs::read(path).ok()?;
+
+    let mut metadata = Metadata::new();
+    metadata.title = xmp_title(&contents)
+        .or_else(|| info_string(&contents, "Title"))
+        .filter(|t| !t.trim().is_empty());
+    let authors = match xmp_creators(&contents) {
+        Some(c) => c,
+        None => info_string(&contents, "Author")
+            .map(|a| split_authors(&a))
+            .unwrap_or_default(),
+    };
+    let authors: Vec<Name> = authors
+        .iter()
+        .filter_map(|a| Name::try_from(a.as_str()).ok())
+        .collect();
+    if !authors.is_empty() {
+        metadata.author = Some(authors);
+    }
+    metadata.date = xmp_create_date(&contents).or_else(|| {
+        info_string(&contents, "CreationDate").and_then(|d| info_date(&d))
+    });
+
+    match metadata == Metadata::new() {
+        true => None,
+        false => Some(metadata),
+    }
+}
+
+/// Number of text content streams searched for a DOI. Content streams
+/// are not mapped to pages, but a page typically has one or two, so
+/// this roughly corresponds to the first few pages.
+const DOI_SEARCH_STREAMS: usize = 6;
+
+/// Find the DOI of a PDF, either from its XMP metadata or from the
+/// text of its first pages.
+///
+/// # Return
+///
+/// Returns `None` if the file is not a PDF or no DOI was found.
+pub fn pdf_doi(path: &Path) -> Option<String> {
+    if !is_pdf(path) {
+        return None;
+    }
+    let contents = std::fs::read(path).ok()?;
+    xmp_doi(&contents).or_else(|| text_doi(&contents))
+}
+
+/// DOI from the XMP packet's PRISM or pdfx `doi` property.
+fn xmp_doi(contents: &[u8]) -> Option<String> {
+    let re = Regex::new(r#"(?:prism|pdfx):doi(?:>|=")\s*(?:doi:)?([^<"]+)"#)
+        .unwrap();
+    let doi = re.captures(contents)?;
+    find_doi(&xml_text(&doi[1]))
+}
+
+/// First DOI in the text of the leading content streams of a PDF.
+fn text_doi(contents: &[u8]) -> Option<String> {
+    let re = Regex::new(r"(?s-u)stream\r?\n(.*?)endstream").unwrap();
+    let doi = re
+        .captures_iter(contents)
+        .map(|c| decode_stream(&c[1]))
+        .filter(|s| is_text_content(s))
+        .take(DOI_SEARCH_STREAMS)
+        .find_map(|s| find_doi(&content_text(&s)));
+    doi
+}
+
+/// Approximate text of a PDF, from every content stream that shows
+/// text (see `content_text`). Content streams stored in compressed
+/// object streams aren't found.
+///
+/// # Return
+///
+/// Returns `None` if the file is not a PDF.
+pub fn pdf_text(path: &Path) -> Option<String> {
+    if !is_pdf(path) {
+        return None;
+    }
+    let contents = std::fs::read(path).ok()?;
+    let re = Regex::new(r"(?s-u)stream\r?\n(.*?)endstream").unwrap();
+    let text: Vec<String> = re
+        .captures_iter(&contents)
+        .map(|c| decode_stream(&c[1]))
+        .filter(|s| is_text_content(s))
+        .map(|s| content_text(&s))
+        .collect();
+    Some(text.join("\n"))
+}
+
+/// Inflate a stream compressed with the FlateDecode filter. Streams
+/// that cannot be inflated are returned unchanged, since they are most
+/// likely not compressed.
+fn decode_stream(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    // A truncated or corrupt stream still yields whatever was inflated
+    // before the error.
+    let _ = ZlibDecoder::new(data).read_to_end(&mut decoded);
+    match decoded.is_empty() {
+        true => data.to_vec(),
+        false => decoded,
+    }
+}
+
+/// Whether a decoded stream is a page content stream that shows text.
+fn is_text_content(stream: &[u8]) -> bool {
+    let re = Regex::new(r"(?-u)\bBT\b[\s\S]*?(?:\bTJ|\bTj|')").unwrap();
+    re.is_match(stream)
+}
+
+/// Approximate text shown by a content stream.
+///
+/// Only literal strings are decoded, which assumes a simple font
+/// encoding. Strings within a `TJ` array are joined, inserting a space
+/// for large negative adjustments, and text positioning operators start
+/// a new line.
+fn content_text(content: &[u8]) -> String {
+    let is_delimiter = |c: u8| b"()<>[]{}/%".contains(&c);
+    let mut text = Vec::new();
+    let mut in_array = false;
+    let mut i = 0;
+    while i < content.len() {
+        let c = content[i];
+        match c {
+            b'(' => match literal_string(&content[i + 1..]) {
+                Some((s, n)) => {
+                    text.extend(s);
+                    i += n;
+                }
+                None => break,
+            },
+            b'[' => in_array = true,
+            b']' => in_array = false,
+            b'<' => match content[i..].iter().position(|&c| c == b'>') {
+                Some(n) => i += n,
+                None => break,
+            },
+            b'+' | b'-' | b'.' | b'0'..=b'9' => {
+                let n = content[i..]
+                    .iter()
+                    .position(|c| !b"+-.0123456789".contains(c))
+                    .unwrap_or(content.len() - i);
+                let number = String::from_utf8_lossy(&content[i..i + n]);
+                if in_array && number.parse::<f64>().unwrap_or(0.) < -250. {
+                    text.push(b' ');
+                }
+                i += n - 1;
+            }
+            _ if c.is_ascii_whitespace() || is_delimiter(c) => (),
+            _ => {
+                let n = content[i..]
+                    .iter()
+                    .position(|&c| c.is_ascii_whitespace() || is_delimiter(c))
+                    .unwrap_or(content.len() - i);
+                match &content[i..i + n] {
+                    b"Td" | b"TD" | b"Tm" | b"T*" | b"ET" | b"'" | b"\"" => {
+                        text.push(b'\n')
+                    }
+                    _ => (),
+                }
+                i += n - 1;
+            }
+        }
+        i += 1;
+    }
+    decode_text_string(&text)
+}
+
+/// First DOI in some text, without trailing punctuation.
+fn find_doi(text: &str) -> Option<String> {
+    let re = regex::Regex::new(r"(?i)\b10\.\d{4,9}/[-._;()/:A-Z0-9]+").unwrap();
+    let mut doi = re.find(text)?.as_str();
+    loop {
+        let trimmed = doi.trim_end_matches(['.', ',', ';']);
+        // Drop a closing parenthesis that belongs to the surrounding
+        // text rather than the DOI.
+        doi = match trimmed.ends_with(')')
+            && trimmed.matches('(').count() < trimmed.matches(')').count()
+        {
+            true => &trimmed[..trimmed.len() - 1],
+            false => break Some(trimmed.to_string()),
+        };
+    }
+}
+
+/// Split the free-form author string of a document information
+/// dictionary into individual names.
+pub(crate) fn split_authors(authors: &str) -> Vec<String> {
+    let re = regex::Regex::new(r"\s*(?:;|\band\b|&)\s*").unwrap();
+    re.split(authors)
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// Title from the XMP packet's Dublin Core `dc:title`.
+fn xmp_title(contents: &[u8]) -> Option<String> {
+    let re =
+        Regex::new(r"(?s)<dc:title>.*?<rdf:li[^>]*>(.*?)</rdf:li>").unwrap();
+    re.captures(contents).map(|c| xml_text(&c[1]))
+}
+
+/// Authors from the XMP packet's Dublin Core `dc:creator` sequence.
+fn xmp_creators(contents: &[u8]) -> Option<Vec<String>> {
+    let creator = Regex::new(r"(?s)<dc:creator>(.*?)</dc:creator>")
+        .unwrap()
+        .captures(contents)?;
+    let creators: Vec<String> = Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>")
+        .unwrap()
+        .captures_iter(&creator[1])
+        .map(|c| xml_text(&c[1]))
+        .filter(|c| !c.is_empty())
+        .collect();
+    match creators.is_empty() {
+        true => None,
+        false => Some(creators),
+    }
+}
+
+/// Creation date from the XMP packet, which may either be an element
+/// or an attribute of `rdf:Description`.
+fn xmp_create_date(contents: &[u8]) -> Option<DateTime> {
+    let re = Regex::new(r#"xmp:CreateDate(?:>|=")([0-9T:.+Z-]+)"#).unwrap();
+    let date = re.captures(contents)?;
+    DateTime::try_from(String::from_utf8_lossy(&date[1]).as_ref()).ok()
+}
+
+/// Decode the text content of an XML or HTML element.
+pub fn xml_text(text: &[u8]) -> String {
+    let text = String::from_utf8_lossy(text);
+    regex::Regex::new(r"&#([xX][0-9a-fA-F]+|[0-9]+);")
+        .unwrap()
+        .replace_all(text.trim(), |c: &regex::Captures| {
+            let code = match c[1].strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => c[1].parse(),
+            };
+            code.ok()
+                .and_then(char::from_u32)
+                .map(String::from)
+                .unwrap_or_else(|| c[0].to_string())
+        })
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Convert a PDF date string (e.g., "D:20190131120000+01'00'") to a
+/// `DateTime`.
+fn info_date(date: &str) -> Option<DateTime> {
+    let date = date.trim_start_matches("D:");
+    let digits: String =
+        date.chars().take_while(|c| c.is_ascii_digit()).collect();
+    // Insert the ISO 8601 separators between the components that are
+    // present.
+    let mut iso = String::new();
+    for (i, c) in digits.chars().take(14).enumerate() {
+        match i {
+            4 | 6 => iso.push('-'),
+            8 => iso.push('T'),
+            10 | 12 => iso.push(':'),
+            _ => (),
+        }
+        iso.push(c);
+    }
+    // The UTC offset is written as "Z" or "+01'00'".
+    let offset = date[digits.len()..].replace('\'', ":");
+    let offset = offset.trim_end_matches(':');
+    if digits.len() >= 10 && !offset.is_empty() {
+        iso.push_str(offset);
+    }
+    match iso.len() >= 4 {
+        true => DateTime::try_from(iso.as_str()).ok(),
+        false => None,
+    }
+}
+
+/// Value of a string entry (e.g., "/Title") of the document information
+/// dictionary.
+///
+/// This finds the last occurrence of the key, since incremental updates
+/// append new dictionaries to the end of the file.
+fn info_string(contents: &[u8], key: &str) -> Option<String> {
+    let re = Regex::new(&format!(r"/{}\s*([(<])", key)).unwrap();
+    let start = re.captures_iter(contents).last()?.get(1)?;
+    let bytes = match contents[start.start()] {
+        b'(' => literal_string(&contents[start.end()..]).map(|(s, _)| s),
+        _ => hex_string(&contents[start.end()..]),
+    }?;
+    Some(decode_text_string(&bytes))
+}
+
+/// Decode the body of a PDF literal string, starting just after the
+/// opening parenthesis.
+///
+/// # Return
+///
+/// The decoded string and the number of bytes consumed, including the
+/// closing parenthesis.
+fn literal_string(contents: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut bytes = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < contents.len() {
+        let c = contents[i];
+        match c {
+            b'\\' => {
+                i += 1;
+                let escaped = *contents.get(i)?;
+                match escaped {
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'b' => bytes.push(0x08),
+                    b'f' => bytes.push(0x0c),
+                    b'0'..=b'7' => {
+                        // Up to three octal digits.
+                        let mut value: u32 = 0;
+                        let mut n = 0;
+                        while n < 3 {
+                            match contents.get(i + n) {
+                                Some(d @ b'0'..=b'7') => {
+                                    value = value * 8 + (d - b'0') as u32;
+                                    n += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                        i += n - 1;
+                    }
+                    // A backslash at the end of a line continues the
+                    // string on the next line.
+                    b'\n' => (),
+                    b'\r' => {
+                        if contents.get(i + 1) == Some(&b'\n') {
+                            i += 1;
+                        }
+                    }
+                    _ => bytes.push(escaped),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                bytes.push(c);
+            }
+            b')' => {
+                if depth == 0 {
+                    return Some((bytes, i + 1));
+                }
+                depth -= 1;
+                bytes.push(c);
+            }
+            _ => bytes.push(c),
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Decode the body of a PDF hexadecimal string, starting just after the
+/// opening angle bracket.
+fn hex_string(contents: &[u8]) -> Option<Vec<u8>> {
+    let end = contents.iter().position(|&c| c == b'>')?;
+    let mut digits: Vec<u8> = contents[..end]
+        .iter()
+        .cloned()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect();
+    // A missing final digit is assumed to be 0.
+    if digits.len() % 2 == 1 {
+        digits.push(b'0');
+    }
+    hex::decode(digits).ok()
+}
+
+/// Decode a PDF text string, which is either UTF-16BE with a byte order
+/// mark or PDFDocEncoding. PDFDocEncoding agrees with Latin-1 for all
+/// printable characters typically found in metadata.
+fn decode_text_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xfe, 0xff]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks(2)
+            .filter(|c| c.len() == 2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(&bytes[3..]).to_string()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+    .trim()
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_string() {
+        let contents = b"1 0 obj\n<< /Title (Classical \\(3rd\\) Electrodynamics) /Author <FEFF004A00610063006B0073006F006E> /CreationDate (D:19990101120000Z) >>\nendobj";
+        assert!(
+            info_string(contents, "Title").as_deref()
+                == Some("Classical (3rd) Electrodynamics")
+        );
+        assert!(info_string(contents, "Author").as_deref() == Some("Jackson"));
+        assert!(
+            info_date(&info_string(contents, "CreationDate").unwrap())
+                .map(String::from)
+                .as_deref()
+                == Some("1999-01-01T12:00:00")
+        );
+        assert!(
+            info_date("D:20190131010000+01'00'")
+                .map(String::from)
+                .as_deref()
+                == Some("2019-01-31T00:00:00")
+        );
+        assert!(info_string(contents, "Subject").is_none());
+    }
+
+    #[test]
+    fn test_xmp() {
+        let contents = br#"<x:xmpmeta><rdf:RDF>
+<rdf:Description xmp:CreateDate="2019-05-04T10:00:00+02:00">
+<dc:title><rdf:Alt><rdf:li xml:lang="x-default">Phase Noise &amp; Jitter</rdf:li></rdf:Alt></dc:title>
+<dc:creator><rdf:Seq><rdf:li>Ali Hajimiri</rdf:li><rdf:li>Thomas H. Lee</rdf:li></rdf:Seq></dc:creator>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        assert!(xmp_title(contents).as_deref() == Some("Phase Noise & Jitter"));
+        assert!(
+            xmp_creators(contents)
+                == Some(vec![
+                    String::from("Ali Hajimiri"),
+                    String::from("Thomas H. Lee")
+                ])
+        );
+        assert!(
+            xmp_create_date(contents).map(String::from).as_deref()
+                == Some("2019-05-04T08:00:00")
+        );
+    }
+
+    #[test]
+    fn test_split_authors() {
+        assert!(
+            split_authors("Adel Sedra; Kenneth Smith and John Doe")
+                == vec!["Adel Sedra", "Kenneth Smith", "John Doe"]
+        );
+    }
+
+    #[test]
+    fn test_find_doi() {
+        assert!(
+            find_doi("(doi: 10.1109/4.658619).").as_deref()
+                == Some("10.1109/4.658619")
+        );
+        assert!(
+            find_doi("DOI 10.1002/(SICI)1097-0118(199905)31:1<1::AID>")
+                .as_deref()
+                == Some("10.1002/(SICI)1097-0118(199905)31:1")
+        );
+        assert!(find_doi("version 10.2 of the standard").is_none());
+    }
+
+    #[test]
+    fn test_text_doi() {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let content = b"BT /F1 9 Tf 72 720 Td [(Phys. Rev.)-300(47, 777)] TJ \
+0 -11 Td [(DOI: 10.1103/Phys)10(Rev.47.777)] TJ ET";
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        let mut contents =
+            b"%PDF-1.5\n4 0 obj\n<< /Filter /FlateDecode >>\nstream\n".to_vec();
+        contents.extend(encoder.finish().unwrap());
+        contents.extend(b"\nendstream\nendobj\n");
+
+        assert!(
+            content_text(content)
+                == "Phys. Rev. 47, 777\nDOI: 10.1103/PhysRev.47.777"
+        );
+        assert!(
+            text_doi(&contents).as_deref() == Some("10.1103/PhysRev.47.777")
+        );
+    }
+}
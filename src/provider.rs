@@ -0,0 +1,246 @@
+use crate::resource::{Resource, PROTECTED_FIELDS};
+use crate::storage::Storage;
+
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::process::Command;
+
+/// Kind of identifier a metadata provider looks resources up by.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Eq, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierKind {
+    Doi,
+    Isbn,
+    Url,
+    /// Path of the resource's file, for providers that read the file
+    /// itself.
+    File,
+}
+
+impl IdentifierKind {
+    fn name(&self) -> &'static str {
+        match self {
+            IdentifierKind::Doi => "doi",
+            IdentifierKind::Isbn => "isbn",
+            IdentifierKind::Url => "url",
+            IdentifierKind::File => "file",
+        }
+    }
+}
+
+/// External program returning the metadata of resources, e.g., to query
+/// a publisher's API or go through an institutional proxy.
+///
+/// The program is run with its arguments followed by the kind of
+/// identifier ("doi", "isbn", "url" or "file") and the identifier. It
+/// prints a JSON object of resource fields, named as in the catalog, and
+/// exits successfully. Printing nothing means it has no metadata for the
+/// identifier.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct MetadataProvider {
+    /// Program followed by the arguments it's run with.
+    pub command: Vec<String>,
+    /// Identifiers the program accepts, tried in order.
+    pub identifiers: Vec<IdentifierKind>,
+}
+
+impl MetadataProvider {
+    /// Identifier of a resource the provider accepts, if it has one.
+    fn identifier(
+        &self,
+        resource: &Resource,
+        storage: &Storage,
+    ) -> Option<(IdentifierKind, String)> {
+        self.identifiers.iter().find_map(|kind| {
+            let identifier = match kind {
+                IdentifierKind::Doi => resource.doi.clone().map(String::from),
+                IdentifierKind::Isbn => resource.isbn.clone().map(String::from),
+                IdentifierKind::Url => {
+                    resource.url.as_ref().map(|u| u.to_string())
+                }
+                IdentifierKind::File => {
+                    let path = storage
+                        .resource_path(&resource.historical_checksums[0]);
+                    match path.exists() {
+                        true => path.to_str().map(String::from),
+                        false => None,
+                    }
+                }
+            };
+            identifier.map(|i| (*kind, i))
+        })
+    }
+
+    /// Run the program for an identifier.
+    ///
+    /// # Returns
+    ///
+    /// The fields it returned, or `None` if it has no metadata.
+    fn fetch(
+        &self,
+        kind: IdentifierKind,
+        identifier: &str,
+    ) -> Result<Option<Map<String, Value>>, String> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| "Command is empty".to_string())?;
+        let output = Command::new(program)
+            .args(args)
+            .arg(kind.name())
+            .arg(identifier)
+            .output()
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        if output.stdout.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        match serde_json::from_slice(&output.stdout) {
+            Ok(Value::Object(fields)) => Ok(Some(fields)),
+            Ok(_) => Err(format!("{} didn't return a JSON object", program)),
+            Err(e) => Err(format!("{} returned invalid JSON: {}", program, e)),
+        }
+    }
+}
+
+/// Resource with fields set over those of another.
+///
+/// # Arguments
+///
+/// * `resource` - Resource whose fields are replaced.
+/// * `fields` - Fields to set, named as in the catalog. Fields
+///   identifying the resource can't be set.
+pub fn set_fields(
+    resource: &Resource,
+    fields: &Map<String, Value>,
+) -> Result<Resource, String> {
+    if let Some(field) = fields
+        .keys()
+        .find(|f| PROTECTED_FIELDS.contains(&f.as_str()))
+    {
+        return Err(format!("{} can't be changed", field));
+    }
+    let mut value = serde_json::to_value(resource).unwrap();
+    for (field, v) in fields {
+        value[field.as_str()] = v.clone();
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// Whether any of the providers accepts an identifier of a resource.
+///
+/// # Arguments
+///
+/// * `providers` - Metadata providers, keyed by name.
+/// * `resource` - Resource to look up.
+/// * `storage` - Resources directory.
+pub fn provided(
+    providers: &IndexMap<String, MetadataProvider>,
+    resource: &Resource,
+    storage: &Storage,
+) -> bool {
+    providers
+        .values()
+        .any(|p| p.identifier(resource, storage).is_some())
+}
+
+/// Resource with the metadata of the first provider that has some for
+/// it, in order.
+///
+/// # Arguments
+///
+/// * `providers` - Metadata providers, keyed by name.
+/// * `resource` - Resource to look up.
+/// * `storage` - Resources directory.
+///
+/// # Returns
+///
+/// The resource with the provided fields, or `None` if no provider has
+/// metadata for it. Errors name the provider.
+pub fn provide(
+    providers: &IndexMap<String, MetadataProvider>,
+    resource: &Resource,
+    storage: &Storage,
+) -> Result<Option<Resource>, String> {
+    for (name, provider) in providers {
+        let (kind, identifier) = match provider.identifier(resource, storage) {
+            Some(i) => i,
+            None => continue,
+        };
+        let fields = provider
+            .fetch(kind, &identifier)
+            .and_then(|f| f.map(|f| set_fields(resource, &f)).transpose())
+            .map_err(|e| format!("Metadata provider {}: {}", name, e))?;
+        if fields.is_some() {
+            return Ok(fields);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageLayout;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_provide() {
+        let resource: Resource = serde_json::from_str(
+            "{\"title\": \"Untitled\", \"isbn\": \"978-0-13-468599-1\",
+              \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}",
+        )
+        .unwrap();
+        let provider = |script: &str, identifiers| MetadataProvider {
+            command: vec!["sh".into(), "-c".into(), script.into(), "sh".into()],
+            identifiers,
+        };
+        let mut providers = IndexMap::new();
+        // Providers without an identifier of the resource, or without
+        // metadata for it, are skipped.
+        providers.insert(
+            "doi".to_string(),
+            provider("exit 1", vec![IdentifierKind::Doi]),
+        );
+        providers.insert(
+            "empty".to_string(),
+            provider("true", vec![IdentifierKind::Isbn]),
+        );
+        providers.insert(
+            "books".to_string(),
+            provider(
+                "echo \"{\\\"title\\\": \\\"$1 $2\\\", \\\"author\\\": [\\\"Steve McConnell\\\"]}\"",
+                vec![IdentifierKind::Url, IdentifierKind::Isbn],
+            ),
+        );
+        let storage = Storage::new(&std::env::temp_dir(), StorageLayout::Flat);
+        assert!(provided(&providers, &resource, &storage));
+        let provided =
+            provide(&providers, &resource, &storage).unwrap().unwrap();
+        assert!(provided.title == "isbn 978-0-13-468599-1");
+        assert!(
+            provided.author.unwrap()[0].last.as_deref() == Some("McConnell")
+        );
+
+        providers.insert(
+            "checksum".to_string(),
+            provider(
+                "echo '{\"checksum\": \"bb\"}'",
+                vec![IdentifierKind::Isbn],
+            ),
+        );
+        providers.shift_remove("books");
+        assert!(provide(&providers, &resource, &storage).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::Resource;
+use crate::search::pick_resource;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How far the user got in reading a resource.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Hash, Eq, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadStatus {
+    Unread,
+    Reading,
+    Read,
+}
+
+impl ReadStatus {
+    /// Name of the status, as written in the catalog.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::Reading => "reading",
+            ReadStatus::Read => "read",
+        }
+    }
+}
+
+/// How soon the user wants to read a resource.
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// Priority with a name, as written in the catalog.
+    pub fn parse(name: &str) -> Option<Priority> {
+        match name {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
+    /// Name of the priority, as written in the catalog.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl Resource {
+    /// Reading status of the resource. Resources without one are
+    /// unread.
+    pub fn read_status(&self) -> ReadStatus {
+        self.read_status.unwrap_or(ReadStatus::Unread)
+    }
+
+    /// Reading priority of the resource, which is normal unless set.
+    pub fn priority(&self) -> Priority {
+        self.priority.unwrap_or(Priority::Normal)
+    }
+}
+
+/// Resources left to read, in the order they should be read: resources
+/// being read first, then by priority and then in catalog order.
+pub fn reading_queue(catalog: &Catalog) -> Vec<&Resource> {
+    let mut queue: Vec<&Resource> = catalog
+        .resources
+        .iter()
+        .filter(|r| r.read_status() != ReadStatus::Read)
+        .collect();
+    // The sort is stable, so resources of the same priority keep their
+    // catalog order.
+    queue.sort_by_key(|r| {
+        (
+            r.read_status() != ReadStatus::Reading,
+            std::cmp::Reverse(r.priority()),
+        )
+    });
+    queue
+}
+
+/// Print the reading queue (see `reading_queue`) along with the status
+/// and priority of each resource.
+pub fn librarian_queue(catalog: &Catalog) {
+    for (i, resource) in reading_queue(catalog).iter().enumerate() {
+        println!(
+            "{:>3}. {} {:<7} {:<6} {}",
+            i + 1,
+            resource.historical_checksums[0],
+            resource.read_status().name(),
+            resource.priority().name(),
+            resource.title
+        );
+    }
+}
+
+/// Change of a resource's reading workflow made by a quick-set
+/// command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadingChange {
+    Status(ReadStatus),
+    /// Rating from 1 to 5.
+    Rating(u8),
+    Priority(Priority),
+}
+
+impl ReadingChange {
+    /// Apply the change to a resource.
+    pub fn apply(&self, resource: &mut Resource) {
+        match self {
+            ReadingChange::Status(s) => resource.read_status = Some(*s),
+            ReadingChange::Rating(r) => resource.rating = Some(*r),
+            ReadingChange::Priority(p) => resource.priority = Some(*p),
+        }
+    }
+
+    /// Field the change sets along with its new value.
+    fn field_value(&self) -> (&'static str, String) {
+        match self {
+            ReadingChange::Status(s) => ("read_status", s.name().to_string()),
+            ReadingChange::Rating(r) => ("rating", r.to_string()),
+            ReadingChange::Priority(p) => ("priority", p.name().to_string()),
+        }
+    }
+}
+
+/// Change the reading status, rating or priority of a resource.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+/// * `operation` - Subcommand making the change, for the audit log.
+/// * `change` - Change to make.
+pub fn librarian_set_reading(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    query: &str,
+    operation: &str,
+    change: ReadingChange,
+) {
+    if let ReadingChange::Rating(r) = change {
+        if !(1..=5).contains(&r) {
+            panic!("Ratings range from 1 to 5, not {}", r);
+        }
+    }
+    let key = match pick_resource(catalog, query, &message("pick-change", &[]))
+    {
+        Some(r) => r.historical_checksums[0].clone(),
+        None => return,
+    };
+    let resource = catalog.resource_mut(&key).unwrap();
+    change.apply(resource);
+    let title = resource.title.clone();
+    let (field, value) = change.field_value();
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new(
+                operation,
+                vec![key.clone()],
+                vec![format!("{} {}: {}", key, field, value)],
+            ),
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "reading-changed",
+            &[
+                ("title", title.into()),
+                ("field", field.into()),
+                ("value", value.into())
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_queue() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"read_status\": \"read\", \"rating\": 4, \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"C\", \"priority\": \"high\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+              {\"title\": \"D\", \"read_status\": \"reading\", \"priority\": \"low\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]},
+              {\"title\": \"E\", \"checksum\": \"ee\", \"historical_checksums\": [\"ee\"]}
+            ]}",
+        )
+        .unwrap();
+        let titles: Vec<&str> = reading_queue(&catalog)
+            .iter()
+            .map(|r| r.title.as_str())
+            .collect();
+        assert!(titles == vec!["D", "C", "A", "E"]);
+
+        let mut resource = catalog.resources[0].clone();
+        ReadingChange::Status(ReadStatus::Read).apply(&mut resource);
+        assert!(resource.read_status() == ReadStatus::Read);
+        assert!(resource.priority() == Priority::Normal);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::catalog::Catalog;
+use crate::resource::Resource;
+use crate::search::ResourceFilter;
+
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Relationship of a resource to another resource.
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    /// The resource cites the other resource.
+    Cites,
+    /// The resource replaces the other resource, e.g., a revised
+    /// datasheet or a later edition.
+    Supersedes,
+    /// The resource is part of the other resource, e.g., a chapter of a
+    /// book or a paper of a proceedings volume.
+    PartOf,
+    /// The resource is a translation of the other resource.
+    TranslationOf,
+    /// The resources are related in some other way.
+    Related,
+}
+
+impl Relation {
+    /// Name of the relation, as written in the catalog.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Relation::Cites => "cites",
+            Relation::Supersedes => "supersedes",
+            Relation::PartOf => "part_of",
+            Relation::TranslationOf => "translation_of",
+            Relation::Related => "related",
+        }
+    }
+
+    /// Name of the relation seen from the other resource.
+    pub fn inverse_name(&self) -> &'static str {
+        match self {
+            Relation::Cites => "cited_by",
+            Relation::Supersedes => "superseded_by",
+            Relation::PartOf => "parts",
+            Relation::TranslationOf => "translations",
+            Relation::Related => "related",
+        }
+    }
+}
+
+/// Relation referring to a resource that isn't in the catalog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownRelation {
+    /// Initial checksum of the resource with the relation.
+    pub key: String,
+    pub relation: Relation,
+    /// Checksum the relation refers to.
+    pub target: String,
+}
+
+impl Catalog {
+    /// Make every relation refer to the initial checksum of its
+    /// resource, so that relations given by a later checksum survive
+    /// further changes of the resource.
+    ///
+    /// # Returns
+    ///
+    /// Relations referring to resources that aren't in the catalog,
+    /// which are kept as they are.
+    pub fn resolve_relations(&mut self) -> Vec<UnknownRelation> {
+        let initial: HashMap<String, String> = self
+            .resources
+            .iter()
+            .flat_map(|r| {
+                r.historical_checksums.iter().map(move |c| {
+                    (c.clone(), r.historical_checksums[0].clone())
+                })
+            })
+            .collect();
+        let mut unknown = vec![];
+        for resource in self.resources.iter_mut() {
+            let key = resource.historical_checksums[0].clone();
+            for (relation, targets) in resource.relations.iter_mut().flatten() {
+                for target in targets.iter_mut() {
+                    match initial.get(target) {
+                        Some(i) => *target = i.clone(),
+                        None => unknown.push(UnknownRelation {
+                            key: key.clone(),
+                            relation: *relation,
+                            target: target.clone(),
+                        }),
+                    }
+                }
+                targets.dedup();
+            }
+        }
+        unknown
+    }
+
+    /// Resources related to a resource, keyed by the name of the
+    /// relation. Relations of other resources to this one are keyed by
+    /// their inverse name (e.g., "cited_by").
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - Resource whose relations are given.
+    pub fn related<'a>(
+        &'a self,
+        resource: &Resource,
+    ) -> IndexMap<&'static str, Vec<&'a Resource>> {
+        let key = &resource.historical_checksums[0];
+        let find = |k: &String| {
+            self.resources
+                .iter()
+                .find(|r| r.historical_checksums[0] == *k)
+        };
+        let mut related: IndexMap<&'static str, Vec<&Resource>> =
+            IndexMap::new();
+        for (relation, targets) in resource.relations.iter().flatten() {
+            related
+                .entry(relation.name())
+                .or_default()
+                .extend(targets.iter().filter_map(find));
+        }
+        for other in &self.resources {
+            for (relation, targets) in other.relations.iter().flatten() {
+                if targets.contains(key) {
+                    related
+                        .entry(relation.inverse_name())
+                        .or_default()
+                        .push(other);
+                }
+            }
+        }
+        related.retain(|_, resources| !resources.is_empty());
+        related
+    }
+}
+
+/// Print a resource as JSON.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `checksum` - Current or historical checksum of the resource.
+/// * `related` - Print the resource along with its related resources,
+///   keyed by relation.
+pub fn librarian_show(catalog: &Catalog, checksum: &str, related: bool) {
+    let resource = catalog
+        .resources
+        .iter()
+        .find(|r| {
+            r.checksum == checksum
+                || r.historical_checksums.iter().any(|c| c == checksum)
+        })
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum));
+    let out = std::io::stdout();
+    match related {
+        true => serde_json::to_writer_pretty(
+            out.lock(),
+            &json!({
+                "resource": resource,
+                "related": catalog.related(resource),
+            }),
+        ),
+        false => serde_json::to_writer_pretty(out.lock(), resource),
+    }
+    .unwrap();
+    println!();
+}
+
+/// Format of the relationship graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// JSON object of nodes and edges.
+    Json,
+}
+
+/// Relationship network of the catalog. Only resources with relations
+/// (to or from them) are nodes.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `format` - Format of the graph.
+/// * `include_private` - Include private resources.
+pub fn relation_graph(
+    catalog: &Catalog,
+    format: GraphFormat,
+    include_private: bool,
+) -> String {
+    let resources = ResourceFilter::default().apply(catalog, include_private);
+    let included =
+        |k: &String| resources.iter().any(|r| r.historical_checksums[0] == *k);
+    let edges: Vec<(&String, Relation, &String)> = resources
+        .iter()
+        .flat_map(|r| {
+            r.relations
+                .iter()
+                .flatten()
+                .flat_map(move |(relation, ts)| {
+                    ts.iter().map(move |t| {
+                        (&r.historical_checksums[0], *relation, t)
+                    })
+                })
+        })
+        .filter(|(_, _, t)| included(t))
+        .collect();
+    let nodes: Vec<&Resource> = resources
+        .into_iter()
+        .filter(|r| {
+            let key = &r.historical_checksums[0];
+            edges.iter().any(|(s, _, t)| *s == key || *t == key)
+        })
+        .collect();
+
+    match format {
+        GraphFormat::Dot => {
+            let quote = |s: &str| {
+                format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            };
+            let mut dot = String::from("digraph library {\n");
+            for node in &nodes {
+                dot.push_str(&format!(
+                    "  {} [label={}];\n",
+                    quote(&node.historical_checksums[0]),
+                    quote(&node.title)
+                ));
+            }
+            for (source, relation, target) in &edges {
+                dot.push_str(&format!(
+                    "  {} -> {} [label={}];\n",
+                    quote(source),
+                    quote(target),
+                    quote(relation.name())
+                ));
+            }
+            dot.push_str("}\n");
+            dot
+        }
+        GraphFormat::Json => {
+            let graph = json!({
+                "nodes": nodes
+                    .iter()
+                    .map(|n| json!({
+                        "id": n.historical_checksums[0],
+                        "title": n.title,
+                    }))
+                    .collect::<Vec<_>>(),
+                "edges": edges
+                    .iter()
+                    .map(|(source, relation, target)| json!({
+                        "source": source,
+                        "target": target,
+                        "relation": relation,
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+            serde_json::to_string_pretty(&graph).unwrap() + "\n"
+        }
+    }
+}
+
+/// Export the relationship network of the catalog.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `file` - File where the graph should be written. If no file is
+///   given, the graph will be written to stdout.
+/// * `format` - Format of the graph.
+/// * `include_private` - Include private resources.
+pub fn librarian_graph(
+    catalog: &Catalog,
+    file: Option<&str>,
+    format: GraphFormat,
+    include_private: bool,
+) {
+    let graph = relation_graph(catalog, format, include_private);
+    match file {
+        Some(f) => std::fs::write(f, graph).expect("Failed to write graph"),
+        None => print!("{}", graph),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relations() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"a2\", \"historical_checksums\": [\"a1\", \"a2\"]},
+              {\"title\": \"B\", \"relations\": {\"cites\": [\"a2\", \"zz\"], \"supersedes\": [\"cc\"]},
+               \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"C\", \"private\": true, \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        // Relations are resolved to initial checksums, and unknown
+        // resources are reported.
+        let unknown = catalog.resolve_relations();
+        assert!(unknown.len() == 1);
+        assert!(unknown[0].key == "bb" && unknown[0].target == "zz");
+        assert!(
+            catalog.resources[1].relations.as_ref().unwrap()[&Relation::Cites]
+                == vec!["a1", "zz"]
+        );
+
+        let related = catalog.related(&catalog.resources[0]);
+        assert!(related.len() == 1);
+        assert!(related["cited_by"][0].title == "B");
+        let related = catalog.related(&catalog.resources[1]);
+        assert!(
+            related.keys().cloned().collect::<Vec<_>>()
+                == vec!["cites", "supersedes"]
+        );
+
+        // Private resources are left out of the graph.
+        let dot = relation_graph(&catalog, GraphFormat::Dot, false);
+        assert!(dot.contains("\"bb\" -> \"a1\" [label=\"cites\"];"));
+        assert!(!dot.contains("\"cc\""));
+        let json = relation_graph(&catalog, GraphFormat::Json, true);
+        assert!(json.contains("\"relation\": \"supersedes\""));
+    }
+}
@@ -0,0 +1,383 @@
+use crate::batch::run;
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::storage::Storage;
+
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+use url::Url;
+
+/// Number of remote objects uploaded or verified at the same time.
+const REMOTE_JOBS: usize = 8;
+
+/// Copy of a resource's file in the library's remote storage (see
+/// `Catalog::remote_storage`).
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, Eq, PartialEq,
+)]
+pub struct RemoteObject {
+    /// Entity tag the storage gave the object when it was uploaded,
+    /// which changes whenever the object does.
+    pub etag: String,
+    /// SHA-1 checksum of the uploaded file.
+    pub checksum: String,
+}
+
+/// Place resource files are kept in, so that only the catalog and the
+/// files in use need to be on the local disk. Objects are named by the
+/// initial checksum of their resource.
+pub trait RemoteStorage: Sync {
+    /// Upload a file, replacing the object of the same name.
+    ///
+    /// # Returns
+    ///
+    /// The entity tag of the new object.
+    fn upload(&self, name: &str, from: &Path) -> Result<String, String>;
+
+    /// Download an object to a file.
+    fn download(&self, name: &str, to: &Path) -> Result<(), String>;
+
+    /// Entity tag of an object, or `None` if there is no such object.
+    fn etag(&self, name: &str) -> Result<Option<String>, String>;
+}
+
+/// Bucket and prefix, written "s3://bucket/prefix", reached with the AWS
+/// command line interface.
+struct S3Storage {
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Storage {
+    fn key(&self, name: &str) -> String {
+        match self.prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", self.prefix, name),
+        }
+    }
+
+    /// Run an `aws s3api` command on an object, returning its output.
+    fn s3api(
+        &self,
+        command: &str,
+        name: &str,
+        args: &[&str],
+    ) -> Result<Value, String> {
+        let output = Command::new("aws")
+            .args(["s3api", command, "--output", "json", "--bucket"])
+            .arg(&self.bucket)
+            .arg("--key")
+            .arg(self.key(name))
+            .args(args)
+            .output()
+            .map_err(|e| e.to_string())?;
+        match output.status.success() {
+            true => {
+                Ok(serde_json::from_slice(&output.stdout)
+                    .unwrap_or(Value::Null))
+            }
+            false => {
+                Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            }
+        }
+    }
+}
+
+/// Entity tag in a response, without the quotes around it.
+fn unquote(etag: &str) -> String {
+    etag.trim_matches('"').to_string()
+}
+
+impl RemoteStorage for S3Storage {
+    fn upload(&self, name: &str, from: &Path) -> Result<String, String> {
+        let from = from.to_str().ok_or("Path is not valid UTF-8")?;
+        let output = self.s3api("put-object", name, &["--body", from])?;
+        match output["ETag"].as_str() {
+            Some(etag) => Ok(unquote(etag)),
+            None => Err("Response lacks an ETag".to_string()),
+        }
+    }
+
+    fn download(&self, name: &str, to: &Path) -> Result<(), String> {
+        let to = to.to_str().ok_or("Path is not valid UTF-8")?;
+        self.s3api("get-object", name, &[to]).map(|_| ())
+    }
+
+    fn etag(&self, name: &str) -> Result<Option<String>, String> {
+        match self.s3api("head-object", name, &[]) {
+            Ok(output) => Ok(output["ETag"].as_str().map(unquote)),
+            Err(e) if e.contains("404") || e.contains("Not Found") => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Collection of a WebDAV share, written as its HTTP or HTTPS URL. A
+/// user name and password in the URL are sent with basic
+/// authentication.
+struct WebDavStorage {
+    agent: ureq::Agent,
+    collection: Url,
+    authorization: Option<String>,
+}
+
+impl WebDavStorage {
+    fn new(mut url: Url) -> WebDavStorage {
+        let authorization = match url.username() {
+            "" => None,
+            user => {
+                let credentials =
+                    format!("{}:{}", user, url.password().unwrap_or(""));
+                Some(format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD
+                        .encode(credentials)
+                ))
+            }
+        };
+        url.set_username("").ok();
+        url.set_password(None).ok();
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        WebDavStorage {
+            agent: ureq::AgentBuilder::new().build(),
+            collection: url,
+            authorization,
+        }
+    }
+
+    fn request(&self, method: &str, name: &str) -> ureq::Request {
+        let url = self.collection.join(name).unwrap();
+        let request = self.agent.request_url(method, &url);
+        match &self.authorization {
+            Some(a) => request.set("Authorization", a),
+            None => request,
+        }
+    }
+}
+
+impl RemoteStorage for WebDavStorage {
+    fn upload(&self, name: &str, from: &Path) -> Result<String, String> {
+        let file = File::open(from).map_err(|e| e.to_string())?;
+        let size = file.metadata().map_err(|e| e.to_string())?.len();
+        // Some servers don't accept chunked uploads, which a request of
+        // unknown length would be.
+        let response = self
+            .request("PUT", name)
+            .set("Content-Length", &size.to_string())
+            .send(file)
+            .map_err(|e| e.to_string())?;
+        // Not every server returns the entity tag of the new object.
+        match response.header("ETag") {
+            Some(etag) => Ok(unquote(etag)),
+            None => self
+                .etag(name)?
+                .ok_or_else(|| "Uploaded object is missing".to_string()),
+        }
+    }
+
+    fn download(&self, name: &str, to: &Path) -> Result<(), String> {
+        let response = self
+            .request("GET", name)
+            .call()
+            .map_err(|e| e.to_string())?;
+        let mut file = File::create(to).map_err(|e| e.to_string())?;
+        std::io::copy(&mut response.into_reader(), &mut file)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn etag(&self, name: &str) -> Result<Option<String>, String> {
+        match self.request("HEAD", name).call() {
+            Ok(response) => Ok(response.header("ETag").map(unquote)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Remote storage at a URL, "s3://bucket/prefix" for an S3 bucket or the
+/// HTTP or HTTPS URL of a WebDAV collection.
+///
+/// # Arguments
+///
+/// * `location` - URL of the storage.
+pub fn connect(location: &str) -> Box<dyn RemoteStorage> {
+    let url = Url::parse(location).unwrap_or_else(|_| {
+        panic!(
+            "{}",
+            message("remote-invalid", &[("location", location.into())])
+        )
+    });
+    match url.scheme() {
+        "s3" => Box::new(S3Storage {
+            bucket: url.host_str().unwrap_or_default().to_string(),
+            prefix: url.path().trim_matches('/').to_string(),
+        }),
+        "http" | "https" => Box::new(WebDavStorage::new(url)),
+        _ => panic!(
+            "{}",
+            message("remote-invalid", &[("location", location.into())])
+        ),
+    }
+}
+
+/// Resources whose local file should be uploaded to the remote storage,
+/// because it has never been or changed since, by initial checksum.
+/// Resources stored as directories are kept locally.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `storage` - Resources directory.
+pub fn pending_uploads(catalog: &Catalog, storage: &Storage) -> Vec<String> {
+    catalog
+        .resources
+        .iter()
+        .filter(|r| storage.resource_path(&r.historical_checksums[0]).is_file())
+        .filter(|r| r.remote.as_ref().map(|o| &o.checksum) != Some(&r.checksum))
+        .map(|r| r.historical_checksums[0].clone())
+        .collect()
+}
+
+/// Upload the resources of a library that has remote storage, remove
+/// the local copies of the uploaded and downloaded files, and check that the objects of
+/// the other resources are still those that were uploaded, by their
+/// entity tag. Objects that are missing or changed are reported.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog, whose resources record the uploaded
+///   objects.
+/// * `storage` - Resources directory.
+pub fn synchronize(catalog: &mut Catalog, storage: &Storage) {
+    let location = match &catalog.remote_storage {
+        Some(l) => l.clone(),
+        None => return,
+    };
+    let remote = connect(&location);
+    let uploads = pending_uploads(catalog, storage);
+    let mut uploaded = vec![];
+    run(
+        &uploads,
+        REMOTE_JOBS,
+        |key| remote.upload(key, &storage.resource_path(key)),
+        |key, result| match result {
+            Ok(etag) => uploaded.push((key.clone(), etag)),
+            Err(e) => eprintln!(
+                "{}",
+                message(
+                    "remote-upload-failed",
+                    &[("key", key.as_str().into()), ("error", e.into())]
+                )
+            ),
+        },
+    );
+    for (key, etag) in &uploaded {
+        let resource = catalog.resource_mut(key).unwrap();
+        resource.remote = Some(RemoteObject {
+            etag: etag.clone(),
+            checksum: resource.checksum.clone(),
+        });
+        std::fs::remove_file(storage.resource_path(key))
+            .expect("Failed to remove resource");
+    }
+
+    let stored: Vec<(String, String)> = catalog
+        .resources
+        .iter()
+        .filter(|r| !uploads.contains(&r.historical_checksums[0]))
+        .filter_map(|r| {
+            r.remote
+                .as_ref()
+                .map(|o| (r.historical_checksums[0].clone(), o.etag.clone()))
+        })
+        .collect();
+    let mut problems = 0;
+    run(
+        &stored,
+        REMOTE_JOBS,
+        |(key, _)| remote.etag(key),
+        |(key, etag), result| {
+            let id = match result {
+                Ok(Some(current)) if &current == etag => {
+                    // A downloaded copy is the same as the remote one,
+                    // which it was checked against when cataloging.
+                    let path = storage.resource_path(key);
+                    if path.is_file() {
+                        std::fs::remove_file(path)
+                            .expect("Failed to remove resource");
+                    }
+                    return;
+                }
+                Ok(Some(_)) => "remote-changed",
+                Ok(None) => "remote-missing",
+                Err(e) => {
+                    problems += 1;
+                    eprintln!(
+                        "{}",
+                        message(
+                            "remote-verify-failed",
+                            &[
+                                ("key", key.as_str().into()),
+                                ("error", e.into())
+                            ]
+                        )
+                    );
+                    return;
+                }
+            };
+            problems += 1;
+            eprintln!("{}", message(id, &[("key", key.as_str().into())]));
+        },
+    );
+    println!(
+        "{}",
+        message(
+            "remote-done",
+            &[
+                ("uploaded", uploaded.len().into()),
+                ("verified", (stored.len() - problems).into()),
+                ("problems", problems.into())
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageLayout;
+
+    #[test]
+    fn test_pending_uploads() {
+        let root = std::env::temp_dir()
+            .join(format!("librarian-remote-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("dd")).unwrap();
+        for key in ["aa", "bb"] {
+            std::fs::write(root.join(key), key).unwrap();
+        }
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"checksum\": \"b2\", \"historical_checksums\": [\"bb\", \"b2\"],
+               \"remote\": {\"etag\": \"e1\", \"checksum\": \"bb\"}},
+              {\"title\": \"C\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"],
+               \"remote\": {\"etag\": \"e2\", \"checksum\": \"cc\"}},
+              {\"title\": \"D\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]}
+            ]}",
+        )
+        .unwrap();
+        let storage = Storage::new(&root, StorageLayout::Flat);
+        // "bb" changed since it was uploaded, "cc" is only in the remote
+        // storage, and "dd" is a directory, which stays local.
+        assert!(pending_uploads(&catalog, &storage) == vec!["aa", "bb"]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::resource::Resource;
+use crate::storage::Storage;
+
+use std::path::Path;
+
+/// Resources with a current or historical checksum starting with a
+/// prefix, each with the position of the matching checksum among its
+/// checksums, starting at 1.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `prefix` - Checksum, or its beginning.
+pub fn resolve<'a>(
+    catalog: &'a Catalog,
+    prefix: &str,
+) -> Vec<(&'a Resource, usize)> {
+    let prefix = prefix.to_lowercase();
+    catalog
+        .resources
+        .iter()
+        .filter_map(|r| {
+            // The current checksum is the last historical checksum.
+            r.historical_checksums
+                .iter()
+                .position(|c| c.starts_with(&prefix))
+                .map(|i| (r, i + 1))
+        })
+        .collect()
+}
+
+/// Print the resource a checksum belongs to and the path of its current
+/// file. Old BibTeX keys and notes refer to resources by checksums that
+/// are no longer current once the contents of their file changed, so
+/// historical checksums are searched too. A unique prefix of a checksum
+/// is enough.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `checksum` - Current or historical checksum, or its beginning.
+/// * `path_only` - Only print the path of the file, e.g., for scripts.
+pub fn librarian_resolve(
+    catalog: &Catalog,
+    resources_path: &Path,
+    checksum: &str,
+    path_only: bool,
+) {
+    let matches = resolve(catalog, checksum);
+    let (resource, revision) = match matches.as_slice() {
+        [] => panic!("No resource with checksum {}", checksum),
+        [m] => *m,
+        _ => {
+            for (resource, _) in &matches {
+                eprintln!(
+                    "  {} {}",
+                    resource.historical_checksums[0], resource.title
+                );
+            }
+            panic!("Checksum {} matches several resources", checksum);
+        }
+    };
+    let key = &resource.historical_checksums[0];
+    let path = Storage::of(resources_path, catalog).resource_path(key);
+    if path_only {
+        println!("{}", path.display());
+        return;
+    }
+    println!("{} {}", key, resource.title);
+    let count = resource.historical_checksums.len();
+    println!(
+        "  {}",
+        message(
+            match revision == count {
+                true => "resolve-current",
+                false => "resolve-historical",
+            },
+            &[
+                ("revision", revision.into()),
+                ("count", count.into()),
+                ("checksum", resource.checksum.as_str().into())
+            ]
+        )
+    );
+    println!("  {}", path.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"cc01\", \"historical_checksums\": [\"aa01\", \"bb01\", \"cc01\"]},
+              {\"title\": \"B\", \"checksum\": \"ab02\", \"historical_checksums\": [\"ab02\"]}
+            ]}",
+        )
+        .unwrap();
+        let titles = |prefix| {
+            resolve(&catalog, prefix)
+                .into_iter()
+                .map(|(r, i)| (r.title.clone(), i))
+                .collect::<Vec<_>>()
+        };
+        assert!(titles("BB") == vec![("A".to_string(), 2)]);
+        assert!(titles("cc01") == vec![("A".to_string(), 3)]);
+        assert!(titles("a").len() == 2);
+        assert!(titles("ff").is_empty());
+    }
+}
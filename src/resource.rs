@@ -1,20 +1,37 @@
-use crate::bibtex::BibtexType;
+use crate::bibtex::{BibtexType, ContentType};
+use crate::reading::{Priority, ReadStatus};
+use crate::relations::Relation;
+use crate::remote::RemoteObject;
 
+use chrono::{Datelike, FixedOffset, NaiveDate, NaiveTime, Timelike};
 use indexmap::IndexMap;
 use std::cmp::PartialOrd;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
+use std::time::SystemTime;
 // use regex::Regex;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use url::Url;
 
 /// Library "tag".
-//
-// How should I store this? One way is with name: String, parent: String.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Tag {}
+///
+/// Tags are stored in the catalog keyed by their name and form a tree
+/// through their parents, e.g., "electromagnetism" with the parent
+/// "physics", written "physics/electromagnetism" in full.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq,
+)]
+pub struct Tag {
+    /// Name of the parent tag. Top-level tags don't have a parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
 
 /// Resource type.
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
@@ -31,8 +48,7 @@ struct ResourceType {
 ///
 /// We've identified this as a MediaPrefix in order to distinguish it
 /// from MediaType, but it technically designates the "type".
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 enum MediaPrefix {
     Application,
     Audio,
@@ -46,12 +62,94 @@ enum MediaPrefix {
     Model,
 }
 
-/// Media (formerly MIME) type.
+impl MediaPrefix {
+    const ALL: [MediaPrefix; 10] = [
+        MediaPrefix::Application,
+        MediaPrefix::Audio,
+        MediaPrefix::Image,
+        MediaPrefix::Message,
+        MediaPrefix::Multipart,
+        MediaPrefix::Text,
+        MediaPrefix::Video,
+        MediaPrefix::Font,
+        MediaPrefix::Example,
+        MediaPrefix::Model,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            MediaPrefix::Application => "application",
+            MediaPrefix::Audio => "audio",
+            MediaPrefix::Image => "image",
+            MediaPrefix::Message => "message",
+            MediaPrefix::Multipart => "multipart",
+            MediaPrefix::Text => "text",
+            MediaPrefix::Video => "video",
+            MediaPrefix::Font => "font",
+            MediaPrefix::Example => "example",
+            MediaPrefix::Model => "model",
+        }
+    }
+}
+
+/// Registration trees a subtype can be in besides the standards tree,
+/// written before the subtype and a dot (e.g., "vnd.ms-excel").
+const MEDIA_TREES: [&str; 3] = ["vnd", "prs", "x"];
+
+/// Media (formerly MIME) type, such as "text/html; charset=utf-8" or
+/// "application/vnd.oasis.opendocument.text+xml".
+///
+/// The type, subtype and parameter names are case-insensitive and kept
+/// in lowercase. Parameter values are kept as written.
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
-#[serde(try_from = "&str", into = "String")]
+#[serde(try_from = "String", into = "String")]
 pub struct MediaType {
     r#type: MediaPrefix,
+    /// Registration tree of the subtype, e.g., "vnd", or `None` in the
+    /// standards tree.
+    tree: Option<String>,
     subtype: String,
+    /// Structured syntax suffix of the subtype, e.g., "xml" in
+    /// "image/svg+xml".
+    suffix: Option<String>,
+    /// Parameters in the order they were given.
+    parameters: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// Type and subtype, without the parameters (e.g., "text/html").
+    pub fn essence(&self) -> String {
+        let mut essence = format!("{}/", self.r#type.name());
+        if let Some(tree) = &self.tree {
+            essence.push_str(tree);
+            essence.push('.');
+        }
+        essence.push_str(&self.subtype);
+        if let Some(suffix) = &self.suffix {
+            essence.push('+');
+            essence.push_str(suffix);
+        }
+        essence
+    }
+
+    /// Registration tree of the subtype, `None` in the standards tree.
+    pub fn tree(&self) -> Option<&str> {
+        self.tree.as_deref()
+    }
+
+    /// Structured syntax suffix of the subtype, e.g., "zip" for EPUB.
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    /// Value of a parameter, e.g., "charset", whose name is matched
+    /// ignoring case.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
 }
 
 #[derive(Debug)]
@@ -79,35 +177,495 @@ impl Error for MediaTypeParseError {
     }
 }
 
+/// Whether a string is a valid type, subtype or parameter name, which
+/// RFC 6838 restricts to letters, digits and a few symbols.
+fn is_restricted_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 127
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+}
+
+/// Whether a character can appear in a parameter value without quotes.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?=".contains(c)
+}
+
+/// Parse the parameters following the subtype of a media type, each
+/// written "; name=value" with a token or quoted string value.
+fn media_parameters(
+    mut rest: &str,
+) -> Result<Vec<(String, String)>, MediaTypeParseError> {
+    let mut parameters = vec![];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(parameters);
+        }
+        rest = rest
+            .strip_prefix(';')
+            .ok_or_else(|| {
+                MediaTypeParseError::new(&format!(
+                    "Expected \";\" before \"{}\".",
+                    rest
+                ))
+            })?
+            .trim_start();
+        // A trailing semicolon is tolerated.
+        if rest.is_empty() {
+            return Ok(parameters);
+        }
+        let (name, value) = rest.split_once('=').ok_or_else(|| {
+            MediaTypeParseError::new(&format!(
+                "Media type parameter \"{}\" lacks a value.",
+                rest
+            ))
+        })?;
+        let name = name.trim_end();
+        if !is_restricted_name(name) {
+            return Err(MediaTypeParseError::new(&format!(
+                "Invalid media type parameter name \"{}\".",
+                name
+            )));
+        }
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => break None,
+                        },
+                        Some((i, '"')) => {
+                            rest = &quoted[i + 1..];
+                            break Some(value);
+                        }
+                        Some((_, c)) => value.push(c),
+                        None => break None,
+                    }
+                }
+                .ok_or_else(|| {
+                    MediaTypeParseError::new(&format!(
+                        "Unterminated value of media type parameter \"{}\".",
+                        name
+                    ))
+                })?
+            }
+            None => {
+                let end = value.find(';').unwrap_or(value.len());
+                let token = value[..end].trim();
+                if token.is_empty() || !token.chars().all(is_token_char) {
+                    return Err(MediaTypeParseError::new(&format!(
+                        "Invalid value \"{}\" of media type parameter \"{}\".",
+                        token, name
+                    )));
+                }
+                rest = &value[end..];
+                token.to_string()
+            }
+        };
+        parameters.push((name.to_lowercase(), value));
+    }
+}
+
 impl TryFrom<&str> for MediaType {
     type Error = MediaTypeParseError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        let media_type_components: Vec<&str> = s.split("/").collect();
-
-        if media_type_components.len() != 2 {
-            return Err(MediaTypeParseError::new(
-                "A media type must contain a type and subtype.",
-            ));
+        let (essence, parameters) = s.split_at(s.find(';').unwrap_or(s.len()));
+        let (prefix, subtype) =
+            essence.trim().split_once('/').ok_or_else(|| {
+                MediaTypeParseError::new(
+                    "A media type must contain a type and subtype.",
+                )
+            })?;
+        let prefix = prefix.to_lowercase();
+        let media_type_prefix = MediaPrefix::ALL
+            .iter()
+            .find(|p| p.name() == prefix)
+            .copied()
+            .ok_or_else(|| {
+                MediaTypeParseError::new(&format!(
+                    "Unknown media type \"{}\".",
+                    prefix
+                ))
+            })?;
+        if !is_restricted_name(subtype) {
+            return Err(MediaTypeParseError::new(&format!(
+                "Invalid media subtype \"{}\".",
+                subtype
+            )));
         }
 
-        let media_type_prefix = format!("\"{}\"", media_type_components[0]);
+        let subtype = subtype.to_lowercase();
+        let (tree, subtype) = match subtype.split_once('.') {
+            Some((tree, rest))
+                if MEDIA_TREES.contains(&tree) && !rest.is_empty() =>
+            {
+                (Some(tree.to_string()), rest.to_string())
+            }
+            _ => (None, subtype.clone()),
+        };
+        let (subtype, suffix) = match subtype.rsplit_once('+') {
+            Some((name, suffix)) if !name.is_empty() && !suffix.is_empty() => {
+                (name.to_string(), Some(suffix.to_string()))
+            }
+            _ => (subtype, None),
+        };
         Ok(MediaType {
-            r#type: serde_json::from_str(&media_type_prefix).unwrap(),
-            subtype: media_type_components[1].to_string(),
+            r#type: media_type_prefix,
+            tree,
+            subtype,
+            suffix,
+            parameters: media_parameters(parameters)?,
         })
     }
 }
 
+impl TryFrom<String> for MediaType {
+    type Error = MediaTypeParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
 impl From<MediaType> for String {
     fn from(media_type: MediaType) -> Self {
-        let type_string = serde_json::to_string(&media_type.r#type).unwrap();
-        format!(
-            "{}/{}",
-            // serde_json includes quotes at the beginning and end of
-            // the string that we don't want here.
-            type_string[1..type_string.len() - 1].to_string(),
-            media_type.subtype
+        let mut s = media_type.essence();
+        for (name, value) in &media_type.parameters {
+            match !value.is_empty() && value.chars().all(is_token_char) {
+                true => s.push_str(&format!("; {}={}", name, value)),
+                false => s.push_str(&format!(
+                    "; {}=\"{}\"",
+                    name,
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                )),
+            }
+        }
+        s
+    }
+}
+
+/// JSON schema of a type serialized as a string.
+///
+/// # Arguments
+///
+/// * `description` - Human-readable description of the string format.
+/// * `pattern` - Regular expression valid strings must match.
+fn string_schema(description: &str, pattern: Option<&str>) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    };
+    schema.metadata().description = Some(description.to_string());
+    schema.string().pattern = pattern.map(|p| p.to_string());
+    schema.into()
+}
+
+impl JsonSchema for MediaType {
+    fn schema_name() -> String {
+        "MediaType".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "Media (MIME) type with the syntax \"type/subtype\", optionally followed by parameters (e.g., \"; charset=utf-8\").",
+            Some("^(application|audio|image|message|multipart|text|video|font|example|model)/[A-Za-z0-9!#$&^_.+-]+\\s*(;.*)?$"),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct IdentifierParseError {
+    details: String,
+}
+
+impl IdentifierParseError {
+    fn new(msg: &str) -> IdentifierParseError {
+        IdentifierParseError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for IdentifierParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for IdentifierParseError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Digits of an identifier, without the hyphens and spaces it's
+/// written with and with a check digit of "x" in uppercase.
+fn identifier_digits(s: &str) -> String {
+    s.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Sum of the digits of an identifier, each multiplied by the weight
+/// of its position. The last digit may be "X" (10). `None` when another
+/// character isn't a digit.
+fn weighted_sum(digits: &str, weight: impl Fn(usize) -> u32) -> Option<u32> {
+    let last = digits.len() - 1;
+    digits
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match (i == last, c) {
+            (true, 'X') => Some(10),
+            (_, c) => c.to_digit(10),
+        })
+        .enumerate()
+        .map(|(i, d)| d.map(|d| d * weight(i)))
+        .sum()
+}
+
+/// International Standard Book Number (ISBN-10 or ISBN-13), which is
+/// kept as written (e.g., with hyphens) once its check digit has been
+/// verified.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Isbn(String);
+
+impl Isbn {
+    /// Digits of the ISBN-13 of the book, which are the same however
+    /// the ISBN was written. ISBN-10s are converted to ISBN-13s.
+    pub fn isbn13(&self) -> String {
+        let digits = identifier_digits(&self.0);
+        match digits.len() {
+            13 => digits,
+            _ => {
+                let digits = format!("978{}", &digits[..9]);
+                let sum =
+                    weighted_sum(&format!("{}0", digits), |i| [1, 3][i % 2])
+                        .unwrap();
+                format!("{}{}", digits, (10 - sum % 10) % 10)
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for Isbn {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let digits = identifier_digits(s);
+        let remainder = match digits.len() {
+            10 => weighted_sum(&digits, |i| 10 - i as u32).map(|s| s % 11),
+            // Only ISBN-10s have a check digit of "X".
+            13 if digits.contains('X') => None,
+            13 => weighted_sum(&digits, |i| [1, 3][i % 2]).map(|s| s % 10),
+            _ => {
+                return Err(IdentifierParseError::new(&format!(
+                    "ISBN \"{}\" doesn't have 10 or 13 digits.",
+                    s
+                )))
+            }
+        };
+        match remainder {
+            Some(0) => Ok(Isbn(s.trim().to_string())),
+            Some(_) => Err(IdentifierParseError::new(&format!(
+                "ISBN \"{}\" has an invalid check digit.",
+                s
+            ))),
+            None => Err(IdentifierParseError::new(&format!(
+                "ISBN \"{}\" contains characters other than digits.",
+                s
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for Isbn {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
+impl From<Isbn> for String {
+    fn from(isbn: Isbn) -> Self {
+        isbn.0
+    }
+}
+
+impl JsonSchema for Isbn {
+    fn schema_name() -> String {
+        "Isbn".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "ISBN-10 or ISBN-13 with a valid check digit, optionally with hyphens or spaces.",
+            Some("^[0-9Xx -]+$"),
+        )
+    }
+}
+
+/// International Standard Serial Number of a journal or other serial,
+/// written as "NNNN-NNNC" once its check digit has been verified.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Issn(String);
+
+impl TryFrom<&str> for Issn {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let digits = identifier_digits(s);
+        if digits.len() != 8 {
+            return Err(IdentifierParseError::new(&format!(
+                "ISSN \"{}\" doesn't have 8 digits.",
+                s
+            )));
+        }
+        match weighted_sum(&digits, |i| 8 - i as u32).map(|s| s % 11) {
+            Some(0) if !digits[..7].contains('X') => {
+                Ok(Issn(format!("{}-{}", &digits[..4], &digits[4..])))
+            }
+            Some(_) => Err(IdentifierParseError::new(&format!(
+                "ISSN \"{}\" has an invalid check digit.",
+                s
+            ))),
+            None => Err(IdentifierParseError::new(&format!(
+                "ISSN \"{}\" contains characters other than digits.",
+                s
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for Issn {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
+impl From<Issn> for String {
+    fn from(issn: Issn) -> Self {
+        issn.0
+    }
+}
+
+impl JsonSchema for Issn {
+    fn schema_name() -> String {
+        "Issn".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "ISSN with a valid check digit.",
+            Some("^[0-9]{4}-?[0-9]{3}[0-9Xx]$"),
+        )
+    }
+}
+
+/// Prefixes a DOI is often written with, which aren't part of it.
+const DOI_PREFIXES: [&str; 5] = [
+    "https://doi.org/",
+    "http://doi.org/",
+    "https://dx.doi.org/",
+    "http://dx.doi.org/",
+    "doi:",
+];
+
+/// Digital object identifier (DOI), such as "10.1109/4.658619". Parsing
+/// strips resolver URLs and "doi:" prefixes, so that a DOI is always
+/// written the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Doi(String);
+
+impl Doi {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// URL of the DOI resolver that redirects to the resource.
+    pub fn resolve_url(&self) -> Url {
+        let mut url = Url::parse("https://doi.org/").unwrap();
+        url.set_path(&self.0);
+        url
+    }
+}
+
+impl TryFrom<&str> for Doi {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let mut doi = s.trim();
+        for prefix in &DOI_PREFIXES {
+            if doi.len() >= prefix.len()
+                && doi[..prefix.len()].eq_ignore_ascii_case(prefix)
+            {
+                doi = doi[prefix.len()..].trim_start();
+            }
+        }
+        // A DOI is "10." and a registrant code of digits and dots,
+        // followed by a slash and a suffix.
+        let valid = match doi.split_once('/') {
+            Some((registrant, suffix)) => {
+                registrant.len() > 3
+                    && registrant.starts_with("10.")
+                    && registrant[3..]
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || c == '.')
+                    && !suffix.is_empty()
+                    && !suffix.chars().any(char::is_whitespace)
+            }
+            None => false,
+        };
+        match valid {
+            true => Ok(Doi(doi.to_string())),
+            false => Err(IdentifierParseError::new(&format!(
+                "\"{}\" isn't a DOI of the form \"10.NNNN/suffix\".",
+                s
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for Doi {
+    type Error = IdentifierParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
+impl From<Doi> for String {
+    fn from(doi: Doi) -> Self {
+        doi.0
+    }
+}
+
+impl fmt::Display for Doi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl JsonSchema for Doi {
+    fn schema_name() -> String {
+        "Doi".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "Digital object identifier without a resolver URL, e.g., \"10.1109/4.658619\".",
+            Some("^10\\.[0-9.]+/\\S+$"),
         )
     }
 }
@@ -116,7 +674,9 @@ impl From<MediaType> for String {
 ///
 /// Classifies a document type according to an extension and media
 /// type.
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, Eq, PartialEq,
+)]
 pub struct DocumentType {
     pub extension: String,
     pub mime: Option<MediaType>,
@@ -124,12 +684,27 @@ pub struct DocumentType {
 
 /// DateTime.
 ///
+/// Dates have any precision from a year to a second, e.g., "1998" or
+/// "2024-03-01T10:00:00". Times given with a UTC offset (e.g.,
+/// "2024-03-01T10:00:00+02:00") are converted to UTC, which is how all
+/// times are stored. A BibLaTeX date range (e.g., "1999/2001") keeps its
+/// last date in `end`, and an open range (e.g., "1999/") has an empty
+/// `end`.
+///
 /// The order of members in this struct is important since it is used
 /// by `#[derive(PartialOrd)]`.
 #[derive(
-    Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, PartialOrd,
+    Serialize,
+    Deserialize,
+    Debug,
+    Default,
+    Clone,
+    Hash,
+    Eq,
+    PartialEq,
+    PartialOrd,
 )]
-#[serde(try_from = "&str", into = "String")]
+#[serde(try_from = "String", into = "String")]
 pub struct DateTime {
     pub year: Option<i32>,
     pub month: Option<i32>,
@@ -137,18 +712,91 @@ pub struct DateTime {
     pub hour: Option<i32>,
     pub minute: Option<i32>,
     pub second: Option<i32>,
+    /// Last date of a range.
+    pub end: Option<Box<DateTime>>,
+}
+
+impl From<chrono::NaiveDateTime> for DateTime {
+    /// Date and time to the second.
+    fn from(time: chrono::NaiveDateTime) -> DateTime {
+        DateTime {
+            year: Some(time.year()),
+            month: Some(time.month() as i32),
+            day: Some(time.day() as i32),
+            hour: Some(time.hour() as i32),
+            minute: Some(time.minute() as i32),
+            second: Some(time.second() as i32),
+            end: None,
+        }
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    /// Date and time in UTC, to the second.
+    fn from(time: SystemTime) -> DateTime {
+        DateTime::from(chrono::DateTime::<chrono::Utc>::from(time).naive_utc())
+    }
 }
 
 impl DateTime {
     pub fn new() -> DateTime {
-        DateTime {
-            year: None,
-            month: None,
-            day: None,
-            hour: None,
-            minute: None,
-            second: None,
+        DateTime::default()
+    }
+
+    /// Check that the components are set from the year down, without
+    /// gaps, and that they name an existing date and time (e.g., not
+    /// February 30).
+    pub fn validate(&self) -> Result<(), DateTimeParseError> {
+        let components =
+            [self.year, self.month, self.day, self.hour, self.minute];
+        let set = components.iter().take_while(|c| c.is_some()).count();
+        let rest = [self.month, self.day, self.hour, self.minute, self.second];
+        if rest[set..].iter().any(|c| c.is_some()) {
+            return Err(DateTimeParseError::new(
+                "date components must be given from the year down",
+            ));
         }
+        let year = match self.year {
+            Some(y) => y,
+            None => return Ok(()),
+        };
+        let month = self.month.unwrap_or(1);
+        if !(1..=12).contains(&month) {
+            return Err(DateTimeParseError::new(
+                "month must be between 1 and 12",
+            ));
+        }
+        NaiveDate::from_ymd_opt(
+            year,
+            month as u32,
+            self.day.unwrap_or(1).max(0) as u32,
+        )
+        .ok_or_else(|| {
+            DateTimeParseError::new(&format!(
+                "{:04}-{:02} has no day {}",
+                year,
+                month,
+                self.day.unwrap_or(1)
+            ))
+        })?;
+        let time = [self.hour, self.minute, self.second]
+            .iter()
+            .map(|c| c.unwrap_or(0))
+            .collect::<Vec<i32>>();
+        if time.iter().any(|c| *c < 0)
+            || NaiveTime::from_hms_opt(
+                time[0] as u32,
+                time[1] as u32,
+                time[2] as u32,
+            )
+            .is_none()
+        {
+            return Err(DateTimeParseError::new(&format!(
+                "{:02}:{:02}:{:02} isn't a time of day",
+                time[0], time[1], time[2]
+            )));
+        }
+        Ok(())
     }
 }
 
@@ -177,122 +825,415 @@ impl Error for DateTimeParseError {
     }
 }
 
+/// Parse a number of exactly `digits` digits at the start of a datetime
+/// string.
+///
+/// # Arguments
+///
+/// * `s` - Rest of the datetime string.
+/// * `digits` - Number of digits of the component.
+/// * `component` - Component name, used for error messages.
+///
+/// # Returns
+///
+/// The number and the rest of the string after it.
+fn parse_datetime_component<'a>(
+    s: &'a str,
+    digits: usize,
+    component: &str,
+) -> Result<(i32, &'a str), DateTimeParseError> {
+    match s.get(..digits) {
+        Some(c) if c.bytes().all(|b| b.is_ascii_digit()) => {
+            Ok((c.parse::<i32>().unwrap(), &s[digits..]))
+        }
+        _ => Err(DateTimeParseError::new(&format!(
+            "{} must be a number of {} digits",
+            component, digits
+        ))),
+    }
+}
+
+/// Parse a UTC offset, either "Z" or "+HH:MM" (the colon and minutes
+/// being optional).
+fn parse_utc_offset(s: &str) -> Result<FixedOffset, DateTimeParseError> {
+    if s == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    let invalid = || {
+        DateTimeParseError::new(&format!(
+            "\"{}\" is neither a UTC offset nor part of a date",
+            s
+        ))
+    };
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+    let (hours, rest) = parse_datetime_component(&s[1..], 2, "offset hour")?;
+    let rest = rest.strip_prefix(':').unwrap_or(rest);
+    let minutes = match rest.is_empty() {
+        true => 0,
+        false => match parse_datetime_component(rest, 2, "offset minute")? {
+            (m, "") => m,
+            _ => return Err(invalid()),
+        },
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .filter(|_| minutes < 60)
+        .ok_or_else(invalid)
+}
+
+/// Parse a single date of a datetime string, which isn't a range.
+fn parse_date(s: &str) -> Result<DateTime, DateTimeParseError> {
+    let mut datetime = DateTime::new();
+    if s.is_empty() {
+        return Ok(datetime);
+    }
+    let (year, mut rest) = parse_datetime_component(s, 4, "year")?;
+    datetime.year = Some(year);
+    // Each component is preceded by its separator.
+    let components: [(&[char], &str); 5] = [
+        (&['-'], "month"),
+        (&['-'], "day"),
+        (&['T', ' '], "hour"),
+        (&[':'], "minute"),
+        (&[':'], "second"),
+    ];
+    for (i, (separators, name)) in components.iter().enumerate() {
+        let after = match rest.strip_prefix(*separators) {
+            Some(a) => a,
+            None => break,
+        };
+        let (value, r) = parse_datetime_component(after, 2, name)?;
+        match i {
+            0 => datetime.month = Some(value),
+            1 => datetime.day = Some(value),
+            2 => datetime.hour = Some(value),
+            3 => datetime.minute = Some(value),
+            _ => datetime.second = Some(value),
+        }
+        rest = r;
+    }
+    // Fractions of a second are dropped.
+    if datetime.second.is_some() {
+        if let Some(fraction) = rest.strip_prefix('.') {
+            rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+    }
+    datetime.validate()?;
+
+    if !rest.is_empty() {
+        if datetime.hour.is_none() {
+            return Err(DateTimeParseError::new(&format!(
+                "unexpected \"{}\" after the date",
+                rest
+            )));
+        }
+        let offset = parse_utc_offset(rest)?;
+        let local = NaiveDate::from_ymd_opt(
+            year,
+            datetime.month.unwrap() as u32,
+            datetime.day.unwrap() as u32,
+        )
+        .unwrap()
+        .and_hms_opt(
+            datetime.hour.unwrap() as u32,
+            datetime.minute.unwrap_or(0) as u32,
+            datetime.second.unwrap_or(0) as u32,
+        )
+        .unwrap();
+        let utc = DateTime::from(local - offset);
+        // The precision of the given time is kept.
+        datetime = DateTime {
+            minute: datetime.minute.and(utc.minute),
+            second: datetime.second.and(utc.second),
+            ..utc
+        };
+    }
+    Ok(datetime)
+}
+
 impl TryFrom<&str> for DateTime {
     type Error = DateTimeParseError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        let mut datetime = DateTime::new();
-        let len = s.len();
-
-        if len >= 4 {
-            datetime.year = Some(i32::from_str_radix(&s[..4], 10).unwrap());
-
-            if len >= 7 {
-                let month = i32::from_str_radix(&s[5..7], 10).unwrap();
-                if month < 1 || month > 12 {
-                    return Err(DateTimeParseError::new(
-                        "month must be between 1 and 12",
-                    ));
-                }
-                datetime.month = Some(month);
-
-                if len >= 10 {
-                    let day = i32::from_str_radix(&s[8..10], 10).unwrap();
-                    if day < 1 || day > 31 {
-                        return Err(DateTimeParseError::new(
-                            "day must be between 1 and 31",
-                        ));
-                    }
-                    datetime.day = Some(day);
-
-                    if len >= 13 {
-                        let hour = i32::from_str_radix(&s[11..13], 10).unwrap();
-                        if hour < 0 || hour > 23 {
-                            return Err(DateTimeParseError::new(
-                                "hour must be between 0 and 23",
-                            ));
-                        }
-                        datetime.hour = Some(hour);
-
-                        if len >= 16 {
-                            let minute =
-                                i32::from_str_radix(&s[14..16], 10).unwrap();
-                            if minute < 0 || minute > 59 {
-                                return Err(DateTimeParseError::new(
-                                    "minute must be between 0 and 59",
-                                ));
-                            }
-                            datetime.minute = Some(minute);
-
-                            if len >= 19 {
-                                let second =
-                                    i32::from_str_radix(&s[17..19], 10)
-                                        .unwrap();
-                                if second < 0 || second > 59 {
-                                    return Err(DateTimeParseError::new(
-                                        "second must be between 0 and 59",
-                                    ));
-                                }
-                                datetime.second = Some(second);
-                            }
-                        }
-                    }
-                }
+        let (start, end) = match s.split_once('/') {
+            Some((start, end)) => (start, Some(end)),
+            None => (s, None),
+        };
+        let mut datetime = parse_date(start)?;
+        if let Some(end) = end {
+            let end = parse_date(end)?;
+            if datetime.year.is_none() && end.year.is_none() {
+                return Err(DateTimeParseError::new(
+                    "a date range needs a start or an end",
+                ));
+            }
+            if end.year.is_some() && end < datetime {
+                return Err(DateTimeParseError::new(&format!(
+                    "{} ends before it starts",
+                    s
+                )));
             }
+            datetime.end = Some(Box::new(end));
         }
-
         Ok(datetime)
     }
 }
 
+impl TryFrom<String> for DateTime {
+    type Error = DateTimeParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
 impl From<DateTime> for String {
     fn from(datetime: DateTime) -> Self {
-        match datetime.year {
-            Some(y) => match datetime.month {
-                Some(m) => match datetime.day {
-                    Some(d) => match datetime.hour {
-                        Some(h) => match datetime.minute {
-                            Some(min) => match datetime.second {
-                                Some(s) => format!(
-                                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
-                                    y, m, d, h, min, s
-                                ),
-                                None => format!(
-                                    "{:04}-{:02}-{:02}T{:02}:{:02}",
-                                    y, m, d, h, min
-                                ),
-                            },
-                            None => {
-                                format!("{:04}-{:02}-{:02}T{:02}", y, m, d, h)
-                            }
-                        },
-                        None => format!("{:04}-{:02}-{:02}", y, m, d),
-                    },
-                    None => format!("{:04}-{:02}", y, m),
-                },
-                None => format!("{:04}", y),
-            },
-            None => format!(""),
+        let components = [
+            datetime.year,
+            datetime.month,
+            datetime.day,
+            datetime.hour,
+            datetime.minute,
+            datetime.second,
+        ];
+        let separators = ["", "-", "-", "T", ":", ":"];
+        let mut s = String::new();
+        for (i, component) in components.iter().enumerate() {
+            match component {
+                Some(c) if i == 0 => s.push_str(&format!("{:04}", c)),
+                Some(c) => s.push_str(&format!("{}{:02}", separators[i], c)),
+                None => break,
+            }
+        }
+        if let Some(end) = datetime.end {
+            s.push('/');
+            s.push_str(&String::from(*end));
         }
+        s
     }
 }
 
-/// Name.
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
-#[serde(try_from = "&str", into = "String")]
+impl JsonSchema for DateTime {
+    fn schema_name() -> String {
+        "DateTime".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "ISO 8601 date and time of any precision, from \"YYYY\" to \"YYYY-MM-DDTHH:MM:SS\", or a range of two such dates separated by a slash (e.g., \"1999/2001\"). Either end of a range can be left open.",
+            Some(
+                r"^(\d{4}(-\d{2}(-\d{2}(T\d{2}(:\d{2}(:\d{2})?)?)?)?)?)?(/(\d{4}(-\d{2}(-\d{2}(T\d{2}(:\d{2}(:\d{2})?)?)?)?)?)?)?$",
+            ),
+        )
+    }
+}
+
+/// Suffixes that follow a family name (e.g., "Martin Luther King
+/// Jr."), which are recognized without a comma separating them.
+const NAME_SUFFIXES: [&str; 9] =
+    ["Jr.", "Jr", "Sr.", "Sr", "II", "III", "IV", "V", "VI"];
+
+/// Person name.
+///
+/// Names are parsed like BibTeX names, either as "First von Last" or as
+/// "von Last, First" and "von Last, Jr, First". The particle ("von")
+/// is made up of the lowercase words before the last name, e.g., "van"
+/// in "Ludwig van Beethoven".
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Hash, Eq, PartialEq,
+)]
+#[serde(try_from = "String", into = "String")]
 pub struct Name {
     pub first: Option<String>,
+    /// Given names after the first, e.g., "Phillips" in "Richard
+    /// Phillips Feynman".
     pub middle: Option<String>,
+    /// Particle preceding the last name, e.g., "van" or "de la".
+    pub particle: Option<String>,
     pub last: Option<String>,
+    /// Suffix following the last name, e.g., "Jr." or "III".
+    pub suffix: Option<String>,
+    /// Name that can't be split into parts, such as an organization,
+    /// which is kept verbatim. It's written in braces (e.g., "{Jet
+    /// Propulsion Laboratory}"), as in BibTeX.
+    pub literal: Option<String>,
+}
+
+/// Whether a word of a name is lowercase, which makes it part of the
+/// particle.
+fn is_particle(word: &str) -> bool {
+    word.chars()
+        .find(|c| c.is_alphabetic())
+        .map(char::is_lowercase)
+        .unwrap_or(false)
+}
+
+/// Words joined by spaces, or `None` if there are none.
+fn join_words(words: &[&str]) -> Option<String> {
+    match words.is_empty() {
+        true => None,
+        false => Some(words.join(" ")),
+    }
 }
 
 impl Name {
     pub fn new() -> Name {
-        Name {
-            first: None,
-            middle: None,
-            last: None,
+        Name::default()
+    }
+
+    /// Name from its given names and its family name, whose leading
+    /// lowercase words are the particle.
+    ///
+    /// # Arguments
+    ///
+    /// * `given` - Given names, e.g., "Johann Sebastian".
+    /// * `family` - Family name, e.g., "Bach" or "van Beethoven".
+    /// * `suffix` - Suffix, e.g., "Jr.".
+    pub fn from_parts(
+        given: Option<&str>,
+        family: &str,
+        suffix: Option<&str>,
+    ) -> Name {
+        let mut name = Name::new();
+        name.set_given(given.unwrap_or(""));
+        name.set_family(family);
+        name.suffix = suffix
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        name
+    }
+
+    /// Set the first and middle names from the given names.
+    fn set_given(&mut self, given: &str) {
+        let words: Vec<&str> = given.split_whitespace().collect();
+        self.first = words.first().map(|w| w.to_string());
+        self.middle = join_words(words.get(1..).unwrap_or(&[]));
+    }
+
+    /// Set the particle and last name from a family name ("von Last").
+    /// The particle runs up to the last lowercase word, but the final
+    /// word is always part of the last name.
+    fn set_family(&mut self, family: &str) {
+        let words: Vec<&str> = family.split_whitespace().collect();
+        let split = match words.len() {
+            0 => 0,
+            n => words[..n - 1]
+                .iter()
+                .rposition(|w| is_particle(w))
+                .map_or(0, |i| i + 1),
+        };
+        self.particle = join_words(&words[..split]);
+        self.last = join_words(&words[split..]);
+    }
+
+    /// Given names, e.g., "Richard Phillips".
+    fn given(&self) -> Option<String> {
+        let given: Vec<&str> = [&self.first, &self.middle]
+            .iter()
+            .filter_map(|n| n.as_deref())
+            .collect();
+        join_words(&given)
+    }
+
+    /// Family name including its particle, e.g., "van Beethoven".
+    fn family(&self) -> Option<String> {
+        let family: Vec<&str> = [&self.particle, &self.last]
+            .iter()
+            .filter_map(|n| n.as_deref())
+            .collect();
+        join_words(&family)
+    }
+
+    /// Name written in a display style. The particle follows the given
+    /// names when the last name comes first (e.g., "Beethoven, Ludwig
+    /// van").
+    pub fn format(&self, format: NameFormat) -> String {
+        if let Some(l) = &self.literal {
+            return l.clone();
+        }
+        let initials: Vec<String> = [&self.first, &self.middle]
+            .iter()
+            .filter_map(|n| n.as_deref())
+            .flat_map(|n| n.split_whitespace())
+            .filter_map(|n| n.chars().next())
+            .map(|c| format!("{}.", c))
+            .collect();
+        let given = match format {
+            NameFormat::FirstLast | NameFormat::LastFirst => self.given(),
+            NameFormat::InitialsLast | NameFormat::LastInitials => join_words(
+                &initials.iter().map(|i| i.as_str()).collect::<Vec<_>>(),
+            ),
+        };
+        let parts = |parts: &[Option<String>]| {
+            parts.iter().flatten().cloned().collect::<Vec<String>>()
+        };
+        match format {
+            NameFormat::FirstLast | NameFormat::InitialsLast => parts(&[
+                given,
+                self.particle.clone(),
+                self.last.clone(),
+                self.suffix.clone(),
+            ])
+            .join(" "),
+            NameFormat::LastFirst | NameFormat::LastInitials => {
+                let given = parts(&[given, self.particle.clone()]).join(" ");
+                parts(&[
+                    self.last.clone(),
+                    Some(given).filter(|g| !g.is_empty()),
+                    self.suffix.clone(),
+                ])
+                .join(", ")
+            }
+        }
+    }
+
+    /// Name as written in BibTeX name fields, i.e., "von Last, Jr,
+    /// First", "von Last, First" or just "von Last".
+    pub fn bibtex(&self) -> String {
+        if let Some(l) = &self.literal {
+            return format!("{{{}}}", l);
         }
+        let family = self.family().unwrap_or_default();
+        // Braces keep a last name of several words together.
+        let family = match (&self.particle, &self.last, self.given()) {
+            (None, Some(l), None) if l.contains(' ') => format!("{{{}}}", l),
+            _ => family,
+        };
+        let mut parts = vec![family];
+        if let Some(s) = &self.suffix {
+            parts.push(s.clone());
+        }
+        if let Some(g) = self.given() {
+            parts.push(g);
+        }
+        parts.join(", ")
+    }
+}
+
+/// Style in which names are displayed.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameFormat {
+    /// "Richard Phillips Feynman"
+    #[default]
+    FirstLast,
+    /// "Feynman, Richard Phillips", as in reference lists.
+    LastFirst,
+    /// "R. P. Feynman"
+    InitialsLast,
+    /// "Feynman, R. P."
+    LastInitials,
+}
+
+impl NameFormat {
+    pub fn is_first_last(&self) -> bool {
+        *self == NameFormat::FirstLast
     }
 }
 
@@ -325,47 +1266,131 @@ impl TryFrom<&str> for Name {
     type Error = NameParseError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let s = s.trim();
         let mut name = Name::new();
-        let subnames: Vec<&str> = s.split(" ").collect();
-        if subnames.len() > 3 {
-            return Err(NameParseError::new(
-                "A name can only contain a maximum of 3 parts.",
-            ));
-        } else if subnames.len() == 3 {
-            name.first = Some(subnames[0].to_string());
-            name.middle = Some(subnames[1].to_string());
-            name.last = Some(subnames[2].to_string());
-        } else if subnames.len() == 2 {
-            name.first = Some(subnames[0].to_string());
-            name.last = Some(subnames[1].to_string());
-        } else if subnames.len() == 1 {
-            name.last = Some(subnames[0].to_string());
+        if s.is_empty() {
+            return Err(NameParseError::new("A name can't be empty."));
+        }
+        if s.len() > 1 && s.starts_with('{') && s.ends_with('}') {
+            name.literal = Some(s[1..s.len() - 1].trim().to_string());
+            return Ok(name);
         }
 
+        let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+        match parts[..] {
+            // "von Last, First"
+            [family, given] if !NAME_SUFFIXES.contains(&given) => {
+                name.set_family(family);
+                name.set_given(given);
+            }
+            // "First von Last Jr" or "First von Last, Jr"
+            [s] | [s, _] => {
+                let mut words: Vec<&str> = s.split_whitespace().collect();
+                if let [_, suffix] = parts[..] {
+                    name.suffix = Some(suffix.to_string());
+                } else if words.len() > 1
+                    && NAME_SUFFIXES.contains(words.last().unwrap())
+                {
+                    name.suffix = words.pop().map(String::from);
+                }
+                // The particle starts at the first lowercase word that
+                // isn't the last word.
+                let start = match words.split_last() {
+                    Some((_, rest)) => rest
+                        .iter()
+                        .position(|w| is_particle(w))
+                        .unwrap_or(rest.len()),
+                    None => 0,
+                };
+                name.set_given(&words[..start].join(" "));
+                name.set_family(&words[start..].join(" "));
+            }
+            // "von Last, Jr, First"
+            [family, suffix, given] => {
+                name.set_family(family);
+                name.set_given(given);
+                name.suffix =
+                    Some(suffix.to_string()).filter(|s| !s.is_empty());
+            }
+            _ => name.literal = Some(s.to_string()),
+        }
+        if name.last.is_none() {
+            name = Name::new();
+            name.literal = Some(s.to_string());
+        }
         Ok(name)
     }
 }
 
+impl TryFrom<String> for Name {
+    type Error = NameParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_str())
+    }
+}
+
 impl From<Name> for String {
+    /// Name written as "First von Last Jr", unless it would be read
+    /// back differently (e.g., a last name of two words), in which case
+    /// it's written as "von Last, Jr, First".
     fn from(name: Name) -> Self {
-        match name.last {
-            Some(l) => match name.first {
-                Some(f) => match name.middle {
-                    Some(m) => format!("{} {} {}", f, m, l),
-                    None => format!("{} {}", f, l),
-                },
-                None => format!("{}", l),
-            },
-            None => format!(""),
+        if let Some(l) = &name.literal {
+            return format!("{{{}}}", l);
+        }
+        let first_last = name.format(NameFormat::FirstLast);
+        if Name::try_from(first_last.as_str()).ok().as_ref() == Some(&name) {
+            return first_last;
+        }
+        let mut parts = vec![name.family().unwrap_or_default()];
+        if let Some(s) = &name.suffix {
+            parts.push(s.clone());
         }
+        parts.push(name.given().unwrap_or_default());
+        parts.join(", ")
     }
 }
 
+impl JsonSchema for Name {
+    fn schema_name() -> String {
+        "Name".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "Person name written as \"First von Last Jr\", \"von Last, First\" or \"von Last, Jr, First\", or a name kept verbatim in braces.",
+            None,
+        )
+    }
+}
+
+/// Secondary file accompanying a resource, such as slides, errata, a
+/// dataset or source code.
+#[skip_serializing_none]
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, Eq, PartialEq,
+)]
+#[serde(deny_unknown_fields)]
+pub struct Attachment {
+    /// File name, which is unique among the attachments of a resource.
+    pub name: String,
+    /// SHA-1 checksum of the file.
+    pub checksum: String,
+    /// What the file holds (e.g., "slides").
+    pub description: Option<String>,
+}
+
+/// Fields of a resource that can't be set from outside the library,
+/// e.g., by an HTTP client or a metadata provider, because they identify
+/// the resource or its files.
+pub const PROTECTED_FIELDS: [&str; 3] =
+    ["checksum", "historical_checksums", "remote"];
+
 /// Library "resource". This represents one unit of library content,
 /// which can either be a file (such as a document or video), or a
 /// directory (e.g., holding the contents of a webpage).
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Eq, PartialEq)]
 #[serde(deny_unknown_fields)] // error when unknown fields encountered
 pub struct Resource {
     /// Title.
@@ -397,6 +1422,9 @@ pub struct Resource {
     pub journal: Option<String>,
     /// Volume of a journal or multi-volume book or other resource.
     pub volume: Option<String>,
+    /// Book series or multi-volume work the resource belongs to (e.g.,
+    /// "Graduate Texts in Mathematics").
+    pub series: Option<String>,
     /// The issue number of a journal, magazine, application note,
     /// etc. This is a string because issue numbers are not always
     /// numbers. For example, they often contain character suffixes as
@@ -404,10 +1432,34 @@ pub struct Resource {
     pub number: Option<String>,
     /// Manufacturer part number.
     pub part_number: Option<String>,
-    /// TODO create a DOI struct with custom
-    /// serialization/deserialization.
+    /// Page range of the resource within the work containing it (e.g.,
+    /// "231-252"), or the number of pages it has.
+    pub pages: Option<String>,
+    /// Chapter of the book the resource is, or contains.
+    pub chapter: Option<String>,
     /// Digital object identifier (DOI).
-    pub doi: Option<String>,
+    pub doi: Option<Doi>,
+    /// International Standard Book Number (ISBN).
+    pub isbn: Option<Isbn>,
+    /// International Standard Serial Number (ISSN) of the journal or
+    /// series.
+    pub issn: Option<Issn>,
+    /// Identifier of the resource in an electronic archive, such as an
+    /// arXiv ID.
+    pub eprint: Option<String>,
+    /// Archive the `eprint` identifier belongs to (e.g., "arxiv").
+    pub eprinttype: Option<String>,
+    /// Archive-specific classification of the resource, such as the
+    /// primary arXiv category (e.g., "hep-th").
+    pub eprintclass: Option<String>,
+    /// Summary of the resource's content, as given by its authors or
+    /// publisher.
+    pub r#abstract: Option<String>,
+    /// Keywords given by the authors or publisher, unlike tags, which
+    /// are the library's own classification.
+    pub keywords: Option<Vec<String>>,
+    /// Language the resource is written in (e.g., "english" or "de").
+    pub language: Option<String>,
     pub tags: Option<Vec<String>>,
     /// Document type (when applicable). This field is also used to
     /// associate a resource with a file extension.
@@ -416,6 +1468,62 @@ pub struct Resource {
     /// Upstream URL where the resource is maintained or where it was
     /// retreived.
     pub url: Option<Url>,
+    /// Date the resource was retrieved from its URL, which is exported
+    /// as BibLaTeX's urldate.
+    pub archived: Option<DateTime>,
+    /// Wayback Machine snapshot of the URL, which is exported as the
+    /// archiveurl field.
+    pub archive_url: Option<Url>,
+    /// Date and time (UTC) the resource was added to the catalog.
+    pub date_added: Option<DateTime>,
+    /// Date and time (UTC) the resource's metadata or file last changed.
+    pub date_modified: Option<DateTime>,
+    /// Key identifying the resource in citations. Resources without a
+    /// citation key are cited by their initial checksum.
+    pub citekey: Option<String>,
+    /// Lifecycle state of the resource in the catalog's workflow (e.g.,
+    /// "reviewed"). See `Catalog::states`.
+    pub state: Option<String>,
+    /// How far the resource has been read. Resources without a status
+    /// are unread.
+    pub read_status: Option<ReadStatus>,
+    /// Rating of the resource from 1 (poor) to 5 (excellent).
+    pub rating: Option<u8>,
+    /// How soon the resource should be read. Resources without a
+    /// priority have normal priority.
+    pub priority: Option<Priority>,
+    /// Personal resources, which are left out of exports (e.g., BibTeX
+    /// files and mirrors) unless private resources are explicitly
+    /// included.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// Resources whose file is no longer in the resources directory,
+    /// which `librarian catalog` keeps with their metadata instead of
+    /// removing them. They are left out of searches and exports unless
+    /// retired resources are explicitly included, and come back when
+    /// their file does.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retired: bool,
+    /// Location of the resource's file or directory relative to the
+    /// resources directory (e.g., "physics/epr.pdf"), for resources
+    /// organized in a subdirectory of it, which stay where they are.
+    /// Other resources are stored under their initial checksum.
+    pub path: Option<String>,
+    /// Secondary files, stored in the `attachments` directory of the
+    /// resources directory under the resource's initial checksum.
+    pub attachments: Option<Vec<Attachment>>,
+    /// Copy of the resource's file in the library's remote storage,
+    /// which is the only copy when the file isn't in the resources
+    /// directory.
+    pub remote: Option<RemoteObject>,
+    /// Other resources this one relates to, referred to by their initial
+    /// checksums (e.g., "cites": ["4b9a..."]).
+    pub relations: Option<BTreeMap<Relation, Vec<String>>>,
+    /// Custom fields the schema doesn't provide (e.g., "course" or
+    /// "shelf"), mapped to their values. Content types can export them
+    /// to BibTeX by mapping a BibTeX field to "extra.<name>".
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub extra: IndexMap<String, String>,
     /// Current SHA-1 checksum.
     pub checksum: String,
     /// An ordered collection (oldest to most recent) of all previous
@@ -425,6 +1533,86 @@ pub struct Resource {
 }
 
 impl Resource {
+    /// New resource with only its identifying fields set.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - Initial title, typically the file name.
+    /// * `document` - Document type, if known.
+    /// * `checksum` - SHA-1 checksum of the resource's content.
+    pub fn new(
+        title: String,
+        document: Option<String>,
+        checksum: String,
+    ) -> Resource {
+        Resource {
+            title,
+            subtitle: None,
+            author: None,
+            editor: None,
+            date: None,
+            edition: None,
+            version: None,
+            publisher: None,
+            organization: None,
+            journal: None,
+            volume: None,
+            series: None,
+            number: None,
+            part_number: None,
+            pages: None,
+            chapter: None,
+            doi: None,
+            isbn: None,
+            issn: None,
+            eprint: None,
+            eprinttype: None,
+            eprintclass: None,
+            r#abstract: None,
+            keywords: None,
+            language: None,
+            tags: None,
+            document,
+            content: None,
+            url: None,
+            archived: None,
+            archive_url: None,
+            date_added: None,
+            date_modified: None,
+            citekey: None,
+            state: None,
+            read_status: None,
+            rating: None,
+            priority: None,
+            private: false,
+            retired: false,
+            path: None,
+            attachments: None,
+            remote: None,
+            relations: None,
+            extra: IndexMap::new(),
+            checksum: checksum.clone(),
+            historical_checksums: vec![checksum],
+        }
+    }
+
+    /// Whether another resource has the same DOI or ISBN, meaning both
+    /// describe the same work. DOIs are compared ignoring case and
+    /// ISBNs by their ISBN-13, so that an ISBN-10 matches the same
+    /// book's ISBN-13.
+    pub fn is_duplicate_of(&self, other: &Resource) -> bool {
+        let isbn = |r: &Resource| r.isbn.as_ref().map(|i| i.isbn13());
+        let same_doi = match (&self.doi, &other.doi) {
+            (Some(a), Some(b)) => a.as_str().eq_ignore_ascii_case(b.as_str()),
+            _ => false,
+        };
+        let same_isbn = match (isbn(self), isbn(other)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        };
+        same_doi || same_isbn
+    }
+
     /// Concatenate fields into a single string, using a space as a
     /// delimeter between fields.
     ///
@@ -446,7 +1634,7 @@ impl Resource {
     /// When an optional field is None, an empty string is
     /// returned. When a field contains a list of values, all items are
     /// concatenated separated by spaces.
-    fn field_string(&self, field: &str) -> Option<String> {
+    pub(crate) fn field_string(&self, field: &str) -> Option<String> {
         match field {
             "title" => Some(self.title.clone()),
             "subtitle" => match &self.subtitle {
@@ -507,10 +1695,18 @@ impl Resource {
                 Some(x) => Some(x.clone()),
                 None => None,
             },
-            "doi" => match &self.doi {
-                Some(x) => Some(x.clone()),
-                None => None,
-            },
+            "series" => self.series.clone(),
+            "pages" => self.pages.clone(),
+            "chapter" => self.chapter.clone(),
+            "language" => self.language.clone(),
+            "doi" => self.doi.clone().map(String::from),
+            "isbn" => self.isbn.clone().map(String::from),
+            "issn" => self.issn.clone().map(String::from),
+            "eprint" => self.eprint.clone(),
+            "eprinttype" => self.eprinttype.clone(),
+            "eprintclass" => self.eprintclass.clone(),
+            "abstract" => self.r#abstract.clone(),
+            "keywords" => self.keywords.as_ref().map(|k| k.join(" ")),
             "tags" => match &self.tags {
                 Some(it) => Some(
                     it.iter()
@@ -532,6 +1728,10 @@ impl Resource {
                 Some(x) => Some(String::from(x.clone())),
                 None => None,
             },
+            "archived" => self.archived.clone().map(String::from),
+            "archive_url" => self.archive_url.as_ref().map(|u| u.to_string()),
+            "citekey" => self.citekey.clone(),
+            "state" => self.state.clone(),
             "checksum" => Some(self.checksum.clone()),
             // TODO should probably exclude historical checksum that
             // is identical to checksum
@@ -542,29 +1742,42 @@ impl Resource {
                     .collect::<Vec<String>>()
                     .join(" "),
             ),
+            "extra" => match self.extra.is_empty() {
+                true => None,
+                false => Some(
+                    self.extra
+                        .values()
+                        .cloned()
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                ),
+            },
+            // A single custom field, e.g., "extra.course".
+            f if f.starts_with("extra.") => {
+                self.extra.get(&f["extra.".len()..]).cloned()
+            }
             &_ => panic!("invalid field specifier"),
         }
     }
 
-    /// The BibTeX type associated with the current resource.
+    /// The content type of the current resource.
     ///
     /// # Arguments
     ///
     /// * `content_types` - A collection of content types as defined
     /// in the catalog. The map key is a string identifying the
-    /// content type and the map value is the associated BibTeX type.
+    /// content type and the map value its definition.
     ///
     /// # Return
     ///
-    /// Returns `None` if the content type for resource is not one of
-    /// the content types defined in the catalog.
-    pub fn bibtex_type(
+    /// Returns `None` if the resource doesn't have a content type.
+    pub fn content_type<'a>(
         &self,
-        content_types: &IndexMap<String, BibtexType>,
-    ) -> Option<BibtexType> {
+        content_types: &'a IndexMap<String, ContentType>,
+    ) -> Option<&'a ContentType> {
         match &self.content {
             Some(c) => Some(match content_types.get(c) {
-                Some(ct) => ct.clone(),
+                Some(ct) => ct,
                 None => panic!(
                     "Failed to retrieve bibtex type for resource {:?}",
                     self.checksum
@@ -573,11 +1786,304 @@ impl Resource {
             None => None,
         }
     }
+
+    /// The BibTeX type associated with the current resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_types` - See `content_type`.
+    ///
+    /// # Return
+    ///
+    /// Returns `None` if the resource doesn't have a content type.
+    pub fn bibtex_type(
+        &self,
+        content_types: &IndexMap<String, ContentType>,
+    ) -> Option<BibtexType> {
+        self.content_type(content_types)
+            .map(|ct| ct.bibtex_type().clone())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    /// Strategy producing a valid `DateTime` of any precision.
+    fn datetime_strategy() -> impl Strategy<Value = DateTime> {
+        (
+            0..10000i32,
+            1..=12i32,
+            // Days that exist in every month.
+            1..=28i32,
+            0..=23i32,
+            0..=59i32,
+            0..=59i32,
+            0..=6usize,
+        )
+            .prop_map(
+                |(year, month, day, hour, minute, second, precision)| {
+                    let keep = |i: usize, v: i32| {
+                        if i < precision {
+                            Some(v)
+                        } else {
+                            None
+                        }
+                    };
+                    DateTime {
+                        year: keep(0, year),
+                        month: keep(1, month),
+                        day: keep(2, day),
+                        hour: keep(3, hour),
+                        minute: keep(4, minute),
+                        second: keep(5, second),
+                        end: None,
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn test_datetime_roundtrip(datetime in datetime_strategy()) {
+            let serialized = String::from(datetime.clone());
+            prop_assert!(DateTime::try_from(serialized.as_str()).unwrap() == datetime);
+        }
+
+        #[test]
+        fn test_datetime_parse_serialize_parse(s in "\\PC*|[0-9x:T -]{0,20}") {
+            if let Ok(datetime) = DateTime::try_from(s.as_str()) {
+                let serialized = String::from(datetime.clone());
+                prop_assert!(DateTime::try_from(serialized.as_str()).unwrap() == datetime);
+            }
+        }
+
+        #[test]
+        fn test_name_roundtrip(parts in prop::collection::vec("[A-Za-z.'-]{1,12}", 1..=3)) {
+            let name = Name::try_from(parts.join(" ").as_str()).unwrap();
+            prop_assert!(String::from(name) == parts.join(" "));
+        }
+
+        #[test]
+        fn test_name_parse_serialize_parse(s in "\\PC*") {
+            if let Ok(name) = Name::try_from(s.as_str()) {
+                let serialized = String::from(name.clone());
+                prop_assert!(Name::try_from(serialized.as_str()).unwrap() == name);
+            }
+        }
+
+        #[test]
+        fn test_media_type_roundtrip(
+            prefix in "application|audio|image|message|multipart|text|video|font|example|model",
+            subtype in "[a-z0-9.+-]{1,20}",
+        ) {
+            let s = format!("{}/{}", prefix, subtype);
+            let media_type = MediaType::try_from(s.as_str()).unwrap();
+            prop_assert!(String::from(media_type) == s);
+        }
+
+        #[test]
+        fn test_media_type_parse_serialize_parse(s in "\\PC*|[a-z]{0,12}/[a-z]{0,5}") {
+            if let Ok(media_type) = MediaType::try_from(s.as_str()) {
+                let serialized = String::from(media_type.clone());
+                prop_assert!(MediaType::try_from(serialized.as_str()).unwrap() == media_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_datetime_malformed() {
+        assert!(DateTime::try_from("19xx").is_err());
+        assert!(DateTime::try_from("1999-xx").is_err());
+        assert!(DateTime::try_from("1999-13").is_err());
+        assert!(DateTime::try_from("1999-1é").is_err());
+        assert!(DateTime::try_from("1999-02-29").is_err());
+        assert!(DateTime::try_from("2000-02-29").is_ok());
+        assert!(DateTime::try_from("1999x12").is_err());
+        assert!(DateTime::try_from("1999-12-01T25").is_err());
+        assert!(DateTime::try_from("1999-12-01+02:00").is_err());
+        assert!(DateTime::try_from("2001/1999").is_err());
+        assert!(DateTime::try_from("/").is_err());
+    }
+
+    #[test]
+    fn test_datetime_offset_and_range() {
+        let date = |s| String::from(DateTime::try_from(s).unwrap());
+        // Times with a UTC offset are stored in UTC.
+        assert!(date("2024-03-01T10:00:00Z") == "2024-03-01T10:00:00");
+        assert!(date("2024-03-01T01:30:00.250+02:00") == "2024-02-29T23:30:00");
+        assert!(date("2023-12-31T20-0500") == "2024-01-01T01");
+        let range = DateTime::try_from("1999/2001-06").unwrap();
+        assert!(range.year == Some(1999));
+        assert!(range.end.as_ref().unwrap().month == Some(6));
+        assert!(String::from(range) == "1999/2001-06");
+        assert!(date("1999/") == "1999/");
+        assert!(date("/2001") == "/2001");
+    }
+
+    #[test]
+    fn test_datetime_from_system_time() {
+        let time = |secs| {
+            String::from(DateTime::from(
+                SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs),
+            ))
+        };
+        assert!(time(0) == "1970-01-01T00:00:00");
+        assert!(time(951_827_696) == "2000-02-29T12:34:56");
+        assert!(time(1_704_067_199) == "2023-12-31T23:59:59");
+    }
+
+    #[test]
+    fn test_media_type_unknown_prefix() {
+        assert!(MediaType::try_from("chemical/x-pdb").is_err());
+        assert!(MediaType::try_from("text/html").is_ok());
+    }
+
+    #[test]
+    fn test_media_type_parameters() {
+        let html = MediaType::try_from("Text/HTML; Charset=utf-8").unwrap();
+        assert!(html.essence() == "text/html");
+        assert!(html.parameter("charset") == Some("utf-8"));
+        assert!(String::from(html) == "text/html; charset=utf-8");
+
+        let odt =
+            MediaType::try_from("application/vnd.oasis.opendocument.text+xml")
+                .unwrap();
+        assert!(odt.tree() == Some("vnd"));
+        assert!(odt.suffix() == Some("xml"));
+        assert!(
+            String::from(odt) == "application/vnd.oasis.opendocument.text+xml"
+        );
+
+        let quoted = MediaType::try_from(
+            "multipart/form-data; boundary=\"a;b \\\"c\\\"\"; x=1",
+        )
+        .unwrap();
+        assert!(quoted.parameter("boundary") == Some("a;b \"c\""));
+        assert!(quoted.parameter("x") == Some("1"));
+        assert!(
+            MediaType::try_from(String::from(quoted.clone()).as_str()).unwrap()
+                == quoted
+        );
+
+        assert!(MediaType::try_from("text/").is_err());
+        assert!(MediaType::try_from("text/plain; charset").is_err());
+        assert!(MediaType::try_from("text/plain; charset=\"utf-8").is_err());
+        assert!(MediaType::try_from("text/plain; =utf-8").is_err());
+    }
+
+    #[test]
+    fn test_name_format() {
+        let name = Name::try_from("Richard Phillips Feynman").unwrap();
+        assert!(
+            name.format(NameFormat::FirstLast) == "Richard Phillips Feynman"
+        );
+        assert!(
+            name.format(NameFormat::LastFirst) == "Feynman, Richard Phillips"
+        );
+        assert!(name.format(NameFormat::InitialsLast) == "R. P. Feynman");
+        assert!(name.format(NameFormat::LastInitials) == "Feynman, R. P.");
+        let name = Name::try_from("Euclid").unwrap();
+        assert!(name.format(NameFormat::LastInitials) == "Euclid");
+    }
+
+    #[test]
+    fn test_name_parts() {
+        let name = Name::try_from("Ludwig van Beethoven").unwrap();
+        assert!(name.particle.as_deref() == Some("van"));
+        assert!(name.last.as_deref() == Some("Beethoven"));
+        assert!(Name::try_from("van Beethoven, Ludwig").unwrap() == name);
+        assert!(name.format(NameFormat::LastFirst) == "Beethoven, Ludwig van");
+        assert!(name.bibtex() == "van Beethoven, Ludwig");
+
+        let name = Name::try_from("Martin Luther King, Jr.").unwrap();
+        assert!(name.suffix.as_deref() == Some("Jr."));
+        assert!(name.middle.as_deref() == Some("Luther"));
+        assert!(String::from(name.clone()) == "Martin Luther King Jr.");
+        assert!(name.bibtex() == "King, Jr., Martin Luther");
+        assert!(Name::try_from("King, Jr., Martin Luther").unwrap() == name);
+
+        let name = Name::try_from("Gabriel José García Márquez");
+        assert!(name.unwrap().middle.as_deref() == Some("José García"));
+        let name = Name::try_from("García Márquez, Gabriel José").unwrap();
+        assert!(name.last.as_deref() == Some("García Márquez"));
+        assert!(String::from(name) == "García Márquez, Gabriel José");
+
+        let name = Name::try_from("{Jet Propulsion Laboratory}").unwrap();
+        assert!(
+            name.format(NameFormat::LastFirst) == "Jet Propulsion Laboratory"
+        );
+        assert!(String::from(name) == "{Jet Propulsion Laboratory}");
+        assert!(Name::try_from("a, b, c, d").unwrap().literal.is_some());
+        assert!(Name::try_from(" ").is_err());
+
+        // Escaped JSON strings aren't borrowed from the input.
+        let names: Vec<Name> = serde_json::from_str(
+            "[\"Erd\\u0151s, P\\u00e1l\", \"O\\\"Brien\"]",
+        )
+        .unwrap();
+        assert!(names[0].last.as_deref() == Some("Erdős"));
+        assert!(names[1].last.as_deref() == Some("O\"Brien"));
+        let doi: Doi =
+            serde_json::from_value(serde_json::json!("10.1000/182")).unwrap();
+        assert!(String::from(doi) == "10.1000/182");
+    }
+
+    #[test]
+    fn test_isbn_issn() {
+        assert!(Isbn::try_from("978-0-471-30932-1").is_ok());
+        assert!(Isbn::try_from("9780471309322").is_err());
+        assert!(
+            Isbn::try_from("0 471 30932 X").unwrap().isbn13()
+                == "9780471309321"
+        );
+        assert!(Isbn::try_from("04713093X2").is_err());
+        assert!(Isbn::try_from("9780000000040").is_ok());
+        assert!(Isbn::try_from("978000000004X").is_err());
+        assert!(Isbn::try_from("978-0-471").is_err());
+        assert!(
+            String::from(Issn::try_from("0031899x").unwrap()) == "0031-899X"
+        );
+        assert!(Issn::try_from("0018-9200").is_ok());
+        assert!(Issn::try_from("0018-9201").is_err());
+    }
+
+    #[test]
+    fn test_doi() {
+        let doi =
+            Doi::try_from(" https://doi.org/10.1103/PhysRev.47.777").unwrap();
+        assert!(doi.as_str() == "10.1103/PhysRev.47.777");
+        assert!(Doi::try_from("DOI:10.1103/PhysRev.47.777").unwrap() == doi);
+        assert!(
+            doi.resolve_url().as_str()
+                == "https://doi.org/10.1103/PhysRev.47.777"
+        );
+        assert!(Doi::try_from("10.1103").is_err());
+        assert!(Doi::try_from("11.1103/PhysRev.47.777").is_err());
+        assert!(Doi::try_from("10.abc/x").is_err());
+    }
+
+    #[test]
+    fn test_resource_is_duplicate_of() {
+        let resource = |doi: Option<&str>, isbn: Option<&str>| {
+            let mut r =
+                Resource::new(String::from("Title"), None, String::from("ab"));
+            r.doi = doi.map(|d| Doi::try_from(d).unwrap());
+            r.isbn = isbn.map(|i| Isbn::try_from(i).unwrap());
+            r
+        };
+        let book = resource(None, Some("978-0-471-30932-1"));
+        assert!(book.is_duplicate_of(&resource(None, Some("9780471309321"))));
+        assert!(book.is_duplicate_of(&resource(None, Some("0-471-30932-x"))));
+        assert!(
+            !book.is_duplicate_of(&resource(None, Some("978-0-262-03384-8")))
+        );
+        let paper = resource(Some("10.1103/PhysRev.47.777"), None);
+        assert!(paper
+            .is_duplicate_of(&resource(Some("10.1103/physrev.47.777"), None)));
+        assert!(!paper.is_duplicate_of(&resource(None, None)));
+    }
 
     #[test]
     fn test_resource_concat_fields() {
@@ -619,10 +2125,17 @@ mod tests {
             "number",
             "part_number",
             "doi",
+            "isbn",
+            "eprint",
+            "eprinttype",
+            "eprintclass",
             "tags",
             "document",
             "content",
             "url",
+            "archived",
+            "citekey",
+            "state",
             "checksum",
             "historical_checksums",
         ]);
@@ -0,0 +1,398 @@
+use crate::bibtex::{latex_escape, BibtexEntry, BibtexType, ContentType};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::import::import_entries;
+use crate::resource::{DateTime, Isbn, NameFormat, Resource};
+use crate::search::ResourceFilter;
+use crate::storage::Storage;
+
+use indexmap::IndexMap;
+use regex::Regex;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// RIS reference type corresponding to a BibTeX entry type.
+fn ris_type(bibtex_type: &BibtexType) -> &'static str {
+    match bibtex_type {
+        BibtexType::Article => "JOUR",
+        BibtexType::Book => "BOOK",
+        BibtexType::Collection => "EDBOOK",
+        BibtexType::Image => "FIGURE",
+        BibtexType::Manual => "STAND",
+        BibtexType::Miscellaneous => "GEN",
+        BibtexType::Online => "ELEC",
+        BibtexType::Patent => "PAT",
+        BibtexType::Report | BibtexType::TechReport => "RPRT",
+        BibtexType::Software => "COMP",
+        BibtexType::Video => "VIDEO",
+    }
+}
+
+/// BibTeX entry type corresponding to an RIS reference type.
+fn bibtex_entry_type(ris_type: &str) -> &'static str {
+    match ris_type {
+        "JOUR" | "JFULL" | "MGZN" | "NEWS" => "article",
+        "BOOK" => "book",
+        "EDBOOK" => "collection",
+        "FIGURE" => "image",
+        "STAND" => "manual",
+        "ELEC" | "WEB" => "online",
+        "PAT" => "patent",
+        "RPRT" => "report",
+        "COMP" => "software",
+        "VIDEO" => "video",
+        _ => "misc",
+    }
+}
+
+/// Date as written in RIS, with missing components left blank (e.g.,
+/// "2019/05/04/" or "2019///").
+fn ris_date(date: &DateTime) -> String {
+    [date.year, date.month, date.day]
+        .iter()
+        .map(|c| match c {
+            Some(c) => format!("{:02}", c),
+            None => String::new(),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+        + "/"
+}
+
+fn ris_serialize_field(tag: &str, value: Option<String>) -> String {
+    match value {
+        Some(v) => format!("{}  - {}\n", tag, v),
+        None => String::new(),
+    }
+}
+
+impl Resource {
+    /// Serialized RIS record of the current resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_types` - See `serialize_bibtex`.
+    /// * `storage` - Resources directory. This is used to link the
+    ///   record to the resource's file.
+    pub fn serialize_ris(
+        &self,
+        content_types: &IndexMap<String, ContentType>,
+        storage: &Storage,
+    ) -> String {
+        let bt = match self.bibtex_type(content_types) {
+            Some(bt) => bt,
+            None => return String::new(),
+        };
+        let mut record = ris_serialize_field("TY", Some(ris_type(&bt).into()));
+        record.push_str(&ris_serialize_field("ID", Some(self.bibtex_key())));
+        // RIS doesn't have a subtitle.
+        let title = match &self.subtitle {
+            Some(s) => format!("{}: {}", self.title, s),
+            None => self.title.clone(),
+        };
+        record.push_str(&ris_serialize_field("TI", Some(title)));
+        for name in self.author.iter().flatten() {
+            record.push_str(&ris_serialize_field(
+                "AU",
+                Some(name.format(NameFormat::LastFirst)),
+            ));
+        }
+        for name in self.editor.iter().flatten() {
+            record.push_str(&ris_serialize_field(
+                "ED",
+                Some(name.format(NameFormat::LastFirst)),
+            ));
+        }
+        if let Some(d) = &self.date {
+            record.push_str(&ris_serialize_field(
+                "PY",
+                d.year.map(|y| y.to_string()),
+            ));
+            record.push_str(&ris_serialize_field("DA", Some(ris_date(d))));
+        }
+        record.push_str(&ris_serialize_field("ET", self.edition.clone()));
+        record.push_str(&ris_serialize_field("PB", self.publisher.clone()));
+        record.push_str(&ris_serialize_field("T2", self.journal.clone()));
+        record.push_str(&ris_serialize_field("VL", self.volume.clone()));
+        record.push_str(&ris_serialize_field("IS", self.number.clone()));
+        record.push_str(&ris_serialize_field(
+            "DO",
+            self.doi.clone().map(String::from),
+        ));
+        // SN holds either identifier.
+        record.push_str(&ris_serialize_field(
+            "SN",
+            self.isbn
+                .clone()
+                .map(String::from)
+                .or_else(|| self.issn.clone().map(String::from)),
+        ));
+        record.push_str(&ris_serialize_field(
+            "UR",
+            self.url.as_ref().map(|u| u.to_string()),
+        ));
+        for tag in self.tags.iter().flatten() {
+            record.push_str(&ris_serialize_field("KW", Some(tag.clone())));
+        }
+        record.push_str(&ris_serialize_field(
+            "L1",
+            Some(
+                storage
+                    .resource_path(&self.historical_checksums[0])
+                    .display()
+                    .to_string(),
+            ),
+        ));
+        record.push_str("ER  - \n\n");
+        record
+    }
+}
+
+/// Parse the records of an RIS file. Each record maps a tag to all of
+/// its values, in order.
+fn parse_ris(contents: &str) -> Vec<IndexMap<String, Vec<String>>> {
+    let line_re = Regex::new(r"^([A-Z][A-Z0-9])  -(?: (.*))?$").unwrap();
+    let mut records = Vec::<IndexMap<String, Vec<String>>>::new();
+    let mut record = IndexMap::<String, Vec<String>>::new();
+    let mut last_tag: Option<String> = None;
+    for line in contents.lines() {
+        let line = line.trim_end().trim_start_matches('\u{feff}');
+        match line_re.captures(line) {
+            Some(c) => {
+                let tag = c[1].to_string();
+                let value = c.get(2).map_or("", |v| v.as_str()).trim();
+                if tag == "ER" {
+                    records.push(record);
+                    record = IndexMap::new();
+                    last_tag = None;
+                    continue;
+                }
+                record
+                    .entry(tag.clone())
+                    .or_default()
+                    .push(value.to_string());
+                last_tag = Some(tag);
+            }
+            // Lines without a tag continue the previous value.
+            None if !line.trim().is_empty() => {
+                if let Some(v) = last_tag
+                    .as_ref()
+                    .and_then(|t| record.get_mut(t))
+                    .and_then(|v| v.last_mut())
+                {
+                    v.push(' ');
+                    v.push_str(line.trim());
+                }
+            }
+            None => (),
+        }
+    }
+    records
+}
+
+/// Convert an RIS record to the BibTeX entry it describes, so that it
+/// can be imported like one.
+fn ris_entry(record: &IndexMap<String, Vec<String>>) -> BibtexEntry {
+    let first = |tags: &[&str]| {
+        tags.iter()
+            .find_map(|t| record.get(*t).and_then(|v| v.first()))
+            .cloned()
+    };
+    let all = |tags: &[&str]| -> Vec<String> {
+        tags.iter()
+            .filter_map(|t| record.get(*t))
+            .flatten()
+            .cloned()
+            .collect()
+    };
+
+    let mut fields = IndexMap::<String, Option<String>>::new();
+    fields.insert("title".into(), first(&["TI", "T1"]));
+    let authors = all(&["AU", "A1"]);
+    let editors = all(&["ED", "A2"]);
+    fields.insert(
+        "author".into(),
+        Some(authors.join(" and ")).filter(|a| !a.is_empty()),
+    );
+    fields.insert(
+        "editor".into(),
+        Some(editors.join(" and ")).filter(|e| !e.is_empty()),
+    );
+    // DA is the full date and PY the year, but older files put the full
+    // date in PY or Y1.
+    let date = first(&["DA", "PY", "Y1"]).and_then(|d| {
+        let parts: Vec<&str> = d
+            .split('/')
+            .take(3)
+            .take_while(|p| {
+                !p.is_empty() && p.chars().all(|c| c.is_ascii_digit())
+            })
+            .collect();
+        match parts.is_empty() {
+            true => None,
+            false => Some(parts.join("-")),
+        }
+    });
+    fields.insert("date".into(), date);
+    fields.insert("edition".into(), first(&["ET"]));
+    fields.insert("publisher".into(), first(&["PB"]));
+    fields.insert("journal".into(), first(&["T2", "JF", "JO", "JA"]));
+    fields.insert("volume".into(), first(&["VL"]));
+    fields.insert("number".into(), first(&["IS"]));
+    fields.insert("doi".into(), first(&["DO"]));
+    let serial_number = first(&["SN"]);
+    let is_isbn = |n: &String| Isbn::try_from(n.as_str()).is_ok();
+    fields.insert("isbn".into(), serial_number.clone().filter(is_isbn));
+    fields.insert("issn".into(), serial_number.filter(|n| !is_isbn(n)));
+    fields.insert("url".into(), first(&["UR"]));
+    let keywords = all(&["KW"]);
+    fields.insert(
+        "keywords".into(),
+        Some(keywords.join(", ")).filter(|k| !k.is_empty()),
+    );
+    fields.insert(
+        "file".into(),
+        first(&["L1"]).map(|f| f.trim_start_matches("file://").to_string()),
+    );
+
+    BibtexEntry {
+        entry_type: bibtex_entry_type(&first(&["TY"]).unwrap_or_default())
+            .to_string(),
+        key: first(&["ID"]).unwrap_or_default(),
+        // Values are escaped so that they are read back verbatim.
+        fields: fields
+            .into_iter()
+            .filter_map(|(f, v)| {
+                v.map(|v| {
+                    let escaped = match f.as_str() {
+                        "file" | "url" | "doi" => v,
+                        _ => latex_escape(&v).unwrap_or(v),
+                    };
+                    (f, escaped)
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Create or update catalog resources from the records of an RIS file.
+/// This behaves like `librarian_import_bibtex`, with each record's ID
+/// taking the place of the BibTeX key and its L1 tag the place of the
+/// file field.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `ris_file_path` - RIS file to import.
+/// * `interactive` - Prompt for the file of records without a valid
+///   L1 tag.
+pub fn librarian_import_ris(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    ris_file_path: &Path,
+    interactive: bool,
+) {
+    let contents = std::fs::read_to_string(ris_file_path)
+        .expect("Failed to read RIS file");
+    let entries = parse_ris(&contents).iter().map(ris_entry).collect();
+    import_entries(
+        catalog,
+        resources_path,
+        entries,
+        ris_file_path.parent().unwrap_or(Path::new(".")),
+        interactive,
+    );
+    catalog.sort();
+    catalog_store.write(catalog);
+}
+
+/// Generate RIS records for cataloged resources.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `ris_file_path` - File where RIS data should be written. If no
+///   file is given, data will be written to stdout.
+/// * `collection` - Only export the resources of this collection, in
+///   collection order.
+/// * `include_private` - Include private resources.
+pub fn librarian_export_ris(
+    catalog: &Catalog,
+    resources_path: &Path,
+    ris_file_path: Option<&str>,
+    collection: Option<&str>,
+    include_private: bool,
+) {
+    let storage = Storage::of(resources_path, catalog);
+    let records: String = ResourceFilter {
+        collection: collection.map(String::from),
+        ..Default::default()
+    }
+    .apply(catalog, include_private)
+    .into_iter()
+    .map(|r| r.serialize_ris(&catalog.content_types, &storage))
+    .collect();
+
+    match ris_file_path {
+        Some(f) => {
+            std::fs::write(f, records).expect("Failed to write RIS file")
+        }
+        None => print!("{}", records),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibtex::latex_unescape;
+    use crate::resource::{Doi, Name};
+
+    #[test]
+    fn test_ris_roundtrip() {
+        let content_types: IndexMap<String, ContentType> = vec![(
+            String::from("paper"),
+            ContentType::Bibtex(BibtexType::Article),
+        )]
+        .into_iter()
+        .collect();
+        let mut resource = Resource::new(
+            String::from("Phase Noise in Oscillators"),
+            None,
+            String::from("88259e88e7677e5ae8a31e33f177a2198cabe95c"),
+        );
+        resource.content = Some(String::from("paper"));
+        resource.author = Some(vec![
+            Name::try_from("Ali Hajimiri").unwrap(),
+            Name::try_from("Thomas H. Lee").unwrap(),
+        ]);
+        resource.date = Some(DateTime::try_from("1998-02").unwrap());
+        resource.journal = Some(String::from("IEEE J. Solid-State Circuits"));
+        resource.doi = Some(Doi::try_from("10.1109/4.658619").unwrap());
+
+        let record = resource.serialize_ris(
+            &content_types,
+            &Storage::new(Path::new("/lib/resources"), Default::default()),
+        );
+        assert!(record.starts_with("TY  - JOUR\n"));
+        assert!(record.contains("AU  - Lee, Thomas H.\n"));
+        assert!(record.contains("DA  - 1998/02//\n"));
+
+        let records = parse_ris(&record);
+        assert!(records.len() == 1);
+        let entry = ris_entry(&records[0]);
+        assert!(entry.entry_type == "article");
+        assert!(entry.key == resource.bibtex_key());
+        assert!(
+            latex_unescape(&entry.fields["author"])
+                == "Hajimiri, Ali and Lee, Thomas H."
+        );
+        assert!(entry.fields["date"] == "1998-02");
+        assert!(
+            entry.fields["file"]
+                == "/lib/resources/88259e88e7677e5ae8a31e33f177a2198cabe95c"
+        );
+    }
+}
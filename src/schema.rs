@@ -0,0 +1,19 @@
+use crate::catalog::Catalog;
+use crate::resource::Resource;
+
+use schemars::schema_for;
+
+/// Print the JSON schema of the catalog format.
+///
+/// # Arguments
+///
+/// * `resource_only` - Print the schema of a single resource instead,
+///   which describes the resource files of a split catalog.
+pub fn librarian_schema(resource_only: bool) {
+    let schema = match resource_only {
+        true => schema_for!(Resource),
+        false => schema_for!(Catalog),
+    };
+    serde_json::to_writer_pretty(std::io::stdout().lock(), &schema).unwrap();
+    println!();
+}
@@ -1,15 +1,321 @@
+use crate::activity::last_activity;
 use crate::catalog::Catalog;
-use crate::resource::Resource;
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::notes::notes_match;
+use crate::resource::{DateTime, Resource};
+use crate::storage::Storage;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Criteria selecting a subset of the cataloged resources, such as the
+/// resources relevant to one project.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceFilter {
+    /// Search query the resources must match.
+    pub query: Option<String>,
+    /// Tags the resources must all have. A tag also matches resources
+    /// with any of its descendants.
+    pub tags: Vec<String>,
+    /// Content type the resources must have.
+    pub content: Option<String>,
+    /// Lifecycle state the resources must be in.
+    pub state: Option<String>,
+    /// Collection the resources must belong to.
+    pub collection: Option<String>,
+    /// Include retired resources (see `Resource::retired`).
+    pub retired: bool,
+}
+
+impl ResourceFilter {
+    /// Resources meeting every criterion of the filter, in catalog
+    /// order, or in collection order when the filter has a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - Library catalog.
+    /// * `include_private` - Include private resources.
+    pub fn apply<'a>(
+        &self,
+        catalog: &'a Catalog,
+        include_private: bool,
+    ) -> Vec<&'a Resource> {
+        let matches: Option<Vec<&Resource>> = self.query.as_ref().map(|q| {
+            scored_search(catalog, q, self.retired)
+                .into_iter()
+                .map(|(_, r)| r)
+                .collect()
+        });
+        let resources: Vec<&Resource> = match &self.collection {
+            Some(c) => catalog
+                .collection(c)
+                .into_iter()
+                .filter(|r| include_private || !r.private)
+                .collect(),
+            None => catalog.exported_resources(include_private).collect(),
+        };
+        resources
+            .into_iter()
+            .filter(|r| match &matches {
+                Some(m) => m.iter().any(|x| std::ptr::eq(*x, *r)),
+                None => true,
+            })
+            .filter(|r| self.retired || !r.retired)
+            .filter(|r| {
+                self.tags.iter().all(|t| {
+                    r.tags.iter().flatten().any(|x| catalog.tag_matches(x, t))
+                })
+            })
+            .filter(|r| self.content.is_none() || r.content == self.content)
+            .filter(|r| {
+                self.state.is_none()
+                    || catalog.resource_state(r) == self.state.as_deref()
+            })
+            .collect()
+    }
+}
+
+/// Order of search results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchOrder {
+    /// Best matches first.
+    Relevance,
+    /// Most recently added resources first.
+    Added,
+    /// Most recently modified resources first.
+    Modified,
+}
 
 /// Print the path of resources matching a query.
 ///
-pub fn librarian_search(catalog: &Catalog, query: &str) {
-    librarian_fuzzy_search(catalog, query);
+/// When the catalog configures a recent-activity boost, the scores of
+/// recently opened or annotated resources are boosted before ranking.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Search query. An empty query matches every resource.
+/// * `state` - Only print resources in this lifecycle state.
+/// * `notes` - Also print resources whose reading notes contain every
+///   word of the query. They are ranked after the resources whose
+///   fields match.
+/// * `added_since` - Only print resources added at or after this date.
+/// * `modified_since` - Only print resources modified at or after this
+///   date.
+/// * `order` - Order of the printed resources. Resources without the
+///   date they are ordered by come last.
+/// * `retired` - Also print retired resources (see
+///   `Resource::retired`).
+#[allow(clippy::too_many_arguments)]
+pub fn librarian_search(
+    catalog: &Catalog,
+    resources_path: &Path,
+    query: &str,
+    state: Option<&str>,
+    notes: bool,
+    added_since: Option<&DateTime>,
+    modified_since: Option<&DateTime>,
+    order: SearchOrder,
+    retired: bool,
+) {
+    librarian_fuzzy_search(
+        catalog,
+        resources_path,
+        query,
+        state,
+        notes,
+        added_since,
+        modified_since,
+        order,
+        retired,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn librarian_fuzzy_search(
+    catalog: &Catalog,
+    resources_path: &Path,
+    query: &str,
+    state: Option<&str>,
+    notes: bool,
+    added_since: Option<&DateTime>,
+    modified_since: Option<&DateTime>,
+    order: SearchOrder,
+    retired: bool,
+) {
+    let mut matches: Vec<(f64, &Resource)> =
+        scored_search(catalog, query, retired)
+            .into_iter()
+            .map(|(s, r)| (s as f64, r))
+            .collect();
+    if notes {
+        let library = resources_path
+            .parent()
+            .expect("resources path does not have a parent");
+        let note_matches: Vec<&Resource> = catalog
+            .resources
+            .iter()
+            .filter(|r| retired || !r.retired)
+            .filter(|r| !matches.iter().any(|(_, m)| std::ptr::eq(*m, *r)))
+            .filter(|r| notes_match(library, &r.historical_checksums[0], query))
+            .collect();
+        // Field matches score at least 1.
+        matches.extend(note_matches.into_iter().map(|r| (0.5, r)));
+    }
+    matches
+        .retain(|(_, r)| state.is_none() || catalog.resource_state(r) == state);
+    // Dates without a time compare before any time of that day.
+    let since = |date: &Option<DateTime>, since: Option<&DateTime>| {
+        since.is_none() || date.as_ref() >= since
+    };
+    matches.retain(|(_, r)| {
+        since(&r.date_added, added_since)
+            && since(&r.date_modified, modified_since)
+    });
+    if let Some(boost) = &catalog.recent_activity {
+        let library = resources_path
+            .parent()
+            .expect("resources path does not have a parent");
+        let storage = Storage::of(resources_path, catalog);
+        let now = SystemTime::now();
+        for (score, resource) in matches.iter_mut() {
+            let key = &resource.historical_checksums[0];
+            *score *= boost.factor(last_activity(library, &storage, key), now);
+        }
+        matches.sort_by(|(s1, _), (s2, _)| s2.partial_cmp(s1).unwrap());
+    }
+    let date = |r: &Resource| match order {
+        SearchOrder::Added => r.date_added.clone(),
+        _ => r.date_modified.clone(),
+    };
+    if order != SearchOrder::Relevance {
+        matches
+            .sort_by(|(_, a), (_, b)| date(b).partial_cmp(&date(a)).unwrap());
+    }
+    let resources: Vec<&Resource> =
+        matches.into_iter().map(|(_, r)| r).collect();
+    serde_json::to_writer_pretty(std::io::stdout().lock(), &resources).unwrap();
 }
 
-fn librarian_fuzzy_search(catalog: &Catalog, query: &str) {
+/// Resource selected by a checksum or a search query. A checksum
+/// selects its resource directly, and the user picks one of the matches
+/// of an ambiguous query.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `query` - Checksum of the resource or search query matching it.
+/// * `purpose` - What the resource is picked for, which starts the
+///   prompt (e.g., "Resource to write notes for"), in the user's
+///   language.
+///
+/// # Returns
+///
+/// `None` if the user didn't pick a resource.
+pub fn pick_resource<'a>(
+    catalog: &'a Catalog,
+    query: &str,
+    purpose: &str,
+) -> Option<&'a Resource> {
+    let by_checksum = catalog.resources.iter().find(|r| {
+        r.checksum == query || r.historical_checksums.iter().any(|c| c == query)
+    });
+    if by_checksum.is_some() {
+        return by_checksum;
+    }
+    let matches: Vec<&Resource> =
+        search(catalog, query).into_iter().take(10).collect();
+    match matches.len() {
+        0 => panic!("No resource matches {}", query),
+        1 => return Some(matches[0]),
+        _ => (),
+    }
+    for (i, resource) in matches.iter().enumerate() {
+        println!("{:>2}. {}", i + 1, resource.title);
+    }
+    let prompt = message(
+        "pick-prompt",
+        &[("purpose", purpose.into()), ("count", matches.len().into())],
+    );
+    loop {
+        let response = ask(&prompt).unwrap_or_default();
+        if response.is_empty() {
+            println!();
+            return None;
+        }
+        match response.parse::<usize>() {
+            Ok(n) if (1..=matches.len()).contains(&n) => {
+                return Some(matches[n - 1])
+            }
+            _ => continue,
+        }
+    }
+}
+
+/// Cataloged resources matching a query, best matches first. Retired
+/// resources never match.
+pub fn search<'a>(catalog: &'a Catalog, query: &str) -> Vec<&'a Resource> {
+    scored_search(catalog, query, false)
+        .into_iter()
+        .map(|(_, r)| r)
+        .collect()
+}
+
+/// Names of the qualifiers a search query can contain (see
+/// `qualifier_matches`).
+const QUALIFIERS: [&str; 3] = ["status", "priority", "rating"];
+
+/// Whether a resource meets a qualifier of a search query, written as
+/// "name:value". Qualifiers select resources by their reading status
+/// (e.g., "status:unread"), priority (e.g., "priority:high") or rating,
+/// which can be compared (e.g., "rating:>=4"). Unrated resources never
+/// meet a rating qualifier.
+fn qualifier_matches(resource: &Resource, qualifier: &str) -> bool {
+    let (name, value) = qualifier.split_once(':').unwrap();
+    match name {
+        "status" => resource.read_status().name() == value,
+        "priority" => resource.priority().name() == value,
+        "rating" => {
+            let (op, n) = value.split_at(
+                value.find(|c: char| c.is_ascii_digit()).unwrap_or(0),
+            );
+            match (resource.rating, n.parse::<u8>()) {
+                (Some(r), Ok(n)) => match op {
+                    ">=" => r >= n,
+                    "<=" => r <= n,
+                    ">" => r > n,
+                    "<" => r < n,
+                    "" | "=" => r == n,
+                    _ => false,
+                },
+                _ => false,
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Cataloged resources matching a query along with their match score,
+/// best matches first.
+///
+/// Qualifiers in the query (see `qualifier_matches`) select resources,
+/// and the rest of the query is matched against their fields. A query
+/// of only qualifiers gives every selected resource the same score.
+/// Retired resources are only searched when `retired` is set.
+fn scored_search<'a>(
+    catalog: &'a Catalog,
+    query: &str,
+    retired: bool,
+) -> Vec<(i64, &'a Resource)> {
+    let (qualifiers, words): (Vec<&str>, Vec<&str>) =
+        query.split_whitespace().partition(|w| {
+            w.split_once(':')
+                .is_some_and(|(name, _)| QUALIFIERS.contains(&name))
+        });
+    let query = words.join(" ");
     let mut matching_resources: Vec<(i64, &Resource)> = std::vec!();
     // TODO I don't like ignoring case, because I'd like it to be
     // considered. However, results with the wrong case seem to be
@@ -19,45 +325,150 @@ fn librarian_fuzzy_search(catalog: &Catalog, query: &str) {
     // TODO I expect there's a more efficient way to do this by
     // inserting each new element into the vector to keep it sorted,
     // rather than inserting all elements and sorting at the end.
-    catalog.resources.iter().for_each(|r| {
-        let score = matcher.fuzzy_match(
-            &r.concat_fields(vec![
-                "title",
-                "subtitle",
-                "author",
-                "editor",
-                "date",
-                "edition",
-                "version",
-                "publisher",
-                "organization",
-                "journal",
-                "volume",
-                "number",
-                "part_number",
-                "doi",
-                "tags",
-                "document",
-                "content",
-                "url",
-                "checksum",
-                "historical_checksums",
-            ]),
-            query,
-        );
-        match score {
-            Some(s) => {
-                if s > 0 {
-                    matching_resources.push((s, r));
+    catalog
+        .resources
+        .iter()
+        .filter(|r| retired || !r.retired)
+        .filter(|r| qualifiers.iter().all(|q| qualifier_matches(r, q)))
+        .for_each(|r| {
+            if query.is_empty() {
+                matching_resources.push((1, r));
+                return;
+            }
+            // Include the ancestors of each tag, so that a query for a parent
+            // tag matches resources tagged with its children.
+            let ancestors: Vec<&str> = r
+                .tags
+                .iter()
+                .flatten()
+                .flat_map(|t| catalog.tag_ancestors(t).into_iter().skip(1))
+                .collect();
+            let score = matcher.fuzzy_match(
+                &(r.concat_fields(vec![
+                    "title",
+                    "subtitle",
+                    "author",
+                    "editor",
+                    "date",
+                    "edition",
+                    "version",
+                    "publisher",
+                    "organization",
+                    "journal",
+                    "volume",
+                    "series",
+                    "number",
+                    "part_number",
+                    "pages",
+                    "chapter",
+                    "doi",
+                    "isbn",
+                    "issn",
+                    "eprint",
+                    "eprinttype",
+                    "eprintclass",
+                    "abstract",
+                    "keywords",
+                    "language",
+                    "tags",
+                    "document",
+                    "content",
+                    "url",
+                    "archived",
+                    "archive_url",
+                    "citekey",
+                    "state",
+                    "checksum",
+                    "historical_checksums",
+                    "extra",
+                ]) + " "
+                    + &ancestors.join(" ")),
+                &query,
+            );
+            match score {
+                Some(s) => {
+                    if s > 0 {
+                        matching_resources.push((s, r));
+                    }
                 }
+                None => (),
             }
-            None => (),
-        }
-    });
+        });
 
     matching_resources.sort_by(|(s1, _), (s2, _)| s2.partial_cmp(&s1).unwrap());
-    let resources: Vec<&Resource> =
-        matching_resources.iter().map(|(_, r)| r).cloned().collect();
+    matching_resources
+}
 
-    serde_json::to_writer_pretty(std::io::stdout().lock(), &resources).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_filter() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"tags\": [\"rf\", \"pll\"], \"content\": \"paper\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"tags\": [\"rf\"], \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"C\", \"tags\": [\"rf\"], \"private\": true, \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let titles = |filter: ResourceFilter, include_private| {
+            filter
+                .apply(&catalog, include_private)
+                .iter()
+                .map(|r| r.title.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let rf = ResourceFilter {
+            tags: vec![String::from("rf")],
+            ..Default::default()
+        };
+        assert!(titles(rf.clone(), false) == vec!["A", "B"]);
+        assert!(titles(rf, true) == vec!["A", "B", "C"]);
+        let rf_pll = ResourceFilter {
+            tags: vec![String::from("rf"), String::from("pll")],
+            ..Default::default()
+        };
+        assert!(titles(rf_pll, false) == vec!["A"]);
+        let paper = ResourceFilter {
+            content: Some(String::from("paper")),
+            ..Default::default()
+        };
+        assert!(titles(paper, false) == vec!["A"]);
+        let mut catalog = catalog.clone();
+        catalog.create_tag("engineering/rf");
+        let engineering = ResourceFilter {
+            tags: vec![String::from("engineering")],
+            ..Default::default()
+        };
+        assert!(engineering.apply(&catalog, false).len() == 2);
+    }
+
+    #[test]
+    fn test_qualifiers() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"Phase Noise\", \"read_status\": \"read\", \"rating\": 5, \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Phase Locked Loops\", \"rating\": 3, \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+              {\"title\": \"Antennas\", \"priority\": \"high\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}
+            ]}",
+        )
+        .unwrap();
+        let titles = |query| {
+            search(&catalog, query)
+                .iter()
+                .map(|r| r.title.clone())
+                .collect::<Vec<String>>()
+        };
+        // Resources without a status are unread.
+        assert!(titles("status:unread phase") == vec!["Phase Locked Loops"]);
+        assert!(titles("status:unread").len() == 2);
+        assert!(titles("rating:>=4") == vec!["Phase Noise"]);
+        assert!(
+            titles("rating:3 priority:normal") == vec!["Phase Locked Loops"]
+        );
+        assert!(titles("priority:high") == vec!["Antennas"]);
+    }
 }
@@ -0,0 +1,564 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::fulltext::{text_path, thumbnail_path, Indexer};
+use crate::history::commit_catalog;
+use crate::i18n::message;
+use crate::opds::{
+    acquisition_feed, media_type, navigation_feed, ACQUISITION_TYPE,
+    NAVIGATION_TYPE,
+};
+use crate::provider::set_fields;
+use crate::resource::Resource;
+use crate::search::search;
+use crate::storage::Storage;
+
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tiny_http::{Header, Response, Server};
+
+/// Address `librarian serve` listens on unless another is given. Only
+/// local clients can reach it.
+pub const SERVE_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Response to a request.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Json(u16, Value),
+    Bibtex(String),
+    /// OPDS feed and its media type.
+    Feed(&'static str, String),
+    /// File and its media type.
+    File(PathBuf, String),
+}
+
+/// Error response, with the error as JSON.
+fn error(status: u16, text: &str) -> Reply {
+    Reply::Json(status, json!({ "error": text }))
+}
+
+/// Whether a request's Host header names the server, so that a web
+/// page can't reach the library through a domain of its own that
+/// resolves to the server's address (DNS rebinding). The host must be
+/// an IP address, localhost or the host the server was told to listen
+/// on. The port isn't checked, since a rebound domain already fails.
+///
+/// # Arguments
+///
+/// * `host` - Value of the Host header.
+/// * `address` - Address the server listens on, as given.
+fn allowed_host(host: &str, address: &str) -> bool {
+    // Strip the port, but not the colons of an IPv6 address.
+    let name = |h: &str| {
+        match h.rsplit_once(':') {
+            Some((n, p))
+                if !n.is_empty()
+                    && !p.is_empty()
+                    && p.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                n.to_string()
+            }
+            _ => h.to_string(),
+        }
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_lowercase()
+    };
+    let host = name(host);
+    host.parse::<IpAddr>().is_ok()
+        || host == "localhost"
+        || host == name(address)
+}
+
+/// Library a server answers for.
+struct Library<'a> {
+    store: &'a mut CatalogStore,
+    catalog_path: &'a Path,
+    resources_path: &'a Path,
+    /// Accept requests that change the catalog.
+    write: bool,
+    include_private: bool,
+    /// Worker indexing the resources, if indexing.
+    indexer: Option<Indexer>,
+}
+
+impl Library<'_> {
+    /// Resources matching the `q` parameter of a query string, all of
+    /// them if it's missing.
+    fn select<'c>(
+        &self,
+        catalog: &'c Catalog,
+        query: &str,
+    ) -> Vec<&'c Resource> {
+        let q = url::form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == "q")
+            .map(|(_, v)| v.into_owned())
+            .unwrap_or_default();
+        search(catalog, &q)
+            .into_iter()
+            .filter(|r| self.include_private || !r.private)
+            .collect()
+    }
+
+    /// Answer a request. The catalog is read again for every request,
+    /// so that changes made by other commands are seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - HTTP method.
+    /// * `url` - Path and query string of the request.
+    /// * `body` - Body of the request.
+    fn respond(&mut self, method: &str, url: &str, body: &str) -> Reply {
+        let mut catalog = self.store.read();
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<&str> =
+            path.split('/').filter(|s| !s.is_empty()).collect();
+        let include_private = self.include_private;
+        let find = move |catalog: &Catalog, key: &str| {
+            catalog
+                .resources
+                .iter()
+                .position(|r| {
+                    (include_private || !r.private)
+                        && r.historical_checksums.iter().any(|c| c == key)
+                })
+                .ok_or_else(|| error(404, &format!("No resource {}", key)))
+        };
+        match (method, segments.as_slice()) {
+            ("GET", ["resources"]) => Reply::Json(
+                200,
+                serde_json::to_value(self.select(&catalog, query)).unwrap(),
+            ),
+            ("GET", ["resources", key]) => match find(&catalog, key) {
+                Ok(i) => Reply::Json(
+                    200,
+                    serde_json::to_value(&catalog.resources[i]).unwrap(),
+                ),
+                Err(e) => e,
+            },
+            ("GET", ["resources", key, "file"]) => match find(&catalog, key) {
+                Ok(i) => {
+                    let path = Storage::of(self.resources_path, &catalog)
+                        .remote(catalog.remote_storage.as_deref())
+                        .fetch(&catalog.resources[i].historical_checksums[0]);
+                    match path.is_file() {
+                        true => Reply::File(
+                            path,
+                            media_type(&catalog, &catalog.resources[i]),
+                        ),
+                        false => error(404, &format!("{} has no file", key)),
+                    }
+                }
+                Err(e) => e,
+            },
+            ("GET", ["resources", key, kind @ ("text" | "thumbnail")]) => {
+                let i = match find(&catalog, key) {
+                    Ok(i) => i,
+                    Err(e) => return e,
+                };
+                let library = self
+                    .resources_path
+                    .parent()
+                    .expect("resources path does not have a parent");
+                let key = &catalog.resources[i].historical_checksums[0];
+                let (path, media_type) = match *kind {
+                    "text" => {
+                        (text_path(library, key), "text/plain; charset=utf-8")
+                    }
+                    _ => (thumbnail_path(library, key), "image/png"),
+                };
+                match path.is_file() {
+                    true => Reply::File(path, media_type.to_string()),
+                    false => error(404, &format!("{} has no {}", key, kind)),
+                }
+            }
+            ("GET", ["index"]) => match &self.indexer {
+                Some(indexer) => {
+                    let (indexed, queued) = indexer.progress();
+                    Reply::Json(
+                        200,
+                        json!({ "indexed": indexed, "queued": queued }),
+                    )
+                }
+                None => error(404, "The server isn't indexing"),
+            },
+            ("GET", ["bibtex"]) => {
+                let storage = Storage::of(self.resources_path, &catalog);
+                Reply::Bibtex(
+                    self.select(&catalog, query)
+                        .iter()
+                        .map(|r| {
+                            r.serialize_bibtex(
+                                &catalog.content_types,
+                                &[storage
+                                    .resource_path(&r.historical_checksums[0])],
+                                false,
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            ("PATCH", ["resources", _]) if !self.write => {
+                error(403, "The server is read-only")
+            }
+            ("PATCH", ["resources", key]) => {
+                let i = match find(&catalog, key) {
+                    Ok(i) => i,
+                    Err(e) => return e,
+                };
+                let fields: serde_json::Map<String, Value> =
+                    match serde_json::from_str(body) {
+                        Ok(f) => f,
+                        Err(e) => return error(400, &e.to_string()),
+                    };
+                let resource = match set_fields(&catalog.resources[i], &fields)
+                {
+                    Ok(r) => r,
+                    Err(e) => return error(400, &e),
+                };
+                if let Some(tags) = &resource.tags {
+                    if !catalog.tags.is_empty() {
+                        if let Err(e) = catalog.validate_tags(tags, false) {
+                            return error(400, &e);
+                        }
+                    }
+                }
+                let key = resource.historical_checksums[0].clone();
+                catalog.resources[i] = resource;
+                catalog.sort();
+                self.store.write(&mut catalog);
+                let library = self
+                    .resources_path
+                    .parent()
+                    .expect("resources path does not have a parent");
+                if !catalog.dry_run {
+                    record(
+                        library,
+                        &AuditEntry::new(
+                            "serve",
+                            vec![key.clone()],
+                            fields
+                                .keys()
+                                .map(|f| format!("set {}", f))
+                                .collect(),
+                        ),
+                    );
+                }
+                if catalog.git {
+                    commit_catalog(
+                        library,
+                        self.catalog_path,
+                        "serve",
+                        &catalog,
+                    );
+                }
+                match find(&catalog, &key) {
+                    Ok(i) => Reply::Json(
+                        200,
+                        serde_json::to_value(&catalog.resources[i]).unwrap(),
+                    ),
+                    Err(e) => e,
+                }
+            }
+            ("GET", ["opds"]) => {
+                Reply::Feed(NAVIGATION_TYPE, navigation_feed(&catalog))
+            }
+            ("GET", ["opds", "all"]) => {
+                let resources = self.select(&catalog, "");
+                Reply::Feed(
+                    ACQUISITION_TYPE,
+                    acquisition_feed(
+                        &catalog,
+                        path,
+                        "All resources",
+                        &resources,
+                    ),
+                )
+            }
+            ("GET", ["opds", kind @ ("tags" | "collections"), name]) => {
+                let name = percent_decode_str(name).decode_utf8_lossy();
+                let resources: Vec<&Resource> = match *kind {
+                    "tags" if catalog.tags.contains_key(name.as_ref()) => self
+                        .select(&catalog, "")
+                        .into_iter()
+                        .filter(|r| {
+                            r.tags
+                                .iter()
+                                .flatten()
+                                .any(|t| catalog.tag_matches(t, &name))
+                        })
+                        .collect(),
+                    "collections"
+                        if catalog.collections.contains_key(name.as_ref()) =>
+                    {
+                        catalog
+                            .collection(&name)
+                            .into_iter()
+                            .filter(|r| include_private || !r.private)
+                            .collect()
+                    }
+                    _ => {
+                        let kind = kind.trim_end_matches('s');
+                        return error(404, &format!("No {} {}", kind, name));
+                    }
+                };
+                let title = match *kind {
+                    "tags" => catalog.tag_path(&name),
+                    _ => name.to_string(),
+                };
+                Reply::Feed(
+                    ACQUISITION_TYPE,
+                    acquisition_feed(&catalog, path, &title, &resources),
+                )
+            }
+            (
+                _,
+                ["resources"]
+                | ["resources", _]
+                | ["resources", _, "file" | "text" | "thumbnail"]
+                | ["index"]
+                | ["bibtex"]
+                | ["opds", ..],
+            ) => error(405, "Method not allowed"),
+            _ => error(404, "Not found"),
+        }
+    }
+}
+
+/// Serve the library over HTTP, so that other programs, such as
+/// browser extensions and editors, can use it without parsing the
+/// catalog themselves.
+///
+/// The API answers `GET /resources?q=<query>` with the resources
+/// matching a search query as JSON, every resource when the query is
+/// missing, `GET /resources/<checksum>` with a single resource,
+/// `GET /resources/<checksum>/file` with its file and
+/// `GET /bibtex?q=<query>` with the BibTeX entries of the matching
+/// resources. With `write`, `PATCH /resources/<checksum>` sets the
+/// fields of a resource given as a JSON object.
+///
+/// `GET /opds` is an OPDS catalog for e-reader apps, leading to feeds of
+/// every resource (`/opds/all`), of the resources with a tag or one of
+/// its descendants (`/opds/tags/<tag>`) and of the resources of a
+/// collection (`/opds/collections/<name>`).
+///
+/// With `index`, the resources are indexed by a background worker (see
+/// `Indexer`) while the server answers requests. `GET /index` reports
+/// its progress. `GET /resources/<checksum>/text` answers with the text
+/// of a resource and `GET /resources/<checksum>/thumbnail` with its
+/// thumbnail, once indexed, here or by `librarian watch`.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog_path` - Location of the stored catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `address` - Address and port to listen on.
+/// * `write` - Accept requests that change the catalog.
+/// * `include_private` - Include private resources.
+/// * `index` - Index resources in the background, pausing this long
+///   after each one.
+pub fn librarian_serve(
+    store: &mut CatalogStore,
+    catalog_path: &Path,
+    resources_path: &Path,
+    address: &str,
+    write: bool,
+    include_private: bool,
+    index: Option<Duration>,
+) {
+    let server = Server::http(address)
+        .unwrap_or_else(|e| panic!("Failed to listen on {}: {}", address, e));
+    let indexer = index.map(|delay| {
+        let indexer = Indexer::spawn(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            delay,
+        );
+        let catalog = store.read();
+        let keys: Vec<String> = catalog
+            .resources
+            .iter()
+            .filter(|r| include_private || !r.private)
+            .map(|r| r.historical_checksums[0].clone())
+            .collect();
+        indexer.queue(&catalog, &Storage::of(resources_path, &catalog), &keys);
+        indexer
+    });
+    let mut library = Library {
+        store,
+        catalog_path,
+        resources_path,
+        write,
+        include_private,
+        indexer,
+    };
+    println!(
+        "{}",
+        message(
+            match write {
+                true => "serve-listening-write",
+                false => "serve-listening",
+            },
+            &[("address", address.into())]
+        )
+    );
+    for mut request in server.incoming_requests() {
+        let host = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Host"))
+            .map(|h| h.value.to_string());
+        let mut body = String::new();
+        let reply = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) if host.is_some_and(|h| !allowed_host(&h, address)) => {
+                error(403, "Host not allowed")
+            }
+            Ok(_) => {
+                library.respond(request.method().as_str(), request.url(), &body)
+            }
+            Err(e) => error(400, &e.to_string()),
+        };
+        let content_type =
+            |t: &str| Header::from_bytes("Content-Type", t).unwrap();
+        let sent = match reply {
+            Reply::Json(status, value) => request.respond(
+                Response::from_string(value.to_string())
+                    .with_status_code(status)
+                    .with_header(content_type("application/json")),
+            ),
+            Reply::Bibtex(text) => request.respond(
+                Response::from_string(text)
+                    .with_header(content_type("application/x-bibtex")),
+            ),
+            Reply::Feed(media_type, feed) => request.respond(
+                Response::from_string(feed)
+                    .with_header(content_type(media_type)),
+            ),
+            Reply::File(path, media_type) => match File::open(&path) {
+                Ok(file) => request.respond(
+                    Response::from_file(file)
+                        .with_header(content_type(&media_type)),
+                ),
+                Err(e) => request.respond(
+                    Response::from_string(e.to_string()).with_status_code(500),
+                ),
+            },
+        };
+        if let Err(e) = sent {
+            eprintln!(
+                "{}",
+                message(
+                    "serve-respond-failed",
+                    &[("error", e.to_string().into())]
+                )
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-serve-{}", std::process::id()));
+        let resources = library.join("resources");
+        std::fs::create_dir_all(&resources).unwrap();
+        std::fs::write(resources.join("aa"), "a").unwrap();
+        let catalog_path = library.join("catalog.json");
+        std::fs::write(
+            &catalog_path,
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"Phase Noise\", \"checksum\": \"a2\", \"historical_checksums\": [\"aa\", \"a2\"]},
+              {\"title\": \"Diary\", \"private\": true, \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let mut store = CatalogStore::open(&catalog_path);
+        let mut server = Library {
+            store: &mut store,
+            catalog_path: &catalog_path,
+            resources_path: &resources,
+            write: false,
+            include_private: false,
+            indexer: None,
+        };
+
+        let titles = |reply: Reply| match reply {
+            Reply::Json(200, Value::Array(resources)) => resources
+                .iter()
+                .map(|r| r["title"].as_str().unwrap().to_string())
+                .collect::<Vec<String>>(),
+            _ => panic!("Unexpected reply"),
+        };
+        // Private resources are hidden.
+        assert!(
+            titles(server.respond("GET", "/resources", "")) == ["Phase Noise"]
+        );
+        assert!(
+            titles(server.respond("GET", "/resources?q=diary", "")).is_empty()
+        );
+        assert!(
+            server.respond("GET", "/resources/a2/file", "")
+                == Reply::File(
+                    resources.join("aa"),
+                    "application/octet-stream".to_string()
+                )
+        );
+        assert!(matches!(
+            server.respond("GET", "/resources/bb", ""),
+            Reply::Json(404, _)
+        ));
+        assert!(matches!(
+            server.respond("GET", "/resources/aa/text", ""),
+            Reply::Json(404, _)
+        ));
+        std::fs::create_dir_all(library.join(".text")).unwrap();
+        std::fs::write(library.join(".text/aa.txt"), "phase noise").unwrap();
+        assert!(
+            server.respond("GET", "/resources/a2/text", "")
+                == Reply::File(
+                    library.join(".text/aa.txt"),
+                    "text/plain; charset=utf-8".to_string()
+                )
+        );
+        assert!(matches!(
+            server.respond("GET", "/index", ""),
+            Reply::Json(404, _)
+        ));
+        let patch = "{\"rating\": 4}";
+        assert!(matches!(
+            server.respond("PATCH", "/resources/aa", patch),
+            Reply::Json(403, _)
+        ));
+        server.write = true;
+        assert!(matches!(
+            server.respond("PATCH", "/resources/aa", "{\"checksum\": \"cc\"}"),
+            Reply::Json(400, _)
+        ));
+        assert!(matches!(
+            server.respond("PATCH", "/resources/aa", patch),
+            Reply::Json(200, _)
+        ));
+        assert!(store.read().resources[1].rating == Some(4));
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn test_allowed_host() {
+        assert!(allowed_host("127.0.0.1:8080", SERVE_ADDRESS));
+        assert!(allowed_host("LOCALHOST:8080", SERVE_ADDRESS));
+        assert!(allowed_host("[::1]:8080", "[::1]:8080"));
+        assert!(allowed_host("192.168.1.20:8080", "0.0.0.0:8080"));
+        assert!(allowed_host("books.lan", "books.lan:80"));
+        assert!(!allowed_host("attacker.example:8080", SERVE_ADDRESS));
+        assert!(!allowed_host("localhost.attacker.example", SERVE_ADDRESS));
+    }
+}
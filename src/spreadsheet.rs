@@ -0,0 +1,310 @@
+use crate::bibtex::{latex_escape, BibtexEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::import::{bibtex_month, import_entries};
+
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Conversion applied to the value of a column before it's imported.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// List of names separated by `separator` (";" by default), each
+    /// written as "First Last" or "Last, First".
+    Names,
+    /// Date written according to `format`, in which "%Y" is the year,
+    /// "%m" the month number, "%b" the (abbreviated) English month name
+    /// and "%d" the day, e.g., "%d.%m.%Y".
+    Date,
+    /// List of values separated by `separator` ("," by default), e.g.,
+    /// keywords.
+    List,
+}
+
+/// Column of a spreadsheet mapped to a field along with a transform.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct TransformedColumn {
+    pub field: String,
+    pub transform: Transform,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+/// Field a spreadsheet column is imported into.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ColumnMapping {
+    /// Name of the field, for values imported as written.
+    Field(String),
+    Transformed(TransformedColumn),
+}
+
+/// Mapping of the columns of a CSV file exported from some source, such
+/// as a lab inventory spreadsheet, to resource fields.
+///
+/// Fields are named as in BibTeX (e.g., "author", "journal", "keywords",
+/// "file"), along with "key" for the citation key and "type" for the
+/// BibTeX entry type. Columns that aren't mapped are ignored.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ImportProfile {
+    /// Character separating the columns, e.g., "\t" for files exported
+    /// as tab-separated values.
+    #[serde(default = "ImportProfile::default_delimiter")]
+    pub delimiter: char,
+    /// BibTeX entry type of rows without a "type" column.
+    #[serde(default = "ImportProfile::default_entry_type")]
+    pub entry_type: String,
+    /// Mapping keyed by column header.
+    pub columns: IndexMap<String, ColumnMapping>,
+}
+
+impl ImportProfile {
+    fn default_delimiter() -> char {
+        ','
+    }
+
+    fn default_entry_type() -> String {
+        String::from("misc")
+    }
+
+    /// BibTeX entry described by a row of the spreadsheet.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - Column headers.
+    /// * `row` - Values of the row.
+    ///
+    /// # Returns
+    ///
+    /// An error describing a value that can't be transformed.
+    fn entry(
+        &self,
+        headers: &csv::StringRecord,
+        row: &csv::StringRecord,
+    ) -> Result<BibtexEntry, String> {
+        let mut entry = BibtexEntry {
+            entry_type: self.entry_type.clone(),
+            key: String::new(),
+            fields: IndexMap::new(),
+        };
+        for (header, value) in headers.iter().zip(row.iter()) {
+            let value = value.trim();
+            let mapping = match self.columns.get(header) {
+                Some(m) if !value.is_empty() => m,
+                _ => continue,
+            };
+            let (field, value) = match mapping {
+                ColumnMapping::Field(f) => (f, value.to_string()),
+                ColumnMapping::Transformed(t) => (&t.field, t.apply(value)?),
+            };
+            match field.as_str() {
+                "key" => entry.key = value,
+                "type" => entry.entry_type = value.to_lowercase(),
+                // Other values are escaped so that they are read back
+                // verbatim.
+                "file" | "url" | "doi" => {
+                    entry.fields.insert(field.clone(), value);
+                }
+                _ => {
+                    let escaped = latex_escape(&value).unwrap_or(value);
+                    entry.fields.insert(field.clone(), escaped);
+                }
+            }
+        }
+        Ok(entry)
+    }
+}
+
+impl TransformedColumn {
+    /// Transform the value of a column.
+    fn apply(&self, value: &str) -> Result<String, String> {
+        let split = |default: &str| -> Vec<String> {
+            value
+                .split(self.separator.as_deref().unwrap_or(default))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        };
+        match self.transform {
+            // Names keep their "Last, First" form, which is also how
+            // BibTeX writes them.
+            Transform::Names => Ok(split(";").join(" and ")),
+            Transform::List => Ok(split(",").join(", ")),
+            Transform::Date => {
+                let format = self.format.as_deref().ok_or_else(|| {
+                    format!("The date column for {} has no format", self.field)
+                })?;
+                parse_date(value, format).ok_or_else(|| {
+                    format!(
+                        "{} doesn't match the date format {}",
+                        value, format
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// Convert a date written according to a format (see `Transform::Date`)
+/// to the catalog's "YYYY-MM-DD" form, or a prefix of it when the format
+/// lacks the day or month.
+fn parse_date(value: &str, format: &str) -> Option<String> {
+    let (mut year, mut month, mut day) = (None, None, None);
+    let mut rest = value.trim();
+    let mut format = format.chars();
+    while let Some(c) = format.next() {
+        if c != '%' {
+            rest = rest.strip_prefix(c)?;
+            continue;
+        }
+        let specifier = format.next()?;
+        let len = match specifier {
+            'b' | 'B' => rest
+                .find(|c: char| !c.is_alphabetic())
+                .unwrap_or(rest.len()),
+            'Y' => 4,
+            _ => rest
+                .char_indices()
+                .take(2)
+                .take_while(|(_, c)| c.is_ascii_digit())
+                .count(),
+        };
+        let (component, remaining) = (rest.get(..len)?, &rest[len..]);
+        rest = remaining;
+        match specifier {
+            'Y' => year = Some(component.parse::<u32>().ok()?),
+            'm' => month = Some(component.parse::<u32>().ok()?),
+            'd' => day = Some(component.parse::<u32>().ok()?),
+            'b' | 'B' => month = Some(bibtex_month(component)?),
+            _ => return None,
+        }
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    match (year?, month, day) {
+        (y, Some(m), Some(d)) => Some(format!("{:04}-{:02}-{:02}", y, m, d)),
+        (y, Some(m), None) => Some(format!("{:04}-{:02}", y, m)),
+        (y, _, _) => Some(format!("{:04}", y)),
+    }
+}
+
+/// Create or update catalog resources from the rows of a CSV file,
+/// using one of the catalog's import profiles to map its columns to
+/// resource fields.
+///
+/// This behaves like `librarian_import_bibtex`, with the "key" column
+/// taking the place of the BibTeX key and the "file" column the place
+/// of the file field.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `csv_file_path` - CSV file to import.
+/// * `profile` - Name of the import profile.
+/// * `interactive` - Prompt for the file of rows without a valid file
+///   column.
+pub fn librarian_import_csv(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    csv_file_path: &Path,
+    profile: &str,
+    interactive: bool,
+) {
+    let profile = catalog
+        .import_profiles
+        .get(profile)
+        .unwrap_or_else(|| panic!("No import profile {}", profile))
+        .clone();
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(profile.delimiter as u8)
+        .flexible(true)
+        .from_path(csv_file_path)
+        .unwrap_or_else(|e| {
+            panic!("Failed to read {}: {}", csv_file_path.display(), e)
+        });
+    let headers = reader
+        .headers()
+        .expect("Failed to read CSV headers")
+        .clone();
+    for column in profile.columns.keys() {
+        if !headers.iter().any(|h| h == column) {
+            eprintln!(
+                "{}",
+                message(
+                    "import-csv-missing-column",
+                    &[("column", column.as_str().into())]
+                )
+            );
+        }
+    }
+    let entries = reader
+        .records()
+        .enumerate()
+        .map(|(i, row)| {
+            let row = row.unwrap_or_else(|e| {
+                panic!("Failed to read row {}: {}", i + 2, e)
+            });
+            profile
+                .entry(&headers, &row)
+                .unwrap_or_else(|e| panic!("Row {}: {}", i + 2, e))
+        })
+        .collect();
+    import_entries(
+        catalog,
+        resources_path,
+        entries,
+        csv_file_path.parent().unwrap_or(Path::new(".")),
+        interactive,
+    );
+    catalog.sort();
+    catalog_store.write(catalog);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_profile() {
+        let profile: ImportProfile = serde_json::from_str(
+            "{\"delimiter\": \";\", \"entry_type\": \"manual\", \"columns\": {
+              \"Item\": \"title\",
+              \"Authors\": {\"field\": \"author\", \"transform\": \"names\", \"separator\": \"/\"},
+              \"Acquired\": {\"field\": \"date\", \"transform\": \"date\", \"format\": \"%d.%m.%Y\"},
+              \"Keywords\": {\"field\": \"keywords\", \"transform\": \"list\", \"separator\": \"|\"}
+            }}",
+        )
+        .unwrap();
+        let headers = csv::StringRecord::from(vec![
+            "Item", "Authors", "Acquired", "Keywords", "Room",
+        ]);
+        let row = csv::StringRecord::from(vec![
+            "Schrödinger Probes",
+            "Hajimiri, Ali / Thomas H. Lee",
+            "3.2.1998",
+            "rf|test equipment",
+            "B12",
+        ]);
+        let entry = profile.entry(&headers, &row).unwrap();
+        assert!(entry.entry_type == "manual");
+        assert!(entry.fields["title"] == "Schr{\\\"o}dinger Probes");
+        assert!(entry.fields["author"] == "Hajimiri, Ali and Thomas H. Lee");
+        assert!(entry.fields["date"] == "1998-02-03");
+        assert!(entry.fields["keywords"] == "rf, test equipment");
+        assert!(!entry.fields.contains_key("Room"));
+
+        assert!(
+            parse_date("Feb 1998", "%b %Y") == Some(String::from("1998-02"))
+        );
+        assert!(parse_date("1998-02", "%d.%m.%Y").is_none());
+    }
+}
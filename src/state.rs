@@ -0,0 +1,101 @@
+use crate::catalog::{Catalog, CatalogStore};
+use crate::resource::Resource;
+
+impl Catalog {
+    /// Lifecycle state of a resource. Resources without a state are in
+    /// the first state of the catalog's workflow.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the resource doesn't have a state and the catalog
+    /// doesn't define a workflow.
+    pub fn resource_state<'a>(
+        &'a self,
+        resource: &'a Resource,
+    ) -> Option<&'a str> {
+        resource
+            .state
+            .as_deref()
+            .or_else(|| self.states.keys().next().map(String::as_str))
+    }
+
+    /// Whether a resource may move from one state to another according
+    /// to the catalog's workflow. Any transition is allowed when the
+    /// catalog doesn't define a workflow.
+    pub fn transition_allowed(&self, from: Option<&str>, to: &str) -> bool {
+        if self.states.is_empty() {
+            return true;
+        }
+        match from {
+            Some(f) => self
+                .states
+                .get(f)
+                .is_some_and(|next| next.iter().any(|n| n == to)),
+            None => self.states.contains_key(to),
+        }
+    }
+}
+
+/// Move a resource to another lifecycle state.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `checksum` - Current or historical checksum of the resource.
+/// * `state` - New state.
+/// * `force` - Allow transitions the catalog's workflow doesn't permit.
+pub fn librarian_state_set(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    checksum: &str,
+    state: &str,
+    force: bool,
+) {
+    if !catalog.states.is_empty() && !catalog.states.contains_key(state) {
+        panic!("Unknown state {}", state);
+    }
+    let resource = catalog
+        .resources
+        .iter()
+        .find(|r| {
+            r.checksum == checksum
+                || r.historical_checksums.iter().any(|c| c == checksum)
+        })
+        .unwrap_or_else(|| panic!("No resource with checksum {}", checksum));
+    let current = catalog.resource_state(resource).map(String::from);
+    if !force && !catalog.transition_allowed(current.as_deref(), state) {
+        panic!(
+            "A resource can't move from {} to {}. Pass --force to move it anyway.",
+            current.as_deref().unwrap_or("no state"),
+            state
+        );
+    }
+    catalog.resource_mut(checksum).unwrap().state = Some(state.to_string());
+    store.write(catalog);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition_allowed() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"states\": {\"new\": [\"triaged\"], \"triaged\": [\"reviewed\", \"archived\"], \"reviewed\": [\"archived\"], \"archived\": []},
+              \"resources\": [
+              {\"title\": \"Notes\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"Phase Noise\", \"state\": \"reviewed\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        assert!(catalog.resource_state(&catalog.resources[0]) == Some("new"));
+        assert!(
+            catalog.resource_state(&catalog.resources[1]) == Some("reviewed")
+        );
+        assert!(catalog.transition_allowed(Some("new"), "triaged"));
+        assert!(!catalog.transition_allowed(Some("new"), "reviewed"));
+        assert!(!catalog.transition_allowed(Some("archived"), "new"));
+    }
+}
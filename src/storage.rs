@@ -0,0 +1,790 @@
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::journal;
+use crate::remote::connect;
+use crate::search::pick_resource;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::{DirEntry, WalkDir};
+
+/// Directory of the resources directory holding the attachments of
+/// resources, in a subdirectory per resource named by its initial
+/// checksum.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Directory of the resources directory holding the revisions of the
+/// files of resources (see `Catalog::keep_revisions`).
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// File marking a directory in a subdirectory of the resources
+/// directory as a resource, such as a saved webpage, instead of a folder
+/// organizing other resources.
+pub const RESOURCE_MARKER: &str = ".resource";
+
+/// Naming scheme of the files in a library's resources directory.
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Eq,
+    PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLayout {
+    /// Every resource is stored directly in the resources directory,
+    /// named by its initial checksum.
+    #[default]
+    Flat,
+    /// Resources are stored in subdirectories named by the first two
+    /// characters of their initial checksum (e.g., `ab/cdef...`). This
+    /// keeps directories small for large libraries, which some
+    /// filesystems handle poorly.
+    Sharded,
+}
+
+impl StorageLayout {
+    pub fn is_flat(&self) -> bool {
+        *self == StorageLayout::Flat
+    }
+}
+
+/// Resources directory of a library. All paths to resource files
+/// should be resolved through this.
+pub struct Storage {
+    root: PathBuf,
+    layout: StorageLayout,
+    /// Leave files where they are when storing or replacing resources.
+    dry_run: bool,
+    /// Catalog the targets of symbolic links in the resources directory
+    /// instead of skipping them.
+    follow_symlinks: bool,
+    /// Location of the remote storage holding the files that aren't on
+    /// the local disk (see `Catalog::remote_storage`).
+    remote: Option<String>,
+    /// Whether file names keep the extension of their document type
+    /// (see `Catalog::file_extensions`).
+    keep_extensions: bool,
+    /// Extension of the file of each resource whose document type is
+    /// known, by initial checksum, when file names keep their extension.
+    extensions: HashMap<String, String>,
+    /// Extension of each document type, by its lowercase form.
+    document_extensions: HashMap<String, String>,
+    /// Location of each resource kept in a subdirectory, relative to the
+    /// resources directory, by initial checksum (see `Resource::path`).
+    paths: HashMap<String, PathBuf>,
+    /// Initial checksum of each resource kept in a subdirectory, by
+    /// location.
+    keys: HashMap<PathBuf, String>,
+}
+
+impl Storage {
+    pub fn new(root: &Path, layout: StorageLayout) -> Storage {
+        Storage {
+            root: root.to_path_buf(),
+            layout,
+            dry_run: false,
+            follow_symlinks: false,
+            remote: None,
+            keep_extensions: false,
+            extensions: HashMap::new(),
+            document_extensions: HashMap::new(),
+            paths: HashMap::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Resources directory of a library, with the layout and file names
+    /// its catalog gives it, which leaves files where they are if the
+    /// catalog was read for a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - Location of the resources directory.
+    /// * `catalog` - Library catalog.
+    pub fn of(root: &Path, catalog: &Catalog) -> Storage {
+        let mut storage = Storage::new(root, catalog.storage);
+        storage.dry_run = catalog.dry_run;
+        storage.keep_extensions = catalog.file_extensions;
+        storage.document_extensions = catalog
+            .document_types
+            .values()
+            .map(|d| (d.extension.to_lowercase(), d.extension.clone()))
+            .collect();
+        if catalog.file_extensions {
+            storage.extensions = catalog
+                .resources
+                .iter()
+                .filter_map(|r| {
+                    let document =
+                        catalog.document_types.get(r.document.as_ref()?)?;
+                    Some((
+                        r.historical_checksums[0].clone(),
+                        document.extension.clone(),
+                    ))
+                })
+                .collect();
+        }
+        for resource in &catalog.resources {
+            if let Some(path) = &resource.path {
+                let key = &resource.historical_checksums[0];
+                storage.paths.insert(key.clone(), PathBuf::from(path));
+                storage.keys.insert(PathBuf::from(path), key.clone());
+            }
+        }
+        storage
+    }
+
+    /// Storage whose files may be in remote storage, from which `fetch`
+    /// downloads them.
+    pub fn remote(mut self, location: Option<&str>) -> Storage {
+        self.remote = location.map(String::from);
+        self
+    }
+
+    /// Storage that doesn't move files, to plan changes without making
+    /// them.
+    pub fn dry_run(mut self, dry_run: bool) -> Storage {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether the storage leaves files where they are (see `dry_run`).
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Storage whose entries include symbolic links, which are cataloged
+    /// as their targets. Links are never moved to where resources are
+    /// stored, since relative links would break, and removing one
+    /// leaves its target alone.
+    pub fn follow_symlinks(mut self, follow: bool) -> Storage {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Path of a resource named by its checksum alone.
+    fn bare_path(&self, checksum: &str) -> PathBuf {
+        match self.layout {
+            StorageLayout::Flat => self.root.join(checksum),
+            StorageLayout::Sharded => self
+                .root
+                .join(&checksum[..checksum.len().min(2)])
+                .join(checksum),
+        }
+    }
+
+    /// Path a resource should have: its location in a subdirectory, if
+    /// it has one, or its stored path.
+    fn expected_path(&self, checksum: &str) -> PathBuf {
+        match self.paths.get(checksum) {
+            Some(path) => self.root.join(path),
+            None => self.stored_path(checksum),
+        }
+    }
+
+    /// Path a resource is stored under, with the extension of its
+    /// document type when file names keep their extension.
+    fn stored_path(&self, checksum: &str) -> PathBuf {
+        let path = self.bare_path(checksum);
+        match self.extensions.get(checksum) {
+            Some(extension) => path.with_extension(extension),
+            None => path,
+        }
+    }
+
+    /// Path of a resource.
+    ///
+    /// A file stored before its document type was known, or whose
+    /// document type changed, keeps its name until `librarian catalog`
+    /// renames it, so that name is used until then.
+    ///
+    /// # Arguments
+    ///
+    /// * `checksum` - Initial checksum of the resource.
+    pub fn resource_path(&self, checksum: &str) -> PathBuf {
+        let path = self.expected_path(checksum);
+        match path.exists() {
+            true => path,
+            false => self.locate(checksum).unwrap_or(path),
+        }
+    }
+
+    /// Location of a file relative to the resources directory, if it's
+    /// cataloged in place rather than stored under its checksum: it's in
+    /// a subdirectory organizing resources, or it's a symbolic link.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file.
+    pub fn folder_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut components = relative.components();
+        let first = self.root.join(components.next()?);
+        let in_folder = components.next().is_some() && !self.is_shard(&first);
+        match in_folder || path.is_symlink() {
+            true => Some(relative.to_string_lossy().into_owned()),
+            false => None,
+        }
+    }
+
+    /// Initial checksum of the resource a file of the resources directory
+    /// is cataloged under. A stored file is named by it, followed by the
+    /// extension of its document type when file names keep it. New files
+    /// keep their name, or their location relative to the resources
+    /// directory when they're in a subdirectory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file.
+    pub fn key(&self, path: &Path) -> String {
+        if let Ok(relative) = path.strip_prefix(&self.root) {
+            if let Some(key) = self.keys.get(relative) {
+                return key.clone();
+            }
+        }
+        if let Some(relative) = self.folder_path(path) {
+            return relative;
+        }
+        let name = path.file_name().unwrap().to_str().unwrap();
+        match name.split_once('.') {
+            Some((stem, extension))
+                if !stem.is_empty()
+                    && stem.chars().all(|c| c.is_ascii_hexdigit())
+                    && self
+                        .document_extensions
+                        .contains_key(&extension.to_lowercase()) =>
+            {
+                stem.to_string()
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// Current file of a resource, whatever extension it has.
+    fn locate(&self, checksum: &str) -> Option<PathBuf> {
+        if self.paths.contains_key(checksum) {
+            return Some(self.expected_path(checksum)).filter(|p| p.exists());
+        }
+        let bare = self.bare_path(checksum);
+        std::iter::once(self.expected_path(checksum))
+            .chain(std::iter::once(bare.clone()))
+            .chain(
+                self.document_extensions
+                    .values()
+                    .map(|e| bare.with_extension(e)),
+            )
+            .find(|p| p.exists())
+    }
+
+    /// Rename the files of resources whose name doesn't match the
+    /// library's naming, because the library started or stopped keeping
+    /// file extensions, the document type of a resource changed or the
+    /// file was stored before its document type was known.
+    ///
+    /// # Arguments
+    ///
+    /// * `checksums` - Initial checksums of the resources.
+    pub fn rename_files<'a>(
+        &self,
+        checksums: impl IntoIterator<Item = &'a String>,
+    ) {
+        if self.dry_run {
+            return;
+        }
+        for checksum in checksums {
+            let expected = self.expected_path(checksum);
+            if expected.exists() {
+                continue;
+            }
+            if let Some(current) = self.locate(checksum) {
+                std::fs::rename(current, expected)
+                    .expect("Failed to rename resource");
+            }
+        }
+    }
+
+    /// Path of a resource, downloading its file from the remote storage
+    /// first if it isn't on the local disk. The downloaded file is kept
+    /// until the next `librarian catalog`.
+    ///
+    /// # Arguments
+    ///
+    /// * `checksum` - Initial checksum of the resource.
+    pub fn fetch(&self, checksum: &str) -> PathBuf {
+        let path = self.resource_path(checksum);
+        if let (false, Some(location)) = (path.exists(), &self.remote) {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .expect("Failed to create resources directory");
+            }
+            // Download next to the file, so that an interrupted download
+            // isn't mistaken for the resource.
+            let partial = path.with_extension("part");
+            match connect(location).download(checksum, &partial) {
+                Ok(()) => std::fs::rename(&partial, &path)
+                    .expect("Failed to move resource"),
+                Err(e) => {
+                    std::fs::remove_file(&partial).ok();
+                    eprintln!(
+                        "{}",
+                        message(
+                            "remote-download-failed",
+                            &[("key", checksum.into()), ("error", e.into())]
+                        )
+                    );
+                }
+            }
+        }
+        path
+    }
+
+    /// Directory holding the attachments of a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `checksum` - Initial checksum of the resource.
+    pub fn attachments_path(&self, checksum: &str) -> PathBuf {
+        self.root.join(ATTACHMENTS_DIR).join(checksum)
+    }
+
+    /// Copy of the file of a resource as it was at one of its
+    /// checksums.
+    ///
+    /// # Arguments
+    ///
+    /// * `checksum` - Checksum of the revision.
+    pub fn revision_path(&self, checksum: &str) -> PathBuf {
+        self.root.join(VERSIONS_DIR).join(checksum)
+    }
+
+    /// Whether a path is one of the shard directories of a sharded
+    /// layout.
+    fn is_shard(&self, path: &Path) -> bool {
+        self.layout == StorageLayout::Sharded
+            && path.is_dir()
+            && path.parent() == Some(self.root.as_path())
+            && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                n.len() == 2 && n.chars().all(|c| c.is_ascii_hexdigit())
+            })
+    }
+
+    /// Whether a directory of the resources directory is a resource
+    /// rather than a folder organizing resources: a directory stored
+    /// under its SHA-1 checksum, or one with a `RESOURCE_MARKER`. A
+    /// folder whose name merely looks hexadecimal, such as "decade", is
+    /// still a folder.
+    fn is_resource_dir(&self, path: &Path) -> bool {
+        let stored = path.parent() == Some(self.root.as_path())
+            && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                n.len() == 40 && n.chars().all(|c| c.is_ascii_hexdigit())
+            });
+        stored || path.join(RESOURCE_MARKER).exists()
+    }
+
+    /// Every file or directory in the resources directory, which
+    /// includes cataloged resources as well as new files that haven't
+    /// been cataloged yet. Attachments and revisions aren't resources,
+    /// so they are left out. Subdirectories that aren't resources are
+    /// searched for resources, leaving out hidden files.
+    pub fn entries(&self) -> Vec<DirEntry> {
+        let mut entries = Vec::<DirEntry>::new();
+        for entry in WalkDir::new(&self.root).min_depth(1).max_depth(1) {
+            let entry = entry.unwrap();
+            if entry.path() == self.root.join(ATTACHMENTS_DIR)
+                || entry.path() == self.root.join(VERSIONS_DIR)
+                || self.skips_symlink(entry.path())
+            {
+                continue;
+            }
+            if self.is_shard(entry.path()) {
+                entries.extend(
+                    WalkDir::new(entry.path())
+                        .min_depth(1)
+                        .max_depth(1)
+                        .into_iter()
+                        .map(|e| e.unwrap())
+                        .filter(|e| !self.skips_symlink(e.path())),
+                );
+            } else if entry.file_type().is_dir()
+                && !self.is_resource_dir(entry.path())
+            {
+                entries.extend(self.folder_entries(entry.path()));
+            } else {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Resources in a folder of the resources directory and its
+    /// subfolders.
+    fn folder_entries(&self, folder: &Path) -> Vec<DirEntry> {
+        let mut entries = Vec::<DirEntry>::new();
+        let mut walk = WalkDir::new(folder).min_depth(1).into_iter();
+        while let Some(entry) = walk.next() {
+            let entry = entry.unwrap();
+            let is_dir = entry.file_type().is_dir();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if is_dir && (hidden || self.is_resource_dir(entry.path())) {
+                walk.skip_current_dir();
+            }
+            if hidden || self.skips_symlink(entry.path()) {
+                continue;
+            }
+            if !is_dir || self.is_resource_dir(entry.path()) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Whether a path is a symbolic link that isn't followed (see
+    /// `follow_symlinks`), which is reported. Links that were cataloged
+    /// while following them are kept.
+    pub fn skips_symlink(&self, path: &Path) -> bool {
+        let cataloged = || {
+            path.strip_prefix(&self.root)
+                .is_ok_and(|p| self.keys.contains_key(p))
+        };
+        let skipped =
+            !self.follow_symlinks && path.is_symlink() && !cataloged();
+        if skipped {
+            eprintln!(
+                "{}",
+                message(
+                    "storage-symlink-skipped",
+                    &[("path", path.display().to_string().into())]
+                )
+            );
+        }
+        skipped
+    }
+
+    /// Remove an entry of the resources directory. A symbolic link is
+    /// removed itself, never its target. While an operation is being
+    /// journaled, the entry is kept in its trash instead (see
+    /// `journal::trash`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File, directory or link to remove.
+    pub fn remove_entry(path: &Path) -> std::io::Result<()> {
+        if journal::trash(path) {
+            return Ok(());
+        }
+        match path.is_dir() && !path.is_symlink() {
+            true => std::fs::remove_dir_all(path),
+            false => std::fs::remove_file(path),
+        }
+    }
+
+    /// Entry of the resources directory that a path belongs to, i.e., the
+    /// file or directory of a resource, as listed by `entries`. Paths in
+    /// attachments and revisions, hidden paths of folders and folders
+    /// themselves have none.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path in the resources directory, which may not exist
+    ///   anymore.
+    pub fn entry_of(&self, path: &Path) -> Option<PathBuf> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut components = relative.components().map(|c| c.as_os_str());
+        let first = components.next()?;
+        if first == ATTACHMENTS_DIR || first == VERSIONS_DIR {
+            return None;
+        }
+        let mut entry = self.root.join(first);
+        let name = first.to_str()?;
+        if self.layout == StorageLayout::Sharded
+            && name.len() == 2
+            && name.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return components.next().map(|c| entry.join(c));
+        }
+        for component in components {
+            if self.is_resource_dir(&entry) || !entry.is_dir() {
+                break;
+            }
+            if component.to_string_lossy().starts_with('.') {
+                return None;
+            }
+            entry.push(component);
+        }
+        match entry.is_dir() && !self.is_resource_dir(&entry) {
+            true => None,
+            false => Some(entry),
+        }
+    }
+
+    /// Move a resource to its location in this storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Current location of the resource.
+    /// * `checksum` - Initial checksum of the resource.
+    ///
+    /// # Returns
+    ///
+    /// The location of the resource, which is `from` in a dry run.
+    pub fn store(&self, from: &Path, checksum: &str) -> PathBuf {
+        if self.dry_run {
+            return from.to_path_buf();
+        }
+        // A new file keeps its extension if it's that of a document type,
+        // which is how the document type of a new resource is found.
+        let extension = from
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| self.document_extensions.get(&e.to_lowercase()))
+            .filter(|_| {
+                self.keep_extensions && !self.extensions.contains_key(checksum)
+            });
+        let to = match extension {
+            Some(e) => self.bare_path(checksum).with_extension(e),
+            None => self.stored_path(checksum),
+        };
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)
+                .expect("Failed to create resources directory");
+        }
+        std::fs::rename(from, &to).expect("Failed to move resource");
+        journal::moved(from, &to);
+        to
+    }
+
+    /// Move the file of a new resource into the attachments of another
+    /// resource, whose own file is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Initial checksum of the new resource.
+    /// * `to` - Initial checksum of the resource to attach it to.
+    /// * `name` - File name of the attachment.
+    pub fn attach(&self, from: &str, to: &str, name: &str) {
+        if self.dry_run {
+            return;
+        }
+        let from = self.locate(from).expect("Attachment is missing");
+        let dir = self.attachments_path(to);
+        std::fs::create_dir_all(&dir)
+            .expect("Failed to create attachments directory");
+        std::fs::rename(&from, dir.join(name))
+            .expect("Failed to move attachment");
+        journal::moved(&from, &dir.join(name));
+    }
+}
+
+/// Open the file of a resource with the desktop's default application,
+/// downloading it from the library's remote storage first if needed.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+pub fn librarian_open(catalog: &Catalog, resources_path: &Path, query: &str) {
+    let resource =
+        match pick_resource(catalog, query, &message("pick-open", &[])) {
+            Some(r) => r,
+            None => return,
+        };
+    let storage = Storage::of(resources_path, catalog)
+        .remote(catalog.remote_storage.as_deref());
+    let path = storage.fetch(&resource.historical_checksums[0]);
+    if !path.exists() {
+        panic!("Resource {} is missing", resource.historical_checksums[0]);
+    }
+    let opener = match cfg!(target_os = "macos") {
+        true => "open",
+        false => "xdg-open",
+    };
+    let status = Command::new(opener)
+        .arg(&path)
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {}: {}", opener, e));
+    if !status.success() {
+        panic!("{} exited with {}", opener, status);
+    }
+}
+
+/// Move every cataloged resource to a new storage layout and record the
+/// layout in the catalog.
+///
+/// # Arguments
+///
+/// * `catalog_store` - Location the updated catalog is written to.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `layout` - New layout.
+pub fn librarian_migrate_storage(
+    catalog_store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    layout: StorageLayout,
+) {
+    let from = Storage::of(resources_path, catalog);
+    let to = Storage {
+        layout,
+        ..Storage::of(resources_path, catalog)
+    };
+    for resource in &catalog.resources {
+        let key = &resource.historical_checksums[0];
+        let path = from.resource_path(key);
+        if path.exists() && path != to.resource_path(key) {
+            to.store(&path, key);
+        }
+    }
+    // Remove shards emptied by the migration.
+    if catalog.storage == StorageLayout::Sharded {
+        for entry in WalkDir::new(resources_path).min_depth(1).max_depth(1) {
+            let entry = entry.unwrap();
+            if from.is_shard(entry.path()) {
+                // This fails if the shard isn't empty, which is fine.
+                std::fs::remove_dir(entry.path()).ok();
+            }
+        }
+    }
+    catalog.storage = layout;
+    catalog_store.write(catalog);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_path() {
+        let checksum = "88259e88e7677e5ae8a31e33f177a2198cabe95c";
+        assert!(
+            Storage::new(Path::new("resources"), StorageLayout::Flat)
+                .resource_path(checksum)
+                == Path::new("resources").join(checksum)
+        );
+        assert!(
+            Storage::new(Path::new("resources"), StorageLayout::Sharded)
+                .resource_path(checksum)
+                == Path::new("resources").join("88").join(checksum)
+        );
+    }
+
+    #[test]
+    fn test_file_extensions() {
+        let root = std::env::temp_dir()
+            .join(format!("librarian-extensions-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {\"pdf\": {\"extension\": \"pdf\", \"mime\": null}},
+              \"content_types\": {}, \"file_extensions\": true, \"resources\": [
+              {\"title\": \"A\", \"document\": \"pdf\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}
+            ]}",
+        )
+        .unwrap();
+        let storage = Storage::of(&root, &catalog);
+        // Files keep their name until they're renamed.
+        std::fs::write(root.join("aa"), "a").unwrap();
+        assert!(storage.resource_path("aa") == root.join("aa"));
+        storage.rename_files(&["aa".to_string()]);
+        assert!(storage.resource_path("aa") == root.join("aa.pdf"));
+        assert!(storage.key(&root.join("aa.pdf")) == "aa");
+        assert!(storage.key(&root.join("paper.pdf")) == "paper.pdf");
+
+        // New files keep the extension of their document type.
+        std::fs::write(root.join("new.PDF"), "b").unwrap();
+        assert!(
+            storage.store(&root.join("new.PDF"), "bb") == root.join("bb.pdf")
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_folders() {
+        let root = std::env::temp_dir()
+            .join(format!("librarian-folders-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("papers/rf")).unwrap();
+        std::fs::create_dir_all(root.join("papers/.git")).unwrap();
+        std::fs::create_dir_all(root.join("sites/blog/images")).unwrap();
+        std::fs::write(root.join("sites/blog").join(RESOURCE_MARKER), "")
+            .unwrap();
+        std::fs::write(root.join("sites/blog/images/a.png"), "a").unwrap();
+        std::fs::write(root.join("papers/rf/lna.pdf"), "b").unwrap();
+        std::fs::write(root.join("papers/.git/HEAD"), "c").unwrap();
+        std::fs::write(root.join("aa"), "d").unwrap();
+        let stored = root.join("88259e88e7677e5ae8a31e33f177a2198cabe95c");
+        std::fs::create_dir_all(&stored).unwrap();
+        std::fs::write(stored.join("index.html"), "e").unwrap();
+        std::fs::create_dir_all(root.join("decade")).unwrap();
+        std::fs::write(root.join("decade/fm.pdf"), "f").unwrap();
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"LNA\", \"path\": \"papers/rf/lna.pdf\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let storage = Storage::of(&root, &catalog);
+        let mut entries: Vec<PathBuf> = storage
+            .entries()
+            .into_iter()
+            .map(|e| e.into_path())
+            .collect();
+        entries.sort();
+        // Marked and stored directories are resources, hidden files of
+        // folders and folders with a hexadecimal name aren't.
+        assert!(
+            entries
+                == vec![
+                    stored.clone(),
+                    root.join("aa"),
+                    root.join("decade/fm.pdf"),
+                    root.join("papers/rf/lna.pdf"),
+                    root.join("sites/blog")
+                ]
+        );
+        assert!(storage.key(&root.join("papers/rf/lna.pdf")) == "bb");
+        assert!(storage.resource_path("bb") == root.join("papers/rf/lna.pdf"));
+        assert!(
+            storage.folder_path(&root.join("sites/blog")).as_deref()
+                == Some("sites/blog")
+        );
+        assert!(storage.folder_path(&root.join("aa")).is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        let root = std::env::temp_dir()
+            .join(format!("librarian-symlinks-{}", std::process::id()));
+        let outside = root.with_extension("outside");
+        std::fs::create_dir_all(root.join("papers")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("a.pdf"), "a").unwrap();
+        std::os::unix::fs::symlink(outside.join("a.pdf"), root.join("a.pdf"))
+            .unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("papers/outside"))
+            .unwrap();
+        let storage = Storage::new(&root, StorageLayout::Flat);
+        assert!(storage.entries().is_empty());
+
+        // Followed links are cataloged in place, and removing one leaves
+        // its target alone.
+        let storage = storage.follow_symlinks(true);
+        assert!(storage.entries().len() == 2);
+        assert!(
+            storage.folder_path(&root.join("a.pdf")).as_deref()
+                == Some("a.pdf")
+        );
+        Storage::remove_entry(&root.join("papers/outside")).unwrap();
+        assert!(outside.join("a.pdf").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::Resource;
+
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Words that stay lowercase in title case, unless they start or end a
+/// title or follow a colon.
+const MINOR_WORDS: [&str; 22] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into",
+    "nor", "of", "on", "or", "per", "the", "to", "via", "vs", "vs.", "with",
+];
+
+/// Capitalization of titles and subtitles.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    /// Every word is capitalized except for minor words such as "of"
+    /// and "the" (e.g., "The Theory of Phase Noise").
+    Title,
+    /// Only the first word and proper nouns are capitalized (e.g., "The
+    /// theory of phase noise").
+    Sentence,
+}
+
+/// Rules making metadata consistent however it was entered, applied by
+/// `librarian fmt` and `librarian check`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct StyleRules {
+    /// Capitalization of titles and subtitles. Capitalization is left as
+    /// entered when this is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case: Option<Case>,
+    /// Words whose capitalization is kept as written, such as proper
+    /// nouns in sentence case (e.g., "Fourier"). Words with capitals
+    /// after their first letter (e.g., "CMOS" and "LaTeX") and words
+    /// with LaTeX markup are always kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preserve: Vec<String>,
+    /// Spelling variants of journal names, keyed by the canonical name
+    /// (e.g., "Physical Review Letters": ["Phys. Rev. Lett.", "PRL"]).
+    /// Variants are compared ignoring case.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub journals: IndexMap<String, Vec<String>>,
+    /// Remove trailing periods from titles, subtitles and journal names.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_trailing_periods: bool,
+}
+
+/// Change of a resource field made by a style rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl StyleRules {
+    /// Capitalization of a word in a title.
+    ///
+    /// # Arguments
+    ///
+    /// * `word` - The word.
+    /// * `case` - Case of the title.
+    /// * `first` - Whether the word starts the title or follows a colon.
+    /// * `last` - Whether the word ends the title.
+    fn word_case(
+        &self,
+        word: &str,
+        case: Case,
+        first: bool,
+        last: bool,
+    ) -> String {
+        let letters: String =
+            word.chars().filter(|c| c.is_alphabetic()).collect();
+        let kept = word.contains(['$', '\\', '{', '}'])
+            || letters.chars().skip(1).any(char::is_uppercase)
+            || self.preserve.iter().any(|p| {
+                p == word.trim_matches(|c: char| !c.is_alphanumeric())
+            });
+        if kept {
+            return word.to_string();
+        }
+        let lower = word.to_lowercase();
+        let capitalize = match case {
+            Case::Title => {
+                first || last || !MINOR_WORDS.contains(&lower.as_str())
+            }
+            Case::Sentence => first,
+        };
+        match capitalize {
+            // Each part of a hyphenated word is capitalized in title case
+            // (e.g., "Low-Noise").
+            true if case == Case::Title => lower
+                .split('-')
+                .map(capitalize_first)
+                .collect::<Vec<String>>()
+                .join("-"),
+            true => capitalize_first(&lower),
+            false => lower,
+        }
+    }
+
+    /// A title in the configured case.
+    pub fn title_case(&self, title: &str) -> String {
+        let case = match self.case {
+            Some(c) => c,
+            None => return title.to_string(),
+        };
+        let words: Vec<&str> = title.split(' ').collect();
+        let mut first = true;
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let styled = match word.is_empty() {
+                    true => String::new(),
+                    false => {
+                        self.word_case(word, case, first, i == words.len() - 1)
+                    }
+                };
+                if !word.is_empty() {
+                    first = word.ends_with(':') || word.ends_with('?');
+                }
+                styled
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Canonical name of a journal.
+    pub fn canonical_journal(&self, journal: &str) -> String {
+        self.journals
+            .iter()
+            .find(|(canonical, variants)| {
+                canonical.eq_ignore_ascii_case(journal)
+                    || variants.iter().any(|v| v.eq_ignore_ascii_case(journal))
+            })
+            .map(|(canonical, _)| canonical.clone())
+            .unwrap_or_else(|| journal.to_string())
+    }
+
+    /// A value without its trailing period, if the rules strip them.
+    /// Values that look abbreviated (e.g., "J. Appl. Phys.") keep their
+    /// period, as do ellipses.
+    fn strip_period(&self, value: String) -> String {
+        let words: Vec<&str> = value.split(' ').collect();
+        let abbreviated = words.len() > 1
+            && (words[..words.len() - 1].iter().any(|w| w.ends_with('.'))
+                || words[words.len() - 1].len() <= 4);
+        match self.strip_trailing_periods
+            && value.ends_with('.')
+            && !value.ends_with("..")
+            && !abbreviated
+        {
+            true => value[..value.len() - 1].to_string(),
+            false => value,
+        }
+    }
+
+    /// Changes the rules make to a resource.
+    pub fn changes(&self, resource: &Resource) -> Vec<StyleChange> {
+        let mut changes = vec![];
+        let mut change = |field, old: &str, new: String| {
+            if old != new {
+                changes.push(StyleChange {
+                    field,
+                    old: old.to_string(),
+                    new,
+                });
+            }
+        };
+        let title = |t: &str| self.strip_period(self.title_case(t.trim()));
+        change("title", &resource.title, title(&resource.title));
+        if let Some(s) = &resource.subtitle {
+            change("subtitle", s, title(s));
+        }
+        if let Some(j) = &resource.journal {
+            change(
+                "journal",
+                j,
+                self.canonical_journal(
+                    &self.strip_period(j.trim().to_string()),
+                ),
+            );
+        }
+        changes
+    }
+}
+
+/// A word with its first letter capitalized.
+fn capitalize_first(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Apply a style change to a resource.
+pub fn apply_change(resource: &mut Resource, change: &StyleChange) {
+    match change.field {
+        "title" => resource.title = change.new.clone(),
+        "subtitle" => resource.subtitle = Some(change.new.clone()),
+        "journal" => resource.journal = Some(change.new.clone()),
+        _ => unreachable!(),
+    }
+}
+
+/// Apply the catalog's style rules to every resource.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `dry_run` - Print the changes without making them.
+pub fn librarian_fmt(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    dry_run: bool,
+) {
+    let rules = catalog.style.clone().unwrap_or_default();
+    let mut changed = vec![];
+    let mut descriptions = vec![];
+    for resource in catalog.resources.iter_mut() {
+        let changes = rules.changes(resource);
+        if changes.is_empty() {
+            continue;
+        }
+        let key = resource.historical_checksums[0].clone();
+        for change in &changes {
+            let description = format!(
+                "{}: \"{}\" -> \"{}\"",
+                change.field, change.old, change.new
+            );
+            println!("{} {}", key, description);
+            descriptions.push(format!("{} {}", key, description));
+            if !dry_run {
+                apply_change(resource, change);
+            }
+        }
+        changed.push(key);
+    }
+    if dry_run || changed.is_empty() {
+        return;
+    }
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new("fmt", changed.clone(), descriptions),
+        );
+    }
+    println!(
+        "{}",
+        message("fmt-done", &[("count", changed.len().into())])
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_style_rules() {
+        let mut rules: StyleRules = serde_json::from_str(
+            "{\"case\": \"title\", \"preserve\": [\"Fourier\"], \"strip_trailing_periods\": true,
+              \"journals\": {\"Physical Review Letters\": [\"Phys. Rev. Lett.\", \"PRL\"]}}",
+        )
+        .unwrap();
+        assert!(
+            rules.title_case(
+                "a theory of phase noise in low-noise CMOS oscillators"
+            ) == "A Theory of Phase Noise in Low-Noise CMOS Oscillators"
+        );
+        assert!(
+            rules.title_case("what is $\\alpha$: a primer")
+                == "What Is $\\alpha$: A Primer"
+        );
+        rules.case = Some(Case::Sentence);
+        assert!(
+            rules.title_case("The Fourier Transform And Its Applications")
+                == "The Fourier transform and its applications"
+        );
+
+        let resource: Resource = serde_json::from_str(
+            "{\"title\": \"The EPR Paradox.\", \"journal\": \"phys. rev. lett.\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]}",
+        )
+        .unwrap();
+        let changes = rules.changes(&resource);
+        assert!(changes.len() == 2);
+        assert!(changes[0].new == "The EPR paradox");
+        assert!(changes[1].new == "Physical Review Letters");
+        // Abbreviations keep their period.
+        assert!(
+            rules.strip_period(String::from("J. Appl. Phys."))
+                == "J. Appl. Phys."
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::i18n::message;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Directory of the library holding CSL styles and locales added by the
+/// user.
+const STYLES_DIR: &str = "styles";
+
+/// CSL styles built into librarian, as their identifier and contents.
+const EMBEDDED_STYLES: [(&str, &str); 2] = [
+    (
+        "librarian-author-date",
+        include_str!("../styles/author-date.csl"),
+    ),
+    ("librarian-numeric", include_str!("../styles/numeric.csl")),
+];
+
+/// Kind of a CSL file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CslKind {
+    /// A citation style, identified by its short identifier.
+    Style,
+    /// A locale, identified by its language (e.g., "en-US").
+    Locale,
+}
+
+/// Identifying information of a CSL style or locale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CslInfo {
+    pub kind: CslKind,
+    /// Short identifier of a style (the last component of its
+    /// identifier URL, e.g., "apa") or the language of a locale.
+    pub id: String,
+    /// Title of a style. Locales don't have a title.
+    pub title: Option<String>,
+}
+
+impl CslInfo {
+    /// Name of the file the style or locale is stored in, following the
+    /// naming of the CSL repositories.
+    fn file_name(&self) -> String {
+        match self.kind {
+            CslKind::Style => format!("{}.csl", self.id),
+            CslKind::Locale => format!("locales-{}.xml", self.id),
+        }
+    }
+}
+
+/// Parse the identifying information of a CSL style or locale.
+///
+/// # Returns
+///
+/// An error if the contents aren't a CSL style or locale.
+pub fn parse_csl(contents: &str) -> Result<CslInfo, String> {
+    let root = Regex::new(r"<(style|locale)\b([^>]*)>").unwrap();
+    let capture = root.captures(contents).ok_or("not a CSL style or locale")?;
+    if !capture[2].contains("http://purl.org/net/xbiblio/csl") {
+        return Err(String::from("missing the CSL namespace"));
+    }
+    let element = |name: &str| {
+        Regex::new(&format!(r"<{0}>\s*([^<]*?)\s*</{0}>", name))
+            .unwrap()
+            .captures(contents)
+            .map(|c| c[1].to_string())
+    };
+    match &capture[1] {
+        "style" => {
+            let id = element("id").ok_or("style without an id")?;
+            Ok(CslInfo {
+                kind: CslKind::Style,
+                id: id
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap()
+                    .to_string(),
+                title: element("title"),
+            })
+        }
+        _ => {
+            let language = Regex::new(r#"xml:lang="([^"]+)""#)
+                .unwrap()
+                .captures(&capture[2])
+                .map(|c| c[1].to_string())
+                .ok_or("locale without a language")?;
+            Ok(CslInfo {
+                kind: CslKind::Locale,
+                id: language,
+                title: None,
+            })
+        }
+    }
+}
+
+/// Contents of a CSL style, looked up first among the styles added to
+/// the library and then among the embedded styles.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `id` - Short identifier of the style.
+pub fn find_style(library: &Path, id: &str) -> Option<String> {
+    let path = library.join(STYLES_DIR).join(format!("{}.csl", id));
+    match std::fs::read_to_string(path) {
+        Ok(s) => Some(s),
+        Err(_) => EMBEDDED_STYLES
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, s)| s.to_string()),
+    }
+}
+
+/// Styles and locales added to the library, along with their files.
+fn library_csl(library: &Path) -> Vec<(CslInfo, PathBuf)> {
+    let mut files: Vec<PathBuf> =
+        match std::fs::read_dir(library.join(STYLES_DIR)) {
+            Ok(entries) => entries.map(|e| e.unwrap().path()).collect(),
+            Err(_) => vec![],
+        };
+    files.sort();
+    files
+        .into_iter()
+        .filter_map(|f| {
+            let contents = std::fs::read_to_string(&f).ok()?;
+            parse_csl(&contents).ok().map(|i| (i, f))
+        })
+        .collect()
+}
+
+/// Print the available CSL styles and locales.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+pub fn librarian_styles_list(library: &Path) {
+    let added = library_csl(library);
+    for (info, _) in added.iter().filter(|(i, _)| i.kind == CslKind::Style) {
+        println!("{}: {}", info.id, info.title.as_deref().unwrap_or(""));
+    }
+    for (id, contents) in EMBEDDED_STYLES.iter() {
+        if !added.iter().any(|(i, _)| i.id == *id) {
+            let title = parse_csl(contents).unwrap().title.unwrap_or_default();
+            println!(
+                "{}",
+                message(
+                    "styles-built-in",
+                    &[("id", (*id).into()), ("title", title.into())]
+                )
+            );
+        }
+    }
+    let locales: Vec<&str> = added
+        .iter()
+        .filter(|(i, _)| i.kind == CslKind::Locale)
+        .map(|(i, _)| i.id.as_str())
+        .collect();
+    if !locales.is_empty() {
+        println!(
+            "{}",
+            message(
+                "styles-locales",
+                &[("locales", locales.join(", ").into())]
+            )
+        );
+    }
+}
+
+/// Add a CSL style or locale to the library, replacing any previous
+/// version with the same identifier.
+///
+/// # Arguments
+///
+/// * `library` - Library directory.
+/// * `source` - File or URL of the style or locale, such as a style
+///   from the CSL repository.
+/// * `dry_run` - Only check that the style or locale is valid.
+pub fn librarian_styles_add(library: &Path, source: &str, dry_run: bool) {
+    let contents = match source.starts_with("http://")
+        || source.starts_with("https://")
+    {
+        true => ureq::get(source)
+            .call()
+            .unwrap_or_else(|e| panic!("Failed to download {}: {}", source, e))
+            .into_string()
+            .expect("Failed to read downloaded style"),
+        false => std::fs::read_to_string(source)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", source, e)),
+    };
+    let info = parse_csl(&contents)
+        .unwrap_or_else(|e| panic!("{} is not valid: {}", source, e));
+    if dry_run {
+        return;
+    }
+    let dir = library.join(STYLES_DIR);
+    std::fs::create_dir_all(&dir).expect("Failed to create styles directory");
+    std::fs::write(dir.join(info.file_name()), contents)
+        .expect("Failed to write style");
+    let id = match info.kind {
+        CslKind::Style => "styles-added-style",
+        CslKind::Locale => "styles-added-locale",
+    };
+    println!("{}", message(id, &[("id", info.id.as_str().into())]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csl() {
+        for (id, contents) in EMBEDDED_STYLES.iter() {
+            assert!(parse_csl(contents).unwrap().id == *id);
+        }
+        let style = parse_csl(
+            "<style xmlns=\"http://purl.org/net/xbiblio/csl\" version=\"1.0\"><info><title>IEEE</title><id>http://www.zotero.org/styles/ieee</id></info></style>",
+        )
+        .unwrap();
+        assert!(style.id == "ieee" && style.title.as_deref() == Some("IEEE"));
+        let locale = parse_csl(
+            "<locale xmlns=\"http://purl.org/net/xbiblio/csl\" version=\"1.0\" xml:lang=\"de-DE\"></locale>",
+        )
+        .unwrap();
+        assert!(
+            locale.kind == CslKind::Locale
+                && locale.file_name() == "locales-de-DE.xml"
+        );
+        assert!(parse_csl("<html></html>").is_err());
+    }
+}
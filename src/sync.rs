@@ -0,0 +1,768 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::inbox::ask;
+use crate::merge::display_value;
+use crate::resource::Resource;
+use crate::storage::Storage;
+
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Directory of the library holding the catalog as it was after the
+/// last sync with each remote, which is the common base of the next
+/// sync.
+const SYNC_DIR: &str = ".sync";
+
+/// Field stamped with the time of every change, which is merged by
+/// keeping the later time rather than flagged as a conflict.
+const STAMP_FIELD: &str = "date_modified";
+
+/// Which value `librarian sync` keeps when both copies of the library
+/// changed a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPreference {
+    Local,
+    Remote,
+    /// The value the user chooses, field by field.
+    Ask,
+}
+
+/// Remote copy of a library.
+#[derive(Debug, Clone, PartialEq)]
+enum Remote {
+    /// Library directory on a mounted filesystem.
+    Path(PathBuf),
+    /// Library directory reached over SSH, written "host:path" as for
+    /// rsync.
+    Rsync { host: String, root: String },
+    /// Bucket and prefix, written "s3://bucket/prefix", reached with the
+    /// AWS command line interface.
+    S3(String),
+}
+
+/// Run an external command, reporting whether it succeeded.
+fn succeeds(command: &mut Command) -> bool {
+    command
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {:?}: {}", command, e))
+        .success()
+}
+
+/// Copy a file or directory, replacing whatever is at `to`.
+///
+/// # Returns
+///
+/// Whether `from` exists.
+fn copy_item(from: &Path, to: &Path) -> bool {
+    if !from.exists() {
+        return false;
+    }
+    if to.is_dir() {
+        std::fs::remove_dir_all(to).expect("Failed to replace directory");
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).expect("Failed to create directory");
+    }
+    if from.is_file() {
+        std::fs::copy(from, to).expect("Failed to copy file");
+        return true;
+    }
+    for entry in WalkDir::new(from) {
+        let entry = entry.unwrap();
+        let target = to.join(entry.path().strip_prefix(from).unwrap());
+        match entry.file_type().is_dir() {
+            true => std::fs::create_dir_all(&target)
+                .expect("Failed to create directory"),
+            false => {
+                std::fs::copy(entry.path(), &target)
+                    .expect("Failed to copy file");
+            }
+        }
+    }
+    true
+}
+
+impl Remote {
+    /// Parse a remote given on the command line.
+    fn parse(remote: &str) -> Remote {
+        if remote.starts_with("s3://") {
+            return Remote::S3(remote.trim_end_matches('/').to_string());
+        }
+        // "host:path" is a path if it exists, as rsync does for paths
+        // with a slash before the colon.
+        match remote.split_once(':') {
+            Some((host, root))
+                if !host.contains('/') && !Path::new(remote).exists() =>
+            {
+                Remote::Rsync {
+                    host: host.to_string(),
+                    root: root.trim_end_matches('/').to_string(),
+                }
+            }
+            _ => Remote::Path(PathBuf::from(remote)),
+        }
+    }
+
+    /// Location of a path relative to the remote library, in the
+    /// syntax of the tool copying it.
+    fn location(&self, relative: &str) -> String {
+        match self {
+            Remote::Path(root) => {
+                root.join(relative).to_string_lossy().into_owned()
+            }
+            Remote::Rsync { host, root } => match root.is_empty() {
+                true => format!("{}:{}", host, relative),
+                false => format!("{}:{}/{}", host, root, relative),
+            },
+            Remote::S3(root) => format!("{}/{}", root, relative),
+        }
+    }
+
+    /// Path relative to the remote library, quoted for the remote shell.
+    fn quoted(root: &str, relative: &str) -> String {
+        let path = match root.is_empty() {
+            true => relative.to_string(),
+            false => format!("{}/{}", root, relative),
+        };
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+
+    /// Whether the remote library has a file or directory.
+    ///
+    /// # Returns
+    ///
+    /// An error when the remote library can't be reached, which mustn't
+    /// be mistaken for the file missing.
+    fn exists(&self, relative: &str) -> Result<bool, String> {
+        let (command, output) = match self {
+            // A library directory that doesn't exist yet is created by
+            // the first sync.
+            Remote::Path(root) => return Ok(root.join(relative).exists()),
+            Remote::Rsync { host, root } => (
+                "ssh",
+                Command::new("ssh")
+                    .arg(host)
+                    .arg(format!("test -e {}", Remote::quoted(root, relative)))
+                    .output(),
+            ),
+            Remote::S3(_) => (
+                "aws",
+                Command::new("aws")
+                    .args(["s3", "ls", &self.location(relative)])
+                    .output(),
+            ),
+        };
+        let output =
+            output.map_err(|e| format!("Failed to run {}: {}", command, e))?;
+        // Both exit with 1 when nothing matches, and with another status
+        // when they fail.
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(format!(
+                "{} failed with {}: {}",
+                command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+        }
+    }
+
+    /// Copy a file or directory of the remote library to `to`, replacing
+    /// whatever is there.
+    ///
+    /// # Returns
+    ///
+    /// Whether the remote library has the file or directory.
+    fn pull(&self, relative: &str, to: &Path) -> bool {
+        let parent = to.parent().unwrap();
+        std::fs::create_dir_all(parent).expect("Failed to create directory");
+        match self {
+            Remote::Path(root) => copy_item(&root.join(relative), to),
+            // Copying into the parent directory works the same for files
+            // and directories.
+            Remote::Rsync { .. } => succeeds(
+                Command::new("rsync")
+                    .args(["-a", "--delete"])
+                    .arg(self.location(relative))
+                    .arg(format!("{}/", parent.display())),
+            ),
+            Remote::S3(_) => {
+                let location = self.location(relative);
+                succeeds(
+                    Command::new("aws")
+                        .args(["s3", "cp", "--quiet", &location])
+                        .arg(to),
+                ) || (succeeds(
+                    Command::new("aws")
+                        .args(["s3", "sync", "--quiet", "--delete"])
+                        .arg(&location)
+                        .arg(to),
+                ) && to.read_dir().is_ok_and(|mut d| d.next().is_some()))
+            }
+        }
+    }
+
+    /// Copy a file or directory to the remote library, replacing
+    /// whatever is there.
+    fn push(&self, from: &Path, relative: &str) -> bool {
+        let location = self.location(relative);
+        match self {
+            Remote::Path(root) => copy_item(from, &root.join(relative)),
+            Remote::Rsync { .. } => {
+                let parent = match location.rsplit_once('/') {
+                    Some((parent, _)) => format!("{}/", parent),
+                    None => format!("{}:", location.split(':').next().unwrap()),
+                };
+                succeeds(
+                    Command::new("rsync")
+                        .args(["-a", "--delete", "--mkpath"])
+                        .arg(from)
+                        .arg(parent),
+                )
+            }
+            Remote::S3(_) => {
+                let action: &[&str] = match from.is_dir() {
+                    true => &["sync", "--delete"],
+                    false => &["cp"],
+                };
+                succeeds(
+                    Command::new("aws")
+                        .arg("s3")
+                        .args(action)
+                        .arg("--quiet")
+                        .arg(from)
+                        .arg(&location),
+                )
+            }
+        }
+    }
+
+    /// Remove a file or directory of the remote library.
+    fn remove(&self, relative: &str) -> bool {
+        match self {
+            Remote::Path(root) => {
+                let path = root.join(relative);
+                match path.is_dir() {
+                    true => std::fs::remove_dir_all(path).is_ok(),
+                    false => std::fs::remove_file(path).is_ok(),
+                }
+            }
+            Remote::Rsync { host, root } => {
+                succeeds(Command::new("ssh").arg(host).arg(format!(
+                    "rm -rf -- {}",
+                    Remote::quoted(root, relative)
+                )))
+            }
+            Remote::S3(_) => {
+                let location = self.location(relative);
+                succeeds(
+                    Command::new("aws")
+                        .args(["s3", "rm", "--quiet", &location]),
+                ) && succeeds(Command::new("aws").args([
+                    "s3",
+                    "rm",
+                    "--quiet",
+                    "--recursive",
+                    &format!("{}/", location),
+                ]))
+            }
+        }
+    }
+}
+
+/// Three-way merge of the fields of an object changed by both copies of
+/// the library. A field changed by one copy only takes its value from
+/// that copy, and `choose` decides fields both copies changed
+/// differently.
+///
+/// # Arguments
+///
+/// * `base` - Object as it was after the last sync, if it existed.
+/// * `local` - Local object.
+/// * `remote` - Remote object.
+/// * `choose` - Called with the name of a conflicting field and its
+///   local and remote values, and returns whether the remote value is
+///   kept. A missing value is null.
+fn merge_fields(
+    base: Option<&Map<String, Value>>,
+    local: &Map<String, Value>,
+    remote: &Map<String, Value>,
+    choose: &mut dyn FnMut(&str, &Value, &Value) -> bool,
+) -> Map<String, Value> {
+    let mut names: Vec<&String> = local.keys().collect();
+    names.extend(remote.keys().filter(|k| !local.contains_key(*k)));
+    let mut merged = Map::new();
+    for name in names {
+        let (b, l, r) = (
+            base.and_then(|b| b.get(name)),
+            local.get(name),
+            remote.get(name),
+        );
+        let value = if l == r || r == b {
+            l
+        } else if l == b {
+            r
+        } else if name == STAMP_FIELD {
+            match r.and_then(Value::as_str) > l.and_then(Value::as_str) {
+                true => r,
+                false => l,
+            }
+        } else {
+            match choose(
+                name,
+                l.unwrap_or(&Value::Null),
+                r.unwrap_or(&Value::Null),
+            ) {
+                true => r,
+                false => l,
+            }
+        };
+        if let Some(value) = value {
+            merged.insert(name.clone(), value.clone());
+        }
+    }
+    merged
+}
+
+/// Three-way merge of the local and remote catalogs of a library.
+///
+/// Resources are matched by initial checksum and merged field by field
+/// (see `merge_fields`), and so are the other settings of the catalog.
+/// A resource added by one copy is added, and one removed by one copy is
+/// removed unless the other copy modified it.
+///
+/// # Arguments
+///
+/// * `base` - Catalog as it was after the last sync, if any.
+/// * `local` - Local catalog.
+/// * `remote` - Remote catalog.
+/// * `choose` - Called with the initial checksum of a resource, or
+///   `None` for the catalog's settings, the name of a field both copies
+///   changed and its local and remote values, and returns whether the
+///   remote value is kept.
+pub fn merge_catalogs(
+    base: Option<&Catalog>,
+    local: &Catalog,
+    remote: &Catalog,
+    mut choose: impl FnMut(Option<&str>, &str, &Value, &Value) -> bool,
+) -> Catalog {
+    let split = |catalog: &Catalog| {
+        let mut settings = match serde_json::to_value(catalog).unwrap() {
+            Value::Object(o) => o,
+            _ => unreachable!(),
+        };
+        let resources: IndexMap<String, Map<String, Value>> =
+            match settings.remove("resources") {
+                Some(Value::Array(resources)) => resources
+                    .into_iter()
+                    .filter_map(|r| match r {
+                        Value::Object(r) => Some((
+                            r["historical_checksums"][0]
+                                .as_str()
+                                .unwrap()
+                                .to_string(),
+                            r,
+                        )),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => IndexMap::new(),
+            };
+        (settings, resources)
+    };
+    let (base_settings, base_resources) = match base {
+        Some(b) => {
+            let (s, r) = split(b);
+            (Some(s), r)
+        }
+        None => (None, IndexMap::new()),
+    };
+    let (local_settings, local_resources) = split(local);
+    let (remote_settings, remote_resources) = split(remote);
+
+    let mut merged = merge_fields(
+        base_settings.as_ref(),
+        &local_settings,
+        &remote_settings,
+        &mut |field, l, r| choose(None, field, l, r),
+    );
+    let mut keys: Vec<&String> = local_resources.keys().collect();
+    keys.extend(
+        remote_resources
+            .keys()
+            .filter(|k| !local_resources.contains_key(*k)),
+    );
+    let mut resources = vec![];
+    for key in keys {
+        let base = base_resources.get(key);
+        let resource =
+            match (local_resources.get(key), remote_resources.get(key)) {
+                (Some(l), Some(r)) => {
+                    merge_fields(base, l, r, &mut |field, l, r| {
+                        choose(Some(key), field, l, r)
+                    })
+                }
+                // The other copy removed the resource, which is kept if this
+                // copy modified it since.
+                (Some(only), None) | (None, Some(only)) => match base {
+                    Some(b) if b == only => continue,
+                    _ => only.clone(),
+                },
+                (None, None) => unreachable!(),
+            };
+        resources.push(Value::Object(resource));
+    }
+    merged.insert(String::from("resources"), Value::Array(resources));
+    let mut catalog: Catalog = serde_json::from_value(Value::Object(merged))
+        .expect("Merged catalog is invalid");
+    catalog.sort();
+    catalog
+}
+
+/// Files of a resource relative to the resources directory: its file
+/// or directory, and the directory of its attachments if it has any.
+fn resource_files(
+    storage: &Storage,
+    resources_path: &Path,
+    resource: &Resource,
+) -> Vec<String> {
+    let key = &resource.historical_checksums[0];
+    let mut files = vec![storage.resource_path(key)];
+    if resource.attachments.as_ref().is_some_and(|a| !a.is_empty()) {
+        files.push(storage.attachments_path(key));
+    }
+    files
+        .iter()
+        .map(|f| {
+            f.strip_prefix(resources_path)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+/// Whether the files of a resource differ between two versions of it.
+fn files_differ(a: &Resource, b: &Resource) -> bool {
+    a.checksum != b.checksum
+        || serde_json::to_value(&a.attachments).unwrap()
+            != serde_json::to_value(&b.attachments).unwrap()
+}
+
+/// Synchronize the library with a remote copy of it: another directory,
+/// an SSH host reached with rsync or an S3 bucket.
+///
+/// The catalogs are merged three ways against the catalog as it was
+/// after the last sync with the remote, asking about fields both copies
+/// changed. Resource files are content addressed, so each copy only
+/// receives the files of resources that are new or changed on the other
+/// side, and files of resources the merge removed are removed. Both
+/// copies must use the same storage layout.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `catalog_path` - Location of the stored catalog, which is at the
+///   same place relative to the remote library.
+/// * `remote` - Remote library, as a path, "host:path" or
+///   "s3://bucket/prefix".
+/// * `prefer` - Which value is kept when both copies changed a field.
+pub fn librarian_sync(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    catalog_path: &Path,
+    remote: &str,
+    prefer: SyncPreference,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let relative = |path: &Path| {
+        path.strip_prefix(library)
+            .expect("path is outside the library directory")
+            .to_string_lossy()
+            .into_owned()
+    };
+    let (catalog_relative, resources_relative) =
+        (relative(catalog_path), relative(resources_path));
+    let target = Remote::parse(remote);
+    let base_path = library.join(SYNC_DIR).join(format!(
+        "{}.json",
+        hex::encode(Sha1::digest(remote.as_bytes()))
+    ));
+    let base: Option<Catalog> = std::fs::read_to_string(&base_path)
+        .ok()
+        .map(|b| serde_json::from_str(&b).expect("Sync base is invalid"));
+
+    let scratch = std::env::temp_dir()
+        .join(format!("librarian-sync-{}", std::process::id()));
+    let fetched = scratch.join("remote").join(&catalog_relative);
+    // A remote that can't be reached would otherwise look like one
+    // without a catalog, whose catalog is then overwritten.
+    let failed = |error: String| -> ! {
+        panic!(
+            "{}",
+            message(
+                "sync-pull-failed",
+                &[("remote", remote.into()), ("error", error.into())]
+            )
+        )
+    };
+    let remote_catalog = match target.exists(&catalog_relative) {
+        Ok(true) => match target.pull(&catalog_relative, &fetched) {
+            true => Some(CatalogStore::open(&fetched).read()),
+            false => failed(format!("Failed to copy {}", catalog_relative)),
+        },
+        Ok(false) => None,
+        Err(e) => failed(e),
+    };
+    if let Some(r) = &remote_catalog {
+        if let (Some(local), Some(other)) = (&catalog.library_id, &r.library_id)
+        {
+            if local != other {
+                panic!(
+                    "{}",
+                    message(
+                        "sync-other-library",
+                        &[
+                            ("remote", remote.into()),
+                            ("library", other.as_str().into()),
+                            ("local", local.as_str().into()),
+                        ]
+                    )
+                );
+            }
+        }
+    }
+
+    let mut conflicts = 0;
+    let mut merged = match &remote_catalog {
+        Some(r) => {
+            merge_catalogs(base.as_ref(), catalog, r, |key, field, l, r| {
+                conflicts += 1;
+                match prefer {
+                    SyncPreference::Local => false,
+                    SyncPreference::Remote => true,
+                    SyncPreference::Ask => loop {
+                        let prompt = message(
+                            "sync-choose",
+                            &[
+                                ("key", key.unwrap_or("catalog").into()),
+                                ("field", field.into()),
+                                ("local", display_value(l).into()),
+                                ("remote", display_value(r).into()),
+                            ],
+                        );
+                        match ask(&prompt).as_deref() {
+                            Some("1") | None => break false,
+                            Some("2") => break true,
+                            _ => (),
+                        }
+                    },
+                }
+            })
+        }
+        None => catalog.clone(),
+    };
+    // A dry run only shows how the merge would change the local
+    // catalog, without transferring anything.
+    if catalog.dry_run {
+        std::fs::remove_dir_all(&scratch).ok();
+        merged.dry_run = true;
+        store.write(&mut merged);
+        return;
+    }
+
+    // Transfer the files of the resources whose merged version comes
+    // from the other copy.
+    let storage = Storage::of(resources_path, &merged);
+    let find = |c: &Catalog, key: &str| -> Option<Resource> {
+        c.resources
+            .iter()
+            .find(|r| r.historical_checksums[0] == key)
+            .cloned()
+    };
+    let remote_resources = remote_catalog
+        .as_ref()
+        .map_or(vec![], |r| r.resources.clone());
+    let (mut fetched_files, mut sent_files) = (0, 0);
+    for resource in &merged.resources {
+        let key = &resource.historical_checksums[0];
+        let (local, remote) = (
+            find(catalog, key),
+            remote_resources
+                .iter()
+                .find(|r| &r.historical_checksums[0] == key),
+        );
+        let files = resource_files(&storage, resources_path, resource);
+        if local.as_ref().is_none_or(|l| files_differ(l, resource)) {
+            for file in &files {
+                let path = format!("{}/{}", resources_relative, file);
+                if target.pull(&path, &resources_path.join(file)) {
+                    fetched_files += 1;
+                } else {
+                    eprintln!(
+                        "{}",
+                        message("sync-missing", &[("file", path.into())])
+                    );
+                }
+            }
+        } else if remote.is_none_or(|r| files_differ(r, resource)) {
+            for file in &files {
+                let path = format!("{}/{}", resources_relative, file);
+                if resources_path.join(file).exists() {
+                    if !target.push(&resources_path.join(file), &path) {
+                        panic!("Failed to send {}", file);
+                    }
+                    sent_files += 1;
+                }
+            }
+        }
+    }
+
+    let removed = |c: &Catalog| -> Vec<Resource> {
+        c.resources
+            .iter()
+            .filter(|r| find(&merged, &r.historical_checksums[0]).is_none())
+            .cloned()
+            .collect()
+    };
+    let (removed_locally, removed_remotely) = (
+        removed(catalog),
+        remote_catalog.as_ref().map_or(vec![], removed),
+    );
+    let changed: Vec<String> = merged
+        .resources
+        .iter()
+        .filter(|r| {
+            find(catalog, &r.historical_checksums[0]).is_none_or(|l| {
+                serde_json::to_value(&l).unwrap()
+                    != serde_json::to_value(r).unwrap()
+            })
+        })
+        .chain(&removed_locally)
+        .map(|r| r.historical_checksums[0].clone())
+        .collect();
+
+    // Write the merged catalog to both copies, then remove the files of
+    // the resources the merge removed.
+    let written = scratch.join("merged").join(&catalog_relative);
+    std::fs::create_dir_all(written.parent().unwrap())
+        .expect("Failed to create directory");
+    if catalog_path.is_dir() {
+        std::fs::create_dir_all(&written).expect("Failed to create directory");
+    }
+    CatalogStore::open(&written).write(&mut merged);
+    if !target.push(&written, &catalog_relative) {
+        panic!(
+            "{}",
+            message("sync-push-failed", &[("remote", remote.into())])
+        );
+    }
+    for resource in &removed_locally {
+        for file in resource_files(&storage, resources_path, resource) {
+            let path = resources_path.join(file);
+            match path.is_dir() {
+                true => std::fs::remove_dir_all(path).ok(),
+                false => std::fs::remove_file(path).ok(),
+            };
+        }
+    }
+    for resource in &removed_remotely {
+        for file in resource_files(&storage, resources_path, resource) {
+            target.remove(&format!("{}/{}", resources_relative, file));
+        }
+    }
+
+    *catalog = merged;
+    store.write(catalog);
+    std::fs::create_dir_all(base_path.parent().unwrap())
+        .expect("Failed to create sync directory");
+    std::fs::write(&base_path, serde_json::to_string(catalog).unwrap())
+        .expect("Failed to record sync base");
+    std::fs::remove_dir_all(&scratch).ok();
+    if !catalog.dry_run {
+        record(
+            library,
+            &AuditEntry::new(
+                "sync",
+                changed.clone(),
+                vec![format!("Synced with {}", remote)],
+            ),
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "sync-done",
+            &[
+                ("remote", remote.into()),
+                ("changed", changed.len().into()),
+                ("fetched", fetched_files.into()),
+                ("sent", sent_files.into()),
+                ("conflicts", conflicts.into()),
+            ]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_catalogs() {
+        let catalog = |resources: &str| -> Catalog {
+            serde_json::from_str(&format!(
+                "{{\"document_types\": {{}}, \"content_types\": {{}}, \"resources\": [{}]}}",
+                resources
+            ))
+            .unwrap()
+        };
+        let base = catalog(
+            "{\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+             {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]},
+             {\"title\": \"C\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]}",
+        );
+        // Locally, "aa" got a date and a rating, and "cc" was removed.
+        let local = catalog(
+            "{\"title\": \"A\", \"date\": \"1998\", \"rating\": 2, \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+             {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}",
+        );
+        // Remotely, "aa" got a new title and another rating, "bb" was
+        // removed and "dd" was added.
+        let remote = catalog(
+            "{\"title\": \"A'\", \"rating\": 4, \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+             {\"title\": \"C\", \"checksum\": \"cc\", \"historical_checksums\": [\"cc\"]},
+             {\"title\": \"D\", \"checksum\": \"dd\", \"historical_checksums\": [\"dd\"]}",
+        );
+        let mut conflicts = vec![];
+        let merged =
+            merge_catalogs(Some(&base), &local, &remote, |key, field, _, _| {
+                conflicts.push(format!("{} {}", key.unwrap(), field));
+                true
+            });
+        assert!(conflicts == vec!["aa rating"]);
+        let keys: Vec<&str> = merged
+            .resources
+            .iter()
+            .map(|r| r.historical_checksums[0].as_str())
+            .collect();
+        assert!(keys == vec!["aa", "dd"]);
+        let a = &merged.resources[0];
+        assert!(a.title == "A'" && a.date.is_some() && a.rating == Some(4));
+
+        // Without a base, resources only one copy has are kept.
+        let merged = merge_catalogs(None, &local, &remote, |_, _, _, _| false);
+        assert!(merged.resources.len() == 4);
+    }
+}
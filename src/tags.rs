@@ -0,0 +1,670 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{confirm, Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::{Resource, Tag};
+use crate::search::search;
+
+use fluent::FluentValue;
+use std::path::Path;
+
+/// Name of a tag written either as its name or as its full path (e.g.,
+/// "physics/electromagnetism").
+fn tag_name(tag: &str) -> &str {
+    tag.rsplit('/').next().unwrap()
+}
+
+impl Catalog {
+    /// Names of a tag and each of its ancestors, starting with the tag
+    /// itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - Tag name or full path.
+    pub fn tag_ancestors<'a>(&'a self, tag: &'a str) -> Vec<&'a str> {
+        let mut ancestors = vec![tag_name(tag)];
+        while let Some(parent) = self
+            .tags
+            .get(*ancestors.last().unwrap())
+            .and_then(|t| t.parent.as_deref())
+        {
+            // Guard against a parent cycle in a hand-edited catalog.
+            if ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    /// Full path of a tag, e.g., "physics/electromagnetism".
+    pub fn tag_path(&self, tag: &str) -> String {
+        let mut ancestors = self.tag_ancestors(tag);
+        ancestors.reverse();
+        ancestors.join("/")
+    }
+
+    /// Whether a resource's tag matches a queried tag, which is the case
+    /// when the queried tag is the tag itself or one of its ancestors.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - Tag of a resource.
+    /// * `query` - Queried tag name or full path.
+    pub fn tag_matches(&self, tag: &str, query: &str) -> bool {
+        tag == query
+            || tag.starts_with(&format!("{}/", query))
+            || self.tag_ancestors(tag).contains(&tag_name(query))
+    }
+
+    /// Whether a tag is in the catalog's tag tree. A tag written as a
+    /// path must match the tag's position in the tree.
+    pub fn tag_known(&self, tag: &str) -> bool {
+        self.tags.contains_key(tag_name(tag))
+            && (!tag.contains('/') || self.tag_path(tag) == tag)
+    }
+
+    /// Add a tag to the tag tree, along with any of its ancestors that
+    /// are missing. Parents are taken from the path the tag is written
+    /// with.
+    pub fn create_tag(&mut self, tag: &str) {
+        let mut parent: Option<String> = None;
+        for name in tag.split('/') {
+            self.tags.entry(name.to_string()).or_insert_with(|| Tag {
+                parent: parent.clone(),
+            });
+            parent = Some(name.to_string());
+        }
+    }
+
+    /// Check that tags are in the tag tree, or add them to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags` - Tags to check.
+    /// * `create` - Add unknown tags to the tree instead of returning an
+    ///   error.
+    ///
+    /// # Returns
+    ///
+    /// An error listing the unknown tags. Tags are only checked when the
+    /// catalog has a tag tree or when `create` is set.
+    pub fn validate_tags(
+        &mut self,
+        tags: &[String],
+        create: bool,
+    ) -> Result<(), String> {
+        let unknown: Vec<String> = tags
+            .iter()
+            .filter(|t| !self.tag_known(t))
+            .cloned()
+            .collect();
+        if unknown.is_empty() || (self.tags.is_empty() && !create) {
+            return Ok(());
+        }
+        match create {
+            true => {
+                for tag in &unknown {
+                    self.create_tag(tag);
+                }
+                Ok(())
+            }
+            false => self.check_tags(tags),
+        }
+    }
+
+    /// Check that tags are in the tag tree, without changing it. Tags
+    /// are only checked when the catalog has a tag tree.
+    ///
+    /// # Returns
+    ///
+    /// An error listing the unknown tags.
+    pub fn check_tags(&self, tags: &[String]) -> Result<(), String> {
+        let unknown: Vec<&str> = tags
+            .iter()
+            .filter(|t| !self.tag_known(t))
+            .map(String::as_str)
+            .collect();
+        match unknown.is_empty() || self.tags.is_empty() {
+            true => Ok(()),
+            false => Err(format!("Unknown tags: {}", unknown.join(", "))),
+        }
+    }
+
+    /// Rewrite the tags of every resource, replacing each tag name with
+    /// the name returned by `map`. Tags whose name is mapped to `None`
+    /// are removed. Tags written as a path are rewritten with the path
+    /// of their tag in the tree, or, for tags that aren't in the tree,
+    /// with each component mapped separately.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the resources whose tags changed.
+    fn rewrite_resource_tags(
+        &mut self,
+        map: impl Fn(&str) -> Option<String>,
+    ) -> Vec<String> {
+        let mut updates = Vec::new();
+        for (i, resource) in self.resources.iter().enumerate() {
+            let tags = match &resource.tags {
+                Some(t) => t,
+                None => continue,
+            };
+            let mut rewritten: Vec<String> = Vec::new();
+            for tag in tags {
+                let name = match map(tag_name(tag)) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                let tag =
+                    match (tag.contains('/'), self.tags.contains_key(&name)) {
+                        (false, _) => name,
+                        (true, true) => self.tag_path(&name),
+                        (true, false) => tag
+                            .split('/')
+                            .filter_map(&map)
+                            .collect::<Vec<String>>()
+                            .join("/"),
+                    };
+                if !rewritten.contains(&tag) {
+                    rewritten.push(tag);
+                }
+            }
+            if &rewritten != tags {
+                updates.push((i, rewritten));
+            }
+        }
+
+        let mut changed = Vec::new();
+        for (i, tags) in updates {
+            let resource = &mut self.resources[i];
+            changed.push(resource.historical_checksums[0].clone());
+            resource.tags = match tags.is_empty() {
+                true => None,
+                false => Some(tags),
+            };
+        }
+        changed
+    }
+
+    /// Rename a tag in the tag tree and on every resource.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the resources whose tags changed, or an
+    /// error if the new name is already taken.
+    pub fn rename_tag(
+        &mut self,
+        old: &str,
+        new: &str,
+    ) -> Result<Vec<String>, String> {
+        if self.tags.contains_key(new) {
+            return Err(format!(
+                "Tag {} already exists. Merge the tags instead.",
+                new
+            ));
+        }
+        let rename = |name: &str| match name == old {
+            true => new.to_string(),
+            false => name.to_string(),
+        };
+        self.tags = self
+            .tags
+            .iter()
+            .map(|(name, tag)| {
+                (
+                    rename(name),
+                    Tag {
+                        parent: tag.parent.as_deref().map(rename),
+                    },
+                )
+            })
+            .collect();
+        Ok(self.rewrite_resource_tags(|t| Some(rename(t))))
+    }
+
+    /// Merge a tag into another, moving its resources and children to
+    /// it and removing it from the tag tree. A tag merged into a tag
+    /// that isn't in the tree is renamed instead.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the resources whose tags changed.
+    pub fn merge_tag(&mut self, from: &str, into: &str) -> Vec<String> {
+        if from == into {
+            return vec![];
+        }
+        if let Some(removed) = self.tags.shift_remove(from) {
+            self.tags.entry(into.to_string()).or_insert(removed);
+        }
+        for tag in self.tags.values_mut() {
+            if tag.parent.as_deref() == Some(from) {
+                tag.parent = Some(into.to_string());
+            }
+        }
+        self.rewrite_resource_tags(|t| match t == from {
+            true => Some(into.to_string()),
+            false => Some(t.to_string()),
+        })
+    }
+
+    /// Remove a tag from the tag tree and from every resource. Its
+    /// children move to its parent.
+    ///
+    /// # Returns
+    ///
+    /// The initial checksums of the resources whose tags changed.
+    pub fn remove_tag(&mut self, tag: &str) -> Vec<String> {
+        if let Some(removed) = self.tags.shift_remove(tag) {
+            for t in self.tags.values_mut() {
+                if t.parent.as_deref() == Some(tag) {
+                    t.parent = removed.parent.clone();
+                }
+            }
+        }
+        self.rewrite_resource_tags(|t| match t == tag {
+            true => None,
+            false => Some(t.to_string()),
+        })
+    }
+}
+
+/// Print the children of a tag, indented by their depth, along with the
+/// number of resources tagged with each of them or their descendants.
+fn print_tag_tree(catalog: &Catalog, parent: Option<&str>, depth: usize) {
+    for (name, tag) in &catalog.tags {
+        if tag.parent.as_deref() != parent {
+            continue;
+        }
+        let count = catalog
+            .resources
+            .iter()
+            .filter(|r| {
+                r.tags
+                    .iter()
+                    .flatten()
+                    .any(|t| catalog.tag_matches(t, name))
+            })
+            .count();
+        println!("{}{} ({})", "  ".repeat(depth), name, count);
+        print_tag_tree(catalog, Some(name), depth + 1);
+    }
+}
+
+/// Print the tag tree and report resources whose tags aren't in it.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `create` - Add the unknown tags to the tag tree.
+pub fn librarian_tag_list(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    create: bool,
+) {
+    let unknown: Vec<(String, String)> = catalog
+        .resources
+        .iter()
+        .flat_map(|r| {
+            r.tags
+                .iter()
+                .flatten()
+                .filter(|t| !catalog.tag_known(t))
+                .map(move |t| (r.historical_checksums[0].clone(), t.clone()))
+        })
+        .collect();
+
+    if create && !unknown.is_empty() {
+        let count = catalog.tags.len();
+        for (_, tag) in &unknown {
+            catalog.create_tag(tag);
+        }
+        store.write(catalog);
+        println!(
+            "{}",
+            message(
+                "tag-created",
+                &[("count", (catalog.tags.len() - count).into())]
+            )
+        );
+    }
+    print_tag_tree(catalog, None, 0);
+    if !create {
+        for (key, tag) in &unknown {
+            println!(
+                "{}",
+                message(
+                    "tag-unknown",
+                    &[("key", key.into()), ("tag", tag.into())]
+                )
+            );
+        }
+    }
+}
+
+/// Whether a tag is in the tag tree or on any resource.
+fn tag_exists(catalog: &Catalog, tag: &str) -> bool {
+    catalog.tags.contains_key(tag)
+        || catalog.resources.iter().any(|r| {
+            r.tags
+                .iter()
+                .flatten()
+                .any(|t| t.split('/').any(|c| c == tag))
+        })
+}
+
+/// Write the catalog after a tag operation, print a summary and record
+/// the operation in the audit log.
+///
+/// # Arguments
+///
+/// * `summary` - Identifier of the summary message, which receives the
+///   number of changed resources as `count` in addition to `args`.
+/// * `args` - Arguments of the summary message.
+/// * `change` - Description of the change in the audit log.
+/// * `changed` - Initial checksums of the changed resources.
+#[allow(clippy::too_many_arguments)]
+fn finish_tag_operation(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    operation: &str,
+    summary: &str,
+    args: &[(&str, FluentValue)],
+    change: String,
+    changed: Vec<String>,
+) {
+    store.write(catalog);
+    let mut args = args.to_vec();
+    args.push(("count", changed.len().into()));
+    println!("{}", message(summary, &args));
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new(operation, changed, vec![change]),
+        );
+    }
+}
+
+/// Add a tag to the tag tree.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `tag` - Tag name, or full path to add it under an existing or new
+///   parent (e.g., "physics/optics").
+pub fn librarian_tag_add(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    tag: &str,
+) {
+    if catalog.tags.contains_key(tag_name(tag)) {
+        panic!("Tag {} already exists", tag_name(tag));
+    }
+    catalog.create_tag(tag);
+    store.write(catalog);
+}
+
+/// Remove a tag from the tag tree and from every resource. Its children
+/// move to its parent.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `tag` - Name of the tag.
+pub fn librarian_tag_rm(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    tag: &str,
+) {
+    if !tag_exists(catalog, tag) {
+        panic!("No tag {}", tag);
+    }
+    let changed = catalog.remove_tag(tag);
+    finish_tag_operation(
+        store,
+        catalog,
+        resources_path,
+        "tag rm",
+        "tag-removed",
+        &[("tag", tag.into())],
+        format!("Removed tag {}", tag),
+        changed,
+    );
+}
+
+/// Rename a tag in the tag tree and on every resource.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `old` - Current name of the tag.
+/// * `new` - New name of the tag.
+pub fn librarian_tag_rename(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    old: &str,
+    new: &str,
+) {
+    if !tag_exists(catalog, old) {
+        panic!("No tag {}", old);
+    }
+    if new.contains('/') {
+        panic!("A tag name can't contain /. Rename it, then merge it into a tag under the new parent.");
+    }
+    let changed = catalog
+        .rename_tag(old, new)
+        .unwrap_or_else(|e| panic!("{}", e));
+    finish_tag_operation(
+        store,
+        catalog,
+        resources_path,
+        "tag rename",
+        "tag-renamed",
+        &[("old", old.into()), ("new", new.into())],
+        format!("Renamed tag {} to {}", old, new),
+        changed,
+    );
+}
+
+/// Merge a tag into another, moving its resources and children to it.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `from` - Tag to merge, which is removed.
+/// * `into` - Tag to merge it into.
+pub fn librarian_tag_merge(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    from: &str,
+    into: &str,
+) {
+    if !tag_exists(catalog, from) {
+        panic!("No tag {}", from);
+    }
+    let changed = catalog.merge_tag(from, into);
+    finish_tag_operation(
+        store,
+        catalog,
+        resources_path,
+        "tag merge",
+        "tag-merged",
+        &[("from", from.into()), ("into", into.into())],
+        format!("Merged tag {} into {}", from, into),
+        changed,
+    );
+}
+
+/// Add a tag to, or remove it from, every resource matching a search
+/// query. The resources that would change are listed before anything is
+/// changed.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `tag` - Tag to add or remove.
+/// * `query` - Search query selecting the resources.
+/// * `remove` - Remove the tag instead of adding it.
+/// * `yes` - Apply the change without asking for confirmation.
+pub fn librarian_tag_apply(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    tag: &str,
+    query: &str,
+    remove: bool,
+    yes: bool,
+) {
+    if !remove {
+        catalog
+            .validate_tags(&[tag.to_string()], false)
+            .unwrap_or_else(|e| {
+                panic!("{}. Add it with librarian tag add first.", e)
+            });
+    }
+    let has_tag = |r: &Resource| {
+        r.tags
+            .iter()
+            .flatten()
+            .any(|t| tag_name(t) == tag_name(tag))
+    };
+    let selected: Vec<(String, String)> = search(catalog, query)
+        .into_iter()
+        .filter(|r| has_tag(r) == remove)
+        .map(|r| (r.historical_checksums[0].clone(), r.title.clone()))
+        .collect();
+    if selected.is_empty() {
+        println!(
+            "{}",
+            message("tag-apply-nothing", &[("query", query.into())])
+        );
+        return;
+    }
+
+    let change = match remove {
+        true => format!("Removed tag {}", tag),
+        false => format!("Added tag {}", tag),
+    };
+    for (key, title) in &selected {
+        println!("  {} ({})", title, key);
+    }
+    let question = message(
+        if remove {
+            "tag-apply-remove"
+        } else {
+            "tag-apply-add"
+        },
+        &[("tag", tag.into()), ("count", selected.len().into())],
+    );
+    if !confirm(if yes { "true" } else { "ask" }, &question) {
+        return;
+    }
+
+    for (key, _) in &selected {
+        let resource = catalog.resource_mut(key).unwrap();
+        let mut tags = resource.tags.take().unwrap_or_default();
+        match remove {
+            true => tags.retain(|t| tag_name(t) != tag_name(tag)),
+            false => tags.push(tag.to_string()),
+        }
+        resource.tags = match tags.is_empty() {
+            true => None,
+            false => Some(tags),
+        };
+    }
+    finish_tag_operation(
+        store,
+        catalog,
+        resources_path,
+        "tag apply",
+        if remove { "tag-removed" } else { "tag-added" },
+        &[("tag", tag.into())],
+        change,
+        selected.into_iter().map(|(k, _)| k).collect(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_tree() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"tags\": {\"physics\": {}, \"electromagnetism\": {\"parent\": \"physics\"}, \"optics\": {\"parent\": \"electromagnetism\"}},
+              \"resources\": []}",
+        )
+        .unwrap();
+        assert!(
+            catalog.tag_path("optics") == "physics/electromagnetism/optics"
+        );
+        assert!(catalog.tag_matches("optics", "physics"));
+        assert!(catalog.tag_matches("physics/electromagnetism", "physics"));
+        assert!(!catalog.tag_matches("physics", "optics"));
+        assert!(!catalog.tag_known("electromagnetism/optics"));
+        assert!(catalog.tag_known("physics/electromagnetism/optics"));
+
+        let tags = vec![String::from("math/topology")];
+        assert!(catalog.validate_tags(&tags, false).is_err());
+        assert!(catalog.validate_tags(&tags, true).is_ok());
+        assert!(catalog.tags["topology"].parent == Some(String::from("math")));
+        assert!(catalog.tags["math"].parent.is_none());
+    }
+
+    #[test]
+    fn test_tag_operations() {
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {},
+              \"tags\": {\"physics\": {}, \"em\": {\"parent\": \"physics\"}, \"optics\": {\"parent\": \"em\"}, \"light\": {}},
+              \"resources\": [
+              {\"title\": \"A\", \"tags\": [\"physics/em/optics\", \"light\"], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"tags\": [\"em\"], \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let tags = |catalog: &Catalog, i: usize| {
+            catalog.resources[i].tags.clone().unwrap_or_default()
+        };
+
+        assert!(catalog.rename_tag("em", "physics").is_err());
+        assert!(
+            catalog.rename_tag("em", "electromagnetism").unwrap().len() == 2
+        );
+        assert!(
+            tags(&catalog, 0)
+                == vec!["physics/electromagnetism/optics", "light"]
+        );
+        assert!(
+            catalog.tags["optics"].parent
+                == Some(String::from("electromagnetism"))
+        );
+
+        assert!(catalog.merge_tag("light", "optics") == vec!["aa"]);
+        assert!(
+            tags(&catalog, 0)
+                == vec!["physics/electromagnetism/optics", "optics"]
+        );
+        assert!(!catalog.tags.contains_key("light"));
+
+        assert!(catalog.remove_tag("electromagnetism").len() == 2);
+        assert!(tags(&catalog, 0) == vec!["physics/optics", "optics"]);
+        assert!(catalog.resources[1].tags.is_none());
+        assert!(catalog.tags["optics"].parent == Some(String::from("physics")));
+    }
+}
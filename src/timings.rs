@@ -0,0 +1,72 @@
+use crate::i18n::message;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Part of a run whose time is reported by `--timings`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Listing the files in the resources directory.
+    Walk,
+    /// Computing checksums of resources.
+    Hash,
+    /// Reading and writing the cache.
+    CacheIo,
+    /// Reading and writing the catalog.
+    Serialization,
+}
+
+const PHASES: [(Phase, &str); 4] = [
+    (Phase::Walk, "walking"),
+    (Phase::Hash, "hashing"),
+    (Phase::CacheIo, "cache I/O"),
+    (Phase::Serialization, "catalog serialization"),
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Nanoseconds spent in each phase, in the order of `PHASES`.
+static NANOS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Start recording the time spent in each phase. Timing is process-wide
+/// so that it doesn't have to be threaded through every function that
+/// walks, hashes or serializes.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, adding the time it takes to `phase` when timing is enabled.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let index = PHASES.iter().position(|(p, _)| *p == phase).unwrap();
+    NANOS[index]
+        .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+/// Print the time spent in each phase, along with the rest of the run
+/// and the total.
+///
+/// # Arguments
+///
+/// * `start` - Time the run started.
+pub fn report(start: Instant) {
+    let total = start.elapsed().as_secs_f64();
+    let mut measured = 0.0;
+    eprintln!("{}", message("timings-header", &[]));
+    for (i, (_, name)) in PHASES.iter().enumerate() {
+        let seconds = NANOS[i].load(Ordering::Relaxed) as f64 / 1e9;
+        measured += seconds;
+        eprintln!("  {:<22} {:>9.3} s", name, seconds);
+    }
+    eprintln!("  {:<22} {:>9.3} s", "other", (total - measured).max(0.0));
+    eprintln!("  {:<22} {:>9.3} s", "total", total);
+}
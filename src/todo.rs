@@ -0,0 +1,124 @@
+use crate::catalog::Catalog;
+use crate::i18n::message;
+use crate::resource::Resource;
+
+use indexmap::IndexMap;
+use serde_json::json;
+
+/// Fields reported by `librarian todo` unless others are given.
+pub const TODO_FIELDS: [&str; 4] = ["author", "date", "content", "tags"];
+
+/// Resources lacking each field. A field that is set but empty, such
+/// as an empty list of tags, is missing too.
+///
+/// # Arguments
+///
+/// * `resources` - Resources to check.
+/// * `fields` - Fields to check, which may include custom fields such
+///   as "extra.course".
+///
+/// # Returns
+///
+/// The resources lacking each field, keyed by field in the order the
+/// fields were given. Fields every resource has are left out.
+pub fn missing_fields<'a>(
+    resources: &[&'a Resource],
+    fields: &[&str],
+) -> IndexMap<String, Vec<&'a Resource>> {
+    let mut missing = IndexMap::new();
+    for field in fields {
+        let lacking: Vec<&Resource> = resources
+            .iter()
+            .filter(|r| r.field_string(field).is_none_or(|v| v.is_empty()))
+            .copied()
+            .collect();
+        if !lacking.is_empty() {
+            missing.insert(field.to_string(), lacking);
+        }
+    }
+    missing
+}
+
+/// Print the resources lacking key fields, grouped by field, to drive
+/// the cleanup of a library.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `fields` - Fields to check (see `missing_fields`).
+/// * `json` - Print a JSON object mapping each field to the number of
+///   resources lacking it and their checksums and titles.
+pub fn librarian_todo(catalog: &Catalog, fields: &[&str], json: bool) {
+    let resources: Vec<&Resource> = catalog.resources.iter().collect();
+    let missing = missing_fields(&resources, fields);
+    if json {
+        let report: serde_json::Map<String, serde_json::Value> = missing
+            .iter()
+            .map(|(field, lacking)| {
+                let entries: Vec<serde_json::Value> = lacking
+                    .iter()
+                    .map(|r| {
+                        json!({
+                            "checksum": r.historical_checksums[0],
+                            "title": r.title,
+                        })
+                    })
+                    .collect();
+                (
+                    field.clone(),
+                    json!({"count": lacking.len(), "resources": entries}),
+                )
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+    if missing.is_empty() {
+        println!("{}", message("todo-none", &[]));
+    }
+    for (field, lacking) in &missing {
+        println!(
+            "{}",
+            message(
+                "todo-missing",
+                &[
+                    ("field", field.as_str().into()),
+                    ("count", lacking.len().into())
+                ]
+            )
+        );
+        for resource in lacking {
+            println!(
+                "  {} {}",
+                resource.historical_checksums[0], resource.title
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_fields() {
+        let catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"author\": [\"Thomas Lee\"], \"tags\": [], \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"author\": [\"Ali Hajimiri\"], \"date\": \"1998\", \"tags\": [\"rf\"], \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let resources: Vec<&Resource> = catalog.resources.iter().collect();
+        let missing = missing_fields(&resources, &TODO_FIELDS);
+        let titles = |field: &str| -> Vec<&str> {
+            missing[field].iter().map(|r| r.title.as_str()).collect()
+        };
+        assert!(
+            missing.keys().collect::<Vec<_>>() == ["date", "content", "tags"]
+        );
+        assert!(titles("date") == vec!["A"]);
+        assert!(titles("content") == vec!["A", "B"]);
+        assert!(titles("tags") == vec!["A"]);
+    }
+}
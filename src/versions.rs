@@ -0,0 +1,232 @@
+use crate::audit::{record, AuditEntry};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::i18n::message;
+use crate::resource::Resource;
+use crate::search::pick_resource;
+use crate::storage::Storage;
+
+use std::path::Path;
+
+/// Copy the current file of every resource to its revision, unless it
+/// was copied already, when the catalog keeps revisions (see
+/// `Catalog::keep_revisions`). A resource whose file changes then still
+/// has a copy of its previous contents. Directories aren't copied.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog, with the current checksums.
+/// * `storage` - Resources directory.
+pub fn save_revisions(catalog: &Catalog, storage: &Storage) {
+    if !catalog.keep_revisions {
+        return;
+    }
+    for resource in &catalog.resources {
+        save_revision(resource, storage);
+    }
+}
+
+/// Copy the current file of a resource to its revision, unless it was
+/// copied already or isn't a file.
+fn save_revision(resource: &Resource, storage: &Storage) {
+    let path = storage.resource_path(&resource.historical_checksums[0]);
+    let revision = storage.revision_path(&resource.checksum);
+    if !path.is_file() || revision.exists() || storage.is_dry_run() {
+        return;
+    }
+    std::fs::create_dir_all(revision.parent().unwrap())
+        .expect("Failed to create versions directory");
+    // A copy that is interrupted mustn't pass for the revision.
+    let partial = revision.with_extension("part");
+    std::fs::copy(&path, &partial)
+        .and_then(|_| std::fs::rename(&partial, &revision))
+        .unwrap_or_else(|e| {
+            panic!("Failed to keep revision of {}: {}", path.display(), e)
+        });
+}
+
+/// Checksum of a revision of a resource.
+///
+/// # Arguments
+///
+/// * `resource` - Resource.
+/// * `revision` - Position of the revision among the resource's
+///   checksums, starting at 1, or the beginning of its checksum.
+fn revision_checksum<'a>(
+    resource: &'a Resource,
+    revision: &str,
+) -> Result<&'a str, String> {
+    let checksums = &resource.historical_checksums;
+    if let Ok(n) = revision.parse::<usize>() {
+        if (1..=checksums.len()).contains(&n) {
+            return Ok(&checksums[n - 1]);
+        }
+    }
+    match checksums.iter().find(|c| c.starts_with(revision)) {
+        Some(c) => Ok(c),
+        None => Err(format!(
+            "{} has no revision {}",
+            resource.historical_checksums[0], revision
+        )),
+    }
+}
+
+/// Print the revisions of a resource, i.e., its checksums in the order
+/// its file had them, and whether a copy of each was kept.
+///
+/// # Arguments
+///
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+pub fn librarian_versions(
+    catalog: &Catalog,
+    resources_path: &Path,
+    query: &str,
+) {
+    let resource =
+        match pick_resource(catalog, query, &message("pick-versions", &[])) {
+            Some(r) => r,
+            None => return,
+        };
+    let storage = Storage::of(resources_path, catalog);
+    println!("{} {}", resource.historical_checksums[0], resource.title);
+    let count = resource.historical_checksums.len();
+    for (i, checksum) in resource.historical_checksums.iter().enumerate() {
+        let id = match (i + 1 == count, storage.revision_path(checksum)) {
+            (true, _) => "versions-current",
+            (false, p) if p.exists() => "versions-kept",
+            (false, _) => "versions-lost",
+        };
+        println!("{:>3}. {} {}", i + 1, checksum, message(id, &[]));
+    }
+}
+
+/// Restore the file of a resource to one of its kept revisions (see
+/// `librarian_versions`). The restored contents become the resource's
+/// current checksum again, so they're appended to its historical
+/// checksums. A copy of the current file is kept first, so that nothing
+/// is lost.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `query` - Checksum of the resource or search query matching it.
+/// * `revision` - Position of the revision among the resource's
+///   checksums, starting at 1, or the beginning of its checksum.
+pub fn librarian_checkout(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    query: &str,
+    revision: &str,
+) {
+    let resource =
+        match pick_resource(catalog, query, &message("pick-checkout", &[])) {
+            Some(r) => r,
+            None => return,
+        };
+    let key = resource.historical_checksums[0].clone();
+    let checksum = revision_checksum(resource, revision)
+        .unwrap_or_else(|e| panic!("{}", e))
+        .to_string();
+    if checksum == resource.checksum {
+        println!("{}", message("versions-unchanged", &[]));
+        return;
+    }
+    let storage = Storage::of(resources_path, catalog);
+    let kept = storage.revision_path(&checksum);
+    if !kept.is_file() {
+        panic!("No copy of revision {} of {} was kept", checksum, key);
+    }
+    let path = storage.resource_path(&key);
+    if !path.is_file() {
+        panic!("{} isn't a file that can be restored", path.display());
+    }
+    // Changes that weren't cataloged have no revision yet and would be
+    // lost.
+    if file_checksum(&path) != resource.checksum {
+        panic!(
+            "{} changed since it was cataloged. Run librarian catalog first.",
+            path.display()
+        );
+    }
+    save_revision(resource, &storage);
+    if !storage.is_dry_run() {
+        // The restored file is prepared among the revisions, which
+        // aren't cataloged if it's interrupted.
+        let partial = kept.with_extension("restore");
+        std::fs::copy(&kept, &partial)
+            .and_then(|_| std::fs::rename(&partial, &path))
+            .unwrap_or_else(|e| {
+                panic!("Failed to restore {}: {}", path.display(), e)
+            });
+    }
+
+    let resource = catalog.resource_mut(&key).unwrap();
+    let previous = std::mem::replace(&mut resource.checksum, checksum.clone());
+    resource.historical_checksums.push(checksum.clone());
+    let title = resource.title.clone();
+    store.write(catalog);
+    if !catalog.dry_run {
+        record(
+            resources_path
+                .parent()
+                .expect("resources path does not have a parent"),
+            &AuditEntry::new(
+                "checkout",
+                vec![key.clone()],
+                vec![format!(
+                    "{} restored from {} to {}",
+                    key, previous, checksum
+                )],
+            ),
+        );
+    }
+    println!(
+        "{}",
+        message(
+            "versions-restored",
+            &[("title", title.into()), ("checksum", checksum.into())]
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageLayout;
+
+    #[test]
+    fn test_save_revisions() {
+        let root = std::env::temp_dir()
+            .join(format!("librarian-versions-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut catalog: Catalog = serde_json::from_str(
+            "{\"document_types\": {}, \"content_types\": {}, \"keep_revisions\": true, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"bb\", \"historical_checksums\": [\"aa\", \"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let storage = Storage::new(&root, StorageLayout::Flat);
+        std::fs::write(root.join("aa"), "b").unwrap();
+        save_revisions(&catalog, &storage);
+        assert!(
+            std::fs::read_to_string(root.join(".versions/bb")).unwrap() == "b"
+        );
+        // Kept revisions aren't resources.
+        assert!(storage.entries().len() == 1);
+
+        let resource = &catalog.resources[0];
+        assert!(revision_checksum(resource, "1") == Ok("aa"));
+        assert!(revision_checksum(resource, "b") == Ok("bb"));
+        assert!(revision_checksum(resource, "3").is_err());
+
+        catalog.keep_revisions = false;
+        catalog.resources[0].checksum = "cc".to_string();
+        save_revisions(&catalog, &storage);
+        assert!(!root.join(".versions/cc").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,507 @@
+use crate::batch::{catch_interrupt, interrupted, write_atomically};
+use crate::cache::{read_cache_from_file, Cache, CacheFields, CACHE_VERSION};
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::fulltext::Indexer;
+use crate::i18n::message;
+use crate::storage::Storage;
+use crate::versions::save_revisions;
+
+use indexmap::IndexMap;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+/// Suffixes of files that are still being downloaded or written.
+const PARTIAL_SUFFIXES: [&str; 4] =
+    [".part", ".crdownload", ".download", ".tmp"];
+
+/// Whether a file shouldn't be cataloged, because it's hidden or still
+/// being written.
+fn is_ignored(name: &str) -> bool {
+    name.starts_with('.') || PARTIAL_SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// Entry of the resources directory that a changed path belongs to,
+/// i.e., the file or directory of a resource (see `Storage::entry_of`).
+/// Ignored files (see `is_ignored`) have none.
+///
+/// # Arguments
+///
+/// * `storage` - Resources directory.
+/// * `path` - Changed path.
+fn resource_entry(storage: &Storage, path: &Path) -> Option<PathBuf> {
+    let entry = storage.entry_of(path)?;
+    let name = entry.file_name()?.to_str()?;
+    match is_ignored(name) {
+        true => None,
+        false => Some(entry),
+    }
+}
+
+/// Move a file from an inbox to the top of the resources directory,
+/// where it's cataloged like any other new file.
+///
+/// # Returns
+///
+/// The new location of the file, unless it couldn't be moved.
+fn take_from_inbox(path: &Path, root: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    if is_ignored(name) || !path.exists() {
+        return None;
+    }
+    let to = root.join(name);
+    if to.exists() {
+        eprintln!(
+            "{}",
+            message("watch-inbox-conflict", &[("name", name.into())])
+        );
+        return None;
+    }
+    // Inboxes are often on another file system, which a rename can't
+    // cross.
+    if std::fs::rename(path, &to).is_err() {
+        if !path.is_file() {
+            eprintln!(
+                "{}",
+                message(
+                    "watch-move-failed",
+                    &[("path", path.display().to_string().into())]
+                )
+            );
+            return None;
+        }
+        std::fs::copy(path, &to).expect("Failed to copy file into the library");
+        std::fs::remove_file(path).expect("Failed to remove file from inbox");
+    }
+    Some(to)
+}
+
+/// Catalog the entries of the resources directory that changed. New
+/// files are hashed and cataloged, and files with the content of an
+/// existing resource are reported and left uncataloged, or removed
+/// with `delete_duplicates`. The cache is updated for just these
+/// entries, so nothing else is rescanned.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog, which is read again first in case
+///   another command changed it.
+/// * `resources_path` - Location of the resources directory.
+/// * `entries` - Changed entries of the resources directory.
+/// * `detect_doi` - See description for `Catalog.update`.
+/// * `fetch_metadata` - See description for `Catalog.update`.
+/// * `attach_duplicates` - See description for `Catalog.update`.
+/// * `delete_duplicates` - Remove new files with the content of an
+///   existing resource.
+/// * `follow_symlinks` - See description for `librarian_catalog`.
+///
+/// # Returns
+///
+/// The initial checksums of the resources that were cataloged or
+/// updated.
+#[allow(clippy::too_many_arguments)]
+fn catalog_entries(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    entries: &HashSet<PathBuf>,
+    detect_doi: bool,
+    fetch_metadata: bool,
+    attach_duplicates: &str,
+    delete_duplicates: bool,
+    follow_symlinks: bool,
+) -> Vec<String> {
+    *catalog = store.read();
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let cache_path = library.join(".cache");
+    let mut cache_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&cache_path)
+        .expect("Failed to open or create cache");
+    // A cache that fails to parse is left for `librarian catalog` to
+    // rebuild.
+    let mut cache = read_cache_from_file(&mut cache_file).ok();
+    if let Some(c) = &cache {
+        catalog.check_library_id(c.library_id.as_deref(), "cache");
+    }
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let storage =
+        Storage::of(resources_path, catalog).follow_symlinks(follow_symlinks);
+    let mut resources = IndexMap::<String, PathBuf>::new();
+    for entry in entries {
+        if !entry.exists() || storage.skips_symlink(entry) {
+            continue;
+        }
+        let name = storage.key(entry);
+        let cached = cache.as_ref().and_then(|c| c.resources.get(&name));
+        if cached.is_some_and(|c| c.is_valid(entry, now)) {
+            continue;
+        }
+        let checksum = file_checksum(entry);
+        let current = catalog
+            .resources
+            .iter()
+            .find(|r| r.historical_checksums[0] == name)
+            .map(|r| r.checksum.clone());
+        if current.as_ref() == Some(&checksum) {
+            // Only the modification time changed.
+            if let Some(c) = cache.as_mut() {
+                c.resources.insert(
+                    name.to_string(),
+                    CacheFields::new(entry, checksum, now),
+                );
+            }
+            continue;
+        }
+        if current.is_none() {
+            // A resource whose file is gone was moved rather than
+            // duplicated.
+            let existing = catalog
+                .resource_mut(&checksum)
+                .map(|r| r.historical_checksums[0].clone())
+                .filter(|k| storage.resource_path(k).exists());
+            if let Some(key) = existing.or_else(|| {
+                resources.contains_key(&checksum).then(|| checksum.clone())
+            }) {
+                let id = match delete_duplicates {
+                    true => "watch-duplicate-deleted",
+                    false => "watch-duplicate",
+                };
+                println!(
+                    "{}",
+                    message(
+                        id,
+                        &[
+                            ("path", entry.display().to_string().into()),
+                            ("key", key.into())
+                        ]
+                    )
+                );
+                if delete_duplicates {
+                    Storage::remove_entry(entry).unwrap();
+                }
+                continue;
+            }
+        }
+        resources.insert(checksum, entry.clone());
+    }
+    let before: HashSet<String> = catalog
+        .resources
+        .iter()
+        .map(|r| r.historical_checksums[0].clone())
+        .collect();
+    if !resources.is_empty() {
+        catalog.ensure_library_id();
+        catalog.update(
+            &storage,
+            &resources,
+            true,
+            "false",
+            false,
+            detect_doi,
+            fetch_metadata,
+            attach_duplicates,
+        );
+        store.write(catalog);
+    }
+    // New resources are now known, with the name of their file.
+    let storage = Storage::of(resources_path, catalog);
+    save_revisions(catalog, &storage);
+
+    let mut changed = vec![];
+    for checksum in resources.keys() {
+        let resource =
+            match catalog.resources.iter().find(|r| &r.checksum == checksum) {
+                Some(r) => r,
+                None => continue,
+            };
+        let key = &resource.historical_checksums[0];
+        changed.push(key.clone());
+        let id = match before.contains(key) {
+            true => "watch-updated",
+            false => "watch-cataloged",
+        };
+        println!(
+            "{}",
+            message(
+                id,
+                &[
+                    ("title", resource.title.as_str().into()),
+                    ("key", key.as_str().into())
+                ]
+            )
+        );
+        if let Some(c) = cache.as_mut() {
+            c.resources.shift_remove(checksum);
+            c.resources.insert(
+                key.clone(),
+                CacheFields::new(
+                    &storage.resource_path(key),
+                    checksum.clone(),
+                    now,
+                ),
+            );
+        }
+    }
+
+    if let Some(mut c) = cache {
+        c.resources.sort_keys();
+        write_atomically(
+            &cache_path,
+            &serde_json::to_string_pretty(&Cache {
+                version: CACHE_VERSION,
+                library_id: catalog.library_id.clone(),
+                resources: c.resources,
+                last_run: c.last_run,
+            })
+            .unwrap(),
+        );
+    }
+    changed
+}
+
+/// Catalog new files as they appear in the resources directory or in
+/// an inbox, until interrupted. Files are cataloged once no changes
+/// were seen for `settle` seconds, so that files still being written
+/// aren't hashed early. Only the files that changed are hashed, and
+/// the cache is updated along with the catalog, so there's no need to
+/// run `librarian catalog` afterwards.
+///
+/// Files in an inbox are moved into the resources directory before
+/// they're cataloged, including those already there when watching
+/// starts. Inboxes aren't watched recursively.
+///
+/// With `index`, the resources are indexed by a background worker (see
+/// `Indexer`), those already cataloged first and new ones as they're
+/// cataloged.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `inboxes` - Additional directories to take new files from.
+/// * `settle` - Seconds without changes before files are cataloged.
+/// * `detect_doi` - See description for `Catalog.update`.
+/// * `fetch_metadata` - See description for `Catalog.update`.
+/// * `attach_duplicates` - See description for `Catalog.update`.
+/// * `delete_duplicates` - Remove new files with the content of an
+///   existing resource, which are otherwise left uncataloged.
+/// * `follow_symlinks` - See description for `librarian_catalog`.
+/// * `index` - Index resources in the background, pausing this long
+///   after each one.
+#[allow(clippy::too_many_arguments)]
+pub fn librarian_watch(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    inboxes: &[PathBuf],
+    settle: f64,
+    detect_doi: bool,
+    fetch_metadata: bool,
+    attach_duplicates: &str,
+    delete_duplicates: bool,
+    follow_symlinks: bool,
+    index: Option<Duration>,
+) {
+    let library = resources_path
+        .parent()
+        .expect("resources path does not have a parent");
+    let indexer = index.map(|delay| Indexer::spawn(library, delay));
+    if let Some(i) = &indexer {
+        let keys: Vec<String> = catalog
+            .resources
+            .iter()
+            .map(|r| r.historical_checksums[0].clone())
+            .collect();
+        i.queue(catalog, &Storage::of(resources_path, catalog), &keys);
+    }
+    let (tx, rx) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).expect("Failed to watch for changes");
+    watcher
+        .watch(resources_path, RecursiveMode::Recursive)
+        .unwrap_or_else(|e| {
+            panic!("Failed to watch {}: {}", resources_path.display(), e)
+        });
+    let mut pending = HashSet::<PathBuf>::new();
+    for inbox in inboxes {
+        watcher
+            .watch(inbox, RecursiveMode::NonRecursive)
+            .unwrap_or_else(|e| {
+                panic!("Failed to watch {}: {}", inbox.display(), e)
+            });
+        for entry in std::fs::read_dir(inbox).expect("Failed to read inbox") {
+            pending.insert(entry.unwrap().path());
+        }
+    }
+    for path in std::iter::once(resources_path)
+        .chain(inboxes.iter().map(|i| i.as_path()))
+    {
+        println!(
+            "{}",
+            message(
+                "watch-watching",
+                &[("path", path.display().to_string().into())]
+            )
+        );
+    }
+
+    catch_interrupt();
+    let settle = Duration::from_secs_f64(settle);
+    while !interrupted() {
+        match rx.recv_timeout(settle) {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, EventKind::Access(_)) {
+                    pending.extend(event.paths);
+                }
+            }
+            Ok(Err(e)) => eprintln!(
+                "{}",
+                message("watch-failed", &[("error", e.to_string().into())])
+            ),
+            Err(RecvTimeoutError::Timeout) => {
+                if pending.is_empty() {
+                    continue;
+                }
+                let storage = Storage::of(resources_path, catalog);
+                let entries: HashSet<PathBuf> = pending
+                    .drain()
+                    .filter_map(|path| {
+                        match inboxes.iter().any(|i| path.parent() == Some(i)) {
+                            true => take_from_inbox(&path, resources_path),
+                            false => resource_entry(&storage, &path),
+                        }
+                    })
+                    .collect();
+                let changed = catalog_entries(
+                    store,
+                    catalog,
+                    resources_path,
+                    &entries,
+                    detect_doi,
+                    fetch_metadata,
+                    attach_duplicates,
+                    delete_duplicates,
+                    follow_symlinks,
+                );
+                if let Some(i) = &indexer {
+                    let storage = Storage::of(resources_path, catalog);
+                    i.queue(catalog, &storage, &changed);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_entry() {
+        use crate::storage::{StorageLayout, RESOURCE_MARKER};
+
+        let root = std::env::temp_dir()
+            .join(format!("librarian-watch-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("site/assets")).unwrap();
+        std::fs::write(root.join("site").join(RESOURCE_MARKER), "").unwrap();
+        std::fs::create_dir_all(root.join("papers/rf")).unwrap();
+        let flat = Storage::new(&root, StorageLayout::Flat);
+        let sharded = Storage::new(&root, StorageLayout::Sharded);
+        assert!(
+            resource_entry(&flat, &root.join("paper.pdf"))
+                == Some(root.join("paper.pdf"))
+        );
+        // Changes inside a resource directory belong to the resource,
+        // while folders lead to the resources they contain.
+        assert!(
+            resource_entry(&flat, &root.join("site/assets/a.png"))
+                == Some(root.join("site"))
+        );
+        assert!(
+            resource_entry(&flat, &root.join("papers/rf/lna.pdf"))
+                == Some(root.join("papers/rf/lna.pdf"))
+        );
+        assert!(resource_entry(&flat, &root.join("papers/rf")).is_none());
+        assert!(
+            resource_entry(&sharded, &root.join("ab/abcd/index.html"))
+                == Some(root.join("ab/abcd"))
+        );
+        assert!(resource_entry(&sharded, &root.join("ab")).is_none());
+        assert!(resource_entry(&flat, &root.join("attachments/abcd/a.pdf"))
+            .is_none());
+        assert!(resource_entry(&flat, &root.join("paper.pdf.part")).is_none());
+        assert!(resource_entry(&flat, &root.join(".DS_Store")).is_none());
+        assert!(resource_entry(&flat, &root.join("papers/.DS_Store")).is_none());
+        assert!(resource_entry(&flat, Path::new("/elsewhere/a.pdf")).is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_catalog_entries() {
+        let library = std::env::temp_dir()
+            .join(format!("librarian-watch-entries-{}", std::process::id()));
+        let resources_path = library.join("resources");
+        std::fs::create_dir_all(&resources_path).unwrap();
+        std::fs::write(resources_path.join("aa"), "a").unwrap();
+        std::fs::write(resources_path.join("bb"), "b").unwrap();
+        std::fs::write(resources_path.join("c.txt"), "c").unwrap();
+        let catalog_path = library.join("catalog.json");
+        std::fs::write(
+            &catalog_path,
+            "{\"document_types\": {}, \"content_types\": {}, \"resources\": [
+              {\"title\": \"A\", \"checksum\": \"aa\", \"historical_checksums\": [\"aa\"]},
+              {\"title\": \"B\", \"checksum\": \"bb\", \"historical_checksums\": [\"bb\"]}
+            ]}",
+        )
+        .unwrap();
+        let mut store = CatalogStore::open(&catalog_path);
+        let mut catalog = store.read();
+        let entries: HashSet<PathBuf> =
+            std::iter::once(resources_path.join("c.txt")).collect();
+
+        let changed = catalog_entries(
+            &mut store,
+            &mut catalog,
+            &resources_path,
+            &entries,
+            false,
+            false,
+            "false",
+            false,
+            false,
+        );
+        assert!(changed.len() == 1);
+        assert!(catalog.resources.len() == 3);
+        assert!(catalog.resources.iter().all(|r| !r.retired));
+
+        // An incremental update doesn't look for orphans, which would be
+        // listed and, here, retired.
+        let storage = Storage::of(&resources_path, &catalog);
+        std::fs::write(resources_path.join("d.txt"), "d").unwrap();
+        let resources: IndexMap<String, PathBuf> =
+            std::iter::once(("dd".to_string(), resources_path.join("d.txt")))
+                .collect();
+        catalog.update(
+            &storage, &resources, true, "true", false, false, false, "false",
+        );
+        assert!(catalog.resources.len() == 4);
+        assert!(catalog.resources.iter().all(|r| !r.retired));
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+}
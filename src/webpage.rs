@@ -0,0 +1,386 @@
+use crate::catalog::{file_checksum, Catalog, CatalogStore};
+use crate::download::DOWNLOADS_DIR;
+use crate::i18n::message;
+use crate::metadata::Metadata;
+use crate::pdf::xml_text;
+use crate::resource::{DateTime, Name, Resource};
+use crate::storage::Storage;
+
+use base64::Engine;
+use regex::Regex;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Largest page or asset that is downloaded, in bytes.
+const MAX_DOWNLOAD: u64 = 50 * 1024 * 1024;
+
+/// Meta tags giving the title, date and authors of a page, most
+/// specific first. Scholarly pages use Highwire Press tags
+/// ("citation_*") and others use Open Graph or Dublin Core.
+const TITLE_TAGS: [&str; 3] = ["citation_title", "og:title", "dc.title"];
+const DATE_TAGS: [&str; 5] = [
+    "citation_publication_date",
+    "citation_date",
+    "article:published_time",
+    "dc.date",
+    "date",
+];
+const AUTHOR_TAGS: [&str; 3] = ["citation_author", "dc.creator", "author"];
+
+/// Values of the meta tags of a page, keyed by the lowercase name (or
+/// property) of the tag. Tags that occur several times, such as one
+/// per author, keep every value in order.
+fn meta_tags(html: &str) -> HashMap<String, Vec<String>> {
+    let tag = Regex::new(r"(?is)<meta\b[^>]*>").unwrap();
+    let attribute = Regex::new(
+        r#"(?is)\b(name|property|content)\s*=\s*(?:"([^"]*)"|'([^']*)')"#,
+    )
+    .unwrap();
+    let mut tags = HashMap::<String, Vec<String>>::new();
+    for t in tag.find_iter(html) {
+        let (mut name, mut content) = (None, None);
+        for a in attribute.captures_iter(t.as_str()) {
+            let value = a.get(2).or_else(|| a.get(3)).unwrap().as_str();
+            match a[1].to_lowercase().as_str() {
+                "content" => content = Some(xml_text(value.as_bytes())),
+                _ => name = Some(value.to_lowercase()),
+            }
+        }
+        if let (Some(n), Some(c)) = (name, content.filter(|c| !c.is_empty())) {
+            tags.entry(n).or_default().push(c);
+        }
+    }
+    tags
+}
+
+/// Metadata of a web page, from its meta tags and title element.
+pub fn html_metadata(html: &str) -> Metadata {
+    let tags = meta_tags(html);
+    let first = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| tags.get(*n).and_then(|v| v.first()).cloned())
+    };
+
+    let mut metadata = Metadata::new();
+    metadata.title = first(&TITLE_TAGS).or_else(|| {
+        Regex::new(r"(?is)<title\b[^>]*>(.*?)</title>")
+            .unwrap()
+            .captures(html)
+            .map(|c| xml_text(c[1].as_bytes()))
+            .map(|t| t.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|t| !t.is_empty())
+    });
+    metadata.author = AUTHOR_TAGS
+        .iter()
+        .find_map(|n| tags.get(*n))
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|a| Name::try_from(a.as_str()).ok())
+                .collect::<Vec<Name>>()
+        })
+        .filter(|a| !a.is_empty());
+    // Dates are often written with slashes (e.g., "2019/05/04") or
+    // with a time, only the date of which is kept.
+    metadata.date = DATE_TAGS
+        .iter()
+        .filter_map(|n| tags.get(*n).and_then(|v| v.first()))
+        .find_map(|d| {
+            let d = d.replace('/', "-");
+            DateTime::try_from(&d[..d.len().min(10)]).ok()
+        });
+    metadata.r#abstract = first(&["citation_abstract", "og:description"]);
+    metadata.language =
+        Regex::new(r#"(?is)<html\b[^>]*\blang\s*=\s*["']([^"']+)"#)
+            .unwrap()
+            .captures(html)
+            .map(|c| c[1].to_string());
+    metadata
+}
+
+/// Replace the references of a page to the images, scripts, stylesheets
+/// and icons it needs to display.
+///
+/// # Arguments
+///
+/// * `html` - Page.
+/// * `base` - URL that relative references are resolved against.
+/// * `replace` - Replacement of a reference given by its resolved URL.
+///   References it returns `None` for are kept.
+pub fn rewrite_assets(
+    html: &str,
+    base: &Url,
+    mut replace: impl FnMut(&Url) -> Option<String>,
+) -> String {
+    let tag = Regex::new(r"(?is)<(img|script|link)\b[^>]*>").unwrap();
+    let reference =
+        Regex::new(r#"(?is)\b(?:src|href)\s*=\s*(?:"([^"]*)"|'([^']*)')"#)
+            .unwrap();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut end = 0;
+    for t in tag.captures_iter(html) {
+        let whole = t.get(0).unwrap();
+        // Links to other pages are left alone.
+        if t[1].eq_ignore_ascii_case("link") {
+            let lower = whole.as_str().to_lowercase();
+            if !lower.contains("stylesheet") && !lower.contains("icon") {
+                continue;
+            }
+        }
+        let value = match reference.captures(whole.as_str()) {
+            Some(r) => r.get(1).or_else(|| r.get(2)).unwrap(),
+            None => continue,
+        };
+        let url = match base.join(&xml_text(value.as_str().as_bytes())) {
+            Ok(u) if u.scheme() == "http" || u.scheme() == "https" => u,
+            _ => continue,
+        };
+        if let Some(r) = replace(&url) {
+            let start = whole.start() + value.start();
+            rewritten.push_str(&html[end..start]);
+            rewritten.push_str(&r.replace('"', "%22").replace('\'', "%27"));
+            end = whole.start() + value.end();
+        }
+    }
+    rewritten.push_str(&html[end..]);
+    rewritten
+}
+
+/// Download a URL.
+///
+/// # Returns
+///
+/// The final URL after redirects, the media type and the body.
+fn get(
+    agent: &ureq::Agent,
+    url: &str,
+) -> Result<(Url, String, Vec<u8>), String> {
+    let response = agent.get(url).call().map_err(|e| e.to_string())?;
+    let final_url =
+        Url::parse(response.get_url()).map_err(|e| e.to_string())?;
+    let media_type = response.content_type().to_string();
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD)
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    Ok((final_url, media_type, body))
+}
+
+/// File name an asset is saved under, from the last segment of its URL.
+fn asset_name(url: &Url, index: usize) -> String {
+    let name: String = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "._-".contains(*c))
+        .take(64)
+        .collect();
+    match name.trim_start_matches('.').is_empty() {
+        true => index.to_string(),
+        false => format!("{}-{}", index, name.trim_start_matches('.')),
+    }
+}
+
+/// Download a web page and catalog it. The page is stored as a
+/// directory resource holding `index.html` and the images, scripts and
+/// stylesheets it uses, or as a single HTML file with them embedded.
+/// The title, authors and date are taken from the page, and its URL
+/// and the date of the snapshot (BibLaTeX's urldate) are recorded.
+///
+/// # Arguments
+///
+/// * `store` - Location of the catalog on disk.
+/// * `catalog` - Library catalog.
+/// * `resources_path` - Location of the resources directory.
+/// * `url` - URL of the page.
+/// * `single_file` - Store the page as one self-contained HTML file.
+pub fn librarian_add_url(
+    store: &mut CatalogStore,
+    catalog: &mut Catalog,
+    resources_path: &Path,
+    url: &str,
+    single_file: bool,
+) {
+    let parsed = Url::parse(url)
+        .unwrap_or_else(|e| panic!("Invalid URL {}: {}", url, e));
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .user_agent(concat!("librarian/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let (base, _, body) = get(&agent, parsed.as_str())
+        .unwrap_or_else(|e| panic!("Failed to download {}: {}", url, e));
+    let html = String::from_utf8_lossy(&body).to_string();
+    let metadata = html_metadata(&html);
+
+    let downloads = resources_path
+        .parent()
+        .expect("resources path does not have a parent")
+        .join(DOWNLOADS_DIR);
+    let snapshot = downloads.join(format!(
+        "{}{}",
+        hex::encode(Sha1::digest(url.as_bytes())),
+        if single_file { ".html" } else { "" }
+    ));
+    std::fs::create_dir_all(&downloads)
+        .expect("Failed to create downloads directory");
+    // Assets referenced several times are only downloaded once.
+    let mut assets = HashMap::<Url, Option<String>>::new();
+    let fetch = |asset: &Url| -> Option<(String, Vec<u8>)> {
+        match get(&agent, asset.as_str()) {
+            Ok((_, media_type, bytes)) => Some((media_type, bytes)),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    message(
+                        "add-url-asset-failed",
+                        &[
+                            ("url", asset.as_str().into()),
+                            ("error", e.to_string().into())
+                        ]
+                    )
+                );
+                None
+            }
+        }
+    };
+    if single_file {
+        let page = rewrite_assets(&html, &base, |asset| {
+            assets
+                .entry(asset.clone())
+                .or_insert_with(|| {
+                    fetch(asset).map(|(media_type, bytes)| {
+                        format!(
+                            "data:{};base64,{}",
+                            media_type,
+                            base64::engine::general_purpose::STANDARD
+                                .encode(bytes)
+                        )
+                    })
+                })
+                .clone()
+        });
+        std::fs::write(&snapshot, page).expect("Failed to write page");
+    } else {
+        let assets_dir = snapshot.join("assets");
+        std::fs::create_dir_all(&assets_dir)
+            .expect("Failed to create assets directory");
+        let page = rewrite_assets(&html, &base, |asset| {
+            let index = assets.len();
+            assets
+                .entry(asset.clone())
+                .or_insert_with(|| {
+                    let (_, bytes) = fetch(asset)?;
+                    let name = asset_name(asset, index);
+                    std::fs::write(assets_dir.join(&name), bytes)
+                        .expect("Failed to write asset");
+                    Some(format!("assets/{}", name))
+                })
+                .clone()
+        });
+        std::fs::remove_dir(&assets_dir).ok();
+        std::fs::write(snapshot.join("index.html"), page)
+            .expect("Failed to write page");
+    }
+
+    let checksum = file_checksum(&snapshot);
+    if catalog.resource_mut(&checksum).is_some() {
+        match single_file {
+            true => std::fs::remove_file(&snapshot),
+            false => std::fs::remove_dir_all(&snapshot),
+        }
+        .unwrap_or(());
+        println!(
+            "{}",
+            message(
+                "fetch-existing",
+                &[("source", url.into()), ("key", checksum.into())]
+            )
+        );
+    } else {
+        Storage::of(resources_path, catalog).store(&snapshot, &checksum);
+        let title = parsed
+            .path_segments()
+            .and_then(|mut s| s.next_back())
+            .filter(|s| !s.is_empty())
+            .or_else(|| parsed.host_str())
+            .unwrap_or(url)
+            .to_string();
+        let document = match single_file {
+            true => catalog.document_type(Path::new("index.html")),
+            false => None,
+        };
+        let mut resource = Resource::new(title, document, checksum.clone());
+        metadata.apply(&mut resource);
+        resource.url = Some(parsed);
+        resource.archived = Some(DateTime::from(SystemTime::now()));
+        if catalog.content_types.contains_key("webpage") {
+            resource.content = Some(String::from("webpage"));
+        }
+        catalog.resources.push(resource);
+        catalog.sort();
+        catalog.assign_citekeys();
+        store.write(catalog);
+        println!("{}", checksum);
+    }
+    std::fs::remove_dir(&downloads).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_metadata() {
+        let html = r#"<!DOCTYPE html><html lang="en"><head>
+<title>
+  Rust &amp; WebAssembly | Blog
+</title>
+<meta property="og:title" content="Rust &amp; WebAssembly">
+<meta name="author" content="Lin Clark">
+<meta name="citation_publication_date" content="2019/05/04">
+<link rel="stylesheet" href="/style.css">
+<link rel="canonical" href="https://example.com/post">
+</head><body><img src='img/a.png'><img src="data:image/png;base64,AA"><script src="https://cdn.example.org/x.js"></script></body></html>"#;
+        let metadata = html_metadata(html);
+        assert!(metadata.title.as_deref() == Some("Rust & WebAssembly"));
+        assert!(
+            metadata.author == Some(vec![Name::try_from("Lin Clark").unwrap()])
+        );
+        assert!(
+            metadata.date.map(String::from).as_deref() == Some("2019-05-04")
+        );
+        assert!(metadata.language.as_deref() == Some("en"));
+
+        let base = Url::parse("https://example.com/blog/post.html").unwrap();
+        let mut requested = vec![];
+        let page = rewrite_assets(html, &base, |u| {
+            requested.push(u.to_string());
+            match u.path().ends_with(".js") {
+                true => None,
+                false => Some(format!("assets/{}", requested.len())),
+            }
+        });
+        assert!(
+            requested
+                == vec![
+                    "https://example.com/style.css",
+                    "https://example.com/blog/img/a.png",
+                    "https://cdn.example.org/x.js",
+                ]
+        );
+        assert!(page.contains(r#"<link rel="stylesheet" href="assets/1">"#));
+        assert!(page.contains("<img src='assets/2'>"));
+        assert!(page.contains(r#"href="https://example.com/post""#));
+        assert!(page.contains(r#"<script src="https://cdn.example.org/x.js">"#));
+    }
+}